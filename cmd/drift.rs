@@ -0,0 +1,41 @@
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::runner::ProviderChange;
+
+pub fn print(drifted: &[ProviderChange], output: OutputFormat) -> Result<()> {
+    if output.is_structured() {
+        let value = json!({ "drift": drifted });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    if drifted.is_empty() {
+        println!("no drift, the providers serve the records of the config");
+        return Ok(());
+    }
+    println!(
+        "{:<20} {:<30} {:<6} {:<30} DESIRED",
+        "PROVIDER", "NAME", "TYPE", "LIVE"
+    );
+    for c in drifted {
+        let live = match c.change.before.is_empty() {
+            true => "-".to_string(),
+            false => c
+                .change
+                .before
+                .iter()
+                .map(|r| r.content.clone())
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        println!(
+            "{:<20} {:<30} {:<6} {:<30} {}",
+            c.provider, c.change.name, c.change.after.r#type, live, c.change.after.content
+        );
+    }
+    println!();
+    println!("{} record(s) drifted", drifted.len());
+    Ok(())
+}
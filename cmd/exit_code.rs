@@ -0,0 +1,230 @@
+use std::process::exit;
+
+use dns_syncer::Runner;
+use dns_syncer::error::Error;
+use dns_syncer::error::Result;
+
+// Exit codes of a single run, so cron jobs and scripts can branch on the
+// outcome. Documented in the README, keep both in sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    // Records synced, at least one was created or updated
+    Success = 0,
+    // Anything not covered below
+    Failure = 1,
+    // Config file unreadable or invalid
+    Config = 2,
+    // Secrets could not be resolved or credentials were rejected
+    Auth = 3,
+    // Public address could not be fetched, no provider was synced
+    Fetch = 4,
+    // At least one provider failed to sync
+    Provider = 5,
+    // Records synced, every one already served what it should
+    NoChanges = 6,
+    // `drift --check` found records changed outside dns-syncer
+    Drift = 7,
+}
+
+impl ExitCode {
+    // Outcome of the last cycle of the runner, told by what became of its
+    // records
+    pub fn from_runner(runner: &Runner) -> Self {
+        match runner.state().last_cycle() {
+            None => Self::Failure,
+            Some(cycle) if cycle.error.is_some() => Self::Fetch,
+            Some(cycle) if !cycle.is_success() => Self::Provider,
+            Some(cycle)
+                if cycle
+                    .providers
+                    .iter()
+                    .flat_map(|p| p.records.iter())
+                    .any(|r| r.status.is_change()) =>
+            {
+                Self::Success
+            }
+            Some(_) => Self::NoChanges,
+        }
+    }
+
+    // Credentials are only blamed when the check did not fail on the network
+    // or the provider being unavailable
+    pub fn from_verify_error(e: &Error) -> Self {
+        if e.is_retryable() {
+            Self::Provider
+        } else {
+            Self::Auth
+        }
+    }
+
+    pub fn exit(self) -> ! {
+        exit(self as i32)
+    }
+}
+
+// A step of a command ending it when it fails: the error is logged and the
+// process exits with `code`
+pub trait OrExit<T> {
+    fn or_exit(self, code: ExitCode) -> T;
+
+    // The error is logged after `context`
+    fn or_exit_with(self, code: ExitCode, context: &str) -> T;
+}
+
+impl<T> OrExit<T> for Result<T> {
+    fn or_exit(self, code: ExitCode) -> T {
+        self.unwrap_or_else(|e| {
+            log::error!("{}", e);
+            code.exit()
+        })
+    }
+
+    fn or_exit_with(self, code: ExitCode, context: &str) -> T {
+        self.map_err(|e| e.context(context)).or_exit(code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+
+    use dns_syncer::CancellationToken;
+    use dns_syncer::Config;
+    use dns_syncer::error::Result;
+    use dns_syncer::fetcher::Fetcher;
+    use dns_syncer::fetcher::HttpFetcher;
+    use dns_syncer::fetcher::StaticFetcher;
+    use dns_syncer::http::MemoryTransport;
+    use dns_syncer::provider::AuthParams;
+    use dns_syncer::provider::MockProvider;
+    use dns_syncer::provider::ParamList;
+    use dns_syncer::provider::Provider;
+    use dns_syncer::provider::ProviderRegistry;
+    use dns_syncer::record::ProviderRecord;
+    use dns_syncer::record::RecordContent;
+    use dns_syncer::record::RecordType;
+
+    use super::*;
+
+    // Provider failing every call, the defaults of the trait
+    struct Down;
+
+    impl Provider for Down {}
+
+    // Runner syncing one record to `provider` with the public address of
+    // `fetcher`
+    fn mock_runner(
+        provider: impl Fn() -> Box<dyn Provider> + Send + Sync + 'static,
+        fetcher: Box<dyn Fetcher>,
+    ) -> Runner {
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+providers:
+  - name: mock-1
+    type: mock
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+records:
+  - type: A
+    name: home
+    providers:
+      - name: mock-1
+        zones: [example.org]
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let mut registry = ProviderRegistry::empty();
+        registry.register(
+            "mock",
+            move |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                Ok(provider())
+            },
+        );
+        let records = config.record_items().unwrap();
+        let mut runner = Runner::new(config, records, &registry).unwrap();
+        runner.set_fetcher("static", fetcher);
+        runner
+    }
+
+    fn static_fetcher() -> Box<dyn Fetcher> {
+        Box::new(StaticFetcher::new(Some(Ipv4Addr::new(192, 0, 2, 1)), None))
+    }
+
+    #[test]
+    fn test_exit_code_values() {
+        // The codes of the README table
+        let codes = [
+            ExitCode::Success,
+            ExitCode::Failure,
+            ExitCode::Config,
+            ExitCode::Auth,
+            ExitCode::Fetch,
+            ExitCode::Provider,
+            ExitCode::NoChanges,
+            ExitCode::Drift,
+        ];
+        let values = codes.iter().map(|c| *c as i32).collect::<Vec<_>>();
+        assert_eq!(values, vec![0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[tokio::test]
+    async fn test_exit_code_from_runner() {
+        let cancel = CancellationToken::new();
+
+        // Nothing ran yet
+        let runner = mock_runner(|| Box::new(MockProvider::new()), static_fetcher());
+        assert_eq!(ExitCode::from_runner(&runner), ExitCode::Failure);
+
+        // Creating the record is a change, finding it as desired is not
+        let provider = MockProvider::new();
+        let view = provider.clone();
+        let mut runner = mock_runner(move || Box::new(provider.clone()), static_fetcher());
+        runner.run(&cancel).await.unwrap();
+        assert_eq!(ExitCode::from_runner(&runner), ExitCode::Success);
+        runner.run(&cancel).await.unwrap();
+        assert_eq!(ExitCode::from_runner(&runner), ExitCode::NoChanges);
+
+        // The address is the same, the record changed at the provider is
+        // updated back
+        let zone = "example.org".to_string();
+        view.delete_record(&zone, "home.example.org", RecordType::A, true)
+            .await
+            .unwrap();
+        let stale = ProviderRecord::builder()
+            .name("home")
+            .value(RecordContent::A(Ipv4Addr::new(198, 51, 100, 1)))
+            .build()
+            .unwrap();
+        view.create_record(&zone, &stale).await.unwrap();
+        runner.run(&cancel).await.unwrap();
+        assert!(!runner.public_ip_changed());
+        assert_eq!(ExitCode::from_runner(&runner), ExitCode::Success);
+
+        let mut runner = mock_runner(|| Box::new(Down), static_fetcher());
+        assert!(runner.run(&cancel).await.is_err());
+        assert_eq!(ExitCode::from_runner(&runner), ExitCode::Provider);
+
+        // No backend answers, no provider is synced
+        let transport = Arc::new(MemoryTransport::new());
+        let fetcher = HttpFetcher::new().with_transport(transport);
+        let mut runner = mock_runner(|| Box::new(MockProvider::new()), Box::new(fetcher));
+        assert!(runner.run(&cancel).await.is_err());
+        assert_eq!(ExitCode::from_runner(&runner), ExitCode::Fetch);
+    }
+
+    #[test]
+    fn test_exit_code_from_verify_error() {
+        let rejected = Error::from_status(403, None);
+        assert_eq!(ExitCode::from_verify_error(&rejected), ExitCode::Auth);
+        let unavailable = Error::from_status(503, None);
+        assert_eq!(
+            ExitCode::from_verify_error(&unavailable),
+            ExitCode::Provider
+        );
+        let context = unavailable.context("zone example.org");
+        assert_eq!(ExitCode::from_verify_error(&context), ExitCode::Provider);
+    }
+}
@@ -0,0 +1,24 @@
+use clap::ValueEnum;
+
+use dns_syncer::record::ProviderRecord;
+use dns_syncer::zonefile;
+use dns_syncer::zonefile::ZoneEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ExportFormat {
+    Bind,
+    Csv,
+}
+
+pub fn print(records: &[ProviderRecord], format: ExportFormat) {
+    let mut entries = records
+        .iter()
+        .filter_map(ZoneEntry::from_record)
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| (&a.name, &a.r#type, &a.data).cmp(&(&b.name, &b.r#type, &b.data)));
+    let content = match format {
+        ExportFormat::Bind => zonefile::write_bind(&entries),
+        ExportFormat::Csv => zonefile::write_csv(&entries),
+    };
+    print!("{}", content);
+}
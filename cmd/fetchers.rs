@@ -0,0 +1,235 @@
+use serde::Serialize;
+use serde_json::json;
+
+use dns_syncer::Config;
+use dns_syncer::error::Result;
+use dns_syncer::fetcher::BackendProbe;
+use dns_syncer::fetcher::ProbeResult;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::runner::create_fetcher;
+
+// The probes of every backend of one configured fetcher
+#[derive(Debug, Clone, Serialize)]
+pub struct FetcherProbe {
+    pub fetcher: String,
+    pub r#type: String,
+    pub backends: Vec<BackendProbe>,
+}
+
+// The address most backends agree on, per family. `None` without answers or
+// when the most common addresses tie.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Consensus {
+    pub v4: Option<String>,
+    pub v6: Option<String>,
+}
+
+impl Consensus {
+    pub fn new(probes: &[FetcherProbe]) -> Self {
+        let backends = probes.iter().flat_map(|p| p.backends.iter());
+        Self {
+            v4: majority(backends.clone().filter_map(|b| b.v4.ip.as_deref())),
+            v6: majority(backends.filter_map(|b| b.v6.ip.as_deref())),
+        }
+    }
+
+    pub fn status(&self, family: &str, result: &ProbeResult) -> &'static str {
+        let consensus = match family {
+            "v4" => self.v4.as_deref(),
+            _ => self.v6.as_deref(),
+        };
+        match (result.ip.as_deref(), consensus) {
+            (None, _) => "failed",
+            (Some(_), None) => "-",
+            (Some(ip), Some(consensus)) if ip == consensus => "ok",
+            (Some(_), Some(_)) => "disagrees",
+        }
+    }
+
+    // Backends answering another address than the majority, as
+    // (fetcher, backend, family, address)
+    pub fn disagreeing(&self, probes: &[FetcherProbe]) -> Vec<(String, String, String, String)> {
+        let mut ret = vec![];
+        for probe in probes {
+            for backend in probe.backends.iter() {
+                for (family, result) in [("v4", &backend.v4), ("v6", &backend.v6)] {
+                    if self.status(family, result) == "disagrees" {
+                        ret.push((
+                            probe.fetcher.clone(),
+                            backend.backend.clone(),
+                            family.to_string(),
+                            result.ip.clone().unwrap_or_default(),
+                        ));
+                    }
+                }
+            }
+        }
+        ret
+    }
+}
+
+fn majority<'a>(ips: impl Iterator<Item = &'a str>) -> Option<String> {
+    let mut counts: Vec<(&str, usize)> = vec![];
+    for ip in ips {
+        match counts.iter_mut().find(|(v, _)| *v == ip) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((ip, 1)),
+        }
+    }
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    match counts.as_slice() {
+        [] => None,
+        [(_, first), (_, second), ..] if first == second => None,
+        [(ip, _), ..] => Some(ip.to_string()),
+    }
+}
+
+// Probe every configured fetcher, whether or not a record uses it
+pub async fn probe(config: &Config) -> Vec<FetcherProbe> {
+    let mut ret = vec![];
+    for cfg in config.fetchers.iter() {
+        let backends = match create_fetcher(cfg) {
+            Ok(mut fetcher) => fetcher.probe().await,
+            Err(e) => {
+                log::error!("{}", e);
+                continue;
+            }
+        };
+        ret.push(FetcherProbe {
+            fetcher: cfg.name.clone(),
+            r#type: cfg.r#type.clone(),
+            backends,
+        });
+    }
+    ret
+}
+
+pub fn print(probes: &[FetcherProbe], output: OutputFormat) -> Result<()> {
+    let consensus = Consensus::new(probes);
+
+    if output.is_structured() {
+        let disagreeing = consensus
+            .disagreeing(probes)
+            .into_iter()
+            .map(|(fetcher, backend, family, ip)| {
+                json!({ "fetcher": fetcher, "backend": backend, "family": family, "ip": ip })
+            })
+            .collect::<Vec<_>>();
+        let value = json!({
+            "fetchers": probes,
+            "consensus": consensus,
+            "disagreeing": disagreeing,
+        });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:<12} {:<6} {:>8}  {:<10} ADDRESS",
+        "FETCHER", "BACKEND", "FAMILY", "LATENCY", "STATUS"
+    );
+    for probe in probes {
+        for backend in probe.backends.iter() {
+            for (family, result) in [("v4", &backend.v4), ("v6", &backend.v6)] {
+                println!(
+                    "{:<20} {:<12} {:<6} {:>8}  {:<10} {}",
+                    probe.fetcher,
+                    backend.backend,
+                    family,
+                    format!("{}ms", result.latency_ms),
+                    consensus.status(family, result),
+                    address(result)
+                );
+            }
+        }
+    }
+
+    println!();
+    println!(
+        "majority: v4 {}, v6 {}",
+        consensus.v4.as_deref().unwrap_or("-"),
+        consensus.v6.as_deref().unwrap_or("-")
+    );
+    for (fetcher, backend, family, ip) in consensus.disagreeing(probes) {
+        println!(
+            "{} backend {} answers {} {}, unlike the majority",
+            fetcher, backend, family, ip
+        );
+    }
+    Ok(())
+}
+
+fn address(result: &ProbeResult) -> String {
+    match (&result.ip, &result.error) {
+        (Some(ip), _) => ip.clone(),
+        (None, Some(e)) => format!("error: {}", e),
+        (None, None) => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn result(ip: Option<&str>) -> ProbeResult {
+        ProbeResult {
+            ip: ip.map(String::from),
+            error: ip.is_none().then(|| "timed out".to_string()),
+            latency_ms: 10,
+        }
+    }
+
+    fn backend(name: &str, v4: Option<&str>) -> BackendProbe {
+        BackendProbe {
+            backend: name.to_string(),
+            v4: result(v4),
+            v6: result(None),
+        }
+    }
+
+    #[test]
+    fn test_consensus() {
+        let probes = vec![FetcherProbe {
+            fetcher: "http".to_string(),
+            r#type: "http_fetcher".to_string(),
+            backends: vec![
+                backend("cloudflare", Some("1.2.3.4")),
+                backend("ipw", Some("1.2.3.4")),
+                backend("other", Some("10.0.0.1")),
+                backend("down", None),
+            ],
+        }];
+        let consensus = Consensus::new(&probes);
+        assert_eq!(consensus.v4.as_deref(), Some("1.2.3.4"));
+        assert_eq!(consensus.v6, None);
+
+        let backends = &probes[0].backends;
+        assert_eq!(consensus.status("v4", &backends[0].v4), "ok");
+        assert_eq!(consensus.status("v4", &backends[2].v4), "disagrees");
+        assert_eq!(consensus.status("v4", &backends[3].v4), "failed");
+
+        let disagreeing = consensus.disagreeing(&probes);
+        assert_eq!(disagreeing.len(), 1);
+        assert_eq!(disagreeing[0].1, "other");
+
+        // A tie has no majority
+        assert_eq!(majority(["1.2.3.4", "10.0.0.1"].into_iter()), None);
+    }
+
+    #[test]
+    fn test_address() {
+        let ok = ProbeResult {
+            ip: Some("1.2.3.4".to_string()),
+            error: None,
+            latency_ms: 12,
+        };
+        assert_eq!(address(&ok), "1.2.3.4");
+
+        let failed = ProbeResult {
+            ip: None,
+            error: Some("timed out".to_string()),
+            latency_ms: 5000,
+        };
+        assert_eq!(address(&failed), "error: timed out");
+    }
+}
@@ -0,0 +1,116 @@
+use chrono::SecondsFormat;
+use chrono::TimeDelta;
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::state::State;
+
+// Print the last `limit` sync cycles and the public ip timeline
+pub fn print(state: &State, limit: usize, output: OutputFormat) -> Result<()> {
+    let cycles = state.history(limit);
+
+    if output.is_structured() {
+        let value = json!({
+            "cycles": cycles,
+            "ip_changes": state.ip_changes,
+            "average_ip_lifetime_secs": state.average_ip_lifetime().map(|d| d.num_seconds()),
+        });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    println!("SYNC CYCLES (latest first)");
+    println!(
+        "{:<22} {:>9}  {:<30} RESULT",
+        "STARTED", "DURATION", "PUBLIC IP"
+    );
+    for cycle in cycles {
+        let result =
+            if cycle.is_success() {
+                "ok".to_string()
+            } else {
+                let mut errors = cycle.error.iter().cloned().collect::<Vec<_>>();
+                errors.extend(cycle.providers.iter().filter(|p| !p.success).map(|p| {
+                    format!("{}: {}", p.provider, p.error.as_deref().unwrap_or("failed"))
+                }));
+                format!("failed ({})", errors.join("; "))
+            };
+        println!(
+            "{:<22} {:>9}  {:<30} {}",
+            cycle.started_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            format_duration(cycle.finished_at - cycle.started_at),
+            cycle
+                .public_ip
+                .as_ref()
+                .map(|ip| ip.to_string())
+                .unwrap_or("-".to_string()),
+            result
+        );
+    }
+
+    println!();
+    println!("PUBLIC IP CHANGES");
+    println!("{:<22} {:<30} {:<30} HELD FOR", "AT", "FROM", "TO");
+    let mut changes = state.ip_changes.iter().peekable();
+    while let Some(change) = changes.next() {
+        let held = changes
+            .peek()
+            .map(|next| format_duration(next.at - change.at))
+            .unwrap_or("-".to_string());
+        println!(
+            "{:<22} {:<30} {:<30} {}",
+            change.at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            change
+                .old
+                .as_ref()
+                .map(|ip| ip.to_string())
+                .unwrap_or("-".to_string()),
+            change.new.to_string(),
+            held
+        );
+    }
+    if let Some(lifetime) = state.average_ip_lifetime() {
+        println!();
+        println!(
+            "Average time between changes: {}",
+            format_duration(lifetime)
+        );
+    }
+
+    Ok(())
+}
+
+// Two most significant units, e.g. `2d 3h` or `1m 5s`
+fn format_duration(delta: TimeDelta) -> String {
+    let secs = delta.num_seconds();
+    if secs <= 0 {
+        return format!("{}ms", delta.num_milliseconds().max(0));
+    }
+
+    let units = [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)];
+    let mut parts = vec![];
+    let mut rest = secs;
+    for (name, size) in units {
+        if rest >= size {
+            parts.push(format!("{}{}", rest / size, name));
+            rest %= size;
+        }
+    }
+    parts.into_iter().take(2).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(TimeDelta::milliseconds(250)), "250ms");
+        assert_eq!(format_duration(TimeDelta::seconds(65)), "1m 5s");
+        assert_eq!(
+            format_duration(TimeDelta::seconds(2 * 86400 + 3 * 3600 + 7)),
+            "2d 3h"
+        );
+    }
+}
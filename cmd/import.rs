@@ -0,0 +1,102 @@
+use dns_syncer::zonefile::ZoneEntry;
+
+// Records YAML for the entries of `zone`, synced by `provider`. Entries the tool
+// cannot sync, or outside the zone, are written as comments so that nothing of
+// the zone file is lost silently.
+pub fn to_yaml(entries: &[ZoneEntry], provider: &str, zone: &str) -> String {
+    let mut ret = String::from("records:\n");
+    let mut skipped = vec![];
+
+    for entry in entries {
+        let Some(name) = relative_name(&entry.name, zone) else {
+            skipped.push(format!("{} is outside of zone {}", describe(entry), zone));
+            continue;
+        };
+        let content = match entry.content() {
+            Ok(content) => content,
+            Err(e) => {
+                skipped.push(format!("{}: {}", describe(entry), e));
+                continue;
+            }
+        };
+
+        ret.push_str(&format!("  - type: {}\n", entry.r#type));
+        ret.push_str(&format!("    name: {}\n", quote(&name)));
+        ret.push_str(&format!("    content: {}\n", quote(&content.to_string())));
+        if let Some(ttl) = entry.ttl {
+            ret.push_str(&format!("    ttl: {}\n", ttl));
+        }
+        if let Some(comment) = entry.comment.as_deref() {
+            ret.push_str(&format!("    comment: {}\n", quote(comment)));
+        }
+        ret.push_str("    providers:\n");
+        ret.push_str(&format!("      - name: {}\n", quote(provider)));
+        ret.push_str(&format!("        zones: [{}]\n", quote(zone)));
+    }
+
+    if !skipped.is_empty() {
+        ret.push_str("\n# Not imported:\n");
+        for reason in skipped.iter() {
+            log::warn!("not imported: {}", reason);
+            ret.push_str(&format!("# - {}\n", reason));
+        }
+    }
+    ret
+}
+
+// Name relative to the zone, the zone itself for its apex
+fn relative_name(name: &str, zone: &str) -> Option<String> {
+    if name.eq_ignore_ascii_case(zone) {
+        return Some(zone.to_string());
+    }
+    let suffix = format!(".{}", zone);
+    if name.len() > suffix.len() && name[name.len() - suffix.len()..].eq_ignore_ascii_case(&suffix)
+    {
+        return Some(name[..name.len() - suffix.len()].to_string());
+    }
+    None
+}
+
+fn describe(entry: &ZoneEntry) -> String {
+    format!("{} {} {}", entry.name, entry.r#type, entry.data)
+}
+
+// JSON strings are valid YAML scalars and never need more escaping
+fn quote(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use dns_syncer::zonefile::parse_bind;
+
+    #[test]
+    fn test_to_yaml() {
+        let content = r#"
+$TTL 1h
+@       IN  SOA ns1.example.org. admin.example.org. 1 3600 900 604800 300
+@       IN  A   1.2.3.4
+home    300 IN  A   1.2.3.5 ; home router
+www         IN  CNAME home
+apex        IN  CNAME @
+ext.other.org. A 5.6.7.8
+"#;
+        let entries = parse_bind(content, Some("example.org")).unwrap();
+        let yaml = to_yaml(&entries, "cloudflare-1", "example.org");
+
+        let value: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        let records = value["records"].as_sequence().unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0]["name"], "example.org");
+        assert_eq!(records[1]["name"], "home");
+        assert_eq!(records[1]["ttl"], 300);
+        assert_eq!(records[1]["comment"], "home router");
+        assert_eq!(records[2]["content"], "home.example.org");
+        assert_eq!(records[2]["providers"][0]["zones"][0], "example.org");
+        assert_eq!(records[3]["content"], "example.org");
+
+        assert!(yaml.contains("# - example.org SOA"));
+        assert!(yaml.contains("# - ext.other.org A 5.6.7.8 is outside of zone example.org"));
+    }
+}
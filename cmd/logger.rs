@@ -0,0 +1,355 @@
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::SecondsFormat;
+use chrono::Utc;
+use clap::ValueEnum;
+use log::Level;
+use log::LevelFilter;
+use log::Log;
+use log::Metadata;
+use log::Record;
+use log::kv::Key;
+use log::kv::Value;
+use log::kv::VisitSource;
+use serde_json::Map;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// Logger writing one line per event to stderr, or to syslog when a target is
+// given. Key-values attached to a log call (provider, zone, record, outcome,
+// ...) become fields in json mode and trailing `key=value` pairs in text mode.
+pub struct Logger {
+    format: LogFormat,
+    level: LevelFilter,
+    syslog: Option<Syslog>,
+}
+
+impl Logger {
+    pub fn init(format: LogFormat, level: LevelFilter, syslog: Option<&SyslogTarget>) {
+        let syslog = match syslog.map(Syslog::connect) {
+            Some(Ok(syslog)) => Some(syslog),
+            Some(Err(e)) => {
+                let _ = writeln!(std::io::stderr(), "cannot connect to syslog: {}", e);
+                None
+            }
+            None => None,
+        };
+
+        let logger = Box::new(Self {
+            format,
+            level,
+            syslog,
+        });
+        if log::set_boxed_logger(logger).is_ok() {
+            log::set_max_level(level);
+        }
+    }
+
+    fn format_text(record: &Record) -> String {
+        let mut fields = TextFields(String::new());
+        let _ = record.key_values().visit(&mut fields);
+
+        format!(
+            "{} {:5} {}{}",
+            timestamp(),
+            record.level(),
+            record.args(),
+            fields.0
+        )
+    }
+
+    fn format_message(record: &Record) -> String {
+        let mut fields = TextFields(String::new());
+        let _ = record.key_values().visit(&mut fields);
+        format!("{}{}", record.args(), fields.0)
+    }
+
+    fn format_json(record: &Record) -> String {
+        let mut fields = JsonFields(Map::new());
+        fields.0.insert("timestamp".to_string(), timestamp().into());
+        fields
+            .0
+            .insert("level".to_string(), level_name(record.level()).into());
+        fields
+            .0
+            .insert("target".to_string(), record.target().into());
+        fields
+            .0
+            .insert("message".to_string(), record.args().to_string().into());
+        let _ = record.key_values().visit(&mut fields);
+
+        serde_json::Value::Object(fields.0).to_string()
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if let Some(syslog) = self.syslog.as_ref() {
+            // Syslog headers carry the timestamp and level already
+            let message = match self.format {
+                LogFormat::Text => Self::format_message(record),
+                LogFormat::Json => Self::format_json(record),
+            };
+            syslog.send(record.level(), &message);
+            return;
+        }
+
+        let line = match self.format {
+            LogFormat::Text => Self::format_text(record),
+            LogFormat::Json => Self::format_json(record),
+        };
+        let _ = writeln!(std::io::stderr(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Syslog
+////////////////////////////////////////////////////////////
+const SYSLOG_LOCAL_SOCKET: &str = "/dev/log";
+// The `daemon` facility
+const SYSLOG_FACILITY: u8 = 3;
+
+// `local` for the local syslog socket, `host:port` for a collector over UDP
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyslogTarget {
+    Local(PathBuf),
+    Udp(String),
+}
+
+impl FromStr for SyslogTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(SyslogTarget::Local(PathBuf::from(SYSLOG_LOCAL_SOCKET))),
+            s if s.starts_with('/') => Ok(SyslogTarget::Local(PathBuf::from(s))),
+            s if s.contains(':') => Ok(SyslogTarget::Udp(
+                s.strip_prefix("udp://").unwrap_or(s).to_string(),
+            )),
+            s => Err(format!(
+                "syslog target {} is neither `local`, a socket path nor host:port",
+                s
+            )),
+        }
+    }
+}
+
+enum SyslogSocket {
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    Udp(UdpSocket, SocketAddr),
+}
+
+// RFC 5424 messages over a datagram socket
+struct Syslog {
+    socket: SyslogSocket,
+    hostname: String,
+    pid: u32,
+}
+
+impl Syslog {
+    fn connect(target: &SyslogTarget) -> std::io::Result<Self> {
+        let socket = match target {
+            #[cfg(unix)]
+            SyslogTarget::Local(path) => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                SyslogSocket::Unix(socket)
+            }
+            #[cfg(not(unix))]
+            SyslogTarget::Local(_) => {
+                return Err(std::io::Error::other(
+                    "local syslog is only available on unix",
+                ));
+            }
+            SyslogTarget::Udp(addr) => {
+                let addr = addr
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or(std::io::Error::other("syslog address resolves to nothing"))?;
+                let bind = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                SyslogSocket::Udp(UdpSocket::bind(bind)?, addr)
+            }
+        };
+
+        Ok(Self {
+            socket,
+            hostname: hostname(),
+            pid: std::process::id(),
+        })
+    }
+
+    fn send(&self, level: Level, message: &str) {
+        let line = syslog_line(level, &timestamp(), &self.hostname, self.pid, message);
+        let _ = match &self.socket {
+            #[cfg(unix)]
+            SyslogSocket::Unix(socket) => socket.send(line.as_bytes()),
+            SyslogSocket::Udp(socket, addr) => socket.send_to(line.as_bytes(), addr),
+        };
+    }
+}
+
+fn syslog_line(level: Level, timestamp: &str, hostname: &str, pid: u32, message: &str) -> String {
+    let severity = match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    };
+    format!(
+        "<{}>1 {} {} dns-syncer {} - - {}",
+        SYSLOG_FACILITY * 8 + severity,
+        timestamp,
+        hostname,
+        pid,
+        message
+    )
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or("-".to_string())
+}
+
+fn timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warn => "warn",
+        Level::Info => "info",
+        Level::Debug => "debug",
+        Level::Trace => "trace",
+    }
+}
+
+struct TextFields(String);
+
+impl<'kvs> VisitSource<'kvs> for TextFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        self.0.push_str(&format!(" {}={}", key, value));
+        Ok(())
+    }
+}
+
+struct JsonFields(Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        let value = match (value.to_u64(), value.to_bool()) {
+            (Some(n), _) => n.into(),
+            (_, Some(b)) => b.into(),
+            _ => value.to_string().into(),
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_syslog_target() {
+        assert_eq!(
+            "local".parse::<SyslogTarget>(),
+            Ok(SyslogTarget::Local(PathBuf::from("/dev/log")))
+        );
+        assert_eq!(
+            "udp://logs.internal:514".parse::<SyslogTarget>(),
+            Ok(SyslogTarget::Udp("logs.internal:514".to_string()))
+        );
+        assert!("logs.internal".parse::<SyslogTarget>().is_err());
+    }
+
+    #[test]
+    fn test_syslog_line() {
+        assert_eq!(
+            syslog_line(
+                Level::Warn,
+                "2025-01-01T00:00:00.000Z",
+                "router",
+                42,
+                "zone example.org not found"
+            ),
+            "<28>1 2025-01-01T00:00:00.000Z router dns-syncer 42 - - zone example.org not found"
+        );
+    }
+
+    #[test]
+    fn test_format_json() {
+        let fields: &[(&str, &dyn log::kv::ToValue)] = &[
+            ("provider", &"cf \"1\""),
+            ("requests", &3u64),
+            ("dry_run", &true),
+        ];
+        let line = Logger::format_json(
+            &Record::builder()
+                .level(Level::Warn)
+                .target("dns_syncer::runner")
+                .args(format_args!(
+                    "zone {} failed:\n\"{}\"",
+                    "example.org", "down"
+                ))
+                .key_values(&fields)
+                .build(),
+        );
+
+        // One line, whatever the message holds
+        assert!(!line.contains('\n'), "{}", line);
+        assert!(line.contains(r#""message":"zone example.org failed:\n\"down\"""#));
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["level"], "warn");
+        assert_eq!(json["target"], "dns_syncer::runner");
+        assert_eq!(json["message"], "zone example.org failed:\n\"down\"");
+        assert_eq!(json["provider"], "cf \"1\"");
+        assert_eq!(json["requests"], 3);
+        assert_eq!(json["dry_run"], true);
+        assert!(json["timestamp"].as_str().unwrap().ends_with('Z'));
+
+        let line = Logger::format_message(
+            &Record::builder()
+                .args(format_args!("provider synced"))
+                .key_values(&fields)
+                .build(),
+        );
+        assert_eq!(
+            line,
+            "provider synced provider=cf \"1\" requests=3 dry_run=true"
+        );
+    }
+}
@@ -1,255 +1,1325 @@
-use std::collections::HashMap;
-use std::process::exit;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
-use tokio;
+use clap::Subcommand;
+use tokio::sync::Notify;
 
+use dns_syncer::CancellationToken;
+use dns_syncer::Config;
+use dns_syncer::DnsSyncer;
+use dns_syncer::Runner;
+use dns_syncer::config::CfgRecordItem;
+use dns_syncer::config::Parser as ConfigParser;
+use dns_syncer::config::RecordFilter;
+use dns_syncer::error::Error;
 use dns_syncer::error::Result;
-use dns_syncer::fetcher::Fetcher;
-use dns_syncer::fetcher::HttpFetcher;
-use dns_syncer::provider::BackendRecords;
-use dns_syncer::provider::Cloudflare;
-use dns_syncer::provider::Provider;
-use dns_syncer::types::FetcherRecordSet;
-use dns_syncer::types::ZoneName;
+use dns_syncer::fetcher::AddressMonitor;
+use dns_syncer::fetcher::NetworkEvents;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::provider::ProviderRegistry;
+use dns_syncer::provider::provider_types;
+use dns_syncer::record::RecordType;
+use dns_syncer::runner::Fixture;
+use dns_syncer::server::CONTROL_COMMANDS;
+use dns_syncer::server::Control;
+use dns_syncer::server::ExternalDnsWebhook;
+use dns_syncer::server::Server;
+use dns_syncer::server::send_control_command;
+use dns_syncer::state::State;
+use dns_syncer::syncer;
+use dns_syncer::types::parse_duration;
+use dns_syncer::verify::CheckStatus;
+use dns_syncer::verify::DEFAULT_RESOLVERS;
+use dns_syncer::verify::parse_resolver;
+use dns_syncer::zonefile;
 
-mod config;
+mod drift;
+mod exit_code;
+mod export;
+mod fetchers;
+mod history;
+mod import;
+mod logger;
+mod plan;
+mod providers;
+mod purge;
+mod simulate;
+mod status;
+mod token;
+mod verify;
+mod zones;
 
-type FetcherMap = HashMap<String, Box<dyn Fetcher>>;
-type ProviderMap = HashMap<String, Box<dyn Provider>>;
+use exit_code::ExitCode;
+use exit_code::OrExit;
 
 #[derive(Parser)]
 struct Args {
+    /// Config file, needed by every command but `providers`
     #[clap(short, long)]
-    config: String,
+    config: Option<String>,
+
+    /// Name of the profile in the config file whose records are synced
+    #[clap(short, long)]
+    profile: Option<String>,
+
+    /// Only sync records with one of these tags
+    #[clap(long, value_delimiter = ',')]
+    tags: Vec<String>,
+
+    /// Do not sync records with one of these tags
+    #[clap(long, value_delimiter = ',')]
+    skip_tags: Vec<String>,
+
+    /// Format of command results: table, json or yaml
+    #[clap(long, global = true, default_value = "table")]
+    output: OutputFormat,
+
+    /// Format of log lines written to stderr
+    #[clap(long, value_enum, default_value = "text")]
+    log_format: logger::LogFormat,
+
+    /// Most verbose level logged: error, warn, info, debug or trace
+    #[clap(long, default_value = "info")]
+    log_level: log::LevelFilter,
+
+    /// Send logs to syslog instead of stderr: `local` or a socket path for the
+    /// local daemon, `host:port` for a collector over UDP
+    #[clap(long)]
+    syslog: Option<logger::SyslogTarget>,
+
+    /// Without a command records are synced like `sync` does, this is deprecated
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+impl Args {
+    // `--json` of a command is a shorthand for `--output json`
+    fn output_format(&self, json: bool) -> OutputFormat {
+        if json {
+            OutputFormat::Json
+        } else {
+            self.output
+        }
+    }
+
+    // How records are synced, `None` if the command does not sync
+    fn sync_mode(&self, check_interval: Duration) -> Option<Result<SyncMode>> {
+        let (once, daemon) = match self.command {
+            Some(Command::Sync { once, daemon, .. }) => (once, daemon),
+            Some(Command::Watch { .. }) => return Some(Ok(SyncMode::Watch)),
+            None => (false, false),
+            Some(_) => return None,
+        };
+        let mode = if once {
+            Ok(SyncMode::Once)
+        } else if daemon && check_interval.is_zero() {
+            Err(Error::ParseError(
+                "--daemon needs a check_interval greater than zero".to_string(),
+            ))
+        } else if daemon || !check_interval.is_zero() {
+            Ok(SyncMode::Daemon)
+        } else {
+            Ok(SyncMode::Once)
+        };
+        Some(mode)
+    }
+
+    // Context of the errors blamed on the config
+    fn invalid_config(&self) -> String {
+        format!(
+            "invalid config {}",
+            self.config.as_deref().unwrap_or_default()
+        )
+    }
+
+    // Records the sync is restricted to, everything when no filter is given
+    fn record_filter(&self) -> RecordFilter {
+        match &self.command {
+            Some(Command::Sync {
+                record,
+                zone,
+                provider,
+                ..
+            }) => RecordFilter {
+                record: record.clone(),
+                zone: zone.clone(),
+                provider: provider.clone(),
+            },
+            _ => RecordFilter::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SyncMode {
+    // Run a single sync cycle and exit with its exit code
+    Once,
+    // Sync every check_interval until killed
+    Daemon,
+    // Sync on address changes until killed
+    Watch,
+}
+
+#[derive(Subcommand)]
+enum FetchersCommand {
+    /// Run every fetcher backend and print its address and latency, pointing out
+    /// backends disagreeing with the majority
+    Test {
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenCommand {
+    /// Check the provider credentials are active and have the permissions
+    /// syncing needs on every zone of the config
+    Verify {
+        /// Only check this provider
+        #[clap(long)]
+        provider: Option<String>,
+
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
 }
 
-#[tokio::main(flavor = "current_thread")]
+#[derive(Subcommand)]
+enum Command {
+    /// Sync the records. Without a flag the tool runs once when check_interval is
+    /// zero and as a daemon otherwise.
+    Sync {
+        /// Run a single sync cycle and exit, whatever check_interval is
+        #[clap(long, conflicts_with = "daemon")]
+        once: bool,
+
+        /// Keep syncing every check_interval until killed
+        #[clap(long)]
+        daemon: bool,
+
+        /// Only sync records whose name matches the glob, relative or full,
+        /// e.g. home or '*.example.com'
+        #[clap(long)]
+        record: Option<String>,
+
+        /// Only sync records in this zone
+        #[clap(long)]
+        zone: Option<String>,
+
+        /// Only sync records of this provider
+        #[clap(long)]
+        provider: Option<String>,
+
+        /// Also sync right away when NetworkManager or systemd-networkd report
+        /// a connectivity or address change on D-Bus. Needs the `dbus` feature.
+        #[clap(long, conflicts_with = "once")]
+        dbus: bool,
+    },
+
+    /// Sync when an interface address changes, and every `--fallback` in case
+    /// the change happened upstream. Linux only, elsewhere it polls.
+    Watch {
+        /// Only react to address changes of this interface, e.g. the WAN one
+        #[clap(long)]
+        interface: Option<String>,
+
+        /// Sync at least this often, e.g. 30m. Zero disables it.
+        #[clap(long, default_value = "1h", value_parser = parse_duration)]
+        fallback: Duration,
+    },
+
+    /// Delete a record from a provider. Only records written by dns-syncer are
+    /// deleted unless forced.
+    Delete {
+        /// Full name of the record, e.g. home.example.com
+        #[clap(long)]
+        record: String,
+
+        /// Name of the provider in the config file
+        #[clap(long)]
+        provider: String,
+
+        /// Zone of the record, guessed from the zones of the provider when not given
+        #[clap(long)]
+        zone: Option<String>,
+
+        /// Only delete the records of this type, e.g. AAAA
+        #[clap(long = "type", value_parser = parse_record_type)]
+        record_type: Option<RecordType>,
+
+        /// Also delete records without the dns-syncer owner marker
+        #[clap(long)]
+        force: bool,
+    },
+
+    /// List the records written by dns-syncer that no config record produces
+    /// anymore, in every zone the providers can access
+    PurgeOrphans {
+        /// Delete the orphaned records instead of listing them
+        #[clap(long)]
+        apply: bool,
+
+        /// Only look at this provider
+        #[clap(long)]
+        provider: Option<String>,
+
+        /// Only look at this zone
+        #[clap(long)]
+        zone: Option<String>,
+
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Write records as a RFC 1035 zone file or CSV, the desired ones from the
+    /// config or with `--live` the ones a provider serves
+    Export {
+        /// Export what the provider serves instead of the config, needs
+        /// `--provider` and `--zone`
+        #[clap(long, requires_all = ["provider", "zone"])]
+        live: bool,
+
+        /// Only export records of this provider
+        #[clap(long)]
+        provider: Option<String>,
+
+        /// Only export records of this zone
+        #[clap(long)]
+        zone: Option<String>,
+
+        #[clap(long, value_enum, default_value = "bind")]
+        format: export::ExportFormat,
+    },
+
+    /// List the provider types compiled in with their record types,
+    /// authentication methods and params
+    Providers {
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Show the last sync cycles and public ip changes of the state file
+    History {
+        /// Number of sync cycles shown
+        #[clap(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Print the records YAML for the records of a BIND zone file. Records of
+    /// types that cannot be synced are listed as comments.
+    Import {
+        /// Zone file to read
+        file: PathBuf,
+
+        /// Name of the provider in the config file syncing the records
+        #[clap(long)]
+        provider: String,
+
+        /// Zone the records belong to, also the origin of relative names
+        #[clap(long)]
+        zone: String,
+    },
+
+    /// Resolve every configured record at public resolvers and compare the
+    /// answers with the desired content
+    Verify {
+        /// Resolver to ask, `ip` or `ip:port`, may be repeated
+        #[clap(long = "resolver", default_values_t = DEFAULT_RESOLVERS.map(String::from))]
+        resolvers: Vec<String>,
+
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Run the configured fetchers and print the address every backend detects.
+    /// Providers are not touched. Same as `fetchers test`.
+    Ip {
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// List the zones the credentials of every configured provider can access,
+    /// with their ids and plans
+    Zones {
+        /// Only ask this provider
+        #[clap(long)]
+        provider: Option<String>,
+
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Troubleshoot the provider credentials
+    Token {
+        #[clap(subcommand)]
+        command: TokenCommand,
+    },
+
+    /// Troubleshoot the configured fetchers
+    Fetchers {
+        #[clap(subcommand)]
+        command: FetchersCommand,
+    },
+
+    /// Run a sync against in-memory providers and fixed public addresses, for
+    /// testing a config without credentials or network
+    Simulate {
+        /// YAML file with the records the providers serve before the sync and
+        /// the public addresses
+        #[clap(long)]
+        fixture: Option<PathBuf>,
+
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Show the changes a sync would make, without making them
+    Plan {
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Compare the records the providers serve with the config every
+    /// `drift.interval` and report the ones changed outside dns-syncer. They
+    /// are only synced back with `drift.reconcile`.
+    Drift {
+        /// Check once and exit, with exit code 7 when records drifted
+        #[clap(long)]
+        check: bool,
+
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Send a command to the control socket of a running daemon
+    Ctl {
+        #[clap(value_parser = clap::builder::PossibleValuesParser::new(CONTROL_COMMANDS))]
+        command: String,
+
+        /// Socket of the daemon, `control.socket` of the config by default
+        #[clap(long)]
+        socket: Option<PathBuf>,
+    },
+
+    /// Show the public ip, the last sync of every provider and the records
+    Status {
+        /// Query the providers for the content they serve, read only
+        #[clap(long)]
+        live: bool,
+
+        /// Same as `--output json`
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Serve the external-dns webhook provider protocol, so external-dns
+    /// manages records through a provider of the config. Run external-dns with
+    /// `--provider=webhook --registry=noop`.
+    Webhook {
+        /// Name of the provider in the config file
+        provider: String,
+
+        /// Address the webhook listens on
+        #[clap(long, default_value = "127.0.0.1:8888")]
+        listen: SocketAddr,
+
+        /// Zone handed to external-dns, may be repeated. All zones the
+        /// provider can access by default.
+        #[clap(long = "zone")]
+        zones: Vec<String>,
+    },
+}
+
+#[tokio::main]
 async fn main() {
     let args = Args::parse();
+    logger::Logger::init(args.log_format, args.log_level, args.syslog.as_ref());
+
+    let code = match &args.command {
+        Some(Command::Providers { json }) => {
+            providers::print(&provider_types(), args.output_format(*json))
+                .or_exit(ExitCode::Failure);
+            ExitCode::Success
+        }
+        Some(Command::Ctl { command, socket }) => {
+            send_control(&args, command, socket.as_deref()).await
+        }
+        Some(Command::History { limit, json }) => {
+            show_history(&args, *limit, args.output_format(*json))
+        }
+        Some(Command::Export {
+            live,
+            provider,
+            zone,
+            format,
+        }) => export(&args, *live, provider.as_deref(), zone.as_deref(), *format).await,
+        Some(Command::Import {
+            file,
+            provider,
+            zone,
+        }) => import(&args, file, provider, zone),
+        Some(Command::Verify { resolvers, json }) => {
+            verify(&args, resolvers, args.output_format(*json)).await
+        }
+        Some(Command::Ip { json })
+        | Some(Command::Fetchers {
+            command: FetchersCommand::Test { json },
+        }) => test_fetchers(&args, args.output_format(*json)).await,
+        Some(Command::Status { live, json }) => {
+            show_status(&args, *live, args.output_format(*json)).await
+        }
+        Some(Command::Simulate { fixture, json }) => {
+            simulate(&args, fixture.as_deref(), args.output_format(*json)).await
+        }
+        Some(Command::Zones { provider, json }) => {
+            list_zones(&args, provider.as_deref(), args.output_format(*json)).await
+        }
+        Some(Command::Token {
+            command: TokenCommand::Verify { provider, json },
+        }) => check_credentials(&args, provider.as_deref(), args.output_format(*json)).await,
+        Some(Command::PurgeOrphans {
+            apply,
+            provider,
+            zone,
+            json,
+        }) => {
+            let output = args.output_format(*json);
+            purge_orphans(&args, provider.as_deref(), zone.as_deref(), *apply, output).await
+        }
+        Some(Command::Delete {
+            record,
+            provider,
+            zone,
+            record_type,
+            force,
+        }) => {
+            let record_type = record_type.clone();
+            delete_record(
+                &args,
+                record,
+                provider,
+                zone.as_deref(),
+                record_type,
+                *force,
+            )
+            .await
+        }
+        Some(Command::Webhook {
+            provider,
+            listen,
+            zones,
+        }) => serve_webhook(&args, provider, *listen, zones).await,
+        Some(Command::Plan { json }) => plan(&args, args.output_format(*json)).await,
+        Some(Command::Drift { check, json }) => {
+            check_drift(&args, *check, args.output_format(*json)).await
+        }
+        Some(Command::Watch {
+            interface,
+            fallback,
+        }) => watch(&args, interface.as_deref(), *fallback).await,
+        Some(Command::Sync { .. }) => sync(&args).await,
+        None => {
+            log::warn!("running without a command is deprecated, use `sync`");
+            sync(&args).await
+        }
+    };
+    code.exit();
+}
+
+// The config of `--config` with the profile selected
+fn load_config(args: &Args) -> Config {
+    let Some(path) = args.config.as_deref() else {
+        log::error!("--config is required");
+        ExitCode::Config.exit();
+    };
+    ConfigParser::parse_yaml(path)
+        .and_then(|cfg| cfg.select_profile(args.profile.as_deref()))
+        .or_exit_with(ExitCode::Config, &args.invalid_config())
+}
+
+// Secrets are only resolved by the commands talking to providers
+async fn resolve_secrets(mut config: Config) -> Config {
+    config
+        .resolve_secrets()
+        .await
+        .or_exit_with(ExitCode::Auth, "resolving secrets failed");
+    config
+}
+
+// Runner of the records selected by the command line, its credentials checked
+async fn verified_runner(config: Config, args: &Args) -> Runner {
+    let runner = init_runner(config, args, &ProviderRegistry::new())
+        .or_exit_with(ExitCode::Config, &args.invalid_config());
+    if let Err(e) = runner.verify().await {
+        log::error!(outcome = "failed"; "credential verification failed: {}", e);
+        ExitCode::from_verify_error(&e).exit();
+    }
+    runner
+}
+
+// Print the answer of the daemon to `command`, 1 when it refused it
+async fn send_control(args: &Args, command: &str, socket: Option<&Path>) -> ExitCode {
+    let socket = match socket {
+        Some(socket) => socket.to_path_buf(),
+        None => {
+            let Some(socket) = load_config(args).control_socket() else {
+                log::error!("no control socket is configured, see --socket");
+                return ExitCode::Config;
+            };
+            socket
+        }
+    };
+    let reply = send_control_command(&socket, command).await.or_exit_with(
+        ExitCode::Failure,
+        &format!("control socket {}", socket.display()),
+    );
+    let rendered = match args.output {
+        OutputFormat::Table => Ok(serde_json::to_string_pretty(&reply).unwrap_or_default()),
+        output => output.render(&reply),
+    };
+    match rendered {
+        Ok(text) => println!("{}", text),
+        Err(e) => log::error!("{}", e),
+    }
+    if reply["ok"] != true {
+        return ExitCode::Failure;
+    }
+    ExitCode::Success
+}
+
+fn show_history(args: &Args, limit: usize, output: OutputFormat) -> ExitCode {
+    let Some(path) = load_config(args).state_file() else {
+        log::error!("no state file is configured");
+        return ExitCode::Config;
+    };
+    State::load(&path)
+        .and_then(|state| history::print(&state, limit, output))
+        .or_exit(ExitCode::Failure);
+    ExitCode::Success
+}
+
+// The desired records of the config, or with `live` what the provider serves
+async fn export(
+    args: &Args,
+    live: bool,
+    provider: Option<&str>,
+    zone: Option<&str>,
+    format: export::ExportFormat,
+) -> ExitCode {
+    let config = load_config(args);
+    let records = match (live, provider, zone) {
+        (true, Some(provider), Some(zone)) => {
+            let config = resolve_secrets(config).await;
+            let instance = DnsSyncer::new()
+                .provider(&config, provider)
+                .or_exit_with(ExitCode::Config, &args.invalid_config());
+            instance
+                .list_zone_records(&zone.to_string())
+                .await
+                .or_exit_with(ExitCode::Provider, &format!("listing zone {} failed", zone))
+        }
+        _ => selected_records(&config, args)
+            .and_then(|records| syncer::desired_records(&config, records, provider, zone))
+            .or_exit(ExitCode::Failure),
+    };
+    export::print(&records, format);
+    ExitCode::Success
+}
+
+fn import(args: &Args, file: &Path, provider: &str, zone: &str) -> ExitCode {
+    let config = load_config(args);
+    if !config.providers.iter().any(|p| p.name == provider) {
+        log::warn!("provider {} is not configured yet", provider);
+    }
+    let entries = std::fs::read_to_string(file)
+        .map_err(Error::from)
+        .and_then(|content| zonefile::parse_bind(&content, Some(zone)))
+        .or_exit_with(ExitCode::Failure, &file.display().to_string());
+    print!("{}", import::to_yaml(&entries, provider, zone));
+    ExitCode::Success
+}
 
-    let config = config::Parser::parse_yaml(&args.config).unwrap();
-    let mut runner = init_runner(config).unwrap();
-    runner.run().await.unwrap();
+// 1 unless every record resolves to its desired content
+async fn verify(args: &Args, resolvers: &[String], output: OutputFormat) -> ExitCode {
+    let config = load_config(args);
+    let resolvers = resolvers
+        .iter()
+        .map(|r| parse_resolver(r))
+        .collect::<Result<Vec<_>>>()
+        .or_exit(ExitCode::Config);
+    let records = selected_records(&config, args).or_exit(ExitCode::Config);
+    let checks = syncer::verify_records(&config, records, &resolvers)
+        .await
+        .or_exit(ExitCode::Config);
+    verify::print(&checks, output).or_exit(ExitCode::Failure);
 
-    exit(1);
+    let healthy = [CheckStatus::Ok, CheckStatus::Proxied];
+    if !checks.iter().all(|c| healthy.contains(&c.status)) {
+        return ExitCode::Failure;
+    }
+    ExitCode::Success
+}
 
-    // // The key is the provider name, value is the backend records per zone
-    // let record_per_provider = to_backend_records(records).unwrap();
-    // //dbg!(&record_per_provider);
+// 4 when no backend answered, 1 when backends disagree
+async fn test_fetchers(args: &Args, output: OutputFormat) -> ExitCode {
+    let probes = fetchers::probe(&load_config(args)).await;
+    fetchers::print(&probes, output).or_exit(ExitCode::Failure);
 
-    // let providers = providers
-    //     .into_iter()
-    //     .filter_map(|p| {
-    //         if record_per_provider.contains_key(&p.name) {
-    //             Some((p.name.clone(), instance_provider(p).unwrap()))
-    //         } else {
-    //             None
-    //         }
-    //     })
-    //     .collect::<HashMap<_, _>>();
+    let failed = probes
+        .iter()
+        .flat_map(|p| p.backends.iter())
+        .all(|b| b.v4.ip.is_none() && b.v6.ip.is_none());
+    if failed {
+        return ExitCode::Fetch;
+    }
+    if !fetchers::Consensus::new(&probes)
+        .disagreeing(&probes)
+        .is_empty()
+    {
+        return ExitCode::Failure;
+    }
+    ExitCode::Success
+}
 
-    // dbg!(
-    //     &providers
-    //         .iter()
-    //         .map(|(name, _)| name.clone())
-    //         .collect::<Vec<_>>()
-    // );
+// The state file tells the public ip and provider outcomes, providers are only
+// asked for the records they serve with `live`
+async fn show_status(args: &Args, live: bool, output: OutputFormat) -> ExitCode {
+    let mut config = load_config(args);
+    let state = match config.state_file() {
+        Some(path) => State::load(&path),
+        None => Ok(State::default()),
+    }
+    .or_exit(ExitCode::Failure);
+    if live {
+        config = resolve_secrets(config).await;
+    }
 
-    // let global_records_clone = global_records.clone();
-    // for (provider_name, records) in record_per_provider.iter() {
-    //     println!("provider_name: {}", provider_name);
-    //     let provider = providers.get(provider_name).unwrap();
-    //     provider
-    //         .sync(records.clone(), global_records_clone.clone().into())
-    //         .await
-    //         .unwrap();
-    // }
+    let records = selected_records(&config, args).or_exit(ExitCode::Failure);
+    let records = DnsSyncer::new()
+        .record_status(&config, records, state.public_ip.as_ref(), live)
+        .await
+        .or_exit(ExitCode::Failure);
+    status::print(&state, &records, output).or_exit(ExitCode::Failure);
+    ExitCode::Success
 }
 
-#[derive(Debug, Clone, Default)]
-struct ProviderBackend {
-    record: BackendRecords,
-    fetchers: Vec<String>,
+// Exits like `sync --once` would with the fixture as the providers
+async fn simulate(args: &Args, fixture: Option<&Path>, output: OutputFormat) -> ExitCode {
+    let config = load_config(args);
+    let fixture = fixture
+        .map(Fixture::load)
+        .transpose()
+        .or_exit_with(ExitCode::Config, &args.invalid_config())
+        .unwrap_or_default();
+    let mut runner = selected_records(&config, args)
+        .and_then(|records| Runner::simulated(config, records, &fixture))
+        .or_exit_with(ExitCode::Config, &args.invalid_config());
+
+    if let Err(e) = runner.verify().await {
+        log::error!(outcome = "failed"; "credential verification failed: {}", e);
+        return ExitCode::from_verify_error(&e);
+    }
+    let simulation = runner
+        .simulate()
+        .await
+        .or_exit_with(ExitCode::Config, &args.invalid_config());
+    simulate::print(&simulation, output).or_exit(ExitCode::Failure);
+    ExitCode::from_runner(&runner)
 }
 
-struct Runner {
-    global_fetcher_name: String,
-    fetchers: FetcherMap,
-    providers: ProviderMap,
-    record_per_provider: HashMap<String, ProviderBackend>,
+// 5 when a provider failed to list its zones
+async fn list_zones(args: &Args, provider: Option<&str>, output: OutputFormat) -> ExitCode {
+    let config = resolve_secrets(load_config(args)).await;
+    let records =
+        selected_records(&config, args).or_exit_with(ExitCode::Config, &args.invalid_config());
+    let zones = DnsSyncer::new()
+        .list_zones(&config, records, provider)
+        .await
+        .or_exit_with(ExitCode::Config, &args.invalid_config());
+    zones::print(&zones, output).or_exit(ExitCode::Failure);
+
+    if zones.iter().any(|z| z.error.is_some()) {
+        return ExitCode::Provider;
+    }
+    ExitCode::Success
 }
 
-fn init_runner(config: config::Cfg) -> Result<Runner> {
-    let config::Cfg {
-        check_interval: _,
-        providers,
-        fetchers,
-        records,
-        public_ip_fecher,
-    } = config;
+// 5 when a provider could not be checked, 3 when credentials lack something
+async fn check_credentials(args: &Args, provider: Option<&str>, output: OutputFormat) -> ExitCode {
+    let config = resolve_secrets(load_config(args)).await;
+    let records =
+        selected_records(&config, args).or_exit_with(ExitCode::Config, &args.invalid_config());
+    let checks = DnsSyncer::new()
+        .check_credentials(&config, records, provider)
+        .await
+        .or_exit_with(ExitCode::Config, &args.invalid_config());
+    token::print(&checks, output).or_exit(ExitCode::Failure);
 
-    let fetchers = create_fetchers(&records, &public_ip_fecher, &fetchers).unwrap();
-    let providers = create_providers(&records, &providers).unwrap();
+    if checks.iter().any(|c| c.error.is_some()) {
+        return ExitCode::Provider;
+    }
+    if !checks.iter().all(|c| c.is_ok()) {
+        return ExitCode::Auth;
+    }
+    ExitCode::Success
+}
 
-    // The key is the provider name, value is the backend records per zone
-    let record_per_provider = to_provider_backends(records).unwrap();
+// 5 when a zone could not be listed or purged
+async fn purge_orphans(
+    args: &Args,
+    provider: Option<&str>,
+    zone: Option<&str>,
+    apply: bool,
+    output: OutputFormat,
+) -> ExitCode {
+    let config = resolve_secrets(load_config(args)).await;
+    let zones = DnsSyncer::new()
+        .purge_orphans(&config, provider, zone, apply)
+        .await
+        .or_exit_with(ExitCode::Config, &args.invalid_config());
+    purge::print(&zones, output).or_exit(ExitCode::Failure);
 
-    Ok(Runner {
-        global_fetcher_name: public_ip_fecher.to_string(),
-        fetchers,
-        providers,
-        record_per_provider,
-    })
+    if zones.iter().any(|z| z.error.is_some()) {
+        return ExitCode::Provider;
+    }
+    ExitCode::Success
 }
 
-impl Runner {
-    async fn run(&mut self) -> Result<()> {
-        let public_ip = self.fetch_public_ip().await?;
+async fn delete_record(
+    args: &Args,
+    record: &str,
+    provider: &str,
+    zone: Option<&str>,
+    record_type: Option<RecordType>,
+    force: bool,
+) -> ExitCode {
+    let config = resolve_secrets(load_config(args)).await;
+    let syncer = DnsSyncer::new();
+    let instance = syncer
+        .provider(&config, provider)
+        .or_exit_with(ExitCode::Config, &args.invalid_config());
+    let deleted = syncer
+        .delete_record(
+            &config,
+            instance.as_ref(),
+            provider,
+            zone,
+            record,
+            record_type,
+            force,
+        )
+        .await
+        .or_exit_with(ExitCode::Provider, &format!("deleting {} failed", record));
+
+    for r in deleted.iter() {
+        println!(
+            "deleted {} {} {}",
+            r.name,
+            r.content.record_type().as_str(),
+            r.content
+        );
+    }
+    if deleted.is_empty() {
+        println!("no record named {} found", record);
+    }
+    ExitCode::Success
+}
+
+// Serve until a signal, all zones of the provider when `zones` is empty
+async fn serve_webhook(
+    args: &Args,
+    provider: &str,
+    listen: SocketAddr,
+    zones: &[String],
+) -> ExitCode {
+    let config = resolve_secrets(load_config(args)).await;
+    let instance = DnsSyncer::new()
+        .provider(&config, provider)
+        .or_exit_with(ExitCode::Config, &args.invalid_config());
+    let zones = match zones.is_empty() {
+        true => instance.list_zones().await.or_exit_with(
+            ExitCode::Provider,
+            &format!("listing zones of {} failed", provider),
+        ),
+        false => zones.to_vec(),
+    };
+    log::info!(
+        "external-dns webhook on {} for {}",
+        listen,
+        zones.join(", ")
+    );
+    let mut server = Server::new();
+    server.add_handler(Arc::new(ExternalDnsWebhook::new(
+        Arc::from(instance),
+        zones,
+    )));
+    let served = tokio::select! {
+        ret = server.serve(listen) => ret,
+        _ = wait_for_signal() => Ok(()),
+    };
+    served.or_exit_with(
+        ExitCode::Failure,
+        &format!("http server on {} failed", listen),
+    );
+    ExitCode::Success
+}
+
+async fn plan(args: &Args, output: OutputFormat) -> ExitCode {
+    let config = resolve_secrets(load_config(args)).await;
+    let mut runner = verified_runner(config, args).await;
+    let changes = runner
+        .plan()
+        .await
+        .or_exit_with(ExitCode::Provider, "planning failed");
+    plan::print(&changes, output).or_exit(ExitCode::Failure);
+    ExitCode::Success
+}
 
-        for (provider_name, backend) in self.record_per_provider.iter() {
-            let provider = self.providers.get_mut(provider_name).unwrap();
-            provider
-                .sync(backend.record.clone(), public_ip.clone().into())
-                .await?;
+// `drift --check` prints the drifted records once, 7 when there are some.
+// Otherwise the check runs every interval until killed, the runner reports
+// drift to the notifications and the metrics, and syncs when the config
+// reconciles.
+async fn check_drift(args: &Args, check: bool, output: OutputFormat) -> ExitCode {
+    let config = resolve_secrets(load_config(args)).await;
+    let cfg = config.drift.clone().unwrap_or_default();
+    let mut runner = verified_runner(config, args).await;
+
+    if check {
+        let drifted = runner
+            .check_drift()
+            .await
+            .or_exit_with(ExitCode::Provider, "drift check failed");
+        drift::print(&drifted, output).or_exit(ExitCode::Failure);
+        if !drifted.is_empty() {
+            return ExitCode::Drift;
         }
+        return ExitCode::Success;
+    }
 
-        Ok(())
+    if cfg.interval.is_zero() {
+        log::error!("invalid config: drift.interval must be greater than zero");
+        return ExitCode::Config;
+    }
+    if let Some(listen) = runner.listen() {
+        let mut server = Server::new();
+        server.add_handler(runner.health());
+        server.add_handler(runner.drift_metrics());
+        tokio::spawn(async move {
+            if let Err(e) = server.serve(listen).await {
+                log::error!("http server on {} failed: {}", listen, e);
+            }
+        });
     }
 
-    async fn fetch_public_ip(&mut self) -> Result<FetcherRecordSet> {
-        let fetcher = self.fetchers.get_mut(&self.global_fetcher_name).unwrap();
-        fetcher.fetch().await
+    let cancel = shutdown_token();
+    while !cancel.is_cancelled() {
+        match runner.check_drift().await {
+            Ok(drifted) if !drifted.is_empty() && cfg.reconcile => {
+                log::info!("syncing {} drifted record(s) back", drifted.len());
+                let _ = runner.run(&cancel).await;
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("drift check failed: {}", e),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(cfg.interval) => {}
+            _ = cancel.cancelled() => break,
+        }
     }
+    ExitCode::Success
 }
 
-fn list_in_use_providers(records: &Vec<config::CfgRecordItem>) -> Vec<String> {
-    let mut ret = records
-        .iter()
-        .flat_map(|r| r.providers.iter().map(|p| p.name.clone()))
-        .collect::<Vec<_>>();
-    ret.sort();
-    ret.dedup();
-    ret
+// Serve the health check and the API of the runner on its HTTP listener
+fn serve_http(runner: &Runner) {
+    let Some(listen) = runner.listen() else {
+        return;
+    };
+    let mut server = Server::new();
+    server.add_handler(runner.health());
+    if let Some(api) = runner.api() {
+        server.add_handler(api);
+    }
+    tokio::spawn(async move {
+        if let Err(e) = server.serve(listen).await {
+            log::error!("http server on {} failed: {}", listen, e);
+        }
+    });
 }
 
-fn create_providers(
-    records: &Vec<config::CfgRecordItem>,
-    providers: &Vec<config::CfgProvider>,
-) -> Result<ProviderMap> {
-    let in_use_providers = list_in_use_providers(records);
+// Sync once with the exit code of the cycle, or as a daemon until killed
+async fn sync(args: &Args) -> ExitCode {
+    let config = load_config(args);
+    let Some(mode) = args.sync_mode(config.check_interval) else {
+        unreachable!("sync_mode is only `None` for commands not syncing");
+    };
+    let mode = mode.or_exit_with(ExitCode::Config, "invalid arguments");
+    let config = resolve_secrets(config).await;
+    let control_socket = config.control_socket();
+    let mut runner = verified_runner(config, args).await;
 
-    let ret = providers
-        .into_iter()
-        .filter(|f| in_use_providers.contains(&f.name))
-        .filter_map(|provider| {
-            match provider.r#type.as_str() {
-                // Create new Cloudflare provider if authentication is valid
-                "cloudflare" => {
-                    let auth = provider.authentication.clone().try_into().ok()?;
-                    let cloudflare = Cloudflare::new(auth);
-
-                    Some((
-                        provider.name.clone(),
-                        Box::new(cloudflare) as Box<dyn Provider>,
-                    ))
+    let cancel = shutdown_token();
+    if mode == SyncMode::Once {
+        let _ = runner.run(&cancel).await;
+        return ExitCode::from_runner(&runner);
+    }
+
+    serve_http(&runner);
+    let sources_changed = runner.watch_sources();
+
+    let mut network_events = None;
+    if let Some(Command::Sync { dbus: true, .. }) = &args.command {
+        match NetworkEvents::connect().await {
+            Ok(events) => network_events = Some(events),
+            Err(Error::NotImplemente) => {
+                log::error!("--dbus needs dns-syncer built with the dbus feature on Linux");
+                return ExitCode::Config;
+            }
+            Err(e) => log::warn!(
+                "network events unavailable, syncing every {:?} only: {}",
+                runner.check_interval(),
+                e
+            ),
+        }
+    }
+
+    let control = control_socket.map(|path| {
+        let control = Arc::new(Control::new());
+        let serving = Arc::clone(&control);
+        tokio::spawn(async move {
+            log::info!("control socket on {}", path.display());
+            if let Err(e) = serving.serve(path.clone()).await {
+                log::error!("control socket {} failed: {}", path.display(), e);
+            }
+        });
+        control
+    });
+
+    // Failures are logged by the runner and retried on the next cycle
+    let config_path = args.config.as_deref().unwrap_or_default();
+    while !cancel.is_cancelled() {
+        if let Some(control) = control.as_ref()
+            && control.take_reload()
+        {
+            match load_runner(config_path, args).await {
+                Ok(next) => {
+                    runner.reload(next);
+                    runner.watch_sources();
+                    log::info!("config {} reloaded", config_path);
                 }
-                // Skip unknown provider types
-                _ => None,
+                Err(e) => log::error!(
+                    "reloading config {} failed, the previous one stays: {}",
+                    config_path,
+                    e
+                ),
+            }
+        }
+        match control.as_ref() {
+            Some(control) if control.is_paused() => log::info!("syncing is paused"),
+            _ => {
+                let _ = runner.run(&cancel).await;
+            }
+        }
+        if let Some(control) = control.as_ref() {
+            control.update_status(runner.state());
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(runner.check_interval()) => {}
+            _ = wait_for_network_event(&mut network_events) => {
+                runner.invalidate_fetchers();
             }
-        })
-        .collect::<HashMap<_, _>>();
-    Ok(ret)
+            _ = wait_for_sources(&sources_changed) => {}
+            _ = wait_for_control(control.as_deref()) => {}
+            _ = cancel.cancelled() => break,
+        }
+    }
+    ExitCode::Success
 }
 
-fn list_in_use_fethers(
-    records: &Vec<config::CfgRecordItem>,
-    public_ip_fecher: &str,
-) -> Vec<String> {
-    let mut ret = records
-        .iter()
-        .flat_map(|r| r.fetchers.iter().map(|f| f.name.clone()))
-        .collect::<Vec<_>>();
-    ret.push(public_ip_fecher.to_string());
-    ret.sort();
-    ret.dedup();
-    ret
+// Sync on address changes of `interface` and every `fallback` until killed
+async fn watch(args: &Args, interface: Option<&str>, fallback: Duration) -> ExitCode {
+    let config = resolve_secrets(load_config(args)).await;
+    let mut runner = verified_runner(config, args).await;
+    let cancel = shutdown_token();
+    runner.set_check_interval(fallback);
+    serve_http(&runner);
+    let sources_changed = runner.watch_sources();
+
+    let mut monitor = match AddressMonitor::new(interface) {
+        Ok(monitor) => Some(monitor),
+        Err(e @ Error::ParseError(_)) => {
+            log::error!("invalid arguments: {}", e);
+            return ExitCode::Config;
+        }
+        Err(e) if fallback.is_zero() => {
+            log::error!("address monitor unavailable and no --fallback: {}", e);
+            return ExitCode::Config;
+        }
+        Err(e) => {
+            log::warn!(
+                "address monitor unavailable, syncing every {:?} instead: {}",
+                fallback,
+                e
+            );
+            None
+        }
+    };
+    while !cancel.is_cancelled() {
+        let _ = runner.run(&cancel).await;
+        tokio::select! {
+            _ = wait_for_address_change(&mut monitor, runner.check_interval()) => {
+                runner.invalidate_fetchers();
+            }
+            _ = wait_for_sources(&sources_changed) => {}
+            _ = cancel.cancelled() => break,
+        }
+    }
+    ExitCode::Success
 }
 
-fn create_fetchers(
-    records: &Vec<config::CfgRecordItem>,
-    public_ip_fecher: &str,
-    fetchers: &Vec<config::CfgFetcher>,
-) -> Result<FetcherMap> {
-    let in_use_fetchers = list_in_use_fethers(records, public_ip_fecher);
+// Cancelled on SIGINT or SIGTERM, so a sync in progress stops between record
+// changes instead of being killed halfway. A second signal exits right away.
+fn shutdown_token() -> CancellationToken {
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        log::info!("shutting down, a second signal exits right away");
+        cancel.cancel();
+        wait_for_signal().await;
+        ExitCode::Failure.exit();
+    });
+    token
+}
 
-    let ret = fetchers
-        .into_iter()
-        .filter(|f| in_use_fetchers.contains(&f.name))
-        .filter_map(|fetcher| {
-            // Create appropriate fetcher based on type
-            match fetcher.r#type.as_str() {
-                // For HTTP fetchers, create new instance with params
-                "http_fetcher" => {
-                    let http_fetcher = HttpFetcher::new_with_args(fetcher.params.clone().into());
-                    Some((
-                        fetcher.name.clone(),
-                        Box::new(http_fetcher) as Box<dyn Fetcher>,
-                    ))
-                }
-                // Skip any unknown fetcher types
-                _ => None,
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::SignalKind;
+    use tokio::signal::unix::signal;
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
             }
-        })
-        .collect::<HashMap<_, _>>();
-    Ok(ret)
+        }
+        Err(e) => {
+            log::warn!("cannot listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
 }
 
-fn to_provider_backends(
-    cfg_records: Vec<config::CfgRecordItem>,
-) -> Result<HashMap<String, ProviderBackend>> {
-    let mut ret: HashMap<String, ProviderBackend> = HashMap::new();
+// Addresses change in bursts, e.g. the old one goes away and the new one comes
+// up, a sync starts once they have been quiet for this long
+const WATCH_SETTLE: Duration = Duration::from_secs(2);
+
+// Return on an address change or after `fallback`, whichever comes first. A
+// failing monitor is dropped and only `fallback` is left.
+async fn wait_for_address_change(monitor: &mut Option<AddressMonitor>, fallback: Duration) {
+    let fallback = async {
+        if fallback.is_zero() {
+            std::future::pending::<()>().await
+        } else {
+            tokio::time::sleep(fallback).await
+        }
+    };
+    let Some(m) = monitor.as_mut() else {
+        return fallback.await;
+    };
+
+    let changed = tokio::select! {
+        result = m.changed() => result,
+        _ = fallback => return,
+    };
+    if let Err(e) = changed {
+        log::warn!("address monitor failed, falling back to polling: {}", e);
+        *monitor = None;
+        return;
+    }
+
+    log::info!("interface address changed");
+    while let Ok(Ok(())) = tokio::time::timeout(WATCH_SETTLE, m.changed()).await {}
+}
 
-    for item in cfg_records {
-        process_record_item(&mut ret, item);
+// Return on a connectivity or address change reported on D-Bus, never without
+// a subscription. A failing subscription is dropped.
+async fn wait_for_network_event(events: &mut Option<NetworkEvents>) {
+    let Some(e) = events.as_mut() else {
+        return std::future::pending().await;
+    };
+    if let Err(err) = e.changed().await {
+        log::warn!(
+            "network events failed, syncing every interval only: {}",
+            err
+        );
+        *events = None;
+        return std::future::pending().await;
     }
-    Ok(ret)
+
+    log::info!("network connectivity or address changed");
+    while let Ok(Ok(())) = tokio::time::timeout(WATCH_SETTLE, e.changed()).await {}
+}
+
+// Return once the records of a source or the API changed, or the API asked
+// for a sync. Containers of a compose project for instance start together and
+// make one sync.
+async fn wait_for_sources(changed: &Notify) {
+    changed.notified().await;
+    log::info!("records changed or a sync was requested");
+    while tokio::time::timeout(WATCH_SETTLE, changed.notified())
+        .await
+        .is_ok()
+    {}
 }
 
-fn process_record_item(
-    records_map: &mut HashMap<String, ProviderBackend>,
-    item: config::CfgRecordItem,
-) {
-    for provider in item.providers {
-        process_provider(records_map, &item.record, provider);
+// Return once a command of the control socket needs the loop, never without
+// a control socket
+async fn wait_for_control(control: Option<&Control>) {
+    match control {
+        Some(control) => control.woken().await,
+        None => std::future::pending().await,
     }
 }
 
-fn process_provider(
-    records_map: &mut HashMap<String, ProviderBackend>,
-    record: &config::CfgRecord,
-    provider: config::CfgRecordProvider,
-) {
-    let provider_name = provider.name;
-    let backend_records = records_map
-        .entry(provider_name)
-        .or_insert(Default::default());
+// Runner of the config read again, for a reload of the daemon
+async fn load_runner(config_path: &str, args: &Args) -> Result<Runner> {
+    let mut config =
+        ConfigParser::parse_yaml(config_path)?.select_profile(args.profile.as_deref())?;
+    config.resolve_secrets().await?;
+    let runner = init_runner(config, args, &ProviderRegistry::new())?;
+    runner.verify().await?;
+    Ok(runner)
+}
 
-    for zone in provider.zones {
-        add_zone_record(&mut backend_records.record, zone, record, &provider.params);
+// Runner of the records selected by the command line
+fn init_runner(config: Config, args: &Args, registry: &ProviderRegistry) -> Result<Runner> {
+    let records = selected_records(&config, args)?;
+    Runner::new(config, records, registry)
+}
+
+fn selected_records(config: &Config, args: &Args) -> Result<Vec<CfgRecordItem>> {
+    let filter = args.record_filter();
+    let records = config
+        .record_items()?
+        .into_iter()
+        .filter(|r| r.is_selected(&args.tags, &args.skip_tags))
+        .filter_map(|r| r.narrow(&filter))
+        .collect::<Vec<_>>();
+
+    if !filter.is_empty() && records.is_empty() {
+        return Err(Error::ParseError(
+            "no record matches the --record, --zone and --provider filters".to_string(),
+        ));
     }
+    Ok(records)
+}
+
+fn parse_record_type(s: &str) -> std::result::Result<RecordType, String> {
+    RecordType::parse(s).ok_or_else(|| format!("unknown record type {}", s))
 }
 
-fn add_zone_record(
-    backend_records: &mut BackendRecords,
-    zone: ZoneName,
-    record: &config::CfgRecord,
-    params: &config::CfgParamList,
-) {
-    let zone_records = backend_records
-        .zones
-        .entry(zone)
-        .or_insert(Default::default());
-    let provider_record = record.clone().into_provider_record(params);
-    zone_records.records.push(provider_record);
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mode(argv: &[&str], check_interval: u64) -> Option<Result<SyncMode>> {
+        let args = Args::parse_from(argv);
+        args.sync_mode(Duration::from_secs(check_interval))
+    }
+
+    #[test]
+    fn test_record_filter() {
+        let args = Args::parse_from([
+            "dns-syncer",
+            "-c",
+            "c.yaml",
+            "sync",
+            "--once",
+            "--record",
+            "*.example.org",
+            "--provider",
+            "cloudflare-1",
+        ]);
+        let filter = args.record_filter();
+        assert_eq!(filter.record.as_deref(), Some("*.example.org"));
+        assert_eq!(filter.zone, None);
+        assert_eq!(filter.provider.as_deref(), Some("cloudflare-1"));
+
+        let args = Args::parse_from(["dns-syncer", "-c", "c.yaml", "plan"]);
+        assert!(args.record_filter().is_empty());
+    }
+
+    #[test]
+    fn test_output_format() {
+        let args = Args::parse_from(["dns-syncer", "-c", "c.yaml", "plan", "--output", "yaml"]);
+        assert_eq!(args.output_format(false), OutputFormat::Yaml);
+        assert_eq!(args.output_format(true), OutputFormat::Json);
+
+        let args = Args::parse_from(["dns-syncer", "-c", "c.yaml", "plan"]);
+        assert_eq!(args.output_format(false), OutputFormat::Table);
+        assert!(Args::try_parse_from(["dns-syncer", "--output", "xml", "providers"]).is_err());
+    }
+
+    #[test]
+    fn test_sync_mode() {
+        let once = mode(&["dns-syncer", "-c", "c.yaml", "sync", "--once"], 30);
+        assert_eq!(once.unwrap().unwrap(), SyncMode::Once);
+
+        let daemon = mode(&["dns-syncer", "-c", "c.yaml", "sync", "--daemon"], 30);
+        assert_eq!(daemon.unwrap().unwrap(), SyncMode::Daemon);
+        let daemon = mode(&["dns-syncer", "-c", "c.yaml", "sync", "--daemon"], 0);
+        assert!(daemon.unwrap().is_err());
+
+        // Without a flag check_interval decides, as before subcommands existed
+        let sync = mode(&["dns-syncer", "-c", "c.yaml", "sync"], 0);
+        assert_eq!(sync.unwrap().unwrap(), SyncMode::Once);
+        let implicit = mode(&["dns-syncer", "-c", "c.yaml"], 30);
+        assert_eq!(implicit.unwrap().unwrap(), SyncMode::Daemon);
+
+        let filtered = mode(
+            &["dns-syncer", "-c", "c.yaml", "sync", "--record", "home"],
+            0,
+        );
+        assert_eq!(filtered.unwrap().unwrap(), SyncMode::Once);
+
+        let watch = mode(
+            &["dns-syncer", "-c", "c.yaml", "watch", "--fallback", "30m"],
+            0,
+        );
+        assert_eq!(watch.unwrap().unwrap(), SyncMode::Watch);
+        assert!(
+            Args::try_parse_from(["dns-syncer", "-c", "c.yaml", "watch", "--fallback", "x"])
+                .is_err()
+        );
+
+        assert!(mode(&["dns-syncer", "-c", "c.yaml", "plan"], 30).is_none());
+        assert!(
+            Args::try_parse_from(["dns-syncer", "-c", "c.yaml", "sync", "--once", "--daemon"])
+                .is_err()
+        );
+    }
 }
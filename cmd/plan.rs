@@ -0,0 +1,51 @@
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::provider::PlanOp;
+use dns_syncer::runner::ProviderChange;
+
+pub fn print(changes: &[ProviderChange], output: OutputFormat) -> Result<()> {
+    if output.is_structured() {
+        let value = json!({ "changes": changes });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:<30} {:<6} {:<10} CHANGE",
+        "PROVIDER", "NAME", "TYPE", "OP"
+    );
+    for c in changes {
+        let before = c
+            .change
+            .before
+            .iter()
+            .map(|r| format!("{} {}", r.r#type, r.content))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let change = match c.change.op {
+            PlanOp::Create => format!("+ {}", c.change.after.content),
+            PlanOp::Update => format!("{} -> {}", before, c.change.after.content),
+            PlanOp::Unchanged => c.change.after.content.clone(),
+        };
+        println!(
+            "{:<20} {:<30} {:<6} {:<10} {}",
+            c.provider,
+            c.change.name,
+            c.change.after.r#type,
+            c.change.op.as_str(),
+            change
+        );
+    }
+
+    let count = |op: PlanOp| changes.iter().filter(|c| c.change.op == op).count();
+    println!();
+    println!(
+        "{} to create, {} to update, {} unchanged",
+        count(PlanOp::Create),
+        count(PlanOp::Update),
+        count(PlanOp::Unchanged)
+    );
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::provider::Capabilities;
+
+pub fn print(types: &[Capabilities], output: OutputFormat) -> Result<()> {
+    if output.is_structured() {
+        let value = json!({ "providers": types });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    for (idx, caps) in types.iter().enumerate() {
+        if idx > 0 {
+            println!();
+        }
+        println!("{}: {}", caps.r#type, caps.description);
+
+        let record_types = caps
+            .record_types
+            .iter()
+            .map(|t| t.as_str())
+            .collect::<Vec<_>>();
+        println!("  record types:   {}", record_types.join(", "));
+
+        println!("  authentication:");
+        for method in caps.auth_methods.iter() {
+            println!(
+                "    {:<14}params: {}",
+                method.name,
+                method.params.join(", ")
+            );
+        }
+
+        println!("  record params:");
+        for param in caps.params.iter() {
+            println!("    {:<14}{}", param.name, param.description);
+        }
+        println!("  operations:     sync, {}", caps.operations.join(", "));
+    }
+    Ok(())
+}
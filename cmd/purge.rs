@@ -0,0 +1,46 @@
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::syncer::ZoneOrphans;
+
+pub fn print(zones: &[ZoneOrphans], output: OutputFormat) -> Result<()> {
+    if output.is_structured() {
+        let value = json!({ "zones": zones });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:<24} {:<36} {:<6} {:<40} ACTION",
+        "PROVIDER", "ZONE", "NAME", "TYPE", "CONTENT"
+    );
+    for z in zones {
+        if let Some(e) = z.error.as_deref() {
+            println!("{:<20} {:<24} error: {}", z.provider, z.zone, e);
+            continue;
+        }
+        for r in z.records.iter() {
+            println!(
+                "{:<20} {:<24} {:<36} {:<6} {:<40} {}",
+                z.provider,
+                z.zone,
+                r.name,
+                r.content.record_type().as_str(),
+                r.content.to_string(),
+                if z.deleted { "deleted" } else { "orphan" }
+            );
+        }
+    }
+
+    let count = zones.iter().map(|z| z.records.len()).sum::<usize>();
+    if count == 0 && zones.iter().all(|z| z.error.is_none()) {
+        println!("no orphaned records");
+    } else if !zones.iter().any(|z| z.deleted) {
+        println!(
+            "{} orphaned records, run with --apply to delete them",
+            count
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::runner::Simulation;
+
+use crate::plan;
+
+pub fn print(simulation: &Simulation, output: OutputFormat) -> Result<()> {
+    if output.is_structured() {
+        let value = json!(simulation);
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    plan::print(&simulation.changes, OutputFormat::Table)?;
+    println!();
+    println!(
+        "{:<20} {:<24} {:<36} {:<6} CONTENT",
+        "PROVIDER", "ZONE", "NAME", "TYPE"
+    );
+    for r in simulation.records.iter() {
+        println!(
+            "{:<20} {:<24} {:<36} {:<6} {}",
+            r.provider,
+            r.zone,
+            r.record.name,
+            r.record.content.record_type().as_str(),
+            r.record.content
+        );
+    }
+    if let Some(e) = simulation.error.as_deref() {
+        println!();
+        println!("sync failed: {}", e);
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use chrono::SecondsFormat;
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::state::State;
+use dns_syncer::syncer::RecordState;
+
+pub fn print(state: &State, records: &[RecordState], output: OutputFormat) -> Result<()> {
+    let providers = state.provider_status();
+
+    if output.is_structured() {
+        let value = json!({
+            "public_ip": state.public_ip,
+            "last_cycle": state.last_cycle(),
+            "providers": providers,
+            "records": records,
+        });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    let public_ip = state
+        .public_ip
+        .as_ref()
+        .map(|ip| ip.to_string())
+        .unwrap_or("unknown".to_string());
+    let last_sync = state
+        .last_cycle()
+        .map(|c| c.finished_at.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or("never".to_string());
+    println!("Public IP: {}", public_ip);
+    let candidates = state.public_ip.as_ref().map(|ip| ip.candidates());
+    for c in candidates
+        .unwrap_or_default()
+        .iter()
+        .filter(|c| !c.source.is_empty())
+    {
+        println!(
+            "  {} from {} at {}",
+            c.address,
+            c.source,
+            c.fetched_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+    }
+    println!("Last sync: {}", last_sync);
+
+    println!();
+    println!("PROVIDERS");
+    println!(
+        "{:<20} {:<8} {:<22} {:<22} LAST ERROR",
+        "NAME", "RESULT", "LAST SYNC", "LAST SUCCESS"
+    );
+    for status in providers.iter() {
+        println!(
+            "{:<20} {:<8} {:<22} {:<22} {}",
+            status.provider,
+            if status.success { "ok" } else { "failed" },
+            status.last_sync.to_rfc3339_opts(SecondsFormat::Secs, true),
+            status
+                .last_success
+                .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .unwrap_or("never".to_string()),
+            status.last_error.as_deref().unwrap_or("-")
+        );
+    }
+
+    println!();
+    println!("RECORDS");
+    println!(
+        "{:<20} {:<30} {:<6} {:<30} LIVE",
+        "PROVIDER", "NAME", "TYPE", "DESIRED"
+    );
+    for record in records {
+        let live = match (&record.live, &record.live_error) {
+            (Some(live), _) if live.is_empty() => "<missing>".to_string(),
+            (Some(live), _) => live.join(", "),
+            (None, Some(e)) => format!("<error: {}>", e),
+            (None, None) => "-".to_string(),
+        };
+        let mark = match record.in_sync {
+            Some(true) => " (in sync)",
+            Some(false) => " (differs)",
+            None => "",
+        };
+        println!(
+            "{:<20} {:<30} {:<6} {:<30} {}{}",
+            record.provider, record.name, record.r#type, record.desired, live, mark
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::syncer::ProviderCredentials;
+
+pub fn print(providers: &[ProviderCredentials], output: OutputFormat) -> Result<()> {
+    if output.is_structured() {
+        let value = json!({ "providers": providers });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    println!("{:<20} {:<30} RESULT", "PROVIDER", "ZONE");
+    for p in providers {
+        let Some(check) = p.check.as_ref() else {
+            let error = p.error.as_deref().unwrap_or_default();
+            println!("{:<20} {:<30} error: {}", p.provider, "-", error);
+            continue;
+        };
+
+        let mut credential = check.credential.clone();
+        if let Some(id) = check.id.as_deref() {
+            credential.push_str(&format!(" (id {})", id));
+        }
+        if let Some(status) = check.status.as_deref() {
+            credential.push_str(&format!(" {}", status));
+        }
+        if let Some(expires_on) = check.expires_on.as_deref() {
+            credential.push_str(&format!(", expires on {}", expires_on));
+        }
+        println!("{:<20} {:<30} {}", p.provider, "-", credential);
+
+        for zone in check.zones.iter() {
+            let result = if zone.missing.is_empty() {
+                "ok".to_string()
+            } else {
+                format!("missing {}", zone.missing.join(", "))
+            };
+            println!("{:<20} {:<30} {}", p.provider, zone.zone, result);
+        }
+    }
+    Ok(())
+}
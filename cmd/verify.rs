@@ -0,0 +1,48 @@
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::verify::CheckStatus;
+use dns_syncer::verify::RecordCheck;
+
+pub fn print(checks: &[RecordCheck], output: OutputFormat) -> Result<()> {
+    if output.is_structured() {
+        let value = json!({ "checks": checks });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<30} {:<6} {:<22} {:<8} {:>6}  {:<30} ANSWER",
+        "NAME", "TYPE", "RESOLVER", "STATUS", "TTL", "DESIRED"
+    );
+    for c in checks {
+        let answer = match c.error.as_deref() {
+            Some(e) => format!("error: {}", e),
+            None if c.answers.is_empty() => "-".to_string(),
+            None => c.answers.join(", "),
+        };
+        println!(
+            "{:<30} {:<6} {:<22} {:<8} {:>6}  {:<30} {}",
+            c.name,
+            c.r#type,
+            c.resolver.to_string(),
+            c.status.as_str(),
+            c.ttl.map(|t| t.to_string()).unwrap_or("-".to_string()),
+            c.desired,
+            answer
+        );
+    }
+
+    let count = |status: CheckStatus| checks.iter().filter(|c| c.status == status).count();
+    println!();
+    println!(
+        "{} ok, {} stale, {} missing, {} proxied, {} failed",
+        count(CheckStatus::Ok),
+        count(CheckStatus::Stale),
+        count(CheckStatus::Missing),
+        count(CheckStatus::Proxied),
+        count(CheckStatus::Error)
+    );
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use serde_json::json;
+
+use dns_syncer::error::Result;
+use dns_syncer::output::OutputFormat;
+use dns_syncer::syncer::ProviderZones;
+
+pub fn print(providers: &[ProviderZones], output: OutputFormat) -> Result<()> {
+    if output.is_structured() {
+        let value = json!({ "providers": providers });
+        println!("{}", output.render(&value)?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<20} {:<30} {:<34} {:<16} STATUS",
+        "PROVIDER", "ZONE", "ID", "PLAN"
+    );
+    for p in providers {
+        if let Some(e) = p.error.as_deref() {
+            println!("{:<20} error: {}", p.provider, e);
+            continue;
+        }
+        for zone in p.zones.iter() {
+            println!(
+                "{:<20} {:<30} {:<34} {:<16} {}",
+                p.provider,
+                zone.name,
+                zone.id.as_deref().unwrap_or("-"),
+                zone.plan.as_deref().unwrap_or("-"),
+                zone.status.as_deref().unwrap_or("-")
+            );
+        }
+        for zone in p.inaccessible.iter() {
+            println!(
+                "{:<20} {:<30} {:<34} {:<16} not accessible, used by records",
+                p.provider, zone, "-", "-"
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,634 @@
+use super::*;
+use std::net::Ipv4Addr;
+
+use crate::provider::Auth;
+use crate::record::RecordType;
+
+#[test]
+fn test_record_deserialize_with_content() {
+    let yaml = r#"
+type: A
+name: case1.dns-syncer-test
+proxied: true
+content: 8.8.8.8
+comment: 'DNS Syncer, google dns'
+ttl: 300
+op: create
+providers:
+- name: "cloudflare-1"
+  params:
+    - name: "proxied"
+      value: "true"
+  zones:
+    - "example-au.org"
+fetchers:
+  - name: "http_fetcher-1"
+"#;
+
+    let cfg_record: CfgRecordItem = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(cfg_record.record.name, "case1.dns-syncer-test");
+    assert_eq!(
+        cfg_record.record.content,
+        RecordContent::A(Ipv4Addr::new(8, 8, 8, 8))
+    );
+    assert_eq!(
+        cfg_record.record.comment,
+        Some("DNS Syncer, google dns".to_string())
+    );
+    assert_eq!(cfg_record.record.op, RecordOp::Create);
+    assert_eq!(cfg_record.providers.len(), 1);
+    assert_eq!(cfg_record.providers[0].name, "cloudflare-1");
+    assert_eq!(cfg_record.providers[0].zones.len(), 1);
+
+    assert_eq!(cfg_record.record.ttl, TTL::Value(300));
+    assert_eq!(cfg_record.fetchers.len(), 1);
+    assert_eq!(cfg_record.fetchers[0].name, "http_fetcher-1");
+}
+
+#[test]
+fn test_record_cfg_deserialize_without_content() {
+    let yaml = r#"
+type: A
+name: case1.dns-syncer-test
+proxied: true
+comment: 'DNS Syncer, google dns'
+op: create
+providers:
+- name: "cloudflare-1"
+  params:
+    - name: "proxied"
+      value: "true"
+  zones:
+    - "example-au.org"
+fetchers:
+  - name: "http_fetcher-1"
+"#;
+
+    let cfg_record: CfgRecordItem = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(cfg_record.record.name, "case1.dns-syncer-test");
+    assert_eq!(
+        cfg_record.record.content,
+        RecordContent::Unassigned(RecordType::A)
+    );
+    assert_eq!(
+        cfg_record.record.comment,
+        Some("DNS Syncer, google dns".to_string())
+    );
+    assert_eq!(cfg_record.record.op, RecordOp::Create);
+    assert_eq!(cfg_record.providers.len(), 1);
+    assert_eq!(cfg_record.providers[0].name, "cloudflare-1");
+    assert_eq!(cfg_record.providers[0].zones.len(), 1);
+    assert_eq!(cfg_record.record.ttl, TTL::Auto);
+    assert_eq!(cfg_record.fetchers.len(), 1);
+    assert_eq!(cfg_record.fetchers[0].name, "http_fetcher-1");
+    assert_eq!(cfg_record.providers[0].params.len(), 1);
+    assert_eq!(cfg_record.providers[0].params[0].name, "proxied");
+    assert_eq!(cfg_record.providers[0].params[0].value, "true");
+}
+
+#[test]
+fn test_providers_cloudflare_api_token_deserialize() {
+    let yaml = r#"
+name: cloudflare-1
+type: cloudflare
+authentication:
+  method: api_token
+  params:
+  - name: api_token
+    value: TestToken
+    "#;
+
+    let cfg_provider: CfgProvider = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(cfg_provider.name, "cloudflare-1");
+    assert_eq!(cfg_provider.r#type, "cloudflare");
+    assert_eq!(cfg_provider.authentication().unwrap().method, "api_token");
+    assert_eq!(cfg_provider.authentication().unwrap().params.len(), 1);
+    assert_eq!(
+        cfg_provider.authentication().unwrap().params[0].name,
+        "api_token"
+    );
+    assert_eq!(
+        cfg_provider.authentication().unwrap().params[0].value,
+        "TestToken"
+    );
+
+    let auth = Auth::new_with_params(&cfg_provider.provider_config().auth).unwrap();
+    assert!(matches!(auth, Auth::ApiToken(token) if token == "TestToken"));
+}
+
+#[test]
+fn test_providers_cloudflare_api_key_deserialize() {
+    let yaml = r#"
+name: cloudflare-1
+type: cloudflare
+authentication:
+  method: api_key
+  params:
+  - name: email
+    value: test@example.com
+  - name: key
+    value: 1234567890
+    "#;
+
+    let cfg_provider: CfgProvider = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(cfg_provider.name, "cloudflare-1");
+    assert_eq!(cfg_provider.r#type, "cloudflare");
+    assert_eq!(cfg_provider.authentication().unwrap().method, "api_key");
+    assert_eq!(cfg_provider.authentication().unwrap().params.len(), 2);
+    assert_eq!(
+        cfg_provider.authentication().unwrap().params[0].name,
+        "email"
+    );
+    assert_eq!(
+        cfg_provider.authentication().unwrap().params[0].value,
+        "test@example.com"
+    );
+    assert_eq!(cfg_provider.authentication().unwrap().params[1].name, "key");
+
+    let auth = Auth::new_with_params(&cfg_provider.provider_config().auth).unwrap();
+    assert!(
+        matches!(auth, Auth::ApiKey { email, key } if email == "test@example.com" && key == "1234567890")
+    );
+}
+
+#[test]
+fn test_fetchers_deserialize() {
+    let yaml = r#"
+name: http_fetcher-1
+type: http_fetcher
+params:
+- name: enabled
+  value: "1.1.1.1,ipinfo.io"
+    "#;
+
+    let cfg_fetcher: CfgFetcher = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(cfg_fetcher.name, "http_fetcher-1");
+    assert_eq!(cfg_fetcher.r#type, "http_fetcher");
+    assert_eq!(cfg_fetcher.params.len(), 1);
+    assert_eq!(cfg_fetcher.params[0].name, "enabled");
+    assert_eq!(cfg_fetcher.params[0].value, "1.1.1.1,ipinfo.io");
+}
+
+#[test]
+fn test_profiles_select() {
+    let yaml = r#"
+check_interval: 30
+public_ip_fecher: http_fetcher-1
+fetchers: []
+providers: []
+records:
+- type: A
+  name: default
+  providers:
+  - name: cloudflare-1
+    zones: [example.org]
+profiles:
+  prod:
+    records:
+    - type: A
+      name: home
+      providers:
+      - name: cloudflare-1
+        zones: [example.org]
+  staging:
+    check_interval: 10s
+    records:
+    - type: A
+      name: home
+      providers:
+      - name: cloudflare-1
+        zones: [staging.example.org]
+"#;
+
+    let cfg: Cfg = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(cfg.profiles.len(), 2);
+
+    let default = cfg.clone().select_profile(None).unwrap();
+    let records = default.record_items().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].record.name, "default");
+
+    let staging = cfg.clone().select_profile(Some("staging")).unwrap();
+    let records = staging.record_items().unwrap();
+    assert_eq!(staging.check_interval, Duration::from_secs(10));
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].providers[0].zones[0], "staging.example.org");
+
+    let prod = cfg.clone().select_profile(Some("prod")).unwrap();
+    let records = prod.record_items().unwrap();
+    assert_eq!(prod.check_interval, Duration::from_secs(30));
+    assert_eq!(records[0].providers[0].zones[0], "example.org");
+
+    assert!(cfg.select_profile(Some("missing")).is_err());
+}
+
+#[test]
+fn test_record_source_deserialize() {
+    let dir = std::env::temp_dir().join(format!(
+        "dns-syncer-test-record-source-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("zone.db"),
+        "$ORIGIN example.org.\nhome 300 IN A 1.2.3.4\nwww IN CNAME home\n@ IN MX 10 mail\n",
+    )
+    .unwrap();
+
+    let yaml = r#"
+- type: A
+  name: inline
+  providers:
+  - name: cloudflare-1
+    zones: [example.org]
+- source:
+    file: zone.db
+    format: bind
+  providers:
+  - name: cloudflare-1
+    zones: [example.org]
+"#;
+    let entries: Vec<CfgRecordEntry> = serde_yaml::from_str(yaml).unwrap();
+    assert!(matches!(entries[0], CfgRecordEntry::Record(_)));
+    assert!(matches!(entries[1], CfgRecordEntry::Source(_)));
+
+    let items = entries[1].clone().into_items(&dir).unwrap();
+    // The MX record is not supported and skipped
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0].record.name, "home.example.org");
+    assert_eq!(items[0].record.ttl, TTL::Value(300));
+    assert_eq!(
+        items[0].record.content,
+        RecordContent::A(Ipv4Addr::new(1, 2, 3, 4))
+    );
+    assert_eq!(items[0].providers[0].name, "cloudflare-1");
+    assert_eq!(
+        items[1].record.content,
+        RecordContent::CNAME("home.example.org".to_string())
+    );
+}
+
+#[test]
+fn test_http_deserialize() {
+    let yaml = r#"
+connect_timeout: 5
+read_timeout: 1m
+proxy: http://proxy.internal:3128
+user_agent: dns-syncer
+max_redirects: 3
+ca_bundle: certs/corp.pem
+pool_idle_timeout: 15m
+keep_alive: 1m
+dns:
+  nameservers: [1.1.1.1, "[2606:4700:4700::1111]:53"]
+  cache_ttl: 5m
+  resolve:
+    api.cloudflare.com: [104.16.132.229]
+"#;
+    let cfg_http: CfgHttp = serde_yaml::from_str(yaml).unwrap();
+    let http = cfg_http.into_http_config(Path::new("/etc/dns-syncer"));
+    assert_eq!(http.connect_timeout, Some(Duration::from_secs(5)));
+    assert_eq!(http.timeout, Some(Duration::from_secs(60)));
+    assert_eq!(http.proxy.as_deref(), Some("http://proxy.internal:3128"));
+    assert_eq!(http.max_redirects, Some(3));
+    assert_eq!(http.pool_idle_timeout, Some(Duration::from_secs(900)));
+    assert_eq!(http.keep_alive, Some(Duration::from_secs(60)));
+    assert_eq!(
+        http.ca_bundle,
+        Some(PathBuf::from("/etc/dns-syncer/certs/corp.pem"))
+    );
+    assert_eq!(
+        http.dns.nameservers,
+        vec![
+            "1.1.1.1:53".parse().unwrap(),
+            "[2606:4700:4700::1111]:53".parse().unwrap()
+        ]
+    );
+    assert_eq!(http.dns.cache_ttl, Some(Duration::from_secs(300)));
+    assert_eq!(
+        http.dns.resolve["api.cloudflare.com"],
+        vec!["104.16.132.229".parse::<std::net::IpAddr>().unwrap()]
+    );
+    assert!(
+        CfgHttp::default()
+            .into_http_config(Path::new("/"))
+            .dns
+            .is_system()
+    );
+
+    let yaml = "dns: { nameservers: [not-an-address] }";
+    assert!(serde_yaml::from_str::<CfgHttp>(yaml).is_err());
+}
+
+#[test]
+fn test_server_deserialize() {
+    let yaml = r#"
+check_interval: 30
+public_ip_fecher: http_fetcher-1
+fetchers: []
+providers: []
+server:
+  listen: 0.0.0.0:8080
+"#;
+    let cfg: Cfg = serde_yaml::from_str(yaml).unwrap();
+    let server = cfg.server.unwrap();
+    assert_eq!(server.listen, "0.0.0.0:8080".parse().unwrap());
+    assert!(server.api.is_none());
+
+    let yaml = r#"
+listen: 127.0.0.1:8080
+api:
+  token: s3cret
+  persist: true
+"#;
+    let server: CfgServer = serde_yaml::from_str(yaml).unwrap();
+    let api = server.api.unwrap();
+    assert_eq!(api.token, "s3cret");
+    assert!(api.persist);
+
+    for token in ["''", "' '"] {
+        let err = serde_yaml::from_str::<CfgServer>(&yaml.replace("s3cret", token)).unwrap_err();
+        assert!(err.to_string().contains("api token is empty"), "{}", err);
+    }
+}
+
+#[test]
+fn test_verify_deserialize() {
+    let verify: CfgVerify = serde_yaml::from_str("delay: 10s").unwrap();
+    let names = verify
+        .resolvers
+        .iter()
+        .map(|r| r.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["cloudflare", "google", "quad9"]);
+    assert_eq!(verify.delay, Duration::from_secs(10));
+
+    let yaml = r#"
+resolvers: [quad9, "https://doh.example.net/dns-query"]
+"#;
+    let verify: CfgVerify = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(verify.resolvers[1].url, "https://doh.example.net/dns-query");
+    assert!(serde_yaml::from_str::<CfgVerify>("resolvers: [1.1.1.1]").is_err());
+}
+
+#[test]
+fn test_notifications_deserialize() {
+    let yaml = r#"
+check_interval: 30
+public_ip_fecher: http_fetcher-1
+fetchers: []
+providers: []
+notifications:
+- name: ops
+  type: webhook
+  events: [ip_change, sync_failure]
+  params:
+  - name: url
+    value: https://hooks.internal/dns
+- name: all
+  type: webhook
+  params:
+  - name: url
+    value: https://hooks.internal/all
+"#;
+    let cfg: Cfg = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(cfg.notifications.len(), 2);
+    assert_eq!(
+        cfg.notifications[0].events,
+        vec![EventKind::IpChange, EventKind::SyncFailure]
+    );
+    assert_eq!(
+        cfg.notifications[0].params[0].value,
+        "https://hooks.internal/dns"
+    );
+    assert!(cfg.notifications[1].events.is_empty());
+}
+
+#[test]
+fn test_state_file() {
+    let yaml = r#"
+check_interval: 30
+public_ip_fecher: http_fetcher-1
+fetchers: []
+providers: []
+state:
+  file: state.json
+control:
+  socket: /run/dns-syncer.sock
+"#;
+    let mut cfg: Cfg = serde_yaml::from_str(yaml).unwrap();
+    cfg.base_dir = PathBuf::from("/var/lib/dns-syncer");
+    assert_eq!(
+        cfg.state_file(),
+        Some(PathBuf::from("/var/lib/dns-syncer/state.json"))
+    );
+    assert_eq!(
+        cfg.control_socket(),
+        Some(PathBuf::from("/run/dns-syncer.sock"))
+    );
+    assert_eq!(cfg.state.unwrap().history_size, 100);
+}
+
+#[test]
+fn test_shared_credentials() {
+    let yaml = r#"
+check_interval: 30
+public_ip_fecher: http_fetcher-1
+fetchers: []
+credentials:
+  cf-main:
+    method: api_token
+    params:
+    - name: api_token
+      value: SharedToken
+providers:
+- name: cloudflare-1
+  type: cloudflare
+  credentials: cf-main
+- name: cloudflare-2
+  type: cloudflare
+  credentials: cf-main
+"#;
+    let mut cfg: Cfg = serde_yaml::from_str(yaml).unwrap();
+    cfg.resolve_credentials().unwrap();
+    for provider in cfg.providers.iter() {
+        let auth = Auth::new_with_params(&provider.provider_config().auth).unwrap();
+        assert!(matches!(auth, Auth::ApiToken(token) if token == "SharedToken"));
+    }
+
+    let mut cfg: Cfg =
+        serde_yaml::from_str(&yaml.replace("credentials: cf-main", "credentials: missing"))
+            .unwrap();
+    assert!(cfg.resolve_credentials().is_err());
+}
+
+#[test]
+fn test_hooks() {
+    let yaml = r#"
+check_interval: 30
+public_ip_fecher: http_fetcher-1
+fetchers: []
+hook:
+  script: hooks/records.rhai
+providers:
+- name: cloudflare-1
+  type: cloudflare
+  hook:
+    command: [/usr/local/bin/hook]
+    timeout: 2s
+"#;
+    let mut cfg: Cfg = serde_yaml::from_str(yaml).unwrap();
+    cfg.base_dir = PathBuf::from("/etc/dns-syncer");
+    cfg.resolve_hooks().unwrap();
+    let hook = RecordHook::from(cfg.hook.unwrap());
+    assert_eq!(
+        hook.program,
+        HookProgram::Rhai(PathBuf::from("/etc/dns-syncer/hooks/records.rhai"))
+    );
+    assert_eq!(hook.timeout, Duration::from_secs(10));
+    let hook = RecordHook::from(cfg.providers[0].hook.clone().unwrap());
+    assert_eq!(
+        hook.program,
+        HookProgram::Command(vec!["/usr/local/bin/hook".to_string()])
+    );
+    assert_eq!(hook.timeout, Duration::from_secs(2));
+
+    let mut cfg: Cfg =
+        serde_yaml::from_str(&yaml.replace("    timeout: 2s", "    script: provider.rhai"))
+            .unwrap();
+    let err = cfg.resolve_hooks().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("cloudflare-1: hook has both a command and a script"),
+        "{}",
+        err
+    );
+    let mut cfg: Cfg =
+        serde_yaml::from_str(&yaml.replace("  script: hooks/records.rhai", "  timeout: 5s"))
+            .unwrap();
+    assert!(cfg.resolve_hooks().is_err());
+}
+
+#[tokio::test]
+async fn test_plain_authentication_secrets_untouched() {
+    let yaml = r#"
+method: api_token
+params:
+- name: api_token
+  value: TestToken
+"#;
+    let mut auth: CfgProviderAuthentication = serde_yaml::from_str(yaml).unwrap();
+    auth.resolve_secrets().await.unwrap();
+    assert_eq!(auth.method, "api_token");
+    assert_eq!(auth.get_value_ref("api_token"), Some("TestToken"));
+
+    let yaml = r#"
+method: keyring
+params:
+- name: service
+  value: dns-syncer
+"#;
+    let mut auth: CfgProviderAuthentication = serde_yaml::from_str(yaml).unwrap();
+    assert!(auth.resolve_secrets().await.is_err());
+}
+
+#[test]
+fn test_record_tags_selection() {
+    let yaml = r#"
+- type: A
+  name: home
+  tags: [home]
+  providers:
+  - name: cloudflare-1
+    zones: [example.org]
+- type: A
+  name: office
+  tags: [office, vpn]
+  providers:
+  - name: cloudflare-1
+    zones: [example.org]
+- type: A
+  name: disabled
+  enabled: false
+  providers:
+  - name: cloudflare-1
+    zones: [example.org]
+"#;
+    let items: Vec<CfgRecordItem> = serde_yaml::from_str(yaml).unwrap();
+    let selected = |tags: &[&str], skip_tags: &[&str]| {
+        let tags = tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let skip_tags = skip_tags.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        items
+            .iter()
+            .filter(|i| i.is_selected(&tags, &skip_tags))
+            .map(|i| i.record.name.as_str())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(selected(&[], &[]), vec!["home", "office"]);
+    assert_eq!(selected(&["home"], &[]), vec!["home"]);
+    assert_eq!(selected(&[], &["vpn"]), vec!["home"]);
+    assert_eq!(selected(&["office"], &["office"]), Vec::<&str>::new());
+}
+
+#[test]
+fn test_record_filter() {
+    let yaml = r#"
+- type: A
+  name: home
+  providers:
+  - name: cloudflare-1
+    zones: [example.org, example.net]
+  - name: cloudflare-2
+    zones: [example.org]
+- type: A
+  name: office
+  providers:
+  - name: cloudflare-1
+    zones: [example.org]
+"#;
+    let items: Vec<CfgRecordItem> = serde_yaml::from_str(yaml).unwrap();
+    let narrow = |record: Option<&str>, zone: Option<&str>, provider: Option<&str>| {
+        let filter = RecordFilter {
+            record: record.map(String::from),
+            zone: zone.map(String::from),
+            provider: provider.map(String::from),
+        };
+        items
+            .iter()
+            .cloned()
+            .filter_map(|i| i.narrow(&filter))
+            .flat_map(|i| {
+                let name = i.record.name.clone();
+                i.providers.into_iter().flat_map(move |p| {
+                    let name = name.clone();
+                    p.zones
+                        .into_iter()
+                        .map(move |z| format!("{}/{}.{}", p.name, name, z))
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert!(RecordFilter::default().is_empty());
+    assert_eq!(narrow(None, None, None).len(), 4);
+    assert_eq!(
+        narrow(Some("home.example.org"), None, None),
+        vec![
+            "cloudflare-1/home.example.org",
+            "cloudflare-2/home.example.org"
+        ]
+    );
+    assert_eq!(
+        narrow(Some("home"), Some("example.net"), None),
+        vec!["cloudflare-1/home.example.net"]
+    );
+    assert_eq!(
+        narrow(Some("*.example.org"), None, Some("cloudflare-1")),
+        vec![
+            "cloudflare-1/home.example.org",
+            "cloudflare-1/office.example.org"
+        ]
+    );
+    assert!(narrow(Some("gone"), None, None).is_empty());
+}
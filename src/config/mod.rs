@@ -0,0 +1,1005 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Deserializer;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::notify::EventKind;
+use crate::provider::AuthParams;
+use crate::provider::HookProgram;
+use crate::provider::ProviderConfig;
+use crate::provider::RecordHook;
+use crate::record;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::TTL;
+use crate::secret::Secret;
+use crate::state::DEFAULT_HISTORY_SIZE;
+use crate::types::DnsConfig;
+use crate::types::HttpConfig;
+use crate::types::Param;
+use crate::types::ZoneName;
+use crate::types::deserialize_duration;
+use crate::types::deserialize_optional_duration;
+use crate::types::glob_match;
+use crate::verify::DEFAULT_DOH_RESOLVERS;
+use crate::verify::DEFAULT_TIMEOUT as VERIFY_TIMEOUT;
+use crate::verify::DohResolver;
+use crate::verify::parse_resolver;
+use crate::zonefile;
+
+////////////////////////////////////////////////////////////
+// Parameters
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgParam {
+    pub name: String,
+    pub value: String,
+}
+
+impl From<CfgParam> for Param {
+    fn from(cfg_param: CfgParam) -> Self {
+        Param::new(cfg_param.name, cfg_param.value)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct CfgParamList(Vec<CfgParam>);
+
+impl CfgParamList {
+    pub fn iter(&self) -> impl Iterator<Item = &CfgParam> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<CfgParamList> for Vec<Param> {
+    fn from(cfg_param_list: CfgParamList) -> Self {
+        cfg_param_list.0.into_iter().map(|p| p.into()).collect()
+    }
+}
+
+impl std::ops::Index<usize> for CfgParamList {
+    type Output = CfgParam;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl IntoIterator for CfgParamList {
+    type Item = CfgParam;
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a CfgParamList {
+    type Item = &'a CfgParam;
+    type IntoIter = std::slice::Iter<'a, CfgParam>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Record
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgRecord {
+    pub name: String,
+
+    #[serde(flatten)]
+    pub content: RecordContent,
+
+    #[serde(default)]
+    pub comment: Option<String>,
+
+    #[serde(default)]
+    pub op: RecordOp,
+
+    #[serde(default)]
+    pub ttl: TTL,
+}
+
+impl CfgRecord {
+    pub fn fqdn(&self, zone: &str) -> String {
+        record::fqdn(&self.name, zone)
+    }
+
+    // The record with the params of a provider, checked by the builder
+    pub fn into_provider_record(self, params: &CfgParamList) -> Result<ProviderRecord> {
+        let mut builder = ProviderRecord::builder()
+            .name(&self.name)
+            .value(self.content)
+            .op(self.op)
+            .ttl(self.ttl);
+        if let Some(comment) = self.comment.as_deref() {
+            builder = builder.comment(comment);
+        }
+        for p in params {
+            builder = builder.param(&p.name, &p.value);
+        }
+        builder.build()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgRecordItem {
+    #[serde(flatten)]
+    pub record: CfgRecord,
+
+    pub providers: Vec<CfgRecordProvider>,
+
+    #[serde(default)]
+    pub fetchers: Vec<CfgRecordFetcher>,
+
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl CfgRecordItem {
+    // A record is synced when it is enabled, has one of `tags` (if any is
+    // given) and none of `skip_tags`.
+    pub fn is_selected(&self, tags: &[String], skip_tags: &[String]) -> bool {
+        self.enabled
+            && (tags.is_empty() || self.tags.iter().any(|t| tags.contains(t)))
+            && !self.tags.iter().any(|t| skip_tags.contains(t))
+    }
+
+    // Keep only the providers and zones passing `filter`, `None` when nothing
+    // is left
+    pub fn narrow(mut self, filter: &RecordFilter) -> Option<Self> {
+        let record = &self.record;
+        self.providers.retain_mut(|p| {
+            if filter.provider.as_ref().is_some_and(|f| *f != p.name) {
+                return false;
+            }
+            p.zones.retain(|z| filter.matches(record, z));
+            !p.zones.is_empty()
+        });
+        (!self.providers.is_empty()).then_some(self)
+    }
+}
+
+// Restricts a sync to some records, an unset field matches everything
+#[derive(Debug, Clone, Default)]
+pub struct RecordFilter {
+    // Glob matched against the record name, relative or full
+    pub record: Option<String>,
+    pub zone: Option<String>,
+    pub provider: Option<String>,
+}
+
+impl RecordFilter {
+    pub fn is_empty(&self) -> bool {
+        self.record.is_none() && self.zone.is_none() && self.provider.is_none()
+    }
+
+    fn matches(&self, record: &CfgRecord, zone: &str) -> bool {
+        let zone_ok = self
+            .zone
+            .as_ref()
+            .is_none_or(|z| z.trim_end_matches('.').eq_ignore_ascii_case(zone));
+        let record_ok = self.record.as_ref().is_none_or(|pattern| {
+            glob_match(pattern, &record.name)
+                || glob_match(pattern.trim_end_matches('.'), &record.fqdn(zone))
+        });
+        zone_ok && record_ok
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CfgRecordSourceFormat {
+    Bind,
+    Csv,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgRecordSource {
+    pub file: PathBuf,
+    pub format: CfgRecordSourceFormat,
+
+    // Origin used to complete relative names in bind zone files
+    #[serde(default)]
+    pub origin: Option<String>,
+
+    // Overrides the comments found in the file
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+impl CfgRecordSource {
+    // Read the file, relative paths are resolved against `base_dir`. Records of
+    // types that cannot be synced are skipped with a warning.
+    pub fn load(&self, base_dir: &Path) -> Result<Vec<CfgRecord>> {
+        let path = base_dir.join(&self.file);
+        let content = std::fs::read_to_string(&path)?;
+        let entries = match self.format {
+            CfgRecordSourceFormat::Bind => zonefile::parse_bind(&content, self.origin.as_deref())?,
+            CfgRecordSourceFormat::Csv => zonefile::parse_csv(&content)?,
+        };
+
+        let records = entries
+            .into_iter()
+            .filter_map(|entry| match entry.content() {
+                Ok(content) => Some(CfgRecord {
+                    name: entry.name,
+                    content,
+                    comment: self.comment.clone().or(entry.comment),
+                    op: RecordOp::default(),
+                    ttl: TTL::from(entry.ttl),
+                }),
+                Err(e) => {
+                    log::warn!("{}: {}, skipped", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        Ok(records)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgRecordSourceItem {
+    pub source: CfgRecordSource,
+
+    pub providers: Vec<CfgRecordProvider>,
+
+    #[serde(default)]
+    pub fetchers: Vec<CfgRecordFetcher>,
+
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+// An entry of the `records` list is either a single record or a file holding
+// many records which share the same providers and fetchers.
+#[derive(Debug, Clone)]
+pub enum CfgRecordEntry {
+    Record(CfgRecordItem),
+    Source(CfgRecordSourceItem),
+}
+
+impl<'de> Deserialize<'de> for CfgRecordEntry {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_yaml::Value::deserialize(deserializer)?;
+
+        if value.get("source").is_some() {
+            CfgRecordSourceItem::deserialize(value)
+                .map(CfgRecordEntry::Source)
+                .map_err(serde::de::Error::custom)
+        } else {
+            CfgRecordItem::deserialize(value)
+                .map(CfgRecordEntry::Record)
+                .map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+impl CfgRecordEntry {
+    pub fn into_items(self, base_dir: &Path) -> Result<Vec<CfgRecordItem>> {
+        match self {
+            CfgRecordEntry::Record(item) => Ok(vec![item]),
+            CfgRecordEntry::Source(item) => Ok(item
+                .source
+                .load(base_dir)?
+                .into_iter()
+                .map(|record| CfgRecordItem {
+                    record,
+                    providers: item.providers.clone(),
+                    fetchers: item.fetchers.clone(),
+                    tags: item.tags.clone(),
+                    enabled: item.enabled,
+                })
+                .collect()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgRecordFetcher {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgRecordProvider {
+    pub name: String,
+    pub zones: Vec<ZoneName>,
+    #[serde(default)]
+    pub params: CfgParamList,
+}
+
+////////////////////////////////////////////////////////////
+// Provider
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgProviderAuthentication {
+    pub method: String,
+    pub params: CfgParamList,
+}
+
+impl CfgProviderAuthentication {
+    pub fn get_value_ref(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|p| p.name == key)
+            .map(|p| p.value.as_str())
+    }
+
+    // Replace secret references by their values. Param values may point to AWS
+    // SSM or Secrets Manager. A `keyring` method reads the secret from the OS
+    // keychain and turns into `api_key` when an email is given, `api_token`
+    // otherwise.
+    pub async fn resolve_secrets(&mut self) -> Result<()> {
+        for param in self.params.0.iter_mut() {
+            param.value = Secret::parse(&param.value).resolve().await?;
+        }
+
+        if self.method != "keyring" {
+            return Ok(());
+        }
+
+        let param = |key: &str| {
+            self.get_value_ref(key)
+                .map(String::from)
+                .ok_or(Error::Secret(format!(
+                    "keyring authentication requires the {} param",
+                    key
+                )))
+        };
+        let secret = Secret::Keyring {
+            service: param("service")?,
+            account: param("account")?,
+        };
+        let value = secret.resolve().await?;
+
+        let (method, params) = match self.get_value_ref("email") {
+            Some(email) => (
+                "api_key",
+                vec![
+                    CfgParam {
+                        name: "email".to_string(),
+                        value: email.to_string(),
+                    },
+                    CfgParam {
+                        name: "key".to_string(),
+                        value,
+                    },
+                ],
+            ),
+            None => (
+                "api_token",
+                vec![CfgParam {
+                    name: "api_token".to_string(),
+                    value,
+                }],
+            ),
+        };
+        self.method = method.to_string();
+        self.params = CfgParamList(params);
+        Ok(())
+    }
+}
+
+impl From<CfgProviderAuthentication> for AuthParams {
+    fn from(cfg: CfgProviderAuthentication) -> Self {
+        Self::new(&cfg.method, cfg.params.into())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgProvider {
+    pub name: String,
+    pub r#type: String,
+
+    // Either an inline authentication or the name of a shared credential block
+    #[serde(default)]
+    pub authentication: Option<CfgProviderAuthentication>,
+    #[serde(default)]
+    pub credentials: Option<String>,
+    // Passed to the provider factory, what they mean is up to the provider type
+    #[serde(default)]
+    pub params: CfgParamList,
+    // Replaces the top-level hook for this provider
+    #[serde(default)]
+    pub hook: Option<CfgHook>,
+}
+
+// Command or Rhai script the changes of every zone go through before they are
+// applied, see `RecordHook`. Exactly one of the two is set, a relative script
+// is resolved against the config directory.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgHook {
+    #[serde(default)]
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub script: Option<PathBuf>,
+    #[serde(
+        default = "default_hook_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub timeout: Duration,
+}
+
+fn default_hook_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl CfgHook {
+    // `owner` is the provider of the hook, or `hook` for the top-level one
+    fn resolve(&mut self, owner: &str, base_dir: &Path) -> Result<()> {
+        match (self.command.is_empty(), self.script.as_mut()) {
+            (false, Some(_)) => Err(Error::ParseError(format!(
+                "{}: hook has both a command and a script",
+                owner
+            ))),
+            (true, None) => Err(Error::ParseError(format!(
+                "{}: hook has neither a command nor a script",
+                owner
+            ))),
+            (true, Some(script)) => {
+                *script = base_dir.join(&script);
+                Ok(())
+            }
+            (false, None) => Ok(()),
+        }
+    }
+}
+
+impl From<CfgHook> for RecordHook {
+    fn from(cfg: CfgHook) -> Self {
+        let program = match cfg.script {
+            Some(script) => HookProgram::Rhai(script),
+            None => HookProgram::Command(cfg.command),
+        };
+        Self {
+            program,
+            timeout: cfg.timeout,
+        }
+    }
+}
+
+impl CfgProvider {
+    pub fn authentication(&self) -> Result<&CfgProviderAuthentication> {
+        self.authentication.as_ref().ok_or(Error::Provider(format!(
+            "{}: provider has no authentication",
+            self.name
+        )))
+    }
+
+    // What the provider factories get, shared credentials already inlined
+    pub fn provider_config(&self) -> ProviderConfig {
+        ProviderConfig {
+            name: self.name.clone(),
+            r#type: self.r#type.clone(),
+            auth: self
+                .authentication()
+                .map(|a| a.clone().into())
+                .unwrap_or_default(),
+            params: self.params.clone().into(),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Fetcher
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgFetcher {
+    pub name: String,
+    pub r#type: String,
+    pub params: CfgParamList,
+}
+
+////////////////////////////////////////////////////////////
+// HTTP client
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CfgHttp {
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub connect_timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub read_timeout: Option<Duration>,
+    #[serde(default)]
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub max_redirects: Option<usize>,
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub pool_idle_timeout: Option<Duration>,
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub keep_alive: Option<Duration>,
+    #[serde(default)]
+    pub dns: CfgDns,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CfgDns {
+    // `ip` or `ip:port`, port 53 when none is given
+    #[serde(default, deserialize_with = "deserialize_nameservers")]
+    pub nameservers: Vec<SocketAddr>,
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub cache_ttl: Option<Duration>,
+    #[serde(default)]
+    pub resolve: HashMap<String, Vec<IpAddr>>,
+}
+
+fn deserialize_nameservers<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<SocketAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| parse_resolver(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+impl CfgHttp {
+    // A relative CA bundle path is resolved against the config directory
+    pub fn into_http_config(self, base_dir: &Path) -> HttpConfig {
+        HttpConfig {
+            connect_timeout: self.connect_timeout,
+            timeout: self.read_timeout,
+            proxy: self.proxy,
+            user_agent: self.user_agent,
+            max_redirects: self.max_redirects,
+            ca_bundle: self.ca_bundle.map(|p| base_dir.join(p)),
+            pool_idle_timeout: self.pool_idle_timeout,
+            keep_alive: self.keep_alive,
+            dns: DnsConfig {
+                nameservers: self.dns.nameservers,
+                cache_ttl: self.dns.cache_ttl,
+                resolve: self.dns.resolve,
+            },
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Notification
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgNotification {
+    pub name: String,
+    pub r#type: String,
+    // Events sent to this sink, all of them when empty
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    #[serde(default)]
+    pub params: CfgParamList,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgHeartbeat {
+    pub url: String,
+    // Pinged when a cycle fails, `<url>/fail` by default
+    #[serde(default)]
+    pub fail_url: Option<String>,
+}
+
+////////////////////////////////////////////////////////////
+// HTTP server
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgServer {
+    // Address the health endpoints are served on, e.g. 0.0.0.0:8080
+    pub listen: SocketAddr,
+    // Management API served next to the health endpoints
+    #[serde(default)]
+    pub api: Option<CfgApi>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgApi {
+    // Bearer token every request must carry, never empty
+    #[serde(deserialize_with = "deserialize_token")]
+    pub token: String,
+    // Keep the records added through the API in the state file
+    #[serde(default)]
+    pub persist: bool,
+}
+
+fn deserialize_token<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let token = String::deserialize(deserializer)?;
+    if token.trim().is_empty() {
+        return Err(serde::de::Error::custom("api token is empty"));
+    }
+    Ok(token)
+}
+
+////////////////////////////////////////////////////////////
+// State
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgState {
+    // Relative paths are resolved against the config directory
+    pub file: PathBuf,
+    // Number of sync cycles and ip changes kept in the history
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+}
+
+fn default_history_size() -> usize {
+    DEFAULT_HISTORY_SIZE
+}
+
+////////////////////////////////////////////////////////////
+// Circuit breaker
+////////////////////////////////////////////////////////////
+// Providers failing `failures` cycles in a row are left out of the cycles
+// for `cooldown`, see `CircuitBreaker`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgCircuitBreaker {
+    #[serde(default = "default_breaker_failures")]
+    pub failures: u32,
+    #[serde(
+        default = "default_breaker_cooldown",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub cooldown: Duration,
+}
+
+fn default_breaker_failures() -> u32 {
+    5
+}
+
+fn default_breaker_cooldown() -> Duration {
+    Duration::from_secs(300)
+}
+
+////////////////////////////////////////////////////////////
+// Control socket
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgControl {
+    // Unix domain socket, relative paths are resolved against the config
+    // directory. A named pipe like `\\.\pipe\dns-syncer` on Windows.
+    pub socket: PathBuf,
+}
+
+////////////////////////////////////////////////////////////
+// Drift check
+////////////////////////////////////////////////////////////
+// How `drift` compares the records the providers serve with the config
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgDrift {
+    #[serde(
+        default = "default_drift_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+    // Sync the records when they drifted instead of only reporting them
+    #[serde(default)]
+    pub reconcile: bool,
+}
+
+impl Default for CfgDrift {
+    fn default() -> Self {
+        Self {
+            interval: default_drift_interval(),
+            reconcile: false,
+        }
+    }
+}
+
+fn default_drift_interval() -> Duration {
+    Duration::from_secs(3600)
+}
+
+////////////////////////////////////////////////////////////
+// Propagation check
+////////////////////////////////////////////////////////////
+// Resolvers asked over DNS-over-HTTPS about the records a sync changed
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgVerify {
+    #[serde(
+        default = "default_doh_resolvers",
+        deserialize_with = "deserialize_doh_resolvers"
+    )]
+    pub resolvers: Vec<DohResolver>,
+    // Time the provider gets to publish the changes
+    #[serde(
+        default = "default_verify_delay",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub delay: Duration,
+    #[serde(
+        default = "default_verify_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub timeout: Duration,
+}
+
+fn default_doh_resolvers() -> Vec<DohResolver> {
+    DEFAULT_DOH_RESOLVERS
+        .iter()
+        .filter_map(|r| DohResolver::parse(r).ok())
+        .collect()
+}
+
+fn deserialize_doh_resolvers<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<DohResolver>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| DohResolver::parse(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+fn default_verify_delay() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_verify_timeout() -> Duration {
+    VERIFY_TIMEOUT
+}
+
+////////////////////////////////////////////////////////////
+// Record source
+////////////////////////////////////////////////////////////
+// Service records are derived from at every cycle, like the labels of Docker
+// containers. A derived hostname goes to the providers whose zones it falls
+// in, the longest zone winning.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgSource {
+    pub name: String,
+    pub r#type: String,
+    #[serde(default)]
+    pub params: CfgParamList,
+    pub providers: Vec<CfgRecordProvider>,
+    #[serde(default)]
+    pub fetchers: Vec<CfgRecordFetcher>,
+    // Comment of the derived records, `derived from source <name>` by default
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+////////////////////////////////////////////////////////////
+// Profile
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct CfgProfile {
+    #[serde(default, deserialize_with = "deserialize_optional_duration")]
+    pub check_interval: Option<Duration>,
+    pub records: Vec<CfgRecordEntry>,
+}
+
+////////////////////////////////////////////////////////////
+// Yaml parser
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Deserialize)]
+pub struct Cfg {
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub check_interval: Duration,
+
+    // Refuse to start on unknown provider or fetcher types instead of skipping
+    // the records using them
+    #[serde(default)]
+    pub strict: bool,
+    pub fetchers: Vec<CfgFetcher>,
+    pub providers: Vec<CfgProvider>,
+    #[serde(default)]
+    pub records: Vec<CfgRecordEntry>,
+    #[serde(default)]
+    pub profiles: HashMap<String, CfgProfile>,
+    #[serde(default)]
+    pub credentials: HashMap<String, CfgProviderAuthentication>,
+    pub public_ip_fecher: String,
+    #[serde(default)]
+    pub http: CfgHttp,
+    #[serde(default)]
+    pub server: Option<CfgServer>,
+    #[serde(default)]
+    pub notifications: Vec<CfgNotification>,
+    #[serde(default)]
+    pub heartbeat: Option<CfgHeartbeat>,
+    #[serde(default)]
+    pub state: Option<CfgState>,
+    // Hook of the providers without one of their own
+    #[serde(default)]
+    pub hook: Option<CfgHook>,
+    #[serde(default)]
+    pub circuit_breaker: Option<CfgCircuitBreaker>,
+    #[serde(default)]
+    pub sources: Vec<CfgSource>,
+    #[serde(default)]
+    pub drift: Option<CfgDrift>,
+    #[serde(default)]
+    pub verify: Option<CfgVerify>,
+    #[serde(default)]
+    pub control: Option<CfgControl>,
+
+    // Directory of the config file, record sources are relative to it
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+impl Cfg {
+    // Replace the top-level records with the ones of the named profile. Providers
+    // and fetchers are shared by all profiles. Without a profile name the config is
+    // returned as is.
+    pub fn select_profile(mut self, name: Option<&str>) -> Result<Self> {
+        let Some(name) = name else {
+            return Ok(self);
+        };
+
+        let profile = self.profiles.remove(name).ok_or(Error::ParseError(format!(
+            "profile {} is not defined",
+            name
+        )))?;
+
+        if let Some(check_interval) = profile.check_interval {
+            self.check_interval = check_interval;
+        }
+        self.records = profile.records;
+        self.profiles.clear();
+        Ok(self)
+    }
+
+    // Copy shared credential blocks into the providers referencing them, so that
+    // every provider ends up with exactly one authentication.
+    pub fn resolve_credentials(&mut self) -> Result<()> {
+        for provider in self.providers.iter_mut() {
+            match (&provider.authentication, &provider.credentials) {
+                (Some(_), Some(_)) => {
+                    return Err(Error::ParseError(format!(
+                        "{}: provider has both authentication and credentials",
+                        provider.name
+                    )));
+                }
+                (None, Some(name)) => {
+                    let auth = self.credentials.get(name).ok_or(Error::ParseError(format!(
+                        "{}: credentials {} is not defined",
+                        provider.name, name
+                    )))?;
+                    provider.authentication = Some(auth.clone());
+                }
+                (Some(_), None) => {}
+                (None, None) => {
+                    return Err(Error::ParseError(format!(
+                        "{}: provider has neither authentication nor credentials",
+                        provider.name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Check the hooks and resolve their scripts against `base_dir`
+    pub fn resolve_hooks(&mut self) -> Result<()> {
+        if let Some(hook) = self.hook.as_mut() {
+            hook.resolve("hook", &self.base_dir)?;
+        }
+        for provider in self.providers.iter_mut() {
+            if let Some(hook) = provider.hook.as_mut() {
+                hook.resolve(&provider.name, &self.base_dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Resolve secret references of every provider authentication, of the
+    // notification params and heartbeat URLs, which often embed a token
+    pub async fn resolve_secrets(&mut self) -> Result<()> {
+        for provider in self.providers.iter_mut() {
+            if let Some(auth) = provider.authentication.as_mut() {
+                auth.resolve_secrets()
+                    .await
+                    .map_err(|e| Error::Secret(format!("{}: {}", provider.name, e)))?;
+            }
+        }
+        for notification in self.notifications.iter_mut() {
+            for param in notification.params.0.iter_mut() {
+                param.value = Secret::parse(&param.value)
+                    .resolve()
+                    .await
+                    .map_err(|e| Error::Secret(format!("{}: {}", notification.name, e)))?;
+            }
+        }
+        if let Some(api) = self.server.as_mut().and_then(|s| s.api.as_mut()) {
+            api.token = Secret::parse(&api.token).resolve().await?;
+            if api.token.trim().is_empty() {
+                return Err(Error::Secret("api token resolved to nothing".to_string()));
+            }
+        }
+        if let Some(heartbeat) = self.heartbeat.as_mut() {
+            heartbeat.url = Secret::parse(&heartbeat.url).resolve().await?;
+            if let Some(url) = heartbeat.fail_url.as_mut() {
+                *url = Secret::parse(url).resolve().await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn state_file(&self) -> Option<PathBuf> {
+        self.state.as_ref().map(|s| self.base_dir.join(&s.file))
+    }
+
+    pub fn control_socket(&self) -> Option<PathBuf> {
+        self.control.as_ref().map(|c| self.base_dir.join(&c.socket))
+    }
+
+    // All records of the config with record sources loaded from their files
+    pub fn record_items(&self) -> Result<Vec<CfgRecordItem>> {
+        let mut ret = vec![];
+        for entry in self.records.iter() {
+            ret.extend(entry.clone().into_items(&self.base_dir)?);
+        }
+        Ok(ret)
+    }
+}
+
+pub struct Parser;
+
+impl Parser {
+    pub fn parse_yaml<P: AsRef<Path>>(path: P) -> Result<Cfg> {
+        let reader = Self::file_reader(&path)?;
+        let mut config: Cfg = serde_yaml::from_reader(reader)?;
+        config.base_dir = path
+            .as_ref()
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        config.resolve_credentials()?;
+        config.resolve_hooks()?;
+        Ok(config)
+    }
+
+    fn file_reader<P: AsRef<Path>>(path: P) -> Result<BufReader<File>> {
+        let f = std::fs::File::open(path)?;
+        Ok(BufReader::new(f))
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Unit test
+////////////////////////////////////////////////////////////
+#[cfg(test)]
+#[path = "config_test.rs"]
+mod test;
@@ -1,28 +1,185 @@
-use std::fmt;
+use std::time::Duration;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum Error {
+    // The request did not get an answer
+    #[error("HTTP error: {0}")]
     HttpError(String),
+    // The answer has a status no other variant covers
+    #[error("HTTP error: status: {status}")]
+    HttpStatus { status: u16 },
+    #[error("Parse error: {0}")]
     ParseError(String),
-    IoError(std::io::Error),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Global fetcher error: {0}")]
     GlobalFetcherError(String),
+    #[error("Provider error: {0}")]
     Provider(String),
+    #[error("Secret error: {0}")]
+    Secret(String),
+    #[error("Notification error: {0}")]
+    Notify(String),
+    // Credentials are missing, invalid or lack a permission. `provider` is
+    // empty until the error reaches the runner.
+    #[error("{}authentication failed: {reason}", prefix(provider))]
+    Auth { provider: String, reason: String },
+    #[error("rate limited{}", retry_after.map(|d| format!(", retry after {}s", d.as_secs())).unwrap_or_default())]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("zone {zone} not found")]
+    ZoneNotFound { zone: String },
+    #[error("record {record} is rejected: {reason}")]
+    RecordRejected { record: String, reason: String },
+    // A record built by `ProviderRecord::builder` failed validation
+    #[error("record {record} is invalid: {reason}")]
+    InvalidRecord {
+        record: String,
+        reason: crate::record::RecordError,
+    },
+    #[error("fetching from {backend} failed: {reason}")]
+    FetchFailed { backend: String, reason: String },
+    // An error of a named provider, fetcher or other component
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<Error>,
+    },
+    // The error of one record of a change set, which the sync outcome marks
+    // failed alone
+    #[error("record {record}: {source}")]
+    Record {
+        record: String,
+        #[source]
+        source: Box<Error>,
+    },
+    // Every failure of an operation that carries on after errors
+    #[error("{}", multiple(.0))]
+    Multiple(Vec<Error>),
+    // The operation was stopped through its cancellation token
+    #[error("cancelled")]
+    Cancelled,
+    #[error("Not implemented")]
     NotImplemente,
 }
 
-impl std::error::Error for Error {}
+fn prefix(provider: &str) -> String {
+    if provider.is_empty() {
+        String::new()
+    } else {
+        format!("{}: ", provider)
+    }
+}
+
+fn multiple(errors: &[Error]) -> String {
+    let mut ret = format!("{} errors:", errors.len());
+    for e in errors {
+        ret.push_str(&format!("\n  - {}", e));
+    }
+    ret
+}
+
+impl Error {
+    // Ok without errors, the error itself when there is one and `Multiple`
+    // otherwise. Nested `Multiple`s are flattened.
+    pub fn from_errors(errors: Vec<Error>) -> Result<()> {
+        let mut flat = vec![];
+        for e in errors {
+            match e {
+                Error::Multiple(inner) => flat.extend(inner),
+                e => flat.push(e),
+            }
+        }
+
+        match flat.len() {
+            0 => Ok(()),
+            1 => Err(flat.remove(0)),
+            _ => Err(Error::Multiple(flat)),
+        }
+    }
+
+    // Error of an HTTP answer with a failure status
+    pub fn from_status(status: u16, retry_after: Option<Duration>) -> Self {
+        match status {
+            401 | 403 => Error::Auth {
+                provider: String::new(),
+                reason: format!("status: {}", status),
+            },
+            429 => Error::RateLimited { retry_after },
+            status => Error::HttpStatus { status },
+        }
+    }
+
+    // The error of `provider`, the provider an authentication failure is
+    // reported for included
+    pub fn with_provider(self, provider: &str) -> Self {
+        match self {
+            Error::Auth {
+                provider: p,
+                reason,
+            } if p.is_empty() => Error::Auth {
+                provider: provider.to_string(),
+                reason,
+            },
+            e @ Error::Auth { .. } => e,
+            e => e.context(provider),
+        }
+    }
+
+    pub fn context(self, context: &str) -> Self {
+        Error::Context {
+            context: context.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    pub fn for_record(self, record: &str) -> Self {
+        Error::Record {
+            record: record.to_string(),
+            source: Box::new(self),
+        }
+    }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    // Whether the same call may succeed later: the network or the remote end
+    // failed, or asked to slow down. Bad credentials, configs and rejected
+    // records fail again.
+    pub fn is_retryable(&self) -> bool {
         match self {
-            Error::HttpError(e) => write!(f, "HTTP error: {}", e),
-            Error::ParseError(e) => write!(f, "Parse error: {}", e),
-            Error::IoError(e) => write!(f, "IO error: {}", e),
-            Error::GlobalFetcherError(e) => write!(f, "Global fetcher error: {}", e),
-            Error::Provider(e) => write!(f, "Provider error: {}", e),
-            Error::NotImplemente => write!(f, "Not implemented"),
+            Error::HttpError(_) | Error::RateLimited { .. } | Error::FetchFailed { .. } => true,
+            Error::HttpStatus { status } => *status == 408 || *status >= 500,
+            Error::IoError(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::Interrupted
+            ),
+            Error::Context { source, .. } | Error::Record { source, .. } => source.is_retryable(),
+            Error::Multiple(errors) => errors.iter().all(Error::is_retryable),
+            _ => false,
+        }
+    }
+
+    // Whether credentials were rejected, wherever in the error
+    pub fn is_auth(&self) -> bool {
+        match self {
+            Error::Auth { .. } => true,
+            Error::Context { source, .. } | Error::Record { source, .. } => source.is_auth(),
+            Error::Multiple(errors) => errors.iter().any(Error::is_auth),
+            _ => false,
+        }
+    }
+
+    // How long the remote end asked to wait before retrying
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Error::RateLimited { retry_after } => *retry_after,
+            Error::Context { source, .. } | Error::Record { source, .. } => source.retry_after(),
+            Error::Multiple(errors) => errors.iter().filter_map(Error::retry_after).max(),
+            _ => None,
         }
     }
 }
@@ -33,12 +190,6 @@ impl From<reqwest::Error> for Error {
     }
 }
 
-impl From<std::io::Error> for Error {
-    fn from(err: std::io::Error) -> Error {
-        Error::IoError(err)
-    }
-}
-
 impl From<std::net::AddrParseError> for Error {
     fn from(err: std::net::AddrParseError) -> Error {
         Error::ParseError(err.to_string())
@@ -56,3 +207,54 @@ impl From<serde_json::Error> for Error {
         Error::ParseError(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_errors() {
+        assert!(Error::from_errors(vec![]).is_ok());
+
+        let err = Error::from_errors(vec![Error::Provider("a".to_string())]).unwrap_err();
+        assert_eq!(err.to_string(), "Provider error: a");
+
+        let nested = Error::Multiple(vec![
+            Error::Provider("b".to_string()),
+            Error::Provider("c".to_string()),
+        ]);
+        let err = Error::from_errors(vec![Error::Provider("a".to_string()), nested]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "3 errors:\n  - Provider error: a\n  - Provider error: b\n  - Provider error: c"
+        );
+    }
+
+    #[test]
+    fn test_retryable() {
+        let auth = Error::from_status(403, None).with_provider("cf");
+        assert_eq!(auth.to_string(), "cf: authentication failed: status: 403");
+        assert!(auth.is_auth());
+        assert!(!auth.is_retryable());
+
+        let limited = Error::from_status(429, Some(Duration::from_secs(30))).with_provider("cf");
+        assert_eq!(limited.to_string(), "cf: rate limited, retry after 30s");
+        assert!(limited.is_retryable());
+        assert_eq!(limited.retry_after(), Some(Duration::from_secs(30)));
+
+        assert!(Error::from_status(502, None).is_retryable());
+        assert!(!Error::from_status(404, None).is_retryable());
+        let zone = Error::ZoneNotFound {
+            zone: "example.org".to_string(),
+        };
+        assert!(!zone.is_retryable());
+
+        let mixed = Error::Multiple(vec![Error::from_status(503, None), zone]);
+        assert!(!mixed.is_retryable());
+        let transient = Error::Multiple(vec![
+            Error::from_status(503, None),
+            Error::HttpError("connection reset".to_string()),
+        ]);
+        assert!(transient.is_retryable());
+    }
+}
@@ -1,16 +1,25 @@
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 
 use async_trait::async_trait;
+use futures_util::future::join_all;
+use futures_util::future::select_ok;
+use tokio_util::sync::CancellationToken;
 
 use crate::error::{Error, Result};
 use crate::wrapper::http;
 
+use super::BackendProbe;
 use super::Fetcher;
-use crate::types::FetcherRecord;
-use crate::types::FetcherRecordSet;
+use super::ProbeResult;
+use crate::record::FetcherRecord;
+use crate::record::FetcherRecordSet;
+use crate::record::RecordLabel;
 use crate::types::Param;
-use crate::types::RecordLabel;
+use crate::types::parse_duration;
+
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Clone)]
 enum FetcherBackend {
@@ -18,47 +27,133 @@ enum FetcherBackend {
     Ipw,
 }
 
+impl FetcherBackend {
+    fn name(&self) -> &'static str {
+        match self {
+            FetcherBackend::Cloudflare => "cloudflare",
+            FetcherBackend::Ipw => "ipw",
+        }
+    }
+
+    // Address of one family, with the backend as source, giving up after
+    // `timeout`
+    async fn fetch(
+        &self,
+        v6: bool,
+        timeout: Duration,
+        transport: Option<&dyn http::HttpTransport>,
+    ) -> Result<FetcherRecord> {
+        let fetch = async {
+            match (self, v6) {
+                (FetcherBackend::Cloudflare, false) => CloudflareFetcher::fetch_v4(transport).await,
+                (FetcherBackend::Cloudflare, true) => CloudflareFetcher::fetch_v6(transport).await,
+                (FetcherBackend::Ipw, false) => IpwFetcher::fetch_v4(transport).await,
+                (FetcherBackend::Ipw, true) => IpwFetcher::fetch_v6(transport).await,
+            }
+        };
+        match tokio::time::timeout(timeout, fetch).await {
+            Ok(Ok(record)) => Ok(record.with_source(self.name())),
+            Ok(Err(e)) => Err(fetch_failed(self.name(), e)),
+            Err(_) => Err(Error::FetchFailed {
+                backend: self.name().to_string(),
+                reason: format!("no answer within {}s", timeout.as_secs()),
+            }),
+        }
+    }
+}
+
+// How the answers of the backends make the addresses of a fetch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Strategy {
+    // Every backend answers for both families, all of them are returned
+    #[default]
+    All,
+    // The first valid answer of each family wins, the other requests are
+    // dropped
+    Fastest,
+}
+
+impl Strategy {
+    fn from_name(name: &str) -> Self {
+        match name {
+            "all" => Strategy::All,
+            "fastest" => Strategy::Fastest,
+            _ => panic!("unknown http fetcher strategy: {}", name),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct HttpFetcher {
     backends: Vec<FetcherBackend>,
+    strategy: Strategy,
     last_fetch_time: Instant,
     cache_alive_time: Duration,
     cache: Option<FetcherRecordSet>,
+    // Longest wait for one backend and address family
+    request_timeout: Duration,
+    // Sends the requests instead of the shared clients when set
+    transport: Option<Arc<dyn http::HttpTransport>>,
+}
+
+impl Default for HttpFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HttpFetcher {
     pub fn new() -> Self {
         Self {
             backends: vec![FetcherBackend::Cloudflare, FetcherBackend::Ipw],
+            strategy: Strategy::default(),
             cache_alive_time: Duration::from_secs(30),
             cache: None,
             last_fetch_time: Instant::now(),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            transport: None,
         }
     }
 
-    pub fn new_with_args(args: Vec<Param>) -> Self {
+    // Fetcher sending its requests through `transport`, like a
+    // `MemoryTransport` answering canned backend responses in tests
+    pub fn with_transport(mut self, transport: Arc<dyn http::HttpTransport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
         if args.is_empty() {
-            return Self::new();
+            return Ok(Self::new());
         }
 
         let mut enabled_backends: Vec<&str> = vec![];
         let mut cache_alive_time: Duration = Duration::default();
+        let mut request_timeout = DEFAULT_REQUEST_TIMEOUT;
+        let mut strategy = Strategy::default();
 
-        args.iter().rev().for_each(|param| {
+        for param in args.iter().rev() {
             if param.name == "enabled" {
                 enabled_backends = param.value.split(',').collect::<Vec<&str>>();
             } else if param.name == "cache_alive_time" {
-                cache_alive_time = Duration::from_secs(param.value.parse::<u64>().unwrap());
+                cache_alive_time = parse_duration(&param.value)?;
+            } else if param.name == "request_timeout" {
+                request_timeout = parse_duration(&param.value)?;
+            } else if param.name == "strategy" {
+                strategy = Strategy::from_name(&param.value);
             }
-        });
+        }
 
         let backends = Self::backends_from_types(enabled_backends);
-        Self {
+        Ok(Self {
             backends,
+            strategy,
             cache_alive_time,
             cache: None,
             last_fetch_time: Instant::now(),
-        }
+            request_timeout,
+            transport: None,
+        })
     }
 
     fn default_backends() -> Vec<&'static str> {
@@ -86,26 +181,72 @@ impl HttpFetcher {
         }
     }
 
+    // Every backend and address family is asked at once, so a hanging
+    // endpoint delays the cycle by the request timeout at most. Records keep
+    // the order of the backends, v4 first.
     async fn do_fetch_from_backends(&self) -> Result<FetcherRecordSet> {
+        let (timeout, transport) = (self.request_timeout, self.transport.as_deref());
+        let fetches = self.backends.iter().flat_map(|backend| {
+            [
+                backend.fetch(false, timeout, transport),
+                backend.fetch(true, timeout, transport),
+            ]
+        });
+
         let mut ret = FetcherRecordSet::default();
-        for backend in self.backends.iter() {
-            match backend {
-                FetcherBackend::Cloudflare => {
-                    ret.push(CloudflareFetcher::fetch_v4().await?);
-                    ret.push(CloudflareFetcher::fetch_v6().await?);
-                }
-                FetcherBackend::Ipw => {
-                    ret.push(IpwFetcher::fetch_v4().await?);
-                    ret.push(IpwFetcher::fetch_v6().await?);
-                }
-            }
+        for record in join_all(fetches).await {
+            ret.push(record?);
         }
         Ok(ret)
     }
 
+    // Backends race per address family, the first valid answer wins and the
+    // requests still in flight are dropped. A family fails only when every
+    // backend failed it.
+    async fn do_fetch_fastest(&self) -> Result<FetcherRecordSet> {
+        let (timeout, transport) = (self.request_timeout, self.transport.as_deref());
+        let race = |v6| {
+            select_ok(
+                self.backends
+                    .iter()
+                    .map(|backend| Box::pin(backend.fetch(v6, timeout, transport))),
+            )
+        };
+        let (v4, v6) = tokio::join!(race(false), race(true));
+
+        let mut ret = FetcherRecordSet::default();
+        ret.push(v4?.0);
+        ret.push(v6?.0);
+        Ok(ret)
+    }
+
+    async fn probe_backend(
+        backend: &FetcherBackend,
+        timeout: Duration,
+        transport: Option<&dyn http::HttpTransport>,
+    ) -> BackendProbe {
+        let probe = |fetch| async move {
+            let started = Instant::now();
+            let result: Result<FetcherRecord> = fetch.await;
+            ProbeResult::new(result.map(|r| r.value), started)
+        };
+        let (v4, v6) = tokio::join!(
+            probe(backend.fetch(false, timeout, transport)),
+            probe(backend.fetch(true, timeout, transport))
+        );
+        BackendProbe {
+            backend: backend.name().to_string(),
+            v4,
+            v6,
+        }
+    }
+
     async fn do_fetch(&mut self) -> Result<FetcherRecordSet> {
         if self.cache.is_none() || self.last_fetch_time.elapsed() > self.cache_alive_time {
-            let records = self.do_fetch_from_backends().await?;
+            let records = match self.strategy {
+                Strategy::All => self.do_fetch_from_backends().await?,
+                Strategy::Fastest => self.do_fetch_fastest().await?,
+            };
             self.cache = Some(records);
             self.last_fetch_time = Instant::now();
         }
@@ -113,10 +254,34 @@ impl HttpFetcher {
     }
 }
 
+fn fetch_failed(backend: &str, e: Error) -> Error {
+    Error::FetchFailed {
+        backend: backend.to_string(),
+        reason: e.to_string(),
+    }
+}
+
 #[async_trait]
 impl Fetcher for HttpFetcher {
-    async fn fetch(&mut self) -> Result<FetcherRecordSet> {
-        self.do_fetch().await
+    async fn fetch(&mut self, cancel: &CancellationToken) -> Result<FetcherRecordSet> {
+        tokio::select! {
+            result = self.do_fetch() => result,
+            _ = cancel.cancelled() => Err(Error::Cancelled),
+        }
+    }
+
+    async fn probe(&mut self) -> Vec<BackendProbe> {
+        let (timeout, transport) = (self.request_timeout, self.transport.as_deref());
+        join_all(
+            self.backends
+                .iter()
+                .map(|backend| Self::probe_backend(backend, timeout, transport)),
+        )
+        .await
+    }
+
+    fn invalidate(&mut self) {
+        self.cache = None;
     }
 }
 
@@ -126,33 +291,170 @@ trait HttpFetcherBackend {
     fn v6_url<'a>() -> &'a str;
     fn parse_content<T: AsRef<str>>(content: T) -> Result<(String, Vec<RecordLabel>)>;
 
-    async fn fetch_v4() -> Result<FetcherRecord> {
-        let url = Self::v4_url();
-        let body = http::get_body_v4(url).await?;
+    async fn fetch_v4(transport: Option<&dyn http::HttpTransport>) -> Result<FetcherRecord> {
+        let body = get_body(Self::v4_url(), false, transport).await?;
         let (ip, labels) = Self::parse_content(body)?;
         let record = FetcherRecord::new_v4_with_labels(ip.parse()?, labels);
         Ok(record)
     }
 
-    async fn fetch_v6() -> Result<FetcherRecord> {
-        let url = Self::v6_url();
-        let body = http::get_body_v6(url).await?;
+    async fn fetch_v6(transport: Option<&dyn http::HttpTransport>) -> Result<FetcherRecord> {
+        let body = get_body(Self::v6_url(), true, transport).await?;
         let (ip, labels) = Self::parse_content(body)?;
         let record = FetcherRecord::new_v6_with_labels(ip.parse()?, labels);
         Ok(record)
     }
 }
 
+// Body of a GET of `url`, through `transport` when given, otherwise through the
+// shared client of the address family
+async fn get_body(
+    url: &str,
+    v6: bool,
+    transport: Option<&dyn http::HttpTransport>,
+) -> Result<String> {
+    match transport {
+        Some(transport) => transport
+            .send(http::Request {
+                method: http::Method::Get,
+                url: url.to_string(),
+                headers: vec![],
+                body: None,
+            })
+            .await?
+            .into_body(),
+        None if v6 => http::get_body_v6(url).await,
+        None => http::get_body_v4(url).await,
+    }
+}
+
 #[cfg(test)]
 mod http_fetcher_tests {
     use super::*;
+    use crate::http::MemoryTransport;
+    use crate::http::Method;
 
     #[tokio::test]
     async fn test_fetcher() {
         let mut fetcher = HttpFetcher::new();
-        let records = fetcher.fetch().await.unwrap();
+        let records = fetcher.fetch(&CancellationToken::new()).await.unwrap();
         dbg!(&records);
     }
+
+    #[test]
+    fn test_strategy_param() {
+        assert_eq!(HttpFetcher::new().strategy, Strategy::All);
+        let param = Param::new("strategy".to_string(), "fastest".to_string());
+        let fetcher = HttpFetcher::new_with_args(vec![param]).unwrap();
+        assert_eq!(fetcher.strategy, Strategy::Fastest);
+
+        let param = Param::new("cache_alive_time".to_string(), "30x".to_string());
+        assert!(HttpFetcher::new_with_args(vec![param]).is_err());
+        let param = Param::new("request_timeout".to_string(), "10".to_string());
+        assert!(HttpFetcher::new_with_args(vec![param]).is_ok());
+        let param = Param::new("request_timeout".to_string(), "ten".to_string());
+        assert!(HttpFetcher::new_with_args(vec![param]).is_err());
+    }
+
+    // Canned answers of every backend, those of the URLs of `delays` held
+    // back for that long
+    struct SlowTransport {
+        inner: MemoryTransport,
+        delays: Vec<(&'static str, Duration)>,
+    }
+
+    #[async_trait]
+    impl http::HttpTransport for SlowTransport {
+        async fn send(&self, request: http::Request) -> Result<http::Response> {
+            if let Some((_, delay)) = self.delays.iter().find(|(url, _)| *url == request.url) {
+                tokio::time::sleep(*delay).await;
+            }
+            self.inner.send(request).await
+        }
+    }
+
+    fn slow_backends(delays: Vec<(&'static str, Duration)>) -> Arc<SlowTransport> {
+        let inner = MemoryTransport::new();
+        for (url, body) in [
+            (CloudflareFetcher::v4_url(), "ip=192.0.2.1\n"),
+            (CloudflareFetcher::v6_url(), "ip=2001:db8::1\n"),
+            (IpwFetcher::v4_url(), "192.0.2.2"),
+            (IpwFetcher::v6_url(), "2001:db8::2"),
+        ] {
+            inner.route(Method::Get, url, 200, body);
+        }
+        Arc::new(SlowTransport { inner, delays })
+    }
+
+    fn sources(records: &FetcherRecordSet) -> Vec<String> {
+        serde_json::to_value(records)
+            .unwrap()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["source"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_fetch_slow_backends() {
+        // The four requests are asked at once, four late answers take the
+        // time of one
+        let late = Duration::from_millis(300);
+        let transport = slow_backends(vec![
+            (CloudflareFetcher::v4_url(), late),
+            (CloudflareFetcher::v6_url(), late),
+            (IpwFetcher::v4_url(), late),
+            (IpwFetcher::v6_url(), late),
+        ]);
+        let mut fetcher = HttpFetcher::new().with_transport(transport.clone());
+        let started = Instant::now();
+        let records = fetcher.fetch(&CancellationToken::new()).await.unwrap();
+        assert!(started.elapsed() < late * 3, "{:?}", started.elapsed());
+        assert_eq!(
+            sources(&records),
+            vec!["cloudflare", "cloudflare", "ipw", "ipw"]
+        );
+        assert_eq!(transport.inner.requests().len(), 4);
+
+        // A hanging backend fails the fetch once the request timeout passed
+        let transport = slow_backends(vec![(IpwFetcher::v6_url(), Duration::from_secs(60))]);
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+        let mut fetcher = HttpFetcher::new_with_args(vec![param("request_timeout", "200ms")])
+            .unwrap()
+            .with_transport(transport.clone());
+        let started = Instant::now();
+        let err = fetcher.fetch(&CancellationToken::new()).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::FetchFailed { backend, .. } if backend == "ipw"),
+            "{}",
+            err
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // Cancelling ends the fetch without waiting for the hanging backend
+        let mut fetcher = HttpFetcher::new().with_transport(transport.clone());
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            canceller.cancel();
+        });
+        let started = Instant::now();
+        let err = fetcher.fetch(&cancel).await.unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        // Racing, the hanging backend is left behind without waiting for the
+        // timeout
+        let mut fetcher = HttpFetcher::new_with_args(vec![param("strategy", "fastest")])
+            .unwrap()
+            .with_transport(transport);
+        let started = Instant::now();
+        let records = fetcher.fetch(&CancellationToken::new()).await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(sources(&records), vec!["cloudflare", "cloudflare"]);
+    }
 }
 
 struct CloudflareFetcher;
@@ -200,13 +502,13 @@ mod cloudflare_fetcher_tests {
 
     #[tokio::test]
     async fn test_fetcher_v4() {
-        let ip = CloudflareFetcher::fetch_v4().await.unwrap();
+        let ip = CloudflareFetcher::fetch_v4(None).await.unwrap();
         println!("{:?}", ip);
     }
 
     #[tokio::test]
     async fn test_fetcher_v6() {
-        let ip = CloudflareFetcher::fetch_v6().await.unwrap();
+        let ip = CloudflareFetcher::fetch_v6(None).await.unwrap();
         println!("{:?}", ip);
     }
 
@@ -287,13 +589,13 @@ mod ipw_fetcher_tests {
 
     #[tokio::test]
     async fn test_fetcher_v4() {
-        let ip = IpwFetcher::fetch_v4().await.unwrap();
+        let ip = IpwFetcher::fetch_v4(None).await.unwrap();
         println!("{:?}", ip);
     }
 
     #[tokio::test]
     async fn test_fetcher_v6() {
-        let ip = IpwFetcher::fetch_v6().await.unwrap();
+        let ip = IpwFetcher::fetch_v6(None).await.unwrap();
         println!("{:?}", ip);
     }
 }
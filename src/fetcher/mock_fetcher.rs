@@ -0,0 +1,106 @@
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::fetcher::BackendProbe;
+use crate::fetcher::Fetcher;
+use crate::fetcher::ProbeResult;
+use crate::record::FetcherRecord;
+use crate::record::FetcherRecordSet;
+use crate::record::RecordContent;
+
+#[derive(Debug, Default)]
+struct MockFetcherState {
+    v4: Option<Ipv4Addr>,
+    v6: Option<Ipv6Addr>,
+    // Fetches fail with it until an address is set again
+    failure: Option<String>,
+    fetches: usize,
+}
+
+// Fetcher whose address a test changes between cycles, or makes fail. Clones
+// share the address, so a clone kept aside drives the one given to a runner.
+#[derive(Debug, Clone, Default)]
+pub struct MockFetcher {
+    state: Arc<Mutex<MockFetcherState>>,
+}
+
+impl MockFetcher {
+    pub fn new(v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Self {
+        let ret = Self::default();
+        ret.set(v4, v6);
+        ret
+    }
+
+    // Answer these addresses from the next fetch on
+    pub fn set(&self, v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) {
+        let mut state = self.state.lock().unwrap();
+        state.v4 = v4;
+        state.v6 = v6;
+        state.failure = None;
+    }
+
+    // Fail every fetch with `reason` until `set` is called
+    pub fn fail(&self, reason: &str) {
+        self.state.lock().unwrap().failure = Some(reason.to_string());
+    }
+
+    // Number of fetches so far, failed ones included
+    pub fn fetch_count(&self) -> usize {
+        self.state.lock().unwrap().fetches
+    }
+}
+
+#[async_trait]
+impl Fetcher for MockFetcher {
+    async fn fetch(&mut self, cancel: &CancellationToken) -> Result<FetcherRecordSet> {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let mut state = self.state.lock().unwrap();
+        state.fetches += 1;
+        if let Some(reason) = state.failure.clone() {
+            return Err(Error::FetchFailed {
+                backend: "mock".to_string(),
+                reason,
+            });
+        }
+
+        let mut ret = FetcherRecordSet::new();
+        if let Some(ip) = state.v4 {
+            ret.push(FetcherRecord::new(RecordContent::A(ip)).with_source("mock"));
+        }
+        if let Some(ip) = state.v6 {
+            ret.push(FetcherRecord::new(RecordContent::AAAA(ip)).with_source("mock"));
+        }
+        Ok(ret)
+    }
+
+    async fn probe(&mut self) -> Vec<BackendProbe> {
+        let started = Instant::now();
+        let state = self.state.lock().unwrap();
+        let missing = || match state.failure.clone() {
+            Some(reason) => Error::FetchFailed {
+                backend: "mock".to_string(),
+                reason,
+            },
+            None => Error::GlobalFetcherError("no address given".to_string()),
+        };
+        let (v4, v6) = match state.failure {
+            Some(_) => (Err(missing()), Err(missing())),
+            None => (state.v4.ok_or_else(missing), state.v6.ok_or_else(missing)),
+        };
+        vec![BackendProbe {
+            backend: "mock".to_string(),
+            v4: ProbeResult::new(v4, started),
+            v6: ProbeResult::new(v6, started),
+        }]
+    }
+}
@@ -5,3 +5,19 @@ pub use types::*;
 
 mod http_fetcher;
 pub use http_fetcher::*;
+
+mod static_fetcher;
+pub use static_fetcher::*;
+
+mod mock_fetcher;
+pub use mock_fetcher::*;
+
+#[cfg(feature = "daemon")]
+mod monitor;
+#[cfg(feature = "daemon")]
+pub use monitor::*;
+
+#[cfg(feature = "daemon")]
+mod network_events;
+#[cfg(feature = "daemon")]
+pub use network_events::*;
@@ -0,0 +1,204 @@
+use crate::error::Result;
+
+// Wakes up when an interface address is added or removed, so a sync follows an
+// address change within seconds instead of waiting for the next interval. Only
+// Linux has it, through a rtnetlink socket.
+pub struct AddressMonitor {
+    inner: imp::Monitor,
+}
+
+impl AddressMonitor {
+    // Watch the addresses of `interface`, or of every interface when `None`
+    pub fn new(interface: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            inner: imp::Monitor::new(interface)?,
+        })
+    }
+
+    // Wait for the next address change
+    pub async fn changed(&mut self) -> Result<()> {
+        self.inner.changed().await
+    }
+}
+
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+// struct nlmsghdr
+const NLMSG_HDRLEN: usize = 16;
+// struct ifaddrmsg
+const IFADDRMSG_LEN: usize = 8;
+
+// Whether a rtnetlink datagram adds or removes an address of the interface
+// `index`, or of any interface when `None`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn is_address_change(buf: &[u8], index: Option<u32>) -> bool {
+    let mut pos = 0;
+    while pos + NLMSG_HDRLEN <= buf.len() {
+        let len = u32::from_ne_bytes(buf[pos..pos + 4].try_into().unwrap()) as usize;
+        let r#type = u16::from_ne_bytes(buf[pos + 4..pos + 6].try_into().unwrap());
+        if len < NLMSG_HDRLEN || pos + len > buf.len() {
+            break;
+        }
+
+        if (r#type == RTM_NEWADDR || r#type == RTM_DELADDR) && len >= NLMSG_HDRLEN + IFADDRMSG_LEN {
+            let at = pos + NLMSG_HDRLEN + 4;
+            let ifindex = u32::from_ne_bytes(buf[at..at + 4].try_into().unwrap());
+            if index.is_none_or(|i| i == ifindex) {
+                return true;
+            }
+        }
+        // Messages are aligned to 4 bytes
+        pos += (len + 3) & !3;
+    }
+    false
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::os::fd::FromRawFd;
+    use std::os::fd::OwnedFd;
+
+    use tokio::io::unix::AsyncFd;
+
+    use super::is_address_change;
+    use crate::error::Error;
+    use crate::error::Result;
+
+    pub struct Monitor {
+        fd: AsyncFd<OwnedFd>,
+        index: Option<u32>,
+    }
+
+    impl Monitor {
+        pub fn new(interface: Option<&str>) -> Result<Self> {
+            let index = interface.map(interface_index).transpose()?;
+
+            // SAFETY: plain syscalls, the descriptor is owned right after creation
+            let fd = unsafe {
+                let fd = libc::socket(
+                    libc::AF_NETLINK,
+                    libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                    libc::NETLINK_ROUTE,
+                );
+                if fd < 0 {
+                    return Err(io::Error::last_os_error().into());
+                }
+                OwnedFd::from_raw_fd(fd)
+            };
+
+            // SAFETY: sockaddr_nl is plain data, zeroed is a valid value
+            let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+            addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+            addr.nl_groups = (libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR) as u32;
+            // SAFETY: addr lives across the call and the length matches its type
+            let ret = unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+                )
+            };
+            if ret < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            Ok(Self {
+                fd: AsyncFd::new(fd)?,
+                index,
+            })
+        }
+
+        pub async fn changed(&mut self) -> Result<()> {
+            let mut buf = vec![0u8; 8192];
+            loop {
+                let mut guard = self.fd.readable().await?;
+                let received = guard.try_io(|fd| {
+                    // SAFETY: buf is valid for writes of its length
+                    let len = unsafe {
+                        libc::recv(
+                            fd.as_raw_fd(),
+                            buf.as_mut_ptr() as *mut libc::c_void,
+                            buf.len(),
+                            0,
+                        )
+                    };
+                    if len < 0 {
+                        Err(io::Error::last_os_error())
+                    } else {
+                        Ok(len as usize)
+                    }
+                });
+                match received {
+                    Ok(Ok(len)) if is_address_change(&buf[..len], self.index) => return Ok(()),
+                    Ok(Ok(_)) => {}
+                    // The kernel dropped messages, something did change
+                    Ok(Err(e)) if e.raw_os_error() == Some(libc::ENOBUFS) => return Ok(()),
+                    Ok(Err(e)) => return Err(e.into()),
+                    Err(_would_block) => {}
+                }
+            }
+        }
+    }
+
+    fn interface_index(name: &str) -> Result<u32> {
+        let invalid = || Error::ParseError(format!("unknown network interface {}", name));
+        let cname = CString::new(name).map_err(|_| invalid())?;
+        // SAFETY: cname is a valid NUL terminated string
+        match unsafe { libc::if_nametoindex(cname.as_ptr()) } {
+            0 => Err(invalid()),
+            index => Ok(index),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use crate::error::Error;
+    use crate::error::Result;
+
+    pub struct Monitor;
+
+    impl Monitor {
+        pub fn new(_interface: Option<&str>) -> Result<Self> {
+            Err(Error::NotImplemente)
+        }
+
+        pub async fn changed(&mut self) -> Result<()> {
+            Err(Error::NotImplemente)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(r#type: u16, ifindex: u32) -> Vec<u8> {
+        let len = (NLMSG_HDRLEN + IFADDRMSG_LEN) as u32;
+        let mut ret = vec![];
+        ret.extend_from_slice(&len.to_ne_bytes());
+        ret.extend_from_slice(&r#type.to_ne_bytes());
+        ret.extend_from_slice(&[0; 10]);
+        // AF_INET, prefix length 24, flags, scope
+        ret.extend_from_slice(&[2, 24, 0, 0]);
+        ret.extend_from_slice(&ifindex.to_ne_bytes());
+        ret
+    }
+
+    #[test]
+    fn test_is_address_change() {
+        assert!(is_address_change(&message(RTM_NEWADDR, 3), None));
+        assert!(is_address_change(&message(RTM_DELADDR, 3), Some(3)));
+        assert!(!is_address_change(&message(RTM_NEWADDR, 2), Some(3)));
+        // RTM_NEWLINK
+        assert!(!is_address_change(&message(16, 3), None));
+
+        let mut batch = message(RTM_NEWADDR, 2);
+        batch.extend(message(RTM_NEWADDR, 3));
+        assert!(is_address_change(&batch, Some(3)));
+        assert!(!is_address_change(&batch[..20], None));
+    }
+}
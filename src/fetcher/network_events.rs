@@ -0,0 +1,200 @@
+use crate::error::Result;
+
+// Wakes up when NetworkManager or systemd-networkd report a connectivity or
+// address change on the system D-Bus, so a daemon syncs right away instead of
+// waiting for the next interval. Needs the `dbus` feature and Linux.
+pub struct NetworkEvents {
+    inner: imp::Events,
+}
+
+impl NetworkEvents {
+    // Subscribe to the signals of both stacks, only the running one sends any
+    pub async fn connect() -> Result<Self> {
+        Ok(Self {
+            inner: imp::Events::connect().await?,
+        })
+    }
+
+    // Wait for the next connectivity or address change
+    pub async fn changed(&mut self) -> Result<()> {
+        self.inner.changed().await
+    }
+}
+
+const NM_INTERFACE: &str = "org.freedesktop.NetworkManager";
+const NETWORKD_INTERFACE: &str = "org.freedesktop.network1";
+
+// Properties of the NetworkManager objects and of the networkd links whose
+// change may come with another public address
+const NM_PROPERTIES: [&str; 7] = [
+    "State",
+    "Connectivity",
+    "PrimaryConnection",
+    "Ip4Config",
+    "Ip6Config",
+    "Addresses",
+    "AddressData",
+];
+const NETWORKD_PROPERTIES: [&str; 5] = [
+    "OperationalState",
+    "AddressState",
+    "IPv4AddressState",
+    "IPv6AddressState",
+    "OnlineState",
+];
+
+// Whether a PropertiesChanged signal for the object interface `interface`,
+// changing `properties`, is a connectivity or address change
+#[cfg_attr(not(all(feature = "dbus", target_os = "linux")), allow(dead_code))]
+fn is_network_change(interface: &str, properties: &[&str]) -> bool {
+    let watched = if interface.starts_with(NM_INTERFACE) {
+        &NM_PROPERTIES[..]
+    } else if interface.starts_with(NETWORKD_INTERFACE) {
+        &NETWORKD_PROPERTIES[..]
+    } else {
+        return false;
+    };
+    properties.iter().any(|p| watched.contains(p))
+}
+
+#[cfg(all(feature = "dbus", target_os = "linux"))]
+mod imp {
+    use std::collections::HashMap;
+
+    use futures_util::StreamExt;
+    use futures_util::stream::SelectAll;
+    use zbus::Connection;
+    use zbus::MatchRule;
+    use zbus::MessageStream;
+    use zbus::message::Type;
+    use zbus::zvariant::OwnedValue;
+
+    use super::NETWORKD_INTERFACE;
+    use super::NM_INTERFACE;
+    use super::is_network_change;
+    use crate::error::Error;
+    use crate::error::Result;
+
+    const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+    pub struct Events {
+        // Kept for the streams to stay subscribed
+        _conn: Connection,
+        streams: SelectAll<MessageStream>,
+    }
+
+    fn dbus_error(e: zbus::Error) -> Error {
+        Error::IoError(std::io::Error::other(format!("d-bus: {}", e)))
+    }
+
+    impl Events {
+        pub async fn connect() -> Result<Self> {
+            let conn = Connection::system().await.map_err(dbus_error)?;
+            let rules = [
+                (NM_INTERFACE, NM_INTERFACE, "StateChanged"),
+                (NM_INTERFACE, PROPERTIES_INTERFACE, "PropertiesChanged"),
+                (
+                    NETWORKD_INTERFACE,
+                    PROPERTIES_INTERFACE,
+                    "PropertiesChanged",
+                ),
+            ];
+            let mut streams = SelectAll::new();
+            for (sender, interface, member) in rules {
+                let rule = MatchRule::builder()
+                    .msg_type(Type::Signal)
+                    .sender(sender)
+                    .and_then(|b| b.interface(interface))
+                    .and_then(|b| b.member(member))
+                    .map_err(dbus_error)?
+                    .build();
+                let stream = MessageStream::for_match_rule(rule, &conn, None)
+                    .await
+                    .map_err(dbus_error)?;
+                streams.push(stream);
+            }
+            Ok(Self {
+                _conn: conn,
+                streams,
+            })
+        }
+
+        pub async fn changed(&mut self) -> Result<()> {
+            while let Some(msg) = self.streams.next().await {
+                let msg = msg.map_err(dbus_error)?;
+                let header = msg.header();
+                if header
+                    .member()
+                    .is_some_and(|m| m.as_str() == "StateChanged")
+                {
+                    return Ok(());
+                }
+                let body = msg.body();
+                let Ok((interface, changed, invalidated)) =
+                    body.deserialize::<(String, HashMap<String, OwnedValue>, Vec<String>)>()
+                else {
+                    continue;
+                };
+                let properties = changed
+                    .keys()
+                    .chain(invalidated.iter())
+                    .map(|p| p.as_str())
+                    .collect::<Vec<_>>();
+                if is_network_change(&interface, &properties) {
+                    return Ok(());
+                }
+            }
+            Err(Error::IoError(std::io::Error::other(
+                "d-bus: connection closed",
+            )))
+        }
+    }
+}
+
+#[cfg(not(all(feature = "dbus", target_os = "linux")))]
+mod imp {
+    use crate::error::Error;
+    use crate::error::Result;
+
+    pub struct Events;
+
+    impl Events {
+        pub async fn connect() -> Result<Self> {
+            Err(Error::NotImplemente)
+        }
+
+        pub async fn changed(&mut self) -> Result<()> {
+            Err(Error::NotImplemente)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_network_change() {
+        assert!(is_network_change(
+            "org.freedesktop.NetworkManager",
+            &["Connectivity"]
+        ));
+        assert!(is_network_change(
+            "org.freedesktop.NetworkManager.Device",
+            &["Autoconnect", "Ip4Config"]
+        ));
+        assert!(!is_network_change(
+            "org.freedesktop.NetworkManager.Device",
+            &["Autoconnect"]
+        ));
+        assert!(is_network_change(
+            "org.freedesktop.network1.Link",
+            &["AddressState"]
+        ));
+        assert!(!is_network_change(
+            "org.freedesktop.network1.Link",
+            &["AdministrativeState"]
+        ));
+        assert!(!is_network_change("org.freedesktop.login1", &["State"]));
+    }
+}
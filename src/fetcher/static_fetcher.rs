@@ -0,0 +1,52 @@
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::fetcher::BackendProbe;
+use crate::fetcher::Fetcher;
+use crate::fetcher::ProbeResult;
+use crate::record::FetcherRecord;
+use crate::record::FetcherRecordSet;
+use crate::record::RecordContent;
+
+// Fetcher answering fixed addresses, for simulations and tests
+#[derive(Debug, Clone)]
+pub struct StaticFetcher {
+    v4: Option<Ipv4Addr>,
+    v6: Option<Ipv6Addr>,
+}
+
+impl StaticFetcher {
+    pub fn new(v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Self {
+        Self { v4, v6 }
+    }
+}
+
+#[async_trait]
+impl Fetcher for StaticFetcher {
+    async fn fetch(&mut self, _cancel: &CancellationToken) -> Result<FetcherRecordSet> {
+        let mut ret = FetcherRecordSet::new();
+        if let Some(ip) = self.v4 {
+            ret.push(FetcherRecord::new(RecordContent::A(ip)).with_source("static"));
+        }
+        if let Some(ip) = self.v6 {
+            ret.push(FetcherRecord::new(RecordContent::AAAA(ip)).with_source("static"));
+        }
+        Ok(ret)
+    }
+
+    async fn probe(&mut self) -> Vec<BackendProbe> {
+        let started = Instant::now();
+        let missing = || Error::GlobalFetcherError("no address given".to_string());
+        vec![BackendProbe {
+            backend: "static".to_string(),
+            v4: ProbeResult::new(self.v4.ok_or_else(missing), started),
+            v6: ProbeResult::new(self.v6.ok_or_else(missing), started),
+        }]
+    }
+}
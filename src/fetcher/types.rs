@@ -1,9 +1,55 @@
+use std::time::Instant;
+
 use crate::error::Result;
-use crate::types::FetcherRecordSet;
+use crate::record::FetcherRecordSet;
 
 use async_trait::async_trait;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
 
 #[async_trait]
-pub trait Fetcher {
-    async fn fetch(&mut self) -> Result<FetcherRecordSet>;
+pub trait Fetcher: Send + Sync {
+    // Fails with `Error::Cancelled` once `cancel` is cancelled, a fetch is
+    // dropped midway since it changes nothing
+    async fn fetch(&mut self, cancel: &CancellationToken) -> Result<FetcherRecordSet>;
+
+    // Ask every backend on its own, bypassing any cache. Used for troubleshooting, so
+    // failures are reported per backend instead of failing the whole probe.
+    async fn probe(&mut self) -> Vec<BackendProbe>;
+
+    // Forget any cached address, the next fetch asks the backends again
+    fn invalidate(&mut self) {}
+}
+
+// The answer of one backend for one address family
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub ip: Option<String>,
+    pub error: Option<String>,
+    pub latency_ms: u64,
+}
+
+impl ProbeResult {
+    pub fn new<T: ToString>(result: Result<T>, started: Instant) -> Self {
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok(ip) => Self {
+                ip: Some(ip.to_string()),
+                error: None,
+                latency_ms,
+            },
+            Err(e) => Self {
+                ip: None,
+                error: Some(e.to_string()),
+                latency_ms,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackendProbe {
+    pub backend: String,
+    pub v4: ProbeResult,
+    pub v6: ProbeResult,
 }
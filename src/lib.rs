@@ -1,8 +1,45 @@
 pub mod error;
 pub use error::*;
 
+// Stops syncs and fetches, see `Runner::run`
+pub use tokio_util::sync::CancellationToken;
+
+pub mod config;
+pub use config::Cfg as Config;
+
+pub mod runner;
+pub use runner::Runner;
+
+pub mod syncer;
+pub use syncer::DnsSyncer;
+
 pub mod fetcher;
+pub mod notify;
+pub mod output;
 pub mod provider;
+pub mod record;
+pub mod secret;
+pub mod server;
+pub mod source;
+pub mod state;
 pub mod types;
+pub mod verify;
+pub mod zonefile;
+
+// Mocks and a Cloudflare API simulator for hermetic tests
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 mod wrapper;
+
+// HTTP transport of the providers, replaceable to test them without network
+pub mod http {
+    pub use crate::wrapper::http::Header;
+    pub use crate::wrapper::http::HeaderKey;
+    pub use crate::wrapper::http::HttpTransport;
+    pub use crate::wrapper::http::MemoryTransport;
+    pub use crate::wrapper::http::Method;
+    pub use crate::wrapper::http::Request;
+    pub use crate::wrapper::http::ReqwestTransport;
+    pub use crate::wrapper::http::Response;
+}
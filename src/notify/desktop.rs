@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+
+use super::Event;
+use super::EventKind;
+use super::Notifier;
+use crate::error::Error;
+use crate::error::Result;
+use crate::types::Param;
+
+// Notification on the desktop of the user running the tool, for laptops and
+// workstations managing personal records. Only ip changes are shown unless
+// the sink subscribes to more events.
+pub struct Desktop {
+    #[cfg_attr(not(feature = "desktop"), allow(dead_code))]
+    timeout_ms: Option<u32>,
+}
+
+impl Desktop {
+    // Params: `timeout` in milliseconds the notification stays visible
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut timeout_ms = None;
+
+        for param in args {
+            match param.name.as_str() {
+                "timeout" => {
+                    timeout_ms = Some(param.value.parse::<u32>().map_err(|_| {
+                        Error::ParseError(format!("desktop: invalid timeout {}", param.value))
+                    })?)
+                }
+                name => {
+                    return Err(Error::ParseError(format!(
+                        "desktop: unknown param {}",
+                        name
+                    )));
+                }
+            }
+        }
+
+        if cfg!(not(feature = "desktop")) {
+            return Err(Error::Notify(
+                "desktop notifications need the desktop feature".to_string(),
+            ));
+        }
+        Ok(Self { timeout_ms })
+    }
+
+    pub fn default_events() -> Vec<EventKind> {
+        vec![EventKind::IpChange]
+    }
+}
+
+#[cfg(feature = "desktop")]
+#[async_trait]
+impl Notifier for Desktop {
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let summary = match event.kind {
+            EventKind::IpChange => "Public IP changed",
+            EventKind::SyncSuccess => "DNS records synced",
+            EventKind::SyncFailure => "DNS sync failed",
+            EventKind::Drift => "DNS records drifted",
+        };
+
+        let mut notification = notify_rust::Notification::new();
+        notification
+            .appname("dns-syncer")
+            .summary(summary)
+            .body(&event.message());
+        if let Some(timeout) = self.timeout_ms {
+            notification.timeout(notify_rust::Timeout::Milliseconds(timeout));
+        }
+
+        // Showing a notification talks to the session bus synchronously
+        tokio::task::spawn_blocking(move || notification.show().map(|_| ()))
+            .await
+            .map_err(|e| Error::Notify(format!("desktop notification task failed: {}", e)))?
+            .map_err(|e| Error::Notify(format!("desktop notification: {}", e)))
+    }
+}
+
+#[cfg(not(feature = "desktop"))]
+#[async_trait]
+impl Notifier for Desktop {
+    async fn notify(&self, _event: &Event) -> Result<()> {
+        Err(Error::Notify(
+            "desktop notifications need the desktop feature".to_string(),
+        ))
+    }
+}
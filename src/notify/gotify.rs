@@ -0,0 +1,133 @@
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::Event;
+use super::EventKind;
+use super::Notifier;
+use super::Priorities;
+use crate::error::Error;
+use crate::error::Result;
+use crate::types::Param;
+use crate::wrapper::http::Client;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HeaderKey;
+
+// Gotify sink posting messages with an application token
+pub struct Gotify {
+    cli: Client,
+    url: String,
+    token: String,
+    priorities: Priorities,
+}
+
+impl Gotify {
+    // Params: `url` of the server and `token` of the application (both
+    // required), `priority_<event>` from 0 to 10.
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut url = None;
+        let mut token = None;
+        let mut priorities = Priorities::new(5, 2, 8, 6);
+
+        for param in args {
+            if let Some(kind) = Priorities::param_kind(&param.name) {
+                let priority = param
+                    .value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|p| (0..=10).contains(p))
+                    .ok_or(Error::ParseError(format!(
+                        "gotify: invalid priority {}",
+                        param.value
+                    )))?;
+                priorities.set(kind, priority);
+                continue;
+            }
+            match param.name.as_str() {
+                "url" => url = Some(param.value),
+                "token" => token = Some(param.value),
+                name => {
+                    return Err(Error::ParseError(format!("gotify: unknown param {}", name)));
+                }
+            }
+        }
+
+        let url = url.ok_or(Error::ParseError("gotify: url is required".to_string()))?;
+        Ok(Self {
+            cli: Client::new()?,
+            url: format!("{}/message", url.trim_end_matches('/')),
+            token: token.ok_or(Error::ParseError("gotify: token is required".to_string()))?,
+            priorities,
+        })
+    }
+
+    fn body(&self, event: &Event) -> serde_json::Value {
+        let title = match event.kind {
+            EventKind::IpChange => "Public IP changed",
+            EventKind::SyncSuccess => "DNS records synced",
+            EventKind::SyncFailure => "DNS sync failed",
+            EventKind::Drift => "DNS records drifted",
+        };
+        json!({
+            "title": title,
+            "message": event.message(),
+            "priority": self.priorities.get(event.kind),
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for Gotify {
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let headers = vec![
+            Header::new(HeaderKey::ContentType, "application/json".to_string()),
+            Header::new(
+                HeaderKey::Custom("X-Gotify-Key".to_string()),
+                self.token.clone(),
+            ),
+        ];
+        let response = self
+            .cli
+            .post(&self.url, Some(headers), self.body(event).to_string())
+            .await?;
+        if response.status == 200 {
+            Ok(())
+        } else {
+            Err(Error::HttpError(format!(
+                "gotify answered status {}: {}",
+                response.status, response.body
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_gotify_body() {
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+        let gotify = Gotify::new_with_args(vec![
+            param("url", "https://gotify.internal/"),
+            param("token", "AbCdEf"),
+            param("priority_ip_change", "7"),
+        ])
+        .unwrap();
+        assert_eq!(gotify.url, "https://gotify.internal/message");
+
+        let event = Event::ip_change("1.1.1.1".to_string(), "2.2.2.2".to_string());
+        let body = gotify.body(&event);
+        assert_eq!(body["priority"], 7);
+        assert_eq!(body["message"], "public ip changed from 1.1.1.1 to 2.2.2.2");
+
+        assert!(Gotify::new_with_args(vec![param("url", "https://gotify.internal")]).is_err());
+        assert!(
+            Gotify::new_with_args(vec![
+                param("url", "https://gotify.internal"),
+                param("token", "AbCdEf"),
+                param("priority_sync_failure", "11"),
+            ])
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::Error;
+use crate::error::Result;
+use crate::wrapper::http::Client;
+
+// Dead man's switch for healthchecks.io, Uptime Kuma push monitors and alike.
+// The url is pinged after every successful cycle, the fail url after a failed
+// one, and the service alerts when pings stop or report a failure.
+pub struct Heartbeat {
+    cli: Client,
+    url: String,
+    fail_url: String,
+}
+
+impl Heartbeat {
+    // Without a fail url `/fail` is appended to the url, as healthchecks.io
+    // expects
+    pub fn new(url: String, fail_url: Option<String>) -> Result<Self> {
+        let fail_url = fail_url.unwrap_or(format!("{}/fail", url.trim_end_matches('/')));
+        Ok(Self {
+            cli: Client::new()?,
+            url,
+            fail_url,
+        })
+    }
+
+    fn url(&self, success: bool) -> &str {
+        if success { &self.url } else { &self.fail_url }
+    }
+
+    pub async fn ping(&self, success: bool) -> Result<()> {
+        let url = self.url(success);
+        let response = self.cli.get(url, None).await?;
+        if (200..300).contains(&response.status) {
+            Ok(())
+        } else {
+            Err(Error::HttpError(format!(
+                "heartbeat answered status {}",
+                response.status
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_heartbeat_urls() {
+        let hb = Heartbeat::new("https://hc-ping.com/f00d/".to_string(), None).unwrap();
+        assert_eq!(hb.url(true), "https://hc-ping.com/f00d/");
+        assert_eq!(hb.url(false), "https://hc-ping.com/f00d/fail");
+
+        let hb = Heartbeat::new(
+            "https://kuma.internal/api/push/abc?status=up".to_string(),
+            Some("https://kuma.internal/api/push/abc?status=down".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            hb.url(false),
+            "https://kuma.internal/api/push/abc?status=down"
+        );
+    }
+}
@@ -0,0 +1,26 @@
+mod types;
+pub use types::*;
+
+mod webhook;
+pub use webhook::*;
+
+mod slack;
+pub use slack::*;
+
+mod ntfy;
+pub use ntfy::*;
+
+mod gotify;
+pub use gotify::*;
+
+mod pushover;
+pub use pushover::*;
+
+mod mqtt;
+pub use mqtt::*;
+
+mod heartbeat;
+pub use heartbeat::*;
+
+mod desktop;
+pub use desktop::*;
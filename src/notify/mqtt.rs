@@ -0,0 +1,345 @@
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use chrono::Utc;
+use serde_json::json;
+
+use super::Event;
+use super::EventKind;
+use super::Notifier;
+use crate::error::Error;
+use crate::error::Result;
+use crate::types::Param;
+use crate::wrapper::mqtt;
+use crate::wrapper::mqtt::Broker;
+use crate::wrapper::mqtt::Message;
+
+const DEFAULT_IP_TOPIC: &str = "dns-syncer/public_ip";
+const DEFAULT_SYNC_TOPIC: &str = "dns-syncer/sync/{provider}";
+const DEFAULT_DRIFT_TOPIC: &str = "dns-syncer/drift/{provider}";
+const DEFAULT_STATE_TOPIC: &str = "dns-syncer/state";
+const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+// Sensors announced to Home Assistant: object id, also the key in the state
+// JSON, name, icon and device class
+const SENSORS: [(&str, &str, &str, Option<&str>); 3] = [
+    ("public_ip", "Public IP", "mdi:ip-network", None),
+    ("last_sync", "Last sync", "mdi:dns", None),
+    (
+        "last_change",
+        "Last IP change",
+        "mdi:clock-outline",
+        Some("timestamp"),
+    ),
+];
+
+// What the sensors show, updated by every event
+#[derive(Debug, Default)]
+struct SensorState {
+    public_ip: Option<String>,
+    // `success` or `failure`
+    last_sync: Option<&'static str>,
+    last_change: Option<String>,
+}
+
+// MQTT sink for home automation. The public address goes to one topic and the
+// outcome of the last sync of every provider to a topic per provider, both
+// retained by default so subscribers get the current value when they connect.
+// Drift reports go to a topic of their own, not to hide the last sync.
+//
+// With `discovery` the sink also announces Home Assistant sensors for the
+// public address, the outcome of the last sync and the time of the last
+// address change, fed by a JSON state topic.
+pub struct Mqtt {
+    broker: Broker,
+    ip_topic: String,
+    sync_topic: String,
+    drift_topic: String,
+    qos: u8,
+    retain: bool,
+    // Prefix of the Home Assistant discovery topics, `None` without discovery
+    discovery_prefix: Option<String>,
+    state_topic: String,
+    state: Mutex<SensorState>,
+    // The sensors were announced, once per process
+    announced: AtomicBool,
+}
+
+impl Mqtt {
+    // Params: `url` of the broker as `mqtt://host[:port]` or `mqtts://...`
+    // (required), `username`, `password`, `client_id`, `ip_topic`,
+    // `sync_topic` and `drift_topic` where `{provider}` is the provider name,
+    // `qos` (0 or 1, 1 by default) and `retain` (true by default). `discovery`
+    // announces Home Assistant sensors under `discovery_prefix`
+    // (`homeassistant` by default) reading `state_topic`.
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut url = None;
+        let mut username = None;
+        let mut password = None;
+        let mut client_id = "dns-syncer".to_string();
+        let mut ip_topic = DEFAULT_IP_TOPIC.to_string();
+        let mut sync_topic = DEFAULT_SYNC_TOPIC.to_string();
+        let mut drift_topic = DEFAULT_DRIFT_TOPIC.to_string();
+        let mut qos = 1;
+        let mut retain = true;
+        let mut discovery = false;
+        let mut discovery_prefix = DEFAULT_DISCOVERY_PREFIX.to_string();
+        let mut state_topic = DEFAULT_STATE_TOPIC.to_string();
+
+        for param in args {
+            match param.name.as_str() {
+                "url" => url = Some(param.value),
+                "username" => username = Some(param.value),
+                "password" => password = Some(param.value),
+                "client_id" => client_id = param.value,
+                "ip_topic" => ip_topic = param.value,
+                "sync_topic" => sync_topic = param.value,
+                "drift_topic" => drift_topic = param.value,
+                "qos" => {
+                    qos = match param.value.as_str() {
+                        "0" => 0,
+                        "1" => 1,
+                        value => {
+                            return Err(Error::ParseError(format!(
+                                "mqtt: qos {} is not 0 or 1",
+                                value
+                            )));
+                        }
+                    }
+                }
+                "retain" => {
+                    retain = param.value.parse().map_err(|_| {
+                        Error::ParseError(format!("mqtt: retain {} is not a bool", param.value))
+                    })?
+                }
+                "discovery" => {
+                    discovery = param.value.parse().map_err(|_| {
+                        Error::ParseError(format!("mqtt: discovery {} is not a bool", param.value))
+                    })?
+                }
+                "discovery_prefix" => discovery_prefix = param.value,
+                "state_topic" => state_topic = param.value,
+                name => {
+                    return Err(Error::ParseError(format!("mqtt: unknown param {}", name)));
+                }
+            }
+        }
+
+        let url = url.ok_or(Error::ParseError("mqtt: url is required".to_string()))?;
+        let mut broker = Broker::parse(&url, &client_id)?;
+        broker.username = username;
+        broker.password = password;
+        Ok(Self {
+            broker,
+            ip_topic,
+            sync_topic,
+            drift_topic,
+            qos,
+            retain,
+            discovery_prefix: discovery.then_some(discovery_prefix),
+            state_topic,
+            state: Mutex::new(SensorState::default()),
+            announced: AtomicBool::new(false),
+        })
+    }
+
+    // Discovery config of every sensor, retained so Home Assistant finds
+    // them after a restart
+    fn discovery_messages(&self, prefix: &str) -> Vec<Message> {
+        let node_id = self
+            .broker
+            .client_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        SENSORS
+            .iter()
+            .map(|(object_id, name, icon, device_class)| {
+                let mut config = json!({
+                    "name": name,
+                    "unique_id": format!("{}_{}", node_id, object_id),
+                    "state_topic": self.state_topic,
+                    "value_template": format!("{{{{ value_json.{} }}}}", object_id),
+                    "icon": icon,
+                    "device": {
+                        "identifiers": [node_id],
+                        "name": "dns-syncer",
+                        "sw_version": env!("CARGO_PKG_VERSION"),
+                    },
+                });
+                if let Some(device_class) = device_class {
+                    config["device_class"] = json!(device_class);
+                }
+                Message {
+                    topic: format!("{}/sensor/{}/{}/config", prefix, node_id, object_id),
+                    payload: config.to_string().into_bytes(),
+                    qos: self.qos,
+                    retain: true,
+                }
+            })
+            .collect()
+    }
+
+    // State JSON of the sensors after `event`
+    fn state_message(&self, event: &Event) -> Option<Message> {
+        let mut state = self.state.lock().ok()?;
+        match event.kind {
+            EventKind::IpChange => {
+                state.last_change = Some(Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+            }
+            EventKind::SyncSuccess => state.last_sync = Some("success"),
+            EventKind::SyncFailure => state.last_sync = Some("failure"),
+            EventKind::Drift => return None,
+        }
+        if let Some(ip) = event.new_ip.as_ref() {
+            state.public_ip = Some(ip.clone());
+        }
+        let payload = json!({
+            "public_ip": state.public_ip,
+            "last_sync": state.last_sync,
+            "last_change": state.last_change,
+        });
+        Some(self.message(self.state_topic.clone(), payload.to_string()))
+    }
+
+    fn message(&self, topic: String, payload: String) -> Message {
+        Message {
+            topic,
+            payload: payload.into_bytes(),
+            qos: self.qos,
+            retain: self.retain,
+        }
+    }
+
+    // A successful sync publishes the address too, so the topic holds it
+    // from the first cycle on and not only after a change. `announce` adds
+    // the discovery config of the sensors.
+    fn messages(&self, event: &Event, announce: bool) -> Vec<Message> {
+        let mut ret = vec![];
+        if let Some(prefix) = self.discovery_prefix.as_deref() {
+            if announce {
+                ret.extend(self.discovery_messages(prefix));
+            }
+            ret.extend(self.state_message(event));
+        }
+        if matches!(event.kind, EventKind::IpChange | EventKind::SyncSuccess)
+            && let Some(ip) = event.new_ip.as_ref()
+        {
+            ret.push(self.message(self.ip_topic.clone(), ip.clone()));
+        }
+        if let Some(provider) = event.provider.as_deref() {
+            let topic = match event.kind {
+                EventKind::Drift => &self.drift_topic,
+                _ => &self.sync_topic,
+            };
+            let topic = topic.replace("{provider}", provider);
+            ret.push(self.message(topic, event.to_json().to_string()));
+        }
+        ret
+    }
+}
+
+#[async_trait]
+impl Notifier for Mqtt {
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let announce = !self.announced.load(Ordering::Relaxed);
+        mqtt::publish(&self.broker, &self.messages(event, announce)).await?;
+        // Announced again with the next event when publishing failed
+        self.announced.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mqtt_messages() {
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+        let sink = Mqtt::new_with_args(vec![
+            param("url", "mqtt://broker.lan"),
+            param("sync_topic", "home/dns/{provider}"),
+        ])
+        .unwrap();
+
+        let event = Event::ip_change("192.0.2.1".to_string(), "192.0.2.2".to_string());
+        let messages = sink.messages(&event, true);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].topic, "dns-syncer/public_ip");
+        assert_eq!(messages[0].payload, b"192.0.2.2");
+        assert!(messages[0].retain);
+
+        let event = Event::sync_failure("cloudflare-1", "timeout".to_string(), 2);
+        let messages = sink.messages(&event, true);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].topic, "home/dns/cloudflare-1");
+        let payload: serde_json::Value = serde_json::from_slice(&messages[0].payload).unwrap();
+        assert_eq!(payload["event"], "sync_failure");
+        assert_eq!(payload["failures"], 2);
+
+        let event = Event::sync_success("cloudflare-1", vec![], "192.0.2.2".to_string());
+        assert_eq!(sink.messages(&event, true).len(), 2);
+
+        let event = Event::drift("cloudflare-1", vec!["home.example.com".to_string()]);
+        let messages = sink.messages(&event, true);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].topic, "dns-syncer/drift/cloudflare-1");
+
+        assert!(Mqtt::new_with_args(vec![param("url", "http://broker.lan")]).is_err());
+    }
+
+    #[test]
+    fn test_mqtt_discovery() {
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+        let sink = Mqtt::new_with_args(vec![
+            param("url", "mqtt://broker.lan"),
+            param("client_id", "dns-syncer.home"),
+            param("discovery", "true"),
+        ])
+        .unwrap();
+
+        let event = Event::sync_success("cloudflare-1", vec![], "192.0.2.1".to_string());
+        let messages = sink.messages(&event, true);
+        let topics = messages
+            .iter()
+            .map(|m| m.topic.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            topics,
+            vec![
+                "homeassistant/sensor/dns_syncer_home/public_ip/config",
+                "homeassistant/sensor/dns_syncer_home/last_sync/config",
+                "homeassistant/sensor/dns_syncer_home/last_change/config",
+                "dns-syncer/state",
+                "dns-syncer/public_ip",
+                "dns-syncer/sync/cloudflare-1",
+            ]
+        );
+        let config: serde_json::Value = serde_json::from_slice(&messages[2].payload).unwrap();
+        assert_eq!(config["state_topic"], "dns-syncer/state");
+        assert_eq!(config["value_template"], "{{ value_json.last_change }}");
+        assert_eq!(config["device_class"], "timestamp");
+        assert_eq!(config["unique_id"], "dns_syncer_home_last_change");
+        let state: serde_json::Value = serde_json::from_slice(&messages[3].payload).unwrap();
+        assert_eq!(state["public_ip"], "192.0.2.1");
+        assert_eq!(state["last_sync"], "success");
+        assert!(state["last_change"].is_null());
+
+        // Announced once, the state keeps what earlier events told
+        let event = Event::ip_change("192.0.2.1".to_string(), "192.0.2.2".to_string());
+        let messages = sink.messages(&event, false);
+        assert_eq!(messages.len(), 2);
+        let state: serde_json::Value = serde_json::from_slice(&messages[0].payload).unwrap();
+        assert_eq!(state["public_ip"], "192.0.2.2");
+        assert_eq!(state["last_sync"], "success");
+        assert!(state["last_change"].is_string());
+        assert!(
+            Mqtt::new_with_args(vec![param("url", "mqtt://broker.lan"), param("qos", "2")])
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use async_trait::async_trait;
+
+use super::Event;
+use super::EventKind;
+use super::Notifier;
+use super::Priorities;
+use crate::error::Error;
+use crate::error::Result;
+use crate::types::Param;
+use crate::wrapper::http::Client;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HeaderKey;
+
+// ntfy sink publishing plain text messages to a topic URL
+pub struct Ntfy {
+    cli: Client,
+    url: String,
+    token: Option<String>,
+    priorities: Priorities,
+}
+
+impl Ntfy {
+    // Params: `url` of the topic (required), `token` and `priority_<event>` as
+    // 1-5 or min, low, default, high, urgent.
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut url = None;
+        let mut token = None;
+        let mut priorities = Priorities::new(3, 2, 4, 4);
+
+        for param in args {
+            if let Some(kind) = Priorities::param_kind(&param.name) {
+                priorities.set(kind, parse_priority(&param.value)?);
+                continue;
+            }
+            match param.name.as_str() {
+                "url" => url = Some(param.value),
+                "token" => token = Some(param.value),
+                name => {
+                    return Err(Error::ParseError(format!("ntfy: unknown param {}", name)));
+                }
+            }
+        }
+
+        Ok(Self {
+            cli: Client::new()?,
+            url: url.ok_or(Error::ParseError("ntfy: url is required".to_string()))?,
+            token,
+            priorities,
+        })
+    }
+
+    fn headers(&self, event: &Event) -> Vec<Header> {
+        let (title, tags) = match event.kind {
+            EventKind::IpChange => ("Public IP changed", "arrows_counterclockwise"),
+            EventKind::SyncSuccess => ("DNS records synced", "white_check_mark"),
+            EventKind::SyncFailure => ("DNS sync failed", "x"),
+            EventKind::Drift => ("DNS records drifted", "warning"),
+        };
+
+        let mut headers = vec![
+            Header::new(HeaderKey::Custom("Title".to_string()), title.to_string()),
+            Header::new(
+                HeaderKey::Custom("Priority".to_string()),
+                self.priorities.get(event.kind).to_string(),
+            ),
+            Header::new(HeaderKey::Custom("Tags".to_string()), tags.to_string()),
+        ];
+        if let Some(token) = self.token.as_ref() {
+            headers.push(Header::new(
+                HeaderKey::Authorization,
+                format!("Bearer {}", token),
+            ));
+        }
+        headers
+    }
+}
+
+#[async_trait]
+impl Notifier for Ntfy {
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let response = self
+            .cli
+            .post(&self.url, Some(self.headers(event)), event.message())
+            .await?;
+        if response.status == 200 {
+            Ok(())
+        } else {
+            Err(Error::HttpError(format!(
+                "ntfy answered status {}: {}",
+                response.status, response.body
+            )))
+        }
+    }
+}
+
+fn parse_priority(value: &str) -> Result<i64> {
+    match value.to_lowercase().as_str() {
+        "1" | "min" => Ok(1),
+        "2" | "low" => Ok(2),
+        "3" | "default" => Ok(3),
+        "4" | "high" => Ok(4),
+        "5" | "urgent" | "max" => Ok(5),
+        _ => Err(Error::ParseError(format!(
+            "ntfy: invalid priority {}",
+            value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ntfy_headers() {
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+        let ntfy = Ntfy::new_with_args(vec![
+            param("url", "https://ntfy.sh/dns-syncer"),
+            param("token", "tk_abc"),
+            param("priority_sync_failure", "urgent"),
+        ])
+        .unwrap();
+
+        let event = Event::sync_failure("cloudflare-1", "timeout".to_string(), 1);
+        let headers = ntfy.headers(&event);
+        assert_eq!(headers[0].value(), "DNS sync failed");
+        assert_eq!(headers[1].value(), "5");
+        assert_eq!(headers[3].value(), "Bearer tk_abc");
+
+        let event = Event::ip_change("1.1.1.1".to_string(), "2.2.2.2".to_string());
+        assert_eq!(ntfy.headers(&event)[1].value(), "3");
+
+        assert!(
+            Ntfy::new_with_args(vec![param("url", "x"), param("priority_ip_change", "9")]).is_err()
+        );
+    }
+}
@@ -0,0 +1,210 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::Event;
+use super::EventKind;
+use super::Notifier;
+use super::Priorities;
+use crate::error::Error;
+use crate::error::Result;
+use crate::types::Param;
+use crate::types::parse_duration;
+use crate::wrapper::http::Client;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HeaderKey;
+
+const PUSHOVER_MESSAGES: &str = "https://api.pushover.net/1/messages.json";
+const EMERGENCY: i64 = 2;
+
+// Pushover sink. A provider failing `emergency_after` times in a row raises an
+// emergency alert, repeated every `retry` until acknowledged or `expire` passes.
+pub struct Pushover {
+    cli: Client,
+    user: String,
+    token: String,
+    device: Option<String>,
+    priorities: Priorities,
+    emergency_after: Option<u32>,
+    retry: Duration,
+    expire: Duration,
+}
+
+impl Pushover {
+    // Params: `user` key and application `token` (both required), `device`,
+    // `priority_<event>` from -2 to 2, `emergency_after`, `retry` and `expire`.
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut user = None;
+        let mut token = None;
+        let mut device = None;
+        let mut priorities = Priorities::new(0, -1, 1, 0);
+        let mut emergency_after = None;
+        let mut retry = Duration::from_secs(60);
+        let mut expire = Duration::from_secs(3600);
+
+        for param in args {
+            if let Some(kind) = Priorities::param_kind(&param.name) {
+                let priority = param
+                    .value
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|p| (-2..=EMERGENCY).contains(p))
+                    .ok_or(Error::ParseError(format!(
+                        "pushover: invalid priority {}",
+                        param.value
+                    )))?;
+                priorities.set(kind, priority);
+                continue;
+            }
+            match param.name.as_str() {
+                "user" => user = Some(param.value),
+                "token" => token = Some(param.value),
+                "device" => device = Some(param.value),
+                "emergency_after" => {
+                    emergency_after = Some(param.value.parse::<u32>().map_err(|_| {
+                        Error::ParseError(format!(
+                            "pushover: invalid emergency_after {}",
+                            param.value
+                        ))
+                    })?)
+                }
+                "retry" => retry = parse_duration(&param.value)?,
+                "expire" => expire = parse_duration(&param.value)?,
+                name => {
+                    return Err(Error::ParseError(format!(
+                        "pushover: unknown param {}",
+                        name
+                    )));
+                }
+            }
+        }
+
+        // Limits of the Pushover API for emergency messages
+        if retry < Duration::from_secs(30) {
+            return Err(Error::ParseError(
+                "pushover: retry must be at least 30s".to_string(),
+            ));
+        }
+        if expire > Duration::from_secs(10800) {
+            return Err(Error::ParseError(
+                "pushover: expire must be at most 3h".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            cli: Client::new()?,
+            user: user.ok_or(Error::ParseError("pushover: user is required".to_string()))?,
+            token: token.ok_or(Error::ParseError("pushover: token is required".to_string()))?,
+            device,
+            priorities,
+            emergency_after,
+            retry,
+            expire,
+        })
+    }
+
+    fn priority(&self, event: &Event) -> i64 {
+        match self.emergency_after {
+            Some(after) if event.kind == EventKind::SyncFailure && event.failures >= after => {
+                EMERGENCY
+            }
+            _ => self.priorities.get(event.kind),
+        }
+    }
+
+    fn body(&self, event: &Event) -> serde_json::Value {
+        let title = match event.kind {
+            EventKind::IpChange => "Public IP changed",
+            EventKind::SyncSuccess => "DNS records synced",
+            EventKind::SyncFailure => "DNS sync failed",
+            EventKind::Drift => "DNS records drifted",
+        };
+        let priority = self.priority(event);
+
+        let mut body = json!({
+            "token": self.token,
+            "user": self.user,
+            "title": title,
+            "message": event.message(),
+            "priority": priority,
+        });
+        if let Some(device) = self.device.as_ref() {
+            body["device"] = json!(device);
+        }
+        if priority == EMERGENCY {
+            body["retry"] = json!(self.retry.as_secs());
+            body["expire"] = json!(self.expire.as_secs());
+        }
+        body
+    }
+}
+
+#[async_trait]
+impl Notifier for Pushover {
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let headers = vec![Header::new(
+            HeaderKey::ContentType,
+            "application/json".to_string(),
+        )];
+        let response = self
+            .cli
+            .post(
+                PUSHOVER_MESSAGES,
+                Some(headers),
+                self.body(event).to_string(),
+            )
+            .await?;
+        if response.status == 200 {
+            Ok(())
+        } else {
+            Err(Error::HttpError(format!(
+                "pushover answered status {}: {}",
+                response.status, response.body
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pushover_emergency() {
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+        let pushover = Pushover::new_with_args(vec![
+            param("user", "uQiRzpo4DXghDmr9QzzfQu27cmVRsG"),
+            param("token", "azGDORePK8gMaC0QOYAMyEEuzJnyUi"),
+            param("emergency_after", "3"),
+            param("retry", "2m"),
+        ])
+        .unwrap();
+
+        let body = pushover.body(&Event::sync_failure(
+            "cloudflare-1",
+            "timeout".to_string(),
+            1,
+        ));
+        assert_eq!(body["priority"], 1);
+        assert!(body.get("retry").is_none());
+
+        let body = pushover.body(&Event::sync_failure(
+            "cloudflare-1",
+            "timeout".to_string(),
+            3,
+        ));
+        assert_eq!(body["priority"], 2);
+        assert_eq!(body["retry"], 120);
+        assert_eq!(body["expire"], 3600);
+
+        assert!(
+            Pushover::new_with_args(vec![
+                param("user", "u"),
+                param("token", "t"),
+                param("retry", "10s"),
+            ])
+            .is_err()
+        );
+    }
+}
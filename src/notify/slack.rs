@@ -0,0 +1,194 @@
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::Event;
+use super::EventKind;
+use super::Notifier;
+use crate::error::Error;
+use crate::error::Result;
+use crate::types::Param;
+use crate::wrapper::http::Client;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HeaderKey;
+
+const SLACK_POST_MESSAGE: &str = "https://slack.com/api/chat.postMessage";
+
+enum Target {
+    Webhook(String),
+    Bot { token: String, channel: String },
+}
+
+// Slack sink, either through an incoming webhook or a bot token posting to a
+// channel. Messages are formatted with Block Kit.
+pub struct Slack {
+    cli: Client,
+    target: Target,
+}
+
+impl Slack {
+    // Params: `webhook_url`, or `token` and `channel` for a bot
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut webhook_url = None;
+        let mut token = None;
+        let mut channel = None;
+
+        for param in args {
+            match param.name.as_str() {
+                "webhook_url" => webhook_url = Some(param.value),
+                "token" => token = Some(param.value),
+                "channel" => channel = Some(param.value),
+                name => {
+                    return Err(Error::ParseError(format!("slack: unknown param {}", name)));
+                }
+            }
+        }
+
+        let target = match (webhook_url, token, channel) {
+            (Some(url), None, None) => Target::Webhook(url),
+            (None, Some(token), Some(channel)) => Target::Bot { token, channel },
+            _ => {
+                return Err(Error::ParseError(
+                    "slack: either webhook_url or token and channel are required".to_string(),
+                ));
+            }
+        };
+
+        Ok(Self {
+            cli: Client::new()?,
+            target,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for Slack {
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let mut payload = blocks(event);
+        let mut headers = vec![Header::new(
+            HeaderKey::ContentType,
+            "application/json; charset=utf-8".to_string(),
+        )];
+
+        let url = match &self.target {
+            Target::Webhook(url) => url.as_str(),
+            Target::Bot { token, channel } => {
+                payload["channel"] = json!(channel);
+                headers.push(Header::new(
+                    HeaderKey::Authorization,
+                    format!("Bearer {}", token),
+                ));
+                SLACK_POST_MESSAGE
+            }
+        };
+
+        let response = self
+            .cli
+            .post(url, Some(headers), payload.to_string())
+            .await?;
+        if response.status != 200 {
+            return Err(Error::HttpError(format!(
+                "slack answered status {}: {}",
+                response.status, response.body
+            )));
+        }
+
+        // The Web API reports errors in the body with a 200 status
+        if let Target::Bot { .. } = self.target {
+            let body: serde_json::Value = serde_json::from_str(&response.body)?;
+            if body["ok"] != json!(true) {
+                return Err(Error::HttpError(format!("slack: {}", body["error"])));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn blocks(event: &Event) -> serde_json::Value {
+    let title = match event.kind {
+        EventKind::IpChange => ":arrows_counterclockwise: Public IP changed",
+        EventKind::SyncSuccess => ":white_check_mark: DNS records synced",
+        EventKind::SyncFailure => ":x: DNS sync failed",
+        EventKind::Drift => ":warning: DNS records drifted",
+    };
+
+    let mut fields = vec![];
+    if let Some(provider) = event.provider.as_ref() {
+        fields.push(json!({ "type": "mrkdwn", "text": format!("*Provider*\n{}", provider) }));
+    }
+    if event.old_ip.is_some() || event.new_ip.is_some() {
+        fields.push(json!({
+            "type": "mrkdwn",
+            "text": format!(
+                "*IP*\n{} → {}",
+                event.old_ip.as_deref().unwrap_or("-"),
+                event.new_ip.as_deref().unwrap_or("-")
+            ),
+        }));
+    }
+    if !event.records.is_empty() {
+        fields.push(json!({
+            "type": "mrkdwn",
+            "text": format!(
+                "*Records {}*\n{}",
+                match event.kind {
+                    EventKind::Drift => "drifted",
+                    _ => "updated",
+                },
+                event.records.join("\n")
+            ),
+        }));
+    }
+
+    let mut blocks = vec![
+        json!({ "type": "header", "text": { "type": "plain_text", "text": title } }),
+        json!({ "type": "section", "text": { "type": "mrkdwn", "text": event.message() } }),
+    ];
+    if !fields.is_empty() {
+        blocks.push(json!({ "type": "section", "fields": fields }));
+    }
+    if let Some(error) = event.error.as_ref() {
+        blocks.push(json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("```{}```", error) },
+        }));
+    }
+
+    json!({ "text": event.message(), "blocks": blocks })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slack_blocks() {
+        let event = Event::sync_success(
+            "cloudflare-1",
+            vec!["a.example.com".to_string(), "b.example.com".to_string()],
+            "2.2.2.2".to_string(),
+        );
+        let payload = blocks(&event);
+        assert_eq!(payload["text"], "provider cloudflare-1 synced 2 record(s)");
+        assert_eq!(payload["blocks"][0]["type"], "header");
+
+        let fields = payload["blocks"][2]["fields"].as_array().unwrap();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[1]["text"], "*IP*\n- → 2.2.2.2");
+        assert_eq!(
+            fields[2]["text"],
+            "*Records updated*\na.example.com\nb.example.com"
+        );
+    }
+
+    #[test]
+    fn test_slack_params() {
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+        assert!(
+            Slack::new_with_args(vec![param("webhook_url", "https://hooks.slack.com/x")]).is_ok()
+        );
+        assert!(
+            Slack::new_with_args(vec![param("token", "xoxb"), param("channel", "#ops")]).is_ok()
+        );
+        assert!(Slack::new_with_args(vec![param("token", "xoxb")]).is_err());
+    }
+}
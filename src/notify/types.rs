@@ -0,0 +1,244 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::error::Result;
+
+////////////////////////////////////////////////////////////
+// Event
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    IpChange,
+    SyncSuccess,
+    SyncFailure,
+    // Records served by a provider differ from the desired ones
+    Drift,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::IpChange => "ip_change",
+            EventKind::SyncSuccess => "sync_success",
+            EventKind::SyncFailure => "sync_failure",
+            EventKind::Drift => "drift",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<EventKind> {
+        EventKind::all().into_iter().find(|k| k.as_str() == name)
+    }
+
+    pub fn all() -> Vec<EventKind> {
+        vec![
+            EventKind::IpChange,
+            EventKind::SyncSuccess,
+            EventKind::SyncFailure,
+            EventKind::Drift,
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub provider: Option<String>,
+    pub records: Vec<String>,
+    pub old_ip: Option<String>,
+    pub new_ip: Option<String>,
+    pub error: Option<String>,
+    // Consecutive failures of the provider, this one included
+    pub failures: u32,
+}
+
+impl Event {
+    fn new(kind: EventKind) -> Self {
+        Self {
+            kind,
+            provider: None,
+            records: vec![],
+            old_ip: None,
+            new_ip: None,
+            error: None,
+            failures: 0,
+        }
+    }
+
+    pub fn ip_change(old_ip: String, new_ip: String) -> Self {
+        Self {
+            old_ip: Some(old_ip),
+            new_ip: Some(new_ip),
+            ..Self::new(EventKind::IpChange)
+        }
+    }
+
+    pub fn sync_success(provider: &str, records: Vec<String>, new_ip: String) -> Self {
+        Self {
+            provider: Some(provider.to_string()),
+            records,
+            new_ip: Some(new_ip),
+            ..Self::new(EventKind::SyncSuccess)
+        }
+    }
+
+    // `records` are the full names of the drifted records
+    pub fn drift(provider: &str, records: Vec<String>) -> Self {
+        Self {
+            provider: Some(provider.to_string()),
+            records,
+            ..Self::new(EventKind::Drift)
+        }
+    }
+
+    pub fn sync_failure(provider: &str, error: String, failures: u32) -> Self {
+        Self {
+            provider: Some(provider.to_string()),
+            error: Some(error),
+            failures,
+            ..Self::new(EventKind::SyncFailure)
+        }
+    }
+
+    // One line summary for sinks showing plain text
+    pub fn message(&self) -> String {
+        let provider = self.provider.as_deref().unwrap_or_default();
+        match self.kind {
+            EventKind::IpChange => format!(
+                "public ip changed from {} to {}",
+                self.old_ip.as_deref().unwrap_or("none"),
+                self.new_ip.as_deref().unwrap_or("none")
+            ),
+            EventKind::SyncSuccess => format!(
+                "provider {} synced {} record(s)",
+                provider,
+                self.records.len()
+            ),
+            EventKind::SyncFailure => format!(
+                "provider {} failed: {}",
+                provider,
+                self.error.as_deref().unwrap_or_default()
+            ),
+            EventKind::Drift => format!(
+                "provider {} serves {} record(s) differing from the config: {}",
+                provider,
+                self.records.len(),
+                self.records.join(", ")
+            ),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "event": self.kind.as_str(),
+            "message": self.message(),
+            "provider": self.provider,
+            "records": self.records,
+            "old_ip": self.old_ip,
+            "new_ip": self.new_ip,
+            "error": self.error,
+            "failures": self.failures,
+        })
+    }
+}
+
+// Priority of each event kind for push services, set by `priority_<event>`
+// params of the sink
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Priorities {
+    ip_change: i64,
+    sync_success: i64,
+    sync_failure: i64,
+    drift: i64,
+}
+
+impl Priorities {
+    pub(crate) fn new(ip_change: i64, sync_success: i64, sync_failure: i64, drift: i64) -> Self {
+        Self {
+            ip_change,
+            sync_success,
+            sync_failure,
+            drift,
+        }
+    }
+
+    // Event kind of a `priority_<event>` param name
+    pub(crate) fn param_kind(name: &str) -> Option<EventKind> {
+        name.strip_prefix("priority_").and_then(EventKind::parse)
+    }
+
+    pub(crate) fn get(&self, kind: EventKind) -> i64 {
+        match kind {
+            EventKind::IpChange => self.ip_change,
+            EventKind::SyncSuccess => self.sync_success,
+            EventKind::SyncFailure => self.sync_failure,
+            EventKind::Drift => self.drift,
+        }
+    }
+
+    pub(crate) fn set(&mut self, kind: EventKind, priority: i64) {
+        match kind {
+            EventKind::IpChange => self.ip_change = priority,
+            EventKind::SyncSuccess => self.sync_success = priority,
+            EventKind::SyncFailure => self.sync_failure = priority,
+            EventKind::Drift => self.drift = priority,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Notifier
+////////////////////////////////////////////////////////////
+// A sink of events, like a chat or a paging system. Built-in sinks implement
+// it, others are added with `Runner::add_notifier`. An error is logged and
+// does not fail the sync.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, event: &Event) -> Result<()>;
+}
+
+struct Sink {
+    name: String,
+    events: Vec<EventKind>,
+    notifier: Box<dyn Notifier>,
+}
+
+// Fans events out to the configured sinks. A failing sink is logged and never
+// fails the sync itself.
+#[derive(Default)]
+pub struct Notifications {
+    sinks: Vec<Sink>,
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // An empty event list subscribes the sink to every event
+    pub fn add(&mut self, name: &str, events: Vec<EventKind>, notifier: Box<dyn Notifier>) {
+        let events = if events.is_empty() {
+            EventKind::all()
+        } else {
+            events
+        };
+        self.sinks.push(Sink {
+            name: name.to_string(),
+            events,
+            notifier,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub async fn send(&self, event: &Event) {
+        for sink in self.sinks.iter().filter(|s| s.events.contains(&event.kind)) {
+            if let Err(e) = sink.notifier.notify(event).await {
+                log::warn!("notification {} failed: {}", sink.name, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+
+use super::Event;
+use super::Notifier;
+use crate::error::Error;
+use crate::error::Result;
+use crate::types::Param;
+use crate::wrapper::http::Client;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HeaderKey;
+
+// Generic webhook sink. Without a template the JSON form of the event is sent,
+// otherwise `{{placeholder}}`s in the template are replaced by event fields.
+pub struct Webhook {
+    cli: Client,
+    url: String,
+    method: String,
+    headers: Vec<Header>,
+    template: Option<String>,
+}
+
+impl Webhook {
+    // Params: `url` (required), `method` (POST or PUT), `header` as
+    // `Name: value` (repeatable) and `template`.
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut url = None;
+        let mut method = "POST".to_string();
+        let mut headers = vec![];
+        let mut template = None;
+
+        for param in args {
+            match param.name.as_str() {
+                "url" => url = Some(param.value),
+                "method" => method = param.value.to_uppercase(),
+                "header" => {
+                    let (name, value) =
+                        param
+                            .value
+                            .split_once(':')
+                            .ok_or(Error::ParseError(format!(
+                                "webhook header {} is not `Name: value`",
+                                param.value
+                            )))?;
+                    headers.push(Header::new(
+                        HeaderKey::Custom(name.trim().to_string()),
+                        value.trim().to_string(),
+                    ));
+                }
+                "template" => template = Some(param.value),
+                name => {
+                    return Err(Error::ParseError(format!(
+                        "webhook: unknown param {}",
+                        name
+                    )));
+                }
+            }
+        }
+
+        if method != "POST" && method != "PUT" {
+            return Err(Error::ParseError(format!(
+                "webhook: unsupported method {}",
+                method
+            )));
+        }
+        if !headers
+            .iter()
+            .any(|h| h.name().eq_ignore_ascii_case("content-type"))
+        {
+            headers.push(Header::new(
+                HeaderKey::ContentType,
+                "application/json".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            cli: Client::new()?,
+            url: url.ok_or(Error::ParseError("webhook: url is required".to_string()))?,
+            method,
+            headers,
+            template,
+        })
+    }
+
+    fn body(&self, event: &Event) -> String {
+        match self.template.as_ref() {
+            Some(template) => render_template(template, event),
+            None => event.to_json().to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    async fn notify(&self, event: &Event) -> Result<()> {
+        let body = self.body(event);
+        let headers = Some(self.headers.clone());
+        let response = match self.method.as_str() {
+            "PUT" => self.cli.put(&self.url, headers, body).await?,
+            _ => self.cli.post(&self.url, headers, body).await?,
+        };
+
+        if (200..300).contains(&response.status) {
+            Ok(())
+        } else {
+            Err(Error::HttpError(format!(
+                "webhook {} answered status {}",
+                self.url, response.status
+            )))
+        }
+    }
+}
+
+// Values are JSON string escaped so templates can quote them in JSON bodies
+pub fn render_template(template: &str, event: &Event) -> String {
+    let fields = [
+        ("event", event.kind.as_str().to_string()),
+        ("message", event.message()),
+        ("provider", event.provider.clone().unwrap_or_default()),
+        ("records", event.records.join(", ")),
+        ("old_ip", event.old_ip.clone().unwrap_or_default()),
+        ("new_ip", event.new_ip.clone().unwrap_or_default()),
+        ("error", event.error.clone().unwrap_or_default()),
+        ("failures", event.failures.to_string()),
+    ];
+
+    let mut ret = template.to_string();
+    for (name, value) in fields {
+        let escaped = serde_json::Value::String(value).to_string();
+        ret = ret.replace(&format!("{{{{{}}}}}", name), &escaped[1..escaped.len() - 1]);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let event = Event::sync_failure("cloudflare-1", "bad \"token\"".to_string(), 1);
+        let body = render_template(
+            r#"{"text": "{{event}} on {{provider}}: {{error}}", "ip": "{{new_ip}}"}"#,
+            &event,
+        );
+        assert_eq!(
+            body,
+            r#"{"text": "sync_failure on cloudflare-1: bad \"token\"", "ip": ""}"#
+        );
+    }
+
+    #[test]
+    fn test_webhook_params() {
+        let params = |list: &[(&str, &str)]| {
+            list.iter()
+                .map(|(n, v)| Param::new(n.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(Webhook::new_with_args(params(&[("method", "POST")])).is_err());
+        assert!(Webhook::new_with_args(params(&[("url", "http://x"), ("method", "GET")])).is_err());
+
+        let hook = Webhook::new_with_args(params(&[
+            ("url", "http://hooks.internal/dns"),
+            ("header", "X-Token: abc"),
+        ]))
+        .unwrap();
+        assert_eq!(hook.headers[0].name(), "X-Token");
+        assert_eq!(hook.headers[0].value(), "abc");
+        assert_eq!(hook.headers[1].name(), "Content-Type");
+
+        let event = Event::ip_change("1.1.1.1".to_string(), "2.2.2.2".to_string());
+        let body: serde_json::Value = serde_json::from_str(&hook.body(&event)).unwrap();
+        assert_eq!(body["event"], "ip_change");
+        assert_eq!(body["old_ip"], "1.1.1.1");
+        assert_eq!(body["new_ip"], "2.2.2.2");
+    }
+}
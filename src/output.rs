@@ -0,0 +1,83 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::error::Result;
+
+// How command results are printed. Tables are for humans, the structured
+// formats carry the same result types for scripts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    pub const NAMES: [&str; 3] = ["table", "json", "yaml"];
+
+    pub fn is_structured(&self) -> bool {
+        *self != OutputFormat::Table
+    }
+
+    // Serialize a result in the format, tables are rendered by each command
+    pub fn render<T: Serialize>(&self, value: &T) -> Result<String> {
+        match self {
+            OutputFormat::Json => Ok(serde_json::to_string_pretty(value)?),
+            OutputFormat::Yaml => Ok(serde_yaml::to_string(value)?),
+            OutputFormat::Table => Err(Error::ParseError(
+                "a table cannot be rendered from a value".to_string(),
+            )),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "yaml" => Ok(OutputFormat::Yaml),
+            _ => Err(Error::ParseError(format!(
+                "unknown output format {}, known formats are {}",
+                value,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Table => "table",
+            OutputFormat::Json => "json",
+            OutputFormat::Yaml => "yaml",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_output_format() {
+        assert_eq!("YAML".parse::<OutputFormat>().unwrap(), OutputFormat::Yaml);
+        assert!("xml".parse::<OutputFormat>().is_err());
+        assert!(!OutputFormat::Table.is_structured());
+
+        let value = serde_json::json!({ "name": "home", "ttl": 300 });
+        let yaml = OutputFormat::Yaml.render(&value).unwrap();
+        assert_eq!(yaml, "name: home\nttl: 300\n");
+        let json = OutputFormat::Json.render(&value).unwrap();
+        assert!(json.contains("\"ttl\": 300"));
+        assert!(OutputFormat::Table.render(&value).is_err());
+    }
+}
@@ -0,0 +1,502 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AuthMethod;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ChangeSet;
+use crate::provider::ExistingRecord;
+use crate::provider::ParamList;
+use crate::provider::ParamSpec;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordChange;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::ZoneName;
+use crate::wrapper::http;
+
+#[derive(Debug, Clone)]
+pub enum AdGuardAuth {
+    // The user and password of the web interface
+    Basic { username: String, password: String },
+    // An instance without authentication, or behind a proxy taking care of it
+    None,
+}
+
+// DNS rewrites of an AdGuard Home instance. Rewrites have no zones, the
+// rewrites of a zone are the ones of its name and the names under it. They
+// have no TTL nor comment either: records are listed with an automatic TTL,
+// and carry no owner marker.
+pub struct AdGuard {
+    cli: Cli,
+    auth: AdGuardAuth,
+}
+
+// AdGuard Home providers from a `basic` authentication, or none. The only
+// param of the provider entry is `url`, the address of the web interface like
+// `http://192.168.1.2:3000`.
+pub struct AdGuardFactory;
+
+impl ProviderFactory for AdGuardFactory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        let mut url = None;
+        for param in params.iter() {
+            match param.name.as_str() {
+                "url" => url = Some(param.value.clone()),
+                _ => {
+                    return Err(Error::ParseError(format!(
+                        "{}: unknown param {}",
+                        name, param.name
+                    )));
+                }
+            }
+        }
+        let url =
+            url.ok_or_else(|| Error::ParseError(format!("{}: adguard requires a url", name)))?;
+        Ok(Box::new(AdGuard::new(
+            &url,
+            AdGuardAuth::new_with_params(auth)?,
+        )?))
+    }
+}
+
+impl AdGuard {
+    pub fn new(url: &str, auth: AdGuardAuth) -> Result<Self> {
+        let transport = Arc::new(http::ReqwestTransport::new()?);
+        Ok(Self::with_transport(url, auth, transport))
+    }
+
+    // Provider sending its API requests through `transport`, like a
+    // `MemoryTransport` answering canned AdGuard Home responses in tests
+    pub fn with_transport(
+        url: &str,
+        auth: AdGuardAuth,
+        transport: Arc<dyn http::HttpTransport>,
+    ) -> Self {
+        Self {
+            cli: Cli::new(url, &auth, transport),
+            auth,
+        }
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "adguard",
+            description: "DNS rewrites of AdGuard Home",
+            record_types: vec![RecordType::A, RecordType::AAAA, RecordType::CNAME],
+            auth_methods: vec![AuthMethod {
+                name: "basic",
+                params: vec!["username", "password"],
+            }],
+            params: vec![ParamSpec {
+                name: "url",
+                description: "Address of the AdGuard Home web interface",
+            }],
+            operations: vec![
+                "list",
+                "apply",
+                "dry_run",
+                "verify",
+                "list_records",
+                "list_zone_records",
+                "get_record",
+                "create_record",
+                "delete_record",
+                "delete_records",
+            ],
+        }
+    }
+
+    // Delete the rewrites of `name`, of `record_type` when given. Rewrites
+    // carry no owner marker, so it takes `force`.
+    async fn delete_matching(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: Option<RecordType>,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let records = self
+            .list(zone)
+            .await?
+            .into_iter()
+            .map(|r| r.record)
+            .filter(|r| r.name.eq_ignore_ascii_case(name))
+            .filter(|r| {
+                record_type
+                    .as_ref()
+                    .is_none_or(|t| r.content.record_type() == *t)
+            })
+            .collect::<Vec<_>>();
+        if records.is_empty() {
+            return Ok(vec![]);
+        }
+        if !force {
+            return Err(Error::Provider(format!(
+                "{} may not be managed by dns-syncer, adguard rewrites carry no owner marker, \
+                 force the deletion to remove it anyway",
+                name
+            )));
+        }
+        for record in records.iter() {
+            self.cli.delete(&Rewrite::from_record(record)).await?;
+        }
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl Provider for AdGuard {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        let rewrites = self.cli.rewrites().await?;
+        Ok(rewrites
+            .into_iter()
+            .filter(|r| in_zone(&r.domain, zone))
+            .filter_map(Rewrite::record)
+            .collect())
+    }
+
+    // Rewrites are added and deleted one by one, a failing one does not stop
+    // the others. New rewrites are added before the ones they replace are
+    // deleted, so a name keeps resolving all along; the old rewrites of a name
+    // whose new one failed are kept.
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let zone = &changes.zone;
+        let diff = RewriteDiff::new(&changes.changes);
+        for rewrite in diff.unchanged.iter() {
+            log::debug!(
+                zone = zone, record = rewrite.domain, outcome = "unchanged";
+                "record {} already up to date", rewrite.domain
+            );
+        }
+
+        let mut errors = vec![];
+        let mut failed: Vec<&str> = vec![];
+        for (rewrite, add) in diff
+            .adds
+            .iter()
+            .map(|r| (r, true))
+            .chain(diff.deletes.iter().map(|r| (r, false)))
+        {
+            if cancel.is_cancelled() {
+                log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+                errors.push(Error::Cancelled);
+                break;
+            }
+            if !add && failed.contains(&rewrite.domain.as_str()) {
+                continue;
+            }
+            let result = match add {
+                true => self.cli.add(rewrite).await,
+                false => self.cli.delete(rewrite).await,
+            };
+            match (result, add) {
+                (Ok(()), true) => log::info!(
+                    zone = zone, record = rewrite.domain, content = rewrite.answer,
+                    outcome = "created";
+                    "record {} set to {}", rewrite.domain, rewrite.answer
+                ),
+                (Ok(()), false) => log::info!(
+                    zone = zone, record = rewrite.domain, content = rewrite.answer,
+                    outcome = "deleted";
+                    "record {} {} deleted", rewrite.domain, rewrite.answer
+                ),
+                (Err(e), _) => {
+                    log::error!(
+                        zone = zone, record = rewrite.domain, outcome = "failed";
+                        "change of record {} failed: {}", rewrite.domain, e
+                    );
+                    // An authentication failure fails every change alike
+                    if e.is_auth() {
+                        return Err(e);
+                    }
+                    failed.push(&rewrite.domain);
+                    errors.push(e.for_record(&rewrite.domain));
+                }
+            }
+        }
+        Error::from_errors(errors)
+    }
+
+    // The credentials are checked, rewrites are not bound to zones
+    async fn verify(&self, _zones: &[ZoneName]) -> Result<()> {
+        self.cli.status().await.map_err(|e| match e {
+            Error::Auth { .. } => Error::Auth {
+                provider: String::new(),
+                reason: format!("{} is rejected", self.auth.describe()),
+            },
+            e => e,
+        })
+    }
+
+    async fn create_record(&self, zone: &ZoneName, record: &ProviderRecord) -> Result<()> {
+        let mut record = record.clone();
+        record.name = record.fqdn(zone);
+        if !matches!(
+            record.content,
+            RecordContent::A(_) | RecordContent::AAAA(_) | RecordContent::CNAME(_)
+        ) {
+            return Err(Error::RecordRejected {
+                record: record.name.clone(),
+                reason: "adguard rewrites need an address or a name".to_string(),
+            });
+        }
+        self.cli.add(&Rewrite::from_record(&record)).await
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, None, force).await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, Some(record_type), force)
+            .await
+    }
+
+    fn request_count(&self) -> u64 {
+        self.cli.request_count()
+    }
+}
+
+// Whether `domain`, maybe a wildcard, is `zone` or under it
+fn in_zone(domain: &str, zone: &str) -> bool {
+    let domain = domain.trim_end_matches('.');
+    domain.eq_ignore_ascii_case(zone)
+        || domain
+            .to_ascii_lowercase()
+            .ends_with(&format!(".{}", zone.to_ascii_lowercase()))
+}
+
+///////////////////////////////////////////////////////////
+// Client
+///////////////////////////////////////////////////////////
+impl AdGuardAuth {
+    // Methods: `basic` with `username` and `password`, or none
+    pub fn new_with_params(auth: &AuthParams) -> Result<Self> {
+        match auth.method.as_str() {
+            "" => Ok(AdGuardAuth::None),
+            "basic" => match (auth.get("username"), auth.get("password")) {
+                (Some(username), Some(password)) => Ok(AdGuardAuth::Basic {
+                    username: username.to_string(),
+                    password: password.to_string(),
+                }),
+                _ => Err(Error::Provider(
+                    "adguard basic auth requires both username and password".into(),
+                )),
+            },
+            method => Err(Error::Provider(format!(
+                "{}: unsupported authentication method for adguard provider",
+                method
+            ))),
+        }
+    }
+
+    // Human readable name of the credential that never leaks the secret
+    pub fn describe(&self) -> String {
+        match self {
+            AdGuardAuth::Basic { username, .. } => format!("adguard user {}", username),
+            AdGuardAuth::None => "adguard without authentication".to_string(),
+        }
+    }
+}
+
+// A DNS rewrite: `answer` is an address, a name answered as a CNAME, or `A`
+// or `AAAA` keeping the upstream answer of that type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct Rewrite {
+    pub domain: String,
+    pub answer: String,
+    // Since v0.107.55, rewrites without it are enabled
+    #[serde(default, skip_serializing)]
+    pub enabled: Option<bool>,
+}
+
+impl Rewrite {
+    fn from_record(record: &ProviderRecord) -> Self {
+        Self {
+            domain: record.name.to_ascii_lowercase(),
+            answer: record.content.to_string(),
+            enabled: None,
+        }
+    }
+
+    // The record of an enabled rewrite, rewrites keeping the upstream answer
+    // are left out
+    fn record(self) -> Option<ExistingRecord> {
+        if self.enabled == Some(false) || matches!(self.answer.as_str(), "A" | "AAAA") {
+            return None;
+        }
+        let content = match self.answer.parse() {
+            Ok(std::net::IpAddr::V4(ip)) => RecordContent::A(ip),
+            Ok(std::net::IpAddr::V6(ip)) => RecordContent::AAAA(ip),
+            Err(_) => RecordContent::CNAME(self.answer.trim_end_matches('.').to_ascii_lowercase()),
+        };
+        Some(ExistingRecord {
+            id: None,
+            record: ProviderRecord {
+                name: self.domain.trim_end_matches('.').to_ascii_lowercase(),
+                content,
+                comment: None,
+                op: RecordOp::default(),
+                ttl: TTL::Auto,
+                params: vec![],
+            },
+        })
+    }
+}
+
+// Rewrites to add and delete to bring the live ones of a zone to the desired
+// ones
+#[derive(Debug, Default)]
+pub(super) struct RewriteDiff {
+    // Desired rewrites served as they are
+    pub unchanged: Vec<Rewrite>,
+    pub adds: Vec<Rewrite>,
+    pub deletes: Vec<Rewrite>,
+}
+
+impl RewriteDiff {
+    pub fn new(changes: &[RecordChange]) -> Self {
+        let mut ret = RewriteDiff::default();
+        let mut desired: Vec<Rewrite> = vec![];
+        for change in changes.iter() {
+            let rewrite = Rewrite::from_record(&change.after);
+            if !desired.contains(&rewrite) {
+                desired.push(rewrite);
+            }
+        }
+        // Records under a name with several desired records are in the before
+        // of each change, they count once
+        let mut live: Vec<Rewrite> = vec![];
+        for existing in changes.iter().flat_map(|c| c.before.iter()) {
+            let rewrite = Rewrite::from_record(&existing.record);
+            if !live.contains(&rewrite) {
+                live.push(rewrite);
+            }
+        }
+
+        for rewrite in desired.iter() {
+            match live.contains(rewrite) {
+                true => ret.unchanged.push(rewrite.clone()),
+                false => ret.adds.push(rewrite.clone()),
+            }
+        }
+        ret.deletes = live.into_iter().filter(|r| !desired.contains(r)).collect();
+        ret
+    }
+}
+
+pub(super) struct Cli {
+    cli: http::Client,
+    // Address of the web interface without a trailing slash
+    url: String,
+    // Requests sent so far, whatever came of them
+    requests: AtomicU64,
+}
+
+impl Cli {
+    pub fn new(url: &str, auth: &AdGuardAuth, transport: Arc<dyn http::HttpTransport>) -> Self {
+        let mut cli = http::Client::with_transport(transport);
+        if let AdGuardAuth::Basic { username, password } = auth {
+            let credentials = BASE64.encode(format!("{}:{}", username, password));
+            cli.set_default_headers(vec![http::Header::new(
+                http::HeaderKey::Authorization,
+                format!("Basic {}", credentials),
+            )]);
+        }
+        Self {
+            cli,
+            url: url.trim_end_matches('/').to_string(),
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn request_count(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    async fn get(&self, path: &str) -> Result<String> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let resp = self.cli.get(&format!("{}{}", self.url, path), None).await?;
+        api_result(resp)
+    }
+
+    async fn post(&self, path: &str, body: &impl Serialize) -> Result<()> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let headers = vec![http::Header::new(
+            http::HeaderKey::ContentType,
+            "application/json".to_string(),
+        )];
+        let resp = self
+            .cli
+            .post(
+                &format!("{}{}", self.url, path),
+                Some(headers),
+                serde_json::to_string(body)?,
+            )
+            .await?;
+        api_result(resp).map(|_| ())
+    }
+
+    pub async fn status(&self) -> Result<()> {
+        self.get("/control/status").await.map(|_| ())
+    }
+
+    pub async fn rewrites(&self) -> Result<Vec<Rewrite>> {
+        let body = self.get("/control/rewrite/list").await?;
+        // A fresh instance answers `null`
+        let rewrites: Option<Vec<Rewrite>> = serde_json::from_str(&body)?;
+        Ok(rewrites.unwrap_or_default())
+    }
+
+    pub async fn add(&self, rewrite: &Rewrite) -> Result<()> {
+        self.post("/control/rewrite/add", rewrite).await
+    }
+
+    pub async fn delete(&self, rewrite: &Rewrite) -> Result<()> {
+        self.post("/control/rewrite/delete", rewrite).await
+    }
+}
+
+// Body of a successful answer. AdGuard Home tells what went wrong in the
+// plain text body of a 400 answer.
+fn api_result(resp: http::Response) -> Result<String> {
+    match resp.status {
+        200 => Ok(resp.body),
+        400 => Err(Error::Provider(format!(
+            "adguard api call failed: {}",
+            resp.body.trim()
+        ))),
+        status => Err(Error::from_status(status, resp.retry_after)),
+    }
+}
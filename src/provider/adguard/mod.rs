@@ -0,0 +1,8 @@
+#[allow(clippy::module_inception)]
+mod adguard;
+pub use adguard::AdGuard;
+pub use adguard::AdGuardAuth;
+pub use adguard::AdGuardFactory;
+
+#[cfg(test)]
+mod unit_test;
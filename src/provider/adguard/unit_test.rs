@@ -0,0 +1,250 @@
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use tokio_util::sync::CancellationToken;
+
+use super::adguard::*;
+use crate::http::MemoryTransport;
+use crate::http::Method;
+use crate::provider::AuthParams;
+use crate::provider::BackendRecords;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordStatus;
+use crate::provider::ZoneRecords;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::Param;
+use crate::types::PublicIp;
+
+const URL: &str = "http://adguard.lan:3000";
+
+fn auth() -> AdGuardAuth {
+    AdGuardAuth::Basic {
+        username: "admin".to_string(),
+        password: "secret".to_string(),
+    }
+}
+
+// AdGuard Home with rewrites of example.org and of another domain, one
+// keeping the upstream answer and one disabled
+fn memory_api() -> MemoryTransport {
+    let transport = MemoryTransport::new();
+    transport.route(
+        Method::Get,
+        &format!("{}/control/rewrite/list", URL),
+        200,
+        r#"[
+  {"domain": "home.example.org", "answer": "1.1.1.1"},
+  {"domain": "home.example.org", "answer": "2001:db8::1"},
+  {"domain": "www.example.org", "answer": "home.example.org"},
+  {"domain": "*.lan.example.org", "answer": "10.0.0.1", "enabled": true},
+  {"domain": "upstream.example.org", "answer": "A"},
+  {"domain": "off.example.org", "answer": "10.0.0.2", "enabled": false},
+  {"domain": "example.net", "answer": "3.3.3.3"},
+  {"domain": "notexample.org", "answer": "4.4.4.4"}
+]"#,
+    );
+    transport.route(
+        Method::Post,
+        &format!("{}/control/rewrite/add", URL),
+        200,
+        "OK",
+    );
+    transport.route(
+        Method::Post,
+        &format!("{}/control/rewrite/delete", URL),
+        200,
+        "OK",
+    );
+    transport
+}
+
+fn desired(zone: &str, name: &str) -> BackendRecords {
+    let mut records = BackendRecords::default();
+    records.zones.insert(
+        zone.to_string(),
+        ZoneRecords {
+            records: vec![ProviderRecord {
+                name: name.to_string(),
+                content: RecordContent::Unassigned(RecordType::A),
+                comment: None,
+                ttl: TTL::Auto,
+                op: RecordOp::Create,
+                params: vec![],
+            }],
+        },
+    );
+    records
+}
+
+#[tokio::test]
+async fn test_adguard_list() {
+    let transport = memory_api();
+    let provider = AdGuard::with_transport(URL, auth(), Arc::new(transport.clone()));
+
+    let records = provider.list(&"example.org".to_string()).await.unwrap();
+    let records = records
+        .iter()
+        .map(|r| (r.record.name.as_str(), r.record.content.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        vec![
+            ("home.example.org", "1.1.1.1".to_string()),
+            ("home.example.org", "2001:db8::1".to_string()),
+            ("www.example.org", "home.example.org".to_string()),
+            ("*.lan.example.org", "10.0.0.1".to_string()),
+        ]
+    );
+
+    let request = transport.requests().pop().unwrap();
+    let auth = request
+        .headers
+        .iter()
+        .find(|h| h.name() == "Authorization")
+        .unwrap();
+    assert_eq!(auth.value(), "Basic YWRtaW46c2VjcmV0");
+}
+
+#[tokio::test]
+async fn test_adguard_sync() {
+    let transport = memory_api();
+    let provider = AdGuard::with_transport(URL, auth(), Arc::new(transport.clone()));
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    let outcome = provider
+        .sync(
+            &desired("example.org", "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Updated);
+
+    // The new address is added before the old rewrites are deleted
+    let writes = transport
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == Method::Post)
+        .map(|r| (r.url, r.body.unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        writes,
+        vec![
+            (
+                format!("{}/control/rewrite/add", URL),
+                r#"{"domain":"home.example.org","answer":"2.2.2.2"}"#.to_string()
+            ),
+            (
+                format!("{}/control/rewrite/delete", URL),
+                r#"{"domain":"home.example.org","answer":"1.1.1.1"}"#.to_string()
+            ),
+            (
+                format!("{}/control/rewrite/delete", URL),
+                r#"{"domain":"home.example.org","answer":"2001:db8::1"}"#.to_string()
+            ),
+        ]
+    );
+
+    // Rewrites already as desired are left alone
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(10, 0, 0, 1)), None);
+    let requests = transport.requests().len();
+    let outcome = provider
+        .sync(
+            &desired("example.org", "*.lan"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty());
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Unchanged);
+    assert_eq!(transport.requests().len(), requests + 1);
+}
+
+#[tokio::test]
+async fn test_adguard_errors() {
+    let transport = memory_api();
+    let provider = AdGuard::with_transport(URL, auth(), Arc::new(transport.clone()));
+    let zone = "example.org".to_string();
+
+    // Rewrites cannot be told owned
+    let err = provider
+        .delete_record(&zone, "www.example.org", RecordType::CNAME, false)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("force the deletion"));
+    let deleted = provider
+        .delete_record(&zone, "www.example.org", RecordType::CNAME, true)
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+
+    transport.route(
+        Method::Post,
+        &format!("{}/control/rewrite/add", URL),
+        400,
+        "invalid answer: \"2.2.2\"\n",
+    );
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+    let outcome = provider
+        .sync(
+            &desired(&zone, "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(err.contains("invalid answer"), "{}", err);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Failed);
+    // The old rewrites stay
+    let last = transport.requests().pop().unwrap();
+    assert!(last.url.ends_with("/control/rewrite/add"));
+
+    transport.route(Method::Get, &format!("{}/control/status", URL), 401, "");
+    let err = provider.verify(&[zone]).await.unwrap_err();
+    assert!(err.is_auth());
+    assert!(err.to_string().contains("adguard user admin"));
+}
+
+#[test]
+fn test_adguard_factory() {
+    let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+    let factory = AdGuardFactory;
+    let basic = AuthParams::new(
+        "basic",
+        vec![param("username", "admin"), param("password", "secret")],
+    );
+    let url = vec![param("url", URL)];
+
+    assert!(factory.create("adguard-1", &basic, &url).is_ok());
+    assert!(
+        factory
+            .create("adguard-1", &AuthParams::default(), &url)
+            .is_ok()
+    );
+    let err = factory.create("adguard-1", &basic, &vec![]).err().unwrap();
+    assert!(err.to_string().contains("requires a url"));
+    let err = factory
+        .create(
+            "adguard-1",
+            &basic,
+            &vec![param("url", URL), param("ttl", "60")],
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("unknown param ttl"));
+    let err = factory
+        .create(
+            "adguard-1",
+            &AuthParams::new("basic", vec![param("username", "admin")]),
+            &url,
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("username and password"));
+}
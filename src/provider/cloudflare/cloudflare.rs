@@ -1,16 +1,41 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
 use async_trait::async_trait;
+use futures_util::Stream;
+use futures_util::TryStreamExt;
+use futures_util::stream;
 use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
 
 use crate::error::Error;
 use crate::error::Result;
-use crate::provider::BackendRecords;
+use crate::provider::AuthMethod;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ChangeSet;
+use crate::provider::CredentialCheck;
+use crate::provider::DEFAULT_ZONE_CONCURRENCY;
+use crate::provider::ExistingRecord;
+use crate::provider::IdCache;
+use crate::provider::ParamList;
+use crate::provider::ParamSpec;
 use crate::provider::Provider;
-use crate::provider::ZoneRecords;
-use crate::types::ProviderRecord;
-use crate::types::PublicIp;
-use crate::types::RecordContent;
-use crate::types::RecordOp;
-use crate::types::TTL;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordChange;
+use crate::provider::ZoneAccess;
+use crate::provider::ZoneInfo;
+use crate::record::OWNER_MARKER;
+use crate::record::ProviderParam;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::ZoneName;
 use crate::wrapper::http;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -25,71 +50,511 @@ pub enum Auth {
 
 pub struct Cloudflare {
     cli: Cli,
+    auth: Auth,
+    // Zone ids by zone name, trusted until Cloudflare answers 404 for one
+    zone_ids: Mutex<HashMap<ZoneName, String>>,
+    zone_concurrency: usize,
+}
+
+// Cloudflare providers from an `api_token` or `api_key` authentication. The
+// only param of the provider entry is `zone_concurrency`, the others are per
+// record.
+pub struct CloudflareFactory;
+
+impl ProviderFactory for CloudflareFactory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        if auth.is_empty() {
+            return Err(Error::Provider(format!(
+                "{}: provider has no authentication",
+                name
+            )));
+        }
+        let mut provider = Cloudflare::new(Auth::new_with_params(auth)?)?;
+        if let Some(param) = params.iter().find(|p| p.name == "zone_concurrency") {
+            let concurrency = param.value.parse::<usize>().map_err(|_| {
+                Error::ParseError(format!(
+                    "{}: invalid zone_concurrency {}",
+                    name, param.value
+                ))
+            })?;
+            provider = provider.with_zone_concurrency(concurrency);
+        }
+        Ok(Box::new(provider))
+    }
 }
 
 impl Cloudflare {
-    pub fn new(authentication: Auth) -> Self {
+    pub fn new(authentication: Auth) -> Result<Self> {
+        let transport = Arc::new(http::ReqwestTransport::new()?);
+        Ok(Self::with_transport(authentication, transport))
+    }
+
+    // Provider sending its API requests through `transport`, like a
+    // `MemoryTransport` answering canned Cloudflare responses in tests
+    pub fn with_transport(authentication: Auth, transport: Arc<dyn http::HttpTransport>) -> Self {
         Self {
-            cli: Cli::new(authentication),
+            cli: Cli::new(authentication.clone(), transport),
+            auth: authentication,
+            zone_ids: Mutex::new(HashMap::new()),
+            zone_concurrency: DEFAULT_ZONE_CONCURRENCY,
         }
     }
 
-    async fn sync_zone(
-        &self,
-        zone: &CfZone,
-        records: &ZoneRecords,
-        public_ip: &PublicIp,
-    ) -> Result<()> {
-        for record in records.records.iter() {
-            // Ignore dns OP
-            let mut record = record.clone();
-            record.op = RecordOp::Purge;
-
-            if !record.name.ends_with(zone.name.as_str()) {
-                record.name = format!("{}.{}", record.name, zone.name);
-            }
+    // Sync up to `concurrency` zones at once, one at a time with 1
+    pub fn with_zone_concurrency(mut self, concurrency: usize) -> Self {
+        self.zone_concurrency = concurrency.max(1);
+        self
+    }
 
-            let (v4, v6) = public_ip.ips();
-            if let Err(e) = record.assign_public_ip_if_unassigned(v4, v6) {
-                log::error!("{}", e);
-                continue;
+    // Records of a zone `keep` accepts, the others are dropped page by page
+    async fn list_where<F>(&self, zone: &ZoneName, keep: F) -> Result<Vec<ExistingRecord>>
+    where
+        F: Fn(&CfRecord) -> bool + Send + Sync,
+    {
+        let zone_id = self.zone_id(zone).await?;
+        match self.collect_records(&zone_id, &keep).await {
+            // The zone was recreated since its id was cached
+            Err(Error::HttpStatus { status: 404 }) if self.forget_zone_id(zone) => {
+                let zone_id = self.zone_id(zone).await?;
+                self.collect_records(&zone_id, &keep).await
             }
+            result => result,
+        }
+    }
 
-            if record.content.is_unknown() {
-                continue;
-            }
+    async fn collect_records<F>(&self, zone_id: &str, keep: &F) -> Result<Vec<ExistingRecord>>
+    where
+        F: Fn(&CfRecord) -> bool + Send + Sync,
+    {
+        let mut ret = vec![];
+        let mut pages = std::pin::pin!(self.cli.records_pages(zone_id));
+        while let Some(page) = pages.try_next().await? {
+            ret.extend(page.into_iter().filter(keep).map(ExistingRecord::from));
+        }
+        Ok(ret)
+    }
 
-            dbg!(&record);
-            self.cli.record_op_purge(zone.id.as_str(), record).await?;
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "cloudflare",
+            description: "Cloudflare DNS through the v4 API",
+            record_types: vec![RecordType::A, RecordType::AAAA, RecordType::CNAME],
+            auth_methods: vec![
+                AuthMethod {
+                    name: "api_token",
+                    params: vec!["api_token"],
+                },
+                AuthMethod {
+                    name: "api_key",
+                    params: vec!["email", "key"],
+                },
+            ],
+            params: vec![ParamSpec {
+                name: "proxied",
+                description: "`true` to serve the record through the Cloudflare proxy",
+            }],
+            operations: vec![
+                "list",
+                "apply",
+                "dry_run",
+                "verify",
+                "check_credentials",
+                "list_zones",
+                "list_zone_info",
+                "zone_info",
+                "list_records",
+                "list_zone_records",
+                "get_record",
+                "create_record",
+                "delete_record",
+                "delete_records",
+                "delete_owned_records",
+            ],
         }
+    }
 
-        Ok(())
+    // Delete the records named `name`, of `record_type` when given, leaving
+    // the ones without the owner marker unless forced
+    async fn delete_matching(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: Option<RecordType>,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let zone_id = self.zone_id(zone).await?;
+
+        let (owned, foreign): (Vec<_>, Vec<_>) = self
+            .cli
+            .records_list_by_name(&zone_id, name)
+            .await?
+            .into_iter()
+            .filter(|r| {
+                record_type
+                    .as_ref()
+                    .is_none_or(|t| r.content.record_type() == *t)
+            })
+            .partition(|r| {
+                force
+                    || r.comment
+                        .as_deref()
+                        .is_some_and(|c| c.contains(OWNER_MARKER))
+            });
+        for r in foreign.iter() {
+            log::warn!(
+                zone = zone, record = r.name, outcome = "skipped";
+                "record {} {} is not managed by dns-syncer, kept", r.name, r.content
+            );
+        }
+        if owned.is_empty() && !foreign.is_empty() {
+            return Err(Error::Provider(format!(
+                "{} is not managed by dns-syncer, force the deletion to remove it anyway",
+                name
+            )));
+        }
+
+        let ids = owned.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+        self.cli.records_delete(&zone_id, ids).await?;
+        Ok(owned.into_iter().map(ProviderRecord::from).collect())
+    }
+
+    // Id of a zone, looked up once and then taken from the cache
+    async fn zone_id(&self, zone: &ZoneName) -> Result<String> {
+        if let Some(id) = self.zone_ids.lock().unwrap().get(zone) {
+            return Ok(id.clone());
+        }
+        let id = self.find_zone(zone).await?.id;
+        self.zone_ids
+            .lock()
+            .unwrap()
+            .insert(zone.clone(), id.clone());
+        Ok(id)
+    }
+
+    // Drop the cached id of a zone, telling whether there was one
+    fn forget_zone_id(&self, zone: &ZoneName) -> bool {
+        let forgotten = self.zone_ids.lock().unwrap().remove(zone).is_some();
+        if forgotten {
+            log::debug!(zone = zone; "cached id of zone {} is stale", zone);
+        }
+        forgotten
+    }
+
+    async fn find_zone(&self, zone: &ZoneName) -> Result<CfZone> {
+        match self.cli.zone_list(zone).await? {
+            Some(cf_zone) => {
+                log::debug!(zone = cf_zone.name; "zone {} has id {}", cf_zone.name, cf_zone.id);
+                Ok(cf_zone)
+            }
+            None => Err(Error::ZoneNotFound {
+                zone: zone.to_string(),
+            }),
+        }
     }
 }
 
 #[async_trait]
 impl Provider for Cloudflare {
-    async fn sync(&self, records: BackendRecords, public_ip: PublicIp) -> Result<()> {
-        for (zone_name, zone_records) in records.zones.iter() {
-            let zone_id = self.cli.zone_list(zone_name).await?;
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        self.list_where(zone, |_| true).await
+    }
 
-            if let None = zone_id {
-                println!("zone_id: {} not found", zone_name);
-                continue;
+    async fn list_named(&self, zone: &ZoneName, names: &[String]) -> Result<Vec<ExistingRecord>> {
+        self.list_where(zone, |r| {
+            names.iter().any(|n| n.eq_ignore_ascii_case(&r.name))
+        })
+        .await
+    }
+
+    // All changes of a zone go in one batch request, which Cloudflare applies
+    // as a whole. Records keep their id: a live record of the desired name
+    // and type is patched, a desired record without one is posted, and only
+    // the live records left over are deleted. Records already served as
+    // desired are left out, so a steady state makes no write at all.
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let zone = &changes.zone;
+        let diff = ZoneDiff::new(&changes.changes);
+        for record in diff.unchanged.iter() {
+            log::debug!(
+                zone = zone, record = record.name, outcome = "unchanged";
+                "record {} already up to date", record.name
+            );
+        }
+        if diff.is_empty() {
+            return Ok(());
+        }
+        let zone_id = self.zone_id(zone).await?;
+        if cancel.is_cancelled() {
+            log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+            return Err(Error::Cancelled);
+        }
+
+        let batch = BatchRecord {
+            deletes: Some(
+                diff.deletes
+                    .iter()
+                    .map(|id| BatchRecordDelete { id: id.clone() })
+                    .collect(),
+            ),
+            patches: Some(diff.patches.clone()),
+            posts: Some(diff.posts.clone()),
+        };
+        if let Err(e) = self.cli.records_batch(&zone_id, batch).await {
+            if matches!(e, Error::HttpStatus { status: 404 }) {
+                self.forget_zone_id(zone);
             }
+            log::error!(
+                zone = zone, outcome = "failed";
+                "changes of zone {} failed: {}", zone, e
+            );
+            return Err(e.context(&format!("zone {}", zone)));
+        }
+        for (record, outcome) in diff
+            .patches
+            .iter()
+            .map(|r| (r, "updated"))
+            .chain(diff.posts.iter().map(|r| (r, "created")))
+        {
+            log::info!(
+                zone = zone, record = record.name, content = record.content.to_string(),
+                outcome = outcome;
+                "record {} set to {}", record.name, record.content
+            );
+        }
+        for id in diff.deletes.iter() {
+            log::info!(zone = zone, outcome = "deleted"; "record with id {} deleted", id);
+        }
+        Ok(())
+    }
 
-            let zone = zone_id.unwrap();
-            println!("zone_id: {} {}", zone.id, zone.name);
-            self.sync_zone(&zone, zone_records, &public_ip).await?;
+    async fn verify(&self, zones: &[ZoneName]) -> Result<()> {
+        let desc = self.auth.describe();
+
+        match self.auth {
+            Auth::ApiToken(_) => {
+                let token = self
+                    .cli
+                    .token_verify()
+                    .await
+                    .map_err(|e| rejected(&desc, e))?;
+                if token.status != "active" {
+                    return Err(Error::Auth {
+                        provider: String::new(),
+                        reason: format!("{} (id {}) is {}", desc, token.id, token.status),
+                    });
+                }
+            }
+            Auth::ApiKey { .. } => {
+                self.cli
+                    .user_details()
+                    .await
+                    .map_err(|e| rejected(&desc, e))?;
+            }
+        }
+
+        for zone in zones {
+            if self.cli.zone_list(zone).await?.is_none() {
+                return Err(Error::Auth {
+                    provider: String::new(),
+                    reason: format!(
+                        "{} cannot access zone {}, it needs the Zone:Read and DNS:Edit permissions for it",
+                        desc, zone
+                    ),
+                });
+            }
         }
         Ok(())
     }
+
+    async fn check_credentials(&self, zones: &[ZoneName]) -> Result<CredentialCheck> {
+        let desc = self.auth.describe();
+        let mut ret = CredentialCheck {
+            credential: desc.clone(),
+            ..Default::default()
+        };
+
+        match self.auth {
+            Auth::ApiToken(_) => {
+                let token = self
+                    .cli
+                    .token_verify()
+                    .await
+                    .map_err(|e| rejected(&desc, e))?;
+                ret.id = Some(token.id);
+                ret.expires_on = token.expires_on;
+                let active = token.status == "active";
+                ret.status = Some(token.status);
+                // Zones cannot be checked with a disabled or expired token
+                if !active {
+                    return Ok(ret);
+                }
+            }
+            Auth::ApiKey { .. } => {
+                self.cli
+                    .user_details()
+                    .await
+                    .map_err(|e| rejected(&desc, e))?;
+            }
+        }
+
+        for zone in zones {
+            let access = match self.cli.zone_list(zone).await? {
+                Some(cf_zone) => cf_zone.access(),
+                None => ZoneAccess {
+                    zone: zone.clone(),
+                    permissions: None,
+                    missing: ZONE_PERMISSIONS.iter().map(|p| p.to_string()).collect(),
+                },
+            };
+            ret.zones.push(access);
+        }
+        Ok(ret)
+    }
+
+    async fn list_zones(&self) -> Result<Vec<ZoneName>> {
+        let zones = self.cli.zones_list().await?;
+        Ok(zones.into_iter().map(|z| z.name).collect())
+    }
+
+    async fn list_zone_info(&self) -> Result<Vec<ZoneInfo>> {
+        let zones = self.cli.zones_list().await?;
+        Ok(zones.into_iter().map(ZoneInfo::from).collect())
+    }
+
+    async fn zone_info(&self, zone: &ZoneName) -> Result<Option<ZoneInfo>> {
+        let cf_zone = self.cli.zone_list(zone).await?;
+        Ok(cf_zone.map(ZoneInfo::from))
+    }
+
+    async fn list_records(&self, zone: &ZoneName, name: &str) -> Result<Vec<ProviderRecord>> {
+        let zone_id = self.zone_id(zone).await?;
+        let records = self.cli.records_list_by_name(&zone_id, name).await?;
+        Ok(records.into_iter().map(ProviderRecord::from).collect())
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, None, force).await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, Some(record_type), force)
+            .await
+    }
+
+    async fn delete_owned_records(
+        &self,
+        zone: &ZoneName,
+        records: &[ProviderRecord],
+    ) -> Result<Vec<ProviderRecord>> {
+        let zone_id = self.zone_id(zone).await?;
+
+        let owned = self
+            .cli
+            .records_list(&zone_id)
+            .await?
+            .into_iter()
+            .filter(|r| {
+                r.comment
+                    .as_deref()
+                    .is_some_and(|c| c.contains(OWNER_MARKER))
+                    && records
+                        .iter()
+                        .any(|d| d.name.eq_ignore_ascii_case(&r.name) && d.content == r.content)
+            })
+            .collect::<Vec<_>>();
+
+        let ids = owned.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+        self.cli.records_delete(&zone_id, ids).await?;
+        Ok(owned.into_iter().map(ProviderRecord::from).collect())
+    }
+
+    fn request_count(&self) -> u64 {
+        self.cli.request_count()
+    }
+
+    fn zone_concurrency(&self) -> usize {
+        self.zone_concurrency
+    }
+
+    fn id_cache(&self) -> IdCache {
+        IdCache {
+            zones: self.zone_ids.lock().unwrap().clone(),
+        }
+    }
+
+    fn load_id_cache(&self, cache: IdCache) {
+        self.zone_ids.lock().unwrap().extend(cache.zones);
+    }
+}
+
+// A credential check failing for another reason than the network is the
+// credential being rejected
+fn rejected(desc: &str, e: Error) -> Error {
+    if e.is_retryable() {
+        return e;
+    }
+    Error::Auth {
+        provider: String::new(),
+        reason: format!("{} is rejected: {}", desc, e),
+    }
 }
 
 ///////////////////////////////////////////////////////////
 // Client
 ///////////////////////////////////////////////////////////
 impl Auth {
+    // Methods: `api_token` with the `api_token` param, `api_key` with `email`
+    // and `key`
+    pub fn new_with_params(auth: &AuthParams) -> Result<Self> {
+        let get = |name: &str| auth.get(name).map(String::from);
+        match auth.method.as_str() {
+            "api_token" => {
+                let api_token = get("api_token").ok_or(Error::Provider(
+                    "cloudflare authencation method is declared as api_token, but api_token is not found"
+                        .to_string(),
+                ))?;
+                Ok(Auth::ApiToken(api_token))
+            }
+            "api_key" => match (get("email"), get("key")) {
+                (Some(email), Some(key)) => Ok(Auth::ApiKey { email, key }),
+                _ => Err(Error::Provider(
+                    "cloudflare api_key auth requires both email and key".into(),
+                )),
+            },
+            method => Err(Error::Provider(format!(
+                "{}: unsupported authentication method for cloudflare provider",
+                method
+            ))),
+        }
+    }
+
+    // Human readable name of the credential that never leaks the secret
+    pub fn describe(&self) -> String {
+        match self {
+            Auth::ApiToken(token) => {
+                let prefix: String = token.chars().take(4).collect();
+                format!("api token {}****", prefix)
+            }
+            Auth::ApiKey { email, .. } => format!("api key of {}", email),
+        }
+    }
+
     fn http_headers(&self) -> Vec<http::Header> {
         match self {
             Auth::ApiToken(token) => vec![http::Header::new(
@@ -112,31 +577,42 @@ impl Auth {
 
 pub(super) struct Cli {
     cli: http::Client,
+    // Requests sent so far, whatever came of them
+    requests: AtomicU64,
 }
 
 impl Cli {
-    pub fn new(auth: Auth) -> Self {
+    pub fn new(auth: Auth, transport: Arc<dyn http::HttpTransport>) -> Self {
         let mut headers = auth.http_headers();
         headers.push(http::Header::new(
             http::HeaderKey::ContentType,
             "application/json".to_string(),
         ));
 
-        let mut cli = http::Client::new();
+        let mut cli = http::Client::with_transport(transport);
         cli.set_default_headers(headers);
 
-        Self { cli }
+        Self {
+            cli,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn request_count(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
     }
 }
 
 // Basic http method wrappers
 impl Cli {
     async fn get(&self, url: &str) -> Result<http::Response> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
         let resp = self.cli.get(url, None).await?;
         Ok(resp)
     }
 
     async fn post(&self, url: &str, body: &str) -> Result<http::Response> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
         let resp = self.cli.post(url, None, body.to_string()).await?;
         Ok(resp)
     }
@@ -151,16 +627,43 @@ impl Cli {
 }
 
 // Cloudflare API response
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct CfMessage {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct CfResultInfo {
+    pub page: u32,
+    pub total_pages: u32,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub(super) struct CfResponse {
     success: bool,
+    #[serde(default)]
     result: serde_json::Value,
+    #[serde(default)]
+    errors: Vec<CfMessage>,
+    #[serde(default)]
+    result_info: Option<CfResultInfo>,
 }
 
 impl CfResponse {
-    fn into_json(self) -> Result<serde_json::Value> {
+    pub(super) fn into_json(self) -> Result<serde_json::Value> {
         if self.success {
             Ok(self.result)
+        } else if !self.errors.is_empty() {
+            let errors = self
+                .errors
+                .iter()
+                .map(|e| format!("{} (code {})", e.message, e.code))
+                .collect::<Vec<_>>();
+            Err(Error::ParseError(format!(
+                "cloudflare api call failed: {}",
+                errors.join(", ")
+            )))
         } else {
             Err(Error::ParseError(format!(
                 "cloudflare api call failed: {:?}",
@@ -170,10 +673,83 @@ impl CfResponse {
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct CfTokenStatus {
+    pub id: String,
+    pub status: String,
+    #[serde(default)]
+    pub expires_on: Option<String>,
+}
+
+// Cloudflare user API
+impl Cli {
+    // Cloudflare reports invalid credentials with a non-200 status and the reason
+    // in the body, so the body is parsed whatever the status is.
+    pub async fn token_verify(&self) -> Result<CfTokenStatus> {
+        let url = "https://api.cloudflare.com/client/v4/user/tokens/verify";
+        let resp = self.get(url).await?;
+        let resp: CfResponse = serde_json::from_str(&resp.body)?;
+        let token: CfTokenStatus = serde_json::from_value(resp.into_json()?)?;
+        Ok(token)
+    }
+
+    pub async fn user_details(&self) -> Result<()> {
+        let url = "https://api.cloudflare.com/client/v4/user";
+        let resp = self.get(url).await?;
+        let resp: CfResponse = serde_json::from_str(&resp.body)?;
+        resp.into_json()?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub(super) struct CfZone {
     pub id: String,
     pub name: String,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub plan: Option<CfPlan>,
+    // Permissions of the credential on the zone, like "#dns_records:edit"
+    #[serde(default)]
+    pub permissions: Option<Vec<String>>,
+}
+
+// Permissions a credential needs on a zone to sync its records
+const ZONE_PERMISSIONS: [&str; 3] = ["#zone:read", "#dns_records:read", "#dns_records:edit"];
+
+impl CfZone {
+    pub fn access(&self) -> ZoneAccess {
+        let missing = match self.permissions.as_ref() {
+            Some(granted) => ZONE_PERMISSIONS
+                .iter()
+                .filter(|p| !granted.iter().any(|g| g == *p))
+                .map(|p| p.to_string())
+                .collect(),
+            None => vec![],
+        };
+        ZoneAccess {
+            zone: self.name.clone(),
+            permissions: self.permissions.clone(),
+            missing,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(super) struct CfPlan {
+    pub name: String,
+}
+
+impl From<CfZone> for ZoneInfo {
+    fn from(zone: CfZone) -> Self {
+        Self {
+            name: zone.name,
+            id: Some(zone.id),
+            plan: zone.plan.map(|p| p.name),
+            status: zone.status,
+        }
+    }
 }
 
 // Cloudflare zone API
@@ -190,6 +766,96 @@ impl Cli {
             _ => Err(Error::ParseError(format!("multiple zones found: {}", name))),
         }
     }
+
+    pub async fn zones_list(&self) -> Result<Vec<CfZone>> {
+        let mut ret = vec![];
+        let mut page = 1;
+
+        loop {
+            let url = format!(
+                "https://api.cloudflare.com/client/v4/zones?per_page=50&page={}",
+                page
+            );
+            let resp = self.get(&url).await?;
+            let resp: CfResponse = serde_json::from_str(&resp.into_body()?)?;
+            let info = resp.result_info.clone();
+            let zones: Vec<CfZone> = serde_json::from_value(resp.into_json()?)?;
+            ret.extend(zones);
+
+            match info {
+                Some(info) if info.page < info.total_pages => page += 1,
+                _ => break,
+            }
+        }
+
+        Ok(ret)
+    }
+}
+
+// Writes bringing the live records of a zone to the desired ones
+#[derive(Debug, Default)]
+pub(super) struct ZoneDiff<'a> {
+    // Desired records served as they are
+    pub unchanged: Vec<&'a ProviderRecord>,
+    // Live records of the desired name and type, rewritten under their id
+    pub patches: Vec<CfRecord>,
+    // Desired records without a live record to take over
+    pub posts: Vec<CfRecord>,
+    // Live records under the desired names no desired record took over
+    pub deletes: Vec<String>,
+}
+
+impl<'a> ZoneDiff<'a> {
+    pub fn new(changes: &'a [RecordChange]) -> Self {
+        let mut ret = ZoneDiff::default();
+
+        // Records under a name with several desired records are in the before
+        // of each change, they count once
+        let mut live: Vec<CfRecord> = vec![];
+        for existing in changes.iter().flat_map(|c| c.before.iter()) {
+            let Some(id) = existing.id.as_ref() else {
+                continue;
+            };
+            if !live.iter().any(|r| &r.id == id) {
+                let mut record = CfRecord::from(existing.record.clone());
+                record.id = id.clone();
+                live.push(record);
+            }
+        }
+
+        // Identical records are matched first, so that a name with several
+        // records of a type does not patch one into another
+        let mut pending = vec![];
+        for change in changes.iter() {
+            let desired = CfRecord::from(change.after.clone());
+            match live.iter().position(|r| r.serves(&desired)) {
+                Some(pos) => {
+                    live.remove(pos);
+                    ret.unchanged.push(&change.after);
+                }
+                None => pending.push(desired),
+            }
+        }
+        for mut desired in pending {
+            let same_type = live.iter().position(|r| {
+                r.name.eq_ignore_ascii_case(&desired.name)
+                    && r.content.record_type() == desired.content.record_type()
+            });
+            match same_type {
+                Some(pos) => {
+                    desired.id = live.remove(pos).id;
+                    ret.patches.push(desired);
+                }
+                None => ret.posts.push(desired),
+            }
+        }
+        ret.deletes = live.into_iter().map(|r| r.id).collect();
+        ret
+    }
+
+    fn is_empty(&self) -> bool {
+        self.patches.is_empty() && self.posts.is_empty() && self.deletes.is_empty()
+    }
 }
 
 // Cloudflare record
@@ -205,6 +871,17 @@ pub(super) struct CfRecord {
     pub content: RecordContent,
 }
 
+impl CfRecord {
+    // Equal to `desired` in every field Cloudflare keeps
+    fn serves(&self, desired: &CfRecord) -> bool {
+        self.name.eq_ignore_ascii_case(&desired.name)
+            && self.content == desired.content
+            && self.ttl == desired.ttl
+            && self.proxied == desired.proxied
+            && self.comment == desired.comment
+    }
+}
+
 impl From<ProviderRecord> for CfRecord {
     fn from(record: ProviderRecord) -> Self {
         Self {
@@ -224,18 +901,64 @@ impl From<ProviderRecord> for CfRecord {
     }
 }
 
+impl From<CfRecord> for ExistingRecord {
+    fn from(record: CfRecord) -> Self {
+        Self {
+            id: Some(record.id.clone()),
+            record: record.into(),
+        }
+    }
+}
+
+impl From<CfRecord> for ProviderRecord {
+    fn from(record: CfRecord) -> Self {
+        Self {
+            name: record.name,
+            content: record.content,
+            comment: record.comment,
+            op: RecordOp::default(),
+            ttl: match record.ttl {
+                1 => TTL::Auto,
+                v => TTL::Value(v),
+            },
+            params: vec![ProviderParam {
+                name: "proxied".to_string(),
+                value: record.proxied.to_string(),
+            }],
+        }
+    }
+}
+
 // Cloudflare record API
 impl Cli {
-    pub async fn _records_list(&self, zone_id: &str) -> Result<Vec<CfRecord>> {
-        let url = format!(
-            "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
-            zone_id
-        );
-        let resp = self.get(&url).await?;
-        let resp: CfResponse = serde_json::from_str(&resp.into_body()?)?;
-        let jsonbody = resp.into_json()?;
-        let records: Vec<CfRecord> = serde_json::from_value(jsonbody)?;
-        Ok(records)
+    pub async fn records_list(&self, zone_id: &str) -> Result<Vec<CfRecord>> {
+        self.records_pages(zone_id).try_concat().await
+    }
+
+    // Pages of the records of a zone, a page being fetched once the one
+    // before it is consumed
+    pub fn records_pages<'a>(
+        &'a self,
+        zone_id: &'a str,
+    ) -> impl Stream<Item = Result<Vec<CfRecord>>> + Send + 'a {
+        stream::try_unfold(Some(1), move |page| async move {
+            let Some(page) = page else {
+                return Ok(None);
+            };
+            let url = format!(
+                "https://api.cloudflare.com/client/v4/zones/{}/dns_records?per_page=100&page={}",
+                zone_id, page
+            );
+            let resp = self.get(&url).await?;
+            let resp: CfResponse = serde_json::from_str(&resp.into_body()?)?;
+            let info = resp.result_info.clone();
+            let records: Vec<CfRecord> = serde_json::from_value(resp.into_json()?)?;
+            let next = match info {
+                Some(info) if info.page < info.total_pages => Some(page + 1),
+                _ => None,
+            };
+            Ok(Some((records, next)))
+        })
     }
 
     pub async fn records_list_by_name(&self, zone_id: &str, name: &str) -> Result<Vec<CfRecord>> {
@@ -253,71 +976,43 @@ impl Cli {
 
 /// Cloudflare record API operations by op
 #[derive(Debug, Clone, Serialize)]
-struct BatchRecordDelete {
+pub(super) struct BatchRecordDelete {
     id: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
-struct BatchRecord {
+pub(super) struct BatchRecord {
     deletes: Option<Vec<BatchRecordDelete>>,
     patches: Option<Vec<CfRecord>>,
     posts: Option<Vec<CfRecord>>,
 }
 
 impl Cli {
-    pub async fn _record_op_create(&self, zone_id: &str, record: ProviderRecord) -> Result<()> {
-        let url = format!(
-            "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
-            zone_id
-        );
-        let cf_record = CfRecord::from(record);
-        let body = serde_json::to_string(&cf_record)?;
-        println!("{}", body);
-        let resp = self.post(&url, &body).await?;
-        let resp: CfResponse =
-            serde_json::from_str(&resp.into_body().map_err(|e| {
-                Error::HttpError(format!("create record failed: {}", e.to_string()))
-            })?)?;
-        resp.into_json().map_err(|e| {
-            Error::HttpError(format!(
-                "create record failed from cloudflare: {}",
-                e.to_string()
-            ))
-        })?;
-
-        Ok(())
-    }
-
-    pub async fn record_op_purge(&self, zone_id: &str, record: ProviderRecord) -> Result<()> {
-        let rcd = self.records_list_by_name(zone_id, &record.name).await?;
-        let deletes: Vec<BatchRecordDelete> = rcd
-            .iter()
-            .map(|r| BatchRecordDelete { id: r.id.clone() })
-            .collect();
+    pub async fn records_delete(&self, zone_id: &str, ids: Vec<String>) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
         let batch = BatchRecord {
-            deletes: Some(deletes),
+            deletes: Some(ids.into_iter().map(|id| BatchRecordDelete { id }).collect()),
             patches: None,
-            posts: Some(vec![record.into()]),
+            posts: None,
         };
+        self.records_batch(zone_id, batch)
+            .await
+            .map_err(|e| e.context("delete records failed from cloudflare"))
+    }
 
+    // Send deletes, patches and posts of a zone in one request, Cloudflare
+    // makes all of them or none
+    pub async fn records_batch(&self, zone_id: &str, batch: BatchRecord) -> Result<()> {
         let url = format!(
             "https://api.cloudflare.com/client/v4/zones/{}/dns_records/batch",
             zone_id
         );
         let body = serde_json::to_string(&batch)?;
         let resp = self.post(&url, &body).await?;
-        let json = resp.into_body().map_err(|e| {
-            Error::HttpError(format!("force overwrite record failed: {}", e.to_string()))
-        })?;
-
-        let resp: CfResponse = serde_json::from_str(&json)?;
-
-        resp.into_json().map_err(|e| {
-            Error::HttpError(format!(
-                "force overwrite record failed from cloudflare: {}",
-                e.to_string()
-            ))
-        })?;
+        let resp: CfResponse = serde_json::from_str(&resp.into_body()?)?;
+        resp.into_json()?;
         Ok(())
     }
 }
@@ -1,8 +1,8 @@
-mod serializer;
-
+#[allow(clippy::module_inception)]
 mod cloudflare;
 pub use cloudflare::Auth;
 pub use cloudflare::Cloudflare;
+pub use cloudflare::CloudflareFactory;
 
 #[cfg(test)]
 mod unit_test;
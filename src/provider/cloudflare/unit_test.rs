@@ -1,51 +1,33 @@
 use std::net::Ipv4Addr;
+use std::sync::Arc;
 
-use super::cloudflare::*;
-use crate::types::ProviderParam;
-use crate::types::ProviderRecord;
-use crate::types::RecordContent;
-use crate::types::RecordOp;
-use crate::types::RecordType;
-use crate::types::TTL;
-
-#[tokio::test]
-async fn test_cf_record_op_purge() {
-    let (zone_name, zone_id) = zone_name();
-    let record = ProviderRecord {
-        name: format!("testcf.{}", zone_name),
-        content: RecordContent::A(Ipv4Addr::new(1, 2, 3, 5)),
-        comment: Some("unit test test_cf_record_op_create".to_string()),
-        ttl: TTL::Value(3600),
-        op: RecordOp::Create,
-        params: vec![ProviderParam {
-            name: "proxied".to_string(),
-            value: "true".to_string(),
-        }],
-    };
-
-    let cli = init_cli();
-    let _resp = cli.record_op_purge(&zone_id, record).await.unwrap();
-}
-
-#[tokio::test]
-async fn test_cf_record_op_create() {
-    let (zone_name, zone_id) = zone_name();
-    let record = ProviderRecord {
-        name: format!("testcf.{}", zone_name),
-        content: RecordContent::A(Ipv4Addr::new(1, 2, 3, 5)),
-        comment: Some("unit test test_cf_record_op_create".to_string()),
-        ttl: TTL::Auto,
-        op: RecordOp::Create,
-        params: vec![ProviderParam {
-            name: "proxied".to_string(),
-            value: "true".to_string(),
-        }],
-    };
+use tokio_util::sync::CancellationToken;
 
-    let cli = init_cli();
-    println!("{:?}", CfRecord::from(record.clone()));
-    let _resp = cli._record_op_create(&zone_id, record).await.unwrap();
-}
+use super::cloudflare::*;
+use crate::http::MemoryTransport;
+use crate::http::Method;
+use crate::http::ReqwestTransport;
+use crate::provider::BackendRecords;
+use crate::provider::ChangeSet;
+use crate::provider::ExistingRecord;
+use crate::provider::IdCache;
+use crate::provider::PlanOp;
+use crate::provider::Provider;
+use crate::provider::RecordStatus;
+use crate::provider::ZoneInfo;
+use crate::provider::ZoneRecords;
+use crate::record::OWNER_MARKER;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::testing::CloudflareSimulator;
+use crate::testing::V4;
+use crate::testing::V4_NEXT;
+use crate::testing::V6;
+use crate::testing::record;
+use crate::types::PublicIp;
 
 #[tokio::test]
 async fn test_cf_records_list_by_name() {
@@ -60,7 +42,7 @@ async fn test_cf_records_list_by_name() {
 async fn test_cf_records_list() {
     let cli = init_cli();
     let (_name, id) = zone_name();
-    let records = cli._records_list(&id).await.unwrap();
+    let records = cli.records_list(&id).await.unwrap();
     println!("{:?}", records);
 }
 
@@ -77,6 +59,14 @@ async fn test_cf_zone_list() {
     }
 }
 
+#[tokio::test]
+async fn test_cf_zones_list() {
+    let cli = init_cli();
+    let (name, _id) = zone_name();
+    let zones = cli.zones_list().await.unwrap();
+    assert!(zones.iter().any(|z| z.name == name));
+}
+
 #[test]
 fn test_cf_record_deserialize() {
     let json = r#"{
@@ -100,7 +90,7 @@ fn test_cf_record_deserialize() {
         record.content,
         RecordContent::A(Ipv4Addr::new(42, 192, 202, 2))
     );
-    assert_eq!(record.proxied, true);
+    assert!(record.proxied);
     println!("{:?}", record);
 }
 
@@ -123,7 +113,7 @@ fn test_cf_record_deserialize_unassigned() {
     let record: CfRecord = serde_json::from_str(json).unwrap();
     assert_eq!(record.comment, Some("hello".to_string()));
     assert_eq!(record.content, RecordContent::Unassigned(RecordType::A));
-    assert_eq!(record.proxied, true);
+    assert!(record.proxied);
     println!("{:?}", record);
 }
 
@@ -174,10 +164,407 @@ value:
     }
 }
 
+#[test]
+fn test_cf_auth_describe() {
+    let auth = Auth::ApiToken("1234567890".to_string());
+    assert_eq!(auth.describe(), "api token 1234****");
+
+    let auth = Auth::ApiKey {
+        email: "test@example.com".to_string(),
+        key: "1234567890".to_string(),
+    };
+    assert_eq!(auth.describe(), "api key of test@example.com");
+}
+
+#[test]
+fn test_cf_response_errors() {
+    let json = r#"{
+        "success": false,
+        "errors": [{"code": 1000, "message": "Invalid API Token"}],
+        "messages": [],
+        "result": null
+    }"#;
+    let resp: CfResponse = serde_json::from_str(json).unwrap();
+    let err = resp.into_json().unwrap_err();
+    assert!(err.to_string().contains("Invalid API Token (code 1000)"));
+}
+
+#[test]
+fn test_cf_zone_info() {
+    let json = r#"{
+        "id": "023e105f4ecef8ad9ca31a8372d0c353",
+        "name": "example.com",
+        "status": "active",
+        "plan": {"id": "0feeeeeeeeeeeeeeeeeeeeeeeeeeeeee", "name": "Free Website"}
+    }"#;
+    let zone: CfZone = serde_json::from_str(json).unwrap();
+    let info = ZoneInfo::from(zone);
+    assert_eq!(info.name, "example.com");
+    assert_eq!(info.id.as_deref(), Some("023e105f4ecef8ad9ca31a8372d0c353"));
+    assert_eq!(info.plan.as_deref(), Some("Free Website"));
+    assert_eq!(info.status.as_deref(), Some("active"));
+
+    let zone: CfZone = serde_json::from_str(r#"{"id": "1", "name": "example.org"}"#).unwrap();
+    assert_eq!(ZoneInfo::from(zone).plan, None);
+}
+
+#[test]
+fn test_cf_zone_access() {
+    let json = r##"{
+        "id": "1",
+        "name": "example.com",
+        "permissions": ["#zone:read", "#dns_records:read", "#analytics:read"]
+    }"##;
+    let zone: CfZone = serde_json::from_str(json).unwrap();
+    let access = zone.access();
+    assert_eq!(access.zone, "example.com");
+    assert_eq!(access.missing, vec!["#dns_records:edit"]);
+
+    // Without permissions listed nothing can be told missing
+    let zone: CfZone = serde_json::from_str(r#"{"id": "1", "name": "example.org"}"#).unwrap();
+    assert_eq!(zone.access().permissions, None);
+    assert!(zone.access().missing.is_empty());
+}
+
+const API: &str = "https://api.cloudflare.com/client/v4";
+
+// Cloudflare serving example.org with one A record, answering batches with
+// success
+fn memory_api() -> MemoryTransport {
+    let transport = MemoryTransport::new();
+    transport.route(
+        Method::Get,
+        &format!("{}/zones?name=example.org", API),
+        200,
+        r#"{"success":true,"result":[{"id":"z1","name":"example.org"}]}"#,
+    );
+    transport.route(
+        Method::Get,
+        &format!("{}/zones?name=missing.org", API),
+        200,
+        r#"{"success":true,"result":[]}"#,
+    );
+    transport.route(
+        Method::Get,
+        &format!("{}/zones/z1/dns_records", API),
+        200,
+        r#"{"success":true,"result":[{"id":"r1","name":"home.example.org","type":"A",
+            "content":"1.1.1.1","proxied":false,"ttl":1,"comment":null}],
+            "result_info":{"page":1,"total_pages":1}}"#,
+    );
+    transport.route(
+        Method::Post,
+        &format!("{}/zones/z1/dns_records/batch", API),
+        200,
+        r#"{"success":true,"result":{}}"#,
+    );
+    transport
+}
+
+fn desired(zone: &str, name: &str) -> BackendRecords {
+    let mut records = BackendRecords::default();
+    records.zones.insert(
+        zone.to_string(),
+        ZoneRecords {
+            records: vec![ProviderRecord {
+                name: name.to_string(),
+                content: RecordContent::Unassigned(RecordType::A),
+                comment: None,
+                ttl: TTL::Auto,
+                op: RecordOp::Create,
+                params: vec![],
+            }],
+        },
+    );
+    records
+}
+
+#[tokio::test]
+async fn test_cf_sync_with_memory_transport() {
+    let transport = memory_api();
+    let auth = Auth::ApiToken("token".to_string());
+    let provider = Cloudflare::with_transport(auth, Arc::new(transport.clone()));
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    let changes = provider
+        .dry_run(&desired("example.org", "home"), &public_ip)
+        .await
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].op, PlanOp::Update);
+
+    let mut records = desired("example.org", "home");
+    records.zones.extend(desired("missing.org", "home").zones);
+    let zones = provider
+        .sync(&records, &public_ip, &CancellationToken::new())
+        .await
+        .into_result()
+        .unwrap();
+
+    let requests = transport.requests();
+    assert_eq!(zones.len(), 1);
+    assert_eq!(zones[0].records[0].status, RecordStatus::Updated);
+    // The zone id was cached by the dry run, only the listing and the batch
+    assert_eq!(zones[0].requests, 2);
+    assert!(requests.iter().all(|r| {
+        r.headers
+            .iter()
+            .any(|h| h.name() == "Authorization" && h.value() == "Bearer token")
+    }));
+    let batches = requests
+        .iter()
+        .filter(|r| r.method == Method::Post)
+        .collect::<Vec<_>>();
+    assert_eq!(batches.len(), 1);
+    let batch: serde_json::Value =
+        serde_json::from_str(batches[0].body.as_deref().unwrap()).unwrap();
+    // The live record is patched in place instead of deleted and posted
+    assert_eq!(batch["deletes"], serde_json::json!([]));
+    assert_eq!(batch["posts"], serde_json::json!([]));
+    assert_eq!(batch["patches"][0]["id"], "r1");
+    assert_eq!(batch["patches"][0]["content"], "2.2.2.2");
+    assert_eq!(batch["patches"][0]["comment"], "[dns-syncer]");
+}
+
+#[tokio::test]
+async fn test_cf_sync_multi_type_name_with_simulator() {
+    let sim = CloudflareSimulator::start().await;
+    sim.add_zone("example.org");
+    sim.add_record("example.org", record("home", RecordContent::A(V4_NEXT)));
+    sim.add_record(
+        "example.org",
+        record("home", RecordContent::A(Ipv4Addr::new(192, 0, 2, 3))),
+    );
+    sim.add_record(
+        "example.org",
+        record("home", RecordContent::AAAA("2001:db8::2".parse().unwrap())),
+    );
+    let provider = sim.provider();
+    let public_ip = PublicIp::new(Some(V4), Some(V6));
+
+    let mut records = desired("example.org", "home");
+    let mut aaaa = records.zones["example.org"].records[0].clone();
+    aaaa.content = RecordContent::Unassigned(RecordType::AAAA);
+    records
+        .zones
+        .get_mut("example.org")
+        .unwrap()
+        .records
+        .push(aaaa);
+    let outcome = provider
+        .sync(&records, &public_ip, &CancellationToken::new())
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+
+    // The live records of the name are in the before of both changes, the
+    // extra address is deleted once in a single batch the simulator accepts
+    assert_eq!(sim.batch_count(), 1);
+    let contents = sim
+        .records("example.org")
+        .iter()
+        .map(|r| r.content.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(contents, vec!["192.0.2.1", "2001:db8::1"]);
+}
+
+#[tokio::test]
+async fn test_cf_sync_unchanged_with_simulator() {
+    let sim = CloudflareSimulator::start().await;
+    sim.add_zone("example.org");
+    let mut live = record("home", RecordContent::A(V4));
+    live.comment = Some(OWNER_MARKER.to_string());
+    sim.add_record("example.org", live);
+    let provider = sim.provider();
+    let public_ip = PublicIp::new(Some(V4), None);
+
+    // A record already served as desired makes no batch request
+    let outcome = provider
+        .sync(
+            &desired("example.org", "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Unchanged);
+    assert_eq!(sim.batch_count(), 0);
+    assert_eq!(sim.records("example.org").len(), 1);
+}
+
+#[tokio::test]
+async fn test_cf_list_named_pages() {
+    let transport = memory_api();
+    let page = |n: u32, name: &str| {
+        format!(
+            r#"{{"success":true,"result":[{{"id":"r{n}","name":"{name}","type":"A",
+                "content":"1.1.1.{n}","proxied":false,"ttl":1,"comment":null}}],
+                "result_info":{{"page":{n},"total_pages":2}}}}"#
+        )
+    };
+    for (n, name) in [(1, "home.example.org"), (2, "www.example.org")] {
+        transport.route(
+            Method::Get,
+            &format!("{}/zones/z1/dns_records?per_page=100&page={}", API, n),
+            200,
+            &page(n, name),
+        );
+    }
+    let auth = Auth::ApiToken("token".to_string());
+    let provider = Cloudflare::with_transport(auth, Arc::new(transport));
+    let zone = "example.org".to_string();
+
+    // Both pages are read, only the records of the names are kept
+    let records = provider
+        .list_named(&zone, &["WWW.example.org".to_string()])
+        .await
+        .unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].id.as_deref(), Some("r2"));
+    assert_eq!(provider.list(&zone).await.unwrap().len(), 2);
+}
+
+#[test]
+fn test_cf_zone_diff() {
+    let live = |id: &str, name: &str, content: RecordContent| ExistingRecord {
+        id: Some(id.to_string()),
+        record: ProviderRecord {
+            name: name.to_string(),
+            content,
+            comment: None,
+            ttl: TTL::Auto,
+            op: RecordOp::default(),
+            params: vec![],
+        },
+    };
+    let existing = vec![
+        live(
+            "r1",
+            "home.example.org",
+            RecordContent::A(Ipv4Addr::new(1, 1, 1, 1)),
+        ),
+        live(
+            "r2",
+            "home.example.org",
+            RecordContent::A(Ipv4Addr::new(3, 3, 3, 3)),
+        ),
+        live(
+            "r3",
+            "www.example.org",
+            RecordContent::CNAME("home.example.org".to_string()),
+        ),
+        live(
+            "r4",
+            "alias.example.org",
+            RecordContent::CNAME("home.example.org".to_string()),
+        ),
+    ];
+    let desired = |name: &str, content: RecordContent| ProviderRecord {
+        name: name.to_string(),
+        content,
+        comment: None,
+        ttl: TTL::Auto,
+        op: RecordOp::default(),
+        params: vec![],
+    };
+    let changes = ChangeSet::replace_by_name(
+        "example.org",
+        &[
+            desired(
+                "home.example.org",
+                RecordContent::A(Ipv4Addr::new(2, 2, 2, 2)),
+            ),
+            desired(
+                "home.example.org",
+                RecordContent::A(Ipv4Addr::new(3, 3, 3, 3)),
+            ),
+            desired(
+                "www.example.org",
+                RecordContent::A(Ipv4Addr::new(2, 2, 2, 2)),
+            ),
+            desired(
+                "alias.example.org",
+                RecordContent::CNAME("home.example.org".to_string()),
+            ),
+        ],
+        &existing,
+    );
+
+    let diff = ZoneDiff::new(&changes.changes);
+    // The identical records are left alone, even behind another record of
+    // the same name and type
+    let unchanged = diff.unchanged.iter().map(|r| r.content.to_string());
+    assert_eq!(
+        unchanged.collect::<Vec<_>>(),
+        vec!["3.3.3.3", "home.example.org"]
+    );
+    assert_eq!(diff.patches.len(), 1);
+    assert_eq!(diff.patches[0].id, "r1");
+    assert_eq!(diff.patches[0].content.to_string(), "2.2.2.2");
+    // Another type under the name is replaced
+    assert_eq!(diff.posts.len(), 1);
+    assert_eq!(diff.posts[0].name, "www.example.org");
+    assert_eq!(diff.deletes, vec!["r3".to_string()]);
+}
+
+#[tokio::test]
+async fn test_cf_zone_id_cache() {
+    let transport = memory_api();
+    let auth = Auth::ApiToken("token".to_string());
+    let provider = Cloudflare::with_transport(auth, Arc::new(transport.clone()));
+    let zone = "example.org".to_string();
+
+    // A cached id saves the zone lookup
+    let mut cache = IdCache::default();
+    cache.zones.insert(zone.clone(), "z1".to_string());
+    provider.load_id_cache(cache.clone());
+    assert_eq!(provider.list(&zone).await.unwrap().len(), 1);
+    assert_eq!(transport.requests().len(), 1);
+
+    // A stale id is looked up again
+    cache.zones.insert(zone.clone(), "gone".to_string());
+    provider.load_id_cache(cache);
+    assert_eq!(provider.list(&zone).await.unwrap().len(), 1);
+    assert_eq!(transport.requests().len(), 4);
+    assert_eq!(provider.id_cache().zones[&zone], "z1");
+}
+
+#[tokio::test]
+async fn test_cf_rejected_token_with_memory_transport() {
+    let transport = MemoryTransport::new();
+    transport.route(
+        Method::Get,
+        &format!("{}/zones", API),
+        403,
+        r#"{"success":false,"errors":[{"code":9109,"message":"Invalid access token"}]}"#,
+    );
+    let auth = Auth::ApiToken("token".to_string());
+    let provider = Cloudflare::with_transport(auth, Arc::new(transport));
+
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+    let err = provider
+        .sync(
+            &desired("example.org", "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await
+        .into_result()
+        .unwrap_err();
+    assert!(err.is_auth());
+    assert!(!err.is_retryable());
+}
+
+#[tokio::test]
+async fn test_cf_token_verify() {
+    let cli = init_cli();
+    let token = cli.token_verify().await.unwrap();
+    assert_eq!(token.status, "active");
+}
+
 fn init_cli() -> Cli {
     let token = std::env::var("CF_API_TOKEN").unwrap();
     let auth = Auth::ApiToken(token);
-    Cli::new(auth)
+    Cli::new(auth, Arc::new(ReqwestTransport::new().unwrap()))
 }
 
 fn zone_name() -> (String, String) {
@@ -0,0 +1,531 @@
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ChangeSet;
+use crate::provider::ExistingRecord;
+use crate::provider::ParamList;
+use crate::provider::ParamSpec;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::record::OWNER_MARKER;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::ZoneName;
+use crate::types::parse_duration;
+
+const DEFAULT_RELOAD_TIMEOUT: Duration = Duration::from_secs(30);
+// First line of a file the provider creates
+const HEADER: &str = "# Written by dns-syncer, address lines of synced names are overwritten";
+
+// Address lines of a dnsmasq configuration file, like a file of
+// `/etc/dnsmasq.d`. The file belongs to dns-syncer: every address line of it
+// is taken as owned, other lines are kept as they are. dnsmasq reads it at
+// start only, the `reload` command is run after every change of the file.
+pub struct Dnsmasq {
+    path: PathBuf,
+    // Command and its arguments, none to leave the reload to someone else
+    reload: Vec<String>,
+    reload_timeout: Duration,
+    // Zones are synced concurrently but the file is read, changed and
+    // written by one of them at a time
+    lock: Mutex<()>,
+    // A reload failed after the file changed, the next change or sync
+    // retries it even if the file is left as it is
+    reload_pending: AtomicBool,
+}
+
+// dnsmasq providers take no authentication. Params of the provider entry:
+//
+// - `file`, the configuration file written, like
+//   `/etc/dnsmasq.d/dns-syncer.conf`
+// - `reload`, the command run after the file changed, like
+//   `systemctl restart dnsmasq`, split on whitespace and run without a shell
+// - `reload_timeout` of the command (30s by default)
+pub struct DnsmasqFactory;
+
+impl ProviderFactory for DnsmasqFactory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        if !auth.method.is_empty() {
+            return Err(Error::Provider(format!(
+                "{}: unsupported authentication method for dnsmasq provider",
+                auth.method
+            )));
+        }
+        let mut file = None;
+        let mut reload = vec![];
+        let mut reload_timeout = DEFAULT_RELOAD_TIMEOUT;
+        for param in params.iter() {
+            match param.name.as_str() {
+                "file" => file = Some(PathBuf::from(&param.value)),
+                "reload" => reload = param.value.split_whitespace().map(String::from).collect(),
+                "reload_timeout" => reload_timeout = parse_duration(&param.value)?,
+                _ => {
+                    return Err(Error::ParseError(format!(
+                        "{}: unknown param {}",
+                        name, param.name
+                    )));
+                }
+            }
+        }
+        let file =
+            file.ok_or_else(|| Error::ParseError(format!("{}: dnsmasq requires a file", name)))?;
+        Ok(Box::new(
+            Dnsmasq::new(file)
+                .with_reload(reload)
+                .with_reload_timeout(reload_timeout),
+        ))
+    }
+}
+
+impl Dnsmasq {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            reload: vec![],
+            reload_timeout: DEFAULT_RELOAD_TIMEOUT,
+            lock: Mutex::new(()),
+            reload_pending: AtomicBool::new(false),
+        }
+    }
+
+    // Run `command` after every change of the file, nothing when empty
+    pub fn with_reload(mut self, command: Vec<String>) -> Self {
+        self.reload = command;
+        self
+    }
+
+    pub fn with_reload_timeout(mut self, timeout: Duration) -> Self {
+        self.reload_timeout = timeout;
+        self
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "dnsmasq",
+            description: "Address lines of a dnsmasq configuration file",
+            record_types: vec![RecordType::A, RecordType::AAAA],
+            auth_methods: vec![],
+            params: vec![
+                ParamSpec {
+                    name: "file",
+                    description: "Configuration file written, like /etc/dnsmasq.d/dns-syncer.conf",
+                },
+                ParamSpec {
+                    name: "reload",
+                    description: "Command run after the file changed, like systemctl restart dnsmasq",
+                },
+                ParamSpec {
+                    name: "reload_timeout",
+                    description: "Time the reload command may take (30s by default)",
+                },
+            ],
+            operations: vec![
+                "list",
+                "apply",
+                "dry_run",
+                "verify",
+                "list_records",
+                "list_zone_records",
+                "get_record",
+                "create_record",
+                "delete_record",
+                "delete_records",
+                "delete_owned_records",
+            ],
+        }
+    }
+
+    // Remove the address lines of `zone` matching `remove` and return their
+    // records
+    async fn remove(
+        &self,
+        zone: &ZoneName,
+        remove: impl Fn(&ProviderRecord) -> bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let _guard = self.lock.lock().await;
+        let mut conf = ConfFile::read(&self.path)?;
+        let mut removed = vec![];
+        conf.lines.retain(|line| match line.record() {
+            Some(record) if in_zone(&record.name, zone) && remove(&record) => {
+                removed.push(record);
+                false
+            }
+            _ => true,
+        });
+        if !removed.is_empty() {
+            self.save(&conf).await?;
+        }
+        Ok(removed)
+    }
+
+    // Write the file and reload dnsmasq. The file is written next to the
+    // target and renamed over it, so dnsmasq never reads half of it; the
+    // temporary file starts with a dot, which dnsmasq skips in `conf-dir`.
+    async fn save(&self, conf: &ConfFile) -> Result<()> {
+        let file_name = self
+            .path
+            .file_name()
+            .ok_or_else(|| Error::Provider(format!("{} is not a file", self.path.display())))?;
+        let tmp = self
+            .path
+            .with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+        fs::write(&tmp, conf.render())?;
+        fs::rename(&tmp, &self.path)?;
+        self.reload_pending.store(true, Ordering::Relaxed);
+        self.reload().await
+    }
+
+    // Run the reload command if a change of the file is not served yet
+    async fn reload(&self) -> Result<()> {
+        if !self.reload_pending.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let Some((program, args)) = self.reload.split_first() else {
+            self.reload_pending.store(false, Ordering::Relaxed);
+            return Ok(());
+        };
+        let child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                Error::Provider(format!("dnsmasq reload {} failed to start: {}", program, e))
+            })?;
+        let output = tokio::time::timeout(self.reload_timeout, child.wait_with_output())
+            .await
+            .map_err(|_| {
+                Error::Provider(format!(
+                    "dnsmasq reload {} timed out after {}s",
+                    program,
+                    self.reload_timeout.as_secs()
+                ))
+            })??;
+        if !output.status.success() {
+            return Err(Error::Provider(format!(
+                "dnsmasq reload {} failed with {}: {}",
+                program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        log::info!("dnsmasq reloaded by {}", self.reload.join(" "));
+        self.reload_pending.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Provider for Dnsmasq {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        let conf = ConfFile::read(&self.path)?;
+        Ok(conf
+            .lines
+            .iter()
+            .filter_map(Line::record)
+            .filter(|r| in_zone(&r.name, zone))
+            .map(ExistingRecord::from)
+            .collect())
+    }
+
+    // The address lines of every desired name are replaced by the desired
+    // ones in one write of the file. Records dnsmasq cannot serve fail alone,
+    // the lines of their name are left as they are.
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let zone = &changes.zone;
+        let _guard = self.lock.lock().await;
+        if cancel.is_cancelled() {
+            log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+            return Err(Error::Cancelled);
+        }
+
+        let mut errors = vec![];
+        let mut failed: Vec<String> = vec![];
+        let mut desired: Vec<Address> = vec![];
+        for change in changes.changes.iter() {
+            match Address::from_record(&change.after) {
+                Ok(address) if !desired.contains(&address) => desired.push(address),
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!(
+                        zone = zone, record = change.after.name, outcome = "failed";
+                        "change of record {} failed: {}", change.after.name, e
+                    );
+                    failed.push(change.after.name.to_ascii_lowercase());
+                    errors.push(e.for_record(&change.after.name));
+                }
+            }
+        }
+        desired.retain(|a| !failed.contains(&a.name));
+
+        let mut conf = ConfFile::read(&self.path)?;
+        let mut changed = false;
+        conf.lines.retain(|line| match line {
+            Line::Address(live)
+                if desired.iter().any(|a| a.name == live.name) && !desired.contains(live) =>
+            {
+                log::info!(
+                    zone = zone, record = live.name, content = live.ip.to_string(),
+                    outcome = "deleted";
+                    "record {} {} deleted", live.name, live.ip
+                );
+                changed = true;
+                false
+            }
+            _ => true,
+        });
+        for address in desired.into_iter() {
+            if conf.lines.contains(&Line::Address(address.clone())) {
+                log::debug!(
+                    zone = zone, record = address.name, outcome = "unchanged";
+                    "record {} already up to date", address.name
+                );
+                continue;
+            }
+            log::info!(
+                zone = zone, record = address.name, content = address.ip.to_string(),
+                outcome = "created";
+                "record {} set to {}", address.name, address.ip
+            );
+            conf.lines.push(Line::Address(address));
+            changed = true;
+        }
+
+        // Nothing is served until the file is written and reloaded, a failure
+        // of either fails every record
+        match changed {
+            true => self.save(&conf).await?,
+            false => self.reload().await?,
+        }
+        Error::from_errors(errors)
+    }
+
+    // The file can be read and its directory exists, dnsmasq has no
+    // credentials to check
+    async fn verify(&self, _zones: &[ZoneName]) -> Result<()> {
+        ConfFile::read(&self.path)?;
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if !dir.is_dir() {
+            return Err(Error::Provider(format!(
+                "dnsmasq directory {} does not exist",
+                dir.display()
+            )));
+        }
+        Ok(())
+    }
+
+    // Add one address line, keeping every line already there
+    async fn create_record(&self, zone: &ZoneName, record: &ProviderRecord) -> Result<()> {
+        let mut record = record.clone();
+        record.name = record.fqdn(zone);
+        let address = Address::from_record(&record)?;
+        let _guard = self.lock.lock().await;
+        let mut conf = ConfFile::read(&self.path)?;
+        let line = Line::Address(address);
+        if conf.lines.contains(&line) {
+            return Ok(());
+        }
+        conf.lines.push(line);
+        self.save(&conf).await
+    }
+
+    // Every address line of the file is owned, `force` changes nothing
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        _force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.remove(zone, |r| r.name.eq_ignore_ascii_case(name))
+            .await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        _force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.remove(zone, |r| {
+            r.name.eq_ignore_ascii_case(name) && r.content.record_type() == record_type
+        })
+        .await
+    }
+
+    async fn delete_owned_records(
+        &self,
+        zone: &ZoneName,
+        records: &[ProviderRecord],
+    ) -> Result<Vec<ProviderRecord>> {
+        self.remove(zone, |r| {
+            records
+                .iter()
+                .any(|d| d.name.eq_ignore_ascii_case(&r.name) && d.content == r.content)
+        })
+        .await
+    }
+}
+
+// Whether `name` is `zone` or under it
+fn in_zone(name: &str, zone: &str) -> bool {
+    let zone = zone.to_ascii_lowercase();
+    name == zone || name.ends_with(&format!(".{}", zone))
+}
+
+///////////////////////////////////////////////////////////
+// Configuration file
+///////////////////////////////////////////////////////////
+// `address=/name/ip`: dnsmasq answers `ip` for the name and every name under
+// it
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Address {
+    // Lowercase, without the trailing dot
+    pub name: String,
+    pub ip: IpAddr,
+}
+
+impl Address {
+    pub fn from_record(record: &ProviderRecord) -> Result<Self> {
+        let ip = match record.content {
+            RecordContent::A(ip) => IpAddr::V4(ip),
+            RecordContent::AAAA(ip) => IpAddr::V6(ip),
+            _ => {
+                return Err(Error::RecordRejected {
+                    record: record.name.clone(),
+                    reason: "dnsmasq address lines need an address".to_string(),
+                });
+            }
+        };
+        if let Some(parent) = record.name.strip_prefix("*.") {
+            return Err(Error::RecordRejected {
+                record: record.name.clone(),
+                reason: format!(
+                    "dnsmasq answers every name under {} already, drop the wildcard",
+                    parent
+                ),
+            });
+        }
+        Ok(Self {
+            name: record.name.trim_end_matches('.').to_ascii_lowercase(),
+            ip,
+        })
+    }
+
+    // A line of one name and one address. Lines of several names, of the
+    // `#` catch-all or answering NXDOMAIN are not records.
+    fn parse(line: &str) -> Option<Self> {
+        let rest = line.trim().strip_prefix("address=/")?;
+        let (name, ip) = rest.split_once('/')?;
+        if name.is_empty() || name == "#" {
+            return None;
+        }
+        Some(Self {
+            name: name.trim_end_matches('.').to_ascii_lowercase(),
+            ip: ip.parse().ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Line {
+    Address(Address),
+    // A comment or an option other than an address line, written back as it
+    // was read
+    Other(String),
+}
+
+impl Line {
+    fn record(&self) -> Option<ProviderRecord> {
+        let Line::Address(address) = self else {
+            return None;
+        };
+        let content = match address.ip {
+            IpAddr::V4(ip) => RecordContent::A(ip),
+            IpAddr::V6(ip) => RecordContent::AAAA(ip),
+        };
+        Some(ProviderRecord {
+            name: address.name.clone(),
+            content,
+            comment: Some(OWNER_MARKER.to_string()),
+            op: RecordOp::default(),
+            ttl: TTL::Auto,
+            params: vec![],
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct ConfFile {
+    pub lines: Vec<Line>,
+}
+
+impl ConfFile {
+    // A missing file has no line
+    pub fn read(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Self::parse(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(Error::from(e).context(&format!("dnsmasq file {}", path.display()))),
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .map(|line| match Address::parse(line) {
+                Some(address) => Line::Address(address),
+                None => Line::Other(line.to_string()),
+            })
+            .collect();
+        Self { lines }
+    }
+
+    pub fn render(&self) -> String {
+        let mut ret = String::new();
+        if self.lines.is_empty() || matches!(self.lines.first(), Some(Line::Address(_))) {
+            ret.push_str(HEADER);
+            ret.push('\n');
+        }
+        for line in self.lines.iter() {
+            match line {
+                Line::Address(address) => {
+                    ret.push_str(&format!("address=/{}/{}\n", address.name, address.ip))
+                }
+                Line::Other(line) => {
+                    ret.push_str(line);
+                    ret.push('\n');
+                }
+            }
+        }
+        ret
+    }
+}
@@ -0,0 +1,7 @@
+#[allow(clippy::module_inception)]
+mod dnsmasq;
+pub use dnsmasq::Dnsmasq;
+pub use dnsmasq::DnsmasqFactory;
+
+#[cfg(test)]
+mod unit_test;
@@ -0,0 +1,257 @@
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+use tokio_util::sync::CancellationToken;
+
+use super::dnsmasq::*;
+use crate::provider::AuthParams;
+use crate::provider::BackendRecords;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordStatus;
+use crate::provider::ZoneRecords;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::Param;
+use crate::types::PublicIp;
+
+// A configuration file with address lines of example.org and of another
+// domain, next to lines that are not records
+fn conf_file(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "dns-syncer-dnsmasq-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("dns-syncer.conf");
+    fs::write(
+        &path,
+        "# LAN names\n\
+         address=/home.example.org/1.1.1.1\n\
+         address=/home.example.org/2001:db8::1\n\
+         address=/nas.example.org/10.0.0.2\n\
+         address=/example.net/3.3.3.3\n\
+         address=/ads.example.org/tracker.example.org/0.0.0.0\n\
+         address=/blocked.example.org/\n\
+         server=/corp.example.org/10.0.0.53\n",
+    )
+    .unwrap();
+    path
+}
+
+fn desired(zone: &str, name: &str) -> BackendRecords {
+    let mut records = BackendRecords::default();
+    records.zones.insert(
+        zone.to_string(),
+        ZoneRecords {
+            records: vec![ProviderRecord {
+                name: name.to_string(),
+                content: RecordContent::Unassigned(RecordType::A),
+                comment: None,
+                ttl: TTL::Auto,
+                op: RecordOp::Create,
+                params: vec![],
+            }],
+        },
+    );
+    records
+}
+
+#[tokio::test]
+async fn test_dnsmasq_list() {
+    let path = conf_file("list");
+    let provider = Dnsmasq::new(&path);
+
+    let records = provider.list(&"example.org".to_string()).await.unwrap();
+    let records = records
+        .iter()
+        .map(|r| (r.record.name.as_str(), r.record.content.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        vec![
+            ("home.example.org", "1.1.1.1".to_string()),
+            ("home.example.org", "2001:db8::1".to_string()),
+            ("nas.example.org", "10.0.0.2".to_string()),
+        ]
+    );
+
+    // A missing file has no record
+    let provider = Dnsmasq::new(path.with_file_name("missing.conf"));
+    assert!(
+        provider
+            .list(&"example.org".to_string())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(provider.verify(&[]).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_dnsmasq_sync() {
+    let path = conf_file("sync");
+    let reloaded = path.with_file_name("reloaded");
+    let provider =
+        Dnsmasq::new(&path).with_reload(vec!["touch".to_string(), reloaded.display().to_string()]);
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    let outcome = provider
+        .sync(
+            &desired("example.org", "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Updated);
+    assert!(reloaded.exists());
+
+    // Other lines stay where they were
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# LAN names\n\
+         address=/nas.example.org/10.0.0.2\n\
+         address=/example.net/3.3.3.3\n\
+         address=/ads.example.org/tracker.example.org/0.0.0.0\n\
+         address=/blocked.example.org/\n\
+         server=/corp.example.org/10.0.0.53\n\
+         address=/home.example.org/2.2.2.2\n"
+    );
+
+    // A file left as it is is not reloaded
+    fs::remove_file(&reloaded).unwrap();
+    let outcome = provider
+        .sync(
+            &desired("example.org", "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty());
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Unchanged);
+    assert!(!reloaded.exists());
+
+    // Every address line is owned
+    let zone = "example.org".to_string();
+    let deleted = provider
+        .delete_record(&zone, "nas.example.org", RecordType::A, false)
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert!(deleted[0].is_owned());
+    assert!(reloaded.exists());
+
+    // A new file starts with a header
+    let path = path.with_file_name("new.conf");
+    let provider = Dnsmasq::new(&path);
+    let record = ProviderRecord {
+        name: "router".to_string(),
+        content: RecordContent::A(Ipv4Addr::new(10, 0, 0, 1)),
+        comment: None,
+        ttl: TTL::Auto,
+        op: RecordOp::Create,
+        params: vec![],
+    };
+    provider.create_record(&zone, &record).await.unwrap();
+    let text = fs::read_to_string(&path).unwrap();
+    assert!(text.starts_with("# Written by dns-syncer"));
+    assert!(text.ends_with("\naddress=/router.example.org/10.0.0.1\n"));
+}
+
+#[tokio::test]
+async fn test_dnsmasq_errors() {
+    let path = conf_file("errors");
+    let provider = Dnsmasq::new(&path);
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    // dnsmasq answers names under an address line already
+    let mut records = desired("example.org", "*.lan");
+    records
+        .zones
+        .get_mut("example.org")
+        .unwrap()
+        .records
+        .extend(
+            desired("example.org", "home").zones["example.org"]
+                .records
+                .clone(),
+        );
+    let outcome = provider
+        .sync(&records, &public_ip, &CancellationToken::new())
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(err.contains("drop the wildcard"), "{}", err);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Failed);
+    assert_eq!(outcome.zones[0].records[1].status, RecordStatus::Updated);
+
+    // A failed reload is retried by the next sync, even with the file left
+    // as it is
+    let provider = Dnsmasq::new(&path).with_reload(vec!["false".to_string()]);
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(3, 3, 3, 3)), None);
+    for _ in 0..2 {
+        let outcome = provider
+            .sync(
+                &desired("example.org", "home"),
+                &public_ip,
+                &CancellationToken::new(),
+            )
+            .await;
+        let err = outcome.errors[0].to_string();
+        assert!(err.contains("dnsmasq reload false failed"), "{}", err);
+        assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Failed);
+    }
+
+    let provider = Dnsmasq::new(path.with_file_name("missing").join("dns-syncer.conf"));
+    let err = provider.verify(&[]).await.unwrap_err();
+    assert!(err.to_string().contains("does not exist"));
+}
+
+#[test]
+fn test_dnsmasq_factory() {
+    let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+    let factory = DnsmasqFactory;
+    let file = param("file", "/etc/dnsmasq.d/dns-syncer.conf");
+
+    assert!(
+        factory
+            .create(
+                "dnsmasq-1",
+                &AuthParams::default(),
+                &vec![
+                    file.clone(),
+                    param("reload", "systemctl restart dnsmasq"),
+                    param("reload_timeout", "10s"),
+                ],
+            )
+            .is_ok()
+    );
+    let err = factory
+        .create("dnsmasq-1", &AuthParams::default(), &vec![])
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("requires a file"));
+    let err = factory
+        .create(
+            "dnsmasq-1",
+            &AuthParams::default(),
+            &vec![file.clone(), param("ttl", "60")],
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("unknown param ttl"));
+    let err = factory
+        .create("dnsmasq-1", &AuthParams::new("basic", vec![]), &vec![file])
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string()
+            .contains("unsupported authentication method")
+    );
+}
@@ -0,0 +1,357 @@
+use std::process::Stdio;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ChangeSet;
+use crate::provider::ExistingRecord;
+use crate::provider::PLUGIN_ZONE_NOT_FOUND;
+use crate::provider::ParamList;
+use crate::provider::ParamSpec;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::webhook::Changes;
+use crate::provider::webhook::WebhookDiff;
+use crate::provider::webhook::rejected_error;
+use crate::provider::webhook::rejections;
+use crate::record::ProviderRecord;
+use crate::record::RecordType;
+use crate::types::ZoneName;
+use crate::types::parse_duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// A command standing for a DNS backend, for backends and workflows no other
+// provider covers. It runs once per call with `{"action": ..., ...}` as JSON
+// on stdin, the bodies of the webhook provider:
+//
+// - `{"action": "list", "zone": ...}` prints the records the zone serves as a
+//   JSON array
+// - `{"action": "apply", "zone": ..., "records": [...], "deletes": [...]}`
+//   makes the changes, printing nothing or `{"rejected": [...]}` for the
+//   records it refused while making the others
+//
+// A failing exit fails the call with what the command wrote on stderr, which
+// is `zone-not-found` for a zone the backend does not have.
+pub struct Exec {
+    command: Vec<String>,
+    timeout: Duration,
+    // Runs so far, whatever came of them
+    runs: AtomicU64,
+}
+
+// Exec providers take no authentication, the command finds its own
+// credentials. Params of the provider entry:
+//
+// - `command` run, like `/usr/local/bin/dns-backend --verbose`, split on
+//   whitespace and run without a shell
+// - `timeout` of every run (30s by default)
+pub struct ExecFactory;
+
+impl ProviderFactory for ExecFactory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        if !auth.method.is_empty() {
+            return Err(Error::Provider(format!(
+                "{}: unsupported authentication method for exec provider",
+                auth.method
+            )));
+        }
+        let mut command = vec![];
+        let mut timeout = DEFAULT_TIMEOUT;
+        for param in params.iter() {
+            match param.name.as_str() {
+                "command" => command = param.value.split_whitespace().map(String::from).collect(),
+                "timeout" => timeout = parse_duration(&param.value)?,
+                _ => {
+                    return Err(Error::ParseError(format!(
+                        "{}: unknown param {}",
+                        name, param.name
+                    )));
+                }
+            }
+        }
+        if command.is_empty() {
+            return Err(Error::ParseError(format!(
+                "{}: exec requires a command",
+                name
+            )));
+        }
+        Ok(Box::new(Exec::new(command).with_timeout(timeout)))
+    }
+}
+
+// Input of a run, the action next to the fields of its body
+#[derive(Serialize)]
+struct Input<'a, T: Serialize> {
+    action: &'a str,
+    #[serde(flatten)]
+    body: T,
+}
+
+#[derive(Serialize)]
+struct ListInput<'a> {
+    zone: &'a str,
+}
+
+impl Exec {
+    pub fn new(command: Vec<String>) -> Self {
+        Self {
+            command,
+            timeout: DEFAULT_TIMEOUT,
+            runs: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "exec",
+            description: "Records handed as JSON to a command",
+            record_types: vec![RecordType::A, RecordType::AAAA, RecordType::CNAME],
+            auth_methods: vec![],
+            params: vec![
+                ParamSpec {
+                    name: "command",
+                    description: "Command run for every call, split on whitespace",
+                },
+                ParamSpec {
+                    name: "timeout",
+                    description: "Time a run may take (30s by default)",
+                },
+            ],
+            operations: vec![
+                "list",
+                "apply",
+                "dry_run",
+                "verify",
+                "list_records",
+                "list_zone_records",
+                "get_record",
+                "create_record",
+                "delete_record",
+                "delete_records",
+                "delete_owned_records",
+            ],
+        }
+    }
+
+    // Run the command with `action` and `body` on stdin, and return what it
+    // printed
+    async fn run(&self, action: &str, zone: &str, body: impl Serialize) -> Result<String> {
+        let Some((program, args)) = self.command.split_first() else {
+            return Err(Error::Provider("exec has no command".to_string()));
+        };
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Error::Provider(format!("exec {} failed to start: {}", program, e)))?;
+
+        let input = serde_json::to_string(&Input { action, body })?;
+        let stdin = child.stdin.take();
+        // The input is written while the command runs, so a command not
+        // reading it is still bound by the timeout. A command exiting without
+        // reading its input is judged by its exit status, not by the broken
+        // pipe.
+        let write = async move {
+            if let Some(mut stdin) = stdin
+                && let Err(e) = stdin.write_all(input.as_bytes()).await
+                && e.kind() != std::io::ErrorKind::BrokenPipe
+            {
+                return Err(e);
+            }
+            Ok(())
+        };
+        let (written, output) = tokio::time::timeout(
+            self.timeout,
+            futures_util::future::join(write, child.wait_with_output()),
+        )
+        .await
+        .map_err(|_| {
+            Error::Provider(format!(
+                "exec {} {} timed out after {}s",
+                program,
+                action,
+                self.timeout.as_secs()
+            ))
+        })?;
+        let output = output?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stderr = stderr.trim();
+            if stderr == PLUGIN_ZONE_NOT_FOUND {
+                return Err(Error::ZoneNotFound {
+                    zone: zone.to_string(),
+                });
+            }
+            return Err(Error::Provider(format!(
+                "exec {} {} failed with {}: {}",
+                program, action, output.status, stderr
+            )));
+        }
+        written?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    async fn records(&self, zone: &ZoneName) -> Result<Vec<ProviderRecord>> {
+        let stdout = self.run("list", zone, ListInput { zone }).await?;
+        Ok(serde_json::from_str(&stdout)?)
+    }
+
+    // Delete the records of a zone matching `delete` and return them
+    async fn delete_matching(
+        &self,
+        zone: &ZoneName,
+        delete: impl Fn(&ProviderRecord) -> bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let deletes = self
+            .records(zone)
+            .await?
+            .into_iter()
+            .filter(|r| delete(r))
+            .collect::<Vec<_>>();
+        if deletes.is_empty() {
+            return Ok(vec![]);
+        }
+        let changes = Changes {
+            zone,
+            records: vec![],
+            deletes: deletes.iter().collect(),
+        };
+        let stdout = self.run("apply", zone, changes).await?;
+        rejected_error(rejections(&stdout)?)?;
+        Ok(deletes)
+    }
+}
+
+#[async_trait]
+impl Provider for Exec {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        let records = self.records(zone).await?;
+        Ok(records.into_iter().map(ExistingRecord::from).collect())
+    }
+
+    // All changes of a zone go in one run, records the command rejects fail
+    // alone
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let zone = &changes.zone;
+        if cancel.is_cancelled() {
+            log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+            return Err(Error::Cancelled);
+        }
+        let diff = WebhookDiff::new(&changes.changes);
+        for record in diff.unchanged.iter() {
+            log::debug!(
+                zone = zone, record = record.name, outcome = "unchanged";
+                "record {} already up to date", record.name
+            );
+        }
+        if diff.is_empty() {
+            return Ok(());
+        }
+
+        let body = Changes {
+            zone,
+            records: diff.records.clone(),
+            deletes: diff.deletes.clone(),
+        };
+        let rejected = rejections(&self.run("apply", zone, body).await?)?;
+        for record in diff.records.iter() {
+            if rejected
+                .iter()
+                .any(|r| r.name.eq_ignore_ascii_case(&record.name))
+            {
+                continue;
+            }
+            log::info!(
+                zone = zone, record = record.name, content = record.content.to_string(),
+                outcome = "created";
+                "record {} set to {}", record.name, record.content
+            );
+        }
+        for rejection in rejected.iter() {
+            log::error!(
+                zone = zone, record = rejection.name, outcome = "failed";
+                "change of record {} failed: {}", rejection.name, rejection.reason
+            );
+        }
+        rejected_error(rejected)
+    }
+
+    // Every zone is listed, which tells the command works and the backend has
+    // the zone
+    async fn verify(&self, zones: &[ZoneName]) -> Result<()> {
+        for zone in zones.iter() {
+            self.records(zone).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, |r| {
+            r.name.eq_ignore_ascii_case(name) && (force || r.is_owned())
+        })
+        .await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, |r| {
+            r.name.eq_ignore_ascii_case(name)
+                && r.content.record_type() == record_type
+                && (force || r.is_owned())
+        })
+        .await
+    }
+
+    async fn delete_owned_records(
+        &self,
+        zone: &ZoneName,
+        records: &[ProviderRecord],
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, |r| {
+            r.is_owned()
+                && records
+                    .iter()
+                    .any(|d| d.name.eq_ignore_ascii_case(&r.name) && d.content == r.content)
+        })
+        .await
+    }
+
+    fn request_count(&self) -> u64 {
+        self.runs.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,7 @@
+#[allow(clippy::module_inception)]
+mod exec;
+pub use exec::Exec;
+pub use exec::ExecFactory;
+
+#[cfg(test)]
+mod unit_test;
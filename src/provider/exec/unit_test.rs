@@ -0,0 +1,269 @@
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+
+use tokio_util::sync::CancellationToken;
+
+use super::exec::*;
+use crate::error::Error;
+use crate::provider::AuthParams;
+use crate::provider::BackendRecords;
+use crate::provider::ChangeSet;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordStatus;
+use crate::provider::ZoneRecords;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::Param;
+use crate::types::PublicIp;
+
+// A backend serving example.org with an owned address and a record made by
+// hand. It keeps its inputs in `inputs` next to it, rejects changes of
+// bad.example.org and fails changes of broken.example.org.
+fn backend(name: &str) -> (Exec, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("dns-syncer-exec-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let inputs = dir.join("inputs");
+    let script = dir.join("backend.sh");
+    fs::write(
+        &script,
+        format!(
+            r#"input=$(cat)
+echo "$input" >> {}
+case "$input" in
+*'"action":"list","zone":"example.org"'*)
+  echo '[{{"name":"home.example.org","type":"A","content":"1.1.1.1","comment":"[dns-syncer]","ttl":300}},
+         {{"name":"www.example.org","type":"CNAME","content":"home.example.org","ttl":"auto"}}]' ;;
+*'"action":"list"'*)
+  echo zone-not-found >&2; exit 1 ;;
+*broken.example.org*)
+  echo 'backend is read only' >&2; exit 2 ;;
+*bad.example.org*)
+  echo '{{"rejected":[{{"name":"bad.example.org","reason":"name not allowed"}}]}}' ;;
+esac
+"#,
+            inputs.display()
+        ),
+    )
+    .unwrap();
+    let exec = Exec::new(vec!["sh".to_string(), script.display().to_string()]);
+    (exec, inputs)
+}
+
+fn desired(zone: &str, names: &[&str]) -> BackendRecords {
+    let mut records = BackendRecords::default();
+    records.zones.insert(
+        zone.to_string(),
+        ZoneRecords {
+            records: names
+                .iter()
+                .map(|name| ProviderRecord {
+                    name: name.to_string(),
+                    content: RecordContent::Unassigned(RecordType::A),
+                    comment: None,
+                    ttl: TTL::Value(300),
+                    op: RecordOp::Create,
+                    params: vec![],
+                })
+                .collect(),
+        },
+    );
+    records
+}
+
+#[tokio::test]
+async fn test_exec_list() {
+    let (provider, inputs) = backend("list");
+
+    let records = provider.list(&"example.org".to_string()).await.unwrap();
+    let records = records
+        .iter()
+        .map(|r| (r.record.name.as_str(), r.record.content.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        vec![
+            ("home.example.org", "1.1.1.1".to_string()),
+            ("www.example.org", "home.example.org".to_string()),
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(&inputs).unwrap(),
+        "{\"action\":\"list\",\"zone\":\"example.org\"}\n"
+    );
+
+    let err = provider.list(&"example.net".to_string()).await.unwrap_err();
+    assert!(matches!(err, Error::ZoneNotFound { .. }));
+    assert_eq!(provider.request_count(), 2);
+}
+
+#[tokio::test]
+async fn test_exec_sync() {
+    let (provider, inputs) = backend("sync");
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    let outcome = provider
+        .sync(
+            &desired("example.org", &["home"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Updated);
+
+    // The changes of the zone go in one run
+    let input = fs::read_to_string(&inputs).unwrap();
+    let apply: serde_json::Value = serde_json::from_str(input.lines().last().unwrap()).unwrap();
+    assert_eq!(
+        apply,
+        serde_json::json!({
+            "action": "apply",
+            "zone": "example.org",
+            "records": [
+                {"name": "home.example.org", "type": "A", "content": "2.2.2.2",
+                 "comment": "[dns-syncer]", "op": "purge", "ttl": 300},
+            ],
+            "deletes": [
+                {"name": "home.example.org", "type": "A", "content": "1.1.1.1",
+                 "comment": "[dns-syncer]", "op": "create", "ttl": 300},
+            ],
+        })
+    );
+
+    // Records already as desired run nothing but the list
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(1, 1, 1, 1)), None);
+    let outcome = provider
+        .sync(
+            &desired("example.org", &["home"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty());
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Unchanged);
+    assert_eq!(provider.request_count(), 3);
+}
+
+#[tokio::test]
+async fn test_exec_errors() {
+    let (provider, _) = backend("errors");
+    let zone = "example.org".to_string();
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    // Records made by hand are deleted with `force` only
+    let deleted = provider
+        .delete_records(&zone, "www.example.org", false)
+        .await
+        .unwrap();
+    assert!(deleted.is_empty());
+    let deleted = provider
+        .delete_records(&zone, "www.example.org", true)
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+
+    // A rejected record fails alone
+    let outcome = provider
+        .sync(
+            &desired(&zone, &["bad", "nas"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(err.contains("name not allowed"), "{}", err);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Failed);
+    assert_eq!(outcome.zones[0].records[1].status, RecordStatus::Created);
+
+    // A failing run fails the zone with its stderr
+    let outcome = provider
+        .sync(
+            &desired(&zone, &["broken"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(err.contains("apply failed with exit status: 2"), "{}", err);
+    assert!(err.contains("backend is read only"), "{}", err);
+
+    let provider = Exec::new(vec!["sleep".to_string(), "5".to_string()])
+        .with_timeout(Duration::from_millis(100));
+    let err = provider.verify(&[zone]).await.unwrap_err();
+    assert!(
+        err.to_string().contains("exec sleep list timed out"),
+        "{}",
+        err
+    );
+
+    // A command not reading an input larger than the pipe buffer is still
+    // stopped by the timeout
+    let records = (0..3000)
+        .map(|i| {
+            ProviderRecord::builder()
+                .name(&format!("host-{}.example.org", i))
+                .value(RecordContent::A(Ipv4Addr::new(192, 0, 2, 1)))
+                .build()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let changes = ChangeSet::replace_by_name("example.org", &records, &[]);
+    let started = Instant::now();
+    let err = provider
+        .apply(&changes, &CancellationToken::new())
+        .await
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("exec sleep apply timed out"),
+        "{}",
+        err
+    );
+    assert!(started.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn test_exec_factory() {
+    let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+    let factory = ExecFactory;
+    let command = param("command", "/usr/local/bin/dns-backend --verbose");
+
+    assert!(
+        factory
+            .create(
+                "exec-1",
+                &AuthParams::default(),
+                &vec![command.clone(), param("timeout", "10s")],
+            )
+            .is_ok()
+    );
+    let err = factory
+        .create("exec-1", &AuthParams::default(), &vec![])
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("requires a command"));
+    let err = factory
+        .create(
+            "exec-1",
+            &AuthParams::default(),
+            &vec![command.clone(), param("ttl", "60")],
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("unknown param ttl"));
+    let err = factory
+        .create("exec-1", &AuthParams::new("bearer", vec![]), &vec![command])
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string()
+            .contains("unsupported authentication method")
+    );
+}
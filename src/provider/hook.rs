@@ -0,0 +1,480 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::BackendRecords;
+use crate::provider::ChangeSet;
+use crate::provider::CredentialCheck;
+use crate::provider::ExistingRecord;
+use crate::provider::IdCache;
+use crate::provider::PlannedChange;
+use crate::provider::Provider;
+use crate::provider::ZoneInfo;
+use crate::provider::desired_names;
+use crate::record::ProviderRecord;
+use crate::record::RecordType;
+use crate::types::PublicIp;
+use crate::types::ZoneName;
+
+// Program transforming the changes of a zone just before they are applied:
+// rewriting comments, adding records, or leaving changes out to veto them.
+// A program failing fails the zone, and one running longer than `timeout` is
+// stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordHook {
+    pub program: HookProgram,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookProgram {
+    // Command reading `{"provider": ..., "changes": <change set>}` as JSON on
+    // stdin and printing the change set to apply. Printing nothing keeps the
+    // changes as they are, exiting with a failure fails the zone with what it
+    // wrote on stderr.
+    Command(Vec<String>),
+    // Rhai script run with `provider` and `changes`, the change set as a map,
+    // in scope. It evaluates to the change set to apply, or to nothing to
+    // keep the changes as they are; `throw` fails the zone.
+    Rhai(PathBuf),
+}
+
+impl RecordHook {
+    pub async fn run(&self, provider: &str, changes: &ChangeSet) -> Result<ChangeSet> {
+        let ret = match &self.program {
+            HookProgram::Command(command) => self.run_command(command, provider, changes).await?,
+            HookProgram::Rhai(script) => self.run_rhai(script, provider, changes).await?,
+        };
+        let Some(ret) = ret else {
+            return Ok(changes.clone());
+        };
+        if ret.zone != changes.zone {
+            return Err(Error::Provider(format!(
+                "hook {} moved the changes of zone {} to {}",
+                self.describe(),
+                changes.zone,
+                ret.zone
+            )));
+        }
+        Ok(ret)
+    }
+
+    // The program, for errors
+    fn describe(&self) -> String {
+        match &self.program {
+            HookProgram::Command(command) => command.first().cloned().unwrap_or_default(),
+            HookProgram::Rhai(script) => script.display().to_string(),
+        }
+    }
+
+    async fn run_command(
+        &self,
+        command: &[String],
+        provider: &str,
+        changes: &ChangeSet,
+    ) -> Result<Option<ChangeSet>> {
+        let Some((program, args)) = command.split_first() else {
+            return Err(Error::Provider("hook has no command".to_string()));
+        };
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Error::Provider(format!("hook {} failed to start: {}", program, e)))?;
+
+        let input = json!({ "provider": provider, "changes": changes }).to_string();
+        let stdin = child.stdin.take();
+        // The input is written while the hook runs, so a hook not reading it
+        // is still bound by the timeout. A hook exiting without reading its
+        // input is judged by its exit status, not by the broken pipe.
+        let write = async move {
+            if let Some(mut stdin) = stdin
+                && let Err(e) = stdin.write_all(input.as_bytes()).await
+                && e.kind() != std::io::ErrorKind::BrokenPipe
+            {
+                return Err(e);
+            }
+            Ok(())
+        };
+        let (written, output) = tokio::time::timeout(
+            self.timeout,
+            futures_util::future::join(write, child.wait_with_output()),
+        )
+        .await
+        .map_err(|_| {
+            Error::Provider(format!(
+                "hook {} timed out after {}s",
+                program,
+                self.timeout.as_secs()
+            ))
+        })?;
+        let output = output?;
+        if !output.status.success() {
+            return Err(Error::Provider(format!(
+                "hook {} failed with {}: {}",
+                program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        written?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if stdout.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&stdout)?))
+    }
+
+    #[cfg(feature = "rhai")]
+    async fn run_rhai(
+        &self,
+        script: &Path,
+        provider: &str,
+        changes: &ChangeSet,
+    ) -> Result<Option<ChangeSet>> {
+        use std::time::Instant;
+
+        let hook_error = |e: &dyn std::fmt::Display| {
+            Error::Provider(format!("hook {} failed: {}", script.display(), e))
+        };
+        let source = std::fs::read_to_string(script).map_err(|e| hook_error(&e))?;
+        let changes = rhai::serde::to_dynamic(changes).map_err(|e| hook_error(&e))?;
+        let provider = provider.to_string();
+        let timeout = self.timeout;
+
+        // Scripts run on a blocking thread, stopped between two operations
+        // once the timeout passed
+        let ret = tokio::task::spawn_blocking(move || {
+            let mut engine = rhai::Engine::new();
+            let deadline = Instant::now() + timeout;
+            engine.on_progress(move |_| (Instant::now() > deadline).then_some(().into()));
+            let mut scope = rhai::Scope::new();
+            scope.push("provider", provider);
+            scope.push("changes", changes);
+            engine.eval_with_scope::<rhai::Dynamic>(&mut scope, &source)
+        })
+        .await
+        .map_err(|e| hook_error(&e))?;
+
+        let ret = match ret {
+            Ok(ret) => ret,
+            Err(e) if matches!(*e, rhai::EvalAltResult::ErrorTerminated(..)) => {
+                return Err(Error::Provider(format!(
+                    "hook {} timed out after {}s",
+                    script.display(),
+                    timeout.as_secs()
+                )));
+            }
+            Err(e) => return Err(hook_error(&e)),
+        };
+        if ret.is_unit() {
+            return Ok(None);
+        }
+        Ok(Some(
+            rhai::serde::from_dynamic(&ret).map_err(|e| hook_error(&e))?,
+        ))
+    }
+
+    #[cfg(not(feature = "rhai"))]
+    async fn run_rhai(
+        &self,
+        script: &Path,
+        _provider: &str,
+        _changes: &ChangeSet,
+    ) -> Result<Option<ChangeSet>> {
+        Err(Error::Provider(format!(
+            "hook {}: built without the rhai feature",
+            script.display()
+        )))
+    }
+}
+
+// Provider running its changes through a hook before applying them, the rest
+// is left to the wrapped provider
+pub struct HookedProvider {
+    name: String,
+    inner: Arc<dyn Provider>,
+    hook: RecordHook,
+}
+
+impl HookedProvider {
+    pub fn new(name: &str, inner: Arc<dyn Provider>, hook: RecordHook) -> Self {
+        Self {
+            name: name.to_string(),
+            inner,
+            hook,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for HookedProvider {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        self.inner.list(zone).await
+    }
+
+    async fn list_named(&self, zone: &ZoneName, names: &[String]) -> Result<Vec<ExistingRecord>> {
+        self.inner.list_named(zone, names).await
+    }
+
+    fn plan(
+        &self,
+        zone: &ZoneName,
+        desired: &[ProviderRecord],
+        existing: &[ExistingRecord],
+    ) -> ChangeSet {
+        self.inner.plan(zone, desired, existing)
+    }
+
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let changes = self.hook.run(&self.name, changes).await?;
+        self.inner.apply(&changes, cancel).await
+    }
+
+    // The plan shows what the hook makes of the changes
+    async fn dry_run(
+        &self,
+        records: &BackendRecords,
+        public_ip: &PublicIp,
+    ) -> Result<Vec<PlannedChange>> {
+        let mut ret = vec![];
+        for (zone, zone_records) in records.zones.iter() {
+            let desired = zone_records.desired(zone, public_ip);
+            let existing = match self.list_named(zone, &desired_names(&desired)).await {
+                Ok(existing) => existing,
+                Err(Error::ZoneNotFound { .. }) => continue,
+                Err(e) => return Err(e.context(&format!("zone {}", zone))),
+            };
+            let changes = self.plan(zone, &desired, &existing);
+            ret.extend(self.hook.run(&self.name, &changes).await?.planned());
+        }
+        Ok(ret)
+    }
+
+    async fn verify(&self, zones: &[ZoneName]) -> Result<()> {
+        self.inner.verify(zones).await
+    }
+
+    async fn list_zones(&self) -> Result<Vec<ZoneName>> {
+        self.inner.list_zones().await
+    }
+
+    async fn list_zone_info(&self) -> Result<Vec<ZoneInfo>> {
+        self.inner.list_zone_info().await
+    }
+
+    async fn zone_info(&self, zone: &ZoneName) -> Result<Option<ZoneInfo>> {
+        self.inner.zone_info(zone).await
+    }
+
+    async fn list_records(&self, zone: &ZoneName, name: &str) -> Result<Vec<ProviderRecord>> {
+        self.inner.list_records(zone, name).await
+    }
+
+    async fn list_zone_records(&self, zone: &ZoneName) -> Result<Vec<ProviderRecord>> {
+        self.inner.list_zone_records(zone).await
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.inner.delete_records(zone, name, force).await
+    }
+
+    async fn get_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+    ) -> Result<Option<ProviderRecord>> {
+        self.inner.get_record(zone, name, record_type).await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.inner
+            .delete_record(zone, name, record_type, force)
+            .await
+    }
+
+    async fn check_credentials(&self, zones: &[ZoneName]) -> Result<CredentialCheck> {
+        self.inner.check_credentials(zones).await
+    }
+
+    async fn delete_owned_records(
+        &self,
+        zone: &ZoneName,
+        records: &[ProviderRecord],
+    ) -> Result<Vec<ProviderRecord>> {
+        self.inner.delete_owned_records(zone, records).await
+    }
+
+    fn request_count(&self) -> u64 {
+        self.inner.request_count()
+    }
+
+    fn zone_concurrency(&self) -> usize {
+        self.inner.zone_concurrency()
+    }
+
+    fn id_cache(&self) -> IdCache {
+        self.inner.id_cache()
+    }
+
+    fn load_id_cache(&self, cache: IdCache) {
+        self.inner.load_id_cache(cache)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::provider::MockProvider;
+    use crate::record::RecordContent;
+
+    fn hook(script: &str) -> RecordHook {
+        RecordHook {
+            program: HookProgram::Command(vec![
+                "sh".to_string(),
+                "-c".to_string(),
+                script.to_string(),
+            ]),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    fn changes() -> ChangeSet {
+        let record = ProviderRecord::builder()
+            .name("home.example.org")
+            .value(RecordContent::A("192.0.2.1".parse().unwrap()))
+            .build()
+            .unwrap();
+        ChangeSet::replace_by_name("example.org", &[record], &[])
+    }
+
+    #[tokio::test]
+    async fn test_record_hook() {
+        // Printing nothing keeps the changes
+        let changes = changes();
+        assert_eq!(
+            hook("cat > /dev/null")
+                .run("mock-1", &changes)
+                .await
+                .unwrap(),
+            changes
+        );
+
+        // Vetoing every change
+        let vetoed = hook(r#"cat > /dev/null; echo '{"zone": "example.org", "changes": []}'"#)
+            .run("mock-1", &changes)
+            .await
+            .unwrap();
+        assert!(vetoed.is_empty());
+
+        let err = hook("echo refused >&2; exit 3")
+            .run("mock-1", &changes)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("refused"));
+
+        let moved = hook(r#"echo '{"zone": "example.net", "changes": []}'"#);
+        assert!(moved.run("mock-1", &changes).await.is_err());
+
+        // A hook not reading a change set larger than the pipe buffer is
+        // still stopped by the timeout
+        let large = ChangeSet {
+            changes: vec![changes.changes[0].clone(); 2000],
+            ..changes.clone()
+        };
+        let mut stuck = hook("sleep 5");
+        stuck.timeout = Duration::from_millis(200);
+        let started = std::time::Instant::now();
+        let err = stuck.run("mock-1", &large).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[cfg(feature = "rhai")]
+    #[tokio::test]
+    async fn test_rhai_hook() {
+        let dir = std::env::temp_dir().join(format!("dns-syncer-hook-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = |name: &str, source: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, source).unwrap();
+            RecordHook {
+                program: HookProgram::Rhai(path),
+                timeout: Duration::from_millis(500),
+            }
+        };
+        let changes = changes();
+
+        // Evaluating to nothing keeps the changes
+        let keep = script("keep.rhai", "let n = changes.changes.len();");
+        assert_eq!(keep.run("mock-1", &changes).await.unwrap(), changes);
+
+        let veto = script(
+            "veto.rhai",
+            r#"if provider == "mock-1" { changes.changes = []; } changes"#,
+        );
+        assert!(veto.run("mock-1", &changes).await.unwrap().is_empty());
+        assert_eq!(veto.run("mock-2", &changes).await.unwrap(), changes);
+
+        let err = script("throw.rhai", r#"throw "refused""#)
+            .run("mock-1", &changes)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("refused"), "{}", err);
+
+        let err = script("loop.rhai", "loop {}")
+            .run("mock-1", &changes)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+
+        let moved = script("moved.rhai", r#"changes.zone = "example.net"; changes"#);
+        assert!(moved.run("mock-1", &changes).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hooked_provider() {
+        let mock = MockProvider::new();
+        let veto = hook(r#"cat > /dev/null; echo '{"zone": "example.org", "changes": []}'"#);
+        let provider = HookedProvider::new("mock-1", Arc::new(mock.clone()), veto);
+        provider
+            .apply(&changes(), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert!(mock.records().values().all(|r| r.is_empty()));
+
+        let provider =
+            HookedProvider::new("mock-1", Arc::new(mock.clone()), hook("cat > /dev/null"));
+        provider
+            .apply(&changes(), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(mock.records()["example.org"].len(), 1);
+    }
+}
@@ -0,0 +1,400 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ChangeSet;
+use crate::provider::ExistingRecord;
+use crate::provider::ParamList;
+use crate::provider::ParamSpec;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::ZoneInfo;
+use crate::provider::webhook::WebhookDiff;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordError;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::ZoneName;
+use crate::types::parse_duration;
+use crate::wrapper::knot::Knotc;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Zones of a Knot DNS server, changed in zone transactions of its control
+// interface: the changes of a zone are set in a transaction and committed
+// together, or aborted together when one of them fails. Knot keeps no
+// comments, so records carry no owner marker.
+pub struct Knot {
+    knotc: Knotc,
+    timeout: Duration,
+    // knotc runs so far, whatever came of them
+    runs: AtomicU64,
+}
+
+// Knot providers take no authentication, access to the control socket is
+// the one of its file. Params of the provider entry:
+//
+// - `control`, the knotc command, like `knotc -s /run/knot/knot.sock`, split
+//   on whitespace (knotc by default)
+// - `timeout` of every knotc run (10s by default)
+pub struct KnotFactory;
+
+impl ProviderFactory for KnotFactory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        if !auth.method.is_empty() {
+            return Err(Error::Provider(format!(
+                "{}: unsupported authentication method for knot provider",
+                auth.method
+            )));
+        }
+        let mut control = vec!["knotc".to_string()];
+        let mut timeout = DEFAULT_TIMEOUT;
+        for param in params.iter() {
+            match param.name.as_str() {
+                "control" => control = param.value.split_whitespace().map(String::from).collect(),
+                "timeout" => timeout = parse_duration(&param.value)?,
+                _ => {
+                    return Err(Error::ParseError(format!(
+                        "{}: unknown param {}",
+                        name, param.name
+                    )));
+                }
+            }
+        }
+        if control.is_empty() {
+            return Err(Error::ParseError(format!(
+                "{}: knot requires a control command",
+                name
+            )));
+        }
+        Ok(Box::new(
+            Knot::new(Knotc::new(control)).with_timeout(timeout),
+        ))
+    }
+}
+
+impl Knot {
+    pub fn new(knotc: Knotc) -> Self {
+        Self {
+            knotc,
+            timeout: DEFAULT_TIMEOUT,
+            runs: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "knot",
+            description: "Zones of a Knot DNS server, changed in zone transactions",
+            record_types: vec![RecordType::A, RecordType::AAAA, RecordType::CNAME],
+            auth_methods: vec![],
+            params: vec![
+                ParamSpec {
+                    name: "control",
+                    description: "knotc command, split on whitespace (knotc by default)",
+                },
+                ParamSpec {
+                    name: "timeout",
+                    description: "Time a knotc run may take (10s by default)",
+                },
+            ],
+            operations: vec![
+                "list",
+                "apply",
+                "dry_run",
+                "verify",
+                "list_zones",
+                "zone_info",
+                "list_records",
+                "list_zone_records",
+                "get_record",
+                "create_record",
+                "delete_record",
+                "delete_records",
+            ],
+        }
+    }
+
+    async fn call(&self, command: &str, args: &[&str]) -> Result<String> {
+        self.runs.fetch_add(1, Ordering::Relaxed);
+        self.knotc.call(command, args, self.timeout).await
+    }
+
+    async fn records(&self, zone: &ZoneName) -> Result<Vec<ProviderRecord>> {
+        let stdout = self.call("zone-read", &[zone]).await?;
+        Ok(stdout.lines().filter_map(parse_record).collect())
+    }
+
+    // Run `commands` of `zone` in one transaction, committed when all of them
+    // succeed and aborted otherwise
+    async fn transaction(&self, zone: &ZoneName, commands: &[(&str, Vec<String>)]) -> Result<()> {
+        self.call("zone-begin", &[zone]).await?;
+        for (command, args) in commands.iter() {
+            let mut call_args = vec![zone.as_str()];
+            call_args.extend(args.iter().map(String::as_str));
+            if let Err(e) = self.call(command, &call_args).await {
+                self.abort(zone).await;
+                return Err(e);
+            }
+        }
+        if let Err(e) = self.call("zone-commit", &[zone]).await {
+            self.abort(zone).await;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // A transaction left open blocks the next ones, its failure is only logged
+    async fn abort(&self, zone: &ZoneName) {
+        if let Err(e) = self.call("zone-abort", &[zone]).await {
+            log::warn!(
+                zone = zone;
+                "abort of the transaction of zone {} failed: {}", zone, e
+            );
+        }
+    }
+
+    // Delete the records of `name`, of `record_type` when given. Records of a
+    // zone carry no owner marker, so it takes `force`.
+    async fn delete_matching(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: Option<RecordType>,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let records = self
+            .records(zone)
+            .await?
+            .into_iter()
+            .filter(|r| r.name.eq_ignore_ascii_case(name.trim_end_matches('.')))
+            .filter(|r| {
+                record_type
+                    .as_ref()
+                    .is_none_or(|t| r.content.record_type() == *t)
+            })
+            .collect::<Vec<_>>();
+        if records.is_empty() {
+            return Ok(vec![]);
+        }
+        if !force {
+            return Err(Error::Provider(format!(
+                "{} may not be managed by dns-syncer, knot records carry no owner marker, \
+                 force the deletion to remove it anyway",
+                name
+            )));
+        }
+        let commands = records
+            .iter()
+            .map(|r| ("zone-unset", unset_args(r)))
+            .collect::<Vec<_>>();
+        self.transaction(zone, &commands).await?;
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl Provider for Knot {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        let records = self.records(zone).await?;
+        Ok(records.into_iter().map(ExistingRecord::from).collect())
+    }
+
+    // The zones the server serves, as `zone-status` lists them
+    async fn list_zones(&self) -> Result<Vec<ZoneName>> {
+        let zones = self.list_zone_info().await?;
+        Ok(zones.into_iter().map(|z| z.name).collect())
+    }
+
+    async fn list_zone_info(&self) -> Result<Vec<ZoneInfo>> {
+        let stdout = self.call("zone-status", &[]).await?;
+        Ok(stdout.lines().filter_map(parse_status).collect())
+    }
+
+    // All changes of a zone go in one transaction: live records no desired
+    // record matches are unset and the missing ones set, then the transaction
+    // is committed, which Knot checks and applies as a whole. A steady state
+    // runs nothing but the read of the zone.
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let zone = &changes.zone;
+        let diff = WebhookDiff::new(&changes.changes);
+        for record in diff.unchanged.iter() {
+            log::debug!(
+                zone = zone, record = record.name, outcome = "unchanged";
+                "record {} already up to date", record.name
+            );
+        }
+        if diff.is_empty() {
+            return Ok(());
+        }
+        if cancel.is_cancelled() {
+            log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+            return Err(Error::Cancelled);
+        }
+
+        let mut commands = vec![];
+        for record in diff.deletes.iter() {
+            commands.push(("zone-unset", unset_args(record)));
+        }
+        for record in diff.records.iter() {
+            commands.push(("zone-set", set_args(record)?));
+        }
+        if let Err(e) = self.transaction(zone, &commands).await {
+            log::error!(
+                zone = zone, outcome = "failed";
+                "changes of zone {} failed: {}", zone, e
+            );
+            return Err(e.context(&format!("zone {}", zone)));
+        }
+
+        for record in diff.deletes.iter() {
+            log::info!(
+                zone = zone, record = record.name, outcome = "deleted";
+                "record {} {} deleted", record.name, record.content
+            );
+        }
+        for record in diff.records.iter() {
+            log::info!(
+                zone = zone, record = record.name, content = record.content.to_string(),
+                outcome = "created";
+                "record {} set to {}", record.name, record.content
+            );
+        }
+        Ok(())
+    }
+
+    // The server has to serve every zone
+    async fn verify(&self, zones: &[ZoneName]) -> Result<()> {
+        for zone in zones.iter() {
+            self.call("zone-status", &[zone]).await?;
+        }
+        Ok(())
+    }
+
+    // A record set next to the ones of its name and type
+    async fn create_record(&self, zone: &ZoneName, record: &ProviderRecord) -> Result<()> {
+        let mut record = record.clone();
+        record.name = record.fqdn(zone);
+        self.transaction(zone, &[("zone-set", set_args(&record)?)])
+            .await
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, None, force).await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, Some(record_type), force)
+            .await
+    }
+
+    fn request_count(&self) -> u64 {
+        self.runs.load(Ordering::Relaxed)
+    }
+}
+
+// A record of `zone-read`, like `[example.org.] home.example.org. 300 A
+// 192.0.2.1`. Records of other types are left out.
+pub(super) fn parse_record(line: &str) -> Option<ProviderRecord> {
+    let (_, rest) = line.split_once("] ")?;
+    let mut fields = rest.split_whitespace();
+    let name = fields.next()?.trim_end_matches('.').to_ascii_lowercase();
+    let ttl = fields.next()?.parse().ok()?;
+    let r#type = fields.next()?;
+    let data = match r#type {
+        "A" | "AAAA" => fields.next()?.to_string(),
+        "CNAME" => fields.next()?.trim_end_matches('.').to_ascii_lowercase(),
+        _ => return None,
+    };
+    Some(ProviderRecord {
+        name,
+        content: RecordContent::parse(r#type, Some(&data)).ok()?,
+        comment: None,
+        op: RecordOp::default(),
+        ttl: TTL::Value(ttl),
+        params: vec![],
+    })
+}
+
+// A zone of `zone-status`, like `[example.org.] role: master | serial: 42 |
+// transaction: none`, its status the part after the name
+pub(super) fn parse_status(line: &str) -> Option<ZoneInfo> {
+    let (name, status) = line.strip_prefix('[')?.split_once("] ")?;
+    Some(ZoneInfo {
+        name: name.trim_end_matches('.').to_string(),
+        status: Some(status.trim().to_string()),
+        ..Default::default()
+    })
+}
+
+// Owner, type and data of a record, names absolute
+fn unset_args(record: &ProviderRecord) -> Vec<String> {
+    vec![
+        absolute(&record.name),
+        record.content.record_type().as_str().to_string(),
+        rdata(&record.content),
+    ]
+}
+
+// Owner, TTL when given, type and data of a record; Knot gives records
+// without a TTL the default of the zone. A record without content fails the
+// whole transaction.
+fn set_args(record: &ProviderRecord) -> Result<Vec<String>> {
+    if !matches!(
+        record.content,
+        RecordContent::A(_) | RecordContent::AAAA(_) | RecordContent::CNAME(_)
+    ) {
+        return Err(Error::InvalidRecord {
+            record: record.name.clone(),
+            reason: RecordError::MissingContent(record.content.record_type().as_str().to_string()),
+        });
+    }
+    let mut args = vec![absolute(&record.name)];
+    if let Some(ttl) = record.ttl.seconds() {
+        args.push(ttl.to_string());
+    }
+    args.push(record.content.record_type().as_str().to_string());
+    args.push(rdata(&record.content));
+    Ok(args)
+}
+
+fn absolute(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+fn rdata(content: &RecordContent) -> String {
+    match content {
+        RecordContent::CNAME(target) => absolute(target),
+        content => content.to_string(),
+    }
+}
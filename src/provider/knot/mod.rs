@@ -0,0 +1,7 @@
+#[allow(clippy::module_inception)]
+mod knot;
+pub use knot::Knot;
+pub use knot::KnotFactory;
+
+#[cfg(test)]
+mod unit_test;
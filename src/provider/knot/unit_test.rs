@@ -0,0 +1,299 @@
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+
+use super::knot::*;
+use crate::error::Error;
+use crate::provider::AuthParams;
+use crate::provider::BackendRecords;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordStatus;
+use crate::provider::ZoneRecords;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::Param;
+use crate::types::PublicIp;
+use crate::wrapper::knot::Knotc;
+
+// A knotc serving example.org with an address, an alias and a text record.
+// It keeps its arguments in `calls` next to it and refuses records of
+// bad.example.org.
+fn knotc(name: &str) -> (Knot, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("dns-syncer-knot-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let calls = dir.join("calls");
+    let script = dir.join("knotc.sh");
+    fs::write(
+        &script,
+        format!(
+            r#"echo "$*" >> {}
+case "$*" in
+"zone-read example.org")
+  echo '[example.org.] example.org. 3600 SOA ns1.example.org. admin.example.org. 42 3600 900 604800 300'
+  echo '[example.org.] example.org. 3600 TXT "v=spf1 -all"'
+  echo '[example.org.] home.example.org. 300 A 1.1.1.1'
+  echo '[example.org.] www.example.org. 3600 CNAME home.example.org.'
+  exit ;;
+"zone-status")
+  echo '[example.org.] role: master | serial: 42 | transaction: none'
+  echo '[example.net.] role: slave | serial: 7 | transaction: none'
+  exit ;;
+*" example.org"|*" example.org "*) ;;
+*)
+  echo 'error: [example.com.] (no such zone found)' >&2; exit 1 ;;
+esac
+case "$*" in
+*bad.example.org*)
+  echo 'error: (malformed data)' >&2; exit 1 ;;
+zone-*) echo OK ;;
+esac
+"#,
+            calls.display()
+        ),
+    )
+    .unwrap();
+    let knot = Knot::new(Knotc::new(vec![
+        "sh".to_string(),
+        script.display().to_string(),
+    ]));
+    (knot, calls)
+}
+
+fn desired(zone: &str, names: &[&str]) -> BackendRecords {
+    let mut records = BackendRecords::default();
+    records.zones.insert(
+        zone.to_string(),
+        ZoneRecords {
+            records: names
+                .iter()
+                .map(|name| ProviderRecord {
+                    name: name.to_string(),
+                    content: RecordContent::Unassigned(RecordType::A),
+                    comment: None,
+                    ttl: TTL::Value(300),
+                    op: RecordOp::Create,
+                    params: vec![],
+                })
+                .collect(),
+        },
+    );
+    records
+}
+
+// The calls after the `zone-read` ones
+fn changes(calls: &PathBuf) -> Vec<String> {
+    fs::read_to_string(calls)
+        .unwrap()
+        .lines()
+        .filter(|l| !l.starts_with("zone-read"))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn test_knot_parse() {
+    let record =
+        parse_record("[example.org.] WWW.example.org. 60 CNAME Home.example.org.").unwrap();
+    assert_eq!(record.name, "www.example.org");
+    assert_eq!(record.content.to_string(), "home.example.org");
+    assert_eq!(record.ttl, TTL::Value(60));
+    assert!(parse_record("[example.org.] example.org. 3600 TXT \"v=spf1 -all\"").is_none());
+    assert!(parse_record("OK").is_none());
+
+    let zone = parse_status("[example.org.] role: master | serial: 42").unwrap();
+    assert_eq!(zone.name, "example.org");
+    assert_eq!(zone.status.unwrap(), "role: master | serial: 42");
+}
+
+#[tokio::test]
+async fn test_knot_list() {
+    let (provider, _) = knotc("list");
+
+    let records = provider.list(&"example.org".to_string()).await.unwrap();
+    let records = records
+        .iter()
+        .map(|r| (r.record.name.as_str(), r.record.content.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        vec![
+            ("home.example.org", "1.1.1.1".to_string()),
+            ("www.example.org", "home.example.org".to_string()),
+        ]
+    );
+    assert_eq!(
+        provider.list_zones().await.unwrap(),
+        vec!["example.org", "example.net"]
+    );
+
+    let err = provider.list(&"example.com".to_string()).await.unwrap_err();
+    assert!(matches!(err, Error::ZoneNotFound { .. }), "{}", err);
+    assert_eq!(provider.request_count(), 3);
+}
+
+#[tokio::test]
+async fn test_knot_sync() {
+    let (provider, calls) = knotc("sync");
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    let outcome = provider
+        .sync(
+            &desired("example.org", &["home", "nas"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Updated);
+    assert_eq!(outcome.zones[0].records[1].status, RecordStatus::Created);
+
+    // The changes of the zone go in one transaction
+    assert_eq!(
+        changes(&calls),
+        vec![
+            "zone-begin example.org",
+            "zone-unset example.org home.example.org. A 1.1.1.1",
+            "zone-set example.org home.example.org. 300 A 2.2.2.2",
+            "zone-set example.org nas.example.org. 300 A 2.2.2.2",
+            "zone-commit example.org",
+        ]
+    );
+
+    // Records already as desired run nothing but the read
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(1, 1, 1, 1)), None);
+    let outcome = provider
+        .sync(
+            &desired("example.org", &["home"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty());
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Unchanged);
+    assert_eq!(changes(&calls).len(), 5);
+}
+
+#[tokio::test]
+async fn test_knot_errors() {
+    let (provider, calls) = knotc("errors");
+    let zone = "example.org".to_string();
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    // Records carry no owner marker, deletes take `force`
+    let err = provider
+        .delete_record(&zone, "www.example.org", RecordType::CNAME, false)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("no owner marker"), "{}", err);
+    let deleted = provider
+        .delete_record(&zone, "www.example.org", RecordType::CNAME, true)
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(
+        changes(&calls)[1..],
+        [
+            "zone-unset example.org www.example.org. CNAME home.example.org.",
+            "zone-commit example.org",
+        ]
+    );
+
+    // A refused change aborts the transaction and fails the whole zone
+    let outcome = provider
+        .sync(
+            &desired(&zone, &["nas", "bad"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(
+        err.contains("zone-set failed: error: (malformed data)"),
+        "{}",
+        err
+    );
+    assert!(outcome.records().all(|r| r.status == RecordStatus::Failed));
+    assert_eq!(
+        changes(&calls)[3..],
+        [
+            "zone-begin example.org",
+            "zone-set example.org nas.example.org. 300 A 2.2.2.2",
+            "zone-set example.org bad.example.org. 300 A 2.2.2.2",
+            "zone-abort example.org",
+        ]
+    );
+
+    let err = provider
+        .verify(&["example.com".to_string()])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::ZoneNotFound { .. }), "{}", err);
+
+    // The arguments go after the script, to $0 and $1
+    let knotc = Knotc::new(vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        "sleep 5".to_string(),
+    ]);
+    let provider = Knot::new(knotc).with_timeout(Duration::from_millis(100));
+    let err = provider.verify(&[zone]).await.unwrap_err();
+    assert!(
+        err.to_string().contains("sh zone-status timed out"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_knot_factory() {
+    let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+    let factory = KnotFactory;
+
+    assert!(
+        factory
+            .create(
+                "knot-1",
+                &AuthParams::default(),
+                &vec![
+                    param("control", "knotc -s /run/knot/knot.sock"),
+                    param("timeout", "5s"),
+                ],
+            )
+            .is_ok()
+    );
+    assert!(
+        factory
+            .create("knot-1", &AuthParams::default(), &vec![])
+            .is_ok()
+    );
+    let err = factory
+        .create(
+            "knot-1",
+            &AuthParams::default(),
+            &vec![param("control", " ")],
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("requires a control command"));
+    let err = factory
+        .create("knot-1", &AuthParams::default(), &vec![param("ttl", "60")])
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("unknown param ttl"));
+    let err = factory
+        .create("knot-1", &AuthParams::new("tsig", vec![]), &vec![])
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string()
+            .contains("unsupported authentication method")
+    );
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::ChangeSet;
+use crate::provider::ExistingRecord;
+use crate::provider::Provider;
+use crate::record::OWNER_MARKER;
+use crate::record::ProviderRecord;
+use crate::record::RecordType;
+use crate::types::ZoneName;
+
+type Zones = HashMap<ZoneName, Vec<ProviderRecord>>;
+
+// Provider keeping its records in memory, to run the sync pipeline without
+// credentials or network. Clones share the records, so a clone kept aside sees
+// what a sync did. Like Cloudflare, a change deletes the records it replaces
+// and adds the new one.
+#[derive(Debug, Clone, Default)]
+pub struct MockProvider {
+    zones: Arc<Mutex<Zones>>,
+    // Only the seeded zones exist, like at a real provider. Otherwise a zone
+    // is created on first use.
+    fixed_zones: bool,
+    // Time every change takes, to cancel a sync in flight
+    delay: Duration,
+}
+
+impl MockProvider {
+    // Every zone exists and starts empty
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Only `zones` exist, with their records
+    pub fn with_zones(zones: Zones) -> Self {
+        Self {
+            zones: Arc::new(Mutex::new(zones)),
+            fixed_zones: true,
+            delay: Duration::ZERO,
+        }
+    }
+
+    // Every change takes `delay`, like an API answering slowly
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    // Records of every zone as they are now
+    pub fn records(&self) -> Zones {
+        self.zones.lock().unwrap().clone()
+    }
+
+    fn has_zone(&self, zones: &Zones, zone: &str) -> bool {
+        !self.fixed_zones || zones.contains_key(zone)
+    }
+}
+
+#[async_trait]
+impl Provider for MockProvider {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        let zones = self.zones.lock().unwrap();
+        if !self.has_zone(&zones, zone) {
+            return Err(Error::ZoneNotFound {
+                zone: zone.to_string(),
+            });
+        }
+        let records = zones.get(zone).cloned().unwrap_or_default();
+        Ok(records.into_iter().map(ExistingRecord::from).collect())
+    }
+
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        if !self.has_zone(&self.zones.lock().unwrap(), &changes.zone) {
+            return Err(Error::ZoneNotFound {
+                zone: changes.zone.clone(),
+            });
+        }
+        for change in changes.changes.iter() {
+            if cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            if !self.delay.is_zero() {
+                tokio::time::sleep(self.delay).await;
+            }
+            let record = &change.after;
+            log::info!(
+                zone = changes.zone, record = record.name, content = record.content.to_string(),
+                outcome = "updated";
+                "record {} set to {}", record.name, record.content
+            );
+            let mut zones = self.zones.lock().unwrap();
+            let current = zones.entry(changes.zone.clone()).or_default();
+            current.retain(|r| {
+                !change.before.iter().any(|b| {
+                    b.record.name.eq_ignore_ascii_case(&r.name) && b.record.content == r.content
+                })
+            });
+            current.push(record.clone());
+        }
+        Ok(())
+    }
+
+    async fn verify(&self, zones: &[ZoneName]) -> Result<()> {
+        let current = self.zones.lock().unwrap();
+        match zones.iter().find(|z| !self.has_zone(&current, z)) {
+            Some(zone) => Err(Error::ZoneNotFound {
+                zone: zone.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    async fn list_zones(&self) -> Result<Vec<ZoneName>> {
+        let mut ret = self
+            .zones
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        ret.sort();
+        Ok(ret)
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let mut zones = self.zones.lock().unwrap();
+        let current = zones.get_mut(zone).ok_or(Error::ZoneNotFound {
+            zone: zone.to_string(),
+        })?;
+
+        let (deleted, kept): (Vec<_>, Vec<_>) = current.drain(..).partition(|r| {
+            r.name.eq_ignore_ascii_case(name)
+                && (force
+                    || r.comment
+                        .as_deref()
+                        .is_some_and(|c| c.contains(OWNER_MARKER)))
+        });
+        *current = kept;
+        Ok(deleted)
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let mut zones = self.zones.lock().unwrap();
+        let current = zones.get_mut(zone).ok_or(Error::ZoneNotFound {
+            zone: zone.to_string(),
+        })?;
+
+        let (deleted, kept): (Vec<_>, Vec<_>) = current.drain(..).partition(|r| {
+            r.name.eq_ignore_ascii_case(name)
+                && r.content.record_type() == record_type
+                && (force || r.is_owned())
+        });
+        *current = kept;
+        Ok(deleted)
+    }
+
+    async fn delete_owned_records(
+        &self,
+        zone: &ZoneName,
+        records: &[ProviderRecord],
+    ) -> Result<Vec<ProviderRecord>> {
+        let mut zones = self.zones.lock().unwrap();
+        let current = zones.get_mut(zone).ok_or(Error::ZoneNotFound {
+            zone: zone.to_string(),
+        })?;
+
+        let (deleted, kept): (Vec<_>, Vec<_>) = current.drain(..).partition(|r| {
+            r.is_owned()
+                && records
+                    .iter()
+                    .any(|d| d.name.eq_ignore_ascii_case(&r.name) && d.content == r.content)
+        });
+        *current = kept;
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::provider::BackendRecords;
+    use crate::provider::PlanOp;
+    use crate::provider::RecordStatus;
+    use crate::provider::ZoneRecords;
+    use crate::record::RecordContent;
+    use crate::record::RecordOp;
+    use crate::record::TTL;
+    use crate::types::PublicIp;
+
+    fn record(name: &str, content: RecordContent) -> ProviderRecord {
+        ProviderRecord {
+            name: name.to_string(),
+            content,
+            comment: None,
+            op: RecordOp::Create,
+            ttl: TTL::Auto,
+            params: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_sync() {
+        let seeded = record(
+            "home.example.org",
+            RecordContent::A(Ipv4Addr::new(1, 1, 1, 1)),
+        );
+        let zones = HashMap::from([("example.org".to_string(), vec![seeded])]);
+        let provider = MockProvider::with_zones(zones);
+        let view = provider.clone();
+
+        let mut records = BackendRecords::default();
+        records.zones.insert(
+            "example.org".to_string(),
+            ZoneRecords {
+                records: vec![
+                    record("home", RecordContent::Unassigned(RecordType::A)),
+                    record("www", RecordContent::CNAME("home.example.org".to_string())),
+                ],
+            },
+        );
+        records.zones.insert(
+            "missing.org".to_string(),
+            ZoneRecords {
+                records: vec![record("www", RecordContent::Unassigned(RecordType::A))],
+            },
+        );
+        let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+        let plan = provider.dry_run(&records, &public_ip).await.unwrap();
+        let ops = plan.iter().map(|c| c.op).collect::<Vec<_>>();
+        assert_eq!(ops, vec![PlanOp::Update, PlanOp::Create]);
+
+        let cancelled = CancellationToken::new();
+        cancelled.cancel();
+        let err = provider.sync(&records, &public_ip, &cancelled);
+        assert!(matches!(err.await.into_result(), Err(Error::Cancelled)));
+        assert!(!view.records()["example.org"][0].is_owned());
+
+        let outcome = provider
+            .sync(&records, &public_ip, &CancellationToken::new())
+            .await;
+        let statuses = outcome
+            .records()
+            .map(|r| (r.name.as_str(), r.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                ("home.example.org", RecordStatus::Updated),
+                ("www.example.org", RecordStatus::Created),
+            ]
+        );
+        outcome.into_result().unwrap();
+        let synced = view.records();
+        assert_eq!(synced.len(), 1);
+        let zone = &synced["example.org"];
+        assert_eq!(zone.len(), 2);
+        assert_eq!(zone[0].name, "home.example.org");
+        assert_eq!(zone[0].content, RecordContent::A(Ipv4Addr::new(2, 2, 2, 2)));
+        assert!(zone.iter().all(|r| r.is_owned()));
+
+        assert!(provider.verify(&["missing.org".to_string()]).await.is_err());
+        let deleted = provider
+            .delete_records(&"example.org".to_string(), "www.example.org", false)
+            .await
+            .unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(view.records()["example.org"].len(), 1);
+
+        let zone = "example.org".to_string();
+        let info = provider.zone_info(&zone).await.unwrap();
+        assert_eq!(info.map(|z| z.name), Some(zone.clone()));
+        let missing = provider.zone_info(&"missing.org".to_string()).await;
+        assert_eq!(missing.unwrap(), None);
+        let found = provider.list_records(&zone, "HOME.example.org").await;
+        assert_eq!(found.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_mock_single_record_ops() {
+        let provider = MockProvider::new();
+        let zone = "example.org".to_string();
+        let v4 = RecordContent::A(Ipv4Addr::new(1, 1, 1, 1));
+        provider
+            .create_record(&zone, &record("home", v4.clone()))
+            .await
+            .unwrap();
+        let v6 = RecordContent::AAAA("2001:db8::1".parse().unwrap());
+        provider
+            .create_record(&zone, &record("home", v6.clone()))
+            .await
+            .unwrap();
+        let unassigned = record("www", RecordContent::Unassigned(RecordType::A));
+        let created = provider.create_record(&zone, &unassigned).await;
+        assert!(matches!(created, Err(Error::InvalidRecord { .. })));
+
+        let found = provider
+            .get_record(&zone, "home.example.org", RecordType::AAAA)
+            .await
+            .unwrap();
+        assert_eq!(found.map(|r| r.content), Some(v6));
+        let missing = provider
+            .get_record(&zone, "home.example.org", RecordType::CNAME)
+            .await
+            .unwrap();
+        assert_eq!(missing, None);
+
+        let deleted = provider
+            .delete_record(&zone, "home.example.org", RecordType::AAAA, false)
+            .await
+            .unwrap();
+        assert_eq!(deleted.len(), 1);
+        let left = &provider.records()[&zone];
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].content, v4);
+        assert!(left[0].is_owned());
+    }
+}
@@ -1,5 +1,65 @@
 mod types;
 pub use types::*;
 
+mod adguard;
+pub use adguard::*;
+
 mod cloudflare;
 pub use cloudflare::*;
+
+mod dnsmasq;
+pub use dnsmasq::*;
+
+mod exec;
+pub use exec::*;
+
+mod hook;
+pub use hook::*;
+
+mod knot;
+pub use knot::*;
+
+mod mock;
+pub use mock::*;
+
+mod plugin;
+pub use plugin::*;
+
+mod registry;
+pub use registry::*;
+
+mod rfc2136;
+pub use rfc2136::*;
+
+mod route53;
+pub use route53::*;
+
+mod unbound;
+pub use unbound::*;
+
+#[cfg(feature = "plugin")]
+mod wasm;
+#[cfg(feature = "plugin")]
+pub use wasm::*;
+
+mod webhook;
+pub use webhook::*;
+
+// Every provider type compiled in
+pub fn provider_types() -> Vec<Capabilities> {
+    let mut ret = vec![
+        AdGuard::capabilities(),
+        Cloudflare::capabilities(),
+        Dnsmasq::capabilities(),
+        Exec::capabilities(),
+        Knot::capabilities(),
+        Rfc2136::capabilities(),
+        Route53::capabilities(),
+        Unbound::capabilities(),
+        Webhook::capabilities(),
+    ];
+    #[cfg(feature = "plugin")]
+    ret.push(WasmPlugin::capabilities());
+    ret.sort_by_key(|c| c.r#type);
+    ret
+}
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::ChangeSet;
+use crate::provider::ExistingRecord;
+use crate::provider::Provider;
+use crate::types::ZoneName;
+
+// Error a plugin answers `list` with for a zone it cannot access
+pub const PLUGIN_ZONE_NOT_FOUND: &str = "zone-not-found";
+
+// A loaded plugin of the `records` interface of wit/provider.wit. The host
+// runtime instantiates the module, calls `init` and implements this by
+// calling the exports; `call` gives an export its JSON input.
+#[async_trait]
+pub trait PluginInstance: Send + Sync {
+    async fn call(&self, export: &str, input: &str) -> std::result::Result<String, String>;
+}
+
+// Provider implemented by a plugin, records and changes cross the plugin
+// boundary as JSON
+pub struct PluginProvider {
+    name: String,
+    instance: Arc<dyn PluginInstance>,
+}
+
+impl PluginProvider {
+    pub fn new(name: &str, instance: Arc<dyn PluginInstance>) -> Self {
+        Self {
+            name: name.to_string(),
+            instance,
+        }
+    }
+
+    async fn call(&self, export: &str, input: &str) -> Result<String> {
+        self.instance
+            .call(export, input)
+            .await
+            .map_err(|e| Error::Provider(format!("plugin {} {}: {}", self.name, export, e)))
+    }
+}
+
+#[async_trait]
+impl Provider for PluginProvider {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        let json = match self.instance.call("list", zone).await {
+            Ok(json) => json,
+            Err(e) if e == PLUGIN_ZONE_NOT_FOUND => {
+                return Err(Error::ZoneNotFound {
+                    zone: zone.to_string(),
+                });
+            }
+            Err(e) => {
+                let reason = format!("plugin {} list: {}", self.name, e);
+                return Err(Error::Provider(reason));
+            }
+        };
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.call("apply", &serde_json::to_string(changes)?).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::record::ProviderRecord;
+    use crate::record::RecordContent;
+
+    // Plugin serving one zone from memory, answering like a module would
+    #[derive(Default)]
+    struct EchoPlugin {
+        applied: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl PluginInstance for EchoPlugin {
+        async fn call(&self, export: &str, input: &str) -> std::result::Result<String, String> {
+            match (export, input) {
+                ("list", "example.org") => Ok(r#"[{"id": "r1", "record":
+                    {"name": "home.example.org", "type": "A", "content": "192.0.2.9"}}]"#
+                    .to_string()),
+                ("list", _) => Err(PLUGIN_ZONE_NOT_FOUND.to_string()),
+                ("apply", changes) => {
+                    self.applied.lock().unwrap().push(changes.to_string());
+                    Ok(String::new())
+                }
+                _ => Err("no such export".to_string()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plugin_provider() {
+        let plugin = Arc::new(EchoPlugin::default());
+        let provider = PluginProvider::new("plugin-1", plugin.clone());
+
+        let zone = "example.org".to_string();
+        let existing = provider.list(&zone).await.unwrap();
+        assert_eq!(existing[0].id.as_deref(), Some("r1"));
+        assert!(matches!(
+            provider.list(&"example.net".to_string()).await,
+            Err(Error::ZoneNotFound { .. })
+        ));
+
+        let desired = ProviderRecord::builder()
+            .name("home.example.org")
+            .value(RecordContent::A("192.0.2.1".parse().unwrap()))
+            .build()
+            .unwrap();
+        let changes = provider.plan(&zone, &[desired], &existing);
+        provider
+            .apply(&changes, &CancellationToken::new())
+            .await
+            .unwrap();
+        let applied: ChangeSet = serde_json::from_str(&plugin.applied.lock().unwrap()[0]).unwrap();
+        assert_eq!(applied, changes);
+        assert_eq!(applied.changes[0].before[0].id.as_deref(), Some("r1"));
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AdGuardFactory;
+use crate::provider::CloudflareFactory;
+use crate::provider::DnsmasqFactory;
+use crate::provider::ExecFactory;
+use crate::provider::KnotFactory;
+use crate::provider::Provider;
+use crate::provider::Rfc2136Factory;
+use crate::provider::Route53Factory;
+use crate::provider::UnboundFactory;
+#[cfg(feature = "plugin")]
+use crate::provider::WasmPluginFactory;
+use crate::provider::WebhookFactory;
+use crate::types::Param;
+
+pub type ParamList = Vec<Param>;
+
+// Authentication of a provider, secrets already resolved
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuthParams {
+    // Like "api_token", empty when the provider has no authentication
+    pub method: String,
+    pub params: ParamList,
+}
+
+impl AuthParams {
+    pub fn new(method: &str, params: ParamList) -> Self {
+        Self {
+            method: method.to_string(),
+            params,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.method.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| p.value.as_str())
+    }
+}
+
+// What the registry gets from the config entry of a provider
+#[derive(Debug, Clone, Default)]
+pub struct ProviderConfig {
+    pub name: String,
+    pub r#type: String,
+    pub auth: AuthParams,
+    pub params: ParamList,
+}
+
+// Builds providers of one type from the params of their config entry. Closures
+// with the same signature are factories too.
+pub trait ProviderFactory: Send + Sync {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>>;
+}
+
+impl<F> ProviderFactory for F
+where
+    F: Fn(&str, &AuthParams, &ParamList) -> Result<Box<dyn Provider>> + Send + Sync,
+{
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        self(name, auth, params)
+    }
+}
+
+// Provider factories by `type:` value. Downstream users register their own
+// providers next to the built-in ones instead of patching the binary.
+pub struct ProviderRegistry {
+    factories: BTreeMap<String, Box<dyn ProviderFactory>>,
+}
+
+impl ProviderRegistry {
+    // Registry with the providers compiled in
+    pub fn new() -> Self {
+        let mut ret = Self::empty();
+        ret.register("adguard", AdGuardFactory);
+        ret.register("cloudflare", CloudflareFactory);
+        ret.register("dnsmasq", DnsmasqFactory);
+        ret.register("exec", ExecFactory);
+        ret.register("knot", KnotFactory);
+        ret.register("rfc2136", Rfc2136Factory);
+        ret.register("route53", Route53Factory);
+        ret.register("unbound", UnboundFactory);
+        ret.register("webhook", WebhookFactory);
+        #[cfg(feature = "plugin")]
+        ret.register("plugin", WasmPluginFactory);
+        ret
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            factories: BTreeMap::new(),
+        }
+    }
+
+    // Register a factory for `type`, replacing the one registered before
+    pub fn register<F>(&mut self, r#type: &str, factory: F)
+    where
+        F: ProviderFactory + 'static,
+    {
+        self.factories.insert(r#type.to_string(), Box::new(factory));
+    }
+
+    pub fn types(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    pub fn create(&self, config: &ProviderConfig) -> Result<Box<dyn Provider>> {
+        match self.factories.get(&config.r#type) {
+            Some(factory) => factory.create(&config.name, &config.auth, &config.params),
+            None => Err(Error::ParseError(format!(
+                "provider {}: unknown type {}, known types are {}",
+                config.name,
+                config.r#type,
+                self.types().join(", ")
+            ))),
+        }
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::provider::MockProvider;
+
+    #[tokio::test]
+    async fn test_registry() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(
+            "mock",
+            |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                Ok(Box::new(MockProvider::new()))
+            },
+        );
+        // The plugin type comes with the plugin feature only
+        let types = [
+            "adguard",
+            "cloudflare",
+            "dnsmasq",
+            "exec",
+            "knot",
+            "mock",
+            "plugin",
+            "rfc2136",
+            "route53",
+            "unbound",
+            "webhook",
+        ]
+        .into_iter()
+        .filter(|t| cfg!(feature = "plugin") || *t != "plugin")
+        .collect::<Vec<_>>();
+        assert_eq!(registry.types(), types);
+
+        let config = ProviderConfig {
+            name: "mock-1".to_string(),
+            r#type: "mock".to_string(),
+            ..Default::default()
+        };
+        let provider = registry.create(&config).unwrap();
+        assert!(provider.list_zones().await.unwrap().is_empty());
+
+        let config = ProviderConfig {
+            r#type: "gandi".to_string(),
+            ..config
+        };
+        let err = registry.create(&config).err().unwrap().to_string();
+        assert!(err.contains(&format!(
+            "unknown type gandi, known types are {}",
+            types.join(", ")
+        )));
+
+        let config = ProviderConfig {
+            r#type: "cloudflare".to_string(),
+            ..config
+        };
+        let err = registry.create(&config).err().unwrap().to_string();
+        assert!(err.contains("mock-1: provider has no authentication"));
+    }
+}
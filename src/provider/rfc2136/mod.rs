@@ -0,0 +1,7 @@
+#[allow(clippy::module_inception)]
+mod rfc2136;
+pub use rfc2136::Rfc2136;
+pub use rfc2136::Rfc2136Factory;
+
+#[cfg(test)]
+mod unit_test;
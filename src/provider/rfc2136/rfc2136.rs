@@ -0,0 +1,627 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AuthMethod;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ChangeSet;
+use crate::provider::DEFAULT_ZONE_CONCURRENCY;
+use crate::provider::ExistingRecord;
+use crate::provider::ParamList;
+use crate::provider::ParamSpec;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordChange;
+use crate::provider::ZoneInfo;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordError;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::ZoneName;
+use crate::types::parse_duration;
+use crate::wrapper::dns_wire;
+use crate::wrapper::dns_wire::Message;
+use crate::wrapper::dns_wire::RData;
+use crate::wrapper::dns_wire::TsigAlgorithm;
+use crate::wrapper::dns_wire::TsigKey;
+
+// Dynamic updates have no automatic TTL
+pub const DEFAULT_TTL: u32 = 300;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+// Types a name is looked up for, the ones synced
+const QUERY_TYPES: [u16; 3] = [dns_wire::TYPE_A, dns_wire::TYPE_AAAA, dns_wire::TYPE_CNAME];
+
+// An authoritative server taking RFC 2136 dynamic updates, like BIND or Knot.
+// Names are looked up by queries to the server and whole zones by a zone
+// transfer; every query, transfer and update is signed when a TSIG key is
+// configured.
+pub struct Rfc2136 {
+    cli: dns_wire::Client,
+    // Messages sent so far, whatever came of them
+    requests: AtomicU64,
+    zone_concurrency: usize,
+}
+
+// RFC 2136 providers from a `tsig` authentication, or unsigned updates the
+// server allows by address without one. Params of the provider entry:
+//
+// - `server`, the host name or address of the primary server, with an
+//   optional port (53 by default)
+// - `timeout` of every exchange with the server (10s by default)
+// - `zone_concurrency`
+pub struct Rfc2136Factory;
+
+impl ProviderFactory for Rfc2136Factory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        let key = tsig_key(auth)?;
+        let mut server = None;
+        let mut timeout = DEFAULT_TIMEOUT;
+        let mut zone_concurrency = DEFAULT_ZONE_CONCURRENCY;
+        for param in params.iter() {
+            match param.name.as_str() {
+                "server" => server = Some(param.value.clone()),
+                "timeout" => timeout = parse_duration(&param.value)?,
+                "zone_concurrency" => {
+                    zone_concurrency = param.value.parse::<usize>().map_err(|_| {
+                        Error::ParseError(format!(
+                            "{}: invalid zone_concurrency {}",
+                            name, param.value
+                        ))
+                    })?
+                }
+                _ => {
+                    return Err(Error::ParseError(format!(
+                        "{}: unknown param {}",
+                        name, param.name
+                    )));
+                }
+            }
+        }
+        let server = server
+            .ok_or_else(|| Error::ParseError(format!("{}: rfc2136 requires a server", name)))?;
+        let provider = Rfc2136::new(dns_wire::Client::new(&server, key, timeout))
+            .with_zone_concurrency(zone_concurrency);
+        Ok(Box::new(provider))
+    }
+}
+
+// Method `tsig` with `key_name`, `secret` in base64 and optionally
+// `algorithm` (hmac-sha256 by default), or none for unsigned messages
+fn tsig_key(auth: &AuthParams) -> Result<Option<TsigKey>> {
+    match auth.method.as_str() {
+        "" => Ok(None),
+        "tsig" => {
+            let (Some(name), Some(secret)) = (auth.get("key_name"), auth.get("secret")) else {
+                return Err(Error::Provider(
+                    "rfc2136 tsig auth requires both key_name and secret".into(),
+                ));
+            };
+            let algorithm = auth.get("algorithm").unwrap_or("hmac-sha256");
+            let algorithm = TsigAlgorithm::parse(algorithm).ok_or_else(|| {
+                Error::Provider(format!(
+                    "{}: unsupported TSIG algorithm, expected hmac-sha256, hmac-sha384 or \
+                     hmac-sha512",
+                    algorithm
+                ))
+            })?;
+            Ok(Some(TsigKey::new(name, algorithm, secret)?))
+        }
+        method => Err(Error::Provider(format!(
+            "{}: unsupported authentication method for rfc2136 provider",
+            method
+        ))),
+    }
+}
+
+impl Rfc2136 {
+    pub fn new(cli: dns_wire::Client) -> Self {
+        Self {
+            cli,
+            requests: AtomicU64::new(0),
+            zone_concurrency: DEFAULT_ZONE_CONCURRENCY,
+        }
+    }
+
+    // Sync up to `concurrency` zones at once, one at a time with 1
+    pub fn with_zone_concurrency(mut self, concurrency: usize) -> Self {
+        self.zone_concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "rfc2136",
+            description: "Authoritative servers taking RFC 2136 dynamic updates, like BIND and Knot",
+            record_types: vec![RecordType::A, RecordType::AAAA, RecordType::CNAME],
+            auth_methods: vec![AuthMethod {
+                name: "tsig",
+                params: vec!["key_name", "secret", "algorithm"],
+            }],
+            params: vec![
+                ParamSpec {
+                    name: "server",
+                    description: "Host name or address of the primary server, with an optional port",
+                },
+                ParamSpec {
+                    name: "timeout",
+                    description: "Timeout of every exchange with the server, 10s by default",
+                },
+            ],
+            operations: vec![
+                "list",
+                "apply",
+                "dry_run",
+                "verify",
+                "zone_info",
+                "list_records",
+                "list_zone_records",
+                "get_record",
+                "create_record",
+                "delete_record",
+                "delete_records",
+            ],
+        }
+    }
+
+    // Human readable name of the key that never leaks the secret
+    fn describe(&self) -> String {
+        match self.cli.key() {
+            Some(key) => format!("TSIG key {}", key.name),
+            None => "unsigned messages".to_string(),
+        }
+    }
+
+    async fn send(&self, msg: &Message) -> Result<Message> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.cli.send(msg).await
+    }
+
+    // SOA record of `zone`, `None` when the server is not authoritative for it
+    async fn soa(&self, zone: &ZoneName) -> Result<Option<dns_wire::Soa>> {
+        let resp = self.send(&Message::query(zone, dns_wire::TYPE_SOA)).await?;
+        match resp.rcode {
+            dns_wire::RCODE_NOERROR => {}
+            dns_wire::RCODE_NXDOMAIN | dns_wire::RCODE_NOTAUTH | dns_wire::RCODE_REFUSED => {
+                return Ok(None);
+            }
+            _ => {
+                return Err(dns_wire::rcode_error(
+                    &resp,
+                    &format!("query of zone {}", zone),
+                ));
+            }
+        }
+        Ok(resp.answers.into_iter().find_map(|r| match r.data {
+            RData::Soa(soa) if resp.authoritative && r.name.eq_ignore_ascii_case(zone) => Some(soa),
+            _ => None,
+        }))
+    }
+
+    async fn check_zone(&self, zone: &ZoneName) -> Result<()> {
+        match self.soa(zone).await? {
+            Some(_) => Ok(()),
+            None => Err(Error::ZoneNotFound {
+                zone: zone.to_string(),
+            }),
+        }
+    }
+
+    // Records named `name` the server answers, of the synced types
+    async fn query_name(&self, zone: &ZoneName, name: &str) -> Result<Vec<ExistingRecord>> {
+        let mut ret = vec![];
+        for r#type in QUERY_TYPES {
+            let resp = self.send(&Message::query(name, r#type)).await?;
+            match resp.rcode {
+                dns_wire::RCODE_NOERROR | dns_wire::RCODE_NXDOMAIN => {}
+                _ => {
+                    let what = format!("query of {} in zone {}", name, zone);
+                    return Err(dns_wire::rcode_error(&resp, &what));
+                }
+            }
+            // A CNAME answers queries of every type, it is taken from its own
+            ret.extend(
+                resp.answers
+                    .into_iter()
+                    .filter(|r| r.r#type == r#type && r.name.eq_ignore_ascii_case(name))
+                    .filter_map(existing),
+            );
+        }
+        Ok(ret)
+    }
+
+    // Send an update of `zone`, an error rcode failing it
+    async fn update(&self, zone: &ZoneName, msg: &Message) -> Result<()> {
+        let resp = self.send(msg).await?;
+        if resp.rcode != dns_wire::RCODE_NOERROR {
+            return Err(dns_wire::rcode_error(
+                &resp,
+                &format!("update of zone {}", zone),
+            ));
+        }
+        Ok(())
+    }
+
+    // Delete the record sets named `name`, of `record_type` when given.
+    // Records on a server carry no owner marker, so it takes `force`.
+    async fn delete_matching(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: Option<RecordType>,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.check_zone(zone).await?;
+        let records = self
+            .query_name(zone, name)
+            .await?
+            .into_iter()
+            .map(|r| r.record)
+            .filter(|r| {
+                record_type
+                    .as_ref()
+                    .is_none_or(|t| r.content.record_type() == *t)
+            })
+            .collect::<Vec<_>>();
+        if records.is_empty() {
+            return Ok(vec![]);
+        }
+        if !force {
+            return Err(Error::Provider(format!(
+                "{} may not be managed by dns-syncer, rfc2136 records carry no owner marker, \
+                 force the deletion to remove it anyway",
+                name
+            )));
+        }
+
+        let mut msg = Message::update(zone);
+        let mut deleted: Vec<RecordType> = vec![];
+        for record in records.iter() {
+            let record_type = record.content.record_type();
+            if !deleted.contains(&record_type)
+                && let Some(r#type) = wire_type(&record_type)
+            {
+                msg.delete_rrset(&record.name, r#type);
+                deleted.push(record_type);
+            }
+        }
+        self.update(zone, &msg).await?;
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl Provider for Rfc2136 {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let records = match self.cli.transfer(zone).await {
+            // Servers refuse the transfers of zones they do not serve alike
+            Err(e @ (Error::Auth { .. } | Error::Provider(_))) => {
+                self.check_zone(zone).await?;
+                return Err(e);
+            }
+            result => result?,
+        };
+        Ok(records
+            .into_iter()
+            .filter(|r| r.class == dns_wire::CLASS_IN)
+            .filter_map(existing)
+            .collect())
+    }
+
+    async fn list_named(&self, zone: &ZoneName, names: &[String]) -> Result<Vec<ExistingRecord>> {
+        self.check_zone(zone).await?;
+        let mut ret = vec![];
+        for name in names {
+            ret.extend(self.query_name(zone, name).await?);
+        }
+        Ok(ret)
+    }
+
+    // All changes of a zone go in one update, which the server applies as a
+    // whole. The records of a name and type form one record set, replaced by
+    // deleting it and adding the desired records; live sets of the desired
+    // names with another type are deleted. Sets already served as desired are
+    // left out, so a steady state sends no update at all.
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let zone = &changes.zone;
+        let diff = ZoneDiff::new(&changes.changes);
+        for set in diff.unchanged.iter() {
+            log::debug!(
+                zone = zone, record = set.name, outcome = "unchanged";
+                "record {} {} already up to date", set.name, set.record_type().as_str()
+            );
+        }
+        if diff.replaced.is_empty() && diff.deleted.is_empty() {
+            return Ok(());
+        }
+        if cancel.is_cancelled() {
+            log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+            return Err(Error::Cancelled);
+        }
+
+        let mut msg = Message::update(zone);
+        for set in diff.deleted.iter().chain(diff.replaced.iter()) {
+            msg.delete_rrset(&set.name, set.r#type);
+        }
+        for set in diff.replaced.iter() {
+            for data in set.values.iter().filter_map(rdata) {
+                msg.add_record(&set.name, set.r#type, set.ttl, data);
+            }
+        }
+        if let Err(e) = self.update(zone, &msg).await {
+            log::error!(
+                zone = zone, outcome = "failed";
+                "changes of zone {} failed: {}", zone, e
+            );
+            return Err(e.context(&format!("zone {}", zone)));
+        }
+
+        for set in diff.deleted.iter() {
+            log::info!(
+                zone = zone, record = set.name, outcome = "deleted";
+                "record {} {} deleted", set.name, set.record_type().as_str()
+            );
+        }
+        for set in diff.replaced.iter() {
+            let values = set.values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+            log::info!(
+                zone = zone, record = set.name, content = values.join(","), outcome = "updated";
+                "record {} set to {}", set.name, values.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    // The server has to be authoritative for every zone. A signed query it
+    // answers proves it knows the key, whether the key may update a zone is
+    // only known by updating it.
+    async fn verify(&self, zones: &[ZoneName]) -> Result<()> {
+        for zone in zones {
+            let soa = self.soa(zone).await.map_err(|e| match e {
+                Error::Auth { reason, .. } => Error::Auth {
+                    provider: String::new(),
+                    reason: format!("{} is rejected: {}", self.describe(), reason),
+                },
+                e => e,
+            })?;
+            if soa.is_none() {
+                return Err(Error::Auth {
+                    provider: String::new(),
+                    reason: format!(
+                        "{} is not authoritative for zone {}",
+                        self.cli.server(),
+                        zone
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn zone_info(&self, zone: &ZoneName) -> Result<Option<ZoneInfo>> {
+        let soa = self.soa(zone).await?;
+        Ok(soa.map(|soa| ZoneInfo {
+            name: zone.clone(),
+            id: None,
+            plan: None,
+            status: Some(format!("serial {}", soa.serial)),
+        }))
+    }
+
+    async fn list_records(&self, zone: &ZoneName, name: &str) -> Result<Vec<ProviderRecord>> {
+        let records = self.list_named(zone, &[name.to_string()]).await?;
+        Ok(records.into_iter().map(|r| r.record).collect())
+    }
+
+    // Dynamic updates add a record next to the ones of its name and type
+    async fn create_record(&self, zone: &ZoneName, record: &ProviderRecord) -> Result<()> {
+        let (Some(data), Some(r#type)) = (
+            rdata(&record.content),
+            wire_type(&record.content.record_type()),
+        ) else {
+            return Err(Error::InvalidRecord {
+                record: record.name.clone(),
+                reason: RecordError::MissingContent(
+                    record.content.record_type().as_str().to_string(),
+                ),
+            });
+        };
+        self.check_zone(zone).await?;
+        let mut msg = Message::update(zone);
+        msg.add_record(&record.fqdn(zone), r#type, ttl(&record.ttl), data);
+        self.update(zone, &msg).await
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, None, force).await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, Some(record_type), force)
+            .await
+    }
+
+    fn request_count(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    fn zone_concurrency(&self) -> usize {
+        self.zone_concurrency
+    }
+}
+
+// A record of a synced type as the provider lists it
+fn existing(record: dns_wire::Record) -> Option<ExistingRecord> {
+    let content = match (record.r#type, record.data) {
+        (dns_wire::TYPE_A, RData::A(ip)) => RecordContent::A(ip),
+        (dns_wire::TYPE_AAAA, RData::AAAA(ip)) => RecordContent::AAAA(ip),
+        (dns_wire::TYPE_CNAME, RData::Name(target)) => {
+            RecordContent::CNAME(target.to_ascii_lowercase())
+        }
+        _ => return None,
+    };
+    Some(ExistingRecord {
+        id: None,
+        record: ProviderRecord {
+            name: record.name.to_ascii_lowercase(),
+            content,
+            comment: None,
+            op: RecordOp::default(),
+            ttl: TTL::Value(record.ttl),
+            params: vec![],
+        },
+    })
+}
+
+fn rdata(content: &RecordContent) -> Option<RData> {
+    match content {
+        RecordContent::A(ip) => Some(RData::A(*ip)),
+        RecordContent::AAAA(ip) => Some(RData::AAAA(*ip)),
+        RecordContent::CNAME(target) => Some(RData::Name(target.clone())),
+        _ => None,
+    }
+}
+
+fn wire_type(record_type: &RecordType) -> Option<u16> {
+    match record_type {
+        RecordType::A => Some(dns_wire::TYPE_A),
+        RecordType::AAAA => Some(dns_wire::TYPE_AAAA),
+        RecordType::CNAME => Some(dns_wire::TYPE_CNAME),
+        RecordType::None => None,
+    }
+}
+
+fn ttl(ttl: &TTL) -> u32 {
+    match ttl {
+        TTL::Auto => DEFAULT_TTL,
+        TTL::Value(v) => *v,
+    }
+}
+
+// The records of a name and type
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct RecordSet {
+    pub name: String,
+    // Type on the wire
+    pub r#type: u16,
+    pub ttl: u32,
+    pub values: Vec<RecordContent>,
+}
+
+impl RecordSet {
+    fn record_type(&self) -> RecordType {
+        self.values
+            .first()
+            .map(RecordContent::record_type)
+            .unwrap_or(RecordType::None)
+    }
+
+    // Same name, type, TTL and values, whatever their order
+    fn serves(&self, desired: &RecordSet) -> bool {
+        self.name.eq_ignore_ascii_case(&desired.name)
+            && self.r#type == desired.r#type
+            && self.ttl == desired.ttl
+            && self.values.len() == desired.values.len()
+            && desired.values.iter().all(|v| self.values.contains(v))
+    }
+}
+
+// Updates bringing the live record sets of a zone to the desired ones
+#[derive(Debug, Default)]
+pub(super) struct ZoneDiff {
+    // Desired record sets served as they are
+    pub unchanged: Vec<RecordSet>,
+    // Desired record sets to serve instead of the live ones
+    pub replaced: Vec<RecordSet>,
+    // Live record sets no record is desired for
+    pub deleted: Vec<RecordSet>,
+}
+
+impl ZoneDiff {
+    pub fn new(changes: &[RecordChange]) -> Self {
+        let mut ret = ZoneDiff::default();
+        let desired = group(changes.iter().map(|c| &c.after));
+        // Records under a name with several desired records are in the before
+        // of each change, they count once
+        let mut before = vec![];
+        for existing in changes.iter().flat_map(|c| c.before.iter()) {
+            if !before.contains(&&existing.record) {
+                before.push(&existing.record);
+            }
+        }
+        let live = group(before.into_iter());
+
+        let same = |a: &RecordSet, b: &RecordSet| {
+            a.name.eq_ignore_ascii_case(&b.name) && a.r#type == b.r#type
+        };
+        for set in desired {
+            match live.iter().find(|l| same(l, &set)) {
+                Some(l) if l.serves(&set) => ret.unchanged.push(set),
+                _ => ret.replaced.push(set),
+            }
+        }
+        for set in live {
+            let kept = ret
+                .unchanged
+                .iter()
+                .chain(ret.replaced.iter())
+                .any(|d| same(d, &set));
+            if !kept {
+                ret.deleted.push(set);
+            }
+        }
+        ret
+    }
+}
+
+// Records grouped into record sets by name and type, in the order of their
+// first record. The TTL of a set is the one of its first record.
+fn group<'a>(records: impl Iterator<Item = &'a ProviderRecord>) -> Vec<RecordSet> {
+    let mut ret: Vec<RecordSet> = vec![];
+    for record in records {
+        let Some(r#type) = wire_type(&record.content.record_type()) else {
+            continue;
+        };
+        match ret
+            .iter_mut()
+            .find(|s| s.name.eq_ignore_ascii_case(&record.name) && s.r#type == r#type)
+        {
+            Some(set) => {
+                if !set.values.contains(&record.content) {
+                    set.values.push(record.content.clone());
+                }
+            }
+            None => ret.push(RecordSet {
+                name: record.name.to_ascii_lowercase(),
+                r#type,
+                ttl: ttl(&record.ttl),
+                values: vec![record.content.clone()],
+            }),
+        }
+    }
+    ret
+}
@@ -0,0 +1,418 @@
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+use super::rfc2136::*;
+use crate::error::Error;
+use crate::provider::AuthParams;
+use crate::provider::BackendRecords;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordStatus;
+use crate::provider::ZoneRecords;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::Param;
+use crate::types::PublicIp;
+use crate::wrapper::dns_wire;
+use crate::wrapper::dns_wire::Message;
+use crate::wrapper::dns_wire::RData;
+use crate::wrapper::dns_wire::Record;
+use crate::wrapper::dns_wire::Soa;
+use crate::wrapper::dns_wire::TsigAlgorithm;
+use crate::wrapper::dns_wire::TsigKey;
+use crate::wrapper::dns_wire::TsigVerifier;
+
+const SECRET: &str = "c2VjcmV0IG9mIHRoZSB0ZXN0IGtleQ==";
+
+fn key() -> TsigKey {
+    TsigKey::new("ddns-key", TsigAlgorithm::HmacSha256, SECRET).unwrap()
+}
+
+fn record(name: &str, r#type: u16, ttl: u32, data: RData) -> Record {
+    Record {
+        name: name.to_string(),
+        r#type,
+        class: dns_wire::CLASS_IN,
+        ttl,
+        data,
+    }
+}
+
+// An authoritative server of example.org taking signed updates, like BIND
+// with an `update-policy` for the key
+struct Server {
+    key: TsigKey,
+    zone: Mutex<Vec<Record>>,
+    updates: Mutex<Vec<Message>>,
+}
+
+impl Server {
+    async fn start() -> (Arc<Server>, SocketAddr) {
+        let server = Arc::new(Server {
+            key: key(),
+            zone: Mutex::new(vec![
+                record(
+                    "example.org",
+                    dns_wire::TYPE_SOA,
+                    3600,
+                    RData::Soa(Soa {
+                        mname: "ns1.example.org".to_string(),
+                        rname: "hostmaster.example.org".to_string(),
+                        serial: 7,
+                        refresh: 7200,
+                        retry: 900,
+                        expire: 1209600,
+                        minimum: 300,
+                    }),
+                ),
+                record(
+                    "example.org",
+                    dns_wire::TYPE_NS,
+                    3600,
+                    RData::Name("ns1.example.org".to_string()),
+                ),
+                record(
+                    "home.example.org",
+                    dns_wire::TYPE_A,
+                    300,
+                    RData::A(Ipv4Addr::new(1, 1, 1, 1)),
+                ),
+                record(
+                    "home.example.org",
+                    dns_wire::TYPE_AAAA,
+                    300,
+                    RData::AAAA("2001:db8::1".parse().unwrap()),
+                ),
+                record(
+                    "www.example.org",
+                    dns_wire::TYPE_CNAME,
+                    300,
+                    RData::Name("home.example.org".to_string()),
+                ),
+            ]),
+            updates: Mutex::new(vec![]),
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ret = Arc::clone(&server);
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let server = Arc::clone(&server);
+                tokio::spawn(async move {
+                    while let Ok(packet) = dns_wire::read_message(&mut stream).await {
+                        for resp in server.handle(&packet) {
+                            dns_wire::write_message(&mut stream, &resp).await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+        (ret, addr)
+    }
+
+    // Signed responses to a request, several for a zone transfer
+    fn handle(&self, packet: &[u8]) -> Vec<Vec<u8>> {
+        let now = dns_wire::unix_now();
+        let mut verifier = TsigVerifier::new(&self.key, vec![]);
+        let req = match verifier.verify(packet, now) {
+            Ok(req) => req,
+            Err(_) => {
+                // Unsigned, or signed with another key
+                let mut resp = Message::decode(packet).unwrap();
+                resp.response = true;
+                resp.rcode = dns_wire::RCODE_NOTAUTH;
+                resp.additionals.clear();
+                return vec![resp.encode().unwrap()];
+            }
+        };
+        let mut resp = req.clone();
+        resp.response = true;
+        resp.authoritative = true;
+
+        let question = req.questions[0].clone();
+        let in_zone = |name: &str| name == "example.org" || name.ends_with(".example.org");
+        if !in_zone(&question.name.to_ascii_lowercase()) {
+            resp.authoritative = false;
+            resp.rcode = dns_wire::RCODE_REFUSED;
+        } else if req.opcode == dns_wire::OPCODE_UPDATE {
+            let mut zone = self.zone.lock().unwrap();
+            for update in req.authorities.iter() {
+                match update.class {
+                    dns_wire::CLASS_ANY => zone.retain(|r| {
+                        !(r.name.eq_ignore_ascii_case(&update.name) && r.r#type == update.r#type)
+                    }),
+                    _ => zone.push(update.clone()),
+                }
+            }
+            self.updates.lock().unwrap().push(req.clone());
+            resp.authorities.clear();
+        } else if question.r#type == dns_wire::TYPE_AXFR {
+            // The records in two messages, the SOA record opening and closing
+            // the transfer
+            let zone = self.zone.lock().unwrap().clone();
+            let mut first = resp.clone();
+            first.answers = zone[..2].to_vec();
+            let mut second = resp;
+            second.answers = zone[2..].to_vec();
+            second.answers.push(zone[0].clone());
+            let (first, mac) = self
+                .key
+                .sign_response(&first, verifier.mac(), true, now)
+                .unwrap();
+            let (second, _) = self.key.sign_response(&second, &mac, false, now).unwrap();
+            return vec![first, second];
+        } else {
+            resp.answers = self
+                .zone
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|r| r.name.eq_ignore_ascii_case(&question.name))
+                // A CNAME answers every type
+                .filter(|r| r.r#type == question.r#type || r.r#type == dns_wire::TYPE_CNAME)
+                .cloned()
+                .collect();
+        }
+        let (packet, _) = self
+            .key
+            .sign_response(&resp, verifier.mac(), true, now)
+            .unwrap();
+        vec![packet]
+    }
+}
+
+fn record_of(name: &str, content: RecordContent) -> ProviderRecord {
+    ProviderRecord {
+        name: name.to_string(),
+        content,
+        comment: None,
+        ttl: TTL::Auto,
+        op: RecordOp::Create,
+        params: vec![],
+    }
+}
+
+fn provider(addr: SocketAddr, key: Option<TsigKey>) -> Rfc2136 {
+    Rfc2136::new(dns_wire::Client::new(
+        &addr.to_string(),
+        key,
+        Duration::from_secs(5),
+    ))
+}
+
+#[tokio::test]
+async fn test_rfc2136_list() {
+    let (_, addr) = Server::start().await;
+    let provider = provider(addr, Some(key()));
+    let zone = "example.org".to_string();
+
+    // Queries of the names, the CNAME answering the A query is not taken twice
+    let names = vec![
+        "www.example.org".to_string(),
+        "HOME.example.org".to_string(),
+    ];
+    let records = provider.list_named(&zone, &names).await.unwrap();
+    let records = records.into_iter().map(|r| r.record).collect::<Vec<_>>();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0].name, "www.example.org");
+    assert_eq!(
+        records[0].content,
+        RecordContent::CNAME("home.example.org".to_string())
+    );
+    assert_eq!(
+        records[1].content,
+        RecordContent::A(Ipv4Addr::new(1, 1, 1, 1))
+    );
+    assert_eq!(records[1].ttl, TTL::Value(300));
+    assert_eq!(records[2].content.record_type(), RecordType::AAAA);
+
+    // A zone transfer of two signed messages, SOA and NS left out
+    let records = provider.list(&zone).await.unwrap();
+    assert_eq!(records.len(), 3);
+    assert!(records.iter().all(|r| r.id.is_none()));
+
+    let err = provider
+        .list_named(&"example.com".to_string(), &names)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::ZoneNotFound { .. }));
+
+    let info = provider.zone_info(&zone).await.unwrap().unwrap();
+    assert_eq!(info.status.as_deref(), Some("serial 7"));
+    assert!(provider.request_count() >= 9);
+}
+
+#[tokio::test]
+async fn test_rfc2136_sync() {
+    let (server, addr) = Server::start().await;
+    let provider = provider(addr, Some(key()));
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+    let mut records = BackendRecords::default();
+    records.zones.insert(
+        "example.org".to_string(),
+        ZoneRecords {
+            records: vec![record_of("home", RecordContent::Unassigned(RecordType::A))],
+        },
+    );
+
+    // home A is replaced, home AAAA is no longer desired and deleted
+    let outcome = provider
+        .sync(&records, &public_ip, &CancellationToken::new())
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    let statuses = outcome.zones[0]
+        .records
+        .iter()
+        .map(|r| (r.r#type.as_str(), r.status))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        vec![
+            ("A", RecordStatus::Updated),
+            ("AAAA", RecordStatus::Deleted)
+        ]
+    );
+    {
+        let updates = server.updates.lock().unwrap();
+        assert_eq!(updates.len(), 1);
+        let update = &updates[0];
+        assert_eq!(update.questions[0].name, "example.org");
+        assert_eq!(update.questions[0].r#type, dns_wire::TYPE_SOA);
+        let changes = update
+            .authorities
+            .iter()
+            .map(|r| (r.r#type, r.class, r.data.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (dns_wire::TYPE_AAAA, dns_wire::CLASS_ANY, RData::Raw(vec![])),
+                (dns_wire::TYPE_A, dns_wire::CLASS_ANY, RData::Raw(vec![])),
+                (
+                    dns_wire::TYPE_A,
+                    dns_wire::CLASS_IN,
+                    RData::A(Ipv4Addr::new(2, 2, 2, 2))
+                ),
+            ]
+        );
+        assert_eq!(update.authorities[2].ttl, DEFAULT_TTL);
+    }
+
+    // The zone now serves what is desired, no update is sent
+    let outcome = provider
+        .sync(&records, &public_ip, &CancellationToken::new())
+        .await;
+    assert!(outcome.errors.is_empty());
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Unchanged);
+    assert_eq!(server.updates.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_rfc2136_errors() {
+    let (server, addr) = Server::start().await;
+    let zone = "example.org".to_string();
+
+    // Records carry no owner marker, deleting them takes force
+    let provider = provider(addr, Some(key()));
+    let err = provider
+        .delete_record(&zone, "www.example.org", RecordType::CNAME, false)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("force"));
+    let deleted = provider
+        .delete_record(&zone, "www.example.org", RecordType::CNAME, true)
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert_eq!(server.updates.lock().unwrap().len(), 1);
+
+    // Messages signed with another key, or not at all, are refused
+    let other = TsigKey::new("other-key", TsigAlgorithm::HmacSha256, SECRET).unwrap();
+    for key in [Some(other), None] {
+        let provider = self::provider(addr, key);
+        let err = provider
+            .verify(std::slice::from_ref(&zone))
+            .await
+            .unwrap_err();
+        assert!(err.is_auth(), "{}", err);
+        let err = provider
+            .create_record(
+                &zone,
+                &record_of("new", RecordContent::A(Ipv4Addr::new(3, 3, 3, 3))),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::ZoneNotFound { .. }), "{}", err);
+    }
+    provider.verify(std::slice::from_ref(&zone)).await.unwrap();
+    assert!(
+        provider
+            .verify(&["example.com".to_string()])
+            .await
+            .unwrap_err()
+            .is_auth()
+    );
+}
+
+#[test]
+fn test_rfc2136_factory() {
+    let params = |params: &[(&str, &str)]| {
+        params
+            .iter()
+            .map(|(n, v)| Param::new(n.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+    };
+    let auth = |params: &[(&str, &str)]| AuthParams {
+        method: "tsig".to_string(),
+        params: params
+            .iter()
+            .map(|(n, v)| Param::new(n.to_string(), v.to_string()))
+            .collect(),
+    };
+    let factory = Rfc2136Factory;
+    let key = auth(&[("key_name", "ddns-key"), ("secret", SECRET)]);
+
+    let server = params(&[("server", "ns1.example.org"), ("timeout", "3s")]);
+    assert!(factory.create("bind", &key, &server).is_ok());
+    assert!(
+        factory
+            .create("bind", &AuthParams::default(), &server)
+            .is_ok()
+    );
+
+    let err = factory.create("bind", &key, &params(&[])).err().unwrap();
+    assert!(err.to_string().contains("requires a server"));
+    let err = factory
+        .create("bind", &key, &params(&[("server", "ns1"), ("port", "53")]))
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("unknown param port"));
+    let err = factory
+        .create(
+            "bind",
+            &auth(&[
+                ("key_name", "k"),
+                ("secret", SECRET),
+                ("algorithm", "hmac-md5"),
+            ]),
+            &server,
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("unsupported TSIG algorithm"));
+    let err = factory
+        .create("bind", &auth(&[("key_name", "k")]), &server)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("key_name and secret"));
+}
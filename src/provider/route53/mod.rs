@@ -0,0 +1,9 @@
+#[allow(clippy::module_inception)]
+mod route53;
+mod xml;
+pub use route53::Route53;
+pub use route53::Route53Auth;
+pub use route53::Route53Factory;
+
+#[cfg(test)]
+mod unit_test;
@@ -0,0 +1,922 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio_util::sync::CancellationToken;
+
+use super::xml;
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AuthMethod;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ChangeSet;
+use crate::provider::CredentialCheck;
+use crate::provider::DEFAULT_ZONE_CONCURRENCY;
+use crate::provider::ExistingRecord;
+use crate::provider::IdCache;
+use crate::provider::ParamList;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordChange;
+use crate::provider::ZoneAccess;
+use crate::provider::ZoneInfo;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::ZoneName;
+use crate::wrapper::aws;
+use crate::wrapper::http;
+
+const API: &str = "https://route53.amazonaws.com/2013-04-01";
+const XMLNS: &str = "https://route53.amazonaws.com/doc/2013-04-01/";
+// Route 53 is a global service signed for us-east-1
+const REGION: &str = "us-east-1";
+// Route 53 has no automatic TTL
+pub const DEFAULT_TTL: u32 = 300;
+// Ambient credentials are temporary, they are looked up again after this
+const AMBIENT_REFRESH: Duration = Duration::from_secs(600);
+
+// Permissions a credential needs on a hosted zone to sync its records
+const ZONE_PERMISSIONS: [&str; 3] = [
+    "route53:ListHostedZonesByName",
+    "route53:ListResourceRecordSets",
+    "route53:ChangeResourceRecordSets",
+];
+
+#[derive(Debug, Clone)]
+pub enum Route53Auth {
+    // An access key of an IAM user, with the session token of temporary ones
+    AccessKey(aws::Credentials),
+    // Credentials of the environment, ECS task role or EC2 instance profile
+    Ambient,
+}
+
+pub struct Route53 {
+    cli: Cli,
+    auth: Route53Auth,
+    // Hosted zone ids by zone name, trusted until Route 53 answers 404 for one
+    zone_ids: Mutex<HashMap<ZoneName, String>>,
+    zone_concurrency: usize,
+}
+
+// Route 53 providers from an `access_key` authentication, or the ambient AWS
+// credentials without one. The only param of the provider entry is
+// `zone_concurrency`.
+pub struct Route53Factory;
+
+impl ProviderFactory for Route53Factory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        let mut provider = Route53::new(Route53Auth::new_with_params(auth)?)?;
+        if let Some(param) = params.iter().find(|p| p.name == "zone_concurrency") {
+            let concurrency = param.value.parse::<usize>().map_err(|_| {
+                Error::ParseError(format!(
+                    "{}: invalid zone_concurrency {}",
+                    name, param.value
+                ))
+            })?;
+            provider = provider.with_zone_concurrency(concurrency);
+        }
+        Ok(Box::new(provider))
+    }
+}
+
+impl Route53 {
+    pub fn new(auth: Route53Auth) -> Result<Self> {
+        let transport = Arc::new(http::ReqwestTransport::new()?);
+        Ok(Self::with_transport(auth, transport))
+    }
+
+    // Provider sending its API requests through `transport`, like a
+    // `MemoryTransport` answering canned Route 53 responses in tests
+    pub fn with_transport(auth: Route53Auth, transport: Arc<dyn http::HttpTransport>) -> Self {
+        Self {
+            cli: Cli::new(auth.clone(), transport),
+            auth,
+            zone_ids: Mutex::new(HashMap::new()),
+            zone_concurrency: DEFAULT_ZONE_CONCURRENCY,
+        }
+    }
+
+    // Sync up to `concurrency` zones at once, one at a time with 1
+    pub fn with_zone_concurrency(mut self, concurrency: usize) -> Self {
+        self.zone_concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "route53",
+            description: "AWS Route 53 hosted zones, public or private",
+            record_types: vec![RecordType::A, RecordType::AAAA, RecordType::CNAME],
+            auth_methods: vec![
+                AuthMethod {
+                    name: "access_key",
+                    params: vec!["access_key_id", "secret_access_key", "session_token"],
+                },
+                AuthMethod {
+                    name: "ambient",
+                    params: vec![],
+                },
+            ],
+            params: vec![],
+            operations: vec![
+                "list",
+                "apply",
+                "dry_run",
+                "verify",
+                "check_credentials",
+                "list_zones",
+                "list_zone_info",
+                "zone_info",
+                "list_records",
+                "list_zone_records",
+                "get_record",
+                "create_record",
+                "delete_record",
+                "delete_records",
+            ],
+        }
+    }
+
+    // Records of a zone `keep` accepts, the others are dropped page by page
+    async fn list_where<F>(&self, zone: &ZoneName, keep: F) -> Result<Vec<ExistingRecord>>
+    where
+        F: Fn(&RecordSet) -> bool + Send + Sync,
+    {
+        let zone_id = self.zone_id(zone).await?;
+        let sets = match self.cli.record_sets(&zone_id, &keep).await {
+            // The zone was recreated since its id was cached
+            Err(Error::HttpStatus { status: 404 }) if self.forget_zone_id(zone) => {
+                let zone_id = self.zone_id(zone).await?;
+                self.cli.record_sets(&zone_id, &keep).await?
+            }
+            result => result?,
+        };
+        Ok(sets.into_iter().flat_map(RecordSet::records).collect())
+    }
+
+    // Delete the record sets named `name`, of `record_type` when given. Route
+    // 53 keeps no comment to carry the owner marker, so it takes `force`.
+    async fn delete_matching(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: Option<RecordType>,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let zone_id = self.zone_id(zone).await?;
+        let sets = self
+            .cli
+            .record_sets(&zone_id, &|s: &RecordSet| {
+                s.name.eq_ignore_ascii_case(name)
+                    && record_type.as_ref().is_none_or(|t| s.r#type == t.as_str())
+            })
+            .await?;
+        if sets.is_empty() {
+            return Ok(vec![]);
+        }
+        if !force {
+            return Err(Error::Provider(format!(
+                "{} may not be managed by dns-syncer, route53 records carry no owner marker, \
+                 force the deletion to remove it anyway",
+                name
+            )));
+        }
+
+        let changes = sets
+            .iter()
+            .map(|s| Change {
+                action: "DELETE",
+                set: s.clone(),
+            })
+            .collect::<Vec<_>>();
+        self.cli.change(&zone_id, &changes).await?;
+        Ok(sets
+            .into_iter()
+            .flat_map(RecordSet::records)
+            .map(|r| r.record)
+            .collect())
+    }
+
+    // Id of a hosted zone, looked up once and then taken from the cache
+    async fn zone_id(&self, zone: &ZoneName) -> Result<String> {
+        if let Some(id) = self.zone_ids.lock().unwrap().get(zone) {
+            return Ok(id.clone());
+        }
+        let hosted_zone = self
+            .cli
+            .hosted_zone(zone)
+            .await?
+            .ok_or(Error::ZoneNotFound {
+                zone: zone.to_string(),
+            })?;
+        log::debug!(zone = zone; "zone {} has id {}", zone, hosted_zone.id);
+        self.zone_ids
+            .lock()
+            .unwrap()
+            .insert(zone.clone(), hosted_zone.id.clone());
+        Ok(hosted_zone.id)
+    }
+
+    // Drop the cached id of a zone, telling whether there was one
+    fn forget_zone_id(&self, zone: &ZoneName) -> bool {
+        let forgotten = self.zone_ids.lock().unwrap().remove(zone).is_some();
+        if forgotten {
+            log::debug!(zone = zone; "cached id of zone {} is stale", zone);
+        }
+        forgotten
+    }
+}
+
+#[async_trait]
+impl Provider for Route53 {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        self.list_where(zone, |_| true).await
+    }
+
+    async fn list_named(&self, zone: &ZoneName, names: &[String]) -> Result<Vec<ExistingRecord>> {
+        self.list_where(zone, |s| {
+            names.iter().any(|n| n.eq_ignore_ascii_case(&s.name))
+        })
+        .await
+    }
+
+    // All changes of a zone go in one change batch, which Route 53 applies as
+    // a whole. The records of a name and type form one record set, UPSERT
+    // replacing it; live sets of the desired names with another type are
+    // deleted. Sets already served as desired are left out, so a steady state
+    // makes no write at all.
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let zone = &changes.zone;
+        let diff = ZoneDiff::new(&changes.changes);
+        for set in diff.unchanged.iter() {
+            log::debug!(
+                zone = zone, record = set.name, outcome = "unchanged";
+                "record {} {} already up to date", set.name, set.r#type
+            );
+        }
+        if diff.changes.is_empty() {
+            return Ok(());
+        }
+        let zone_id = self.zone_id(zone).await?;
+        if cancel.is_cancelled() {
+            log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+            return Err(Error::Cancelled);
+        }
+
+        if let Err(e) = self.cli.change(&zone_id, &diff.changes).await {
+            if matches!(e, Error::HttpStatus { status: 404 }) {
+                self.forget_zone_id(zone);
+            }
+            log::error!(
+                zone = zone, outcome = "failed";
+                "changes of zone {} failed: {}", zone, e
+            );
+            return Err(e.context(&format!("zone {}", zone)));
+        }
+        for change in diff.changes.iter() {
+            let set = &change.set;
+            match change.action {
+                "DELETE" => log::info!(
+                    zone = zone, record = set.name, outcome = "deleted";
+                    "record {} {} deleted", set.name, set.r#type
+                ),
+                _ => log::info!(
+                    zone = zone, record = set.name, content = set.values.join(","),
+                    outcome = "updated";
+                    "record {} set to {}", set.name, set.values.join(", ")
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    async fn verify(&self, zones: &[ZoneName]) -> Result<()> {
+        let desc = self.auth.describe();
+        for zone in zones {
+            let hosted_zone = self
+                .cli
+                .hosted_zone(zone)
+                .await
+                .map_err(|e| rejected(&desc, e))?;
+            if hosted_zone.is_none() {
+                return Err(Error::Auth {
+                    provider: String::new(),
+                    reason: format!(
+                        "{} cannot access hosted zone {}, it needs the {} permissions for it",
+                        desc,
+                        zone,
+                        ZONE_PERMISSIONS.join(", ")
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // IAM policies cannot be read with the permissions syncing needs, only
+    // the zones the credential cannot see are reported
+    async fn check_credentials(&self, zones: &[ZoneName]) -> Result<CredentialCheck> {
+        let desc = self.auth.describe();
+        let mut ret = CredentialCheck {
+            credential: desc.clone(),
+            id: match &self.auth {
+                Route53Auth::AccessKey(credentials) => Some(credentials.access_key_id.clone()),
+                Route53Auth::Ambient => None,
+            },
+            ..Default::default()
+        };
+        self.cli
+            .hosted_zones()
+            .await
+            .map_err(|e| rejected(&desc, e))?;
+
+        for zone in zones {
+            let missing = match self.cli.hosted_zone(zone).await? {
+                Some(_) => vec![],
+                None => ZONE_PERMISSIONS.iter().map(|p| p.to_string()).collect(),
+            };
+            ret.zones.push(ZoneAccess {
+                zone: zone.clone(),
+                permissions: None,
+                missing,
+            });
+        }
+        Ok(ret)
+    }
+
+    async fn list_zones(&self) -> Result<Vec<ZoneName>> {
+        let zones = self.cli.hosted_zones().await?;
+        Ok(zones.into_iter().map(|z| z.name).collect())
+    }
+
+    async fn list_zone_info(&self) -> Result<Vec<ZoneInfo>> {
+        let zones = self.cli.hosted_zones().await?;
+        Ok(zones.into_iter().map(ZoneInfo::from).collect())
+    }
+
+    async fn zone_info(&self, zone: &ZoneName) -> Result<Option<ZoneInfo>> {
+        let hosted_zone = self.cli.hosted_zone(zone).await?;
+        Ok(hosted_zone.map(ZoneInfo::from))
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, None, force).await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, Some(record_type), force)
+            .await
+    }
+
+    fn request_count(&self) -> u64 {
+        self.cli.request_count()
+    }
+
+    fn zone_concurrency(&self) -> usize {
+        self.zone_concurrency
+    }
+
+    fn id_cache(&self) -> IdCache {
+        IdCache {
+            zones: self.zone_ids.lock().unwrap().clone(),
+        }
+    }
+
+    fn load_id_cache(&self, cache: IdCache) {
+        self.zone_ids.lock().unwrap().extend(cache.zones);
+    }
+}
+
+// A credential check failing for another reason than the network is the
+// credential being rejected
+fn rejected(desc: &str, e: Error) -> Error {
+    if e.is_retryable() || matches!(e, Error::Auth { .. }) {
+        return e;
+    }
+    Error::Auth {
+        provider: String::new(),
+        reason: format!("{} is rejected: {}", desc, e),
+    }
+}
+
+///////////////////////////////////////////////////////////
+// Client
+///////////////////////////////////////////////////////////
+impl Route53Auth {
+    // Methods: `access_key` with `access_key_id`, `secret_access_key` and
+    // optionally `session_token`, `ambient` or none for the ambient
+    // credentials
+    pub fn new_with_params(auth: &AuthParams) -> Result<Self> {
+        let get = |name: &str| auth.get(name).map(String::from);
+        match auth.method.as_str() {
+            "" | "ambient" => Ok(Route53Auth::Ambient),
+            "access_key" => match (get("access_key_id"), get("secret_access_key")) {
+                (Some(access_key_id), Some(secret_access_key)) => {
+                    Ok(Route53Auth::AccessKey(aws::Credentials {
+                        access_key_id,
+                        secret_access_key,
+                        session_token: get("session_token"),
+                    }))
+                }
+                _ => Err(Error::Provider(
+                    "route53 access_key auth requires both access_key_id and secret_access_key"
+                        .into(),
+                )),
+            },
+            method => Err(Error::Provider(format!(
+                "{}: unsupported authentication method for route53 provider",
+                method
+            ))),
+        }
+    }
+
+    // Human readable name of the credential that never leaks the secret
+    pub fn describe(&self) -> String {
+        match self {
+            Route53Auth::AccessKey(credentials) => {
+                format!("access key {}", credentials.access_key_id)
+            }
+            Route53Auth::Ambient => "ambient aws credentials".to_string(),
+        }
+    }
+}
+
+pub(super) struct Cli {
+    cli: http::Client,
+    auth: Route53Auth,
+    // Ambient credentials with when they were looked up
+    ambient: Mutex<Option<(aws::Credentials, Instant)>>,
+    // Requests sent so far, whatever came of them
+    requests: AtomicU64,
+}
+
+impl Cli {
+    pub fn new(auth: Route53Auth, transport: Arc<dyn http::HttpTransport>) -> Self {
+        Self {
+            cli: http::Client::with_transport(transport),
+            auth,
+            ambient: Mutex::new(None),
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn request_count(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    async fn credentials(&self) -> Result<aws::Credentials> {
+        if let Route53Auth::AccessKey(credentials) = &self.auth {
+            return Ok(credentials.clone());
+        }
+        if let Some((credentials, at)) = self.ambient.lock().unwrap().as_ref()
+            && at.elapsed() < AMBIENT_REFRESH
+        {
+            return Ok(credentials.clone());
+        }
+        let credentials = aws::Credentials::ambient().await.map_err(|e| Error::Auth {
+            provider: String::new(),
+            reason: e.to_string(),
+        })?;
+        *self.ambient.lock().unwrap() = Some((credentials.clone(), Instant::now()));
+        Ok(credentials)
+    }
+
+    // Send a signed request to `path` of the API and return the body of the
+    // answer, its error turned into ours
+    async fn send(&self, method: http::Method, path: &str, body: Option<String>) -> Result<String> {
+        let url = format!("{}{}", API, path);
+        let credentials = self.credentials().await?;
+        let mut headers = vec![];
+        if body.is_some() {
+            headers.push(http::Header::new(
+                http::HeaderKey::ContentType,
+                "text/xml".to_string(),
+            ));
+        }
+        let signed = headers
+            .iter()
+            .map(|h| (h.name().to_string(), h.value().to_string()))
+            .collect::<Vec<_>>();
+        let signer = aws::SigV4 {
+            credentials: &credentials,
+            region: REGION,
+            service: "route53",
+        };
+        let payload = body.as_deref().unwrap_or_default();
+        headers.extend(signer.sign(
+            method.as_str(),
+            &url,
+            &signed,
+            payload.as_bytes(),
+            Utc::now(),
+        )?);
+
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let resp = match body {
+            Some(body) => self.cli.post(&url, Some(headers), body).await?,
+            None => self.cli.get(&url, Some(headers)).await?,
+        };
+        if resp.status == 200 {
+            return Ok(resp.body);
+        }
+        Err(api_error(resp))
+    }
+}
+
+// Error of a failed answer: the code and message of its `ErrorResponse`
+fn api_error(resp: http::Response) -> Error {
+    let code = xml::text(&resp.body, "Code").unwrap_or_default();
+    let message = xml::text(&resp.body, "Message").unwrap_or_default();
+    match (resp.status, code.as_str()) {
+        (_, "Throttling" | "PriorRequestNotComplete") => Error::RateLimited {
+            retry_after: resp.retry_after,
+        },
+        (401 | 403, _) => Error::Auth {
+            provider: String::new(),
+            reason: format!("{}: {}", code, message),
+        },
+        (400, _) if !code.is_empty() => {
+            Error::Provider(format!("route53 api call failed: {}: {}", code, message))
+        }
+        (status, _) => Error::from_status(status, resp.retry_after),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct HostedZone {
+    pub id: String,
+    pub name: ZoneName,
+    pub private: bool,
+}
+
+impl HostedZone {
+    fn parse(xml: &str) -> Option<Self> {
+        let id = xml::text(xml, "Id")?;
+        Some(Self {
+            id: id.trim_start_matches("/hostedzone/").to_string(),
+            name: unescape_name(&xml::text(xml, "Name")?),
+            private: xml::text(xml, "PrivateZone").as_deref() == Some("true"),
+        })
+    }
+}
+
+impl From<HostedZone> for ZoneInfo {
+    fn from(zone: HostedZone) -> Self {
+        Self {
+            name: zone.name,
+            id: Some(zone.id),
+            plan: None,
+            status: Some(if zone.private { "private" } else { "public" }.to_string()),
+        }
+    }
+}
+
+// Names come with a trailing dot and some characters, like `*`, as `\ddd`
+// octal escapes
+fn unescape_name(name: &str) -> String {
+    let name = name.trim_end_matches('.');
+    let mut ret = String::new();
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+        let digits = chars.clone().take(3).collect::<String>();
+        match u8::from_str_radix(&digits, 8) {
+            Ok(b) if digits.len() == 3 => {
+                ret.push(b as char);
+                chars.nth(2);
+            }
+            _ => ret.push(c),
+        }
+    }
+    ret.to_ascii_lowercase()
+}
+
+// Route 53 hosted zone API
+impl Cli {
+    // The hosted zone named `name`, `None` when the credentials see none
+    pub async fn hosted_zone(&self, name: &str) -> Result<Option<HostedZone>> {
+        let path = format!(
+            "/hostedzonesbyname?dnsname={}&maxitems=10",
+            aws::uri_encode(name, true)
+        );
+        let body = self.send(http::Method::Get, &path, None).await?;
+        let zones = xml::elements(&body, "HostedZone")
+            .into_iter()
+            .filter_map(HostedZone::parse)
+            .filter(|z| z.name.eq_ignore_ascii_case(name))
+            .collect::<Vec<_>>();
+        match zones.len() {
+            0 => Ok(None),
+            1 => Ok(zones.into_iter().next()),
+            _ => Err(Error::ParseError(format!(
+                "multiple hosted zones found: {}",
+                name
+            ))),
+        }
+    }
+
+    pub async fn hosted_zones(&self) -> Result<Vec<HostedZone>> {
+        let mut ret = vec![];
+        let mut marker: Option<String> = None;
+        loop {
+            let path = match marker.as_ref() {
+                Some(marker) => format!(
+                    "/hostedzone?maxitems=100&marker={}",
+                    aws::uri_encode(marker, true)
+                ),
+                None => "/hostedzone?maxitems=100".to_string(),
+            };
+            let body = self.send(http::Method::Get, &path, None).await?;
+            ret.extend(
+                xml::elements(&body, "HostedZone")
+                    .into_iter()
+                    .filter_map(HostedZone::parse),
+            );
+            marker = match xml::text(&body, "IsTruncated").as_deref() {
+                Some("true") => xml::text(&body, "NextMarker"),
+                _ => None,
+            };
+            if marker.is_none() {
+                break;
+            }
+        }
+        Ok(ret)
+    }
+}
+
+// A record set, the records of a name and type
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct RecordSet {
+    pub name: String,
+    pub r#type: String,
+    pub ttl: u32,
+    pub values: Vec<String>,
+}
+
+impl RecordSet {
+    // Alias, weighted, latency or other routed record sets are not synced and
+    // left out
+    fn parse(xml: &str) -> Option<Self> {
+        if xml::element(xml, "AliasTarget").is_some()
+            || xml::element(xml, "SetIdentifier").is_some()
+        {
+            return None;
+        }
+        Some(Self {
+            name: unescape_name(&xml::text(xml, "Name")?),
+            r#type: xml::text(xml, "Type")?,
+            ttl: xml::text(xml, "TTL")?.parse().ok()?,
+            values: xml::elements(xml, "ResourceRecord")
+                .into_iter()
+                .filter_map(|r| xml::text(r, "Value"))
+                .collect(),
+        })
+    }
+
+    // A record per value, of the types that can be synced
+    fn records(self) -> Vec<ExistingRecord> {
+        let ttl = self.ttl;
+        self.values
+            .iter()
+            .filter_map(|value| {
+                let content = match RecordContent::parse(&self.r#type, Some(value)) {
+                    Ok(RecordContent::CNAME(target)) => {
+                        RecordContent::CNAME(target.trim_end_matches('.').to_string())
+                    }
+                    Ok(content) if !content.is_unknown() => content,
+                    _ => return None,
+                };
+                Some(ExistingRecord {
+                    id: None,
+                    record: ProviderRecord {
+                        name: self.name.clone(),
+                        content,
+                        comment: None,
+                        op: RecordOp::default(),
+                        ttl: TTL::Value(ttl),
+                        params: vec![],
+                    },
+                })
+            })
+            .collect()
+    }
+
+    fn to_xml(&self) -> String {
+        let records = self
+            .values
+            .iter()
+            .map(|v| {
+                format!(
+                    "<ResourceRecord><Value>{}</Value></ResourceRecord>",
+                    xml::escape(v)
+                )
+            })
+            .collect::<String>();
+        format!(
+            "<ResourceRecordSet><Name>{}</Name><Type>{}</Type><TTL>{}</TTL>\
+             <ResourceRecords>{}</ResourceRecords></ResourceRecordSet>",
+            xml::escape(&self.name),
+            self.r#type,
+            self.ttl,
+            records
+        )
+    }
+
+    // Same name, type, TTL and values, whatever their order
+    fn serves(&self, desired: &RecordSet) -> bool {
+        let sorted = |values: &[String]| {
+            let mut values = values.to_vec();
+            values.sort();
+            values
+        };
+        self.name.eq_ignore_ascii_case(&desired.name)
+            && self.r#type == desired.r#type
+            && self.ttl == desired.ttl
+            && sorted(&self.values) == sorted(&desired.values)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Change {
+    // UPSERT or DELETE
+    pub action: &'static str,
+    pub set: RecordSet,
+}
+
+// Route 53 record set API
+impl Cli {
+    // Record sets of a zone `keep` accepts, the others are dropped page by
+    // page
+    pub async fn record_sets<F>(&self, zone_id: &str, keep: &F) -> Result<Vec<RecordSet>>
+    where
+        F: Fn(&RecordSet) -> bool + Send + Sync,
+    {
+        let mut ret = vec![];
+        let mut next: Option<(String, String)> = None;
+        loop {
+            let mut path = format!("/hostedzone/{}/rrset?maxitems=300", zone_id);
+            if let Some((name, r#type)) = next.as_ref() {
+                path.push_str(&format!(
+                    "&name={}&type={}",
+                    aws::uri_encode(name, true),
+                    r#type
+                ));
+            }
+            let body = self.send(http::Method::Get, &path, None).await?;
+            ret.extend(
+                xml::elements(&body, "ResourceRecordSet")
+                    .into_iter()
+                    .filter_map(RecordSet::parse)
+                    .filter(|s| keep(s)),
+            );
+            next = match xml::text(&body, "IsTruncated").as_deref() {
+                Some("true") => {
+                    xml::text(&body, "NextRecordName").zip(xml::text(&body, "NextRecordType"))
+                }
+                _ => None,
+            };
+            if next.is_none() {
+                break;
+            }
+        }
+        Ok(ret)
+    }
+
+    // Send the changes of a zone in one batch, Route 53 makes all of them or
+    // none
+    pub async fn change(&self, zone_id: &str, changes: &[Change]) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let changes = changes
+            .iter()
+            .map(|c| {
+                format!(
+                    "<Change><Action>{}</Action>{}</Change>",
+                    c.action,
+                    c.set.to_xml()
+                )
+            })
+            .collect::<String>();
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <ChangeResourceRecordSetsRequest xmlns=\"{}\"><ChangeBatch>\
+             <Comment>dns-syncer</Comment><Changes>{}</Changes>\
+             </ChangeBatch></ChangeResourceRecordSetsRequest>",
+            XMLNS, changes
+        );
+        let path = format!("/hostedzone/{}/rrset/", zone_id);
+        self.send(http::Method::Post, &path, Some(body)).await?;
+        Ok(())
+    }
+}
+
+// Writes bringing the live record sets of a zone to the desired ones
+#[derive(Debug, Default)]
+pub(super) struct ZoneDiff {
+    // Desired record sets served as they are
+    pub unchanged: Vec<RecordSet>,
+    pub changes: Vec<Change>,
+}
+
+impl ZoneDiff {
+    pub fn new(changes: &[RecordChange]) -> Self {
+        let mut ret = ZoneDiff::default();
+        let desired = group(changes.iter().map(|c| &c.after));
+        // Records under a name with several desired records are in the before
+        // of each change, they count once
+        let mut before = vec![];
+        for existing in changes.iter().flat_map(|c| c.before.iter()) {
+            if !before.contains(&&existing.record) {
+                before.push(&existing.record);
+            }
+        }
+        let live = group(before.into_iter());
+
+        for set in desired {
+            match live
+                .iter()
+                .find(|l| l.name.eq_ignore_ascii_case(&set.name) && l.r#type == set.r#type)
+            {
+                Some(l) if l.serves(&set) => ret.unchanged.push(set),
+                _ => ret.changes.push(Change {
+                    action: "UPSERT",
+                    set,
+                }),
+            }
+        }
+        for set in live {
+            let replaced = ret
+                .unchanged
+                .iter()
+                .chain(ret.changes.iter().map(|c| &c.set))
+                .any(|d| d.name.eq_ignore_ascii_case(&set.name) && d.r#type == set.r#type);
+            if !replaced {
+                ret.changes.push(Change {
+                    action: "DELETE",
+                    set,
+                });
+            }
+        }
+        ret
+    }
+}
+
+// Records grouped into record sets by name and type, in the order of their
+// first record. The TTL of a set is the one of its first record.
+fn group<'a>(records: impl Iterator<Item = &'a ProviderRecord>) -> Vec<RecordSet> {
+    let mut ret: Vec<RecordSet> = vec![];
+    for record in records {
+        let r#type = record.content.record_type().as_str().to_string();
+        let value = record.content.to_string();
+        match ret
+            .iter_mut()
+            .find(|s| s.name.eq_ignore_ascii_case(&record.name) && s.r#type == r#type)
+        {
+            Some(set) => {
+                if !set.values.contains(&value) {
+                    set.values.push(value);
+                }
+            }
+            None => ret.push(RecordSet {
+                name: record.name.to_ascii_lowercase(),
+                r#type,
+                ttl: match record.ttl {
+                    TTL::Auto => DEFAULT_TTL,
+                    TTL::Value(v) => v,
+                },
+                values: vec![value],
+            }),
+        }
+    }
+    ret
+}
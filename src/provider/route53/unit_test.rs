@@ -0,0 +1,378 @@
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use tokio_util::sync::CancellationToken;
+
+use super::route53::*;
+use crate::error::Error;
+use crate::http::MemoryTransport;
+use crate::http::Method;
+use crate::provider::AuthParams;
+use crate::provider::BackendRecords;
+use crate::provider::ExistingRecord;
+use crate::provider::PlanOp;
+use crate::provider::Provider;
+use crate::provider::RecordChange;
+use crate::provider::RecordStatus;
+use crate::provider::ZoneRecords;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::Param;
+use crate::types::PublicIp;
+use crate::wrapper::aws::Credentials;
+
+const API: &str = "https://route53.amazonaws.com/2013-04-01";
+
+fn auth() -> Route53Auth {
+    Route53Auth::AccessKey(Credentials {
+        access_key_id: "AKIDEXAMPLE".to_string(),
+        secret_access_key: "secret".to_string(),
+        session_token: None,
+    })
+}
+
+// Route 53 hosting example.org with an A and an AAAA record under home, an
+// alias and a wildcard, answering change batches with success
+fn memory_api() -> MemoryTransport {
+    let transport = MemoryTransport::new();
+    transport.route(
+        Method::Get,
+        &format!("{}/hostedzonesbyname?dnsname=example.org&maxitems=10", API),
+        200,
+        r#"<?xml version="1.0"?>
+<ListHostedZonesByNameResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+  <HostedZones>
+    <HostedZone><Id>/hostedzone/Z1</Id><Name>example.org.</Name>
+      <Config><PrivateZone>false</PrivateZone></Config></HostedZone>
+    <HostedZone><Id>/hostedzone/Z2</Id><Name>sub.example.org.</Name></HostedZone>
+  </HostedZones>
+  <IsTruncated>false</IsTruncated>
+</ListHostedZonesByNameResponse>"#,
+    );
+    transport.route(
+        Method::Get,
+        &format!("{}/hostedzonesbyname?dnsname=missing.org&maxitems=10", API),
+        200,
+        "<ListHostedZonesByNameResponse><HostedZones/></ListHostedZonesByNameResponse>",
+    );
+    transport.route(
+        Method::Get,
+        &format!("{}/hostedzone/Z1/rrset?maxitems=300", API),
+        200,
+        r#"<ListResourceRecordSetsResponse>
+  <ResourceRecordSets>
+    <ResourceRecordSet><Name>example.org.</Name><Type>NS</Type><TTL>172800</TTL>
+      <ResourceRecords><ResourceRecord><Value>ns-1.awsdns-00.com.</Value></ResourceRecord></ResourceRecords>
+    </ResourceRecordSet>
+    <ResourceRecordSet><Name>home.example.org.</Name><Type>A</Type><TTL>300</TTL>
+      <ResourceRecords><ResourceRecord><Value>1.1.1.1</Value></ResourceRecord></ResourceRecords>
+    </ResourceRecordSet>
+  </ResourceRecordSets>
+  <IsTruncated>true</IsTruncated>
+  <NextRecordName>home.example.org.</NextRecordName>
+  <NextRecordType>AAAA</NextRecordType>
+</ListResourceRecordSetsResponse>"#,
+    );
+    transport.route(
+        Method::Get,
+        &format!(
+            "{}/hostedzone/Z1/rrset?maxitems=300&name=home.example.org.&type=AAAA",
+            API
+        ),
+        200,
+        r#"<ListResourceRecordSetsResponse>
+  <ResourceRecordSets>
+    <ResourceRecordSet><Name>home.example.org.</Name><Type>AAAA</Type><TTL>60</TTL>
+      <ResourceRecords><ResourceRecord><Value>2001:db8::1</Value></ResourceRecord></ResourceRecords>
+    </ResourceRecordSet>
+    <ResourceRecordSet><Name>www.example.org.</Name><Type>A</Type>
+      <AliasTarget><HostedZoneId>Z2FDTNDATAQYW2</HostedZoneId>
+        <DNSName>d1.cloudfront.net.</DNSName></AliasTarget>
+    </ResourceRecordSet>
+    <ResourceRecordSet><Name>\052.example.org.</Name><Type>CNAME</Type><TTL>300</TTL>
+      <ResourceRecords><ResourceRecord><Value>home.example.org.</Value></ResourceRecord></ResourceRecords>
+    </ResourceRecordSet>
+  </ResourceRecordSets>
+  <IsTruncated>false</IsTruncated>
+</ListResourceRecordSetsResponse>"#,
+    );
+    transport.route(
+        Method::Post,
+        &format!("{}/hostedzone/Z1/rrset/", API),
+        200,
+        "<ChangeResourceRecordSetsResponse><ChangeInfo><Id>/change/C1</Id>\
+         <Status>PENDING</Status></ChangeInfo></ChangeResourceRecordSetsResponse>",
+    );
+    transport
+}
+
+fn desired(zone: &str, name: &str) -> BackendRecords {
+    let mut records = BackendRecords::default();
+    records.zones.insert(
+        zone.to_string(),
+        ZoneRecords {
+            records: vec![ProviderRecord {
+                name: name.to_string(),
+                content: RecordContent::Unassigned(RecordType::A),
+                comment: None,
+                ttl: TTL::Auto,
+                op: RecordOp::Create,
+                params: vec![],
+            }],
+        },
+    );
+    records
+}
+
+#[tokio::test]
+async fn test_route53_list() {
+    let transport = memory_api();
+    let provider = Route53::with_transport(auth(), Arc::new(transport.clone()));
+
+    let records = provider.list(&"example.org".to_string()).await.unwrap();
+    let records = records
+        .iter()
+        .map(|r| {
+            (
+                r.record.name.as_str(),
+                r.record.content.clone(),
+                r.record.ttl.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    // NS and alias record sets are left out, the pages are followed
+    assert_eq!(
+        records,
+        vec![
+            (
+                "home.example.org",
+                RecordContent::A(Ipv4Addr::new(1, 1, 1, 1)),
+                TTL::Value(300)
+            ),
+            (
+                "home.example.org",
+                RecordContent::AAAA("2001:db8::1".parse().unwrap()),
+                TTL::Value(60)
+            ),
+            (
+                "*.example.org",
+                RecordContent::CNAME("home.example.org".to_string()),
+                TTL::Value(300)
+            ),
+        ]
+    );
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 3);
+    let auth = requests[0]
+        .headers
+        .iter()
+        .find(|h| h.name() == "Authorization")
+        .unwrap();
+    assert!(
+        auth.value()
+            .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/")
+    );
+    assert!(auth.value().contains("/us-east-1/route53/aws4_request"));
+
+    // The zone id is looked up once
+    provider.list(&"example.org".to_string()).await.unwrap();
+    assert_eq!(transport.requests().len(), 5);
+    assert_eq!(provider.id_cache().zones["example.org"], "Z1");
+
+    let err = provider.list(&"missing.org".to_string()).await.unwrap_err();
+    assert!(matches!(err, Error::ZoneNotFound { .. }));
+    let info = provider
+        .zone_info(&"example.org".to_string())
+        .await
+        .unwrap();
+    assert_eq!(info.unwrap().status.as_deref(), Some("public"));
+}
+
+#[tokio::test]
+async fn test_route53_sync() {
+    let transport = memory_api();
+    let provider = Route53::with_transport(auth(), Arc::new(transport.clone()));
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    let changes = provider
+        .dry_run(&desired("example.org", "home"), &public_ip)
+        .await
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].op, PlanOp::Update);
+
+    let outcome = provider
+        .sync(
+            &desired("example.org", "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    let statuses = outcome.zones[0]
+        .records
+        .iter()
+        .map(|r| (r.r#type.as_str(), r.status))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        vec![
+            ("A", RecordStatus::Updated),
+            ("AAAA", RecordStatus::Deleted)
+        ]
+    );
+
+    // The A record set is replaced, the AAAA one deleted as it was listed
+    let batch = transport.requests().pop().unwrap();
+    assert_eq!(batch.method, Method::Post);
+    let body = batch.body.unwrap();
+    assert!(body.contains(
+        "<Change><Action>UPSERT</Action><ResourceRecordSet><Name>home.example.org</Name>\
+         <Type>A</Type><TTL>300</TTL><ResourceRecords><ResourceRecord><Value>2.2.2.2</Value>"
+    ));
+    assert!(body.contains(
+        "<Change><Action>DELETE</Action><ResourceRecordSet><Name>home.example.org</Name>\
+         <Type>AAAA</Type><TTL>60</TTL><ResourceRecords><ResourceRecord><Value>2001:db8::1</Value>"
+    ));
+
+    // Zones Route 53 does not host are skipped
+    let outcome = provider
+        .sync(
+            &desired("missing.org", "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty());
+    assert!(outcome.zones.is_empty());
+}
+
+#[test]
+fn test_route53_zone_diff() {
+    let record = |content: RecordContent, ttl: TTL| ProviderRecord {
+        name: "home.example.org".to_string(),
+        content,
+        comment: Some("[dns-syncer]".to_string()),
+        op: RecordOp::Purge,
+        ttl,
+        params: vec![],
+    };
+    let a = |ip: [u8; 4]| RecordContent::A(Ipv4Addr::from(ip));
+    let live = vec![
+        ExistingRecord::from(record(a([1, 1, 1, 1]), TTL::Value(300))),
+        ExistingRecord::from(record(a([1, 1, 1, 2]), TTL::Value(300))),
+    ];
+    let change = |after: ProviderRecord| RecordChange {
+        before: live.clone(),
+        after,
+    };
+
+    // The same values in another order, the automatic TTL being 300
+    let changes = vec![
+        change(record(a([1, 1, 1, 2]), TTL::Auto)),
+        change(record(a([1, 1, 1, 1]), TTL::Auto)),
+    ];
+    let diff = ZoneDiff::new(&changes);
+    assert_eq!(diff.unchanged.len(), 1);
+    assert!(diff.changes.is_empty());
+
+    let changes = vec![change(record(a([1, 1, 1, 1]), TTL::Value(60)))];
+    let diff = ZoneDiff::new(&changes);
+    assert_eq!(diff.changes.len(), 1);
+    assert_eq!(diff.changes[0].action, "UPSERT");
+    assert_eq!(diff.changes[0].set.values, vec!["1.1.1.1"]);
+    assert_eq!(diff.changes[0].set.ttl, 60);
+}
+
+#[tokio::test]
+async fn test_route53_errors() {
+    let transport = memory_api();
+    let provider = Route53::with_transport(auth(), Arc::new(transport.clone()));
+    // Without comments records cannot be told owned
+    let err = provider
+        .delete_record(
+            &"example.org".to_string(),
+            "home.example.org",
+            RecordType::A,
+            false,
+        )
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("force the deletion"));
+    let deleted = provider
+        .delete_record(
+            &"example.org".to_string(),
+            "home.example.org",
+            RecordType::A,
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+
+    transport.route(
+        Method::Post,
+        &format!("{}/hostedzone/Z1/rrset/", API),
+        400,
+        r#"<ErrorResponse><Error><Type>Sender</Type><Code>InvalidChangeBatch</Code>
+            <Message>RRSet of type CNAME with DNS name home.example.org. is not permitted</Message>
+            </Error></ErrorResponse>"#,
+    );
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+    let outcome = provider
+        .sync(
+            &desired("example.org", "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(
+        err.contains("InvalidChangeBatch: RRSet of type CNAME"),
+        "{}",
+        err
+    );
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Failed);
+
+    transport.route(
+        Method::Get,
+        &format!("{}/hostedzone", API),
+        403,
+        r#"<ErrorResponse><Error><Type>Sender</Type><Code>AccessDenied</Code>
+            <Message>User is not authorized to perform: route53:ListHostedZones</Message>
+            </Error></ErrorResponse>"#,
+    );
+    let err = provider.list_zones().await.unwrap_err();
+    assert!(err.is_auth());
+    assert!(err.to_string().contains("AccessDenied"));
+}
+
+#[test]
+fn test_route53_auth() {
+    let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+    let auth = Route53Auth::new_with_params(&AuthParams::default()).unwrap();
+    assert!(matches!(auth, Route53Auth::Ambient));
+
+    let auth = Route53Auth::new_with_params(&AuthParams::new(
+        "access_key",
+        vec![
+            param("access_key_id", "AKIDEXAMPLE"),
+            param("secret_access_key", "secret"),
+        ],
+    ))
+    .unwrap();
+    assert_eq!(auth.describe(), "access key AKIDEXAMPLE");
+
+    assert!(
+        Route53Auth::new_with_params(&AuthParams::new(
+            "access_key",
+            vec![param("access_key_id", "AKIDEXAMPLE")],
+        ))
+        .is_err()
+    );
+    assert!(Route53Auth::new_with_params(&AuthParams::new("api_token", vec![])).is_err());
+}
@@ -0,0 +1,78 @@
+// Just enough XML for the Route 53 API: its answers are small documents
+// without mixed content, and never nest an element in one of the same name.
+
+// Inner XML of every `<tag>` element of `xml`, in order
+pub(super) fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}", tag);
+    let close = format!("</{}>", tag);
+    let mut ret = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        // `<ResourceRecordSets>` is not a `<ResourceRecordSet>`
+        if !after.starts_with(['>', ' ', '/']) {
+            rest = after;
+            continue;
+        }
+        let Some(end) = after.find('>') else {
+            break;
+        };
+        if after[..end].ends_with('/') {
+            ret.push("");
+            rest = &after[end + 1..];
+            continue;
+        }
+        let inner = &after[end + 1..];
+        let Some(len) = inner.find(&close) else {
+            break;
+        };
+        ret.push(&inner[..len]);
+        rest = &inner[len + close.len()..];
+    }
+    ret
+}
+
+// Inner XML of the first `<tag>` element of `xml`
+pub(super) fn element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    elements(xml, tag).into_iter().next()
+}
+
+// Text of the first `<tag>` element of `xml`
+pub(super) fn text(xml: &str, tag: &str) -> Option<String> {
+    element(xml, tag).map(unescape)
+}
+
+pub(super) fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_xml_elements() {
+        let xml = r#"<Sets xmlns="urn:x"><Set><Name>a&amp;b</Name><Values/></Set>
+            <Set id="2"><Name>c</Name></Set></Sets>"#;
+        let sets = elements(xml, "Set");
+        assert_eq!(sets.len(), 2);
+        assert_eq!(text(sets[0], "Name").as_deref(), Some("a&b"));
+        assert_eq!(element(sets[0], "Values"), Some(""));
+        assert_eq!(text(sets[1], "Name").as_deref(), Some("c"));
+        assert_eq!(element(xml, "Missing"), None);
+        assert_eq!(escape("<a & b>"), "&lt;a &amp; b&gt;");
+    }
+}
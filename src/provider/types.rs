@@ -1,23 +1,1154 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::time::Instant;
 
 use async_trait::async_trait;
+use futures_util::StreamExt;
+use futures_util::stream;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
 
+use crate::error::Error;
 use crate::error::Result;
-use crate::types::ProviderRecord;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordError;
+use crate::record::RecordOp;
+use crate::record::RecordType;
 use crate::types::PublicIp;
 use crate::types::ZoneName;
+use crate::types::glob_match;
+use crate::verify::Propagation;
 
+pub const DEFAULT_ZONE_CONCURRENCY: usize = 4;
+
+// A provider lists what a zone serves, plans the changes from it and applies
+// them. `sync` and `dry_run` are built on these three, so a provider only has
+// to implement `list` and `apply` to be synced.
 #[async_trait]
-pub trait Provider {
-    async fn sync(&self, records: BackendRecords, public_ip: PublicIp) -> Result<()>;
+pub trait Provider: Send + Sync {
+    // Every record of a zone as the provider serves it now. Fails with
+    // `Error::ZoneNotFound` when the zone does not exist.
+    async fn list(&self, _zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        Err(Error::NotImplemente)
+    }
+
+    // Records of a zone under one of `names`, what `sync` and `dry_run` plan
+    // from. Providers listing page by page drop the other records as pages
+    // come, so large zones are never held whole.
+    async fn list_named(&self, zone: &ZoneName, names: &[String]) -> Result<Vec<ExistingRecord>> {
+        let records = self.list(zone).await?;
+        Ok(records
+            .into_iter()
+            .filter(|r| names.iter().any(|n| n.eq_ignore_ascii_case(&r.record.name)))
+            .collect())
+    }
+
+    // Changes turning `existing` into `desired` on a zone, without calling the
+    // provider. By default every record under a desired name is replaced,
+    // whatever its type.
+    fn plan(
+        &self,
+        zone: &ZoneName,
+        desired: &[ProviderRecord],
+        existing: &[ExistingRecord],
+    ) -> ChangeSet {
+        ChangeSet::replace_by_name(zone, desired, existing)
+    }
+
+    // Make the changes of a zone. A failing change does not stop the others,
+    // all failures are returned. Once `cancel` is cancelled the changes left
+    // are not made and `Error::Cancelled` is returned.
+    async fn apply(&self, _changes: &ChangeSet, _cancel: &CancellationToken) -> Result<()> {
+        Err(Error::NotImplemente)
+    }
+
+    // List, plan and apply every zone of `records`, telling what became of
+    // every record. Up to `zone_concurrency` zones sync at once, outcomes keep
+    // the order of the zones. Zones the provider does not have are logged and
+    // skipped.
+    async fn sync(
+        &self,
+        records: &BackendRecords,
+        public_ip: &PublicIp,
+        cancel: &CancellationToken,
+    ) -> SyncOutcome {
+        let requests = self.request_count();
+        let mut zones = vec![];
+        for (zone, zone_records) in records.zones.iter() {
+            zones.push(sync_zone(self, zone, zone_records, public_ip, cancel));
+        }
+        let zones = stream::iter(zones)
+            .buffered(self.zone_concurrency().max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut ret = SyncOutcome::default();
+        for (outcome, error) in zones {
+            ret.zones.extend(outcome);
+            // Zones left after a cancellation fail alike, one error tells it
+            let cancelled = |e: &Error| matches!(e, Error::Cancelled);
+            if let Some(e) = error
+                && !(cancelled(&e) && ret.errors.iter().any(cancelled))
+            {
+                ret.errors.push(e);
+            }
+        }
+        ret.requests = self.request_count().saturating_sub(requests);
+        ret
+    }
+
+    // Zones `sync` works on at once
+    fn zone_concurrency(&self) -> usize {
+        DEFAULT_ZONE_CONCURRENCY
+    }
+
+    // API requests the provider made so far, providers not counting them
+    // report none
+    fn request_count(&self) -> u64 {
+        0
+    }
+
+    // Ids the provider resolved so far, like the ids of zones, to keep across
+    // runs
+    fn id_cache(&self) -> IdCache {
+        IdCache::default()
+    }
+
+    // Ids kept from an earlier run, trusted until the provider answers that
+    // they are gone
+    fn load_id_cache(&self, _cache: IdCache) {}
+
+    // Changes `sync` would make for these records, without making them
+    async fn dry_run(
+        &self,
+        records: &BackendRecords,
+        public_ip: &PublicIp,
+    ) -> Result<Vec<PlannedChange>> {
+        let mut ret = vec![];
+        for (zone, zone_records) in records.zones.iter() {
+            let desired = zone_records.desired(zone, public_ip);
+            let existing = match self.list_named(zone, &desired_names(&desired)).await {
+                Ok(existing) => existing,
+                Err(Error::ZoneNotFound { .. }) => continue,
+                Err(e) => return Err(e.context(&format!("zone {}", zone))),
+            };
+            ret.extend(self.plan(zone, &desired, &existing).planned());
+        }
+        Ok(ret)
+    }
+
+    // Check that the credentials are valid and can access the given zones.
+    // Providers without a way to check it accept everything.
+    async fn verify(&self, _zones: &[ZoneName]) -> Result<()> {
+        Ok(())
+    }
+
+    // Names of all zones the credentials can access
+    async fn list_zones(&self) -> Result<Vec<ZoneName>> {
+        Err(Error::NotImplemente)
+    }
+
+    // Zones the credentials can access with what the provider tells about them.
+    // Providers without more to tell give the names only.
+    async fn list_zone_info(&self) -> Result<Vec<ZoneInfo>> {
+        let zones = self.list_zones().await?;
+        Ok(zones
+            .into_iter()
+            .map(|name| ZoneInfo {
+                name,
+                ..Default::default()
+            })
+            .collect())
+    }
+
+    // What the provider tells about one zone, `None` when the credentials
+    // cannot access it
+    async fn zone_info(&self, zone: &ZoneName) -> Result<Option<ZoneInfo>> {
+        let zones = self.list_zone_info().await?;
+        Ok(zones
+            .into_iter()
+            .find(|z| z.name.eq_ignore_ascii_case(zone)))
+    }
+
+    // Records of a zone named `name` as the provider serves them now, without
+    // changing anything
+    async fn list_records(&self, zone: &ZoneName, name: &str) -> Result<Vec<ProviderRecord>> {
+        let records = self.list_zone_records(zone).await?;
+        Ok(records
+            .into_iter()
+            .filter(|r| r.name.eq_ignore_ascii_case(name))
+            .collect())
+    }
+
+    // Every record of a zone the provider serves now, without changing anything
+    async fn list_zone_records(&self, zone: &ZoneName) -> Result<Vec<ProviderRecord>> {
+        let records = self.list(zone).await?;
+        Ok(records.into_iter().map(|r| r.record).collect())
+    }
+
+    // Delete the records of a zone named `name` and return them. Unless `force`
+    // is set only records carrying the owner marker are deleted.
+    async fn delete_records(
+        &self,
+        _zone: &ZoneName,
+        _name: &str,
+        _force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        Err(Error::NotImplemente)
+    }
+
+    // The record of a zone named `name` of type `record_type`, the first one
+    // when the name has several, `None` when it has none
+    async fn get_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+    ) -> Result<Option<ProviderRecord>> {
+        let records = self.list_records(zone, name).await?;
+        Ok(records
+            .into_iter()
+            .find(|r| r.content.record_type() == record_type))
+    }
+
+    // Create one record in a zone, with the owner marker, keeping every record
+    // already there. The name gets the zone appended when it is not a full
+    // name.
+    async fn create_record(&self, zone: &ZoneName, record: &ProviderRecord) -> Result<()> {
+        if record.content.is_unassigned() {
+            return Err(Error::InvalidRecord {
+                record: record.name.clone(),
+                reason: RecordError::MissingContent(
+                    record.content.record_type().as_str().to_string(),
+                ),
+            });
+        }
+        let mut record = record.clone();
+        record.name = record.fqdn(zone);
+        record.mark_owned();
+        let changes = ChangeSet {
+            zone: zone.clone(),
+            changes: vec![RecordChange {
+                before: vec![],
+                after: record,
+            }],
+        };
+        self.apply(&changes, &CancellationToken::new()).await
+    }
+
+    // Delete the records of a zone named `name` of type `record_type` and
+    // return them. Unless `force` is set only records carrying the owner
+    // marker are deleted.
+    async fn delete_record(
+        &self,
+        _zone: &ZoneName,
+        _name: &str,
+        _record_type: RecordType,
+        _force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        Err(Error::NotImplemente)
+    }
+
+    // Check the credentials are valid and what they are allowed to do on
+    // `zones`, reporting every missing permission instead of the first one
+    async fn check_credentials(&self, _zones: &[ZoneName]) -> Result<CredentialCheck> {
+        Err(Error::NotImplemente)
+    }
+
+    // Delete the records of a zone equal to `records` by name and content that
+    // carry the owner marker, and return them
+    async fn delete_owned_records(
+        &self,
+        _zone: &ZoneName,
+        _records: &[ProviderRecord],
+    ) -> Result<Vec<ProviderRecord>> {
+        Err(Error::NotImplemente)
+    }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ZoneInfo {
+    pub name: ZoneName,
+    pub id: Option<String>,
+    // Billing plan of the zone, like "Free Website"
+    pub plan: Option<String>,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ZoneRecords {
     pub records: Vec<ProviderRecord>,
 }
 
-#[derive(Debug, Clone, Default)]
+impl ZoneRecords {
+    // Records to sync on `zone`, with their full name, the owner marker and the
+    // public address filled in. Records that cannot be synced are logged and
+    // left out.
+    pub fn desired(&self, zone: &str, public_ip: &PublicIp) -> Vec<ProviderRecord> {
+        let (v4, v6) = public_ip.ips();
+        let mut ret = vec![];
+        for record in self.records.iter() {
+            let mut record = record.clone();
+            record.op = RecordOp::Purge;
+            record.name = record.fqdn(zone);
+            record.mark_owned();
+
+            if let Err(e) = record.assign_public_ip_if_unassigned(v4, v6) {
+                log::error!(zone = zone, record = record.name, outcome = "failed"; "{}", e);
+                continue;
+            }
+            if !record.content.is_unknown() {
+                ret.push(record);
+            }
+        }
+        ret
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BackendRecords {
     pub zones: HashMap<ZoneName, ZoneRecords>,
 }
+
+////////////////////////////////////////////////////////////
+// Plan
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanOp {
+    Create,
+    Update,
+    Unchanged,
+}
+
+impl PlanOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PlanOp::Create => "create",
+            PlanOp::Update => "update",
+            PlanOp::Unchanged => "unchanged",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedRecord {
+    pub r#type: String,
+    pub content: String,
+}
+
+impl From<&RecordContent> for PlannedRecord {
+    fn from(content: &RecordContent) -> Self {
+        Self {
+            r#type: content.record_type().as_str().to_string(),
+            content: content.to_string(),
+        }
+    }
+}
+
+// One record of a plan, `before` lists what the provider serves under the
+// record name now and `after` what it will serve
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedChange {
+    pub zone: ZoneName,
+    pub name: String,
+    pub op: PlanOp,
+    pub before: Vec<PlannedRecord>,
+    pub after: PlannedRecord,
+}
+
+impl PlannedChange {
+    pub fn new(zone: &str, name: &str, before: Vec<PlannedRecord>, after: PlannedRecord) -> Self {
+        let op = if before.is_empty() {
+            PlanOp::Create
+        } else if before.len() == 1 && before[0] == after {
+            PlanOp::Unchanged
+        } else {
+            PlanOp::Update
+        };
+
+        Self {
+            zone: zone.to_string(),
+            name: name.to_string(),
+            op,
+            before,
+            after,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Id cache
+////////////////////////////////////////////////////////////
+// Ids of provider objects, so a restart needs no lookup to find them again
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IdCache {
+    // Zone ids by zone name
+    #[serde(default)]
+    pub zones: HashMap<ZoneName, String>,
+}
+
+impl IdCache {
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+}
+
+// Names of the records `desired` replaces, for `Provider::list_named`
+pub fn desired_names(desired: &[ProviderRecord]) -> Vec<String> {
+    let mut ret = desired.iter().map(|r| r.name.clone()).collect::<Vec<_>>();
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+// List, plan and apply one zone for `Provider::sync`. A zone the provider does
+// not have has no outcome.
+async fn sync_zone<P: Provider + ?Sized>(
+    provider: &P,
+    zone: &ZoneName,
+    zone_records: &ZoneRecords,
+    public_ip: &PublicIp,
+    cancel: &CancellationToken,
+) -> (Option<ZoneSyncOutcome>, Option<Error>) {
+    if cancel.is_cancelled() {
+        log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+        return (None, Some(Error::Cancelled));
+    }
+    let started = Instant::now();
+    let requests = provider.request_count();
+    let desired = zone_records.desired(zone, public_ip);
+    let (mut outcome, error) = match provider.list_named(zone, &desired_names(&desired)).await {
+        Ok(existing) => {
+            let changes = provider.plan(zone, &desired, &existing);
+            let result = provider.apply(&changes, cancel).await;
+            let outcome = ZoneSyncOutcome::from_apply(&changes, result.as_ref().err());
+            (outcome, result.err())
+        }
+        Err(Error::ZoneNotFound { .. }) => {
+            log::warn!(zone = zone, outcome = "skipped"; "zone {} not found", zone);
+            return (None, None);
+        }
+        Err(e) => {
+            let e = e.context(&format!("zone {}", zone));
+            let outcome = ZoneSyncOutcome {
+                zone: zone.clone(),
+                error: Some(e.to_string()),
+                ..Default::default()
+            };
+            (outcome, Some(e))
+        }
+    };
+    outcome.requests = provider.request_count().saturating_sub(requests);
+    outcome.duration_ms = started.elapsed().as_millis() as u64;
+    (Some(outcome), error)
+}
+
+////////////////////////////////////////////////////////////
+// Outcomes
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordStatus {
+    Created,
+    Updated,
+    Unchanged,
+    // Served under the name of a synced record with another type, replaced
+    // by it
+    Deleted,
+    Failed,
+}
+
+impl RecordStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordStatus::Created => "created",
+            RecordStatus::Updated => "updated",
+            RecordStatus::Unchanged => "unchanged",
+            RecordStatus::Deleted => "deleted",
+            RecordStatus::Failed => "failed",
+        }
+    }
+
+    // The record serves what the config asks for
+    pub fn is_synced(&self) -> bool {
+        matches!(
+            self,
+            RecordStatus::Created | RecordStatus::Updated | RecordStatus::Unchanged
+        )
+    }
+
+    // The sync changed what the record serves
+    pub fn is_change(&self) -> bool {
+        matches!(self, RecordStatus::Created | RecordStatus::Updated)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordOutcome {
+    pub zone: ZoneName,
+    pub name: String,
+    pub r#type: String,
+    pub status: RecordStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // What the verification resolvers answered after the sync
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub propagation: Vec<Propagation>,
+}
+
+// What a sync did on one zone
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ZoneSyncOutcome {
+    pub zone: ZoneName,
+    pub records: Vec<RecordOutcome>,
+    // API requests made while the zone synced, see `Provider::request_count`.
+    // Zones syncing at the same time count the requests of each other.
+    pub requests: u64,
+    pub duration_ms: u64,
+    // Failure of the zone as a whole, like listing its records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ZoneSyncOutcome {
+    // Outcome of applying `changes`, failing with `error` if any. Records are
+    // failed when the error names them, with `Error::for_record` or as
+    // rejected, and all of them when it names none.
+    pub fn from_apply(changes: &ChangeSet, error: Option<&Error>) -> Self {
+        let mut failed = vec![];
+        if let Some(e) = error {
+            record_errors(e, &mut failed);
+        }
+        let failure = |name: &str| match error {
+            Some(e) if failed.is_empty() => Some(e.to_string()),
+            _ => failed
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, e)| e.clone()),
+        };
+
+        let mut records = vec![];
+        for change in changes.changes.iter() {
+            let after = &change.after;
+            let r#type = after.content.record_type();
+            if let Some(error) = failure(&after.name) {
+                records.push(RecordOutcome {
+                    zone: changes.zone.clone(),
+                    name: after.name.clone(),
+                    r#type: r#type.as_str().to_string(),
+                    status: RecordStatus::Failed,
+                    error: Some(error),
+                    propagation: vec![],
+                });
+                continue;
+            }
+            let status = match change.planned(&changes.zone).op {
+                PlanOp::Create => RecordStatus::Created,
+                PlanOp::Update => RecordStatus::Updated,
+                PlanOp::Unchanged => RecordStatus::Unchanged,
+            };
+            records.push(RecordOutcome {
+                zone: changes.zone.clone(),
+                name: after.name.clone(),
+                r#type: r#type.as_str().to_string(),
+                status,
+                error: None,
+                propagation: vec![],
+            });
+            for before in change.before.iter() {
+                let before_type = before.record.content.record_type();
+                if before_type != r#type {
+                    records.push(RecordOutcome {
+                        zone: changes.zone.clone(),
+                        name: before.record.name.clone(),
+                        r#type: before_type.as_str().to_string(),
+                        status: RecordStatus::Deleted,
+                        error: None,
+                        propagation: vec![],
+                    });
+                }
+            }
+        }
+
+        Self {
+            zone: changes.zone.clone(),
+            records,
+            ..Default::default()
+        }
+    }
+}
+
+// Record names an error is about, with the error
+fn record_errors(error: &Error, out: &mut Vec<(String, String)>) {
+    match error {
+        Error::Record { record, source } => out.push((record.clone(), source.to_string())),
+        Error::Context { source, .. } => record_errors(source, out),
+        Error::RecordRejected { record, .. } => out.push((record.clone(), error.to_string())),
+        Error::Multiple(errors) => errors.iter().for_each(|e| record_errors(e, out)),
+        _ => {}
+    }
+}
+
+// What `Provider::sync` did on every zone, with the failures
+#[derive(Debug, Default)]
+pub struct SyncOutcome {
+    pub zones: Vec<ZoneSyncOutcome>,
+    pub errors: Vec<Error>,
+    // API requests of the whole sync
+    pub requests: u64,
+}
+
+impl SyncOutcome {
+    pub fn records(&self) -> impl Iterator<Item = &RecordOutcome> {
+        self.zones.iter().flat_map(|z| z.records.iter())
+    }
+
+    // The zones when nothing failed, the failures otherwise
+    pub fn into_result(self) -> Result<Vec<ZoneSyncOutcome>> {
+        Error::from_errors(self.errors)?;
+        Ok(self.zones)
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Changes
+////////////////////////////////////////////////////////////
+// A record as a provider serves it, `id` is what the provider knows it by when
+// it has one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExistingRecord {
+    pub id: Option<String>,
+    pub record: ProviderRecord,
+}
+
+impl From<ProviderRecord> for ExistingRecord {
+    fn from(record: ProviderRecord) -> Self {
+        Self { id: None, record }
+    }
+}
+
+// One record name of a zone, `before` lists what the provider serves under it
+// now and `after` replaces them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordChange {
+    pub before: Vec<ExistingRecord>,
+    pub after: ProviderRecord,
+}
+
+impl RecordChange {
+    pub fn planned(&self, zone: &str) -> PlannedChange {
+        PlannedChange::new(
+            zone,
+            &self.after.name,
+            self.before
+                .iter()
+                .map(|r| PlannedRecord::from(&r.record.content))
+                .collect(),
+            PlannedRecord::from(&self.after.content),
+        )
+    }
+}
+
+// Changes of one zone, applied in order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub zone: ZoneName,
+    pub changes: Vec<RecordChange>,
+}
+
+impl ChangeSet {
+    // Every desired record replaces what `existing` has under its name
+    pub fn replace_by_name(
+        zone: &str,
+        desired: &[ProviderRecord],
+        existing: &[ExistingRecord],
+    ) -> Self {
+        let changes = desired
+            .iter()
+            .map(|after| RecordChange {
+                before: existing
+                    .iter()
+                    .filter(|r| r.record.name.eq_ignore_ascii_case(&after.name))
+                    .cloned()
+                    .collect(),
+                after: after.clone(),
+            })
+            .collect();
+        Self {
+            zone: zone.to_string(),
+            changes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn planned(&self) -> Vec<PlannedChange> {
+        self.changes.iter().map(|c| c.planned(&self.zone)).collect()
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Capabilities
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthMethod {
+    pub name: &'static str,
+    pub params: Vec<&'static str>,
+}
+
+// What a provider type supports, for users to discover valid `type:` values and
+// for records to be checked before anything is sent
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub r#type: &'static str,
+    pub description: &'static str,
+    pub record_types: Vec<RecordType>,
+    pub auth_methods: Vec<AuthMethod>,
+    // Params of the provider entries of a record
+    pub params: Vec<ParamSpec>,
+    // Operations besides `sync` and `plan`, like `dry_run` or `delete_records`
+    pub operations: Vec<&'static str>,
+}
+
+impl Capabilities {
+    pub fn supports(&self, record_type: &RecordType) -> bool {
+        self.record_types.contains(record_type)
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Credentials
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CredentialCheck {
+    // Human readable name of the credential, never the secret
+    pub credential: String,
+    pub id: Option<String>,
+    pub status: Option<String>,
+    pub expires_on: Option<String>,
+    pub zones: Vec<ZoneAccess>,
+}
+
+impl CredentialCheck {
+    // Active, when the provider tells, and nothing missing on any zone
+    pub fn is_ok(&self) -> bool {
+        self.status.as_deref().is_none_or(|s| s == "active")
+            && self.zones.iter().all(|z| z.missing.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ZoneAccess {
+    pub zone: ZoneName,
+    // Permissions granted on the zone, `None` when the provider does not tell
+    pub permissions: Option<Vec<String>>,
+    // Permissions syncing needs that are not granted
+    pub missing: Vec<String>,
+}
+
+// Two records of a zone with the same name and type that disagree on their
+// content or op. Providers apply them in order, so the last one silently wins.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecordConflict {
+    pub zone: ZoneName,
+    pub name: String,
+    pub r#type: String,
+    pub reason: String,
+}
+
+impl fmt::Display for RecordConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} in zone {}: {}",
+            self.r#type, self.name, self.zone, self.reason
+        )
+    }
+}
+
+impl BackendRecords {
+    pub fn has_wildcard_zones(&self) -> bool {
+        self.zones.keys().any(|zone| zone.contains('*'))
+    }
+
+    // Replace zone patterns like "*.example.org" by every zone of `available`
+    // they match. Records of a pattern are appended to the records the matched
+    // zone may already have. Patterns matching nothing are dropped.
+    pub fn expand_zones(&self, available: &[ZoneName]) -> BackendRecords {
+        let mut ret = BackendRecords::default();
+
+        let mut zones = self.zones.iter().collect::<Vec<_>>();
+        // Explicit zones first so that their records keep coming first
+        zones.sort_by_key(|(zone, _)| zone.contains('*'));
+
+        for (zone, zone_records) in zones {
+            if !zone.contains('*') {
+                ret.zones
+                    .entry(zone.clone())
+                    .or_default()
+                    .records
+                    .extend(zone_records.records.iter().cloned());
+                continue;
+            }
+
+            let matched = available
+                .iter()
+                .filter(|name| glob_match(zone, name))
+                .collect::<Vec<_>>();
+            if matched.is_empty() {
+                log::warn!("zone pattern {} matches no zone", zone);
+            }
+            for name in matched {
+                ret.zones
+                    .entry(name.clone())
+                    .or_default()
+                    .records
+                    .extend(zone_records.records.iter().cloned());
+            }
+        }
+
+        ret
+    }
+
+    pub fn conflicts(&self) -> Vec<RecordConflict> {
+        let mut ret = vec![];
+
+        for (zone, zone_records) in self.zones.iter() {
+            let mut seen: HashMap<(String, String), &ProviderRecord> = HashMap::new();
+
+            for record in zone_records.records.iter() {
+                let key = (
+                    record.fqdn(zone),
+                    record.content.record_type().as_str().to_string(),
+                );
+
+                let Some(first) = seen.get(&key) else {
+                    seen.insert(key, record);
+                    continue;
+                };
+
+                let reason = if first.op != record.op {
+                    format!("conflicting ops {:?} and {:?}", first.op, record.op)
+                } else if first.content != record.content {
+                    format!(
+                        "different contents {} and {}",
+                        first.content, record.content
+                    )
+                } else {
+                    continue;
+                };
+
+                ret.push(RecordConflict {
+                    zone: zone.clone(),
+                    name: key.0,
+                    r#type: key.1,
+                    reason,
+                });
+            }
+        }
+
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::record::RecordType;
+    use crate::record::TTL;
+
+    fn record(name: &str, content: RecordContent, op: RecordOp) -> ProviderRecord {
+        ProviderRecord {
+            name: name.to_string(),
+            content,
+            comment: None,
+            op,
+            ttl: TTL::Auto,
+            params: vec![],
+        }
+    }
+
+    #[test]
+    fn test_backend_records_conflicts() {
+        let v4 = RecordContent::A(Ipv4Addr::new(1, 2, 3, 4));
+        let mut backend = BackendRecords::default();
+        backend.zones.insert(
+            "example.org".to_string(),
+            ZoneRecords {
+                records: vec![
+                    record("home", v4.clone(), RecordOp::Create),
+                    // Same record given with its full name is not a conflict
+                    record("home.example.org", v4.clone(), RecordOp::Create),
+                    record(
+                        "home",
+                        RecordContent::Unassigned(RecordType::A),
+                        RecordOp::Create,
+                    ),
+                    record("www", v4.clone(), RecordOp::Create),
+                    record("www.example.org", v4.clone(), RecordOp::Purge),
+                    record(
+                        "www",
+                        RecordContent::Unassigned(RecordType::AAAA),
+                        RecordOp::Create,
+                    ),
+                ],
+            },
+        );
+
+        let conflicts = backend.conflicts();
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].name, "home.example.org");
+        assert!(conflicts[0].reason.contains("different contents"));
+        assert_eq!(conflicts[1].name, "www.example.org");
+        assert!(conflicts[1].reason.contains("conflicting ops"));
+    }
+
+    #[test]
+    fn test_backend_records_expand_zones() {
+        let v4 = RecordContent::A(Ipv4Addr::new(1, 2, 3, 4));
+        let mut backend = BackendRecords::default();
+        backend.zones.insert(
+            "*.example.org".to_string(),
+            ZoneRecords {
+                records: vec![record("home", v4.clone(), RecordOp::Create)],
+            },
+        );
+        backend.zones.insert(
+            "a.example.org".to_string(),
+            ZoneRecords {
+                records: vec![record("www", v4.clone(), RecordOp::Create)],
+            },
+        );
+        assert!(backend.has_wildcard_zones());
+
+        let available = vec![
+            "a.example.org".to_string(),
+            "b.example.org".to_string(),
+            "example.com".to_string(),
+        ];
+        let expanded = backend.expand_zones(&available);
+        assert!(!expanded.has_wildcard_zones());
+        assert_eq!(expanded.zones.len(), 2);
+
+        let names = |zone: &str| {
+            expanded.zones[zone]
+                .records
+                .iter()
+                .map(|r| r.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("a.example.org"), vec!["www", "home"]);
+        assert_eq!(names("b.example.org"), vec!["home"]);
+    }
+
+    #[test]
+    fn test_change_set_replace_by_name() {
+        let v4 = RecordContent::A(Ipv4Addr::new(1, 2, 3, 4));
+        let existing = vec![
+            ExistingRecord {
+                id: Some("1".to_string()),
+                record: record("HOME.example.org", v4.clone(), RecordOp::Create),
+            },
+            ExistingRecord {
+                id: Some("2".to_string()),
+                record: record(
+                    "home.example.org",
+                    RecordContent::AAAA("::1".parse().unwrap()),
+                    RecordOp::Create,
+                ),
+            },
+            record("mail.example.org", v4.clone(), RecordOp::Create).into(),
+        ];
+        let zone_records = ZoneRecords {
+            records: vec![
+                record("home", v4.clone(), RecordOp::Create),
+                record("www", v4.clone(), RecordOp::Create),
+            ],
+        };
+        let desired = zone_records.desired("example.org", &PublicIp::new(None, None));
+        assert!(desired.iter().all(|r| r.is_owned()));
+
+        let changes = ChangeSet::replace_by_name("example.org", &desired, &existing);
+        assert_eq!(changes.changes.len(), 2);
+        let ids = changes.changes[0]
+            .before
+            .iter()
+            .map(|r| r.id.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["1", "2"]);
+
+        let planned = changes.planned();
+        assert_eq!(planned[0].op, PlanOp::Update);
+        assert_eq!(planned[1].name, "www.example.org");
+        assert_eq!(planned[1].op, PlanOp::Create);
+    }
+
+    #[test]
+    fn test_planned_change_op() {
+        let old = PlannedRecord::from(&RecordContent::A(Ipv4Addr::new(1, 1, 1, 1)));
+        let new = PlannedRecord::from(&RecordContent::A(Ipv4Addr::new(2, 2, 2, 2)));
+
+        let change = PlannedChange::new("example.org", "home.example.org", vec![], new.clone());
+        assert_eq!(change.op, PlanOp::Create);
+        let change = PlannedChange::new("example.org", "home.example.org", vec![old], new.clone());
+        assert_eq!(change.op, PlanOp::Update);
+        let change = PlannedChange::new("example.org", "home.example.org", vec![new.clone()], new);
+        assert_eq!(change.op, PlanOp::Unchanged);
+
+        let json = serde_json::to_value(&change).unwrap();
+        assert_eq!(json["op"], "unchanged");
+        assert_eq!(json["after"]["type"], "A");
+        assert_eq!(json["after"]["content"], "2.2.2.2");
+    }
+
+    #[test]
+    fn test_zone_sync_outcome_from_apply() {
+        let v4 = RecordContent::A(Ipv4Addr::new(1, 2, 3, 4));
+        let existing = vec![record("home.example.org", v4.clone(), RecordOp::Create).into()];
+        let zone_records = ZoneRecords {
+            records: vec![
+                record(
+                    "home",
+                    RecordContent::A(Ipv4Addr::new(2, 2, 2, 2)),
+                    RecordOp::Create,
+                ),
+                record("nas", v4.clone(), RecordOp::Create),
+                record("www", v4, RecordOp::Create),
+            ],
+        };
+        let desired = zone_records.desired("example.org", &PublicIp::new(None, None));
+        let changes = ChangeSet::replace_by_name("example.org", &desired, &existing);
+        fn statuses(outcome: &ZoneSyncOutcome) -> Vec<(&str, RecordStatus, Option<&str>)> {
+            outcome
+                .records
+                .iter()
+                .map(|r| (r.name.as_str(), r.status, r.error.as_deref()))
+                .collect()
+        }
+
+        // Only the record an error is about fails, the context around it
+        // and the wording of its source do not matter
+        let error = Error::from_errors(vec![
+            Error::Provider("refused".to_string())
+                .for_record("NAS.example.org")
+                .context("zone example.org"),
+        ])
+        .unwrap_err();
+        let outcome = ZoneSyncOutcome::from_apply(&changes, Some(&error));
+        assert_eq!(
+            statuses(&outcome),
+            vec![
+                ("home.example.org", RecordStatus::Updated, None),
+                (
+                    "nas.example.org",
+                    RecordStatus::Failed,
+                    Some("Provider error: refused")
+                ),
+                ("www.example.org", RecordStatus::Created, None),
+            ]
+        );
+
+        // An error of the whole zone fails every record
+        let error = Error::HttpStatus { status: 502 };
+        let outcome = ZoneSyncOutcome::from_apply(&changes, Some(&error));
+        assert!(
+            outcome
+                .records
+                .iter()
+                .all(|r| r.status == RecordStatus::Failed
+                    && r.error.as_deref() == Some("HTTP error: status: 502"))
+        );
+
+        // Without an error every record keeps the status of its change
+        let outcome = ZoneSyncOutcome::from_apply(&changes, None);
+        assert_eq!(
+            statuses(&outcome),
+            vec![
+                ("home.example.org", RecordStatus::Updated, None),
+                ("nas.example.org", RecordStatus::Created, None),
+                ("www.example.org", RecordStatus::Created, None),
+            ]
+        );
+        assert_eq!(outcome.zone, "example.org");
+    }
+
+    // Provider whose zones take a while to list, counting the zones listed
+    // at the same time
+    #[derive(Default)]
+    struct Slow {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Provider for Slow {
+        async fn list(&self, _zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(vec![])
+        }
+
+        async fn apply(&self, _changes: &ChangeSet, _cancel: &CancellationToken) -> Result<()> {
+            Ok(())
+        }
+
+        fn zone_concurrency(&self) -> usize {
+            2
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_zone_concurrency() {
+        let mut records = BackendRecords::default();
+        let zones = ["a.org", "b.org", "c.org", "d.org", "e.org"];
+        for zone in zones {
+            records.zones.insert(
+                zone.to_string(),
+                ZoneRecords {
+                    records: vec![record(
+                        "home",
+                        RecordContent::A(Ipv4Addr::new(1, 1, 1, 1)),
+                        RecordOp::default(),
+                    )],
+                },
+            );
+        }
+
+        let provider = Slow::default();
+        let outcome = provider
+            .sync(&records, &PublicIp::default(), &CancellationToken::new())
+            .await;
+        let synced = outcome
+            .zones
+            .iter()
+            .map(|z| z.zone.clone())
+            .collect::<Vec<_>>();
+        let expected = records.zones.keys().cloned().collect::<Vec<_>>();
+        assert_eq!(synced, expected);
+        assert_eq!(provider.max_in_flight.load(Ordering::SeqCst), 2);
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let outcome = provider.sync(&records, &PublicIp::default(), &cancel).await;
+        assert!(outcome.zones.is_empty());
+        assert!(matches!(outcome.errors.as_slice(), [Error::Cancelled]));
+    }
+}
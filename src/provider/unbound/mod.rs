@@ -0,0 +1,7 @@
+#[allow(clippy::module_inception)]
+mod unbound;
+pub use unbound::Unbound;
+pub use unbound::UnboundFactory;
+
+#[cfg(test)]
+mod unit_test;
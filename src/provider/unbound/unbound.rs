@@ -0,0 +1,514 @@
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AuthMethod;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ChangeSet;
+use crate::provider::ExistingRecord;
+use crate::provider::ParamList;
+use crate::provider::ParamSpec;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordChange;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::ZoneName;
+use crate::types::parse_duration;
+use crate::wrapper::unbound::Control;
+use crate::wrapper::unbound::ControlTls;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// `local-data` of an Unbound resolver, changed through its remote control
+// interface. Local data has no zones, the data of a zone is the one of its
+// name and of the names under it. It has no comment either, so records carry
+// no owner marker. Changes live in the memory of Unbound: a restart drops
+// them and the next sync adds them back.
+pub struct Unbound {
+    control: Control,
+    timeout: Duration,
+    // Commands sent so far, whatever came of them
+    requests: AtomicU64,
+}
+
+// Unbound providers from a `tls` authentication, or none to go through
+// unbound-control or to an interface with `control-use-cert: no`. Params of
+// the provider entry:
+//
+// - `server`, the remote control interface: a host name or address with an
+//   optional port (8953 by default), or the path of a unix socket
+// - `control`, the unbound-control command used without a `server`, like
+//   `unbound-control -c /etc/unbound/unbound.conf`, split on whitespace
+// - `timeout` of every command (10s by default)
+pub struct UnboundFactory;
+
+impl ProviderFactory for UnboundFactory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        let tls = control_tls(auth)?;
+        let mut server = None;
+        let mut control = None;
+        let mut timeout = DEFAULT_TIMEOUT;
+        for param in params.iter() {
+            match param.name.as_str() {
+                "server" => server = Some(param.value.clone()),
+                "control" => {
+                    control = Some(param.value.split_whitespace().map(String::from).collect())
+                }
+                "timeout" => timeout = parse_duration(&param.value)?,
+                _ => {
+                    return Err(Error::ParseError(format!(
+                        "{}: unknown param {}",
+                        name, param.name
+                    )));
+                }
+            }
+        }
+        let control = match (server, control) {
+            (Some(_), Some(_)) => {
+                return Err(Error::ParseError(format!(
+                    "{}: unbound takes either a server or a control command",
+                    name
+                )));
+            }
+            (Some(server), None) => Control::socket(&server, tls),
+            (None, _) if tls.is_some() => {
+                return Err(Error::ParseError(format!(
+                    "{}: unbound tls auth requires a server",
+                    name
+                )));
+            }
+            (None, control) => {
+                Control::Program(control.unwrap_or_else(|| vec!["unbound-control".to_string()]))
+            }
+        };
+        Ok(Box::new(Unbound::new(control).with_timeout(timeout)))
+    }
+}
+
+// Method `tls` with the `server_cert`, `control_cert` and `control_key` files
+// of unbound.conf, or none
+fn control_tls(auth: &AuthParams) -> Result<Option<ControlTls>> {
+    match auth.method.as_str() {
+        "" => Ok(None),
+        "tls" => match (
+            auth.get("server_cert"),
+            auth.get("control_cert"),
+            auth.get("control_key"),
+        ) {
+            (Some(server_cert), Some(control_cert), Some(control_key)) => {
+                Ok(Some(ControlTls::from_files(
+                    Path::new(server_cert),
+                    Path::new(control_cert),
+                    Path::new(control_key),
+                )?))
+            }
+            _ => Err(Error::Provider(
+                "unbound tls auth requires server_cert, control_cert and control_key".into(),
+            )),
+        },
+        method => Err(Error::Provider(format!(
+            "{}: unsupported authentication method for unbound provider",
+            method
+        ))),
+    }
+}
+
+impl Unbound {
+    pub fn new(control: Control) -> Self {
+        Self {
+            control,
+            timeout: DEFAULT_TIMEOUT,
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "unbound",
+            description: "Local data of an Unbound resolver",
+            record_types: vec![RecordType::A, RecordType::AAAA, RecordType::CNAME],
+            auth_methods: vec![AuthMethod {
+                name: "tls",
+                params: vec!["server_cert", "control_cert", "control_key"],
+            }],
+            params: vec![
+                ParamSpec {
+                    name: "server",
+                    description: "Remote control interface, host:port or a unix socket path",
+                },
+                ParamSpec {
+                    name: "control",
+                    description: "unbound-control command used without a server",
+                },
+                ParamSpec {
+                    name: "timeout",
+                    description: "Time a command may take (10s by default)",
+                },
+            ],
+            operations: vec![
+                "list",
+                "apply",
+                "dry_run",
+                "verify",
+                "list_records",
+                "list_zone_records",
+                "get_record",
+                "create_record",
+                "delete_record",
+                "delete_records",
+            ],
+        }
+    }
+
+    async fn call(&self, command: &str, input: &[String]) -> Result<String> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.control.call(command, input, self.timeout).await
+    }
+
+    // Every local data of the resolver, of any type
+    async fn local_data(&self) -> Result<Vec<LocalData>> {
+        let answer = self.call("list_local_data", &[]).await?;
+        Ok(answer.lines().filter_map(LocalData::parse).collect())
+    }
+
+    // Replace the local data of `names` by `lines`. Unbound removes a name
+    // with all its data, so the data of other types is added back.
+    async fn replace(
+        &self,
+        live: &[LocalData],
+        names: &[String],
+        lines: Vec<String>,
+    ) -> Result<()> {
+        let mut lines = lines;
+        lines.extend(
+            live.iter()
+                .filter(|d| names.contains(&d.name) && d.record().is_none())
+                .map(|d| d.line.clone()),
+        );
+        let names = names.iter().map(|n| format!("{}.", n)).collect::<Vec<_>>();
+        self.call("local_datas_remove", &names).await?;
+        if !lines.is_empty() {
+            self.call("local_datas", &lines).await?;
+        }
+        Ok(())
+    }
+
+    // Delete the records of `name`, of `record_type` when given. Local data
+    // carries no owner marker, so it takes `force`.
+    async fn delete_matching(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: Option<RecordType>,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        let live = self.local_data().await?;
+        let (deleted, kept): (Vec<_>, Vec<_>) = live
+            .iter()
+            .filter(|d| d.name == name && in_zone(&d.name, zone))
+            .filter_map(|d| d.record().map(|r| (d, r)))
+            .partition(|(_, r)| {
+                record_type
+                    .as_ref()
+                    .is_none_or(|t| r.content.record_type() == *t)
+            });
+        if deleted.is_empty() {
+            return Ok(vec![]);
+        }
+        if !force {
+            return Err(Error::Provider(format!(
+                "{} may not be managed by dns-syncer, unbound local data carries no owner \
+                 marker, force the deletion to remove it anyway",
+                name
+            )));
+        }
+        let lines = kept.iter().map(|(d, _)| d.line.clone()).collect();
+        self.replace(&live, &[name], lines).await?;
+        Ok(deleted.into_iter().map(|(_, r)| r).collect())
+    }
+}
+
+#[async_trait]
+impl Provider for Unbound {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        let live = self.local_data().await?;
+        Ok(live
+            .iter()
+            .filter(|d| in_zone(&d.name, zone))
+            .filter_map(LocalData::record)
+            .map(ExistingRecord::from)
+            .collect())
+    }
+
+    // The data of every changed name is removed and added back as desired,
+    // names already as desired are left alone. Records Unbound cannot serve
+    // fail alone, the data of their name is left as it is.
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let zone = &changes.zone;
+        if cancel.is_cancelled() {
+            log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+            return Err(Error::Cancelled);
+        }
+        let mut errors = vec![];
+        let mut diff = LocalDataDiff::new(&changes.changes);
+        for (name, e) in diff.rejected.drain(..) {
+            log::error!(
+                zone = zone, record = name, outcome = "failed";
+                "change of record {} failed: {}", name, e
+            );
+            errors.push(e.for_record(&name));
+        }
+        for name in diff.unchanged.iter() {
+            log::debug!(
+                zone = zone, record = name, outcome = "unchanged";
+                "record {} already up to date", name
+            );
+        }
+        if diff.names.is_empty() {
+            return Error::from_errors(errors);
+        }
+
+        let live = self.local_data().await?;
+        self.replace(&live, &diff.names, diff.lines.clone()).await?;
+        for line in diff.lines.iter() {
+            log::info!(zone = zone, outcome = "created"; "local data {} added", line);
+        }
+        Error::from_errors(errors)
+    }
+
+    // The interface answers its status, local data is not bound to zones
+    async fn verify(&self, _zones: &[ZoneName]) -> Result<()> {
+        self.call("status", &[]).await.map(|_| ())
+    }
+
+    // Add one local data, keeping the data of the name
+    async fn create_record(&self, zone: &ZoneName, record: &ProviderRecord) -> Result<()> {
+        let mut record = record.clone();
+        record.name = record.fqdn(zone);
+        let line = local_data_line(&record)?;
+        self.call(&format!("local_data {}", line), &[])
+            .await
+            .map(|_| ())
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, None, force).await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, name, Some(record_type), force)
+            .await
+    }
+
+    fn request_count(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+}
+
+// Whether `name` is `zone` or under it
+fn in_zone(name: &str, zone: &str) -> bool {
+    let zone = zone.trim_end_matches('.').to_ascii_lowercase();
+    name == zone || name.ends_with(&format!(".{}", zone))
+}
+
+// The local data line of a record, like `home.example.org. 300 IN A
+// 192.0.2.1`. Records without a TTL get the default of Unbound, 3600s.
+fn local_data_line(record: &ProviderRecord) -> Result<String> {
+    let name = record.name.trim_end_matches('.').to_ascii_lowercase();
+    if name.starts_with("*.") {
+        return Err(Error::RecordRejected {
+            record: record.name.clone(),
+            reason: "unbound local data has no wildcards".to_string(),
+        });
+    }
+    let content = match &record.content {
+        RecordContent::A(ip) => ip.to_string(),
+        RecordContent::AAAA(ip) => ip.to_string(),
+        RecordContent::CNAME(target) => format!("{}.", target.trim_end_matches('.')),
+        _ => {
+            return Err(Error::RecordRejected {
+                record: record.name.clone(),
+                reason: "unbound local data needs an address or a name".to_string(),
+            });
+        }
+    };
+    let ttl = match record.ttl.seconds() {
+        Some(ttl) => format!(" {}", ttl),
+        None => String::new(),
+    };
+    Ok(format!(
+        "{}.{} IN {} {}",
+        name,
+        ttl,
+        record.content.record_type().as_str(),
+        content
+    ))
+}
+
+///////////////////////////////////////////////////////////
+// Local data
+///////////////////////////////////////////////////////////
+// A line of `list_local_data`: `name. TTL IN TYPE RDATA`, tab separated
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct LocalData {
+    // Lowercase, without the trailing dot
+    pub name: String,
+    pub ttl: u32,
+    pub r#type: String,
+    pub rdata: String,
+    // As listed, to add it back
+    pub line: String,
+}
+
+impl LocalData {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let name = fields.next()?;
+        let ttl = fields.next()?.parse().ok()?;
+        if fields.next()? != "IN" {
+            return None;
+        }
+        let r#type = fields.next()?.to_string();
+        let rdata = fields.collect::<Vec<_>>().join(" ");
+        Some(Self {
+            name: name.trim_end_matches('.').to_ascii_lowercase(),
+            ttl,
+            r#type,
+            rdata,
+            line: line.trim().to_string(),
+        })
+    }
+
+    // The record of data of a synced type
+    pub fn record(&self) -> Option<ProviderRecord> {
+        let rdata = match self.r#type.as_str() {
+            "A" | "AAAA" => self.rdata.clone(),
+            "CNAME" => self.rdata.trim_end_matches('.').to_ascii_lowercase(),
+            _ => return None,
+        };
+        Some(ProviderRecord {
+            name: self.name.clone(),
+            content: RecordContent::parse(&self.r#type, Some(&rdata)).ok()?,
+            comment: None,
+            op: RecordOp::default(),
+            ttl: TTL::Value(self.ttl),
+            params: vec![],
+        })
+    }
+}
+
+// Names whose local data changes and their new data
+#[derive(Debug, Default)]
+pub(super) struct LocalDataDiff {
+    // Desired names served as they are
+    pub unchanged: Vec<String>,
+    // Names whose data is replaced
+    pub names: Vec<String>,
+    // Local data lines of the replaced names
+    pub lines: Vec<String>,
+    // Names with a record Unbound cannot serve, left as they are
+    pub rejected: Vec<(String, Error)>,
+}
+
+impl LocalDataDiff {
+    pub fn new(changes: &[RecordChange]) -> Self {
+        let mut ret = LocalDataDiff::default();
+        // Desired records by name, in the order of the changes
+        let mut names: Vec<(String, Vec<&ProviderRecord>)> = vec![];
+        for change in changes.iter() {
+            let name = change.after.name.trim_end_matches('.').to_ascii_lowercase();
+            match names.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, records)) => records.push(&change.after),
+                None => names.push((name, vec![&change.after])),
+            }
+        }
+
+        for (name, desired) in names.into_iter() {
+            let lines = match desired
+                .iter()
+                .map(|r| local_data_line(r))
+                .collect::<Result<Vec<_>>>()
+            {
+                Ok(lines) => lines,
+                Err(e) => {
+                    ret.rejected.push((desired[0].name.clone(), e));
+                    continue;
+                }
+            };
+            let live = changes
+                .iter()
+                .find(|c| c.after.name.eq_ignore_ascii_case(&name))
+                .map(|c| c.before.iter().map(|e| &e.record).collect::<Vec<_>>())
+                .unwrap_or_default();
+            if serves(&live, &desired) {
+                ret.unchanged.push(name);
+                continue;
+            }
+            ret.names.push(name);
+            for line in lines {
+                if !ret.lines.contains(&line) {
+                    ret.lines.push(line);
+                }
+            }
+        }
+        ret
+    }
+}
+
+// Whether the live records of a name are the desired ones: each desired
+// record served by one of them, with its TTL unless it leaves it to Unbound,
+// and none other
+fn serves(live: &[&ProviderRecord], desired: &[&ProviderRecord]) -> bool {
+    let mut live = live.to_vec();
+    for record in desired.iter() {
+        let served = live.iter().position(|r| {
+            r.content == record.content && (record.ttl == TTL::Auto || r.ttl == record.ttl)
+        });
+        match served {
+            Some(pos) => {
+                live.remove(pos);
+            }
+            // Duplicate desired records are served by the same data
+            None if desired
+                .iter()
+                .filter(|r| r.content == record.content)
+                .count()
+                > 1 => {}
+            None => return false,
+        }
+    }
+    live.is_empty()
+}
@@ -0,0 +1,334 @@
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+
+use super::unbound::*;
+use crate::provider::AuthParams;
+use crate::provider::BackendRecords;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordStatus;
+use crate::provider::ZoneRecords;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::Param;
+use crate::types::PublicIp;
+use crate::wrapper::unbound::Control;
+
+// Local data and the commands received by a control interface
+#[derive(Default)]
+struct ControlState {
+    data: Vec<String>,
+    commands: Vec<String>,
+}
+
+// A remote control interface without TLS keeping local data like Unbound:
+// home.example.org with an address and a text record, and a name of another
+// zone. It refuses local data of bad.example.org.
+async fn control_server() -> (Unbound, Arc<Mutex<ControlState>>) {
+    let state = Arc::new(Mutex::new(ControlState {
+        data: vec![
+            "home.example.org.\t300\tIN\tA\t1.1.1.1".to_string(),
+            "home.example.org.\t3600\tIN\tTXT\t\"v=spf1 -all\"".to_string(),
+            "nas.example.net.\t3600\tIN\tA\t10.0.0.2".to_string(),
+        ],
+        ..Default::default()
+    }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = listener.local_addr().unwrap().to_string();
+    let served = state.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let request = lines.next_line().await.unwrap().unwrap();
+            let command = request.strip_prefix("UBCT1 ").unwrap().to_string();
+            let mut input = vec![];
+            if command == "local_datas" || command == "local_datas_remove" {
+                while let Some(line) = lines.next_line().await.unwrap() {
+                    if line == "\x04" {
+                        break;
+                    }
+                    input.push(line);
+                }
+            }
+            let answer = answer(&mut served.lock().unwrap(), &command, input);
+            writer.write_all(answer.as_bytes()).await.unwrap();
+        }
+    });
+    (Unbound::new(Control::socket(&server, None)), state)
+}
+
+fn answer(state: &mut ControlState, command: &str, input: Vec<String>) -> String {
+    state.commands.push(command.to_string());
+    let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+    let add = |data: &mut Vec<String>, line: &str| {
+        if line.contains("bad.example.org") {
+            return "error parsing local-data\n".to_string();
+        }
+        // Listed like Unbound does, the TTL defaulting to 3600s
+        let has_ttl = line
+            .split_whitespace()
+            .nth(1)
+            .unwrap()
+            .parse::<u32>()
+            .is_ok();
+        let mut fields = vec![];
+        let mut rest = line.trim();
+        while fields.len() < if has_ttl { 4 } else { 3 } {
+            let (field, tail) = rest.split_once(char::is_whitespace).unwrap();
+            fields.push(field);
+            rest = tail.trim_start();
+        }
+        if !has_ttl {
+            fields.insert(1, "3600");
+        }
+        let line = format!("{}\t{}", fields.join("\t"), rest);
+        data.push(line);
+        "ok\n".to_string()
+    };
+    match command {
+        "status" => "version: 1.19.0\nis running...\n".to_string(),
+        "list_local_data" => state.data.iter().map(|l| format!("{}\n", l)).collect(),
+        "local_data" => add(&mut state.data, arg),
+        "local_datas" => input.iter().map(|l| add(&mut state.data, l)).collect(),
+        "local_datas_remove" => {
+            state
+                .data
+                .retain(|d| !input.iter().any(|n| d.starts_with(&format!("{}\t", n))));
+            "ok\n".to_string()
+        }
+        _ => "error unknown command\n".to_string(),
+    }
+}
+
+fn desired(zone: &str, names: &[&str]) -> BackendRecords {
+    let mut records = BackendRecords::default();
+    records.zones.insert(
+        zone.to_string(),
+        ZoneRecords {
+            records: names
+                .iter()
+                .map(|name| ProviderRecord {
+                    name: name.to_string(),
+                    content: RecordContent::Unassigned(RecordType::A),
+                    comment: None,
+                    ttl: TTL::Value(300),
+                    op: RecordOp::Create,
+                    params: vec![],
+                })
+                .collect(),
+        },
+    );
+    records
+}
+
+#[test]
+fn test_local_data_parse() {
+    let data = LocalData::parse("www.example.org.\t3600\tIN\tCNAME\tHome.Example.org.").unwrap();
+    let record = data.record().unwrap();
+    assert_eq!(record.name, "www.example.org");
+    assert_eq!(record.content.to_string(), "home.example.org");
+    assert_eq!(record.ttl, TTL::Value(3600));
+
+    // Data of other types is listed but not synced
+    let data = LocalData::parse("home.example.org.\t3600\tIN\tTXT\t\"v=spf1 -all\"").unwrap();
+    assert_eq!(data.rdata, "\"v=spf1 -all\"");
+    assert!(data.record().is_none());
+    assert!(LocalData::parse("ok").is_none());
+}
+
+#[tokio::test]
+async fn test_unbound_list() {
+    let (provider, _) = control_server().await;
+
+    let records = provider.list(&"example.org".to_string()).await.unwrap();
+    let records = records
+        .iter()
+        .map(|r| (r.record.name.as_str(), r.record.content.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(records, vec![("home.example.org", "1.1.1.1".to_string())]);
+    assert!(provider.verify(&[]).await.is_ok());
+    assert_eq!(provider.request_count(), 2);
+}
+
+#[tokio::test]
+async fn test_unbound_sync() {
+    let (provider, state) = control_server().await;
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    let outcome = provider
+        .sync(
+            &desired("example.org", &["home", "nas"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Updated);
+    assert_eq!(outcome.zones[0].records[1].status, RecordStatus::Created);
+
+    // Changed names are replaced, their data of other types kept
+    assert_eq!(
+        state.lock().unwrap().data,
+        vec![
+            "nas.example.net.\t3600\tIN\tA\t10.0.0.2",
+            "home.example.org.\t300\tIN\tA\t2.2.2.2",
+            "nas.example.org.\t300\tIN\tA\t2.2.2.2",
+            "home.example.org.\t3600\tIN\tTXT\t\"v=spf1 -all\"",
+        ]
+    );
+
+    // Names already as desired send nothing but the list
+    let commands = state.lock().unwrap().commands.len();
+    let outcome = provider
+        .sync(
+            &desired("example.org", &["home", "nas"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty());
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Unchanged);
+    assert_eq!(
+        state.lock().unwrap().commands[commands..],
+        ["list_local_data"]
+    );
+}
+
+#[tokio::test]
+async fn test_unbound_errors() {
+    let (provider, state) = control_server().await;
+    let zone = "example.org".to_string();
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    // Local data has no owner, deletes take `force`
+    let err = provider
+        .delete_record(&zone, "home.example.org", RecordType::A, false)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("no owner marker"), "{}", err);
+    let deleted = provider
+        .delete_record(&zone, "home.example.org", RecordType::A, true)
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert!(state.lock().unwrap().data.iter().any(|d| d.contains("TXT")));
+
+    // Wildcards fail alone
+    let outcome = provider
+        .sync(
+            &desired(&zone, &["*", "nas"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(err.contains("no wildcards"), "{}", err);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Failed);
+    assert_eq!(outcome.zones[0].records[1].status, RecordStatus::Created);
+
+    // Errors answered by Unbound fail the call
+    let outcome = provider
+        .sync(
+            &desired(&zone, &["bad"]),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(
+        err.contains("unbound local_datas failed: error parsing local-data"),
+        "{}",
+        err
+    );
+
+    let provider = Unbound::new(Control::socket("127.0.0.1:1", None));
+    assert!(provider.verify(&[]).await.is_err());
+}
+
+#[test]
+fn test_unbound_factory() {
+    let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+    let factory = UnboundFactory;
+    let server = param("server", "127.0.0.1:8953");
+
+    assert!(
+        factory
+            .create(
+                "unbound-1",
+                &AuthParams::default(),
+                &vec![server.clone(), param("timeout", "5s")],
+            )
+            .is_ok()
+    );
+    assert!(
+        factory
+            .create("unbound-1", &AuthParams::default(), &vec![])
+            .is_ok()
+    );
+    let err = factory
+        .create(
+            "unbound-1",
+            &AuthParams::default(),
+            &vec![server.clone(), param("control", "unbound-control")],
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("either a server or a control"));
+    let err = factory
+        .create(
+            "unbound-1",
+            &AuthParams::default(),
+            &vec![server.clone(), param("ttl", "60")],
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("unknown param ttl"));
+    let err = factory
+        .create(
+            "unbound-1",
+            &AuthParams::new("tls", vec![]),
+            &vec![server.clone()],
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("requires server_cert"));
+    let err = factory
+        .create(
+            "unbound-1",
+            &AuthParams::new(
+                "tls",
+                vec![
+                    param("server_cert", "/nonexistent/unbound_server.pem"),
+                    param("control_cert", "/nonexistent/unbound_control.pem"),
+                    param("control_key", "/nonexistent/unbound_control.key"),
+                ],
+            ),
+            &vec![server.clone()],
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("/nonexistent/unbound_server.pem"));
+    let err = factory
+        .create(
+            "unbound-1",
+            &AuthParams::new("bearer", vec![]),
+            &vec![server],
+        )
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string()
+            .contains("unsupported authentication method")
+    );
+}
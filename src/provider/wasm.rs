@@ -0,0 +1,294 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde_json::json;
+use wasmtime::Engine;
+use wasmtime::Store;
+use wasmtime::component::Component;
+use wasmtime::component::ComponentExportIndex;
+use wasmtime::component::Instance;
+use wasmtime::component::Linker;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ParamList;
+use crate::provider::ParamSpec;
+use crate::provider::PluginInstance;
+use crate::provider::PluginProvider;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::record::RecordType;
+
+// Instance exporting the `records` interface of wit/provider.wit
+const RECORDS_INTERFACE: &str = "dns-syncer:provider/records@0.1.0";
+
+// A WebAssembly component of the `world provider` of wit/provider.wit, run by
+// wasmtime. Calls go one at a time, on a blocking thread.
+pub struct WasmPlugin {
+    loaded: Arc<Mutex<Loaded>>,
+}
+
+struct Loaded {
+    store: Store<()>,
+    instance: Instance,
+    records: ComponentExportIndex,
+}
+
+// Plugin providers load the component of their `module` param, the other
+// params and the authentication go to its `init`
+pub struct WasmPluginFactory;
+
+impl ProviderFactory for WasmPluginFactory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        let module = params
+            .iter()
+            .find(|p| p.name == "module")
+            .ok_or(Error::ParseError(format!(
+                "{}: plugin requires a module",
+                name
+            )))?;
+        let pairs = |params: &ParamList| {
+            params
+                .iter()
+                .filter(|p| p.name != "module")
+                .map(|p| json!({ "name": p.name, "value": p.value }))
+                .collect::<Vec<_>>()
+        };
+        let config = json!({
+            "name": name,
+            "auth": { "method": auth.method, "params": pairs(&auth.params) },
+            "params": pairs(params),
+        });
+        let plugin = WasmPlugin::load(Path::new(&module.value), &config.to_string())?;
+        Ok(Box::new(PluginProvider::new(name, Arc::new(plugin))))
+    }
+}
+
+impl WasmPlugin {
+    // Compile and instantiate the component at `path`, then `init` it with
+    // `config`
+    pub fn load(path: &Path, config: &str) -> Result<Self> {
+        let plugin_error =
+            |e: wasmtime::Error| Error::Provider(format!("plugin {}: {:#}", path.display(), e));
+        let engine = Engine::default();
+        let component = Component::from_file(&engine, path).map_err(plugin_error)?;
+        let mut store = Store::new(&engine, ());
+        let instance = Linker::new(&engine)
+            .instantiate(&mut store, &component)
+            .map_err(plugin_error)?;
+        let records = instance
+            .get_export_index(&mut store, None, RECORDS_INTERFACE)
+            .ok_or(Error::Provider(format!(
+                "plugin {}: no {} export",
+                path.display(),
+                RECORDS_INTERFACE
+            )))?;
+        let mut loaded = Loaded {
+            store,
+            instance,
+            records,
+        };
+        loaded
+            .call("init", config)
+            .map_err(|e| Error::Provider(format!("plugin {} init: {}", path.display(), e)))?;
+        Ok(Self {
+            loaded: Arc::new(Mutex::new(loaded)),
+        })
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "plugin",
+            description: "WebAssembly component of wit/provider.wit",
+            record_types: vec![RecordType::A, RecordType::AAAA, RecordType::CNAME],
+            auth_methods: vec![],
+            params: vec![ParamSpec {
+                name: "module",
+                description: "Path of the .wasm component, other params go to its init",
+            }],
+            operations: vec!["list", "apply", "dry_run"],
+        }
+    }
+}
+
+impl Loaded {
+    // Call an export of the records interface. `list` answers a string, the
+    // other exports nothing.
+    fn call(&mut self, export: &str, input: &str) -> std::result::Result<String, String> {
+        let index = self
+            .instance
+            .get_export_index(&mut self.store, Some(&self.records), export)
+            .ok_or(format!("no {} export", export))?;
+        let trap = |e: wasmtime::Error| format!("{:#}", e);
+        if export == "list" {
+            let func = self
+                .instance
+                .get_typed_func::<(&str,), (std::result::Result<String, String>,)>(
+                    &mut self.store,
+                    index,
+                )
+                .map_err(trap)?;
+            let (ret,) = func.call(&mut self.store, (input,)).map_err(trap)?;
+            func.post_return(&mut self.store).map_err(trap)?;
+            ret
+        } else {
+            let func = self
+                .instance
+                .get_typed_func::<(&str,), (std::result::Result<(), String>,)>(
+                    &mut self.store,
+                    index,
+                )
+                .map_err(trap)?;
+            let (ret,) = func.call(&mut self.store, (input,)).map_err(trap)?;
+            func.post_return(&mut self.store).map_err(trap)?;
+            ret.map(|_| String::new())
+        }
+    }
+}
+
+#[async_trait]
+impl PluginInstance for WasmPlugin {
+    async fn call(&self, export: &str, input: &str) -> std::result::Result<String, String> {
+        let loaded = self.loaded.clone();
+        let (export, input) = (export.to_string(), input.to_string());
+        tokio::task::spawn_blocking(move || {
+            let mut loaded = loaded.lock().unwrap_or_else(|e| e.into_inner());
+            loaded.call(&export, &input)
+        })
+        .await
+        .map_err(|e| format!("plugin task failed: {}", e))?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::provider::PLUGIN_ZONE_NOT_FOUND;
+    use crate::record::ProviderRecord;
+    use crate::record::RecordContent;
+    use crate::types::Param;
+
+    const LISTED: &str =
+        r#"[{"id":"r1","record":{"name":"home.example.org","type":"A","content":"192.0.2.9"}}]"#;
+
+    // A component serving example.org with one record, failing `list` of
+    // other zones and accepting every `init` and `apply`
+    fn component() -> String {
+        format!(
+            r#"(component
+  (core module $m
+    (memory (export "memory") 1)
+    (global $heap (mut i32) (i32.const 4096))
+    (data (i32.const 256) "{listed}")
+    (data (i32.const 128) "{not_found}")
+    (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+      (local $ret i32)
+      (local.set $ret (global.get $heap))
+      (global.set $heap (i32.add (global.get $heap) (local.get 3)))
+      (local.get $ret))
+    (func (export "init") (param i32 i32) (result i32)
+      (i32.store8 (i32.const 64) (i32.const 0))
+      (i32.const 64))
+    (func (export "apply") (param i32 i32) (result i32)
+      (i32.store8 (i32.const 64) (i32.const 0))
+      (i32.const 64))
+    ;; "example.org" is the zone of 11 bytes
+    (func (export "list") (param i32 i32) (result i32)
+      (if (i32.eq (local.get 1) (i32.const 11))
+        (then
+          (i32.store8 (i32.const 64) (i32.const 0))
+          (i32.store (i32.const 68) (i32.const 256))
+          (i32.store (i32.const 72) (i32.const {listed_len})))
+        (else
+          (i32.store8 (i32.const 64) (i32.const 1))
+          (i32.store (i32.const 68) (i32.const 128))
+          (i32.store (i32.const 72) (i32.const {not_found_len}))))
+      (i32.const 64)))
+  (core instance $i (instantiate $m))
+  (func $init (param "config" string) (result (result (error string)))
+    (canon lift (core func $i "init") (memory $i "memory") (realloc (func $i "realloc"))))
+  (func $list (param "zone" string) (result (result string (error string)))
+    (canon lift (core func $i "list") (memory $i "memory") (realloc (func $i "realloc"))))
+  (func $apply (param "changes" string) (result (result (error string)))
+    (canon lift (core func $i "apply") (memory $i "memory") (realloc (func $i "realloc"))))
+  (instance $records
+    (export "init" (func $init))
+    (export "list" (func $list))
+    (export "apply" (func $apply)))
+  (export "{interface}" (instance $records)))"#,
+            listed = LISTED.replace('"', "\\\""),
+            listed_len = LISTED.len(),
+            not_found = PLUGIN_ZONE_NOT_FOUND,
+            not_found_len = PLUGIN_ZONE_NOT_FOUND.len(),
+            interface = RECORDS_INTERFACE,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_wasm_plugin() {
+        let dir = std::env::temp_dir().join(format!("dns-syncer-wasm-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let module = dir.join("records.wasm");
+        fs::write(&module, wat::parse_str(component()).unwrap()).unwrap();
+
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+        let provider = WasmPluginFactory
+            .create(
+                "plugin-1",
+                &AuthParams::default(),
+                &vec![param("module", &module.display().to_string())],
+            )
+            .unwrap();
+
+        let zone = "example.org".to_string();
+        let existing = provider.list(&zone).await.unwrap();
+        assert_eq!(existing[0].id.as_deref(), Some("r1"));
+        assert_eq!(existing[0].record.content.to_string(), "192.0.2.9");
+        assert!(matches!(
+            provider.list(&"example.nl".to_string()).await,
+            Err(Error::ZoneNotFound { .. })
+        ));
+
+        let desired = ProviderRecord::builder()
+            .name("home.example.org")
+            .value(RecordContent::A("192.0.2.1".parse().unwrap()))
+            .build()
+            .unwrap();
+        let changes = provider.plan(&zone, &[desired], &existing);
+        provider
+            .apply(&changes, &CancellationToken::new())
+            .await
+            .unwrap();
+
+        let err = WasmPluginFactory
+            .create("plugin-1", &AuthParams::default(), &vec![])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("requires a module"));
+        let err = WasmPluginFactory
+            .create(
+                "plugin-1",
+                &AuthParams::default(),
+                &vec![param(
+                    "module",
+                    &dir.join("missing.wasm").display().to_string(),
+                )],
+            )
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("missing.wasm"), "{}", err);
+    }
+}
@@ -0,0 +1,12 @@
+#[allow(clippy::module_inception)]
+mod webhook;
+pub(super) use webhook::Changes;
+pub use webhook::Webhook;
+pub use webhook::WebhookAuth;
+pub(super) use webhook::WebhookDiff;
+pub use webhook::WebhookFactory;
+pub(super) use webhook::rejected_error;
+pub(super) use webhook::rejections;
+
+#[cfg(test)]
+mod unit_test;
@@ -0,0 +1,266 @@
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use tokio_util::sync::CancellationToken;
+
+use super::webhook::*;
+use crate::error::Error;
+use crate::http::MemoryTransport;
+use crate::http::Method;
+use crate::provider::AuthParams;
+use crate::provider::BackendRecords;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordStatus;
+use crate::provider::ZoneRecords;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::Param;
+use crate::types::PublicIp;
+
+const URL: &str = "http://127.0.0.1:8888/dns";
+
+fn auth() -> WebhookAuth {
+    WebhookAuth::Bearer {
+        token: "token".to_string(),
+    }
+}
+
+// An endpoint serving example.org with an owned address and a record made by
+// hand, without the optional zone list
+fn memory_endpoint() -> MemoryTransport {
+    let transport = MemoryTransport::new();
+    transport.route(
+        Method::Get,
+        &format!("{}/records?zone=example.org", URL),
+        200,
+        r#"[
+  {"name": "home.example.org", "type": "A", "content": "1.1.1.1", "comment": "[dns-syncer]", "ttl": 300},
+  {"name": "home.example.org", "type": "AAAA", "content": "2001:db8::1", "comment": "[dns-syncer]", "ttl": 300},
+  {"name": "www.example.org", "type": "CNAME", "content": "home.example.org", "ttl": "auto"}
+]"#,
+    );
+    transport.route(Method::Post, &format!("{}/records", URL), 200, "");
+    transport
+}
+
+fn desired(zone: &str, name: &str) -> BackendRecords {
+    let mut records = BackendRecords::default();
+    records.zones.insert(
+        zone.to_string(),
+        ZoneRecords {
+            records: vec![ProviderRecord {
+                name: name.to_string(),
+                content: RecordContent::Unassigned(RecordType::A),
+                comment: None,
+                ttl: TTL::Value(300),
+                op: RecordOp::Create,
+                params: vec![],
+            }],
+        },
+    );
+    records
+}
+
+#[tokio::test]
+async fn test_webhook_list() {
+    let transport = memory_endpoint();
+    let provider = Webhook::with_transport(URL, auth(), Arc::new(transport.clone()));
+
+    let records = provider.list(&"example.org".to_string()).await.unwrap();
+    let records = records
+        .iter()
+        .map(|r| (r.record.name.as_str(), r.record.content.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        records,
+        vec![
+            ("home.example.org", "1.1.1.1".to_string()),
+            ("home.example.org", "2001:db8::1".to_string()),
+            ("www.example.org", "home.example.org".to_string()),
+        ]
+    );
+    let request = transport.requests().pop().unwrap();
+    assert_eq!(request.url, format!("{}/records?zone=example.org", URL));
+    let auth = request
+        .headers
+        .iter()
+        .find(|h| h.name() == "Authorization")
+        .unwrap();
+    assert_eq!(auth.value(), "Bearer token");
+
+    // Zones the endpoint does not have, and an endpoint without the zone list
+    let err = provider.list(&"example.net".to_string()).await.unwrap_err();
+    assert!(matches!(err, Error::ZoneNotFound { .. }));
+    let err = provider.list_zones().await.unwrap_err();
+    assert!(matches!(err, Error::NotImplemente));
+    transport.route(
+        Method::Get,
+        &format!("{}/zones", URL),
+        200,
+        r#"["example.org"]"#,
+    );
+    assert_eq!(provider.list_zones().await.unwrap(), vec!["example.org"]);
+}
+
+#[tokio::test]
+async fn test_webhook_sync() {
+    let transport = memory_endpoint();
+    let provider = Webhook::with_transport(URL, auth(), Arc::new(transport.clone()));
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+
+    let outcome = provider
+        .sync(
+            &desired("example.org", "home"),
+            &public_ip,
+            &CancellationToken::new(),
+        )
+        .await;
+    assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Updated);
+
+    // The changes of the zone go in one request
+    let posts = transport
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == Method::Post)
+        .collect::<Vec<_>>();
+    assert_eq!(posts.len(), 1);
+    let body: serde_json::Value = serde_json::from_str(posts[0].body.as_ref().unwrap()).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "zone": "example.org",
+            "records": [
+                {"name": "home.example.org", "type": "A", "content": "2.2.2.2",
+                 "comment": "[dns-syncer]", "op": "purge", "ttl": 300},
+            ],
+            "deletes": [
+                {"name": "home.example.org", "type": "A", "content": "1.1.1.1",
+                 "comment": "[dns-syncer]", "op": "create", "ttl": 300},
+                {"name": "home.example.org", "type": "AAAA", "content": "2001:db8::1",
+                 "comment": "[dns-syncer]", "op": "create", "ttl": 300},
+            ],
+        })
+    );
+
+    // Records already as desired send nothing
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(1, 1, 1, 1)), None);
+    let requests = transport.requests().len();
+    let mut records = desired("example.org", "home");
+    records.zones.get_mut("example.org").unwrap().records[0].ttl = TTL::Auto;
+    let outcome = provider
+        .sync(&records, &public_ip, &CancellationToken::new())
+        .await;
+    assert!(outcome.errors.is_empty());
+    let posts = transport.requests()[requests..]
+        .iter()
+        .filter(|r| r.method == Method::Post)
+        .map(|r| r.body.clone().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(posts.len(), 1);
+    assert!(!posts[0].contains("\"1.1.1.1\""));
+    assert!(posts[0].contains("2001:db8::1"));
+}
+
+#[tokio::test]
+async fn test_webhook_errors() {
+    let transport = memory_endpoint();
+    let provider = Webhook::with_transport(URL, auth(), Arc::new(transport.clone()));
+    let zone = "example.org".to_string();
+
+    // Records made by hand are deleted with `force` only
+    let deleted = provider
+        .delete_record(&zone, "www.example.org", RecordType::CNAME, false)
+        .await
+        .unwrap();
+    assert!(deleted.is_empty());
+    let deleted = provider
+        .delete_record(&zone, "www.example.org", RecordType::CNAME, true)
+        .await
+        .unwrap();
+    assert_eq!(deleted.len(), 1);
+
+    // A rejected record fails alone
+    transport.route(
+        Method::Post,
+        &format!("{}/records", URL),
+        200,
+        r#"{"rejected": [{"name": "home.example.org", "reason": "address out of range"}]}"#,
+    );
+    let mut records = desired(&zone, "home");
+    records
+        .zones
+        .get_mut(&zone)
+        .unwrap()
+        .records
+        .extend(desired(&zone, "nas").zones[&zone].records.clone());
+    let public_ip = PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None);
+    let outcome = provider
+        .sync(&records, &public_ip, &CancellationToken::new())
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(err.contains("address out of range"), "{}", err);
+    assert_eq!(outcome.zones[0].records[0].status, RecordStatus::Failed);
+    assert_eq!(outcome.zones[0].records[1].status, RecordStatus::Created);
+
+    transport.route(
+        Method::Post,
+        &format!("{}/records", URL),
+        422,
+        "unsupported type\n",
+    );
+    let outcome = provider
+        .sync(&records, &public_ip, &CancellationToken::new())
+        .await;
+    let err = outcome.errors[0].to_string();
+    assert!(
+        err.contains("webhook call failed: unsupported type"),
+        "{}",
+        err
+    );
+
+    transport.route(
+        Method::Get,
+        &format!("{}/records?zone=example.org", URL),
+        401,
+        "",
+    );
+    let err = provider.verify(&[zone]).await.unwrap_err();
+    assert!(err.is_auth());
+    assert!(err.to_string().contains("webhook bearer token"));
+}
+
+#[test]
+fn test_webhook_factory() {
+    let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+    let factory = WebhookFactory;
+    let bearer = AuthParams::new("bearer", vec![param("token", "token")]);
+    let url = vec![param("url", URL)];
+
+    assert!(factory.create("webhook-1", &bearer, &url).is_ok());
+    assert!(
+        factory
+            .create("webhook-1", &AuthParams::default(), &url)
+            .is_ok()
+    );
+    let err = factory.create("webhook-1", &bearer, &vec![]).err().unwrap();
+    assert!(err.to_string().contains("requires a url"));
+    let err = factory
+        .create(
+            "webhook-1",
+            &bearer,
+            &vec![param("url", URL), param("ttl", "60")],
+        )
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("unknown param ttl"));
+    let err = factory
+        .create("webhook-1", &AuthParams::new("bearer", vec![]), &url)
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("requires a token"));
+}
@@ -0,0 +1,506 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::AuthMethod;
+use crate::provider::AuthParams;
+use crate::provider::Capabilities;
+use crate::provider::ChangeSet;
+use crate::provider::ExistingRecord;
+use crate::provider::ParamList;
+use crate::provider::ParamSpec;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::RecordChange;
+use crate::record::ProviderRecord;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::types::ZoneName;
+use crate::wrapper::http;
+
+#[derive(Debug, Clone)]
+pub enum WebhookAuth {
+    // Sent as `Authorization: Bearer <token>`
+    Bearer { token: String },
+    // An endpoint without authentication, or behind a proxy taking care of it
+    None,
+}
+
+// An HTTP endpoint bridging to a backend dns-syncer has no provider for.
+// Records cross as JSON, the way `--json` prints them:
+//
+// - `GET <url>/records?zone=<zone>` answers the records the zone serves, or
+//   404 when the endpoint has no such zone
+// - `POST <url>/records` gets `{"zone": ..., "records": [...], "deletes":
+//   [...]}`, the records to create and the ones to delete, a changed record
+//   being in both. It may answer `{"rejected": [{"name": ..., "reason": ...}]}`
+//   for records it refused while making the other changes.
+// - `GET <url>/zones` optionally answers the names of the zones, for zone
+//   patterns
+pub struct Webhook {
+    cli: Cli,
+    auth: WebhookAuth,
+}
+
+// Webhook providers from a `bearer` authentication, or none. The only param
+// of the provider entry is `url`, the address the paths of the protocol are
+// appended to, like `http://127.0.0.1:8888/dns`.
+pub struct WebhookFactory;
+
+impl ProviderFactory for WebhookFactory {
+    fn create(
+        &self,
+        name: &str,
+        auth: &AuthParams,
+        params: &ParamList,
+    ) -> Result<Box<dyn Provider>> {
+        let mut url = None;
+        for param in params.iter() {
+            match param.name.as_str() {
+                "url" => url = Some(param.value.clone()),
+                _ => {
+                    return Err(Error::ParseError(format!(
+                        "{}: unknown param {}",
+                        name, param.name
+                    )));
+                }
+            }
+        }
+        let url =
+            url.ok_or_else(|| Error::ParseError(format!("{}: webhook requires a url", name)))?;
+        Ok(Box::new(Webhook::new(
+            &url,
+            WebhookAuth::new_with_params(auth)?,
+        )?))
+    }
+}
+
+impl Webhook {
+    pub fn new(url: &str, auth: WebhookAuth) -> Result<Self> {
+        let transport = Arc::new(http::ReqwestTransport::new()?);
+        Ok(Self::with_transport(url, auth, transport))
+    }
+
+    // Provider sending its requests through `transport`, like a
+    // `MemoryTransport` answering canned endpoint responses in tests
+    pub fn with_transport(
+        url: &str,
+        auth: WebhookAuth,
+        transport: Arc<dyn http::HttpTransport>,
+    ) -> Self {
+        Self {
+            cli: Cli::new(url, &auth, transport),
+            auth,
+        }
+    }
+
+    pub fn capabilities() -> Capabilities {
+        Capabilities {
+            r#type: "webhook",
+            description: "Records sent as JSON to an HTTP endpoint",
+            record_types: vec![RecordType::A, RecordType::AAAA, RecordType::CNAME],
+            auth_methods: vec![AuthMethod {
+                name: "bearer",
+                params: vec!["token"],
+            }],
+            params: vec![ParamSpec {
+                name: "url",
+                description: "Address of the endpoint the paths of the protocol are appended to",
+            }],
+            operations: vec![
+                "list",
+                "apply",
+                "dry_run",
+                "verify",
+                "list_zones",
+                "list_records",
+                "list_zone_records",
+                "get_record",
+                "create_record",
+                "delete_record",
+                "delete_records",
+                "delete_owned_records",
+            ],
+        }
+    }
+
+    // Delete the records of a zone matching `delete` and return them
+    async fn delete_matching(
+        &self,
+        zone: &ZoneName,
+        delete: impl Fn(&ProviderRecord) -> bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let deletes = self
+            .cli
+            .records(zone)
+            .await?
+            .into_iter()
+            .filter(|r| delete(r))
+            .collect::<Vec<_>>();
+        if deletes.is_empty() {
+            return Ok(vec![]);
+        }
+        let rejected = self
+            .cli
+            .post_changes(&Changes {
+                zone,
+                records: vec![],
+                deletes: deletes.iter().collect(),
+            })
+            .await?;
+        rejected_error(rejected)?;
+        Ok(deletes)
+    }
+
+    fn auth_error(&self, e: Error) -> Error {
+        match e {
+            Error::Auth { .. } => Error::Auth {
+                provider: String::new(),
+                reason: format!("{} is rejected", self.auth.describe()),
+            },
+            e => e,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for Webhook {
+    async fn list(&self, zone: &ZoneName) -> Result<Vec<ExistingRecord>> {
+        let records = self.cli.records(zone).await?;
+        Ok(records.into_iter().map(ExistingRecord::from).collect())
+    }
+
+    // All changes of a zone go in one request, records the endpoint rejects
+    // fail alone
+    async fn apply(&self, changes: &ChangeSet, cancel: &CancellationToken) -> Result<()> {
+        let zone = &changes.zone;
+        if cancel.is_cancelled() {
+            log::warn!(zone = zone, outcome = "cancelled"; "sync of zone {} cancelled", zone);
+            return Err(Error::Cancelled);
+        }
+        let diff = WebhookDiff::new(&changes.changes);
+        for record in diff.unchanged.iter() {
+            log::debug!(
+                zone = zone, record = record.name, outcome = "unchanged";
+                "record {} already up to date", record.name
+            );
+        }
+        if diff.is_empty() {
+            return Ok(());
+        }
+
+        let body = Changes {
+            zone,
+            records: diff.records.clone(),
+            deletes: diff.deletes.clone(),
+        };
+        let rejected = self
+            .cli
+            .post_changes(&body)
+            .await
+            .map_err(|e| self.auth_error(e))?;
+        for record in diff.records.iter() {
+            if rejected
+                .iter()
+                .any(|r| r.name.eq_ignore_ascii_case(&record.name))
+            {
+                continue;
+            }
+            log::info!(
+                zone = zone, record = record.name, content = record.content.to_string(),
+                outcome = "created";
+                "record {} set to {}", record.name, record.content
+            );
+        }
+        for rejection in rejected.iter() {
+            log::error!(
+                zone = zone, record = rejection.name, outcome = "failed";
+                "change of record {} failed: {}", rejection.name, rejection.reason
+            );
+        }
+        rejected_error(rejected)
+    }
+
+    // Every zone is listed, which tells the endpoint has it and takes the
+    // credentials
+    async fn verify(&self, zones: &[ZoneName]) -> Result<()> {
+        for zone in zones.iter() {
+            self.cli
+                .records(zone)
+                .await
+                .map_err(|e| self.auth_error(e))?;
+        }
+        Ok(())
+    }
+
+    async fn list_zones(&self) -> Result<Vec<ZoneName>> {
+        self.cli.zones().await
+    }
+
+    async fn delete_records(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, |r| {
+            r.name.eq_ignore_ascii_case(name) && (force || r.is_owned())
+        })
+        .await
+    }
+
+    async fn delete_record(
+        &self,
+        zone: &ZoneName,
+        name: &str,
+        record_type: RecordType,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, |r| {
+            r.name.eq_ignore_ascii_case(name)
+                && r.content.record_type() == record_type
+                && (force || r.is_owned())
+        })
+        .await
+    }
+
+    async fn delete_owned_records(
+        &self,
+        zone: &ZoneName,
+        records: &[ProviderRecord],
+    ) -> Result<Vec<ProviderRecord>> {
+        self.delete_matching(zone, |r| {
+            r.is_owned()
+                && records
+                    .iter()
+                    .any(|d| d.name.eq_ignore_ascii_case(&r.name) && d.content == r.content)
+        })
+        .await
+    }
+
+    fn request_count(&self) -> u64 {
+        self.cli.request_count()
+    }
+}
+
+// One error per rejected record, named so that the other records of the zone
+// do not fail with them
+pub(crate) fn rejected_error(rejected: Vec<Rejection>) -> Result<()> {
+    Error::from_errors(
+        rejected
+            .into_iter()
+            .map(|r| Error::RecordRejected {
+                record: r.name,
+                reason: r.reason,
+            })
+            .collect(),
+    )
+}
+
+///////////////////////////////////////////////////////////
+// Client
+///////////////////////////////////////////////////////////
+impl WebhookAuth {
+    // Methods: `bearer` with `token`, or none
+    pub fn new_with_params(auth: &AuthParams) -> Result<Self> {
+        match auth.method.as_str() {
+            "" => Ok(WebhookAuth::None),
+            "bearer" => match auth.get("token") {
+                Some(token) => Ok(WebhookAuth::Bearer {
+                    token: token.to_string(),
+                }),
+                None => Err(Error::Provider(
+                    "webhook bearer auth requires a token".into(),
+                )),
+            },
+            method => Err(Error::Provider(format!(
+                "{}: unsupported authentication method for webhook provider",
+                method
+            ))),
+        }
+    }
+
+    // Human readable name of the credential that never leaks the secret
+    pub fn describe(&self) -> String {
+        match self {
+            WebhookAuth::Bearer { .. } => "webhook bearer token".to_string(),
+            WebhookAuth::None => "webhook without authentication".to_string(),
+        }
+    }
+}
+
+// Body of `POST <url>/records`, and the input of the `apply` of an exec
+// provider
+#[derive(Debug, Serialize)]
+pub(crate) struct Changes<'a> {
+    pub zone: &'a str,
+    pub records: Vec<&'a ProviderRecord>,
+    pub deletes: Vec<&'a ProviderRecord>,
+}
+
+// A record the endpoint refused, the other changes are made
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Rejection {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChangesResult {
+    #[serde(default)]
+    rejected: Vec<Rejection>,
+}
+
+// Records refused by the answer to a change, an empty answer refused none
+pub(crate) fn rejections(body: &str) -> Result<Vec<Rejection>> {
+    if body.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    let result: ChangesResult = serde_json::from_str(body)?;
+    Ok(result.rejected)
+}
+
+// Records to send to bring the live records of a zone to the desired ones
+#[derive(Debug, Default)]
+pub(crate) struct WebhookDiff<'a> {
+    // Desired records served as they are
+    pub unchanged: Vec<&'a ProviderRecord>,
+    // Desired records the zone does not serve yet
+    pub records: Vec<&'a ProviderRecord>,
+    // Live records under the desired names no desired record matches
+    pub deletes: Vec<&'a ProviderRecord>,
+}
+
+impl<'a> WebhookDiff<'a> {
+    pub fn new(changes: &'a [RecordChange]) -> Self {
+        let mut ret = WebhookDiff::default();
+
+        // Records under a name with several desired records are in the before
+        // of each change, they count once
+        let mut live: Vec<&ProviderRecord> = vec![];
+        for existing in changes.iter().flat_map(|c| c.before.iter()) {
+            if !live.contains(&&existing.record) {
+                live.push(&existing.record);
+            }
+        }
+
+        for change in changes.iter() {
+            let desired = &change.after;
+            if ret.records.contains(&desired) || ret.unchanged.contains(&desired) {
+                continue;
+            }
+            match live.iter().position(|r| serves(r, desired)) {
+                Some(pos) => {
+                    live.remove(pos);
+                    ret.unchanged.push(desired);
+                }
+                None => ret.records.push(desired),
+            }
+        }
+        ret.deletes = live;
+        ret
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty() && self.deletes.is_empty()
+    }
+}
+
+// Whether a live record serves a desired one: same name and content, and
+// the same TTL unless the desired one leaves it to the endpoint
+fn serves(live: &ProviderRecord, desired: &ProviderRecord) -> bool {
+    live.name.eq_ignore_ascii_case(&desired.name)
+        && live.content == desired.content
+        && (desired.ttl == TTL::Auto || live.ttl == desired.ttl)
+}
+
+pub(super) struct Cli {
+    cli: http::Client,
+    // Address of the endpoint without a trailing slash
+    url: String,
+    // Requests sent so far, whatever came of them
+    requests: AtomicU64,
+}
+
+impl Cli {
+    pub fn new(url: &str, auth: &WebhookAuth, transport: Arc<dyn http::HttpTransport>) -> Self {
+        let mut cli = http::Client::with_transport(transport);
+        if let WebhookAuth::Bearer { token } = auth {
+            cli.set_default_headers(vec![http::Header::new(
+                http::HeaderKey::Authorization,
+                format!("Bearer {}", token),
+            )]);
+        }
+        Self {
+            cli,
+            url: url.trim_end_matches('/').to_string(),
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    pub fn request_count(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    async fn get(&self, path: &str) -> Result<http::Response> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.cli.get(&format!("{}{}", self.url, path), None).await
+    }
+
+    pub async fn records(&self, zone: &ZoneName) -> Result<Vec<ProviderRecord>> {
+        let resp = self.get(&format!("/records?zone={}", zone)).await?;
+        if resp.status == 404 {
+            return Err(Error::ZoneNotFound {
+                zone: zone.to_string(),
+            });
+        }
+        Ok(serde_json::from_str(&endpoint_result(resp)?)?)
+    }
+
+    // Endpoints without the optional `/zones` answer 404
+    pub async fn zones(&self) -> Result<Vec<ZoneName>> {
+        let resp = self.get("/zones").await?;
+        if resp.status == 404 {
+            return Err(Error::NotImplemente);
+        }
+        Ok(serde_json::from_str(&endpoint_result(resp)?)?)
+    }
+
+    // The records the endpoint rejected
+    pub async fn post_changes(&self, changes: &Changes<'_>) -> Result<Vec<Rejection>> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let headers = vec![http::Header::new(
+            http::HeaderKey::ContentType,
+            "application/json".to_string(),
+        )];
+        let resp = self
+            .cli
+            .post(
+                &format!("{}/records", self.url),
+                Some(headers),
+                serde_json::to_string(changes)?,
+            )
+            .await?;
+        rejections(&endpoint_result(resp)?)
+    }
+}
+
+// Body of a successful answer. An endpoint tells why it refused a request in
+// the plain text body of a 400 or 422 answer.
+fn endpoint_result(resp: http::Response) -> Result<String> {
+    match resp.status {
+        200..=299 => Ok(resp.body),
+        400 | 422 => Err(Error::Provider(format!(
+            "webhook call failed: {}",
+            resp.body.trim()
+        ))),
+        status => Err(Error::from_status(status, resp.retry_after)),
+    }
+}
@@ -0,0 +1,860 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::types::IpCandidate;
+use crate::types::PublicIp;
+
+// Records as the config, zone files, fetchers and providers see them. A record
+// type is added to `RecordType` and `RecordContent` and every other model
+// converts from these.
+
+// Name of a record completed with the zone name when it is relative
+pub fn fqdn(name: &str, zone: &str) -> String {
+    if name == zone || name.ends_with(&format!(".{}", zone)) {
+        name.to_string()
+    } else {
+        format!("{}.{}", name, zone)
+    }
+}
+
+// The longest of `zones` a full name falls in
+pub fn zone_of<'a>(name: &str, zones: &'a [String]) -> Option<&'a String> {
+    zones
+        .iter()
+        .filter(|z| name == z.as_str() || name.ends_with(&format!(".{}", z)))
+        .max_by_key(|z| z.len())
+}
+
+////////////////////////////////////////////////////////////
+// Record
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordLabel {
+    key: String,
+    val: String,
+}
+
+impl RecordLabel {
+    pub fn new(key: String, val: String) -> Self {
+        Self { key, val }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    AAAA,
+    CNAME,
+    None,
+}
+
+impl RecordType {
+    // Types that can be synced, case insensitive
+    pub fn parse(ty: &str) -> Option<Self> {
+        match ty.to_ascii_uppercase().as_str() {
+            "A" => Some(RecordType::A),
+            "AAAA" => Some(RecordType::AAAA),
+            "CNAME" => Some(RecordType::CNAME),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            RecordType::A => "A",
+            RecordType::AAAA => "AAAA",
+            RecordType::CNAME => "CNAME",
+            RecordType::None => "None",
+        }
+    }
+}
+
+impl Serialize for RecordType {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordType {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let ty = String::deserialize(deserializer)?;
+        RecordType::parse(&ty)
+            .ok_or_else(|| serde::de::Error::custom(format!("Unknown record type: {}", ty)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordContent {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    CNAME(String),
+    Unassigned(RecordType),
+    Unknown,
+}
+
+impl RecordContent {
+    // Content of type `ty` from its text, as config, zone files and provider
+    // APIs give it. Without text the content is the public address of the cycle.
+    pub fn parse(ty: &str, content: Option<&str>) -> Result<Self> {
+        let Some(record_type) = RecordType::parse(ty) else {
+            return Err(Error::ParseError(format!("Unknown record type: {}", ty)));
+        };
+        let Some(content) = content else {
+            return Ok(RecordContent::Unassigned(record_type));
+        };
+        match record_type {
+            RecordType::A => Ok(RecordContent::A(content.parse()?)),
+            RecordType::AAAA => Ok(RecordContent::AAAA(content.parse()?)),
+            // Zone files end names with a dot, providers do not
+            RecordType::CNAME => Ok(RecordContent::CNAME(
+                content.trim_end_matches('.').to_string(),
+            )),
+            RecordType::None => Ok(RecordContent::Unknown),
+        }
+    }
+
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            RecordContent::A(_) => RecordType::A,
+            RecordContent::AAAA(_) => RecordType::AAAA,
+            RecordContent::CNAME(_) => RecordType::CNAME,
+            RecordContent::Unassigned(ty) => ty.clone(),
+            RecordContent::Unknown => RecordType::None,
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, RecordContent::Unknown)
+    }
+
+    pub fn is_unassigned(&self) -> bool {
+        matches!(self, RecordContent::Unassigned(_))
+    }
+}
+
+impl fmt::Display for RecordContent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordContent::A(v4) => write!(f, "{}", v4),
+            RecordContent::AAAA(v6) => write!(f, "{}", v6),
+            RecordContent::CNAME(name) => write!(f, "{}", name),
+            RecordContent::Unassigned(ty) => write!(f, "<public {} address>", ty.as_str()),
+            RecordContent::Unknown => write!(f, "<unknown>"),
+        }
+    }
+}
+
+// `type` and `content` fields, what the config and the Cloudflare API use. A
+// content still to be assigned only has its type.
+impl Serialize for RecordContent {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStruct;
+
+        match self {
+            RecordContent::A(_) | RecordContent::AAAA(_) | RecordContent::CNAME(_) => {
+                let mut state = serializer.serialize_struct("RecordContent", 2)?;
+                state.serialize_field("type", self.record_type().as_str())?;
+                state.serialize_field("content", &self.to_string())?;
+                state.end()
+            }
+            RecordContent::Unassigned(ty) => {
+                let mut state = serializer.serialize_struct("RecordContent", 1)?;
+                state.serialize_field("type", ty.as_str())?;
+                state.end()
+            }
+            RecordContent::Unknown => serializer.serialize_unit(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordContent {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RecordContentHelper {
+            #[serde(rename = "type")]
+            ty: Option<String>,
+            content: Option<String>,
+        }
+
+        let helper = RecordContentHelper::deserialize(deserializer)?;
+
+        let ty = helper
+            .ty
+            .ok_or(serde::de::Error::custom("have to give a type for record"))?;
+        RecordContent::parse(&ty, helper.content.as_deref()).map_err(serde::de::Error::custom)
+    }
+}
+
+// An address a fetcher found, `source` names the fetcher or backend that
+// answered it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FetcherRecord {
+    #[serde(flatten)]
+    pub value: RecordContent,
+    #[serde(default)]
+    pub labels: Vec<RecordLabel>,
+    #[serde(default)]
+    pub source: String,
+    #[serde(default = "Utc::now")]
+    pub fetched_at: DateTime<Utc>,
+}
+
+impl FetcherRecord {
+    pub fn new(value: RecordContent) -> Self {
+        Self {
+            value,
+            labels: vec![],
+            source: String::new(),
+            fetched_at: Utc::now(),
+        }
+    }
+
+    pub fn new_v4_with_labels(value: Ipv4Addr, labels: Vec<RecordLabel>) -> Self {
+        Self {
+            labels,
+            ..Self::new(RecordContent::A(value))
+        }
+    }
+
+    pub fn new_v6_with_labels(value: Ipv6Addr, labels: Vec<RecordLabel>) -> Self {
+        Self {
+            labels,
+            ..Self::new(RecordContent::AAAA(value))
+        }
+    }
+
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = source.to_string();
+        self
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct FetcherRecordSet {
+    contents: Vec<FetcherRecord>,
+}
+
+impl FetcherRecordSet {
+    pub fn new() -> Self {
+        Self { contents: vec![] }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contents.is_empty()
+    }
+
+    pub fn push(&mut self, content: FetcherRecord) {
+        self.contents.push(content);
+    }
+}
+
+impl From<FetcherRecordSet> for PublicIp {
+    fn from(set: FetcherRecordSet) -> Self {
+        let candidates = set
+            .contents
+            .into_iter()
+            .filter_map(|record| {
+                let address = match record.value {
+                    RecordContent::A(ip) => IpAddr::V4(ip),
+                    RecordContent::AAAA(ip) => IpAddr::V6(ip),
+                    _ => return None,
+                };
+                Some(IpCandidate {
+                    address,
+                    source: record.source,
+                    fetched_at: record.fetched_at,
+                })
+            })
+            .collect();
+        Self::from_candidates(candidates)
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Provider Record
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum TTL {
+    Value(u32),
+    #[default]
+    Auto,
+}
+
+impl TTL {
+    // Seconds of the TTL, `None` when the provider picks it
+    pub fn seconds(&self) -> Option<u32> {
+        match self {
+            TTL::Value(v) => Some(*v),
+            TTL::Auto => None,
+        }
+    }
+}
+
+impl From<Option<u32>> for TTL {
+    fn from(seconds: Option<u32>) -> Self {
+        seconds.map(TTL::Value).unwrap_or_default()
+    }
+}
+
+// Seconds, or "auto" when the provider picks it
+impl Serialize for TTL {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            TTL::Value(v) => serializer.serialize_u32(*v),
+            TTL::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TTL {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_yaml::Value::deserialize(deserializer)?;
+
+        match value.as_u64() {
+            Some(int_value) => Ok(TTL::Value(int_value as u32)),
+            _ => Ok(TTL::Auto),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub enum RecordOp {
+    #[serde(rename = "create", alias = "Create")]
+    #[default]
+    Create,
+    #[serde(rename = "purge", alias = "Purge")]
+    Purge,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderParam {
+    pub name: String,
+    pub value: String,
+}
+
+// Appended to the comment of every record a sync writes. Only records carrying it
+// are deleted, records created by hand are left alone.
+pub const OWNER_MARKER: &str = "[dns-syncer]";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderRecord {
+    pub name: String,
+    #[serde(flatten)]
+    pub content: RecordContent,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub op: RecordOp,
+    #[serde(default)]
+    pub ttl: TTL,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<ProviderParam>,
+}
+
+impl ProviderRecord {
+    // Builder checking the name, content, TTL and params of the record
+    pub fn builder() -> ProviderRecordBuilder {
+        ProviderRecordBuilder::default()
+    }
+
+    pub fn fqdn(&self, zone: &str) -> String {
+        fqdn(&self.name, zone)
+    }
+
+    pub fn assign_public_ip_if_unassigned(
+        &mut self,
+        v4: Option<Ipv4Addr>,
+        v6: Option<Ipv6Addr>,
+    ) -> Result<()> {
+        match (&self.content, (v4, v6)) {
+            (RecordContent::Unassigned(RecordType::A), (Some(v4), _)) => {
+                self.content = RecordContent::A(v4)
+            }
+            (RecordContent::Unassigned(RecordType::AAAA), (_, Some(v6))) => {
+                self.content = RecordContent::AAAA(v6)
+            }
+            (RecordContent::Unassigned(ty), (_, _)) => {
+                return Err(Error::Provider(format!(
+                    "content is declared as {} but neither v4 nor v6 is provided",
+                    ty.as_str()
+                )));
+            }
+            // Static contents are kept as they are
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn mark_owned(&mut self) {
+        if self.is_owned() {
+            return;
+        }
+        self.comment = match self.comment.take() {
+            Some(c) if !c.is_empty() => Some(format!("{} {}", c, OWNER_MARKER)),
+            _ => Some(OWNER_MARKER.to_string()),
+        };
+    }
+
+    pub fn is_owned(&self) -> bool {
+        self.comment
+            .as_deref()
+            .is_some_and(|c| c.contains(OWNER_MARKER))
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Builder
+////////////////////////////////////////////////////////////
+// Most providers refuse a TTL outside of a second to a day, Cloudflare takes
+// 30s at least
+pub const MIN_TTL: u32 = 30;
+pub const MAX_TTL: u32 = 86400;
+
+// Why `ProviderRecordBuilder::build` refused a record
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RecordError {
+    #[error("name is missing")]
+    MissingName,
+    #[error("invalid name {name}: {reason}")]
+    InvalidName { name: String, reason: String },
+    #[error("type is missing")]
+    MissingType,
+    #[error("type {0} cannot be synced")]
+    UnsupportedType(String),
+    // Only A and AAAA records take the public address when given no content
+    #[error("a {0} record needs a content")]
+    MissingContent(String),
+    #[error("{content} is not a valid {type} content")]
+    InvalidContent { r#type: String, content: String },
+    #[error("{found} content given for a {expected} record")]
+    TypeMismatch { expected: String, found: String },
+    #[error("TTL {0} is out of range {MIN_TTL}..={MAX_TTL}")]
+    TtlOutOfRange(u32),
+    #[error("invalid param key {0:?}")]
+    InvalidParam(String),
+    #[error("param {0} is given twice")]
+    DuplicateParam(String),
+}
+
+#[derive(Debug, Clone)]
+enum BuilderContent {
+    Text(String),
+    Value(RecordContent),
+}
+
+// Builds a `ProviderRecord` checked like the config checks its records, see
+// `ProviderRecord::builder`
+#[derive(Debug, Clone, Default)]
+pub struct ProviderRecordBuilder {
+    name: Option<String>,
+    record_type: Option<RecordType>,
+    content: Option<BuilderContent>,
+    comment: Option<String>,
+    op: RecordOp,
+    ttl: TTL,
+    params: Vec<ProviderParam>,
+}
+
+impl ProviderRecordBuilder {
+    // Relative to the zone or full, a trailing dot is dropped
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn record_type(mut self, record_type: RecordType) -> Self {
+        self.record_type = Some(record_type);
+        self
+    }
+
+    // Content as text, parsed for the record type
+    pub fn content(mut self, content: &str) -> Self {
+        self.content = Some(BuilderContent::Text(content.to_string()));
+        self
+    }
+
+    // Content already parsed, its type must agree with `record_type` if given
+    pub fn value(mut self, content: RecordContent) -> Self {
+        self.content = Some(BuilderContent::Value(content));
+        self
+    }
+
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.comment = Some(comment.to_string());
+        self
+    }
+
+    pub fn op(mut self, op: RecordOp) -> Self {
+        self.op = op;
+        self
+    }
+
+    pub fn ttl(mut self, ttl: TTL) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn param(mut self, name: &str, value: &str) -> Self {
+        self.params.push(ProviderParam {
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    pub fn build(self) -> Result<ProviderRecord> {
+        let name = self.name.clone().unwrap_or_default();
+        self.validate().map_err(|reason| Error::InvalidRecord {
+            record: name,
+            reason,
+        })
+    }
+
+    fn validate(self) -> std::result::Result<ProviderRecord, RecordError> {
+        let name = self.name.ok_or(RecordError::MissingName)?;
+        let name = name.strip_suffix('.').unwrap_or(&name).to_string();
+        check_name(&name).map_err(|reason| RecordError::InvalidName {
+            name: name.clone(),
+            reason,
+        })?;
+
+        let content = match (self.record_type, self.content) {
+            (Some(RecordType::None), _) => {
+                return Err(RecordError::UnsupportedType("None".to_string()));
+            }
+            (Some(ty), Some(BuilderContent::Text(text))) => {
+                RecordContent::parse(ty.as_str(), Some(&text)).map_err(|_| {
+                    RecordError::InvalidContent {
+                        r#type: ty.as_str().to_string(),
+                        content: text,
+                    }
+                })?
+            }
+            (None, Some(BuilderContent::Text(_)) | None) => return Err(RecordError::MissingType),
+            (Some(ty), None) => RecordContent::Unassigned(ty),
+            (ty, Some(BuilderContent::Value(value))) => {
+                let found = value.record_type();
+                if let Some(ty) = ty.filter(|ty| *ty != found) {
+                    return Err(RecordError::TypeMismatch {
+                        expected: ty.as_str().to_string(),
+                        found: found.as_str().to_string(),
+                    });
+                }
+                value
+            }
+        };
+        match &content {
+            RecordContent::Unknown | RecordContent::Unassigned(RecordType::None) => {
+                return Err(RecordError::UnsupportedType("None".to_string()));
+            }
+            RecordContent::Unassigned(RecordType::CNAME) => {
+                return Err(RecordError::MissingContent("CNAME".to_string()));
+            }
+            RecordContent::CNAME(target) if check_name(target).is_err() => {
+                return Err(RecordError::InvalidContent {
+                    r#type: "CNAME".to_string(),
+                    content: target.clone(),
+                });
+            }
+            _ => {}
+        }
+
+        if let TTL::Value(v) = self.ttl
+            && !(MIN_TTL..=MAX_TTL).contains(&v)
+        {
+            return Err(RecordError::TtlOutOfRange(v));
+        }
+
+        for (idx, param) in self.params.iter().enumerate() {
+            let valid = !param.name.is_empty()
+                && param
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if !valid {
+                return Err(RecordError::InvalidParam(param.name.clone()));
+            }
+            if self.params[..idx].iter().any(|p| p.name == param.name) {
+                return Err(RecordError::DuplicateParam(param.name.clone()));
+            }
+        }
+
+        Ok(ProviderRecord {
+            name,
+            content,
+            comment: self.comment,
+            op: self.op,
+            ttl: self.ttl,
+            params: self.params,
+        })
+    }
+}
+
+// Host name syntax of RFC 1123, with underscores for service labels like
+// _acme-challenge and a leading "*" label for wildcards
+fn check_name(name: &str) -> std::result::Result<(), String> {
+    if name.is_empty() {
+        return Err("empty name".to_string());
+    }
+    if name.len() > 253 {
+        return Err("longer than 253 characters".to_string());
+    }
+    for (idx, label) in name.split('.').enumerate() {
+        if idx == 0 && label == "*" {
+            continue;
+        }
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("label {:?} is not 1 to 63 characters", label));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(format!("label {} starts or ends with a hyphen", label));
+        }
+        if let Some(c) = label
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+        {
+            return Err(format!("invalid character {:?}", c));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_content() {
+        assert_eq!(
+            RecordContent::parse("aaaa", Some("::1")).unwrap(),
+            RecordContent::AAAA(Ipv6Addr::LOCALHOST)
+        );
+        assert_eq!(
+            RecordContent::parse("CNAME", Some("home.example.org.")).unwrap(),
+            RecordContent::CNAME("home.example.org".to_string())
+        );
+        assert_eq!(
+            RecordContent::parse("A", None).unwrap(),
+            RecordContent::Unassigned(RecordType::A)
+        );
+        assert!(RecordContent::parse("A", Some("::1")).is_err());
+        assert!(RecordContent::parse("MX", Some("mail.example.org")).is_err());
+
+        assert_eq!(fqdn("home", "example.org"), "home.example.org");
+        assert_eq!(fqdn("example.org", "example.org"), "example.org");
+        assert_eq!(TTL::from(Some(300)).seconds(), Some(300));
+        assert_eq!(TTL::from(None), TTL::Auto);
+    }
+
+    #[test]
+    fn test_serialize_record_content() {
+        let content = RecordContent::A(Ipv4Addr::new(192, 168, 1, 1));
+        let json = serde_json::to_value(&content).unwrap();
+        assert_eq!(json["type"], "A");
+        assert_eq!(json["content"], "192.168.1.1");
+
+        let content = RecordContent::Unassigned(RecordType::AAAA);
+        let json = serde_json::to_value(&content).unwrap();
+        assert_eq!(json, serde_json::json!({ "type": "AAAA" }));
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let record = ProviderRecord {
+            name: "home.example.org".to_string(),
+            content: RecordContent::AAAA(Ipv6Addr::LOCALHOST),
+            comment: Some(OWNER_MARKER.to_string()),
+            op: RecordOp::Purge,
+            ttl: TTL::Value(300),
+            params: vec![ProviderParam {
+                name: "proxied".to_string(),
+                value: "true".to_string(),
+            }],
+        };
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["type"], "AAAA");
+        assert_eq!(json["content"], "::1");
+        assert_eq!(json["op"], "purge");
+        assert_eq!(json["ttl"], 300);
+        assert_eq!(
+            serde_json::from_value::<ProviderRecord>(json).unwrap(),
+            record
+        );
+
+        let record = ProviderRecord {
+            ttl: TTL::Auto,
+            params: vec![],
+            ..record
+        };
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["ttl"], "auto");
+        assert!(json.get("params").is_none());
+        assert_eq!(
+            serde_json::from_value::<ProviderRecord>(json).unwrap(),
+            record
+        );
+
+        let mut set = FetcherRecordSet::new();
+        set.push(
+            FetcherRecord::new(RecordContent::A(Ipv4Addr::new(192, 0, 2, 1))).with_source("ipw"),
+        );
+        let json = serde_json::to_value(&set).unwrap();
+        assert_eq!(json[0]["content"], "192.0.2.1");
+        assert_eq!(json[0]["source"], "ipw");
+        assert_eq!(
+            serde_json::from_value::<FetcherRecordSet>(json).unwrap(),
+            set
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let record = ProviderRecord::builder()
+            .name("home.example.org.")
+            .record_type(RecordType::A)
+            .content("192.0.2.1")
+            .ttl(TTL::Value(300))
+            .param("proxied", "true")
+            .build()
+            .unwrap();
+        assert_eq!(record.name, "home.example.org");
+        assert_eq!(
+            record.content,
+            RecordContent::A(Ipv4Addr::new(192, 0, 2, 1))
+        );
+
+        let record = ProviderRecord::builder()
+            .name("*.home")
+            .record_type(RecordType::AAAA)
+            .build()
+            .unwrap();
+        assert_eq!(record.content, RecordContent::Unassigned(RecordType::AAAA));
+
+        let reason = |builder: ProviderRecordBuilder| match builder.build() {
+            Err(Error::InvalidRecord { reason, .. }) => reason,
+            other => panic!("unexpected {:?}", other),
+        };
+        let a = || {
+            ProviderRecord::builder()
+                .name("home")
+                .record_type(RecordType::A)
+        };
+        assert_eq!(reason(ProviderRecord::builder()), RecordError::MissingName);
+        assert!(matches!(
+            reason(a().name("ho me")),
+            RecordError::InvalidName { .. }
+        ));
+        assert!(matches!(
+            reason(a().name("-home")),
+            RecordError::InvalidName { .. }
+        ));
+        assert_eq!(
+            reason(ProviderRecord::builder().name("home").content("192.0.2.1")),
+            RecordError::MissingType
+        );
+        assert!(matches!(
+            reason(a().content("::1")),
+            RecordError::InvalidContent { .. }
+        ));
+        assert!(matches!(
+            reason(a().value(RecordContent::AAAA(Ipv6Addr::LOCALHOST))),
+            RecordError::TypeMismatch { .. }
+        ));
+        assert_eq!(
+            reason(a().record_type(RecordType::CNAME)),
+            RecordError::MissingContent("CNAME".to_string())
+        );
+        assert_eq!(
+            reason(a().ttl(TTL::Value(1))),
+            RecordError::TtlOutOfRange(1)
+        );
+        assert_eq!(
+            reason(a().param("proxied", "true").param("proxied", "false")),
+            RecordError::DuplicateParam("proxied".to_string())
+        );
+        assert_eq!(
+            reason(a().param("", "true")),
+            RecordError::InvalidParam(String::new())
+        );
+    }
+
+    #[test]
+    fn test_owner_marker() {
+        let mut record = ProviderRecord {
+            name: "home".to_string(),
+            content: RecordContent::A(Ipv4Addr::new(1, 2, 3, 4)),
+            comment: Some("home router".to_string()),
+            op: RecordOp::Create,
+            ttl: TTL::Auto,
+            params: vec![],
+        };
+        assert!(!record.is_owned());
+
+        record.mark_owned();
+        record.mark_owned();
+        assert!(record.is_owned());
+        assert_eq!(record.comment.as_deref(), Some("home router [dns-syncer]"));
+
+        record.comment = None;
+        record.mark_owned();
+        assert_eq!(record.comment.as_deref(), Some("[dns-syncer]"));
+    }
+
+    #[test]
+    fn test_assign_public_ip() {
+        let mut record = ProviderRecord {
+            name: "home".to_string(),
+            content: RecordContent::Unassigned(RecordType::A),
+            comment: None,
+            op: RecordOp::default(),
+            ttl: TTL::default(),
+            params: vec![],
+        };
+        let v4 = Ipv4Addr::new(1, 2, 3, 4);
+        assert!(record.assign_public_ip_if_unassigned(None, None).is_err());
+        record
+            .assign_public_ip_if_unassigned(Some(v4), None)
+            .unwrap();
+        assert_eq!(record.content, RecordContent::A(v4));
+
+        record.content = RecordContent::CNAME("target.example.com".to_string());
+        record
+            .assign_public_ip_if_unassigned(Some(v4), None)
+            .unwrap();
+        assert_eq!(
+            record.content,
+            RecordContent::CNAME("target.example.com".to_string())
+        );
+    }
+}
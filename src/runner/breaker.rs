@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    // The provider syncs every cycle
+    Closed,
+    // The provider failed too often and is left out until the cooldown ends
+    Open,
+    // The cooldown ended, the next cycle probes the provider
+    HalfOpen,
+}
+
+#[derive(Debug, Default)]
+struct Circuit {
+    consecutive: u32,
+    opened_at: Option<Instant>,
+}
+
+// Leaves a provider out of the cycles after `failures` consecutive failed
+// syncs, so an outage does not cost every cycle its full timeouts. Once
+// `cooldown` passed one cycle probes the provider again: a success closes the
+// circuit, a failure opens it for another cooldown.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failures: u32,
+    cooldown: Duration,
+    circuits: HashMap<String, Circuit>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failures: u32, cooldown: Duration) -> Self {
+        Self {
+            failures: failures.max(1),
+            cooldown,
+            circuits: HashMap::new(),
+        }
+    }
+
+    pub fn state(&self, provider: &str) -> CircuitState {
+        match self.circuits.get(provider).and_then(|c| c.opened_at) {
+            None => CircuitState::Closed,
+            Some(at) if at.elapsed() >= self.cooldown => CircuitState::HalfOpen,
+            Some(_) => CircuitState::Open,
+        }
+    }
+
+    // Whether the provider syncs this cycle
+    pub fn allow(&self, provider: &str) -> bool {
+        self.state(provider) != CircuitState::Open
+    }
+
+    // Time left before the provider is probed, `None` unless the circuit is
+    // open
+    pub fn retry_in(&self, provider: &str) -> Option<Duration> {
+        let opened_at = self.circuits.get(provider)?.opened_at?;
+        self.cooldown.checked_sub(opened_at.elapsed())
+    }
+
+    pub fn record_success(&mut self, provider: &str) {
+        self.circuits.remove(provider);
+    }
+
+    // Returns the state of the circuit after the failure
+    pub fn record_failure(&mut self, provider: &str) -> CircuitState {
+        let circuit = self.circuits.entry(provider.to_string()).or_default();
+        circuit.consecutive += 1;
+        if circuit.opened_at.is_some() || circuit.consecutive >= self.failures {
+            circuit.opened_at = Some(Instant::now());
+        }
+        self.state(provider)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let mut breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        assert_eq!(breaker.record_failure("cf-1"), CircuitState::Closed);
+        assert!(breaker.allow("cf-1"));
+        assert_eq!(breaker.record_failure("cf-1"), CircuitState::Open);
+        assert!(!breaker.allow("cf-1"));
+        assert!(breaker.retry_in("cf-1").is_some());
+        assert!(breaker.allow("cf-2"));
+
+        breaker.record_success("cf-1");
+        assert_eq!(breaker.state("cf-1"), CircuitState::Closed);
+        assert_eq!(breaker.retry_in("cf-1"), None);
+
+        // Without a cooldown an open circuit is probed at once, a failing
+        // probe opens it again
+        let mut breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.record_failure("cf-1");
+        assert_eq!(breaker.state("cf-1"), CircuitState::HalfOpen);
+        assert!(breaker.allow("cf-1"));
+        breaker.record_failure("cf-1");
+        assert_eq!(breaker.state("cf-1"), CircuitState::HalfOpen);
+        breaker.record_success("cf-1");
+        assert_eq!(breaker.state("cf-1"), CircuitState::Closed);
+    }
+}
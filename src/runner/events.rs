@@ -0,0 +1,96 @@
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+
+use futures_core::Stream;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::error::Error;
+use crate::runner::SyncObserver;
+use crate::types::PublicIp;
+
+// What a sync changed or failed at, as `Runner::events` streams it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SyncEvent {
+    // The fetched address differs from the one of the previous cycle, `old`
+    // is `None` on the first fetch
+    IpChanged {
+        old: Option<PublicIp>,
+        new: PublicIp,
+    },
+    RecordUpdated {
+        provider: String,
+        zone: String,
+        record: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+// Events of the runner in the order they happened. Events are buffered until
+// read, the stream ends when the runner is dropped.
+pub struct EventStream {
+    rx: mpsc::UnboundedReceiver<SyncEvent>,
+}
+
+impl EventStream {
+    // Next event, for callers not using `Stream` combinators
+    pub async fn next(&mut self) -> Option<SyncEvent> {
+        self.rx.recv().await
+    }
+
+    // Next event if one is already there
+    pub fn try_next(&mut self) -> Option<SyncEvent> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Stream for EventStream {
+    type Item = SyncEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+// Observer feeding an `EventStream`, sends fail quietly once it is dropped
+pub(super) struct EventSender {
+    tx: mpsc::UnboundedSender<SyncEvent>,
+}
+
+impl EventSender {
+    pub(super) fn channel() -> (Self, EventStream) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self { tx }, EventStream { rx })
+    }
+
+    fn send(&self, event: SyncEvent) {
+        let _ = self.tx.send(event);
+    }
+}
+
+impl SyncObserver for EventSender {
+    fn on_ip_changed(&self, old: Option<&PublicIp>, new: &PublicIp) {
+        self.send(SyncEvent::IpChanged {
+            old: old.cloned(),
+            new: new.clone(),
+        });
+    }
+
+    fn on_record_applied(&self, provider: &str, zone: &str, record: &str) {
+        self.send(SyncEvent::RecordUpdated {
+            provider: provider.to_string(),
+            zone: zone.to_string(),
+            record: record.to_string(),
+        });
+    }
+
+    fn on_error(&self, error: &Error) {
+        self.send(SyncEvent::Error {
+            message: error.to_string(),
+        });
+    }
+}
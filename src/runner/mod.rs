@@ -0,0 +1,1626 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Cfg;
+use crate::config::CfgFetcher;
+use crate::config::CfgHook;
+use crate::config::CfgNotification;
+use crate::config::CfgParamList;
+use crate::config::CfgProvider;
+use crate::config::CfgRecord;
+use crate::config::CfgRecordItem;
+use crate::config::CfgRecordProvider;
+use crate::config::CfgSource;
+use crate::error::Error;
+use crate::error::Result;
+use crate::fetcher::Fetcher;
+use crate::fetcher::HttpFetcher;
+use crate::notify::Desktop;
+use crate::notify::Event;
+use crate::notify::EventKind;
+use crate::notify::Gotify;
+use crate::notify::Heartbeat;
+use crate::notify::Mqtt;
+use crate::notify::Notifications;
+use crate::notify::Notifier;
+use crate::notify::Ntfy;
+use crate::notify::Pushover;
+use crate::notify::Slack;
+use crate::notify::Webhook;
+use crate::provider::BackendRecords;
+use crate::provider::HookedProvider;
+use crate::provider::PlanOp;
+use crate::provider::PlannedChange;
+use crate::provider::Provider;
+use crate::provider::ProviderRegistry;
+use crate::provider::RecordHook;
+use crate::record::FetcherRecordSet;
+use crate::record::RecordType;
+use crate::server::Api;
+use crate::server::DriftMetrics;
+use crate::server::Health;
+use crate::source::RecordSource;
+use crate::source::Source;
+use crate::state::DEFAULT_HISTORY_SIZE;
+use crate::state::ProviderResult;
+use crate::state::State;
+use crate::state::SyncCycle;
+use crate::types::PublicIp;
+use crate::types::ZoneName;
+use crate::verify::DohVerifier;
+
+mod breaker;
+mod events;
+mod observer;
+mod simulate;
+pub use breaker::*;
+pub use events::EventStream;
+pub use events::SyncEvent;
+pub use observer::*;
+pub use simulate::*;
+
+pub type FetcherMap = HashMap<String, Box<dyn Fetcher>>;
+pub type ProviderMap = HashMap<String, Arc<dyn Provider>>;
+
+// A record of a source as synced: provider, zone, name and type
+type SourcedKey = (String, ZoneName, String, RecordType);
+
+// A planned change with the name of the provider making it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderChange {
+    pub provider: String,
+    #[serde(flatten)]
+    pub change: PlannedChange,
+}
+
+// Records a provider syncs, per zone. Shared with the sync tasks of every
+// cycle instead of copied.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProviderBackend {
+    pub record: Arc<BackendRecords>,
+    pub fetchers: Vec<String>,
+}
+
+// Syncs the records of a config to its providers, one cycle per `run`
+pub struct Runner {
+    check_interval: Duration,
+    listen: Option<std::net::SocketAddr>,
+    health: Arc<Health>,
+    notifications: Notifications,
+    heartbeat: Option<Heartbeat>,
+    state_file: Option<PathBuf>,
+    history_size: usize,
+    state: State,
+    public_ip_changed: bool,
+    global_fetcher_name: String,
+    fetchers: FetcherMap,
+    providers: ProviderMap,
+    record_per_provider: HashMap<String, ProviderBackend>,
+    observers: Vec<Arc<dyn SyncObserver>>,
+    breaker: Option<CircuitBreaker>,
+    // Records of the config, the records of the sources join them every cycle
+    static_records: Vec<CfgRecordItem>,
+    sources: Vec<Source>,
+    sourced: HashSet<SourcedKey>,
+    sources_changed: Arc<Notify>,
+    api: Option<Arc<Api>>,
+    persist_api: bool,
+    drift: Arc<DriftMetrics>,
+    // Checks the records changed by a sync propagated
+    verifier: Option<Arc<DohVerifier>>,
+    // Drifted records of every provider at the last check, to only notify
+    // about new drift
+    drift_seen: HashMap<String, Vec<String>>,
+}
+
+impl Runner {
+    // Runner syncing `records`, a subset of the records of `config`, with the
+    // providers built by `registry`
+    pub fn new(
+        config: Cfg,
+        records: Vec<CfgRecordItem>,
+        registry: &ProviderRegistry,
+    ) -> Result<Self> {
+        config
+            .http
+            .clone()
+            .into_http_config(&config.base_dir)
+            .install()?;
+
+        let state_file = config.state_file();
+        let history_size = config
+            .state
+            .as_ref()
+            .map(|s| s.history_size)
+            .unwrap_or(DEFAULT_HISTORY_SIZE);
+        let state = match state_file.as_ref() {
+            Some(path) => State::load(path)?,
+            None => State::default(),
+        };
+
+        let Cfg {
+            check_interval,
+            strict,
+            providers,
+            fetchers,
+            records: _,
+            profiles: _,
+            credentials: _,
+            public_ip_fecher,
+            http: _,
+            server,
+            notifications,
+            heartbeat,
+            state: _,
+            hook,
+            circuit_breaker,
+            sources,
+            drift: _,
+            verify,
+            control: _,
+            base_dir,
+        } = config;
+
+        let notifications = create_notifications(&notifications, strict)?;
+        let heartbeat = heartbeat
+            .map(|h| Heartbeat::new(h.url, h.fail_url))
+            .transpose()?;
+
+        let fetchers = create_fetchers(&records, &sources, &public_ip_fecher, &fetchers, strict)?;
+        // Records of the API may go to any provider
+        let api_cfg = server.as_ref().and_then(|s| s.api.clone());
+        let in_use_providers = match api_cfg {
+            Some(_) => providers.iter().map(|p| p.name.clone()).collect(),
+            None => list_in_use_providers(&records, &sources),
+        };
+        let providers = create_providers(
+            &in_use_providers,
+            &providers,
+            hook.as_ref(),
+            strict,
+            registry,
+        )?;
+        for (name, provider) in providers.iter() {
+            if let Some(cache) = state.ids.get(name) {
+                provider.load_id_cache(cache.clone());
+            }
+        }
+        let records = prune_records(records, &providers, &fetchers, &public_ip_fecher, strict)?;
+
+        let sources = create_sources(sources, &base_dir, strict)?;
+        let verifier = verify
+            .map(|v| DohVerifier::new(v.resolvers, v.delay, v.timeout))
+            .transpose()?
+            .map(Arc::new);
+
+        let sources_changed = Arc::new(Notify::new());
+        let persist_api = api_cfg.as_ref().is_some_and(|a| a.persist);
+        if persist_api && state_file.is_none() {
+            return Err(Error::ParseError(
+                "server.api.persist needs a state file".to_string(),
+            ));
+        }
+        let api = api_cfg.map(|cfg| {
+            let mut names = providers.keys().cloned().collect::<Vec<_>>();
+            names.sort();
+            let api = Api::new(&cfg.token, names, Arc::clone(&sources_changed));
+            if cfg.persist {
+                api.set_records(state.api_records.clone());
+            }
+            api.update_status(&state);
+            Arc::new(api)
+        });
+
+        // The key is the provider name, value is the backend records per zone
+        let record_per_provider = to_provider_backends(records.clone())?;
+        lint_provider_backends(&record_per_provider)?;
+
+        Ok(Self {
+            check_interval,
+            listen: server.map(|s| s.listen),
+            health: Arc::new(Health::new(check_interval)),
+            notifications,
+            heartbeat,
+            state_file,
+            history_size,
+            state,
+            public_ip_changed: false,
+            global_fetcher_name: public_ip_fecher.to_string(),
+            fetchers,
+            providers,
+            record_per_provider,
+            observers: vec![],
+            breaker: circuit_breaker.map(|b| CircuitBreaker::new(b.failures, b.cooldown)),
+            static_records: records,
+            sources,
+            sourced: HashSet::new(),
+            sources_changed,
+            api,
+            persist_api,
+            drift: Arc::new(DriftMetrics::new()),
+            verifier,
+            drift_seen: HashMap::new(),
+        })
+    }
+
+    // Runner syncing every enabled record of `config` with the builtin providers
+    pub fn from_config(config: Cfg) -> Result<Self> {
+        let records = config
+            .record_items()?
+            .into_iter()
+            .filter(|r| r.is_selected(&[], &[]))
+            .collect();
+        Self::new(config, records, &ProviderRegistry::new())
+    }
+
+    pub fn check_interval(&self) -> Duration {
+        self.check_interval
+    }
+
+    // Health is reset, readiness depends on the interval
+    pub fn set_check_interval(&mut self, check_interval: Duration) {
+        self.check_interval = check_interval;
+        self.health = Arc::new(Health::new(check_interval));
+    }
+
+    // Address of the configured HTTP listener
+    pub fn listen(&self) -> Option<SocketAddr> {
+        self.listen
+    }
+
+    pub fn health(&self) -> Arc<Health> {
+        self.health.clone()
+    }
+
+    // Management API of the config, served by the HTTP listener
+    pub fn api(&self) -> Option<Arc<Api>> {
+        self.api.clone()
+    }
+
+    // Outcome of the drift checks, served by the HTTP listener
+    pub fn drift_metrics(&self) -> Arc<DriftMetrics> {
+        self.drift.clone()
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    // Whether the last cycle fetched another public address than the one
+    // before it
+    pub fn public_ip_changed(&self) -> bool {
+        self.public_ip_changed
+    }
+
+    // Records of every provider, the key is the provider name
+    pub fn backends(&self) -> &HashMap<String, ProviderBackend> {
+        &self.record_per_provider
+    }
+
+    pub fn providers(&self) -> &ProviderMap {
+        &self.providers
+    }
+
+    // Names of the fetchers in use, the public ip fetcher included
+    pub fn fetcher_names(&self) -> Vec<String> {
+        let mut ret = self.fetchers.keys().cloned().collect::<Vec<_>>();
+        ret.push(self.global_fetcher_name.clone());
+        ret.sort();
+        ret.dedup();
+        ret
+    }
+
+    pub fn set_fetcher(&mut self, name: &str, fetcher: Box<dyn Fetcher>) {
+        self.fetchers.insert(name.to_string(), fetcher);
+    }
+
+    // `None` keeps the state in memory only
+    pub fn set_state_file(&mut self, state_file: Option<PathBuf>) {
+        self.state_file = state_file;
+    }
+
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat;
+    }
+
+    pub fn set_notifications(&mut self, notifications: Notifications) {
+        self.notifications = notifications;
+    }
+
+    // Send the `events` to `notifier` next to the sinks of the config, every
+    // event when the list is empty
+    pub fn add_notifier(
+        &mut self,
+        name: &str,
+        events: Vec<EventKind>,
+        notifier: Box<dyn Notifier>,
+    ) {
+        self.notifications.add(name, events, notifier);
+    }
+
+    // Observers are told in the order they were added
+    pub fn add_observer(&mut self, observer: Arc<dyn SyncObserver>) {
+        self.observers.push(observer);
+    }
+
+    // Stream of the address changes, record updates and errors of the next
+    // cycles, each call gets a stream of its own
+    pub fn events(&mut self) -> EventStream {
+        let (sender, stream) = events::EventSender::channel();
+        self.observers.push(Arc::new(sender));
+        stream
+    }
+
+    // Source of records besides the ones of the config. Its providers must be
+    // in use by the config, records for others are skipped.
+    pub fn add_source(&mut self, cfg: CfgSource, source: Box<dyn RecordSource>) {
+        self.sources.push(Source::new(cfg, source));
+    }
+
+    // Start watching the sources for changes, the returned `Notify` wakes
+    // whenever their records may have changed
+    pub fn watch_sources(&self) -> Arc<Notify> {
+        for source in self.sources.iter() {
+            source.watch(Arc::clone(&self.sources_changed));
+        }
+        Arc::clone(&self.sources_changed)
+    }
+
+    // Fail fast on bad credentials instead of on the first sync
+    pub async fn verify(&self) -> Result<()> {
+        for (provider_name, backend) in self.record_per_provider.iter() {
+            let Some(provider) = self.providers.get(provider_name) else {
+                continue;
+            };
+            let records = resolve_zones(provider.as_ref(), &backend.record)
+                .await
+                .map_err(|e| e.with_provider(provider_name))?;
+            let zones = records.zones.keys().cloned().collect::<Vec<_>>();
+            provider
+                .verify(&zones)
+                .await
+                .map_err(|e| e.with_provider(provider_name))?;
+        }
+        Ok(())
+    }
+
+    // One sync cycle, every provider syncing on a task of its own. Cancelling
+    // `cancel` stops the providers after the record changes in flight and the
+    // cycle fails with `Error::Cancelled`.
+    pub async fn run(&mut self, cancel: &CancellationToken) -> Result<()> {
+        let started_at = Utc::now();
+        let mut cycle = SyncCycle {
+            started_at,
+            finished_at: started_at,
+            public_ip: None,
+            providers: vec![],
+            error: None,
+        };
+        let result = self.run_cycle(&mut cycle, cancel).await;
+
+        cycle.finished_at = Utc::now();
+        self.state.push_cycle(cycle, self.history_size);
+        if let Some(api) = self.api.as_ref() {
+            if self.persist_api {
+                self.state.api_records = api.records();
+            }
+            api.update_status(&self.state);
+        }
+        for (name, provider) in self.providers.iter() {
+            let cache = provider.id_cache();
+            if cache.is_empty() {
+                self.state.ids.remove(name);
+            } else {
+                self.state.ids.insert(name.clone(), cache);
+            }
+        }
+        if let Some(path) = self.state_file.as_ref()
+            && let Err(e) = self.state.save(path)
+        {
+            log::warn!("failed to save state file {}: {}", path.display(), e);
+        }
+        // A shutdown is no failure to report
+        if let Some(heartbeat) = self.heartbeat.as_ref()
+            && !cancel.is_cancelled()
+            && let Err(e) = heartbeat.ping(result.is_ok()).await
+        {
+            log::warn!("heartbeat ping failed: {}", e);
+        }
+        result
+    }
+
+    async fn run_cycle(&mut self, cycle: &mut SyncCycle, cancel: &CancellationToken) -> Result<()> {
+        if let Some(sourced) = self.refresh_sources().await {
+            self.delete_unsourced(sourced).await;
+        }
+        let public_ip: Arc<PublicIp> = match self.fetch_public_ip(cancel).await {
+            Ok(public_ip) => Arc::new(public_ip.into()),
+            Err(e) => {
+                log::error!(outcome = "failed"; "fetching public ip failed: {}", e);
+                cycle.error = Some(format!("fetching public ip failed: {}", e));
+                self.observers.iter().for_each(|o| o.on_error(&e));
+                return Err(e);
+            }
+        };
+        self.observers
+            .iter()
+            .for_each(|o| o.on_fetch_complete(&public_ip));
+        self.check_public_ip_change(&public_ip).await;
+        cycle.public_ip = Some(PublicIp::clone(&public_ip));
+
+        // Providers sync on tasks of their own, on worker threads with a
+        // multi-thread runtime. A failing provider does not stop the others,
+        // the cycle returns all failures at the end.
+        let mut tasks = vec![];
+        for (provider_name, backend) in self.record_per_provider.iter() {
+            let Some(provider) = self.providers.get(provider_name).cloned() else {
+                continue;
+            };
+            if let Some(breaker) = self.breaker.as_ref()
+                && !breaker.allow(provider_name)
+            {
+                let retry_in = breaker.retry_in(provider_name).unwrap_or_default();
+                log::warn!(
+                    provider = provider_name, outcome = "skipped";
+                    "provider {} skipped, circuit open for {}s", provider_name, retry_in.as_secs()
+                );
+                cycle.providers.push(ProviderResult {
+                    provider: provider_name.clone(),
+                    success: false,
+                    error: Some(format!(
+                        "circuit open, next attempt in {}s",
+                        retry_in.as_secs()
+                    )),
+                    requests: 0,
+                    records: vec![],
+                });
+                continue;
+            }
+            let records = Arc::clone(&backend.record);
+            let public_ip = Arc::clone(&public_ip);
+            let cancel = cancel.clone();
+            let verifier = self.verifier.clone();
+            let task = tokio::spawn(async move {
+                let records = resolve_zones(provider.as_ref(), &records).await?;
+                let mut outcome = provider.sync(&records, &public_ip, &cancel).await;
+                if let Some(verifier) = verifier
+                    && !cancel.is_cancelled()
+                {
+                    verifier.verify(&records, &public_ip, &mut outcome).await;
+                }
+                Ok(outcome)
+            });
+            tasks.push((provider_name, task));
+        }
+
+        // The outcome of every record feeds the history, the observers and
+        // the notifications alike
+        let mut errors = vec![];
+        for (provider_name, task) in tasks {
+            let outcome = task
+                .await
+                .unwrap_or_else(|e| Err(Error::Provider(format!("sync task failed: {}", e))));
+            let (zones, requests, result) = match outcome {
+                Ok(outcome) => (
+                    outcome.zones,
+                    outcome.requests,
+                    Error::from_errors(outcome.errors),
+                ),
+                Err(e) => (vec![], 0, Err(e)),
+            };
+            let records = zones
+                .iter()
+                .flat_map(|z| z.records.iter().cloned())
+                .collect::<Vec<_>>();
+            cycle.providers.push(ProviderResult {
+                provider: provider_name.clone(),
+                success: result.is_ok(),
+                error: result.as_ref().err().map(|e| e.to_string()),
+                requests,
+                records: records.clone(),
+            });
+            for record in records.iter().filter(|r| r.status.is_synced()) {
+                self.observers
+                    .iter()
+                    .for_each(|o| o.on_record_applied(provider_name, &record.zone, &record.name));
+            }
+            match result {
+                Ok(()) => {
+                    self.health.record_success(provider_name);
+                    if let Some(breaker) = self.breaker.as_mut() {
+                        breaker.record_success(provider_name);
+                    }
+                    log::info!(
+                        provider = provider_name, outcome = "success";
+                        "provider {} synced", provider_name
+                    );
+                    let mut names = records
+                        .into_iter()
+                        .filter(|r| r.status.is_synced())
+                        .map(|r| r.name)
+                        .collect::<Vec<_>>();
+                    names.sort();
+                    let event = Event::sync_success(provider_name, names, public_ip.to_string());
+                    self.notifications.send(&event).await;
+                }
+                Err(Error::Cancelled) => errors.push(Error::Cancelled),
+                Err(e) => {
+                    let failures = self.health.record_failure(provider_name);
+                    log::error!(
+                        provider = provider_name, outcome = "failed";
+                        "provider {} failed: {}", provider_name, e
+                    );
+                    if let Some(breaker) = self.breaker.as_mut()
+                        && breaker.record_failure(provider_name) == CircuitState::Open
+                    {
+                        log::warn!(
+                            provider = provider_name;
+                            "circuit of provider {} open after {} failures", provider_name, failures
+                        );
+                    }
+                    let event = Event::sync_failure(provider_name, e.to_string(), failures);
+                    self.notifications.send(&event).await;
+                    let e = e.with_provider(provider_name);
+                    self.observers.iter().for_each(|o| o.on_error(&e));
+                    errors.push(e);
+                }
+            }
+        }
+
+        self.health.cycle_completed();
+        let result = Error::from_errors(errors);
+        if let Err(e) = result.as_ref() {
+            log::error!(outcome = "failed"; "sync cycle failed: {}", e);
+        }
+        result
+    }
+
+    // Take over the config of `next`, a runner built from the config read
+    // again. The state, the health, the API and the metrics served by the
+    // listener, the observers and the wake up of the sources stay the ones of
+    // this runner, adding or removing the API needs a restart.
+    pub fn reload(&mut self, mut next: Runner) {
+        next.state = std::mem::take(&mut self.state);
+        next.health = Arc::clone(&self.health);
+        next.drift = Arc::clone(&self.drift);
+        next.observers = std::mem::take(&mut self.observers);
+        next.sources_changed = Arc::clone(&self.sources_changed);
+        next.api = self.api.take();
+        *self = next;
+    }
+
+    // Make the next cycle ask the fetcher backends again
+    pub fn invalidate_fetchers(&mut self) {
+        for fetcher in self.fetchers.values_mut() {
+            fetcher.invalidate();
+        }
+    }
+
+    // Changes of every provider a run would make
+    pub async fn plan(&mut self) -> Result<Vec<ProviderChange>> {
+        self.refresh_sources().await;
+        let public_ip: PublicIp = self
+            .fetch_public_ip(&CancellationToken::new())
+            .await?
+            .into();
+        self.observers
+            .iter()
+            .for_each(|o| o.on_fetch_complete(&public_ip));
+
+        let mut ret = vec![];
+        for (provider_name, backend) in self.record_per_provider.iter() {
+            let Some(provider) = self.providers.get(provider_name) else {
+                continue;
+            };
+            let records = resolve_zones(provider.as_ref(), &backend.record).await?;
+            let changes = provider
+                .dry_run(&records, &public_ip)
+                .await
+                .map_err(|e| e.with_provider(provider_name))?;
+            ret.extend(changes.into_iter().map(|change| ProviderChange {
+                provider: provider_name.clone(),
+                change,
+            }));
+        }
+
+        ret.sort_by(|a, b| (&a.provider, &a.change.name).cmp(&(&b.provider, &b.change.name)));
+        self.observers.iter().for_each(|o| o.on_plan_ready(&ret));
+        Ok(ret)
+    }
+
+    // Records the providers serve differently from the config, e.g. edited
+    // by hand in the dashboard. Nothing is changed, a provider whose drifted
+    // records differ from the last check is notified about.
+    pub async fn check_drift(&mut self) -> Result<Vec<ProviderChange>> {
+        let drifted = self
+            .plan()
+            .await?
+            .into_iter()
+            .filter(|c| c.change.op != PlanOp::Unchanged)
+            .collect::<Vec<_>>();
+
+        let mut per_provider = self
+            .record_per_provider
+            .keys()
+            .map(|name| (name.clone(), vec![]))
+            .collect::<BTreeMap<_, Vec<String>>>();
+        for change in drifted.iter() {
+            per_provider
+                .entry(change.provider.clone())
+                .or_default()
+                .push(change.change.name.clone());
+        }
+        self.drift.record_check(
+            per_provider
+                .iter()
+                .map(|(k, v)| (k.clone(), v.len()))
+                .collect(),
+        );
+
+        for (provider_name, names) in per_provider {
+            if self.drift_seen.get(&provider_name) == Some(&names) {
+                continue;
+            }
+            if !names.is_empty() {
+                log::warn!(
+                    provider = provider_name.as_str(), outcome = "drift";
+                    "provider {} serves {} record(s) differing from the config",
+                    provider_name, names.len()
+                );
+                let event = Event::drift(&provider_name, names.clone());
+                self.notifications.send(&event).await;
+            }
+            self.drift_seen.insert(provider_name, names);
+        }
+        Ok(drifted)
+    }
+
+    // Read the sources again and sync their records, and the ones of the API,
+    // along with the ones of the config. Returns the records of the sources
+    // now synced, `None` when there is no source or the records conflict and
+    // the previous ones stay.
+    async fn refresh_sources(&mut self) -> Option<HashSet<SourcedKey>> {
+        if self.sources.is_empty() && self.api.is_none() {
+            return None;
+        }
+        for source in self.sources.iter_mut() {
+            if let Err(e) = source.refresh().await {
+                log::warn!(
+                    "source {} failed, its last records are kept: {}",
+                    source.name(),
+                    e
+                );
+            }
+        }
+
+        let sourced = self
+            .sources
+            .iter()
+            .flat_map(|s| s.items().iter().cloned())
+            .chain(self.api.iter().flat_map(|a| a.items()))
+            .collect::<Vec<_>>();
+        let sourced = prune_records(
+            sourced,
+            &self.providers,
+            &self.fetchers,
+            &self.global_fetcher_name,
+            false,
+        )
+        .ok()?;
+        let mut keys = HashSet::new();
+        for item in sourced.iter() {
+            for provider in item.providers.iter() {
+                for zone in provider.zones.iter() {
+                    keys.insert((
+                        provider.name.clone(),
+                        zone.clone(),
+                        item.record.fqdn(zone),
+                        item.record.content.record_type(),
+                    ));
+                }
+            }
+        }
+
+        let records = self.static_records.iter().cloned().chain(sourced);
+        let backends = to_provider_backends(records.collect())
+            .and_then(|b| lint_provider_backends(&b).map(|_| b));
+        match backends {
+            Ok(backends) => self.record_per_provider = backends,
+            Err(e) => {
+                log::error!("records of the sources left as they were: {}", e);
+                return None;
+            }
+        }
+        Some(keys)
+    }
+
+    // Delete the records gone from the sources since the last cycle, the ones
+    // the providers fail to delete are tried again next cycle
+    async fn delete_unsourced(&mut self, sourced: HashSet<SourcedKey>) {
+        let gone = self
+            .sourced
+            .difference(&sourced)
+            .cloned()
+            .collect::<Vec<_>>();
+        self.sourced = sourced;
+        for key in gone {
+            let (provider_name, zone, name, record_type) = &key;
+            let Some(provider) = self.providers.get(provider_name) else {
+                continue;
+            };
+            match provider
+                .delete_record(zone, name, record_type.clone(), false)
+                .await
+            {
+                Ok(deleted) if !deleted.is_empty() => log::info!(
+                    provider = provider_name;
+                    "record {} gone from its source, deleted", name
+                ),
+                Ok(_) => {}
+                Err(e) => {
+                    log::warn!(
+                        provider = provider_name;
+                        "record {} gone from its source, deleting failed: {}", name, e
+                    );
+                    self.sourced.insert(key);
+                }
+            }
+        }
+    }
+
+    // The first address ever fetched is only remembered, later ones are
+    // compared with the one of the previous cycle, across restarts when a
+    // state file is configured
+    async fn check_public_ip_change(&mut self, public_ip: &PublicIp) {
+        self.public_ip_changed = self.state.public_ip.as_ref() != Some(public_ip);
+        if self.public_ip_changed {
+            let previous = self.state.public_ip.as_ref();
+            self.observers
+                .iter()
+                .for_each(|o| o.on_ip_changed(previous, public_ip));
+        }
+        match self.state.public_ip.as_ref() {
+            Some(previous) if previous != public_ip => {
+                log::info!("public ip changed from {} to {}", previous, public_ip);
+                let event = Event::ip_change(previous.to_string(), public_ip.to_string());
+                self.notifications.send(&event).await;
+            }
+            _ => {}
+        }
+    }
+
+    // Without a public ip fetcher only records with static contents are left
+    async fn fetch_public_ip(&mut self, cancel: &CancellationToken) -> Result<FetcherRecordSet> {
+        match self.fetchers.get_mut(&self.global_fetcher_name) {
+            Some(fetcher) => fetcher.fetch(cancel).await,
+            None => Ok(FetcherRecordSet::default()),
+        }
+    }
+}
+
+// Expand zone patterns against the zones the provider can access
+pub async fn resolve_zones(
+    provider: &dyn Provider,
+    records: &Arc<BackendRecords>,
+) -> Result<Arc<BackendRecords>> {
+    if !records.has_wildcard_zones() {
+        return Ok(Arc::clone(records));
+    }
+
+    let available = provider.list_zones().await?;
+    Ok(Arc::new(records.expand_zones(&available)))
+}
+
+fn list_in_use_providers(records: &[CfgRecordItem], sources: &[CfgSource]) -> Vec<String> {
+    let mut ret = records
+        .iter()
+        .flat_map(|r| r.providers.iter().map(|p| p.name.clone()))
+        .chain(
+            sources
+                .iter()
+                .flat_map(|s| s.providers.iter().map(|p| p.name.clone())),
+        )
+        .collect::<Vec<_>>();
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+fn create_providers(
+    in_use_providers: &[String],
+    providers: &[CfgProvider],
+    hook: Option<&CfgHook>,
+    strict: bool,
+    registry: &ProviderRegistry,
+) -> Result<ProviderMap> {
+    let mut ret = ProviderMap::new();
+    for provider in providers
+        .iter()
+        .filter(|f| in_use_providers.contains(&f.name))
+    {
+        match registry.create(&provider.provider_config()) {
+            Ok(p) => {
+                let mut p: Arc<dyn Provider> = Arc::from(p);
+                if let Some(hook) = provider.hook.as_ref().or(hook) {
+                    let hook = RecordHook::from(hook.clone());
+                    p = Arc::new(HookedProvider::new(&provider.name, p, hook));
+                }
+                ret.insert(provider.name.clone(), p);
+            }
+            Err(e) if strict => return Err(e),
+            Err(e) => log::warn!("{}, records using it are skipped", e),
+        }
+    }
+    Ok(ret)
+}
+
+fn create_notifications(notifications: &[CfgNotification], strict: bool) -> Result<Notifications> {
+    let mut ret = Notifications::new();
+    for notification in notifications {
+        match create_notifier(notification) {
+            Ok(n) => {
+                let events = match notification.r#type.as_str() {
+                    "desktop" if notification.events.is_empty() => Desktop::default_events(),
+                    _ => notification.events.clone(),
+                };
+                ret.add(&notification.name, events, n);
+            }
+            Err(e) if strict => return Err(e),
+            Err(e) => log::warn!("{}, notifications are not sent to it", e),
+        }
+    }
+    Ok(ret)
+}
+
+fn create_notifier(notification: &CfgNotification) -> Result<Box<dyn Notifier>> {
+    let params = notification.params.clone().into();
+    let notifier: Box<dyn Notifier> = match notification.r#type.as_str() {
+        "webhook" => Box::new(Webhook::new_with_args(params)?),
+        "slack" => Box::new(Slack::new_with_args(params)?),
+        "ntfy" => Box::new(Ntfy::new_with_args(params)?),
+        "gotify" => Box::new(Gotify::new_with_args(params)?),
+        "pushover" => Box::new(Pushover::new_with_args(params)?),
+        "desktop" => Box::new(Desktop::new_with_args(params)?),
+        "mqtt" => Box::new(Mqtt::new_with_args(params)?),
+        ty => {
+            return Err(Error::ParseError(format!(
+                "notification {}: unknown type {}",
+                notification.name, ty
+            )));
+        }
+    };
+    Ok(notifier)
+}
+
+fn create_sources(sources: Vec<CfgSource>, base_dir: &Path, strict: bool) -> Result<Vec<Source>> {
+    let mut ret = vec![];
+    for cfg in sources {
+        match crate::source::create_source(&cfg, base_dir) {
+            Ok(source) => ret.push(Source::new(cfg, source)),
+            Err(e) if strict => return Err(e),
+            Err(e) => log::warn!("{}, its records are not synced", e),
+        }
+    }
+    Ok(ret)
+}
+
+fn list_in_use_fethers(
+    records: &[CfgRecordItem],
+    sources: &[CfgSource],
+    public_ip_fecher: &str,
+) -> Vec<String> {
+    let mut ret = records
+        .iter()
+        .flat_map(|r| r.fetchers.iter().map(|f| f.name.clone()))
+        .chain(
+            sources
+                .iter()
+                .flat_map(|s| s.fetchers.iter().map(|f| f.name.clone())),
+        )
+        .collect::<Vec<_>>();
+    ret.push(public_ip_fecher.to_string());
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+fn create_fetchers(
+    records: &[CfgRecordItem],
+    sources: &[CfgSource],
+    public_ip_fecher: &str,
+    fetchers: &[CfgFetcher],
+    strict: bool,
+) -> Result<FetcherMap> {
+    let in_use_fetchers = list_in_use_fethers(records, sources, public_ip_fecher);
+
+    let mut ret = FetcherMap::new();
+    for fetcher in fetchers
+        .iter()
+        .filter(|f| in_use_fetchers.contains(&f.name))
+    {
+        match create_fetcher(fetcher) {
+            Ok(f) => {
+                ret.insert(fetcher.name.clone(), f);
+            }
+            Err(e) if strict => return Err(e),
+            Err(e) => log::warn!("{}, records using it are skipped", e),
+        }
+    }
+    Ok(ret)
+}
+
+pub fn create_fetcher(fetcher: &CfgFetcher) -> Result<Box<dyn Fetcher>> {
+    match fetcher.r#type.as_str() {
+        "http_fetcher" => Ok(Box::new(HttpFetcher::new_with_args(
+            fetcher.params.clone().into(),
+        )?)),
+        ty => Err(Error::ParseError(format!(
+            "fetcher {}: unknown type {}",
+            fetcher.name, ty
+        ))),
+    }
+}
+
+// Remove records depending on providers or fetchers which could not be created.
+// A record keeps the providers that exist, and is dropped entirely when none is
+// left or a fetcher it needs is missing. In strict mode any of these is an error.
+fn prune_records(
+    records: Vec<CfgRecordItem>,
+    providers: &ProviderMap,
+    fetchers: &FetcherMap,
+    public_ip_fecher: &str,
+    strict: bool,
+) -> Result<Vec<CfgRecordItem>> {
+    let mut ret = vec![];
+
+    for mut item in records {
+        let mut missing = item
+            .providers
+            .iter()
+            .filter(|p| !providers.contains_key(&p.name))
+            .map(|p| format!("provider {}", p.name))
+            .collect::<Vec<_>>();
+        let providers_missing = missing.len();
+
+        missing.extend(
+            item.fetchers
+                .iter()
+                .filter(|f| !fetchers.contains_key(&f.name))
+                .map(|f| format!("fetcher {}", f.name)),
+        );
+        if item.record.content.is_unassigned() && !fetchers.contains_key(public_ip_fecher) {
+            missing.push(format!("public ip fetcher {}", public_ip_fecher));
+        }
+
+        if missing.is_empty() {
+            ret.push(item);
+            continue;
+        }
+
+        let msg = format!(
+            "record {} uses unavailable {}",
+            item.record.name,
+            missing.join(", ")
+        );
+        if strict {
+            return Err(Error::ParseError(msg));
+        }
+
+        item.providers.retain(|p| providers.contains_key(&p.name));
+        if missing.len() == providers_missing && !item.providers.is_empty() {
+            log::warn!("{}, synced to the other providers only", msg);
+            ret.push(item);
+        } else {
+            log::warn!("{}, skipped", msg);
+        }
+    }
+
+    Ok(ret)
+}
+
+// Refuse records which would overwrite each other at the provider
+fn lint_provider_backends(backends: &HashMap<String, ProviderBackend>) -> Result<()> {
+    let conflicts = backends
+        .iter()
+        .flat_map(|(name, backend)| {
+            backend
+                .record
+                .conflicts()
+                .into_iter()
+                .map(move |c| format!("{}: {}", name, c))
+        })
+        .collect::<Vec<_>>();
+
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::ParseError(format!(
+            "conflicting records in config:\n  {}",
+            conflicts.join("\n  ")
+        )))
+    }
+}
+
+pub fn to_provider_backends(
+    cfg_records: Vec<CfgRecordItem>,
+) -> Result<HashMap<String, ProviderBackend>> {
+    let mut ret: HashMap<String, ProviderBackend> = HashMap::new();
+
+    for item in cfg_records {
+        process_record_item(&mut ret, item)?;
+    }
+    Ok(ret)
+}
+
+fn process_record_item(
+    records_map: &mut HashMap<String, ProviderBackend>,
+    item: CfgRecordItem,
+) -> Result<()> {
+    for provider in item.providers {
+        process_provider(records_map, &item.record, provider)?;
+    }
+    Ok(())
+}
+
+fn process_provider(
+    records_map: &mut HashMap<String, ProviderBackend>,
+    record: &CfgRecord,
+    provider: CfgRecordProvider,
+) -> Result<()> {
+    let provider_name = provider.name;
+    let backend_records = records_map.entry(provider_name).or_default();
+
+    for zone in provider.zones {
+        add_zone_record(
+            Arc::make_mut(&mut backend_records.record),
+            zone,
+            record,
+            &provider.params,
+        )?;
+    }
+    Ok(())
+}
+
+fn add_zone_record(
+    backend_records: &mut BackendRecords,
+    zone: ZoneName,
+    record: &CfgRecord,
+    params: &CfgParamList,
+) -> Result<()> {
+    let zone_records = backend_records.zones.entry(zone).or_default();
+    let provider_record = record.clone().into_provider_record(params)?;
+    zone_records.records.push(provider_record);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::fetcher::StaticFetcher;
+    use crate::provider::AuthParams;
+    use crate::provider::MockProvider;
+    use crate::provider::ParamList;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl SyncObserver for Recorder {
+        fn on_fetch_complete(&self, public_ip: &PublicIp) {
+            self.0.lock().unwrap().push(format!("fetch {}", public_ip));
+        }
+
+        fn on_plan_ready(&self, changes: &[ProviderChange]) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("plan {}", changes.len()));
+        }
+
+        fn on_record_applied(&self, provider: &str, zone: &str, record: &str) {
+            let event = format!("applied {} {} {}", provider, zone, record);
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    // Notifier keeping the message of every event it is sent
+    struct Pager(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait::async_trait]
+    impl Notifier for Pager {
+        async fn notify(&self, event: &Event) -> Result<()> {
+            self.0.lock().unwrap().push(event.message());
+            Ok(())
+        }
+    }
+
+    // Runner syncing one record to a mock provider, with a static fetcher
+    fn mock_runner() -> Runner {
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+providers:
+  - name: mock-1
+    type: mock
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+records:
+  - type: A
+    name: home
+    providers:
+      - name: mock-1
+        zones: [example.org]
+"#;
+        let config: Cfg = serde_yaml::from_str(yaml).unwrap();
+        let mut registry = ProviderRegistry::empty();
+        registry.register(
+            "mock",
+            |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                Ok(Box::new(MockProvider::new()))
+            },
+        );
+        let records = config.record_items().unwrap();
+        let mut runner = Runner::new(config, records, &registry).unwrap();
+        let fetcher = StaticFetcher::new(Some(Ipv4Addr::new(192, 0, 2, 1)), None);
+        runner.set_fetcher("static", Box::new(fetcher));
+        runner
+    }
+
+    #[test]
+    fn test_unknown_types() {
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+providers:
+  - name: mock-1
+    type: mock
+  - name: gandi-1
+    type: gandi
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+  - name: upnp
+    type: upnp
+    params: []
+records:
+  - type: A
+    name: home
+    providers:
+      - name: mock-1
+        zones: [example.org]
+      - name: gandi-1
+        zones: [example.org]
+  - type: A
+    name: nas
+    providers:
+      - name: gandi-1
+        zones: [example.org]
+  - type: A
+    name: lan
+    fetchers:
+      - name: upnp
+    providers:
+      - name: mock-1
+        zones: [example.org]
+"#;
+        let mut registry = ProviderRegistry::empty();
+        registry.register(
+            "mock",
+            |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                Ok(Box::new(MockProvider::new()))
+            },
+        );
+        let runner = |yaml: &str| {
+            let config: Cfg = serde_yaml::from_str(yaml).unwrap();
+            let records = config.record_items().unwrap();
+            Runner::new(config, records, &registry)
+        };
+
+        // Permissive, entries of unknown types are dropped with the records
+        // needing them, a record keeps the providers that exist
+        let permissive = runner(yaml).unwrap();
+        let names = permissive
+            .backends()
+            .iter()
+            .flat_map(|(provider, backend)| {
+                backend.record.zones.values().flat_map(move |zone| {
+                    zone.records
+                        .iter()
+                        .map(move |r| format!("{} {}", provider, r.name))
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["mock-1 home"]);
+        assert_eq!(permissive.fetcher_names(), vec!["static"]);
+
+        // Strict, the first unknown type is an error
+        let err = runner(&format!("strict: true\n{}", yaml)).err().unwrap();
+        assert!(
+            err.to_string().contains("fetcher upnp: unknown type upnp"),
+            "{}",
+            err
+        );
+        let yaml = yaml.replace("  - name: upnp\n    type: upnp\n    params: []\n", "");
+        let err = runner(&format!("strict: true\n{}", yaml)).err().unwrap();
+        assert!(err.to_string().contains("unknown type gandi"), "{}", err);
+        let yaml = yaml.replace("    type: gandi", "    type: mock");
+        let err = runner(&format!("strict: true\n{}", yaml)).err().unwrap();
+        assert!(
+            err.to_string()
+                .contains("record lan uses unavailable fetcher upnp"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_observer() {
+        let mut runner = mock_runner();
+        let recorder = Arc::new(Recorder::default());
+        runner.add_observer(recorder.clone());
+
+        runner.plan().await.unwrap();
+        runner.run(&CancellationToken::new()).await.unwrap();
+        let events = recorder.0.lock().unwrap().clone();
+        assert_eq!(
+            events,
+            vec![
+                "fetch 192.0.2.1",
+                "plan 1",
+                "fetch 192.0.2.1",
+                "applied mock-1 example.org home.example.org",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let mut runner = mock_runner();
+        let recorder = Arc::new(Recorder::default());
+        runner.add_observer(recorder.clone());
+        runner.run(&CancellationToken::new()).await.unwrap();
+        let health = runner.health();
+
+        runner.reload(mock_runner());
+        // The history and the observers carry over, the providers are new
+        assert_eq!(runner.state().cycles.len(), 1);
+        assert!(Arc::ptr_eq(&health, &runner.health()));
+        runner.run(&CancellationToken::new()).await.unwrap();
+        assert_eq!(runner.state().cycles.len(), 2);
+        assert_eq!(
+            recorder
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|e| e.starts_with("applied"))
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run_on_worker_thread() {
+        let mut runner = mock_runner();
+        let cycle = tokio::spawn(async move {
+            let result = runner.run(&CancellationToken::new()).await;
+            (runner, result)
+        });
+        let (runner, result) = cycle.await.unwrap();
+        result.unwrap();
+        assert_eq!(runner.state().cycles.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let mut runner = mock_runner();
+        let mut events = runner.events();
+        let cancel = CancellationToken::new();
+
+        runner.run(&cancel).await.unwrap();
+        let public_ip = PublicIp::new(Some(Ipv4Addr::new(192, 0, 2, 1)), None);
+        assert_eq!(
+            events.next().await,
+            Some(SyncEvent::IpChanged {
+                old: None,
+                new: public_ip,
+            })
+        );
+        assert_eq!(
+            events.next().await,
+            Some(SyncEvent::RecordUpdated {
+                provider: "mock-1".to_string(),
+                zone: "example.org".to_string(),
+                record: "home.example.org".to_string(),
+            })
+        );
+
+        // The same address is no change
+        runner.run(&cancel).await.unwrap();
+        assert!(matches!(
+            events.try_next(),
+            Some(SyncEvent::RecordUpdated { .. })
+        ));
+        assert_eq!(events.try_next(), None);
+
+        drop(runner);
+        assert_eq!(events.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_add_notifier() {
+        let mut runner = mock_runner();
+        let pages = Arc::new(Mutex::new(vec![]));
+        let pager = Box::new(Pager(pages.clone()));
+        runner.add_notifier("pager", vec![EventKind::SyncSuccess], pager);
+        runner.run(&CancellationToken::new()).await.unwrap();
+        assert_eq!(
+            *pages.lock().unwrap(),
+            vec!["provider mock-1 synced 1 record(s)"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_drift() {
+        let provider = MockProvider::new();
+        let mut registry = ProviderRegistry::empty();
+        registry.register("mock", crate::testing::mock_factory(provider.clone()));
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+providers:
+  - name: mock-1
+    type: mock
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+records:
+  - type: A
+    name: home
+    providers:
+      - name: mock-1
+        zones: [example.org]
+"#;
+        let config: Cfg = serde_yaml::from_str(yaml).unwrap();
+        let records = config.record_items().unwrap();
+        let mut runner = Runner::new(config, records, &registry).unwrap();
+        let fetcher = StaticFetcher::new(Some(Ipv4Addr::new(192, 0, 2, 1)), None);
+        runner.set_fetcher("static", Box::new(fetcher));
+        let pages = Arc::new(Mutex::new(vec![]));
+        let pager = Box::new(Pager(pages.clone()));
+        runner.add_notifier("pager", vec![EventKind::Drift], pager);
+
+        runner.run(&CancellationToken::new()).await.unwrap();
+        assert!(runner.check_drift().await.unwrap().is_empty());
+        assert!(pages.lock().unwrap().is_empty());
+
+        // Deleted by hand at the provider
+        provider
+            .delete_record(
+                &"example.org".into(),
+                "home.example.org",
+                RecordType::A,
+                true,
+            )
+            .await
+            .unwrap();
+        let drifted = runner.check_drift().await.unwrap();
+        assert_eq!(drifted.len(), 1);
+        assert_eq!(drifted[0].change.op, PlanOp::Create);
+        // Reported once, not at every check
+        runner.check_drift().await.unwrap();
+        assert_eq!(
+            *pages.lock().unwrap(),
+            vec!["provider mock-1 serves 1 record(s) differing from the config: home.example.org"]
+        );
+        let metrics = runner.drift_metrics().render();
+        assert!(metrics.contains("dns_syncer_drifted_records{provider=\"mock-1\"} 1\n"));
+        assert!(metrics.contains("dns_syncer_drift_checks_total 3\n"));
+        // Nothing was changed
+        assert!(provider.records()["example.org"].is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_in_flight() {
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+providers:
+  - name: mock-1
+    type: mock
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+records:
+  - type: A
+    name: home
+    providers:
+      - name: mock-1
+        zones: [example.org]
+  - type: A
+    name: nas
+    providers:
+      - name: mock-1
+        zones: [example.org]
+  - type: A
+    name: vpn
+    providers:
+      - name: mock-1
+        zones: [example.org]
+"#;
+        // Every change takes 200ms, the three of the zone 600ms
+        let delay = Duration::from_millis(200);
+        let provider = MockProvider::new().with_delay(delay);
+        let view = provider.clone();
+        let config: Cfg = serde_yaml::from_str(yaml).unwrap();
+        let mut registry = ProviderRegistry::empty();
+        registry.register(
+            "mock",
+            move |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                Ok(Box::new(provider.clone()))
+            },
+        );
+        let records = config.record_items().unwrap();
+        let mut runner = Runner::new(config, records, &registry).unwrap();
+        let fetcher = StaticFetcher::new(Some(Ipv4Addr::new(192, 0, 2, 1)), None);
+        runner.set_fetcher("static", Box::new(fetcher));
+
+        // Cancelled during the first change, the cycle ends with it
+        let cancel = CancellationToken::new();
+        let canceller = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            canceller.cancel();
+        });
+        let started = std::time::Instant::now();
+        let err = runner.run(&cancel).await.unwrap_err();
+        assert!(matches!(err, Error::Cancelled), "{}", err);
+        assert!(started.elapsed() < delay * 2, "{:?}", started.elapsed());
+        assert_eq!(view.records()["example.org"].len(), 1);
+        assert!(!runner.state().cycles[0].providers[0].success);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        // Provider failing every listing
+        struct Broken;
+
+        #[async_trait::async_trait]
+        impl Provider for Broken {}
+
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+circuit_breaker:
+  failures: 2
+  cooldown: 1h
+providers:
+  - name: broken-1
+    type: broken
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+records:
+  - type: A
+    name: home
+    providers:
+      - name: broken-1
+        zones: [example.org]
+"#;
+        let config: Cfg = serde_yaml::from_str(yaml).unwrap();
+        let mut registry = ProviderRegistry::empty();
+        registry.register(
+            "broken",
+            |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                Ok(Box::new(Broken))
+            },
+        );
+        let records = config.record_items().unwrap();
+        let mut runner = Runner::new(config, records, &registry).unwrap();
+        let fetcher = StaticFetcher::new(Some(Ipv4Addr::new(192, 0, 2, 1)), None);
+        runner.set_fetcher("static", Box::new(fetcher));
+
+        let cancel = CancellationToken::new();
+        assert!(runner.run(&cancel).await.is_err());
+        assert!(runner.run(&cancel).await.is_err());
+        // Open after two failures, the provider is left out
+        assert!(runner.run(&cancel).await.is_ok());
+        let skipped = &runner.state().last_cycle().unwrap().providers[0];
+        assert!(!skipped.success);
+        assert!(skipped.error.as_deref().unwrap().contains("circuit open"));
+    }
+
+    #[tokio::test]
+    async fn test_sources() {
+        use crate::source::SourcedRecord;
+
+        // Source handing out what the test put in
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<SourcedRecord>>>);
+
+        #[async_trait::async_trait]
+        impl RecordSource for Shared {
+            async fn records(&self) -> Result<Vec<SourcedRecord>> {
+                Ok(self.0.lock().unwrap().clone())
+            }
+        }
+
+        let provider = MockProvider::new();
+        let mut registry = ProviderRegistry::empty();
+        registry.register("mock", crate::testing::mock_factory(provider.clone()));
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+providers:
+  - name: mock-1
+    type: mock
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+sources:
+  - name: containers
+    type: shared
+    providers:
+      - name: mock-1
+        zones: [example.org]
+"#;
+        let config: Cfg = serde_yaml::from_str(yaml).unwrap();
+        let cfg = config.sources[0].clone();
+        let mut config_without_source = config.clone();
+        config_without_source.sources.clear();
+        // The unknown type is skipped, the source is added by hand
+        let mut runner = Runner::new(config, vec![], &registry).unwrap();
+        let fetcher = StaticFetcher::new(Some(Ipv4Addr::new(192, 0, 2, 1)), None);
+        runner.set_fetcher("static", Box::new(fetcher));
+        let source = Shared::default();
+        runner.add_source(cfg, Box::new(source.clone()));
+
+        let cancel = CancellationToken::new();
+        *source.0.lock().unwrap() = vec![
+            SourcedRecord::new("app.example.org"),
+            SourcedRecord::new("web.example.org"),
+            SourcedRecord::new("app.example.net"),
+        ];
+        runner.run(&cancel).await.unwrap();
+        let names = |provider: &MockProvider| {
+            let mut names = provider.records()["example.org"]
+                .iter()
+                .map(|r| r.name.clone())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&provider), vec!["app.example.org", "web.example.org"]);
+
+        // A stopped container takes its record along
+        source.0.lock().unwrap().remove(0);
+        runner.run(&cancel).await.unwrap();
+        assert_eq!(names(&provider), vec!["web.example.org"]);
+
+        // Providers are created for the sources alone too
+        assert!(runner.providers().contains_key("mock-1"));
+        let runner = Runner::new(config_without_source, vec![], &registry).unwrap();
+        assert!(runner.providers().is_empty());
+    }
+}
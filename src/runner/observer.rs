@@ -0,0 +1,26 @@
+use crate::error::Error;
+use crate::runner::ProviderChange;
+use crate::types::PublicIp;
+
+// Progress of the runner for embedders, like a GUI showing what a sync does
+// without parsing logs. Callbacks run inline on the sync task and must not
+// block, slow work belongs on a channel. Every method defaults to nothing.
+pub trait SyncObserver: Send + Sync {
+    // The public address of a cycle or plan is known
+    fn on_fetch_complete(&self, _public_ip: &PublicIp) {}
+
+    // The address of a cycle differs from the one of the previous cycle,
+    // `old` is `None` when no address was fetched before
+    fn on_ip_changed(&self, _old: Option<&PublicIp>, _new: &PublicIp) {}
+
+    // `Runner::plan` computed the changes of every provider
+    fn on_plan_ready(&self, _changes: &[ProviderChange]) {}
+
+    // A record is synced. Records of a provider are reported once its sync
+    // succeeded, with their full name.
+    fn on_record_applied(&self, _provider: &str, _zone: &str, _record: &str) {}
+
+    // Fetching the public address or syncing a provider failed, the error
+    // names the provider
+    fn on_error(&self, _error: &Error) {}
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use super::ProviderChange;
+use super::Runner;
+use crate::config::Cfg;
+use crate::config::CfgRecordItem;
+use crate::error::Result;
+use crate::fetcher::StaticFetcher;
+use crate::notify::Notifications;
+use crate::provider::AuthParams;
+use crate::provider::MockProvider;
+use crate::provider::ParamList;
+use crate::provider::Provider;
+use crate::provider::ProviderRegistry;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::TTL;
+use crate::types::ZoneName;
+
+// Documentation addresses of RFC 5737 and RFC 3849, used when the fixture
+// gives no public address
+const DEFAULT_V4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+const DEFAULT_V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FixtureIp {
+    pub v4: Option<Ipv4Addr>,
+    pub v6: Option<Ipv6Addr>,
+}
+
+impl Default for FixtureIp {
+    fn default() -> Self {
+        Self {
+            v4: Some(DEFAULT_V4),
+            v6: Some(DEFAULT_V6),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FixtureRecord {
+    pub name: String,
+    #[serde(flatten)]
+    pub content: RecordContent,
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub ttl: TTL,
+}
+
+impl From<FixtureRecord> for ProviderRecord {
+    fn from(record: FixtureRecord) -> Self {
+        Self {
+            name: record.name,
+            content: record.content,
+            comment: record.comment,
+            op: RecordOp::default(),
+            ttl: record.ttl,
+            params: vec![],
+        }
+    }
+}
+
+// What the mock providers serve before the simulated sync, and the public
+// address the fetchers answer
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Fixture {
+    #[serde(default)]
+    pub public_ip: FixtureIp,
+    // Provider name to its zones with their records
+    #[serde(default)]
+    pub providers: HashMap<String, HashMap<ZoneName, Vec<FixtureRecord>>>,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_yaml::from_reader(BufReader::new(file))?)
+    }
+
+    // Providers of the fixture only have its zones, the others accept any zone
+    pub fn provider(&self, name: &str) -> MockProvider {
+        match self.providers.get(name) {
+            Some(zones) => MockProvider::with_zones(
+                zones
+                    .iter()
+                    .map(|(zone, records)| {
+                        let records = records.iter().cloned().map(ProviderRecord::from).collect();
+                        (zone.clone(), records)
+                    })
+                    .collect(),
+            ),
+            None => MockProvider::new(),
+        }
+    }
+}
+
+// A record a mock provider serves after the simulated sync
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulatedRecord {
+    pub provider: String,
+    pub zone: String,
+    #[serde(flatten)]
+    pub record: ProviderRecord,
+}
+
+// Outcome of a simulated sync: the planned changes, what the providers serve
+// afterwards and the error of the cycle
+#[derive(Debug, Clone, Serialize)]
+pub struct Simulation {
+    pub changes: Vec<ProviderChange>,
+    pub records: Vec<SimulatedRecord>,
+    pub error: Option<String>,
+}
+
+impl Runner {
+    // Runner syncing `records` with every provider swapped for a mock seeded
+    // from `fixture` and the fetchers answering the fixture addresses. Nothing
+    // leaves the process: no state file, notification or heartbeat is written.
+    pub fn simulated(config: Cfg, records: Vec<CfgRecordItem>, fixture: &Fixture) -> Result<Self> {
+        let mocks = config
+            .providers
+            .iter()
+            .map(|p| (p.name.clone(), fixture.provider(&p.name)))
+            .collect::<HashMap<_, _>>();
+        let mut registry = ProviderRegistry::empty();
+        for cfg in config.providers.iter() {
+            let mocks = mocks.clone();
+            let factory =
+                move |name: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                    Ok(Box::new(mocks[name].clone()))
+                };
+            registry.register(&cfg.r#type, factory);
+        }
+
+        let mut runner = Self::new(config, records, &registry)?;
+        runner.set_state_file(None);
+        runner.set_heartbeat(None);
+        runner.set_notifications(Notifications::new());
+        for name in runner.fetcher_names() {
+            let fetcher = StaticFetcher::new(fixture.public_ip.v4, fixture.public_ip.v6);
+            runner.set_fetcher(&name, Box::new(fetcher));
+        }
+        Ok(runner)
+    }
+
+    // Plan and run one cycle, then read back every record the providers serve.
+    // A failing cycle is part of the outcome, only planning fails the call.
+    pub async fn simulate(&mut self) -> Result<Simulation> {
+        let changes = self.plan().await?;
+        let error = self
+            .run(&CancellationToken::new())
+            .await
+            .err()
+            .map(|e| e.to_string());
+
+        let mut records = vec![];
+        for (provider, instance) in self.providers.iter() {
+            for zone in instance.list_zones().await? {
+                for record in instance.list_zone_records(&zone).await? {
+                    records.push(SimulatedRecord {
+                        provider: provider.clone(),
+                        zone: zone.clone(),
+                        record,
+                    });
+                }
+            }
+        }
+        records.sort_by(|a, b| {
+            let (ta, tb) = (
+                a.record.content.record_type(),
+                b.record.content.record_type(),
+            );
+            (&a.provider, &a.zone, &a.record.name, ta.as_str()).cmp(&(
+                &b.provider,
+                &b.zone,
+                &b.record.name,
+                tb.as_str(),
+            ))
+        });
+
+        Ok(Simulation {
+            changes,
+            records,
+            error,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fixture() {
+        let yaml = r#"
+public_ip:
+  v4: 203.0.113.7
+providers:
+  cloudflare-1:
+    example.org:
+      - name: home.example.org
+        type: A
+        content: 198.51.100.1
+        comment: "home [dns-syncer]"
+"#;
+        let fixture: Fixture = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(fixture.public_ip.v4, Some(Ipv4Addr::new(203, 0, 113, 7)));
+        assert_eq!(fixture.public_ip.v6, None);
+
+        let records = fixture.provider("cloudflare-1").records();
+        assert_eq!(records["example.org"][0].name, "home.example.org");
+        assert!(records["example.org"][0].is_owned());
+        assert!(fixture.provider("other").records().is_empty());
+
+        let fixture = Fixture::default();
+        assert_eq!(fixture.public_ip.v4, Some(DEFAULT_V4));
+    }
+
+    #[tokio::test]
+    async fn test_simulate() {
+        let config = r#"
+check_interval: 0
+public_ip_fecher: static
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+providers:
+  - name: cloudflare-1
+    type: cloudflare
+    authentication: {method: api_token, params: []}
+records:
+  - type: A
+    name: home
+    providers:
+      - name: cloudflare-1
+        zones: [example.org]
+  - type: A
+    name: nas
+    providers:
+      - name: cloudflare-1
+        zones: [example.org]
+"#;
+        let fixture = r#"
+public_ip:
+  v4: 203.0.113.7
+providers:
+  cloudflare-1:
+    example.org:
+      - name: home.example.org
+        type: A
+        content: 198.51.100.1
+        comment: "home [dns-syncer]"
+"#;
+        let config: Cfg = serde_yaml::from_str(config).unwrap();
+        let fixture: Fixture = serde_yaml::from_str(fixture).unwrap();
+        let records = config.record_items().unwrap();
+        let mut runner = Runner::simulated(config, records, &fixture).unwrap();
+        runner.verify().await.unwrap();
+
+        let simulation = runner.simulate().await.unwrap();
+        assert_eq!(simulation.error, None);
+        assert_eq!(simulation.changes.len(), 2);
+        let records = simulation
+            .records
+            .iter()
+            .map(|r| format!("{} {}", r.record.name, r.record.content))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                "home.example.org 203.0.113.7",
+                "nas.example.org 203.0.113.7"
+            ]
+        );
+        assert!(runner.state().last_cycle().is_some());
+    }
+}
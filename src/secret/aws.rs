@@ -0,0 +1,83 @@
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::wrapper::aws;
+use crate::wrapper::http;
+
+#[derive(Debug, Deserialize)]
+struct SsmParameterValue {
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SsmGetParameter {
+    #[serde(rename = "Parameter")]
+    parameter: SsmParameterValue,
+}
+
+#[derive(Debug, Deserialize)]
+struct SecretsManagerGetSecretValue {
+    #[serde(rename = "SecretString")]
+    secret_string: Option<String>,
+}
+
+pub async fn ssm_parameter(name: &str) -> Result<String> {
+    let body = json!({ "Name": name, "WithDecryption": true }).to_string();
+    let resp = call("ssm", "AmazonSSM.GetParameter", body).await?;
+    let resp: SsmGetParameter = serde_json::from_str(&resp)?;
+    Ok(resp.parameter.value)
+}
+
+pub async fn secretsmanager_secret(name: &str) -> Result<String> {
+    let body = json!({ "SecretId": name }).to_string();
+    let resp = call("secretsmanager", "secretsmanager.GetSecretValue", body).await?;
+    let resp: SecretsManagerGetSecretValue = serde_json::from_str(&resp)?;
+    resp.secret_string.ok_or(Error::Secret(format!(
+        "{}: secret is binary, not a string",
+        name
+    )))
+}
+
+// Call an action of a service speaking the AWS JSON 1.1 protocol
+async fn call(service: &str, target: &str, body: String) -> Result<String> {
+    let credentials = aws::Credentials::ambient().await?;
+    let region = aws::region().await?;
+    let url = format!("https://{}.{}.amazonaws.com/", service, region);
+
+    let mut headers = vec![
+        http::Header::new(
+            http::HeaderKey::ContentType,
+            "application/x-amz-json-1.1".to_string(),
+        ),
+        http::Header::new(
+            http::HeaderKey::Custom("X-Amz-Target".to_string()),
+            target.to_string(),
+        ),
+    ];
+    let signed = headers
+        .iter()
+        .map(|h| (h.name().to_string(), h.value().to_string()))
+        .collect::<Vec<_>>();
+    let signer = aws::SigV4 {
+        credentials: &credentials,
+        region: &region,
+        service,
+    };
+    headers.extend(signer.sign("POST", &url, &signed, body.as_bytes(), Utc::now())?);
+
+    let cli = http::Client::new()?;
+    let resp = cli.post(&url, Some(headers), body).await?;
+    if resp.status != 200 {
+        return Err(Error::Secret(format!(
+            "{} failed with status {}: {}",
+            target,
+            resp.status,
+            aws::JsonError::from_body(&resp.body)
+        )));
+    }
+    Ok(resp.body)
+}
@@ -0,0 +1,30 @@
+use crate::error::Error;
+use crate::error::Result;
+
+// Read a password from the OS keychain: Secret Service on Linux, Keychain on
+// macOS and Credential Manager on Windows.
+#[cfg(feature = "keyring")]
+pub async fn get(service: &str, account: &str) -> Result<String> {
+    let (service, account) = (service.to_string(), account.to_string());
+
+    tokio::task::spawn_blocking(move || {
+        keyring::Entry::new(&service, &account)
+            .and_then(|entry| entry.get_password())
+            .map_err(|e| {
+                Error::Secret(format!(
+                    "cannot read {}/{} from keyring: {}",
+                    service, account, e
+                ))
+            })
+    })
+    .await
+    .map_err(|e| Error::Secret(format!("keyring task failed: {}", e)))?
+}
+
+#[cfg(not(feature = "keyring"))]
+pub async fn get(service: &str, account: &str) -> Result<String> {
+    Err(Error::Secret(format!(
+        "cannot read {}/{}: built without the keyring feature",
+        service, account
+    )))
+}
@@ -0,0 +1,58 @@
+mod aws;
+mod keyring;
+
+use crate::error::Result;
+
+// Where the value of a credential comes from. Secrets are resolved once at
+// startup so that providers only ever see plain values.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Secret {
+    Plain(String),
+    Keyring { service: String, account: String },
+    AwsSsm(String),
+    AwsSecretsManager(String),
+}
+
+impl Secret {
+    // Parse a config value, `aws_ssm:/path/to/param` and `aws_secretsmanager:name`
+    // are references, anything else is a plain value.
+    pub fn parse(value: &str) -> Self {
+        if let Some(name) = value.strip_prefix("aws_ssm:") {
+            Secret::AwsSsm(name.to_string())
+        } else if let Some(name) = value.strip_prefix("aws_secretsmanager:") {
+            Secret::AwsSecretsManager(name.to_string())
+        } else {
+            Secret::Plain(value.to_string())
+        }
+    }
+
+    pub async fn resolve(&self) -> Result<String> {
+        match self {
+            Secret::Plain(value) => Ok(value.clone()),
+            Secret::Keyring { service, account } => keyring::get(service, account).await,
+            Secret::AwsSsm(name) => aws::ssm_parameter(name).await,
+            Secret::AwsSecretsManager(name) => aws::secretsmanager_secret(name).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_secret_parse() {
+        assert_eq!(
+            Secret::parse("aws_ssm:/dns-syncer/token"),
+            Secret::AwsSsm("/dns-syncer/token".to_string())
+        );
+        assert_eq!(
+            Secret::parse("aws_secretsmanager:cf-token"),
+            Secret::AwsSecretsManager("cf-token".to_string())
+        );
+        assert_eq!(
+            Secret::parse("AABBCCDD"),
+            Secret::Plain("AABBCCDD".to_string())
+        );
+    }
+}
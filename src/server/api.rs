@@ -0,0 +1,365 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::Notify;
+
+use super::Handler;
+use super::Request;
+use super::Response;
+use crate::config::CfgParamList;
+use crate::config::CfgRecord;
+use crate::config::CfgRecordItem;
+use crate::config::CfgRecordProvider;
+use crate::error::Error;
+use crate::error::Result;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::state::ProviderStatus;
+use crate::state::State;
+use crate::state::SyncCycle;
+use crate::types::PublicIp;
+use crate::types::ZoneName;
+
+// Comment of the records added through the API without one
+const DEFAULT_COMMENT: &str = "added through the API";
+
+// A record added through the API, synced along with the records of the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiRecord {
+    // Full name, e.g. home.example.com
+    pub name: String,
+    #[serde(rename = "type")]
+    pub record_type: String,
+    // The public address of the cycle when not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    pub provider: String,
+    pub zone: ZoneName,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl ApiRecord {
+    fn content(&self) -> Result<RecordContent> {
+        let content = RecordContent::parse(&self.record_type, self.content.as_deref())?;
+        match content.record_type() {
+            RecordType::None => Err(Error::ParseError(format!(
+                "unsupported record type {}",
+                self.record_type
+            ))),
+            _ => Ok(content),
+        }
+    }
+
+    // Records of a name, type, provider and zone replace each other
+    fn same_key(&self, other: &ApiRecord) -> bool {
+        self.name == other.name
+            && self.record_type.eq_ignore_ascii_case(&other.record_type)
+            && self.provider == other.provider
+            && self.zone == other.zone
+    }
+
+    pub fn to_item(&self) -> Result<CfgRecordItem> {
+        Ok(CfgRecordItem {
+            record: CfgRecord {
+                name: self.name.clone(),
+                content: self.content()?,
+                comment: Some(
+                    self.comment
+                        .as_deref()
+                        .unwrap_or(DEFAULT_COMMENT)
+                        .to_string(),
+                ),
+                op: RecordOp::default(),
+                ttl: self.ttl.into(),
+            },
+            providers: vec![CfgRecordProvider {
+                name: self.provider.clone(),
+                zones: vec![self.zone.clone()],
+                params: CfgParamList::default(),
+            }],
+            fetchers: vec![],
+            tags: vec![],
+            enabled: true,
+        })
+    }
+}
+
+// What `/v1/status` answers, taken from the state after every cycle
+#[derive(Debug, Clone, Default, Serialize)]
+struct ApiStatus {
+    public_ip: Option<PublicIp>,
+    last_cycle: Option<SyncCycle>,
+    providers: Vec<ProviderStatus>,
+}
+
+// Management API of a running daemon under `/v1`. Every request carries the
+// token as `Authorization: Bearer <token>`.
+//
+// - `GET /v1/records` lists the records added through the API
+// - `POST /v1/records` adds a record, or replaces the one of the same name,
+//   type, provider and zone
+// - `DELETE /v1/records/<name>` removes the records of a name, `?type=` only
+//   the ones of a type
+// - `POST /v1/sync` starts a sync cycle
+// - `GET /v1/status` shows the public address, the last cycle and the status
+//   of every provider
+//
+// Records are synced like the ones of sources: the next cycle creates them
+// and deletes the removed ones when they carry the owner marker.
+pub struct Api {
+    token: String,
+    providers: Vec<String>,
+    records: Mutex<Vec<ApiRecord>>,
+    status: Mutex<ApiStatus>,
+    changed: Arc<Notify>,
+}
+
+impl Api {
+    // Records may only go to `providers`, `changed` is woken whenever the
+    // records change or a sync is asked for
+    pub fn new(token: &str, providers: Vec<String>, changed: Arc<Notify>) -> Self {
+        Self {
+            token: token.to_string(),
+            providers,
+            records: Mutex::new(vec![]),
+            status: Mutex::new(ApiStatus::default()),
+            changed,
+        }
+    }
+
+    pub fn records(&self) -> Vec<ApiRecord> {
+        self.records.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+
+    // Restore the records, e.g. from the state file
+    pub fn set_records(&self, records: Vec<ApiRecord>) {
+        if let Ok(mut r) = self.records.lock() {
+            *r = records;
+        }
+    }
+
+    // Records to sync, the ones not valid anymore are skipped
+    pub fn items(&self) -> Vec<CfgRecordItem> {
+        self.records()
+            .iter()
+            .filter_map(|r| match r.to_item() {
+                Ok(item) => Some(item),
+                Err(e) => {
+                    log::warn!("api record {} skipped: {}", r.name, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    pub fn update_status(&self, state: &State) {
+        if let Ok(mut status) = self.status.lock() {
+            *status = ApiStatus {
+                public_ip: state.public_ip.clone(),
+                last_cycle: state.last_cycle().cloned(),
+                providers: state.provider_status(),
+            };
+        }
+    }
+
+    fn add(&self, record: ApiRecord) -> Result<()> {
+        if !self.providers.contains(&record.provider) {
+            return Err(Error::ParseError(format!(
+                "provider {} is not configured",
+                record.provider
+            )));
+        }
+        if record.name != record.zone && !record.name.ends_with(&format!(".{}", record.zone)) {
+            return Err(Error::ParseError(format!(
+                "{} is not in zone {}",
+                record.name, record.zone
+            )));
+        }
+        record.content()?;
+
+        let mut records = self
+            .records
+            .lock()
+            .map_err(|_| Error::Provider("api records are poisoned".to_string()))?;
+        records.retain(|r| !r.same_key(&record));
+        log::info!("api: record {} {} added", record.name, record.record_type);
+        records.push(record);
+        Ok(())
+    }
+
+    // Number of records removed
+    fn remove(&self, name: &str, record_type: Option<&str>) -> usize {
+        let Ok(mut records) = self.records.lock() else {
+            return 0;
+        };
+        let len = records.len();
+        records.retain(|r| {
+            r.name != name || record_type.is_some_and(|ty| !r.record_type.eq_ignore_ascii_case(ty))
+        });
+        let removed = len - records.len();
+        if removed > 0 {
+            log::info!("api: {} records of {} removed", removed, name);
+        }
+        removed
+    }
+
+    fn authorized(&self, req: &Request) -> bool {
+        req.header("authorization")
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), self.token.as_bytes()))
+    }
+}
+
+// Compare without stopping at the first difference, so the time taken does
+// not tell how much of a guessed token is right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn query_param<'a>(req: &'a Request, name: &str) -> Option<&'a str> {
+    req.query
+        .as_deref()?
+        .split('&')
+        .filter_map(|p| p.split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v)
+}
+
+fn error(status: u16, message: impl std::fmt::Display) -> Response {
+    Response::json(status, &json!({ "error": message.to_string() }))
+}
+
+#[async_trait]
+impl Handler for Api {
+    async fn handle(&self, req: &Request) -> Option<Response> {
+        let path = req.path.strip_prefix("/v1/")?;
+        if !self.authorized(req) {
+            return Some(error(401, "missing or wrong bearer token"));
+        }
+
+        let resp = match (req.method.as_str(), path) {
+            ("GET", "records") => Response::json(200, &json!(self.records())),
+            ("POST", "records") => match serde_json::from_slice::<ApiRecord>(&req.body) {
+                Ok(record) => match self.add(record.clone()) {
+                    Ok(()) => {
+                        self.changed.notify_one();
+                        Response::json(201, &json!(record))
+                    }
+                    Err(e) => error(400, e),
+                },
+                Err(e) => error(400, e),
+            },
+            ("DELETE", path) if path.starts_with("records/") => {
+                let name = &path["records/".len()..];
+                match self.remove(name, query_param(req, "type")) {
+                    0 => error(404, format!("no record named {}", name)),
+                    removed => {
+                        self.changed.notify_one();
+                        Response::json(200, &json!({ "removed": removed }))
+                    }
+                }
+            }
+            ("POST", "sync") => {
+                log::info!("api: sync requested");
+                self.changed.notify_one();
+                Response::json(202, &json!({ "status": "scheduled" }))
+            }
+            ("GET", "status") => match self.status.lock() {
+                Ok(status) => Response::json(200, &json!(*status)),
+                Err(_) => error(500, "status is poisoned"),
+            },
+            _ => error(404, format!("no endpoint {} {}", req.method, req.path)),
+        };
+        Some(resp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        let (path, query) = match path.split_once('?') {
+            Some((path, query)) => (path, Some(query.to_string())),
+            None => (path, None),
+        };
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query,
+            headers: vec![("Authorization".to_string(), "Bearer s3cret".to_string())],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api() {
+        let changed = Arc::new(Notify::new());
+        let api = Api::new("s3cret", vec!["cloudflare-1".to_string()], changed.clone());
+
+        let mut req = request("GET", "/v1/records", "");
+        req.headers.clear();
+        assert_eq!(api.handle(&req).await.unwrap().status, 401);
+        for token in ["Bearer s3creT", "Bearer s3cre", "Bearer "] {
+            req.headers = vec![("Authorization".to_string(), token.to_string())];
+            assert_eq!(api.handle(&req).await.unwrap().status, 401);
+        }
+        assert!(api.handle(&request("GET", "/healthz", "")).await.is_none());
+
+        let body = r#"{"name": "home.example.com", "type": "A", "provider": "cloudflare-1",
+                       "zone": "example.com", "ttl": 300}"#;
+        let resp = api.handle(&request("POST", "/v1/records", body)).await;
+        assert_eq!(resp.unwrap().status, 201);
+        let body = r#"{"name": "nas.example.com", "type": "AAAA", "content": "2001:db8::1",
+                       "provider": "cloudflare-1", "zone": "example.com"}"#;
+        api.handle(&request("POST", "/v1/records", body)).await;
+        // Tells the daemon to sync
+        changed.notified().await;
+
+        let items = api.items();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0].record.content,
+            RecordContent::Unassigned(RecordType::A)
+        );
+        assert_eq!(items[0].record.comment.as_deref(), Some(DEFAULT_COMMENT));
+
+        for body in [
+            r#"{"name": "a.example.com", "type": "A", "provider": "route53", "zone": "example.com"}"#,
+            r#"{"name": "a.example.org", "type": "A", "provider": "cloudflare-1", "zone": "example.com"}"#,
+            r#"{"name": "a.example.com", "type": "TXT", "provider": "cloudflare-1", "zone": "example.com"}"#,
+            r#"{"name": "a.example.com", "type": "A", "content": "::1", "provider": "cloudflare-1", "zone": "example.com"}"#,
+        ] {
+            let resp = api.handle(&request("POST", "/v1/records", body)).await;
+            assert_eq!(resp.unwrap().status, 400, "{}", body);
+        }
+
+        let resp = api
+            .handle(&request("DELETE", "/v1/records/nas.example.com?type=A", ""))
+            .await;
+        assert_eq!(resp.unwrap().status, 404);
+        let resp = api
+            .handle(&request("DELETE", "/v1/records/nas.example.com", ""))
+            .await;
+        assert_eq!(resp.unwrap().status, 200);
+        assert_eq!(api.records().len(), 1);
+
+        let resp = api.handle(&request("POST", "/v1/sync", "")).await;
+        assert_eq!(resp.unwrap().status, 202);
+        let resp = api.handle(&request("GET", "/v1/status", "")).await.unwrap();
+        assert_eq!(resp.status, 200);
+        let status: serde_json::Value = serde_json::from_slice(&resp.body).unwrap();
+        assert!(status["last_cycle"].is_null());
+    }
+}
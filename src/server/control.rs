@@ -0,0 +1,259 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use serde::Serialize;
+use serde_json::json;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::sync::Notify;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::state::ProviderStatus;
+use crate::state::State;
+use crate::state::SyncCycle;
+use crate::types::PublicIp;
+
+// Commands of the control socket, one per line
+pub const CONTROL_COMMANDS: [&str; 5] = ["sync-now", "status", "reload", "pause", "resume"];
+
+// What `status` answers, taken from the state after every cycle
+#[derive(Debug, Clone, Default, Serialize)]
+struct ControlStatus {
+    public_ip: Option<PublicIp>,
+    last_cycle: Option<SyncCycle>,
+    providers: Vec<ProviderStatus>,
+}
+
+// Control of a running daemon over a local socket, a Unix domain socket or a
+// named pipe on Windows. A client writes a command per line and gets a JSON
+// object per line back, `ok` telling whether the command was taken:
+//
+// - `sync-now` starts a sync cycle
+// - `status` shows the public address, the last cycle and every provider
+// - `reload` reads the config again before the next cycle
+// - `pause` stops syncing until `resume`, which syncs right away
+//
+// The daemon loop asks `is_paused` and `take_reload` before every cycle and
+// waits on `woken` between them.
+#[derive(Debug, Default)]
+pub struct Control {
+    paused: AtomicBool,
+    reload: AtomicBool,
+    wake: Notify,
+    status: Mutex<ControlStatus>,
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    // Whether a reload was asked for since the last call
+    pub fn take_reload(&self) -> bool {
+        self.reload.swap(false, Ordering::Relaxed)
+    }
+
+    // Return once a command needs the daemon loop to act
+    pub async fn woken(&self) {
+        self.wake.notified().await
+    }
+
+    pub fn update_status(&self, state: &State) {
+        if let Ok(mut status) = self.status.lock() {
+            *status = ControlStatus {
+                public_ip: state.public_ip.clone(),
+                last_cycle: state.last_cycle().cloned(),
+                providers: state.provider_status(),
+            };
+        }
+    }
+
+    // Answer of a command line
+    pub fn handle(&self, line: &str) -> serde_json::Value {
+        let command = line.trim();
+        log::info!("control: {}", command);
+        match command {
+            "sync-now" if self.is_paused() => {
+                json!({ "ok": false, "error": "syncing is paused, resume first" })
+            }
+            "sync-now" => {
+                self.wake.notify_one();
+                json!({ "ok": true, "status": "scheduled" })
+            }
+            "status" => match self.status.lock() {
+                Ok(status) => json!({ "ok": true, "paused": self.is_paused(), "state": *status }),
+                Err(_) => json!({ "ok": false, "error": "status is poisoned" }),
+            },
+            "reload" => {
+                self.reload.store(true, Ordering::Relaxed);
+                self.wake.notify_one();
+                json!({ "ok": true, "status": "scheduled" })
+            }
+            "pause" => {
+                self.paused.store(true, Ordering::Relaxed);
+                json!({ "ok": true, "paused": true })
+            }
+            "resume" => {
+                if self.paused.swap(false, Ordering::Relaxed) {
+                    self.wake.notify_one();
+                }
+                json!({ "ok": true, "paused": false })
+            }
+            _ => json!({
+                "ok": false,
+                "error": format!(
+                    "unknown command {}, expected one of {}",
+                    command,
+                    CONTROL_COMMANDS.join(", ")
+                ),
+            }),
+        }
+    }
+
+    // Answer the commands of a client until it disconnects
+    async fn session<S>(&self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut stream = BufReader::new(stream);
+        let mut line = String::new();
+        while stream.read_line(&mut line).await? > 0 {
+            if !line.trim().is_empty() {
+                let mut reply = self.handle(&line).to_string();
+                reply.push('\n');
+                stream.get_mut().write_all(reply.as_bytes()).await?;
+            }
+            line.clear();
+        }
+        Ok(())
+    }
+
+    // Accept clients on the socket at `path` until the task is dropped. A
+    // socket left behind by an earlier run is replaced, the new one is only
+    // accessible to the user of the daemon.
+    #[cfg(unix)]
+    pub async fn serve(self: std::sync::Arc<Self>, path: PathBuf) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        use tokio::net::UnixListener;
+
+        if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let control = std::sync::Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = control.session(stream).await {
+                    log::debug!("control client failed: {}", e);
+                }
+            });
+        }
+    }
+
+    // Accept clients on the named pipe `path`, e.g. `\\.\pipe\dns-syncer`
+    #[cfg(windows)]
+    pub async fn serve(self: std::sync::Arc<Self>, path: PathBuf) -> Result<()> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let mut server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(&path)?;
+        loop {
+            server.connect().await?;
+            // The next client connects to a new instance
+            let client = std::mem::replace(&mut server, ServerOptions::new().create(&path)?);
+            let control = std::sync::Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = control.session(client).await {
+                    log::debug!("control client failed: {}", e);
+                }
+            });
+        }
+    }
+}
+
+// Send `command` to the daemon listening at `path` and return its answer
+pub async fn send_control_command(path: &Path, command: &str) -> Result<serde_json::Value> {
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(path).await?;
+    #[cfg(windows)]
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new().open(path)?;
+
+    let mut stream = BufReader::new(stream);
+    stream
+        .get_mut()
+        .write_all(format!("{}\n", command).as_bytes())
+        .await?;
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    serde_json::from_str(&line).map_err(|e| {
+        Error::ParseError(format!(
+            "invalid answer of the control socket {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_control() {
+        let control = Arc::new(Control::new());
+        let path = std::env::temp_dir().join(format!("dns-syncer-{}.sock", std::process::id()));
+        let server = tokio::spawn(Arc::clone(&control).serve(path.clone()));
+        // Wait for the socket
+        for _ in 0..50 {
+            if path.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let reply = send_control_command(&path, "sync-now").await.unwrap();
+        assert_eq!(reply["ok"], true);
+        tokio::time::timeout(Duration::from_secs(5), control.woken())
+            .await
+            .unwrap();
+
+        send_control_command(&path, "pause").await.unwrap();
+        assert!(control.is_paused());
+        let reply = send_control_command(&path, "sync-now").await.unwrap();
+        assert_eq!(reply["ok"], false);
+        let reply = send_control_command(&path, "status").await.unwrap();
+        assert_eq!(reply["paused"], true);
+        assert!(reply["state"]["last_cycle"].is_null());
+        send_control_command(&path, "resume").await.unwrap();
+        assert!(!control.is_paused());
+
+        assert!(!control.take_reload());
+        send_control_command(&path, "reload").await.unwrap();
+        assert!(control.take_reload());
+        assert!(!control.take_reload());
+
+        let reply = send_control_command(&path, "restart").await.unwrap();
+        assert_eq!(reply["ok"], false);
+
+        server.abort();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
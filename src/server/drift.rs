@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+
+use super::Handler;
+use super::Request;
+use super::Response;
+
+#[derive(Debug, Default)]
+struct DriftState {
+    checks: u64,
+    last_check: Option<DateTime<Utc>>,
+    // Drifted records per provider, zero for the ones without drift
+    drifted: BTreeMap<String, usize>,
+}
+
+// Outcome of the drift checks as Prometheus metrics at `/metrics`
+#[derive(Debug, Default)]
+pub struct DriftMetrics {
+    state: Mutex<DriftState>,
+}
+
+impl DriftMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_check(&self, drifted: BTreeMap<String, usize>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.checks += 1;
+            state.last_check = Some(Utc::now());
+            state.drifted = drifted;
+        }
+    }
+
+    // Text exposition format
+    pub fn render(&self) -> String {
+        let Ok(state) = self.state.lock() else {
+            return String::new();
+        };
+        let mut ret = String::new();
+        let _ = writeln!(
+            ret,
+            "# HELP dns_syncer_drift_checks_total Drift checks run.\n\
+             # TYPE dns_syncer_drift_checks_total counter\n\
+             dns_syncer_drift_checks_total {}",
+            state.checks
+        );
+        if let Some(at) = state.last_check {
+            let _ = writeln!(
+                ret,
+                "# HELP dns_syncer_drift_last_check_timestamp_seconds End of the last drift check.\n\
+                 # TYPE dns_syncer_drift_last_check_timestamp_seconds gauge\n\
+                 dns_syncer_drift_last_check_timestamp_seconds {}",
+                at.timestamp()
+            );
+        }
+        let _ = writeln!(
+            ret,
+            "# HELP dns_syncer_drifted_records Records a provider serves differently from the config.\n\
+             # TYPE dns_syncer_drifted_records gauge"
+        );
+        for (provider, count) in state.drifted.iter() {
+            let _ = writeln!(
+                ret,
+                "dns_syncer_drifted_records{{provider=\"{}\"}} {}",
+                provider.replace('\\', "\\\\").replace('"', "\\\""),
+                count
+            );
+        }
+        ret
+    }
+}
+
+#[async_trait]
+impl Handler for DriftMetrics {
+    async fn handle(&self, req: &Request) -> Option<Response> {
+        match (req.method.as_str(), req.path.as_str()) {
+            ("GET", "/metrics") => Some(Response {
+                status: 200,
+                content_type: "text/plain; version=0.0.4".to_string(),
+                body: self.render().into_bytes(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_drift_metrics() {
+        let metrics = DriftMetrics::new();
+        assert!(
+            metrics
+                .render()
+                .contains("dns_syncer_drift_checks_total 0\n")
+        );
+        assert!(!metrics.render().contains("last_check"));
+
+        metrics.record_check(BTreeMap::from([
+            ("cloudflare-1".to_string(), 2),
+            ("route53".to_string(), 0),
+        ]));
+        let text = metrics.render();
+        assert!(text.contains("dns_syncer_drift_checks_total 1\n"));
+        assert!(text.contains("dns_syncer_drifted_records{provider=\"cloudflare-1\"} 2\n"));
+        assert!(text.contains("dns_syncer_drifted_records{provider=\"route53\"} 0\n"));
+        assert!(text.contains("dns_syncer_drift_last_check_timestamp_seconds "));
+    }
+}
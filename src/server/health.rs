@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::Handler;
+use super::Request;
+use super::Response;
+
+// Consecutive failed syncs after which a provider is considered broken
+pub const PERSISTENT_FAILURES: u32 = 3;
+
+#[derive(Debug, Default)]
+struct HealthState {
+    last_sync: Option<Instant>,
+    failures: HashMap<String, u32>,
+}
+
+// Liveness and readiness of the sync loop. The daemon is ready when the last
+// cycle finished within two check intervals and no provider keeps failing.
+#[derive(Debug)]
+pub struct Health {
+    interval: Duration,
+    state: Mutex<HealthState>,
+}
+
+impl Health {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            state: Mutex::new(HealthState::default()),
+        }
+    }
+
+    pub fn record_success(&self, provider: &str) {
+        if let Ok(mut state) = self.state.lock() {
+            state.failures.insert(provider.to_string(), 0);
+        }
+    }
+
+    // Returns the number of consecutive failures of the provider
+    pub fn record_failure(&self, provider: &str) -> u32 {
+        let Ok(mut state) = self.state.lock() else {
+            return 0;
+        };
+        let count = state.failures.entry(provider.to_string()).or_default();
+        *count += 1;
+        *count
+    }
+
+    pub fn cycle_completed(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.last_sync = Some(Instant::now());
+        }
+    }
+
+    // Reasons why the daemon is not ready, empty when it is
+    pub fn unready_reasons(&self) -> Vec<String> {
+        let Ok(state) = self.state.lock() else {
+            return vec!["health state is poisoned".to_string()];
+        };
+
+        let mut reasons = vec![];
+        match state.last_sync {
+            None => reasons.push("no sync cycle completed yet".to_string()),
+            Some(last) if last.elapsed() > self.interval * 2 => reasons.push(format!(
+                "last sync cycle completed {}s ago",
+                last.elapsed().as_secs()
+            )),
+            Some(_) => {}
+        }
+
+        let mut failing = state
+            .failures
+            .iter()
+            .filter(|(_, count)| **count >= PERSISTENT_FAILURES)
+            .map(|(name, count)| format!("provider {} failed {} times in a row", name, count))
+            .collect::<Vec<_>>();
+        failing.sort();
+        reasons.extend(failing);
+
+        reasons
+    }
+}
+
+#[async_trait]
+impl Handler for Health {
+    async fn handle(&self, req: &Request) -> Option<Response> {
+        match (req.method.as_str(), req.path.as_str()) {
+            ("GET", "/healthz") => Some(Response::json(200, &json!({ "status": "ok" }))),
+            ("GET", "/readyz") => {
+                let reasons = self.unready_reasons();
+                if reasons.is_empty() {
+                    Some(Response::json(200, &json!({ "status": "ready" })))
+                } else {
+                    Some(Response::json(
+                        503,
+                        &json!({ "status": "unready", "reasons": reasons }),
+                    ))
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn get(path: &str) -> Request {
+        Request {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            query: None,
+            headers: vec![],
+            body: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_readiness() {
+        let health = Health::new(Duration::from_secs(60));
+        assert_eq!(health.handle(&get("/healthz")).await.unwrap().status, 200);
+        assert_eq!(health.handle(&get("/readyz")).await.unwrap().status, 503);
+        assert!(health.handle(&get("/other")).await.is_none());
+
+        health.record_success("cloudflare-1");
+        health.cycle_completed();
+        assert_eq!(health.handle(&get("/readyz")).await.unwrap().status, 200);
+
+        for _ in 0..PERSISTENT_FAILURES {
+            health.record_failure("cloudflare-1");
+        }
+        let reasons = health.unready_reasons();
+        assert_eq!(reasons.len(), 1);
+        assert!(reasons[0].contains("cloudflare-1"));
+
+        health.record_success("cloudflare-1");
+        assert!(health.unready_reasons().is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use http_body_util::BodyExt;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+
+use super::Handler;
+use super::Request;
+use super::Response;
+use crate::error::Result;
+
+pub struct Server {
+    handlers: Vec<Arc<dyn Handler>>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self { handlers: vec![] }
+    }
+
+    pub fn add_handler(&mut self, handler: Arc<dyn Handler>) {
+        self.handlers.push(handler);
+    }
+
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        log::info!("http server listening on {}", addr);
+
+        let handlers = Arc::new(self.handlers);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let handlers = handlers.clone();
+
+            tokio::spawn(async move {
+                let service = service_fn(move |req| {
+                    let handlers = handlers.clone();
+                    async move { Ok::<_, Infallible>(dispatch(&handlers, req).await) }
+                });
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    log::debug!("http connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+async fn dispatch(
+    handlers: &[Arc<dyn Handler>],
+    req: hyper::Request<Incoming>,
+) -> hyper::Response<Full<Bytes>> {
+    let (parts, body) = req.into_parts();
+    let body = match body.collect().await {
+        Ok(body) => body.to_bytes().to_vec(),
+        Err(e) => return into_hyper(Response::text(400, format!("bad request: {}", e))),
+    };
+
+    let req = Request {
+        method: parts.method.to_string(),
+        path: parts.uri.path().to_string(),
+        query: parts.uri.query().map(String::from),
+        headers: parts
+            .headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or_default().to_string()))
+            .collect(),
+        body,
+    };
+
+    for handler in handlers {
+        if let Some(resp) = handler.handle(&req).await {
+            return into_hyper(resp);
+        }
+    }
+    into_hyper(Response::text(404, "not found"))
+}
+
+fn into_hyper(resp: Response) -> hyper::Response<Full<Bytes>> {
+    hyper::Response::builder()
+        .status(resp.status)
+        .header("Content-Type", resp.content_type)
+        .body(Full::new(Bytes::from(resp.body)))
+        .unwrap_or_default()
+}
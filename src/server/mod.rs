@@ -0,0 +1,69 @@
+mod api;
+mod drift;
+mod health;
+mod webhook;
+pub use api::*;
+pub use drift::*;
+pub use health::*;
+pub use webhook::*;
+
+#[cfg(feature = "daemon")]
+mod control;
+#[cfg(feature = "daemon")]
+pub use control::*;
+#[cfg(feature = "daemon")]
+mod listener;
+#[cfg(feature = "daemon")]
+pub use listener::*;
+
+use async_trait::async_trait;
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8".to_string(),
+            body: body.into().into_bytes(),
+        }
+    }
+
+    pub fn json(status: u16, body: &serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json".to_string(),
+            body: body.to_string().into_bytes(),
+        }
+    }
+}
+
+// Endpoints served by the HTTP listener. Handlers are asked in turn and the
+// first one returning a response wins, `None` means the path is not theirs.
+#[async_trait]
+pub trait Handler: Send + Sync {
+    async fn handle(&self, req: &Request) -> Option<Response>;
+}
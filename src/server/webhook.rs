@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::Handler;
+use super::Request;
+use super::Response;
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::Provider;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::record::zone_of;
+use crate::types::ZoneName;
+
+// Media type of every request and answer of the protocol
+pub const WEBHOOK_MEDIA_TYPE: &str = "application/external.dns.webhook+json;version=1";
+
+// A name with its targets of one type, as external-dns sees records
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Endpoint {
+    pub dns_name: String,
+    #[serde(default)]
+    pub targets: Vec<String>,
+    pub record_type: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub set_identifier: String,
+    // 0 when not set
+    #[serde(rename = "recordTTL", default)]
+    pub record_ttl: u32,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provider_specific: Vec<serde_json::Value>,
+}
+
+impl Endpoint {
+    fn record_type(&self) -> Option<RecordType> {
+        RecordType::parse(&self.record_type).filter(|ty| *ty != RecordType::None)
+    }
+
+    // One record per target
+    fn records(&self) -> Result<Vec<ProviderRecord>> {
+        let Some(record_type) = self.record_type() else {
+            return Ok(vec![]);
+        };
+        self.targets
+            .iter()
+            .map(|target| {
+                Ok(ProviderRecord {
+                    name: self.dns_name.trim_end_matches('.').to_string(),
+                    content: RecordContent::parse(record_type.as_str(), Some(target))?,
+                    comment: None,
+                    op: RecordOp::default(),
+                    ttl: match self.record_ttl {
+                        0 => TTL::Auto,
+                        ttl => TTL::Value(ttl),
+                    },
+                    params: vec![],
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Changes {
+    #[serde(default)]
+    create: Vec<Endpoint>,
+    #[serde(default)]
+    update_old: Vec<Endpoint>,
+    #[serde(default)]
+    update_new: Vec<Endpoint>,
+    #[serde(default)]
+    delete: Vec<Endpoint>,
+}
+
+// Serves the external-dns webhook provider protocol on top of a provider, so
+// external-dns manages records through any provider of dns-syncer. Records
+// are created with the owner marker and only owned records are deleted, so
+// external-dns runs with `--registry=noop`. Types the provider cannot sync,
+// like the TXT records of other registries, are left out.
+pub struct ExternalDnsWebhook {
+    provider: Arc<dyn Provider>,
+    zones: Vec<ZoneName>,
+}
+
+impl ExternalDnsWebhook {
+    // `zones` are the domain filter handed to external-dns
+    pub fn new(provider: Arc<dyn Provider>, zones: Vec<ZoneName>) -> Self {
+        Self { provider, zones }
+    }
+
+    fn zone_of(&self, endpoint: &Endpoint) -> Result<&ZoneName> {
+        let name = endpoint.dns_name.trim_end_matches('.');
+        zone_of(name, &self.zones).ok_or(Error::Provider(format!(
+            "no zone holds {}",
+            endpoint.dns_name
+        )))
+    }
+
+    pub async fn records(&self) -> Result<Vec<Endpoint>> {
+        let mut ret = vec![];
+        for zone in self.zones.iter() {
+            // Records of a name and type are one endpoint
+            let mut endpoints: BTreeMap<(String, String), Endpoint> = BTreeMap::new();
+            for record in self.provider.list_zone_records(zone).await? {
+                let record_type = record.content.record_type().as_str().to_string();
+                let endpoint = endpoints
+                    .entry((record.name.clone(), record_type.clone()))
+                    .or_insert_with(|| Endpoint {
+                        dns_name: record.name.clone(),
+                        targets: vec![],
+                        record_type,
+                        set_identifier: String::new(),
+                        record_ttl: match record.ttl {
+                            TTL::Value(ttl) => ttl,
+                            TTL::Auto => 0,
+                        },
+                        labels: HashMap::new(),
+                        provider_specific: vec![],
+                    });
+                endpoint.targets.push(record.content.to_string());
+            }
+            ret.extend(endpoints.into_values());
+        }
+        Ok(ret)
+    }
+
+    // Deletes first, then updates as a delete of the old records and a
+    // create of the new ones, then creates. Every change is tried, all
+    // failures are returned.
+    async fn apply(&self, changes: Changes) -> Result<()> {
+        let mut errors = vec![];
+        let deletes = changes.delete.iter().chain(changes.update_old.iter());
+        for endpoint in deletes {
+            if let Err(e) = self.delete(endpoint).await {
+                errors.push(e.context(&endpoint.dns_name));
+            }
+        }
+        let creates = changes.update_new.iter().chain(changes.create.iter());
+        for endpoint in creates {
+            if let Err(e) = self.create(endpoint).await {
+                errors.push(e.context(&endpoint.dns_name));
+            }
+        }
+        Error::from_errors(errors)
+    }
+
+    async fn delete(&self, endpoint: &Endpoint) -> Result<()> {
+        let Some(record_type) = endpoint.record_type() else {
+            return Ok(());
+        };
+        let zone = self.zone_of(endpoint)?;
+        let name = endpoint.dns_name.trim_end_matches('.');
+        let deleted = self
+            .provider
+            .delete_record(zone, name, record_type, false)
+            .await?;
+        log::info!("webhook: deleted {} records of {}", deleted.len(), name);
+        Ok(())
+    }
+
+    async fn create(&self, endpoint: &Endpoint) -> Result<()> {
+        let zone = self.zone_of(endpoint)?;
+        for record in endpoint.records()? {
+            self.provider.create_record(zone, &record).await?;
+            log::info!("webhook: created {} {}", record.name, record.content);
+        }
+        Ok(())
+    }
+}
+
+fn webhook_json<T: Serialize>(status: u16, body: &T) -> Response {
+    match serde_json::to_vec(body) {
+        Ok(body) => Response {
+            status,
+            content_type: WEBHOOK_MEDIA_TYPE.to_string(),
+            body,
+        },
+        Err(e) => Response::text(500, e.to_string()),
+    }
+}
+
+#[async_trait]
+impl Handler for ExternalDnsWebhook {
+    async fn handle(&self, req: &Request) -> Option<Response> {
+        let resp = match (req.method.as_str(), req.path.as_str()) {
+            // Negotiation, the answer is the domain filter
+            ("GET", "/") => webhook_json(200, &serde_json::json!({ "include": self.zones })),
+            ("GET", "/healthz") => Response::text(200, "ok"),
+            ("GET", "/records") => match self.records().await {
+                Ok(endpoints) => webhook_json(200, &endpoints),
+                Err(e) => Response::text(500, e.to_string()),
+            },
+            ("POST", "/records") => match serde_json::from_slice::<Changes>(&req.body) {
+                Ok(changes) => match self.apply(changes).await {
+                    Ok(()) => Response {
+                        status: 204,
+                        content_type: WEBHOOK_MEDIA_TYPE.to_string(),
+                        body: vec![],
+                    },
+                    Err(e) => Response::text(500, e.to_string()),
+                },
+                Err(e) => Response::text(400, e.to_string()),
+            },
+            // Endpoints of types the provider cannot sync are dropped, so
+            // external-dns does not plan them
+            ("POST", "/adjustendpoints") => {
+                match serde_json::from_slice::<Vec<Endpoint>>(&req.body) {
+                    Ok(mut endpoints) => {
+                        endpoints.retain(|e| e.record_type().is_some());
+                        webhook_json(200, &endpoints)
+                    }
+                    Err(e) => Response::text(400, e.to_string()),
+                }
+            }
+            _ => return None,
+        };
+        Some(resp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::provider::MockProvider;
+    use crate::record::OWNER_MARKER;
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: None,
+            headers: vec![],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn record(name: &str, ip: Ipv4Addr, comment: Option<&str>) -> ProviderRecord {
+        ProviderRecord {
+            name: name.to_string(),
+            content: RecordContent::A(ip),
+            comment: comment.map(str::to_string),
+            op: RecordOp::default(),
+            ttl: TTL::Auto,
+            params: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_external_dns_webhook() {
+        let www = record("www.example.org", Ipv4Addr::new(192, 0, 2, 1), None);
+        let zone = "example.org".to_string();
+        let provider = MockProvider::with_zones(HashMap::from([(zone.clone(), vec![www])]));
+        let webhook = ExternalDnsWebhook::new(Arc::new(provider.clone()), vec![zone.clone()]);
+
+        let resp = webhook.handle(&request("GET", "/", "")).await.unwrap();
+        assert_eq!(resp.content_type, WEBHOOK_MEDIA_TYPE);
+        assert_eq!(resp.body, br#"{"include":["example.org"]}"#);
+
+        let changes = r#"{
+            "Create": [
+                {"dnsName": "app.example.org", "targets": ["192.0.2.10", "192.0.2.11"],
+                 "recordType": "A", "recordTTL": 300},
+                {"dnsName": "a-app.example.org", "targets": ["\"heritage=external-dns\""],
+                 "recordType": "TXT"}
+            ],
+            "Delete": [
+                {"dnsName": "www.example.org", "targets": ["192.0.2.1"], "recordType": "A"}
+            ]
+        }"#;
+        let resp = webhook
+            .handle(&request("POST", "/records", changes))
+            .await
+            .unwrap();
+        assert_eq!(resp.status, 204);
+
+        // The record not written by dns-syncer is kept
+        let resp = webhook
+            .handle(&request("GET", "/records", ""))
+            .await
+            .unwrap();
+        let endpoints: Vec<Endpoint> = serde_json::from_slice(&resp.body).unwrap();
+        let endpoints = endpoints
+            .iter()
+            .map(|e| (e.dns_name.as_str(), e.targets.join(","), e.record_ttl))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            endpoints,
+            vec![
+                ("app.example.org", "192.0.2.10,192.0.2.11".to_string(), 300),
+                ("www.example.org", "192.0.2.1".to_string(), 0),
+            ]
+        );
+        assert!(
+            provider.records()["example.org"]
+                .iter()
+                .filter(|r| r.name == "app.example.org")
+                .all(|r| r.comment.as_deref().unwrap().contains(OWNER_MARKER))
+        );
+
+        // An update replaces the owned records
+        let changes = r#"{
+            "UpdateOld": [{"dnsName": "app.example.org", "targets": ["192.0.2.10", "192.0.2.11"],
+                           "recordType": "A"}],
+            "UpdateNew": [{"dnsName": "app.example.org", "targets": ["192.0.2.12"],
+                           "recordType": "A"}]
+        }"#;
+        webhook
+            .handle(&request("POST", "/records", changes))
+            .await
+            .unwrap();
+        let records = webhook.records().await.unwrap();
+        assert_eq!(records[0].targets, vec!["192.0.2.12"]);
+
+        let adjusted = r#"[
+            {"dnsName": "app.example.org", "targets": ["192.0.2.12"], "recordType": "A"},
+            {"dnsName": "app.example.org", "targets": ["v=spf1 -all"], "recordType": "TXT"}
+        ]"#;
+        let resp = webhook
+            .handle(&request("POST", "/adjustendpoints", adjusted))
+            .await
+            .unwrap();
+        let endpoints: Vec<Endpoint> = serde_json::from_slice(&resp.body).unwrap();
+        assert_eq!(endpoints.len(), 1);
+
+        assert!(
+            webhook
+                .handle(&request("GET", "/metrics", ""))
+                .await
+                .is_none()
+        );
+    }
+}
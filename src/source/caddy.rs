@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::RecordSource;
+use super::SourcedRecord;
+use super::parse_header;
+use super::parse_types;
+use crate::error::Error;
+use crate::error::Result;
+use crate::record::RecordType;
+use crate::types::Param;
+use crate::wrapper::http::Client;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HttpTransport;
+
+const DEFAULT_URL: &str = "http://localhost:2019";
+
+// Records of the public address for the site hostnames of Caddy, the host
+// matchers of the routes of its HTTP servers read from the admin API
+pub struct Caddy {
+    cli: Client,
+    url: String,
+    headers: Vec<Header>,
+    servers: Vec<String>,
+    types: Vec<RecordType>,
+}
+
+impl Caddy {
+    // Params: `url` of the admin API, `header` sent with every request as
+    // `Name: value`, `servers` (comma separated, all by default) and the
+    // record `types` (A by default)
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut url = DEFAULT_URL.to_string();
+        let mut headers = vec![];
+        let mut servers = vec![];
+        let mut types = vec![RecordType::A];
+
+        for param in args {
+            match param.name.as_str() {
+                "url" => url = param.value.trim_end_matches('/').to_string(),
+                "header" => headers.push(parse_header("caddy", &param.value)?),
+                "servers" => {
+                    servers = param
+                        .value
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .collect()
+                }
+                "types" => types = parse_types("caddy", &param.value)?,
+                name => {
+                    return Err(Error::ParseError(format!("caddy: unknown param {}", name)));
+                }
+            }
+        }
+
+        Ok(Self {
+            cli: Client::new()?,
+            url,
+            headers,
+            servers,
+            types,
+        })
+    }
+
+    // Source reading the API at `url` through `transport`, for tests
+    pub fn with_transport(url: &str, transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            cli: Client::with_transport(transport),
+            url: url.to_string(),
+            headers: vec![],
+            servers: vec![],
+            types: vec![RecordType::A],
+        }
+    }
+
+    fn hosts(&self, servers: &serde_json::Map<String, Value>) -> Vec<String> {
+        let mut ret = vec![];
+        for (name, server) in servers.iter() {
+            if self.servers.is_empty() || self.servers.contains(name) {
+                route_hosts(&server["routes"], &mut ret);
+            }
+        }
+        ret
+    }
+}
+
+// Hosts matched by `routes` and the routes of their subroute handlers.
+// Placeholders are left out, they are only known per request.
+fn route_hosts(routes: &Value, hosts: &mut Vec<String>) {
+    let Some(routes) = routes.as_array() else {
+        return;
+    };
+    for route in routes {
+        let matchers = route["match"].as_array().into_iter().flatten();
+        for host in matchers.flat_map(|m| m["host"].as_array().into_iter().flatten()) {
+            if let Some(host) = host.as_str().filter(|h| !h.contains('{')) {
+                hosts.push(host.to_string());
+            }
+        }
+        for handler in route["handle"].as_array().into_iter().flatten() {
+            if handler["handler"] == "subroute" {
+                route_hosts(&handler["routes"], hosts);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RecordSource for Caddy {
+    async fn records(&self) -> Result<Vec<SourcedRecord>> {
+        let url = format!("{}/config/apps/http/servers", self.url);
+        let body = self
+            .cli
+            .get(&url, Some(self.headers.clone()))
+            .await?
+            .into_body()
+            .map_err(|e| e.context("caddy: reading config"))?;
+        // Caddy without HTTP servers answers null
+        let servers = match serde_json::from_str::<Value>(&body)? {
+            Value::Object(servers) => servers,
+            _ => serde_json::Map::new(),
+        };
+
+        let mut ret = vec![];
+        for host in self.hosts(&servers) {
+            for ty in self.types.iter() {
+                ret.push(SourcedRecord::public(&host, ty.clone()));
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wrapper::http::MemoryTransport;
+    use crate::wrapper::http::Method;
+
+    #[tokio::test]
+    async fn test_caddy_records() {
+        let transport = MemoryTransport::new();
+        transport.route(
+            Method::Get,
+            "http://localhost:2019/config/apps/http/servers",
+            200,
+            r#"{
+                "srv0": {"listen": [":443"], "routes": [
+                    {"match": [{"host": ["app.example.org", "www.example.org"]}],
+                     "handle": [{"handler": "subroute", "routes": [
+                        {"match": [{"host": ["api.example.org"]}]},
+                        {"handle": [{"handler": "reverse_proxy"}]}
+                     ]}]},
+                    {"match": [{"host": ["{http.request.host}"]}]}
+                ]},
+                "srv1": {"listen": [":8080"], "routes": [
+                    {"match": [{"host": ["internal.example.org"]}]}
+                ]}
+            }"#,
+        );
+        let mut source = Caddy::with_transport(DEFAULT_URL, Arc::new(transport.clone()));
+        let names =
+            |records: Vec<SourcedRecord>| records.into_iter().map(|r| r.name).collect::<Vec<_>>();
+
+        let records = source.records().await.unwrap();
+        assert_eq!(
+            names(records),
+            vec![
+                "app.example.org",
+                "www.example.org",
+                "api.example.org",
+                "internal.example.org"
+            ]
+        );
+
+        source.servers = vec!["srv1".to_string()];
+        let records = source.records().await.unwrap();
+        assert_eq!(names(records), vec!["internal.example.org"]);
+
+        // A Caddy serving nothing yet
+        transport.route(
+            Method::Get,
+            "http://localhost:2019/config/apps/http/servers",
+            200,
+            "null",
+        );
+        assert!(source.records().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use chrono::Utc;
+use tokio::sync::Notify;
+
+use super::RecordSource;
+use super::SourcedRecord;
+use super::parse_types;
+use super::watch_file;
+use crate::error::Error;
+use crate::error::Result;
+use crate::record::RecordContent;
+use crate::record::RecordType;
+use crate::types::Param;
+use crate::types::glob_match;
+use crate::types::parse_duration;
+
+const DEFAULT_POLL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LeaseFormat {
+    Dnsmasq,
+    Kea,
+    Isc,
+}
+
+impl LeaseFormat {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "dnsmasq" => Ok(Self::Dnsmasq),
+            "kea" => Ok(Self::Kea),
+            "isc" => Ok(Self::Isc),
+            _ => Err(Error::ParseError(format!(
+                "dhcp: invalid format {}, expected dnsmasq, kea or isc",
+                value
+            ))),
+        }
+    }
+}
+
+// A lease of a lease file, `expires` is a unix timestamp or `None` for an
+// infinite lease
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lease {
+    pub hostname: String,
+    pub address: IpAddr,
+    pub expires: Option<i64>,
+}
+
+// Records of the hosts of a LAN, from the lease file of its DHCP server and
+// read again at every cycle. Every active lease with a hostname becomes a
+// record `<hostname>.<domain>` of the leased address.
+pub struct Dhcp {
+    file: PathBuf,
+    format: LeaseFormat,
+    domain: String,
+    hosts: Vec<String>,
+    types: Vec<RecordType>,
+    poll: Duration,
+}
+
+impl Dhcp {
+    // Params: `file` (required, relative to the config directory), its
+    // `format` (dnsmasq by default, kea or isc), the `domain` appended to the
+    // hostnames (required), `hosts`, comma separated patterns of the hostnames
+    // to keep (all by default), the record `types` (A and AAAA by default) and
+    // `poll`, how often daemons look for changes of the file (10s by default,
+    // 0 disables it)
+    pub fn new_with_args(args: Vec<Param>, base_dir: &Path) -> Result<Self> {
+        let mut file = None;
+        let mut format = LeaseFormat::Dnsmasq;
+        let mut domain = None;
+        let mut hosts = vec![];
+        let mut types = vec![RecordType::A, RecordType::AAAA];
+        let mut poll = DEFAULT_POLL;
+
+        for param in args {
+            match param.name.as_str() {
+                "file" => file = Some(base_dir.join(&param.value)),
+                "format" => format = LeaseFormat::parse(&param.value)?,
+                "domain" => domain = Some(param.value.trim_matches('.').to_ascii_lowercase()),
+                "hosts" => {
+                    hosts = param
+                        .value
+                        .split(',')
+                        .map(|h| h.trim().to_string())
+                        .filter(|h| !h.is_empty())
+                        .collect()
+                }
+                "types" => types = parse_types("dhcp", &param.value)?,
+                "poll" => poll = parse_duration(&param.value)?,
+                name => {
+                    return Err(Error::ParseError(format!("dhcp: unknown param {}", name)));
+                }
+            }
+        }
+
+        Ok(Self {
+            file: file.ok_or(Error::ParseError("dhcp: file is required".to_string()))?,
+            format,
+            domain: domain.ok_or(Error::ParseError("dhcp: domain is required".to_string()))?,
+            hosts,
+            types,
+            poll,
+        })
+    }
+}
+
+// The hostname of a lease as a DNS label, `None` when the client sent none or
+// one that is not a valid label. A FQDN is cut to its first label.
+fn host_label(hostname: &str) -> Option<String> {
+    let label = hostname.trim().split('.').next()?.to_ascii_lowercase();
+    let valid = !label.is_empty()
+        && label.len() <= 63
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-');
+    valid.then_some(label)
+}
+
+// dnsmasq.leases, a lease per line:
+// `<expiry> <mac> <address> <hostname> <client-id>`, the expiry 0 for an
+// infinite lease and the hostname `*` when unknown. The `duid` line of the
+// IPv6 leases is skipped.
+pub fn parse_dnsmasq(content: &str) -> Vec<Lease> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            if fields.len() < 4 {
+                return None;
+            }
+            let expires = fields[0].parse::<i64>().ok()?;
+            Some(Lease {
+                hostname: fields[3].to_string(),
+                address: fields[2].parse().ok()?,
+                expires: (expires != 0).then_some(expires),
+            })
+        })
+        .collect()
+}
+
+// The memfile CSV of Kea, of DHCPv4 or DHCPv6. Its columns are found by the
+// header, a lease of an address is superseded by the later lines of the same
+// address, leases whose state is not 0 (declined or reclaimed) are dropped.
+pub fn parse_kea(content: &str) -> Result<Vec<Lease>> {
+    let mut lines = content.lines();
+    let header = lines
+        .next()
+        .ok_or(Error::ParseError("kea: empty lease file".to_string()))?
+        .split(',')
+        .collect::<Vec<_>>();
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|c| c.trim() == name)
+            .ok_or(Error::ParseError(format!("kea: no column {}", name)))
+    };
+    let (address, expire, hostname) = (column("address")?, column("expire")?, column("hostname")?);
+    let state = column("state").ok();
+
+    let mut leases: HashMap<IpAddr, Option<Lease>> = HashMap::new();
+    for line in lines {
+        let fields = line.split(',').collect::<Vec<_>>();
+        let field = |i: usize| fields.get(i).map(|f| f.trim()).unwrap_or_default();
+        let Ok(ip) = field(address).parse::<IpAddr>() else {
+            continue;
+        };
+        let active = state.is_none_or(|i| matches!(field(i), "" | "0"));
+        let lease = active.then(|| Lease {
+            // Kea escapes commas of the hostname
+            hostname: field(hostname).replace("&#x2c", ","),
+            address: ip,
+            expires: field(expire).parse().ok(),
+        });
+        leases.insert(ip, lease);
+    }
+    Ok(leases.into_values().flatten().collect())
+}
+
+// `ends` of an ISC lease: `never`, `epoch <timestamp>` or
+// `<weekday> <yyyy/mm/dd> <hh:mm:ss>` in UTC
+fn parse_isc_time(value: &str) -> Option<Option<i64>> {
+    let fields = value.split_whitespace().collect::<Vec<_>>();
+    match fields.as_slice() {
+        ["never"] => Some(None),
+        ["epoch", ts, ..] => ts.parse().ok().map(Some),
+        [_, date, time] => {
+            NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y/%m/%d %H:%M:%S")
+                .ok()
+                .map(|t| Some(t.and_utc().timestamp()))
+        }
+        _ => None,
+    }
+}
+
+// dhcpd.leases of the ISC DHCP server, `lease <address> { ... }` blocks of
+// which the last one of an address holds. Only `binding state active` leases
+// are kept.
+pub fn parse_isc(content: &str) -> Vec<Lease> {
+    let mut leases: HashMap<IpAddr, Option<Lease>> = HashMap::new();
+    let mut current: Option<(IpAddr, Lease, bool)> = None;
+
+    for line in content.lines() {
+        // Statements end with `;`, possibly followed by a comment
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if let Some(rest) = line.strip_prefix("lease ") {
+            current = rest
+                .trim_end_matches('{')
+                .trim()
+                .parse::<IpAddr>()
+                .ok()
+                .map(|ip| {
+                    let lease = Lease {
+                        hostname: String::new(),
+                        address: ip,
+                        expires: None,
+                    };
+                    (ip, lease, false)
+                });
+            continue;
+        }
+        let Some((ip, lease, active)) = current.as_mut() else {
+            continue;
+        };
+        if line == "}" {
+            leases.insert(*ip, active.then(|| lease.clone()));
+            current = None;
+        } else if let Some(value) = line.strip_prefix("ends ") {
+            lease.expires = parse_isc_time(value).flatten();
+        } else if let Some(value) = line.strip_prefix("binding state ") {
+            *active = value.trim() == "active";
+        } else if let Some(value) = line.strip_prefix("client-hostname ") {
+            lease.hostname = value.trim_matches('"').to_string();
+        }
+    }
+    leases.into_values().flatten().collect()
+}
+
+#[async_trait]
+impl RecordSource for Dhcp {
+    async fn records(&self) -> Result<Vec<SourcedRecord>> {
+        let content = std::fs::read_to_string(&self.file)
+            .map_err(|e| Error::ParseError(format!("dhcp: {}: {}", self.file.display(), e)))?;
+        let mut leases = match self.format {
+            LeaseFormat::Dnsmasq => parse_dnsmasq(&content),
+            LeaseFormat::Kea => {
+                parse_kea(&content).map_err(|e| e.context(&self.file.display().to_string()))?
+            }
+            LeaseFormat::Isc => parse_isc(&content),
+        };
+        // The lease ending last comes first, it wins when a host holds several
+        leases.sort_by_key(|l| (std::cmp::Reverse(l.expires.unwrap_or(i64::MAX)), l.address));
+
+        let now = Utc::now().timestamp();
+        let mut ret: Vec<SourcedRecord> = vec![];
+        for lease in leases {
+            if lease.expires.is_some_and(|t| t <= now) {
+                continue;
+            }
+            let Some(host) = host_label(&lease.hostname) else {
+                continue;
+            };
+            if !self.hosts.is_empty() && !self.hosts.iter().any(|p| glob_match(p, &host)) {
+                continue;
+            }
+            let content = match lease.address {
+                IpAddr::V4(ip) => RecordContent::A(ip),
+                IpAddr::V6(ip) => RecordContent::AAAA(ip),
+            };
+            if !self.types.contains(&content.record_type()) {
+                continue;
+            }
+            let name = format!("{}.{}", host, self.domain);
+            if ret
+                .iter()
+                .any(|r| r.name == name && r.content.record_type() == content.record_type())
+            {
+                continue;
+            }
+            let mut record = SourcedRecord::new(&name);
+            record.content = content;
+            ret.push(record);
+        }
+        Ok(ret)
+    }
+
+    // Polls the modification time, a new lease is picked up within `poll`
+    fn watch(&self, changed: Arc<Notify>) {
+        watch_file("dhcp", self.file.clone(), self.poll, changed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_leases() {
+        let dnsmasq = "\
+1893456000 aa:bb:cc:dd:ee:01 192.168.1.10 nas 01:aa:bb:cc:dd:ee:01
+0 aa:bb:cc:dd:ee:02 192.168.1.11 * *
+duid 00:01:00:01:2c:3d:4e:5f:aa:bb:cc:dd:ee:01
+1893456000 1234 2001:db8::10 nas 00:01:00:01
+";
+        let leases = parse_dnsmasq(dnsmasq);
+        assert_eq!(leases.len(), 3);
+        assert_eq!(leases[0].expires, Some(1893456000));
+        assert_eq!(leases[1].expires, None);
+        assert_eq!(leases[2].address, "2001:db8::10".parse::<IpAddr>().unwrap());
+
+        let kea = "\
+address,hwaddr,client_id,valid_lifetime,expire,subnet_id,fqdn_fwd,fqdn_rev,hostname,state,user_context,pool_id
+192.168.1.20,aa:bb:cc:dd:ee:03,,3600,1893456000,1,0,0,printer.lan.,0,,0
+192.168.1.21,aa:bb:cc:dd:ee:04,,3600,1893456000,1,0,0,tv,0,,0
+192.168.1.21,aa:bb:cc:dd:ee:04,,3600,1893456000,1,0,0,tv,2,,0
+";
+        let leases = parse_kea(kea).unwrap();
+        assert_eq!(leases.len(), 1);
+        assert_eq!(leases[0].hostname, "printer.lan.");
+        assert!(parse_kea("address,hwaddr\n").is_err());
+
+        let isc = r#"
+lease 192.168.1.30 {
+  starts 4 2026/10/15 10:00:00;
+  ends 2 2030/01/01 00:00:00;
+  binding state active;
+  next binding state free;
+  client-hostname "laptop";
+}
+lease 192.168.1.31 {
+  ends never;
+  binding state active;
+  client-hostname "phone";
+}
+lease 192.168.1.31 {
+  ends epoch 1000; # Thu Jan 01 00:16:40 1970
+  binding state active;
+  client-hostname "phone";
+}
+lease 192.168.1.32 {
+  ends never;
+  binding state free;
+  client-hostname "tablet";
+}
+"#;
+        let mut leases = parse_isc(isc);
+        leases.sort_by_key(|l| l.address);
+        assert_eq!(
+            leases,
+            vec![
+                Lease {
+                    hostname: "laptop".to_string(),
+                    address: "192.168.1.30".parse().unwrap(),
+                    expires: Some(1893456000),
+                },
+                Lease {
+                    hostname: "phone".to_string(),
+                    address: "192.168.1.31".parse().unwrap(),
+                    expires: Some(1000),
+                }
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dhcp_records() {
+        let dir = std::env::temp_dir();
+        let name = format!("dns-syncer-{}.leases", std::process::id());
+        let path = dir.join(&name);
+        std::fs::write(
+            &path,
+            "\
+1893456000 aa:bb:cc:dd:ee:01 192.168.1.10 NAS *
+1893459600 aa:bb:cc:dd:ee:05 192.168.1.15 nas *
+1000 aa:bb:cc:dd:ee:02 192.168.1.11 old-laptop *
+0 aa:bb:cc:dd:ee:03 192.168.1.12 printer *
+0 aa:bb:cc:dd:ee:04 192.168.1.13 * *
+0 aa:bb:cc:dd:ee:06 192.168.1.14 bad_name *
+1893456000 1234 2001:db8::10 nas 00:01:00:01
+",
+        )
+        .unwrap();
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+
+        let mut source = Dhcp::new_with_args(
+            vec![param("file", &name), param("domain", "lan.example.org.")],
+            &dir,
+        )
+        .unwrap();
+        let records = source.records().await.unwrap();
+        let records = records
+            .iter()
+            .map(|r| (r.name.as_str(), r.content.clone()))
+            .collect::<Vec<_>>();
+        // Expired leases, unknown and invalid hostnames are skipped, the
+        // lease ending last wins
+        assert_eq!(
+            records,
+            vec![
+                (
+                    "printer.lan.example.org",
+                    RecordContent::A("192.168.1.12".parse().unwrap())
+                ),
+                (
+                    "nas.lan.example.org",
+                    RecordContent::A("192.168.1.15".parse().unwrap())
+                ),
+                (
+                    "nas.lan.example.org",
+                    RecordContent::AAAA("2001:db8::10".parse().unwrap())
+                ),
+            ]
+        );
+
+        source.hosts = vec!["n*".to_string()];
+        source.types = vec![RecordType::A];
+        let records = source.records().await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].name, "nas.lan.example.org");
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(source.records().await.is_err());
+        assert!(Dhcp::new_with_args(vec![param("file", &name)], &dir).is_err());
+        assert!(
+            Dhcp::new_with_args(
+                vec![
+                    param("file", &name),
+                    param("domain", "lan"),
+                    param("format", "udhcpd")
+                ],
+                &dir
+            )
+            .is_err()
+        );
+    }
+}
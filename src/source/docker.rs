@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::sync::Notify;
+
+use super::RecordSource;
+use super::SourcedRecord;
+use crate::error::Error;
+use crate::error::Result;
+use crate::record::RecordContent;
+use crate::types::Param;
+use crate::wrapper::aws;
+use crate::wrapper::unix;
+
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+const DEFAULT_LABEL_PREFIX: &str = "dns-syncer";
+// Wait before connecting to the events API again
+const EVENTS_RETRY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize)]
+struct Container {
+    #[serde(rename = "Names", default)]
+    names: Vec<String>,
+    #[serde(rename = "Labels", default)]
+    labels: HashMap<String, String>,
+}
+
+// Records from the labels of the running containers of a Docker daemon:
+// `<prefix>.hostname` (comma separated names), `<prefix>.provider`,
+// `<prefix>.type` (A by default) and `<prefix>.content` (the public address by
+// default)
+pub struct Docker {
+    socket: PathBuf,
+    prefix: String,
+}
+
+impl Docker {
+    // Params: `socket` of the Docker API and `label_prefix`
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut socket = PathBuf::from(DEFAULT_SOCKET);
+        let mut prefix = DEFAULT_LABEL_PREFIX.to_string();
+
+        for param in args {
+            match param.name.as_str() {
+                "socket" => socket = PathBuf::from(param.value),
+                "label_prefix" => prefix = param.value,
+                name => {
+                    return Err(Error::ParseError(format!("docker: unknown param {}", name)));
+                }
+            }
+        }
+        Ok(Self { socket, prefix })
+    }
+
+    fn label<'a>(&self, container: &'a Container, key: &str) -> Option<&'a str> {
+        container
+            .labels
+            .get(&format!("{}.{}", self.prefix, key))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+    }
+
+    fn records_of(&self, containers: &[Container]) -> Vec<SourcedRecord> {
+        let mut ret = vec![];
+        for container in containers {
+            let Some(hostnames) = self.label(container, "hostname") else {
+                continue;
+            };
+            let ty = self.label(container, "type").unwrap_or("A");
+            let content = match RecordContent::parse(ty, self.label(container, "content")) {
+                Ok(content) => content,
+                Err(e) => {
+                    let name = container.names.first().map(|n| n.trim_start_matches('/'));
+                    log::warn!(
+                        "docker: container {}: {}, skipped",
+                        name.unwrap_or_default(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            for hostname in hostnames
+                .split(',')
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+            {
+                let mut record = SourcedRecord::new(hostname);
+                record.content = content.clone();
+                record.provider = self.label(container, "provider").map(str::to_string);
+                ret.push(record);
+            }
+        }
+        ret
+    }
+}
+
+#[async_trait]
+impl RecordSource for Docker {
+    async fn records(&self) -> Result<Vec<SourcedRecord>> {
+        let filters = format!(r#"{{"label":["{}.hostname"]}}"#, self.prefix);
+        let path = format!(
+            "/containers/json?filters={}",
+            aws::uri_encode(&filters, true)
+        );
+        let (status, body) = unix::get(&self.socket, &path)
+            .await
+            .map_err(|e| e.context("docker"))?;
+        if status != 200 {
+            return Err(Error::HttpStatus { status }.context("docker: listing containers"));
+        }
+        let containers: Vec<Container> = serde_json::from_str(&body)?;
+        Ok(self.records_of(&containers))
+    }
+
+    // Containers starting or stopping wake `changed`. The task ends with the
+    // runner dropping `changed`.
+    fn watch(&self, changed: Arc<Notify>) {
+        let changed = Arc::downgrade(&changed);
+        let socket = self.socket.clone();
+        let filters = format!(
+            r#"{{"type":["container"],"event":["start","die"],"label":["{}.hostname"]}}"#,
+            self.prefix
+        );
+        let path = format!("/events?filters={}", aws::uri_encode(&filters, true));
+        tokio::spawn(async move {
+            while changed.strong_count() > 0 {
+                match unix::get_stream(&socket, &path).await {
+                    Ok((200, mut body)) => {
+                        while let Ok(Some(_)) = body.line().await {
+                            let Some(changed) = changed.upgrade() else {
+                                return;
+                            };
+                            changed.notify_one();
+                        }
+                        log::warn!("docker: events stream ended");
+                    }
+                    Ok((status, _)) => log::warn!("docker: events answered {}", status),
+                    Err(e) => log::warn!("docker: events failed: {}", e),
+                }
+                tokio::time::sleep(EVENTS_RETRY).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::record::RecordType;
+    use crate::record::TTL;
+
+    #[test]
+    fn test_docker_labels() {
+        let docker = Docker::new_with_args(vec![]).unwrap();
+        let containers: Vec<Container> = serde_json::from_str(
+            r#"[
+                {"Names": ["/web"], "Labels": {
+                    "dns-syncer.hostname": "app.example.org, www.example.org",
+                    "dns-syncer.provider": "cf-1"}},
+                {"Names": ["/nas"], "Labels": {
+                    "dns-syncer.hostname": "nas.example.org",
+                    "dns-syncer.content": "192.0.2.9"}},
+                {"Names": ["/bad"], "Labels": {
+                    "dns-syncer.hostname": "bad.example.org",
+                    "dns-syncer.type": "MX"}},
+                {"Names": ["/db"], "Labels": {"com.example": "x"}}
+            ]"#,
+        )
+        .unwrap();
+
+        let records = docker.records_of(&containers);
+        let expected = vec![
+            SourcedRecord {
+                name: "app.example.org".to_string(),
+                content: RecordContent::Unassigned(RecordType::A),
+                provider: Some("cf-1".to_string()),
+                ttl: TTL::Auto,
+            },
+            SourcedRecord {
+                name: "www.example.org".to_string(),
+                content: RecordContent::Unassigned(RecordType::A),
+                provider: Some("cf-1".to_string()),
+                ttl: TTL::Auto,
+            },
+            SourcedRecord {
+                name: "nas.example.org".to_string(),
+                content: RecordContent::A(Ipv4Addr::new(192, 0, 2, 9)),
+                provider: None,
+                ttl: TTL::Auto,
+            },
+        ];
+        assert_eq!(records, expected);
+
+        let param = Param {
+            name: "unknown".to_string(),
+            value: "x".to_string(),
+        };
+        assert!(Docker::new_with_args(vec![param]).is_err());
+    }
+}
@@ -0,0 +1,650 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use tokio::sync::Notify;
+
+use super::RecordSource;
+use super::SourcedRecord;
+use crate::error::Error;
+use crate::error::Result;
+use crate::record::RecordContent;
+use crate::record::RecordType;
+use crate::types::Param;
+use crate::wrapper::http::Client;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HeaderKey;
+use crate::wrapper::http::HttpTransport;
+use crate::wrapper::http::ReqwestTransport;
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+const DEFAULT_ANNOTATION_PREFIX: &str = "dns-syncer";
+// Wait before watching a kind again after its stream failed
+const WATCH_RETRY: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Ingress,
+    Service,
+    Gateway,
+}
+
+impl Kind {
+    fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "ingress" => Ok(Kind::Ingress),
+            "service" => Ok(Kind::Service),
+            "gateway" => Ok(Kind::Gateway),
+            kind => Err(Error::ParseError(format!(
+                "kubernetes: unknown kind {}",
+                kind
+            ))),
+        }
+    }
+
+    fn path(&self, namespace: Option<&str>) -> String {
+        let (api, plural) = match self {
+            Kind::Ingress => ("/apis/networking.k8s.io/v1", "ingresses"),
+            Kind::Service => ("/api/v1", "services"),
+            Kind::Gateway => ("/apis/gateway.networking.k8s.io/v1", "gateways"),
+        };
+        match namespace {
+            Some(ns) => format!("{}/namespaces/{}/{}", api, ns, plural),
+            None => format!("{}/{}", api, plural),
+        }
+    }
+}
+
+// Where the records of a resource point to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    // The addresses in the status of the resource
+    LoadBalancer,
+    // The public address of the cycle
+    PublicIp,
+}
+
+////////////////////////////////////////////////////////////
+// Resources
+////////////////////////////////////////////////////////////
+#[derive(Debug, Deserialize)]
+struct List<T> {
+    items: Vec<T>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Metadata {
+    name: String,
+    #[serde(default)]
+    namespace: String,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LoadBalancerStatus {
+    #[serde(rename = "loadBalancer", default)]
+    load_balancer: LoadBalancer,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LoadBalancer {
+    #[serde(default)]
+    ingress: Vec<LoadBalancerIngress>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LoadBalancerIngress {
+    ip: Option<String>,
+    hostname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ingress {
+    metadata: Metadata,
+    #[serde(default)]
+    spec: IngressSpec,
+    #[serde(default)]
+    status: LoadBalancerStatus,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct IngressSpec {
+    #[serde(default)]
+    rules: Vec<IngressRule>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct IngressRule {
+    host: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Service {
+    metadata: Metadata,
+    #[serde(default)]
+    status: LoadBalancerStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct Gateway {
+    metadata: Metadata,
+    #[serde(default)]
+    spec: GatewaySpec,
+    #[serde(default)]
+    status: GatewayStatus,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GatewaySpec {
+    #[serde(default)]
+    listeners: Vec<GatewayListener>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GatewayListener {
+    hostname: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GatewayStatus {
+    #[serde(default)]
+    addresses: Vec<GatewayAddress>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct GatewayAddress {
+    #[serde(rename = "type")]
+    ty: Option<String>,
+    value: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Auth {
+    token: Option<String>,
+    token_file: Option<PathBuf>,
+}
+
+impl Auth {
+    // Service account tokens are rotated, the file is read for every request
+    fn headers(&self) -> Result<Vec<Header>> {
+        let token = match (self.token.as_ref(), self.token_file.as_ref()) {
+            (Some(token), _) => token.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path)?.trim().to_string(),
+            (None, None) => return Ok(vec![]),
+        };
+        Ok(vec![Header::new(
+            HeaderKey::Authorization,
+            format!("Bearer {}", token),
+        )])
+    }
+}
+
+// A resource of any kind as the records see it
+struct Resource {
+    metadata: Metadata,
+    hosts: Vec<String>,
+    // IP addresses or hostnames of the status
+    addresses: Vec<String>,
+}
+
+impl From<Ingress> for Resource {
+    fn from(ingress: Ingress) -> Self {
+        Self {
+            metadata: ingress.metadata,
+            hosts: ingress
+                .spec
+                .rules
+                .into_iter()
+                .filter_map(|r| r.host)
+                .collect(),
+            addresses: ingress.status.load_balancer.addresses(),
+        }
+    }
+}
+
+impl From<Service> for Resource {
+    // Services only have the names of their hostname annotation
+    fn from(service: Service) -> Self {
+        Self {
+            metadata: service.metadata,
+            hosts: vec![],
+            addresses: service.status.load_balancer.addresses(),
+        }
+    }
+}
+
+impl From<Gateway> for Resource {
+    fn from(gateway: Gateway) -> Self {
+        Self {
+            metadata: gateway.metadata,
+            hosts: gateway
+                .spec
+                .listeners
+                .into_iter()
+                .filter_map(|l| l.hostname)
+                .collect(),
+            addresses: gateway
+                .status
+                .addresses
+                .into_iter()
+                .filter(|a| matches!(a.ty.as_deref(), None | Some("IPAddress" | "Hostname")))
+                .map(|a| a.value)
+                .collect(),
+        }
+    }
+}
+
+impl LoadBalancer {
+    fn addresses(self) -> Vec<String> {
+        self.ingress
+            .into_iter()
+            .filter_map(|i| i.ip.or(i.hostname))
+            .collect()
+    }
+}
+
+////////////////////////////////////////////////////////////
+// Source
+////////////////////////////////////////////////////////////
+// Records from the Ingress, Service and Gateway resources of a Kubernetes
+// cluster, like external-dns. Ingresses and Gateways give the hosts of their
+// rules and listeners, Services of type LoadBalancer the names of their
+// `<prefix>/hostname` annotation, which adds names to the other kinds too.
+pub struct Kubernetes {
+    cli: Client,
+    transport: Option<Arc<ReqwestTransport>>,
+    url: String,
+    auth: Auth,
+    namespace: Option<String>,
+    kinds: Vec<Kind>,
+    target: Target,
+    prefix: String,
+}
+
+impl Kubernetes {
+    // Params: `url` of the API server, `token` or `token_file`, `ca_file`,
+    // `namespace` (all by default), `kinds` (comma separated, ingress and
+    // service by default), `target` (load_balancer or public_ip) and
+    // `annotation_prefix`. In a pod the service account gives the defaults.
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let account = Path::new(SERVICE_ACCOUNT_DIR);
+        let mut url = in_cluster_url();
+        let mut token = None;
+        let mut token_file = Some(account.join("token")).filter(|p| p.exists());
+        let mut ca_file = Some(account.join("ca.crt")).filter(|p| p.exists());
+        let mut namespace = None;
+        let mut kinds = vec![Kind::Ingress, Kind::Service];
+        let mut target = Target::LoadBalancer;
+        let mut prefix = DEFAULT_ANNOTATION_PREFIX.to_string();
+
+        for param in args {
+            match param.name.as_str() {
+                "url" => url = Some(param.value.trim_end_matches('/').to_string()),
+                "token" => token = Some(param.value),
+                "token_file" => token_file = Some(PathBuf::from(param.value)),
+                "ca_file" => ca_file = Some(PathBuf::from(param.value)),
+                "namespace" => namespace = Some(param.value),
+                "kinds" => {
+                    kinds = param
+                        .value
+                        .split(',')
+                        .map(|k| Kind::from_name(k.trim()))
+                        .collect::<Result<_>>()?
+                }
+                "target" => {
+                    target = match param.value.as_str() {
+                        "load_balancer" => Target::LoadBalancer,
+                        "public_ip" => Target::PublicIp,
+                        value => {
+                            return Err(Error::ParseError(format!(
+                                "kubernetes: unknown target {}",
+                                value
+                            )));
+                        }
+                    }
+                }
+                "annotation_prefix" => prefix = param.value,
+                name => {
+                    return Err(Error::ParseError(format!(
+                        "kubernetes: unknown param {}",
+                        name
+                    )));
+                }
+            }
+        }
+
+        let url = url.ok_or(Error::ParseError(
+            "kubernetes: url is required outside a cluster".to_string(),
+        ))?;
+        let transport = Arc::new(match ca_file {
+            Some(path) => ReqwestTransport::new_with_ca(&path)?,
+            None => ReqwestTransport::new()?,
+        });
+        Ok(Self {
+            cli: Client::with_transport(transport.clone()),
+            transport: Some(transport),
+            url,
+            auth: Auth { token, token_file },
+            namespace,
+            kinds,
+            target,
+            prefix,
+        })
+    }
+
+    // Source reading the API at `url` through `transport`, for tests. It
+    // cannot watch.
+    pub fn with_transport(url: &str, transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            cli: Client::with_transport(transport),
+            transport: None,
+            url: url.to_string(),
+            auth: Auth::default(),
+            namespace: None,
+            kinds: vec![Kind::Ingress, Kind::Service, Kind::Gateway],
+            target: Target::LoadBalancer,
+            prefix: DEFAULT_ANNOTATION_PREFIX.to_string(),
+        }
+    }
+
+    async fn list<T: DeserializeOwned>(&self, kind: Kind) -> Result<Vec<T>> {
+        let url = format!("{}{}", self.url, kind.path(self.namespace.as_deref()));
+        let body = self
+            .cli
+            .get(&url, Some(self.auth.headers()?))
+            .await?
+            .into_body()
+            .map_err(|e| e.context(&format!("kubernetes: listing {:?}", kind)))?;
+        Ok(serde_json::from_str::<List<T>>(&body)?.items)
+    }
+
+    fn annotation<'a>(&self, metadata: &'a Metadata, key: &str) -> Option<&'a str> {
+        metadata
+            .annotations
+            .get(&format!("{}/{}", self.prefix, key))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+    }
+
+    fn records_of(&self, resource: Resource) -> Vec<SourcedRecord> {
+        let metadata = &resource.metadata;
+        let mut hosts = resource.hosts.clone();
+        if let Some(names) = self.annotation(metadata, "hostname") {
+            hosts.extend(names.split(',').map(|n| n.trim().to_string()));
+        }
+        hosts.retain(|h| !h.is_empty());
+        if hosts.is_empty() {
+            return vec![];
+        }
+
+        // A target annotation wins over the status, one address per type
+        let contents = match (self.annotation(metadata, "target"), self.target) {
+            (Some(target), _) => to_contents(target.split(',').map(str::trim)),
+            (None, Target::PublicIp) => vec![RecordContent::Unassigned(RecordType::A)],
+            (None, Target::LoadBalancer) => {
+                to_contents(resource.addresses.iter().map(String::as_str))
+            }
+        };
+        if contents.is_empty() {
+            log::debug!(
+                "kubernetes: {}/{} has no address yet",
+                metadata.namespace,
+                metadata.name
+            );
+            return vec![];
+        }
+
+        let provider = self.annotation(metadata, "provider").map(str::to_string);
+        let mut ret = vec![];
+        for host in hosts.iter() {
+            for content in contents.iter() {
+                let mut record = SourcedRecord::new(host);
+                record.content = content.clone();
+                record.provider = provider.clone();
+                ret.push(record);
+            }
+        }
+        ret
+    }
+
+    async fn resources(&self, kind: Kind) -> Result<Vec<Resource>> {
+        let resources = match kind {
+            Kind::Ingress => into_resources(self.list::<Ingress>(kind).await?),
+            Kind::Gateway => into_resources(self.list::<Gateway>(kind).await?),
+            Kind::Service => into_resources(self.list::<Service>(kind).await?),
+        };
+        Ok(resources)
+    }
+}
+
+fn into_resources<T: Into<Resource>>(items: Vec<T>) -> Vec<Resource> {
+    items.into_iter().map(Into::into).collect()
+}
+
+// API server of the cluster the pod runs in
+fn in_cluster_url() -> Option<String> {
+    let host = std::env::var("KUBERNETES_SERVICE_HOST").ok()?;
+    let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or("443".to_string());
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => Some(format!("https://[{}]:{}", ip, port)),
+        _ => Some(format!("https://{}:{}", host, port)),
+    }
+}
+
+// The first IPv4 and IPv6 address, a hostname only without either
+fn to_contents<'a>(addresses: impl Iterator<Item = &'a str>) -> Vec<RecordContent> {
+    let mut v4 = None;
+    let mut v6 = None;
+    let mut hostname = None;
+    for address in addresses {
+        match address.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => v4 = v4.or(Some(RecordContent::A(ip))),
+            Ok(IpAddr::V6(ip)) => v6 = v6.or(Some(RecordContent::AAAA(ip))),
+            Err(_) if !address.is_empty() => {
+                let name = address.trim_end_matches('.').to_string();
+                hostname = hostname.or(Some(RecordContent::CNAME(name)));
+            }
+            Err(_) => {}
+        }
+    }
+    if v4.is_none() && v6.is_none() {
+        return hostname.into_iter().collect();
+    }
+    v4.into_iter().chain(v6).collect()
+}
+
+#[async_trait]
+impl RecordSource for Kubernetes {
+    async fn records(&self) -> Result<Vec<SourcedRecord>> {
+        let mut ret = vec![];
+        for kind in self.kinds.iter() {
+            for resource in self.resources(*kind).await? {
+                ret.extend(self.records_of(resource));
+            }
+        }
+        Ok(ret)
+    }
+
+    // Every kind is watched on a stream of its own, resumed from the last
+    // version seen. The tasks end with the runner dropping `changed`.
+    fn watch(&self, changed: Arc<Notify>) {
+        let Some(transport) = self.transport.clone() else {
+            return;
+        };
+        for kind in self.kinds.iter() {
+            let changed = Arc::downgrade(&changed);
+            let transport = transport.clone();
+            let url = format!("{}{}", self.url, kind.path(self.namespace.as_deref()));
+            let auth = self.auth.clone();
+            let kind = *kind;
+            tokio::spawn(async move {
+                let mut version: Option<String> = None;
+                while changed.strong_count() > 0 {
+                    let url = match version.as_ref() {
+                        Some(v) => format!("{}?watch=1&resourceVersion={}", url, v),
+                        None => format!("{}?watch=1", url),
+                    };
+                    let stream = match auth.headers() {
+                        Ok(headers) => transport.get_lines(&url, &headers).await,
+                        Err(e) => Err(e),
+                    };
+                    match stream {
+                        Ok((200, mut lines)) => {
+                            while let Ok(Some(line)) = lines.line().await {
+                                let Some(changed) = changed.upgrade() else {
+                                    return;
+                                };
+                                match serde_json::from_str::<WatchEvent>(&line) {
+                                    // The version is too old to resume from
+                                    Ok(event) if event.r#type == "ERROR" => version = None,
+                                    Ok(event) => {
+                                        version = event.object.metadata.resource_version;
+                                        if event.r#type != "BOOKMARK" {
+                                            changed.notify_one();
+                                        }
+                                    }
+                                    Err(e) => log::debug!("kubernetes: bad watch event: {}", e),
+                                }
+                            }
+                        }
+                        Ok((status, _)) => {
+                            log::warn!("kubernetes: watching {:?} answered {}", kind, status);
+                            version = None;
+                        }
+                        Err(e) => log::warn!("kubernetes: watching {:?} failed: {}", kind, e),
+                    }
+                    tokio::time::sleep(WATCH_RETRY).await;
+                }
+            });
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WatchEvent {
+    r#type: String,
+    #[serde(default)]
+    object: WatchObject,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WatchObject {
+    #[serde(default)]
+    metadata: WatchMetadata,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct WatchMetadata {
+    #[serde(rename = "resourceVersion")]
+    resource_version: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::wrapper::http::MemoryTransport;
+    use crate::wrapper::http::Method;
+
+    const URL: &str = "https://k8s.example.org:6443";
+
+    #[tokio::test]
+    async fn test_kubernetes_records() {
+        let transport = MemoryTransport::new();
+        transport.route(
+            Method::Get,
+            &format!("{}/apis/networking.k8s.io/v1/ingresses", URL),
+            200,
+            r#"{"items": [
+                {"metadata": {"name": "web", "namespace": "default",
+                              "annotations": {"dns-syncer/provider": "cf-1"}},
+                 "spec": {"rules": [{"host": "app.example.org"}, {"http": {}}]},
+                 "status": {"loadBalancer": {"ingress": [
+                    {"ip": "192.0.2.10"}, {"ip": "192.0.2.11"}]}}},
+                {"metadata": {"name": "pending", "namespace": "default"},
+                 "spec": {"rules": [{"host": "pending.example.org"}]}}
+            ]}"#,
+        );
+        transport.route(
+            Method::Get,
+            &format!("{}/api/v1/services", URL),
+            200,
+            r#"{"items": [
+                {"metadata": {"name": "kubernetes", "namespace": "default"}},
+                {"metadata": {"name": "nas", "namespace": "default",
+                              "annotations": {"dns-syncer/hostname": "nas.example.org"}},
+                 "status": {"loadBalancer": {"ingress": [{"hostname": "lb.example.net"}]}}}
+            ]}"#,
+        );
+        transport.route(
+            Method::Get,
+            &format!("{}/apis/gateway.networking.k8s.io/v1/gateways", URL),
+            200,
+            r#"{"items": [
+                {"metadata": {"name": "gw", "namespace": "infra",
+                              "annotations": {"dns-syncer/target": "192.0.2.20"}},
+                 "spec": {"listeners": [{"hostname": "gw.example.org"}]}}
+            ]}"#,
+        );
+
+        let source = Kubernetes::with_transport(URL, Arc::new(transport));
+        let records = source.records().await.unwrap();
+        let records = records
+            .iter()
+            .map(|r| (r.name.as_str(), r.content.clone(), r.provider.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                (
+                    "app.example.org",
+                    RecordContent::A(Ipv4Addr::new(192, 0, 2, 10)),
+                    Some("cf-1")
+                ),
+                (
+                    "nas.example.org",
+                    RecordContent::CNAME("lb.example.net".to_string()),
+                    None
+                ),
+                (
+                    "gw.example.org",
+                    RecordContent::A(Ipv4Addr::new(192, 0, 2, 20)),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_kubernetes_params() {
+        let param = |name: &str, value: &str| Param {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        let source = Kubernetes::new_with_args(vec![
+            param("url", "https://k8s.example.org:6443/"),
+            param("kinds", "ingress, gateway"),
+            param("target", "public_ip"),
+            param("namespace", "web"),
+        ])
+        .unwrap();
+        assert_eq!(source.url, URL);
+        assert_eq!(source.kinds, vec![Kind::Ingress, Kind::Gateway]);
+        assert_eq!(source.target, Target::PublicIp);
+        assert_eq!(
+            Kind::Ingress.path(source.namespace.as_deref()),
+            "/apis/networking.k8s.io/v1/namespaces/web/ingresses"
+        );
+
+        let kinds = vec![param("url", URL), param("kinds", "pod")];
+        assert!(Kubernetes::new_with_args(kinds).is_err());
+    }
+}
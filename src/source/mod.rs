@@ -0,0 +1,333 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+use crate::config::CfgRecord;
+use crate::config::CfgRecordItem;
+use crate::config::CfgRecordProvider;
+use crate::config::CfgSource;
+use crate::error::Error;
+use crate::error::Result;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::RecordType;
+use crate::record::TTL;
+use crate::record::zone_of;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HeaderKey;
+
+mod caddy;
+mod dhcp;
+#[cfg(unix)]
+mod docker;
+mod kubernetes;
+mod traefik;
+mod zonefile;
+pub use caddy::*;
+pub use dhcp::*;
+#[cfg(unix)]
+pub use docker::*;
+pub use kubernetes::*;
+pub use traefik::*;
+pub use zonefile::*;
+
+// Record a source derived, `name` is the full hostname
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourcedRecord {
+    pub name: String,
+    pub content: RecordContent,
+    // Restricts the record to this provider of the source
+    pub provider: Option<String>,
+    pub ttl: TTL,
+}
+
+impl SourcedRecord {
+    // Record of the public address
+    pub fn new(name: &str) -> Self {
+        Self::public(name, RecordType::A)
+    }
+
+    // Record of the public address of type `record_type`
+    pub fn public(name: &str, record_type: RecordType) -> Self {
+        Self {
+            name: name.trim_end_matches('.').to_ascii_lowercase(),
+            content: RecordContent::Unassigned(record_type),
+            provider: None,
+            ttl: TTL::Auto,
+        }
+    }
+}
+
+// Record types of the `types` param of a source, comma separated
+pub(crate) fn parse_types(source: &str, value: &str) -> Result<Vec<RecordType>> {
+    value
+        .split(',')
+        .map(|ty| {
+            RecordType::parse(ty.trim())
+                .filter(|ty| *ty != RecordType::CNAME)
+                .ok_or(Error::ParseError(format!(
+                    "{}: invalid record type {}",
+                    source, ty
+                )))
+        })
+        .collect()
+}
+
+// A `header` param of a source, as `Name: value`
+pub(crate) fn parse_header(source: &str, value: &str) -> Result<Header> {
+    let (name, value) = value.split_once(':').ok_or(Error::ParseError(format!(
+        "{}: header {} is not `Name: value`",
+        source, value
+    )))?;
+    Ok(Header::new(
+        HeaderKey::Custom(name.trim().to_string()),
+        value.trim().to_string(),
+    ))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// Wake `changed` within `poll` of a change of the modification time of
+// `file`, until the daemon drops `changed`. A zero `poll` never wakes it.
+pub(crate) fn watch_file(source: &str, file: PathBuf, poll: Duration, changed: Arc<Notify>) {
+    if poll.is_zero() {
+        return;
+    }
+    let changed = Arc::downgrade(&changed);
+    let source = source.to_string();
+    tokio::spawn(async move {
+        let mut last = modified(&file);
+        loop {
+            tokio::time::sleep(poll).await;
+            let Some(changed) = changed.upgrade() else {
+                return;
+            };
+            let now = modified(&file);
+            if now != last {
+                log::info!("{}: {} changed", source, file.display());
+                last = now;
+                changed.notify_one();
+            }
+        }
+    });
+}
+
+// Derives records from a running service, read again at every cycle
+#[async_trait]
+pub trait RecordSource: Send + Sync {
+    async fn records(&self) -> Result<Vec<SourcedRecord>>;
+
+    // Wake `changed` whenever the records may have changed, so daemons sync
+    // right away instead of at the next check interval. Sources without events
+    // leave it alone.
+    fn watch(&self, _changed: Arc<Notify>) {}
+}
+
+// Relative paths of the params are resolved against `base_dir`, the config
+// directory
+pub fn create_source(cfg: &CfgSource, base_dir: &Path) -> Result<Box<dyn RecordSource>> {
+    let params = cfg.params.clone().into();
+    let source: Box<dyn RecordSource> = match cfg.r#type.as_str() {
+        "caddy" => Box::new(Caddy::new_with_args(params)?),
+        "dhcp" => Box::new(Dhcp::new_with_args(params, base_dir)?),
+        #[cfg(unix)]
+        "docker" => Box::new(Docker::new_with_args(params)?),
+        "kubernetes" => Box::new(Kubernetes::new_with_args(params)?),
+        "traefik" => Box::new(Traefik::new_with_args(params)?),
+        "zonefile" => Box::new(ZoneFile::new_with_args(params, base_dir)?),
+        ty => {
+            return Err(Error::ParseError(format!(
+                "source {}: unknown type {}",
+                cfg.name, ty
+            )));
+        }
+    };
+    Ok(source)
+}
+
+// A configured source with the records it derived last
+pub struct Source {
+    cfg: CfgSource,
+    inner: Box<dyn RecordSource>,
+    items: Vec<CfgRecordItem>,
+}
+
+impl Source {
+    pub fn new(cfg: CfgSource, inner: Box<dyn RecordSource>) -> Self {
+        Self {
+            cfg,
+            inner,
+            items: vec![],
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.cfg.name
+    }
+
+    pub fn items(&self) -> &[CfgRecordItem] {
+        &self.items
+    }
+
+    pub fn watch(&self, changed: Arc<Notify>) {
+        self.inner.watch(changed);
+    }
+
+    // Read the records again, the last ones are kept when it fails. Of the
+    // records of the same name and type only the first is kept.
+    pub async fn refresh(&mut self) -> Result<()> {
+        let mut records = self.inner.records().await?;
+        let mut seen = HashSet::new();
+        records.retain(|r| {
+            let unique = seen.insert((r.name.clone(), r.content.record_type()));
+            if !unique {
+                log::warn!(
+                    "source {}: {} derived twice, the first is kept",
+                    self.cfg.name,
+                    r.name
+                );
+            }
+            unique
+        });
+        self.items = records
+            .into_iter()
+            .filter_map(|record| self.to_item(record))
+            .collect();
+        Ok(())
+    }
+
+    // Derived records are told from configured ones by their comment
+    fn comment(&self) -> String {
+        self.cfg
+            .comment
+            .clone()
+            .unwrap_or_else(|| format!("derived from source {}", self.cfg.name))
+    }
+
+    // The record for every provider of the source serving a zone the name
+    // falls in, the longest zone of each provider winning
+    fn to_item(&self, record: SourcedRecord) -> Option<CfgRecordItem> {
+        let providers = self
+            .cfg
+            .providers
+            .iter()
+            .filter(|p| record.provider.as_ref().is_none_or(|name| *name == p.name))
+            .filter_map(|p| {
+                let zone = zone_of(&record.name, &p.zones)?;
+                Some(CfgRecordProvider {
+                    name: p.name.clone(),
+                    zones: vec![zone.clone()],
+                    params: p.params.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        if providers.is_empty() {
+            log::warn!(
+                "source {}: no provider serves a zone of {}, skipped",
+                self.cfg.name,
+                record.name
+            );
+            return None;
+        }
+
+        Some(CfgRecordItem {
+            record: CfgRecord {
+                name: record.name,
+                content: record.content,
+                comment: Some(self.comment()),
+                op: RecordOp::default(),
+                ttl: record.ttl,
+            },
+            providers,
+            fetchers: self.cfg.fetchers.clone(),
+            tags: vec![],
+            enabled: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Fixed(Vec<SourcedRecord>);
+
+    #[async_trait]
+    impl RecordSource for Fixed {
+        async fn records(&self) -> Result<Vec<SourcedRecord>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_source_items() {
+        let cfg: CfgSource = serde_yaml::from_str(
+            r#"
+name: containers
+type: docker
+comment: from docker
+providers:
+  - name: cf-1
+    zones: [example.org, lab.example.org]
+  - name: cf-2
+    zones: [example.com]
+"#,
+        )
+        .unwrap();
+        let mut other = SourcedRecord::new("App.Example.COM.");
+        other.provider = Some("cf-1".to_string());
+        let records = vec![
+            SourcedRecord::new("nas.lab.example.org"),
+            SourcedRecord::new("NAS.lab.example.org"),
+            SourcedRecord::new("example.com"),
+            SourcedRecord::new("app.example.net"),
+            other,
+        ];
+        let mut source = Source::new(cfg, Box::new(Fixed(records)));
+        source.refresh().await.unwrap();
+
+        let items = source
+            .items()
+            .iter()
+            .map(|i| {
+                let providers = i
+                    .providers
+                    .iter()
+                    .map(|p| format!("{}:{}", p.name, p.zones.join(",")))
+                    .collect::<Vec<_>>();
+                (i.record.name.as_str(), providers)
+            })
+            .collect::<Vec<_>>();
+        // Unserved names are skipped, also when the provider label points
+        // elsewhere
+        assert_eq!(
+            items,
+            vec![
+                (
+                    "nas.lab.example.org",
+                    vec!["cf-1:lab.example.org".to_string()]
+                ),
+                ("example.com", vec!["cf-2:example.com".to_string()]),
+            ]
+        );
+        assert_eq!(
+            source.items()[0].record.comment.as_deref(),
+            Some("from docker")
+        );
+
+        source.cfg.comment = None;
+        source.refresh().await.unwrap();
+        assert_eq!(
+            source.items()[0].record.comment.as_deref(),
+            Some("derived from source containers")
+        );
+    }
+}
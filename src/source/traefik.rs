@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::RecordSource;
+use super::SourcedRecord;
+use super::parse_header;
+use super::parse_types;
+use crate::error::Error;
+use crate::error::Result;
+use crate::record::RecordType;
+use crate::types::Param;
+use crate::wrapper::http::Client;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HttpTransport;
+
+#[derive(Debug, Deserialize)]
+struct Router {
+    #[serde(default)]
+    rule: String,
+    #[serde(default)]
+    status: String,
+    #[serde(rename = "entryPoints", default)]
+    entry_points: Vec<String>,
+}
+
+// Records of the public address for the hosts of the `Host()` matchers of the
+// HTTP routers of Traefik, read from its API
+pub struct Traefik {
+    cli: Client,
+    url: String,
+    headers: Vec<Header>,
+    entry_points: Vec<String>,
+    types: Vec<RecordType>,
+}
+
+impl Traefik {
+    // Params: `url` of the API (required), `header` sent with every request as
+    // `Name: value`, e.g. basic auth, `entrypoints` (comma separated, all by
+    // default) and the record `types` (A by default)
+    pub fn new_with_args(args: Vec<Param>) -> Result<Self> {
+        let mut url = None;
+        let mut headers = vec![];
+        let mut entry_points = vec![];
+        let mut types = vec![RecordType::A];
+
+        for param in args {
+            match param.name.as_str() {
+                "url" => url = Some(param.value.trim_end_matches('/').to_string()),
+                "header" => headers.push(parse_header("traefik", &param.value)?),
+                "entrypoints" => {
+                    entry_points = param
+                        .value
+                        .split(',')
+                        .map(|e| e.trim().to_string())
+                        .collect()
+                }
+                "types" => types = parse_types("traefik", &param.value)?,
+                name => {
+                    return Err(Error::ParseError(format!(
+                        "traefik: unknown param {}",
+                        name
+                    )));
+                }
+            }
+        }
+
+        Ok(Self {
+            cli: Client::new()?,
+            url: url.ok_or(Error::ParseError("traefik: url is required".to_string()))?,
+            headers,
+            entry_points,
+            types,
+        })
+    }
+
+    // Source reading the API at `url` through `transport`, for tests
+    pub fn with_transport(url: &str, transport: Arc<dyn HttpTransport>) -> Self {
+        Self {
+            cli: Client::with_transport(transport),
+            url: url.to_string(),
+            headers: vec![],
+            entry_points: vec![],
+            types: vec![RecordType::A],
+        }
+    }
+
+    // Disabled routers and the ones of other entrypoints are left out
+    fn hosts(&self, routers: &[Router]) -> Vec<String> {
+        let mut ret = vec![];
+        for router in routers {
+            if !router.status.is_empty() && router.status != "enabled" {
+                continue;
+            }
+            if !self.entry_points.is_empty()
+                && !router
+                    .entry_points
+                    .iter()
+                    .any(|e| self.entry_points.contains(e))
+            {
+                continue;
+            }
+            ret.extend(rule_hosts(&router.rule));
+        }
+        ret
+    }
+}
+
+// Hosts of the `Host()` matchers of a rule, negated ones left out. Traefik v2
+// takes several hosts per matcher, v3 one.
+fn rule_hosts(rule: &str) -> Vec<String> {
+    let mut ret = vec![];
+    let mut rest = rule;
+    while let Some(pos) = rest.find("Host(") {
+        let negated = rest[..pos].trim_end().ends_with('!');
+        let word = rest[..pos]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric());
+        rest = &rest[pos + "Host(".len()..];
+        let args = &rest[..rest.find(')').unwrap_or(rest.len())];
+        if negated || word {
+            continue;
+        }
+        ret.extend(
+            args.split(',')
+                .map(|a| a.trim().trim_matches(|c| c == '`' || c == '"'))
+                .filter(|a| !a.is_empty())
+                .map(str::to_string),
+        );
+    }
+    ret
+}
+
+#[async_trait]
+impl RecordSource for Traefik {
+    async fn records(&self) -> Result<Vec<SourcedRecord>> {
+        let url = format!("{}/api/http/routers", self.url);
+        let body = self
+            .cli
+            .get(&url, Some(self.headers.clone()))
+            .await?
+            .into_body()
+            .map_err(|e| e.context("traefik: listing routers"))?;
+        let routers: Vec<Router> = serde_json::from_str(&body)?;
+
+        let mut ret = vec![];
+        for host in self.hosts(&routers) {
+            for ty in self.types.iter() {
+                ret.push(SourcedRecord::public(&host, ty.clone()));
+            }
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wrapper::http::MemoryTransport;
+    use crate::wrapper::http::Method;
+
+    #[test]
+    fn test_traefik_rule_hosts() {
+        assert_eq!(
+            rule_hosts("Host(`a.example.org`) && PathPrefix(`/api`)"),
+            vec!["a.example.org"]
+        );
+        assert_eq!(
+            rule_hosts("Host(`a.example.org`, \"b.example.org\") || Host(`c.example.org`)"),
+            vec!["a.example.org", "b.example.org", "c.example.org"]
+        );
+        assert!(rule_hosts("HostSNI(`*`) || HostRegexp(`^.+\\.example\\.org$`)").is_empty());
+        assert!(rule_hosts("PathPrefix(`/`) && !Host(`a.example.org`)").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_traefik_records() {
+        let transport = MemoryTransport::new();
+        transport.route(
+            Method::Get,
+            "http://traefik:8080/api/http/routers",
+            200,
+            r#"[
+                {"name": "web@docker", "rule": "Host(`app.example.org`)",
+                 "status": "enabled", "entryPoints": ["websecure"]},
+                {"name": "old@file", "rule": "Host(`old.example.org`)",
+                 "status": "disabled", "entryPoints": ["websecure"]},
+                {"name": "dashboard@internal", "rule": "PathPrefix(`/api`)",
+                 "status": "enabled", "entryPoints": ["traefik"]}
+            ]"#,
+        );
+        let mut source = Traefik::with_transport("http://traefik:8080", Arc::new(transport));
+        source.types = vec![RecordType::A, RecordType::AAAA];
+
+        let records = source.records().await.unwrap();
+        assert_eq!(
+            records,
+            vec![
+                SourcedRecord::public("app.example.org", RecordType::A),
+                SourcedRecord::public("app.example.org", RecordType::AAAA),
+            ]
+        );
+
+        source.entry_points = vec!["web".to_string()];
+        assert!(source.records().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+use super::RecordSource;
+use super::SourcedRecord;
+use super::watch_file;
+use crate::error::Error;
+use crate::error::Result;
+use crate::record::RecordType;
+use crate::types::Param;
+use crate::types::parse_duration;
+use crate::zonefile::parse_bind;
+
+const DEFAULT_POLL: Duration = Duration::from_secs(10);
+
+// Records of a RFC 1035 zone file maintained by hand, read again at every
+// cycle. Records of types that cannot be synced are left out.
+pub struct ZoneFile {
+    file: PathBuf,
+    origin: Option<String>,
+    types: Vec<RecordType>,
+    poll: Duration,
+}
+
+impl ZoneFile {
+    // Params: `file` (required, relative to the config directory), `origin`
+    // completing relative names, the record `types` (A, AAAA and CNAME by
+    // default) and `poll`, how often daemons look for changes of the file
+    // (10s by default, 0 disables it)
+    pub fn new_with_args(args: Vec<Param>, base_dir: &Path) -> Result<Self> {
+        let mut file = None;
+        let mut origin = None;
+        let mut types = vec![RecordType::A, RecordType::AAAA, RecordType::CNAME];
+        let mut poll = DEFAULT_POLL;
+
+        for param in args {
+            match param.name.as_str() {
+                "file" => file = Some(base_dir.join(&param.value)),
+                "origin" => origin = Some(param.value),
+                "types" => {
+                    types = param
+                        .value
+                        .split(',')
+                        .map(|ty| {
+                            RecordType::parse(ty.trim()).ok_or(Error::ParseError(format!(
+                                "zonefile: invalid record type {}",
+                                ty
+                            )))
+                        })
+                        .collect::<Result<_>>()?
+                }
+                "poll" => poll = parse_duration(&param.value)?,
+                name => {
+                    return Err(Error::ParseError(format!(
+                        "zonefile: unknown param {}",
+                        name
+                    )));
+                }
+            }
+        }
+
+        Ok(Self {
+            file: file.ok_or(Error::ParseError("zonefile: file is required".to_string()))?,
+            origin,
+            types,
+            poll,
+        })
+    }
+}
+
+#[async_trait]
+impl RecordSource for ZoneFile {
+    async fn records(&self) -> Result<Vec<SourcedRecord>> {
+        let content = std::fs::read_to_string(&self.file)
+            .map_err(|e| Error::ParseError(format!("zonefile: {}: {}", self.file.display(), e)))?;
+        let entries = parse_bind(&content, self.origin.as_deref())
+            .map_err(|e| e.context(&self.file.display().to_string()))?;
+
+        let mut ret = vec![];
+        for entry in entries {
+            let Some(ty) = RecordType::parse(&entry.r#type) else {
+                continue;
+            };
+            if !self.types.contains(&ty) {
+                continue;
+            }
+            match entry.content() {
+                Ok(content) => {
+                    let mut record = SourcedRecord::public(&entry.name, ty);
+                    record.content = content;
+                    record.ttl = entry.ttl.into();
+                    ret.push(record);
+                }
+                Err(e) => log::warn!("zonefile: {}: {}, skipped", self.file.display(), e),
+            }
+        }
+        Ok(ret)
+    }
+
+    // Polls the modification time, an edit is picked up within `poll`
+    fn watch(&self, changed: Arc<Notify>) {
+        watch_file("zonefile", self.file.clone(), self.poll, changed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::record::RecordContent;
+    use crate::record::TTL;
+
+    const ZONE: &str = "$ORIGIN example.org.
+$TTL 3600
+@       IN SOA ns1 hostmaster 1 7200 3600 1209600 3600
+        IN NS  ns1
+@       IN A   192.0.2.1
+www 300 IN CNAME example.org.
+nas     IN AAAA 2001:db8::9
+mail    IN MX  10 mx.example.net.
+";
+
+    #[tokio::test]
+    async fn test_zonefile_records() {
+        let dir = std::env::temp_dir();
+        let name = format!("dns-syncer-zone-{}.db", std::process::id());
+        let path = dir.join(&name);
+        std::fs::write(&path, ZONE).unwrap();
+        let param = |n: &str, v: &str| Param::new(n.to_string(), v.to_string());
+
+        let mut source =
+            ZoneFile::new_with_args(vec![param("file", &name), param("poll", "50ms")], &dir)
+                .unwrap();
+        let records = source.records().await.unwrap();
+        let records = records
+            .iter()
+            .map(|r| (r.name.as_str(), r.content.clone(), r.ttl.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                (
+                    "example.org",
+                    RecordContent::A(Ipv4Addr::new(192, 0, 2, 1)),
+                    TTL::Value(3600)
+                ),
+                (
+                    "www.example.org",
+                    RecordContent::CNAME("example.org".to_string()),
+                    TTL::Value(300)
+                ),
+                (
+                    "nas.example.org",
+                    RecordContent::AAAA("2001:db8::9".parse().unwrap()),
+                    TTL::Value(3600)
+                ),
+            ]
+        );
+
+        source.types = vec![RecordType::AAAA];
+        assert_eq!(source.records().await.unwrap().len(), 1);
+
+        // An edit wakes the daemon
+        let changed = Arc::new(Notify::new());
+        source.watch(changed.clone());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(5), changed.notified())
+            .await
+            .unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(source.records().await.is_err());
+        assert!(ZoneFile::new_with_args(vec![], &dir).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+
+use chrono::DateTime;
+use chrono::TimeDelta;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::IdCache;
+use crate::provider::RecordOutcome;
+use crate::server::ApiRecord;
+use crate::types::PublicIp;
+
+pub const DEFAULT_HISTORY_SIZE: usize = 100;
+
+////////////////////////////////////////////////////////////
+// History
+////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderResult {
+    pub provider: String,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // API requests the sync made
+    #[serde(default)]
+    pub requests: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub records: Vec<RecordOutcome>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncCycle {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    #[serde(default)]
+    pub public_ip: Option<PublicIp>,
+    #[serde(default)]
+    pub providers: Vec<ProviderResult>,
+    // Failure before any provider was synced, e.g. the public ip fetch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl SyncCycle {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && self.providers.iter().all(|p| p.success)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpChange {
+    pub at: DateTime<Utc>,
+    pub old: Option<PublicIp>,
+    pub new: PublicIp,
+}
+
+// Latest outcome of a provider over the kept history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProviderStatus {
+    pub provider: String,
+    pub last_sync: DateTime<Utc>,
+    pub success: bool,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+////////////////////////////////////////////////////////////
+// State file
+////////////////////////////////////////////////////////////
+// What the daemon remembers between runs. Only the last `history_size` cycles
+// and ip changes are kept.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub public_ip: Option<PublicIp>,
+    #[serde(default)]
+    pub cycles: VecDeque<SyncCycle>,
+    #[serde(default)]
+    pub ip_changes: VecDeque<IpChange>,
+    // Ids resolved by every provider, by provider name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub ids: HashMap<String, IdCache>,
+    // Records added through the management API, when it persists them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_records: Vec<ApiRecord>,
+}
+
+impl State {
+    // A missing file is an empty state
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| Error::ParseError(format!("state file {}: {}", path.display(), e))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Written to a temporary file first so a crash never leaves half a state
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn push_cycle(&mut self, cycle: SyncCycle, history_size: usize) {
+        if let Some(ip) = cycle.public_ip.as_ref() {
+            self.update_public_ip(ip.clone(), cycle.started_at, history_size);
+        }
+        self.cycles.push_back(cycle);
+        while self.cycles.len() > history_size {
+            self.cycles.pop_front();
+        }
+    }
+
+    fn update_public_ip(&mut self, ip: PublicIp, at: DateTime<Utc>, history_size: usize) {
+        if self.public_ip.as_ref() == Some(&ip) {
+            return;
+        }
+        self.ip_changes.push_back(IpChange {
+            at,
+            old: self.public_ip.replace(ip.clone()),
+            new: ip,
+        });
+        while self.ip_changes.len() > history_size {
+            self.ip_changes.pop_front();
+        }
+    }
+
+    // Most recent cycles first
+    pub fn history(&self, limit: usize) -> Vec<&SyncCycle> {
+        self.cycles.iter().rev().take(limit).collect()
+    }
+
+    pub fn last_cycle(&self) -> Option<&SyncCycle> {
+        self.cycles.back()
+    }
+
+    // Per provider status, sorted by provider name
+    pub fn provider_status(&self) -> Vec<ProviderStatus> {
+        let mut ret: Vec<ProviderStatus> = vec![];
+
+        for cycle in self.cycles.iter() {
+            for result in cycle.providers.iter() {
+                let idx = match ret.iter().position(|s| s.provider == result.provider) {
+                    Some(idx) => idx,
+                    None => {
+                        ret.push(ProviderStatus {
+                            provider: result.provider.clone(),
+                            last_sync: cycle.finished_at,
+                            success: result.success,
+                            last_success: None,
+                            last_error: None,
+                            last_error_at: None,
+                        });
+                        ret.len() - 1
+                    }
+                };
+
+                let status = &mut ret[idx];
+                status.last_sync = cycle.finished_at;
+                status.success = result.success;
+                if result.success {
+                    status.last_success = Some(cycle.finished_at);
+                } else {
+                    status.last_error = result.error.clone();
+                    status.last_error_at = Some(cycle.finished_at);
+                }
+            }
+        }
+
+        ret.sort_by(|a, b| a.provider.cmp(&b.provider));
+        ret
+    }
+
+    // Mean time an address was held between two changes, the first address
+    // ever seen does not count as a change
+    pub fn average_ip_lifetime(&self) -> Option<TimeDelta> {
+        let changes = self
+            .ip_changes
+            .iter()
+            .filter(|c| c.old.is_some())
+            .collect::<Vec<_>>();
+        let first = self.ip_changes.front()?;
+        let last = changes.last()?;
+        Some((last.at - first.at) / changes.len() as i32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn cycle(ip: [u8; 4], success: bool) -> SyncCycle {
+        let ip = Ipv4Addr::from(ip);
+        SyncCycle {
+            started_at: Utc::now(),
+            finished_at: Utc::now(),
+            public_ip: Some(PublicIp::new(Some(ip), None)),
+            providers: vec![ProviderResult {
+                provider: "cloudflare-1".to_string(),
+                success,
+                error: (!success).then(|| "timeout".to_string()),
+                requests: 0,
+                records: vec![],
+            }],
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_state_history() {
+        let mut state = State::default();
+        state.push_cycle(cycle([1, 1, 1, 1], true), 3);
+        state.push_cycle(cycle([1, 1, 1, 1], false), 3);
+        state.push_cycle(cycle([2, 2, 2, 2], true), 3);
+        state.push_cycle(cycle([2, 2, 2, 2], true), 3);
+
+        assert_eq!(state.cycles.len(), 3);
+        assert!(!state.history(3)[2].is_success());
+        assert_eq!(state.ip_changes.len(), 2);
+        assert!(state.average_ip_lifetime().is_some());
+
+        let status = state.provider_status();
+        assert_eq!(status.len(), 1);
+        assert!(status[0].success);
+        assert!(status[0].last_success.is_some());
+        assert_eq!(status[0].last_error.as_deref(), Some("timeout"));
+        assert_eq!(state.ip_changes[0].old, None);
+        assert_eq!(
+            state.ip_changes[1].new,
+            PublicIp::new(Some(Ipv4Addr::new(2, 2, 2, 2)), None)
+        );
+    }
+
+    #[test]
+    fn test_state_load_save() {
+        let path =
+            std::env::temp_dir().join(format!("dns-syncer-state-{}.json", std::process::id()));
+        assert_eq!(State::load(&path).unwrap(), State::default());
+
+        let mut state = State::default();
+        state.push_cycle(cycle([1, 1, 1, 1], true), 10);
+        state.save(&path).unwrap();
+        assert_eq!(State::load(&path).unwrap(), state);
+        fs::remove_file(&path).unwrap();
+    }
+}
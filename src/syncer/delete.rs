@@ -0,0 +1,379 @@
+use serde::Serialize;
+
+use crate::config::Cfg;
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::Provider;
+use crate::record::ProviderRecord;
+use crate::record::RecordType;
+use crate::types::ZoneName;
+use crate::types::glob_match;
+
+use super::DnsSyncer;
+
+// Records of a provider zone written by dns-syncer that no config record
+// produces anymore
+#[derive(Debug, Clone, Serialize)]
+pub struct ZoneOrphans {
+    pub provider: String,
+    pub zone: String,
+    pub records: Vec<ProviderRecord>,
+    pub deleted: bool,
+    pub error: Option<String>,
+}
+
+// Owned records of `live` whose full name and type is not in `wanted`
+pub fn orphans(live: Vec<ProviderRecord>, wanted: &[(String, RecordType)]) -> Vec<ProviderRecord> {
+    live.into_iter()
+        .filter(|r| r.is_owned())
+        .filter(|r| {
+            let r#type = r.content.record_type();
+            !wanted
+                .iter()
+                .any(|(name, t)| name.eq_ignore_ascii_case(&r.name) && *t == r#type)
+        })
+        .collect()
+}
+
+impl DnsSyncer {
+    // Find the owned records no config record produces in the zones of the
+    // providers, and delete them when `apply` is set
+    pub async fn purge_orphans(
+        &self,
+        config: &Cfg,
+        only_provider: Option<&str>,
+        only_zone: Option<&str>,
+        apply: bool,
+    ) -> Result<Vec<ZoneOrphans>> {
+        let providers = self.configured(config, only_provider)?;
+        // Disabled and deselected records are still in the config, they are
+        // not orphans
+        let items = config.record_items()?;
+
+        let mut ret = vec![];
+        for cfg in providers {
+            let mut orphans = ZoneOrphans {
+                provider: cfg.name.clone(),
+                zone: "-".to_string(),
+                records: vec![],
+                deleted: false,
+                error: None,
+            };
+            let zones = match self.create(cfg) {
+                Ok(provider) => provider.list_zones().await.map(|zones| (provider, zones)),
+                Err(e) => Err(e),
+            };
+            let (provider, zones) = match zones {
+                Ok(v) => v,
+                Err(e) => {
+                    orphans.error = Some(e.to_string());
+                    ret.push(orphans);
+                    continue;
+                }
+            };
+
+            for zone in zones {
+                if only_zone.is_some_and(|z| dns_name(z) != dns_name(&zone)) {
+                    continue;
+                }
+                let wanted = items
+                    .iter()
+                    .flat_map(|item| {
+                        item.providers
+                            .iter()
+                            .filter(|p| {
+                                p.name == cfg.name && p.zones.iter().any(|z| glob_match(z, &zone))
+                            })
+                            .map(|_| (item.record.fqdn(&zone), item.record.content.record_type()))
+                    })
+                    .collect::<Vec<_>>();
+
+                let mut zone_orphans = ZoneOrphans {
+                    zone: zone.clone(),
+                    ..orphans.clone()
+                };
+                let found = match provider.list_zone_records(&zone).await {
+                    Ok(live) => self::orphans(live, &wanted),
+                    Err(e) => {
+                        zone_orphans.error = Some(e.to_string());
+                        ret.push(zone_orphans);
+                        continue;
+                    }
+                };
+                if found.is_empty() {
+                    continue;
+                }
+
+                if apply {
+                    match provider.delete_owned_records(&zone, &found).await {
+                        Ok(deleted) => {
+                            zone_orphans.records = deleted;
+                            zone_orphans.deleted = true;
+                        }
+                        Err(e) => {
+                            zone_orphans.records = found;
+                            zone_orphans.error = Some(e.to_string());
+                        }
+                    }
+                } else {
+                    zone_orphans.records = found;
+                }
+                ret.push(zone_orphans);
+            }
+        }
+        Ok(ret)
+    }
+
+    // Delete `record` of `provider`, in `zone` or the zone of the provider
+    // holding it, and return the deleted records. Records still in the config
+    // are created again by the next sync, which is only warned about.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn delete_record(
+        &self,
+        config: &Cfg,
+        provider: &dyn Provider,
+        provider_name: &str,
+        zone: Option<&str>,
+        record: &str,
+        record_type: Option<RecordType>,
+        force: bool,
+    ) -> Result<Vec<ProviderRecord>> {
+        let record = dns_name(record);
+        let zone = match zone {
+            Some(zone) => zone.to_string(),
+            None => zone_of(&record, provider.list_zones().await?).ok_or(Error::Provider(
+                format!("no zone of provider {} holds {}", provider_name, record),
+            ))?,
+        };
+
+        let in_config = config.record_items()?.iter().any(|item| {
+            item.providers.iter().any(|p| {
+                p.name == provider_name
+                    && p.zones
+                        .iter()
+                        .any(|z| dns_name(&item.record.fqdn(z)) == record)
+            })
+        });
+        if in_config {
+            log::warn!(
+                "{} is still in the config, the next sync creates it again",
+                record
+            );
+        }
+
+        match record_type {
+            Some(record_type) => {
+                provider
+                    .delete_record(&zone, &record, record_type, force)
+                    .await
+            }
+            None => provider.delete_records(&zone, &record, force).await,
+        }
+    }
+}
+
+// A name as given by a user, lowercase and without the trailing dot of an
+// absolute name
+pub fn dns_name(name: &str) -> String {
+    let name = name.to_ascii_lowercase();
+    name.strip_suffix('.').map(String::from).unwrap_or(name)
+}
+
+// The zone of `zones` holding `record`, the longest one when zones nest
+pub fn zone_of(record: &str, zones: Vec<ZoneName>) -> Option<ZoneName> {
+    let record = dns_name(record);
+    zones
+        .into_iter()
+        .filter(|z| {
+            let zone = dns_name(z);
+            record == zone || record.ends_with(&format!(".{}", zone))
+        })
+        .max_by_key(|z| z.len())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::provider::AuthParams;
+    use crate::provider::MockProvider;
+    use crate::provider::ParamList;
+    use crate::provider::ProviderRegistry;
+    use crate::record::RecordContent;
+    use crate::record::RecordOp;
+    use crate::record::TTL;
+
+    fn record(name: &str, content: RecordContent, comment: Option<&str>) -> ProviderRecord {
+        ProviderRecord {
+            name: name.to_string(),
+            content,
+            comment: comment.map(String::from),
+            op: RecordOp::default(),
+            ttl: TTL::Auto,
+            params: vec![],
+        }
+    }
+
+    // Syncer whose mock provider serves `mock` to every config entry of type
+    // mock
+    fn mock_syncer(mock: &MockProvider) -> DnsSyncer {
+        let mock = mock.clone();
+        let mut registry = ProviderRegistry::empty();
+        registry.register(
+            "mock",
+            move |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                Ok(Box::new(mock.clone()))
+            },
+        );
+        DnsSyncer::with_registry(registry)
+    }
+
+    fn config() -> Cfg {
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+providers:
+  - name: mock-1
+    type: mock
+records:
+  - type: A
+    name: home
+    providers:
+      - name: mock-1
+        zones: [example.org]
+"#;
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_orphans() {
+        let a = RecordContent::A(Ipv4Addr::new(1, 2, 3, 4));
+        let live = vec![
+            record("home.example.org", a.clone(), Some("[dns-syncer]")),
+            record("old.example.org", a.clone(), Some("router [dns-syncer]")),
+            record("manual.example.org", a.clone(), Some("by hand")),
+            record(
+                "home.example.org",
+                RecordContent::CNAME("x.example.org".to_string()),
+                Some("[dns-syncer]"),
+            ),
+        ];
+        let wanted = vec![("Home.example.org".to_string(), RecordType::A)];
+
+        let names = orphans(live, &wanted)
+            .into_iter()
+            .map(|r| format!("{} {}", r.name, r.content.record_type().as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["old.example.org A", "home.example.org CNAME"]);
+    }
+
+    #[tokio::test]
+    async fn test_purge_orphans() {
+        let a = RecordContent::A(Ipv4Addr::new(1, 2, 3, 4));
+        let zones = [(
+            "example.org".to_string(),
+            vec![
+                record("home.example.org", a.clone(), Some("[dns-syncer]")),
+                record("old.example.org", a.clone(), Some("[dns-syncer]")),
+                record("manual.example.org", a.clone(), None),
+            ],
+        )];
+        let mock = MockProvider::with_zones(zones.into());
+        let syncer = mock_syncer(&mock);
+        let config = config();
+
+        let purged = syncer
+            .purge_orphans(&config, None, None, false)
+            .await
+            .unwrap();
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].records[0].name, "old.example.org");
+        assert!(!purged[0].deleted);
+        assert_eq!(mock.records()["example.org"].len(), 3);
+
+        let purged = syncer
+            .purge_orphans(&config, None, Some("Example.org."), true)
+            .await
+            .unwrap();
+        assert!(purged[0].deleted);
+        let names = mock.records()["example.org"]
+            .iter()
+            .map(|r| r.name.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["home.example.org", "manual.example.org"]);
+
+        let purged = syncer.purge_orphans(&config, Some("other"), None, true);
+        assert!(matches!(purged.await, Err(Error::ParseError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_delete_record() {
+        let a = RecordContent::A(Ipv4Addr::new(1, 2, 3, 4));
+        let zones = [(
+            "example.org".to_string(),
+            vec![
+                record("home.example.org", a.clone(), Some("[dns-syncer]")),
+                record("manual.example.org", a.clone(), None),
+            ],
+        )];
+        let mock = MockProvider::with_zones(zones.into());
+        let syncer = mock_syncer(&mock);
+        let config = config();
+        let provider = syncer.provider(&config, "mock-1").unwrap();
+        let delete = |record: &'static str, force: bool| {
+            syncer.delete_record(
+                &config,
+                provider.as_ref(),
+                "mock-1",
+                None,
+                record,
+                None,
+                force,
+            )
+        };
+
+        // Records without the owner marker are only deleted when forced
+        assert!(
+            delete("manual.example.org", false)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(delete("Manual.Example.org.", true).await.unwrap().len(), 1);
+        assert_eq!(delete("HOME.example.org", false).await.unwrap().len(), 1);
+        assert!(mock.records()["example.org"].is_empty());
+
+        assert!(matches!(
+            delete("home.example.com", false).await,
+            Err(Error::Provider(_))
+        ));
+        assert!(matches!(
+            syncer.provider(&config, "other"),
+            Err(Error::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_zone_of() {
+        let zones = || vec!["example.org".to_string(), "lab.example.org".to_string()];
+        assert_eq!(zone_of("www.example.org", zones()).unwrap(), "example.org");
+        assert_eq!(
+            zone_of("nas.lab.example.org", zones()).unwrap(),
+            "lab.example.org"
+        );
+        assert_eq!(zone_of("example.org", zones()).unwrap(), "example.org");
+
+        // Names are case-insensitive, an absolute name ends with a dot
+        assert_eq!(zone_of("WWW.Example.org", zones()).unwrap(), "example.org");
+        assert_eq!(zone_of("www.example.org.", zones()).unwrap(), "example.org");
+        assert_eq!(dns_name("WWW.Example.org."), "www.example.org");
+
+        assert_eq!(zone_of("www.example.com", zones()), None);
+        assert_eq!(zone_of("wwwexample.org", zones()), None);
+    }
+}
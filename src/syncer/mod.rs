@@ -0,0 +1,188 @@
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Cfg;
+use crate::config::CfgProvider;
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::PlanOp;
+use crate::provider::PlannedChange;
+use crate::provider::Provider;
+use crate::provider::ProviderRegistry;
+use crate::provider::ZoneRecords;
+use crate::provider::desired_names;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordError;
+use crate::record::TTL;
+use crate::runner::Runner;
+use crate::types::PublicIp;
+
+mod delete;
+mod records;
+mod zones;
+pub use delete::*;
+pub use records::*;
+pub use zones::*;
+
+// The shortest way to keep records up to date from another program, like a
+// VPN manager publishing the address of its tunnel. `Runner` gives control
+// over cycles, fetchers and observers.
+pub struct DnsSyncer {
+    registry: ProviderRegistry,
+}
+
+impl DnsSyncer {
+    // Syncer with the builtin provider types
+    pub fn new() -> Self {
+        Self::with_registry(ProviderRegistry::new())
+    }
+
+    // Syncer creating the providers of configs with `registry`
+    pub fn with_registry(registry: ProviderRegistry) -> Self {
+        Self { registry }
+    }
+
+    // Make `provider` serve `content` under `name` of `zone`, and nothing else
+    // under that name. The record is only written when it differs, the change
+    // tells whether it was created, updated or already there.
+    pub async fn ensure_record(
+        &self,
+        provider: &dyn Provider,
+        zone: &str,
+        name: &str,
+        content: RecordContent,
+        ttl: TTL,
+    ) -> Result<PlannedChange> {
+        if content.is_unassigned() {
+            return Err(Error::InvalidRecord {
+                record: name.to_string(),
+                reason: RecordError::MissingContent(content.record_type().as_str().to_string()),
+            });
+        }
+        let record = ProviderRecord::builder()
+            .name(name)
+            .value(content)
+            .ttl(ttl)
+            .build()?;
+        let zone = zone.to_string();
+        let desired = ZoneRecords {
+            records: vec![record],
+        }
+        .desired(&zone, &PublicIp::new(None, None));
+
+        let existing = provider.list_named(&zone, &desired_names(&desired)).await?;
+        let changes = provider.plan(&zone, &desired, &existing);
+        let planned = changes.planned().remove(0);
+        if planned.op != PlanOp::Unchanged {
+            provider.apply(&changes, &CancellationToken::new()).await?;
+        }
+        Ok(planned)
+    }
+
+    // One sync cycle of every enabled record of `config`, the way
+    // `dns-syncer sync --once` does it
+    pub async fn sync_once(&self, config: &Cfg) -> Result<()> {
+        let records = config
+            .record_items()?
+            .into_iter()
+            .filter(|r| r.is_selected(&[], &[]))
+            .collect();
+        let mut runner = Runner::new(config.clone(), records, &self.registry)?;
+        runner.run(&CancellationToken::new()).await
+    }
+
+    // Provider `name` of `config`, with the HTTP settings of the config
+    // installed
+    pub fn provider(&self, config: &Cfg, name: &str) -> Result<Box<dyn Provider>> {
+        let cfg = self.configured(config, Some(name))?.remove(0);
+        self.create(cfg)
+    }
+
+    // Create a configured provider whether or not a record uses it
+    fn create(&self, provider: &CfgProvider) -> Result<Box<dyn Provider>> {
+        self.registry.create(&provider.provider_config())
+    }
+
+    // Configured providers, only `only` when given
+    fn configured<'a>(&self, config: &'a Cfg, only: Option<&str>) -> Result<Vec<&'a CfgProvider>> {
+        if let Some(name) = only
+            && !config.providers.iter().any(|p| p.name == name)
+        {
+            return Err(Error::ParseError(format!(
+                "provider {} is not configured",
+                name
+            )));
+        }
+        install_http(config)?;
+        Ok(config
+            .providers
+            .iter()
+            .filter(|p| only.is_none_or(|name| name == p.name))
+            .collect())
+    }
+}
+
+fn install_http(config: &Cfg) -> Result<()> {
+    config
+        .http
+        .clone()
+        .into_http_config(&config.base_dir)
+        .install()
+}
+
+impl Default for DnsSyncer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::provider::MockProvider;
+
+    #[tokio::test]
+    async fn test_ensure_record() {
+        let syncer = DnsSyncer::new();
+        let provider = MockProvider::new();
+        let ip = Ipv4Addr::new(192, 0, 2, 1);
+
+        let change = syncer
+            .ensure_record(
+                &provider,
+                "example.org",
+                "vpn",
+                RecordContent::A(ip),
+                TTL::Auto,
+            )
+            .await
+            .unwrap();
+        assert_eq!(change.op, PlanOp::Create);
+        assert_eq!(change.name, "vpn.example.org");
+        let records = provider.records()["example.org"].clone();
+        assert_eq!(records[0].content, RecordContent::A(ip));
+        assert!(records[0].is_owned());
+
+        let change = syncer
+            .ensure_record(
+                &provider,
+                "example.org",
+                "vpn",
+                RecordContent::A(ip),
+                TTL::Auto,
+            )
+            .await
+            .unwrap();
+        assert_eq!(change.op, PlanOp::Unchanged);
+
+        let unassigned = RecordContent::Unassigned(crate::record::RecordType::A);
+        assert!(matches!(
+            syncer
+                .ensure_record(&provider, "example.org", "vpn", unassigned, TTL::Auto)
+                .await,
+            Err(Error::InvalidRecord { .. })
+        ));
+    }
+}
@@ -0,0 +1,387 @@
+use std::net::SocketAddr;
+
+use serde::Serialize;
+
+use crate::config::Cfg;
+use crate::config::CfgRecordItem;
+use crate::error::Result;
+use crate::provider::BackendRecords;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordType;
+use crate::runner::Runner;
+use crate::runner::resolve_zones;
+use crate::runner::to_provider_backends;
+use crate::state::State;
+use crate::types::PublicIp;
+use crate::verify::DEFAULT_TIMEOUT;
+use crate::verify::RecordCheck;
+use crate::verify::check_record;
+
+use super::DnsSyncer;
+
+// Desired content of a record next to what the provider serves
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordState {
+    pub provider: String,
+    pub zone: String,
+    pub name: String,
+    pub r#type: String,
+    pub desired: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub live: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub live_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_sync: Option<bool>,
+}
+
+impl RecordState {
+    // `live` is left out when providers are not queried
+    pub fn new(
+        provider: &str,
+        zone: &str,
+        name: String,
+        content: &RecordContent,
+        public_ip: Option<&PublicIp>,
+        live: Option<std::result::Result<Vec<String>, String>>,
+    ) -> Self {
+        let desired = desired_content(content, public_ip);
+        let (live, live_error) = match live {
+            Some(Ok(live)) => (Some(live), None),
+            Some(Err(e)) => (None, Some(e)),
+            None => (None, None),
+        };
+        // Not known while the public address has never been fetched
+        let in_sync = match (desired.as_ref(), live.as_ref()) {
+            (Some(desired), Some(live)) => Some(live.len() == 1 && live[0] == *desired),
+            _ => None,
+        };
+
+        Self {
+            provider: provider.to_string(),
+            zone: zone.to_string(),
+            name,
+            r#type: content.record_type().as_str().to_string(),
+            desired: desired.unwrap_or(content.to_string()),
+            live,
+            live_error,
+            in_sync,
+        }
+    }
+}
+
+// Records following the public address take the last one in the state
+fn desired_content(content: &RecordContent, public_ip: Option<&PublicIp>) -> Option<String> {
+    let (v4, v6) = public_ip.map(|ip| ip.ips()).unwrap_or_default();
+    match content {
+        RecordContent::Unassigned(RecordType::A) => v4.map(|ip| ip.to_string()),
+        RecordContent::Unassigned(RecordType::AAAA) => v6.map(|ip| ip.to_string()),
+        RecordContent::Unassigned(_) | RecordContent::Unknown => None,
+        content => Some(content.to_string()),
+    }
+}
+
+// `records` of the config as providers should serve them, with the public
+// address of the state file, only those of `provider` and `zone` when given.
+// Zone patterns cannot be expanded without asking the provider, records under
+// them are skipped.
+pub fn desired_records(
+    config: &Cfg,
+    records: Vec<CfgRecordItem>,
+    provider: Option<&str>,
+    zone: Option<&str>,
+) -> Result<Vec<ProviderRecord>> {
+    let state = match config.state_file() {
+        Some(path) => State::load(&path)?,
+        None => State::default(),
+    };
+
+    let mut ret = vec![];
+    let backends = to_provider_backends(records)?;
+    for (provider_name, backend) in backends.iter() {
+        if provider.is_some_and(|p| p != provider_name) {
+            continue;
+        }
+        let mut records = (*backend.record).clone();
+        records.zones.retain(|z, _| {
+            if z.contains('*') {
+                log::warn!("zone pattern {} cannot be expanded offline, skipped", z);
+                return false;
+            }
+            zone.is_none_or(|zone| zone == z)
+        });
+        ret.extend(assigned_records(&records, state.public_ip.as_ref()));
+    }
+    Ok(ret)
+}
+
+// Names are completed with their zone and records taking the public address
+// get `public_ip`, records left without content are skipped
+fn assigned_records(records: &BackendRecords, public_ip: Option<&PublicIp>) -> Vec<ProviderRecord> {
+    let (v4, v6) = public_ip.map(|ip| ip.ips()).unwrap_or((None, None));
+
+    let mut ret = vec![];
+    for (zone, zone_records) in records.zones.iter() {
+        for record in zone_records.records.iter() {
+            let mut record = record.clone();
+            record.name = record.fqdn(zone);
+            if record.assign_public_ip_if_unassigned(v4, v6).is_err() {
+                log::warn!(
+                    "{} takes the public address which is not known yet, skipped",
+                    record.name
+                );
+                continue;
+            }
+            ret.push(record);
+        }
+    }
+    ret
+}
+
+// Every desired record of `records` is asked at every resolver
+pub async fn verify_records(
+    config: &Cfg,
+    records: Vec<CfgRecordItem>,
+    resolvers: &[SocketAddr],
+) -> Result<Vec<RecordCheck>> {
+    let mut records = desired_records(config, records, None, None)?;
+    records.sort_by(|a, b| a.name.cmp(&b.name));
+    records.dedup_by(|a, b| a.name == b.name && a.content == b.content);
+
+    let mut ret = vec![];
+    for record in records.iter() {
+        for resolver in resolvers.iter() {
+            ret.push(check_record(*resolver, record, DEFAULT_TIMEOUT).await);
+        }
+    }
+    Ok(ret)
+}
+
+impl DnsSyncer {
+    // Desired content of `records` next to what their providers serve, which
+    // are only asked with `live`. Records following the public address take
+    // `public_ip`.
+    pub async fn record_status(
+        &self,
+        config: &Cfg,
+        records: Vec<CfgRecordItem>,
+        public_ip: Option<&PublicIp>,
+        live: bool,
+    ) -> Result<Vec<RecordState>> {
+        let (backends, providers) = if live {
+            let runner = Runner::new(config.clone(), records, &self.registry)?;
+            (runner.backends().clone(), runner.providers().clone())
+        } else {
+            (to_provider_backends(records)?, Default::default())
+        };
+
+        let mut ret = vec![];
+        for (provider_name, backend) in backends.iter() {
+            let provider = providers.get(provider_name);
+            let records = match provider {
+                Some(provider) => resolve_zones(provider.as_ref(), &backend.record)
+                    .await
+                    .unwrap_or_else(|_| backend.record.clone()),
+                None => backend.record.clone(),
+            };
+
+            for (zone, zone_records) in records.zones.iter() {
+                for record in zone_records.records.iter() {
+                    let name = record.fqdn(zone);
+                    let live = match provider {
+                        Some(provider) => Some(
+                            provider
+                                .list_records(zone, &name)
+                                .await
+                                .map(|live| {
+                                    live.into_iter()
+                                        .filter(|r| {
+                                            r.content.record_type() == record.content.record_type()
+                                        })
+                                        .map(|r| r.content.to_string())
+                                        .collect()
+                                })
+                                .map_err(|e| e.to_string()),
+                        ),
+                        None => None,
+                    };
+                    ret.push(RecordState::new(
+                        provider_name,
+                        zone,
+                        name,
+                        &record.content,
+                        public_ip,
+                        live,
+                    ));
+                }
+            }
+        }
+
+        ret.sort_by(|a, b| (&a.provider, &a.name).cmp(&(&b.provider, &b.name)));
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::provider::AuthParams;
+    use crate::provider::MockProvider;
+    use crate::provider::ParamList;
+    use crate::provider::Provider;
+    use crate::provider::ProviderRegistry;
+    use crate::provider::ZoneRecords;
+    use crate::record::RecordOp;
+    use crate::record::TTL;
+
+    fn record(name: &str, content: RecordContent) -> ProviderRecord {
+        ProviderRecord {
+            name: name.to_string(),
+            content,
+            comment: None,
+            op: RecordOp::Create,
+            ttl: TTL::Auto,
+            params: vec![],
+        }
+    }
+
+    fn config() -> Cfg {
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+providers:
+  - name: mock-1
+    type: mock
+records:
+  - type: A
+    name: home
+    providers:
+      - name: mock-1
+        zones: [example.org]
+  - type: CNAME
+    name: www
+    content: home.example.org
+    providers:
+      - name: mock-1
+        zones: [example.org, "*.example.net"]
+"#;
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_record_state() {
+        let ip = PublicIp::new(Some(Ipv4Addr::new(1, 2, 3, 4)), None);
+        let content = RecordContent::Unassigned(RecordType::A);
+
+        let status = RecordState::new(
+            "cloudflare-1",
+            "example.com",
+            "home.example.com".to_string(),
+            &content,
+            Some(&ip),
+            Some(Ok(vec!["1.2.3.4".to_string()])),
+        );
+        assert_eq!(status.desired, "1.2.3.4");
+        assert_eq!(status.in_sync, Some(true));
+
+        let status = RecordState::new(
+            "cloudflare-1",
+            "example.com",
+            "home.example.com".to_string(),
+            &content,
+            None,
+            Some(Ok(vec!["1.2.3.4".to_string()])),
+        );
+        assert_eq!(status.desired, "<public A address>");
+        assert_eq!(status.in_sync, None);
+    }
+
+    #[test]
+    fn test_assigned_records() {
+        let mut records = BackendRecords::default();
+        records.zones.insert(
+            "example.org".to_string(),
+            ZoneRecords {
+                records: vec![
+                    record("home", RecordContent::Unassigned(RecordType::A)),
+                    record("www", RecordContent::CNAME("home.example.org".to_string())),
+                ],
+            },
+        );
+
+        let desired = assigned_records(&records, None);
+        assert_eq!(desired.len(), 1);
+        assert_eq!(desired[0].name, "www.example.org");
+
+        let public_ip = PublicIp::new(Some(Ipv4Addr::new(1, 2, 3, 4)), None);
+        let desired = assigned_records(&records, Some(&public_ip));
+        assert_eq!(desired.len(), 2);
+        assert_eq!(desired[0].name, "home.example.org");
+        assert_eq!(
+            desired[0].content,
+            RecordContent::A(Ipv4Addr::new(1, 2, 3, 4))
+        );
+    }
+
+    #[test]
+    fn test_desired_records() {
+        // Without a state file the public address is not known, zone patterns
+        // are skipped
+        let config = config();
+        let records = config.record_items().unwrap();
+        let desired = desired_records(&config, records, None, None).unwrap();
+        let names = desired.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["www.example.org"]);
+
+        let records = config.record_items().unwrap();
+        let desired = desired_records(&config, records, Some("other"), None).unwrap();
+        assert!(desired.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_status() {
+        let ip = Ipv4Addr::new(192, 0, 2, 1);
+        let zones = [(
+            "example.org".to_string(),
+            vec![record("home.example.org", RecordContent::A(ip))],
+        )];
+        let mock = MockProvider::with_zones(zones.into());
+        let mut registry = ProviderRegistry::empty();
+        registry.register(
+            "mock",
+            move |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                Ok(Box::new(mock.clone()))
+            },
+        );
+        let syncer = DnsSyncer::with_registry(registry);
+        let config = config();
+        let public_ip = PublicIp::new(Some(ip), None);
+
+        let records = config.record_items().unwrap();
+        let status = syncer
+            .record_status(&config, records, Some(&public_ip), false)
+            .await
+            .unwrap();
+        assert_eq!(status[0].name, "home.example.org");
+        assert_eq!(status[0].desired, "192.0.2.1");
+        assert_eq!(status[0].live, None);
+
+        let records = config.record_items().unwrap();
+        let status = syncer
+            .record_status(&config, records, Some(&public_ip), true)
+            .await
+            .unwrap();
+        let home = status.iter().find(|s| s.name == "home.example.org");
+        assert_eq!(home.unwrap().in_sync, Some(true));
+        let www = status.iter().find(|s| s.name == "www.example.org");
+        assert_eq!(www.unwrap().live, Some(vec![]));
+        assert_eq!(www.unwrap().in_sync, Some(false));
+    }
+}
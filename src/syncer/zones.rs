@@ -0,0 +1,213 @@
+use serde::Serialize;
+
+use crate::config::Cfg;
+use crate::config::CfgRecordItem;
+use crate::error::Result;
+use crate::provider::CredentialCheck;
+use crate::provider::ZoneInfo;
+use crate::runner::resolve_zones;
+use crate::runner::to_provider_backends;
+
+use super::DnsSyncer;
+
+// Zones a configured provider can access, next to the zones its records need
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderZones {
+    pub provider: String,
+    pub zones: Vec<ZoneInfo>,
+    // Zones of the config the credentials cannot access
+    pub inaccessible: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl ProviderZones {
+    pub fn new(provider: &str, result: Result<Vec<ZoneInfo>>, configured: Vec<String>) -> Self {
+        match result {
+            Ok(zones) => {
+                let inaccessible = configured
+                    .into_iter()
+                    .filter(|c| !zones.iter().any(|z| z.name == *c))
+                    .collect();
+                Self {
+                    provider: provider.to_string(),
+                    zones,
+                    inaccessible,
+                    error: None,
+                }
+            }
+            Err(e) => Self {
+                provider: provider.to_string(),
+                zones: vec![],
+                inaccessible: vec![],
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+// Credential check of a configured provider on the zones its records use
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderCredentials {
+    pub provider: String,
+    pub check: Option<CredentialCheck>,
+    pub error: Option<String>,
+}
+
+impl ProviderCredentials {
+    pub fn new(provider: &str, result: Result<CredentialCheck>) -> Self {
+        let (check, error) = match result {
+            Ok(check) => (Some(check), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Self {
+            provider: provider.to_string(),
+            check,
+            error,
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.check.as_ref().is_some_and(|c| c.is_ok())
+    }
+}
+
+impl DnsSyncer {
+    // Zones of every configured provider, or only `only`, next to the zones
+    // `records` need. Providers failing to list their zones are reported next
+    // to the others.
+    pub async fn list_zones(
+        &self,
+        config: &Cfg,
+        records: Vec<CfgRecordItem>,
+        only: Option<&str>,
+    ) -> Result<Vec<ProviderZones>> {
+        let providers = self.configured(config, only)?;
+        let backends = to_provider_backends(records)?;
+
+        let mut ret = vec![];
+        for cfg in providers {
+            let configured = backends
+                .get(&cfg.name)
+                .map(|b| {
+                    b.record
+                        .zones
+                        .keys()
+                        .filter(|z| !z.contains('*'))
+                        .cloned()
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            let zones = match self.create(cfg) {
+                Ok(provider) => provider.list_zone_info().await,
+                Err(e) => Err(e),
+            };
+            ret.push(ProviderZones::new(&cfg.name, zones, configured));
+        }
+        Ok(ret)
+    }
+
+    // Check the credentials of every configured provider, or only `only`, on
+    // the zones `records` use. Wildcard zones are checked on every zone they
+    // match.
+    pub async fn check_credentials(
+        &self,
+        config: &Cfg,
+        records: Vec<CfgRecordItem>,
+        only: Option<&str>,
+    ) -> Result<Vec<ProviderCredentials>> {
+        let providers = self.configured(config, only)?;
+        let backends = to_provider_backends(records)?;
+
+        let mut ret = vec![];
+        for cfg in providers {
+            let records = backends
+                .get(&cfg.name)
+                .map(|b| b.record.clone())
+                .unwrap_or_default();
+            let check = match self.create(cfg) {
+                Ok(provider) => match resolve_zones(provider.as_ref(), &records).await {
+                    Ok(records) => {
+                        let mut zones = records.zones.keys().cloned().collect::<Vec<_>>();
+                        zones.sort();
+                        provider.check_credentials(&zones).await
+                    }
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            ret.push(ProviderCredentials::new(&cfg.name, check));
+        }
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Error;
+    use crate::provider::AuthParams;
+    use crate::provider::MockProvider;
+    use crate::provider::ParamList;
+    use crate::provider::Provider;
+    use crate::provider::ProviderRegistry;
+
+    #[test]
+    fn test_inaccessible() {
+        let zones = vec![ZoneInfo {
+            name: "example.org".to_string(),
+            ..Default::default()
+        }];
+        let configured = vec!["example.org".to_string(), "example.net".to_string()];
+        let p = ProviderZones::new("cf", Ok(zones), configured);
+        assert_eq!(p.inaccessible, vec!["example.net"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_zones() {
+        let yaml = r#"
+check_interval: 0
+public_ip_fecher: static
+fetchers:
+  - name: static
+    type: http_fetcher
+    params: []
+providers:
+  - name: mock-1
+    type: mock
+  - name: gandi-1
+    type: gandi
+records:
+  - type: A
+    name: home
+    providers:
+      - name: mock-1
+        zones: [example.org, example.net]
+"#;
+        let config: Cfg = serde_yaml::from_str(yaml).unwrap();
+        let mut registry = ProviderRegistry::empty();
+        registry.register(
+            "mock",
+            |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+                let zones = [("example.org".to_string(), vec![])].into();
+                Ok(Box::new(MockProvider::with_zones(zones)))
+            },
+        );
+        let syncer = DnsSyncer::with_registry(registry);
+        let records = config.record_items().unwrap();
+
+        // A provider the registry cannot create is reported, not an error
+        let zones = syncer.list_zones(&config, records, None).await.unwrap();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[0].zones[0].name, "example.org");
+        assert_eq!(zones[0].inaccessible, vec!["example.net"]);
+        let error = zones[1].error.as_deref().unwrap();
+        assert!(error.contains("unknown type gandi"), "{}", error);
+
+        let records = config.record_items().unwrap();
+        let zones = syncer.list_zones(&config, records, Some("mock-1")).await;
+        assert_eq!(zones.unwrap().len(), 1);
+        let records = config.record_items().unwrap();
+        let zones = syncer.list_zones(&config, records, Some("other")).await;
+        assert!(matches!(zones, Err(Error::ParseError(_))));
+    }
+}
@@ -0,0 +1,386 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use serde_json::json;
+use wiremock::Mock;
+use wiremock::MockServer;
+use wiremock::Respond;
+use wiremock::ResponseTemplate;
+use wiremock::matchers;
+
+use crate::error::Result;
+use crate::provider::Auth;
+use crate::provider::AuthParams;
+use crate::provider::Cloudflare;
+use crate::provider::ParamList;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::record::ProviderParam;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::TTL;
+use crate::wrapper::http;
+
+const CLOUDFLARE_API: &str = "https://api.cloudflare.com";
+
+// A record as the Cloudflare API shows it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SimRecord {
+    #[serde(default)]
+    id: String,
+    name: String,
+    #[serde(flatten)]
+    content: RecordContent,
+    #[serde(default)]
+    proxied: bool,
+    #[serde(default = "auto_ttl")]
+    ttl: u32,
+    #[serde(default)]
+    comment: Option<String>,
+}
+
+fn auto_ttl() -> u32 {
+    1
+}
+
+impl From<SimRecord> for ProviderRecord {
+    fn from(record: SimRecord) -> Self {
+        Self {
+            name: record.name,
+            content: record.content,
+            comment: record.comment,
+            op: RecordOp::default(),
+            ttl: match record.ttl {
+                1 => TTL::Auto,
+                v => TTL::Value(v),
+            },
+            params: vec![ProviderParam {
+                name: "proxied".to_string(),
+                value: record.proxied.to_string(),
+            }],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SimZone {
+    id: String,
+    name: String,
+    records: Vec<SimRecord>,
+}
+
+#[derive(Debug, Default)]
+struct SimState {
+    zones: Vec<SimZone>,
+    next_id: usize,
+    // Batch requests applied so far
+    batches: usize,
+}
+
+impl SimState {
+    fn new_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    fn zone_mut(&mut self, id: &str) -> Option<&mut SimZone> {
+        self.zones.iter_mut().find(|z| z.id == id)
+    }
+}
+
+fn success(result: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "success": true,
+        "errors": [],
+        "result": result,
+        "result_info": { "page": 1, "total_pages": 1 },
+    }))
+}
+
+fn failure(status: u16, code: i64, message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_json(json!({
+        "success": false,
+        "errors": [{ "code": code, "message": message }],
+        "result": null,
+    }))
+}
+
+fn zone_json(zone: &SimZone) -> Value {
+    json!({
+        "id": zone.id,
+        "name": zone.name,
+        "status": "active",
+        "plan": { "name": "Free Website" },
+    })
+}
+
+// Answers the zone, record and token endpoints the Cloudflare provider uses
+struct CloudflareApi {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl CloudflareApi {
+    fn batch(&self, zone_id: &str, body: &[u8]) -> ResponseTemplate {
+        #[derive(Deserialize)]
+        struct Delete {
+            id: String,
+        }
+        #[derive(Deserialize)]
+        struct Batch {
+            #[serde(default)]
+            deletes: Option<Vec<Delete>>,
+            #[serde(default)]
+            patches: Option<Vec<SimRecord>>,
+            #[serde(default)]
+            posts: Option<Vec<SimRecord>>,
+        }
+
+        let batch: Batch = match serde_json::from_slice(body) {
+            Ok(batch) => batch,
+            Err(e) => return failure(400, 9207, &format!("invalid batch: {}", e)),
+        };
+        let mut state = self.state.lock().unwrap();
+        state.batches += 1;
+        let mut ids = vec![];
+        for _ in batch.posts.iter().flatten() {
+            ids.push(state.new_id("r"));
+        }
+        let Some(zone) = state.zone_mut(zone_id) else {
+            return failure(
+                404,
+                7003,
+                "Could not route to /zones, perhaps your object identifier is invalid?",
+            );
+        };
+
+        // Like Cloudflare, a batch deleting a record twice is refused whole
+        let deletes = batch.deletes.iter().flatten().collect::<Vec<_>>();
+        for (i, delete) in deletes.iter().enumerate() {
+            if !zone.records.iter().any(|r| r.id == delete.id) {
+                return failure(404, 81044, "Record does not exist.");
+            }
+            if deletes[..i].iter().any(|d| d.id == delete.id) {
+                return failure(400, 81058, "A record can only be deleted once per batch.");
+            }
+        }
+        let deleted = batch.deletes.unwrap_or_default();
+        zone.records
+            .retain(|r| !deleted.iter().any(|d| d.id == r.id));
+        for patch in batch.patches.unwrap_or_default() {
+            match zone.records.iter_mut().find(|r| r.id == patch.id) {
+                Some(record) => *record = patch,
+                None => return failure(404, 81044, "Record does not exist."),
+            }
+        }
+        for (mut post, id) in batch.posts.unwrap_or_default().into_iter().zip(ids) {
+            post.id = id;
+            zone.records.push(post);
+        }
+        success(json!({}))
+    }
+}
+
+impl Respond for CloudflareApi {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let query = |name: &str| {
+            request
+                .url
+                .query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.to_string())
+        };
+        let segments = request
+            .url
+            .path()
+            .trim_start_matches("/client/v4/")
+            .split('/')
+            .collect::<Vec<_>>();
+
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["user", "tokens", "verify"]) => {
+                success(json!({ "id": "simulator", "status": "active" }))
+            }
+            ("GET", ["user"]) => success(json!({ "id": "simulator" })),
+            ("GET", ["zones"]) => {
+                let state = self.state.lock().unwrap();
+                let name = query("name");
+                let zones = state
+                    .zones
+                    .iter()
+                    .filter(|z| name.as_ref().is_none_or(|n| z.name.eq_ignore_ascii_case(n)))
+                    .map(zone_json)
+                    .collect::<Vec<_>>();
+                success(Value::Array(zones))
+            }
+            ("GET", ["zones", zone_id, "dns_records"]) => {
+                let mut state = self.state.lock().unwrap();
+                let Some(zone) = state.zone_mut(zone_id) else {
+                    return failure(
+                        404,
+                        7003,
+                        "Could not route to /zones, perhaps your object identifier is invalid?",
+                    );
+                };
+                let name = query("name");
+                let records = zone
+                    .records
+                    .iter()
+                    .filter(|r| name.as_ref().is_none_or(|n| r.name.eq_ignore_ascii_case(n)))
+                    .map(|r| serde_json::to_value(r).unwrap_or_default())
+                    .collect::<Vec<_>>();
+                success(Value::Array(records))
+            }
+            ("POST", ["zones", zone_id, "dns_records"]) => {
+                let body = format!(
+                    r#"{{"posts":[{}]}}"#,
+                    String::from_utf8_lossy(&request.body)
+                );
+                self.batch(zone_id, body.as_bytes())
+            }
+            ("POST", ["zones", zone_id, "dns_records", "batch"]) => {
+                self.batch(zone_id, &request.body)
+            }
+            _ => failure(404, 7000, "No route for that URI"),
+        }
+    }
+}
+
+// Transport sending the requests meant for api.cloudflare.com to the simulator
+struct SimulatorTransport {
+    uri: String,
+    cli: reqwest::Client,
+}
+
+#[async_trait]
+impl http::HttpTransport for SimulatorTransport {
+    async fn send(&self, request: http::Request) -> Result<http::Response> {
+        let url = request.url.replacen(CLOUDFLARE_API, &self.uri, 1);
+        let method = match request.method {
+            http::Method::Get => reqwest::Method::GET,
+            http::Method::Post => reqwest::Method::POST,
+            http::Method::Put => reqwest::Method::PUT,
+        };
+        let mut builder = self.cli.request(method, url);
+        for header in request.headers.iter() {
+            builder = builder.header(header.name(), header.value());
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        http::Response::read(builder.send().await?).await
+    }
+}
+
+// Local server speaking the part of the Cloudflare v4 API the provider uses,
+// keeping zones and records in memory. Providers built by it send their
+// requests there instead of api.cloudflare.com and accept any credential.
+pub struct CloudflareSimulator {
+    server: MockServer,
+    state: Arc<Mutex<SimState>>,
+}
+
+impl CloudflareSimulator {
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        let state = Arc::new(Mutex::new(SimState::default()));
+        Mock::given(matchers::any())
+            .respond_with(CloudflareApi {
+                state: state.clone(),
+            })
+            .mount(&server)
+            .await;
+        Self { server, state }
+    }
+
+    // Base URL of the server, in place of https://api.cloudflare.com
+    pub fn uri(&self) -> String {
+        self.server.uri()
+    }
+
+    // Create a zone and return its id
+    pub fn add_zone(&self, name: &str) -> String {
+        let mut state = self.state.lock().unwrap();
+        let id = state.new_id("z");
+        state.zones.push(SimZone {
+            id: id.clone(),
+            name: name.to_string(),
+            records: vec![],
+        });
+        id
+    }
+
+    // Add a record to a zone as if it was created by hand. Records without a
+    // full name get the zone name appended.
+    pub fn add_record(&self, zone: &str, record: ProviderRecord) {
+        let mut state = self.state.lock().unwrap();
+        let id = state.new_id("r");
+        let Some(sim_zone) = state.zones.iter_mut().find(|z| z.name == zone) else {
+            panic!("simulator has no zone {}", zone);
+        };
+        sim_zone.records.push(SimRecord {
+            id,
+            name: record.fqdn(zone),
+            content: record.content,
+            proxied: record
+                .params
+                .iter()
+                .any(|p| p.name == "proxied" && p.value == "true"),
+            ttl: record.ttl.seconds().unwrap_or(1),
+            comment: record.comment,
+        });
+    }
+
+    // Records of a zone as they are now, sorted by name and type
+    pub fn records(&self, zone: &str) -> Vec<ProviderRecord> {
+        let state = self.state.lock().unwrap();
+        let mut ret = state
+            .zones
+            .iter()
+            .find(|z| z.name == zone)
+            .map(|z| z.records.clone())
+            .unwrap_or_default();
+        ret.sort_by(|a, b| {
+            (&a.name, a.content.record_type().as_str())
+                .cmp(&(&b.name, b.content.record_type().as_str()))
+        });
+        ret.into_iter().map(ProviderRecord::from).collect()
+    }
+
+    // Batch requests applied so far, the mutations of the API
+    pub fn batch_count(&self) -> usize {
+        self.state.lock().unwrap().batches
+    }
+
+    pub fn transport(&self) -> Arc<dyn http::HttpTransport> {
+        Arc::new(SimulatorTransport {
+            uri: self.uri(),
+            cli: reqwest::Client::builder()
+                .no_proxy()
+                .build()
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn provider(&self) -> Cloudflare {
+        let auth = Auth::ApiToken("simulator".to_string());
+        Cloudflare::with_transport(auth, self.transport())
+    }
+
+    // Factory of providers talking to the simulator, to register as the
+    // `cloudflare` type of a `ProviderRegistry`
+    pub fn factory(&self) -> impl ProviderFactory + 'static {
+        let transport = self.transport();
+        move |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+            let auth = Auth::ApiToken("simulator".to_string());
+            Ok(Box::new(Cloudflare::with_transport(
+                auth,
+                transport.clone(),
+            )))
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Building blocks to run full sync cycles without credentials or network:
+// mock providers and fetchers, a local Cloudflare API and fixtures.
+
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+
+use crate::config::Cfg;
+use crate::error::Result;
+use crate::provider::AuthParams;
+use crate::provider::ParamList;
+use crate::provider::Provider;
+use crate::provider::ProviderFactory;
+use crate::provider::ProviderRegistry;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordOp;
+use crate::record::TTL;
+use crate::runner::Runner;
+
+mod cloudflare;
+pub use cloudflare::*;
+
+pub use crate::fetcher::MockFetcher;
+pub use crate::provider::MockProvider;
+
+// Documentation addresses of RFC 5737 and RFC 3849
+pub const V4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+pub const V4_NEXT: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 2);
+pub const V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+
+// Config syncing the A record `home` and the AAAA record `home6` of
+// example.org with the provider `cf-1`, of type cloudflare, and the fetcher
+// `mock`
+pub const CONFIG: &str = r#"
+check_interval: 0
+public_ip_fecher: mock
+providers:
+  - name: cf-1
+    type: cloudflare
+    authentication:
+      method: api_token
+      params:
+        - name: api_token
+          value: simulator
+fetchers:
+  - name: mock
+    type: http_fetcher
+    params: []
+records:
+  - type: A
+    name: home
+    providers:
+      - name: cf-1
+        zones: [example.org]
+  - type: AAAA
+    name: home6
+    providers:
+      - name: cf-1
+        zones: [example.org]
+"#;
+
+// Record with the default TTL and no comment nor params
+pub fn record(name: &str, content: RecordContent) -> ProviderRecord {
+    ProviderRecord {
+        name: name.to_string(),
+        content,
+        comment: None,
+        op: RecordOp::default(),
+        ttl: TTL::default(),
+        params: vec![],
+    }
+}
+
+// Factory handing out clones of `provider`, so the test sees what syncs did
+pub fn mock_factory(provider: MockProvider) -> impl ProviderFactory + 'static {
+    move |_: &str, _: &AuthParams, _: &ParamList| -> Result<Box<dyn Provider>> {
+        Ok(Box::new(provider.clone()))
+    }
+}
+
+// Runner of every record of `config_yaml`, with the providers of `registry`
+// and every fetcher replaced by a clone of `fetcher`
+pub fn runner(
+    config_yaml: &str,
+    registry: &ProviderRegistry,
+    fetcher: &MockFetcher,
+) -> Result<Runner> {
+    let config: Cfg = serde_yaml::from_str(config_yaml)?;
+    let records = config.record_items()?;
+    let mut runner = Runner::new(config, records, registry)?;
+    for name in runner.fetcher_names() {
+        runner.set_fetcher(&name, Box::new(fetcher.clone()));
+    }
+    Ok(runner)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::error::Error;
+    use crate::provider::ChangeSet;
+
+    #[tokio::test]
+    async fn test_sync_with_cloudflare_simulator() {
+        let sim = CloudflareSimulator::start().await;
+        sim.add_zone("example.org");
+        sim.add_record("example.org", record("home", RecordContent::A(V4_NEXT)));
+        sim.add_record(
+            "example.org",
+            record("www", RecordContent::CNAME("home.example.org".to_string())),
+        );
+
+        let mut registry = ProviderRegistry::empty();
+        registry.register("cloudflare", sim.factory());
+        let fetcher = MockFetcher::new(Some(V4), Some(V6));
+        let mut runner = runner(CONFIG, &registry, &fetcher).unwrap();
+        runner.run(&CancellationToken::new()).await.unwrap();
+
+        let records = sim.records("example.org");
+        let contents = records
+            .iter()
+            .map(|r| (r.name.as_str(), r.content.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            contents,
+            vec![
+                ("home.example.org", "192.0.2.1".to_string()),
+                ("home6.example.org", "2001:db8::1".to_string()),
+                ("www.example.org", "home.example.org".to_string()),
+            ]
+        );
+        // Both records of the zone go in one batch
+        assert_eq!(sim.batch_count(), 1);
+        assert_eq!(fetcher.fetch_count(), 1);
+
+        // Records served as desired are not written again
+        runner.run(&CancellationToken::new()).await.unwrap();
+        assert_eq!(sim.batch_count(), 1);
+
+        // The provider reads back what the sync wrote
+        let provider = sim.provider();
+        let listed = provider.list(&"example.org".to_string()).await.unwrap();
+        assert_eq!(listed.len(), 3);
+        assert!(listed.iter().all(|r| r.id.is_some()));
+    }
+
+    #[tokio::test]
+    async fn test_cloudflare_simulator_errors() {
+        let sim = CloudflareSimulator::start().await;
+        let provider = sim.provider();
+        assert!(matches!(
+            provider.list(&"example.org".to_string()).await,
+            Err(Error::ZoneNotFound { .. })
+        ));
+
+        sim.add_zone("example.org");
+        let change = ChangeSet::replace_by_name(
+            "example.org",
+            &[record("home.example.org", RecordContent::A(V4))],
+            &[],
+        );
+        provider
+            .apply(&change, &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(sim.records("example.org").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_mock_fetcher() {
+        let provider = MockProvider::new();
+        let mut registry = ProviderRegistry::empty();
+        registry.register("cloudflare", mock_factory(provider.clone()));
+        let fetcher = MockFetcher::new(Some(V4), None);
+        let cancel = CancellationToken::new();
+        let mut runner = runner(CONFIG, &registry, &fetcher).unwrap();
+
+        runner.run(&cancel).await.unwrap();
+        assert_eq!(provider.records()["example.org"].len(), 1);
+
+        fetcher.set(Some(V4_NEXT), Some(V6));
+        runner.run(&cancel).await.unwrap();
+        assert!(runner.public_ip_changed());
+        let records = provider.records()["example.org"].clone();
+        assert_eq!(records.len(), 2);
+        assert!(
+            records
+                .iter()
+                .any(|r| r.content == RecordContent::A(V4_NEXT))
+        );
+
+        fetcher.fail("unreachable");
+        assert!(runner.run(&cancel).await.is_err());
+        assert_eq!(fetcher.fetch_count(), 3);
+    }
+}
@@ -1,8 +1,17 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
+use chrono::DateTime;
+use chrono::Utc;
 use serde::Deserialize;
 use serde::Deserializer;
+use serde::Serialize;
 
 use crate::error::Error;
 use crate::error::Result;
@@ -10,6 +19,7 @@ use crate::error::Result;
 ////////////////////////////////////////////////////////////
 // Global Types
 ////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
 pub struct Param {
     pub name: String,
     pub value: String,
@@ -21,267 +31,372 @@ impl Param {
     }
 }
 
-////////////////////////////////////////////////////////////
-// Public IP
-////////////////////////////////////////////////////////////
-#[derive(Debug, Clone, PartialEq)]
-pub struct PublicIp {
-    v4: Option<Ipv4Addr>,
-    v6: Option<Ipv6Addr>,
+// Match `text` against a pattern where '*' stands for any sequence of
+// characters, dots included.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t) = (pattern.as_bytes(), text.as_bytes());
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && p[pi] == b'*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if pi < p.len() && p[pi].eq_ignore_ascii_case(&t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if let Some((star_pi, star_ti)) = star {
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            star = Some((star_pi, star_ti + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|&c| c == b'*')
 }
 
-impl PublicIp {
-    pub fn new(v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Self {
-        Self { v4, v6 }
+// Parse durations like "90", "90s", "5m", "1h30m" or "500ms". A bare number
+// is taken as seconds.
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
     }
 
-    pub fn ips(&self) -> (Option<Ipv4Addr>, Option<Ipv6Addr>) {
-        (self.v4, self.v6)
+    let invalid = || Error::ParseError(format!("invalid duration: {}", value));
+    if value.is_empty() {
+        return Err(invalid());
     }
-}
 
-////////////////////////////////////////////////////////////
-// Record
-////////////////////////////////////////////////////////////
-pub type ZoneName = String;
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or(invalid())?;
+        let number: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let secs = |factor: u64| number.checked_mul(factor).map(Duration::from_secs);
+        let unit = match &rest[..unit_len] {
+            "ms" => Some(Duration::from_millis(number)),
+            "s" => Some(Duration::from_secs(number)),
+            "m" => secs(60),
+            "h" => secs(3600),
+            "d" => secs(86400),
+            _ => return Err(invalid()),
+        };
+        total = unit
+            .and_then(|unit| total.checked_add(unit))
+            .ok_or(Error::ParseError(format!("duration too long: {}", value)))?;
+        rest = &rest[unit_len..];
+    }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct RecordLabel {
-    key: String,
-    val: String,
+    Ok(total)
 }
 
-impl RecordLabel {
-    pub fn new(key: String, val: String) -> Self {
-        Self { key, val }
+// Deserialize either an integer of seconds or a duration string
+pub fn deserialize_duration<'de, D>(deserializer: D) -> std::result::Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = serde_yaml::Value::deserialize(deserializer)?;
+
+    match value {
+        serde_yaml::Value::Number(n) => n
+            .as_u64()
+            .map(Duration::from_secs)
+            .ok_or(serde::de::Error::custom(format!("invalid duration: {}", n))),
+        serde_yaml::Value::String(s) => parse_duration(&s).map_err(serde::de::Error::custom),
+        _ => Err(serde::de::Error::custom(
+            "duration must be a number of seconds or a string like 5m",
+        )),
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum RecordType {
-    A,
-    AAAA,
-    CNAME,
-    None,
+pub fn deserialize_optional_duration<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_duration(deserializer).map(Some)
 }
 
-impl RecordType {
-    pub fn as_str(&self) -> &str {
-        match self {
-            RecordType::A => "A",
-            RecordType::AAAA => "AAAA",
-            RecordType::CNAME => "CNAME",
-            RecordType::None => "None",
-        }
-    }
+// Settings applied to every HTTP client created by the library, for both
+// fetchers and providers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HttpConfig {
+    pub connect_timeout: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+    pub max_redirects: Option<usize>,
+    pub ca_bundle: Option<PathBuf>,
+    // How long an unused connection stays open for the next request
+    pub pool_idle_timeout: Option<Duration>,
+    // Interval of TCP keep-alive probes and of HTTP/2 pings on idle
+    // connections
+    pub keep_alive: Option<Duration>,
+    pub dns: DnsConfig,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum RecordContent {
-    A(Ipv4Addr),
-    AAAA(Ipv6Addr),
-    CNAME(String),
-    Unassigned(RecordType),
-    Unknown,
+impl HttpConfig {
+    // Validate the settings and make them the process wide default. Clients
+    // created before this call keep their old settings.
+    pub fn install(self) -> Result<()> {
+        crate::wrapper::http::set_config(self)
+    }
 }
 
-impl RecordContent {
-    pub fn is_unknown(&self) -> bool {
-        matches!(self, RecordContent::Unknown)
-    }
+// How the hostnames of fetcher and provider endpoints are resolved. The
+// system resolver is used when nothing is set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DnsConfig {
+    // Asked in order over UDP instead of the system resolver
+    pub nameservers: Vec<SocketAddr>,
+    // Answers are kept this long, or for their own TTL when shorter. Answers
+    // of the system resolver are only kept when set.
+    pub cache_ttl: Option<Duration>,
+    // Fixed addresses of hostnames, asked nowhere, like curl's --resolve
+    pub resolve: HashMap<String, Vec<IpAddr>>,
+}
 
-    pub fn is_unassigned(&self) -> bool {
-        matches!(self, RecordContent::Unassigned(_))
+impl DnsConfig {
+    pub fn is_system(&self) -> bool {
+        self == &Self::default()
     }
 }
 
-impl<'de> Deserialize<'de> for RecordContent {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        #[derive(Deserialize)]
-        struct RecordContentHelper {
-            #[serde(rename = "type")]
-            ty: Option<String>,
-            content: Option<String>,
-        }
-
-        let helper = RecordContentHelper::deserialize(deserializer)?;
-
-        match (helper.ty.as_deref(), helper.content) {
-            (Some("a" | "A"), None) => Ok(RecordContent::Unassigned(RecordType::A)),
-            (Some("a" | "A"), Some(content)) => {
-                let v4: Ipv4Addr = content.parse().map_err(serde::de::Error::custom)?;
-                Ok(RecordContent::A(v4))
-            }
-            (Some("aaaa" | "AAAA"), None) => Ok(RecordContent::Unassigned(RecordType::AAAA)),
-            (Some("aaaa" | "AAAA"), Some(content)) => {
-                let v6: Ipv6Addr = content.parse().map_err(serde::de::Error::custom)?;
-                Ok(RecordContent::AAAA(v6))
-            }
-            (Some("cname" | "CNAME"), None) => Ok(RecordContent::Unassigned(RecordType::CNAME)),
-            (Some("cname" | "CNAME"), Some(content)) => Ok(RecordContent::CNAME(content)),
-            (Some(ty), _) => Err(serde::de::Error::custom(format!(
-                "Unknown record type: {}",
-                ty
-            ))),
-            (None, _) => Err(serde::de::Error::custom("have to give a type for record")),
-        }
-    }
+////////////////////////////////////////////////////////////
+// Public IP
+////////////////////////////////////////////////////////////
+// An address answered by a fetcher
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpCandidate {
+    pub address: IpAddr,
+    // Fetcher or fetcher backend that answered it, like "cloudflare". Empty
+    // when unknown.
+    #[serde(default)]
+    pub source: String,
+    pub fetched_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct FetcherRecord {
-    pub value: RecordContent,
-    pub labels: Vec<RecordLabel>,
+// Every address the fetchers of a cycle answered. Records get the newest
+// candidate of their address family, the others are kept for reporting and
+// for policies choosing between them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "PublicIpRepr", into = "PublicIpRepr")]
+pub struct PublicIp {
+    candidates: Vec<IpCandidate>,
 }
 
-impl FetcherRecord {
-    pub fn new(value: RecordContent) -> Self {
-        Self {
-            value,
-            labels: vec![],
-        }
+impl PublicIp {
+    // Addresses of no particular source, fetched now
+    pub fn new(v4: Option<Ipv4Addr>, v6: Option<Ipv6Addr>) -> Self {
+        let addresses = [v4.map(IpAddr::V4), v6.map(IpAddr::V6)];
+        Self::from_candidates(
+            addresses
+                .into_iter()
+                .flatten()
+                .map(|address| IpCandidate {
+                    address,
+                    source: String::new(),
+                    fetched_at: Utc::now(),
+                })
+                .collect(),
+        )
     }
 
-    pub fn new_v4_with_labels(value: Ipv4Addr, labels: Vec<RecordLabel>) -> Self {
-        Self {
-            value: RecordContent::A(value),
-            labels,
-        }
+    pub fn from_candidates(candidates: Vec<IpCandidate>) -> Self {
+        Self { candidates }
     }
 
-    pub fn new_v6_with_labels(value: Ipv6Addr, labels: Vec<RecordLabel>) -> Self {
-        Self {
-            value: RecordContent::AAAA(value),
-            labels,
-        }
+    pub fn candidates(&self) -> &[IpCandidate] {
+        &self.candidates
     }
-}
 
-#[derive(Debug, Default, Clone, PartialEq)]
-pub struct FetcherRecordSet {
-    contents: Vec<FetcherRecord>,
-}
-
-impl FetcherRecordSet {
-    pub fn new() -> Self {
-        Self { contents: vec![] }
+    pub fn push(&mut self, candidate: IpCandidate) {
+        self.candidates.push(candidate);
     }
 
     pub fn is_empty(&self) -> bool {
-        self.contents.is_empty()
+        self.candidates.is_empty()
     }
 
-    pub fn push(&mut self, content: FetcherRecord) {
-        self.contents.push(content);
+    // Only the candidates answered by `source`
+    pub fn from_source(&self, source: &str) -> Self {
+        Self::from_candidates(
+            self.candidates
+                .iter()
+                .filter(|c| c.source == source)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub fn v4(&self) -> Option<Ipv4Addr> {
+        match self.newest(IpAddr::is_ipv4)?.address {
+            IpAddr::V4(ip) => Some(ip),
+            IpAddr::V6(_) => None,
+        }
     }
-}
 
-impl From<FetcherRecordSet> for PublicIp {
-    fn from(set: FetcherRecordSet) -> Self {
-        let mut v4 = None;
-        let mut v6 = None;
-
-        for content in set.contents {
-            match content.value {
-                RecordContent::A(ip) => v4 = Some(ip),
-                RecordContent::AAAA(ip) => v6 = Some(ip),
-                _ => {}
-            }
+    pub fn v6(&self) -> Option<Ipv6Addr> {
+        match self.newest(IpAddr::is_ipv6)?.address {
+            IpAddr::V6(ip) => Some(ip),
+            IpAddr::V4(_) => None,
         }
+    }
 
-        Self::new(v4, v6)
+    pub fn ips(&self) -> (Option<Ipv4Addr>, Option<Ipv6Addr>) {
+        (self.v4(), self.v6())
     }
-}
 
-////////////////////////////////////////////////////////////
-// Provider Record
-////////////////////////////////////////////////////////////
-#[derive(Debug, Clone, PartialEq)]
-pub enum TTL {
-    Value(u32),
-    Auto,
+    // Of candidates fetched at the same time the last one wins
+    fn newest(&self, family: fn(&IpAddr) -> bool) -> Option<&IpCandidate> {
+        self.candidates
+            .iter()
+            .filter(|c| family(&c.address))
+            .max_by_key(|c| c.fetched_at)
+    }
 }
 
-impl Default for TTL {
-    fn default() -> Self {
-        Self::Auto
+// Two public addresses are the same when records would get the same addresses,
+// whatever answered them and when
+impl PartialEq for PublicIp {
+    fn eq(&self, other: &Self) -> bool {
+        self.ips() == other.ips()
     }
 }
 
-impl<'de> Deserialize<'de> for TTL {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let value = serde_yaml::Value::deserialize(deserializer)?;
+// The chosen addresses are stored next to the candidates, state files written
+// before candidates existed only have them
+#[derive(Serialize, Deserialize)]
+struct PublicIpRepr {
+    v4: Option<Ipv4Addr>,
+    v6: Option<Ipv6Addr>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    candidates: Vec<IpCandidate>,
+}
 
-        match value.as_u64() {
-            Some(int_value) => Ok(TTL::Value(int_value as u32)),
-            _ => Ok(TTL::Auto),
+impl From<PublicIpRepr> for PublicIp {
+    fn from(repr: PublicIpRepr) -> Self {
+        if repr.candidates.is_empty() {
+            Self::new(repr.v4, repr.v6)
+        } else {
+            Self::from_candidates(repr.candidates)
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
-pub enum RecordOp {
-    #[serde(alias = "create")]
-    Create,
-    #[serde(alias = "purge")]
-    Purge,
+impl From<PublicIp> for PublicIpRepr {
+    fn from(ip: PublicIp) -> Self {
+        Self {
+            v4: ip.v4(),
+            v6: ip.v6(),
+            candidates: ip.candidates,
+        }
+    }
 }
 
-impl Default for RecordOp {
-    fn default() -> Self {
-        Self::Create
+impl fmt::Display for PublicIp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.ips() {
+            (Some(v4), Some(v6)) => write!(f, "{}, {}", v4, v6),
+            (Some(v4), None) => write!(f, "{}", v4),
+            (None, Some(v6)) => write!(f, "{}", v6),
+            (None, None) => write!(f, "none"),
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct ProviderParam {
-    pub name: String,
-    pub value: String,
-}
+////////////////////////////////////////////////////////////
+// Zone
+////////////////////////////////////////////////////////////
+pub type ZoneName = String;
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct ProviderRecord {
-    pub name: String,
-    pub content: RecordContent,
-    pub comment: Option<String>,
-    pub op: RecordOp,
-    pub ttl: TTL,
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.example.org", "home.example.org"));
+        assert!(glob_match("*.example.org", "a.b.example.org"));
+        assert!(!glob_match("*.example.org", "example.org"));
+        assert!(glob_match("example.*", "example.org"));
+        assert!(glob_match("example.*", "Example.co.uk"));
+        assert!(!glob_match("example.*", "myexample.org"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("exact.org", "exact.org"));
+    }
 
-    pub params: Vec<ProviderParam>,
-}
+    #[test]
+    fn test_public_ip_candidates() {
+        let at = |secs| DateTime::from_timestamp(secs, 0).unwrap();
+        let candidate = |address: &str, source: &str, secs| IpCandidate {
+            address: address.parse().unwrap(),
+            source: source.to_string(),
+            fetched_at: at(secs),
+        };
+        let ip = PublicIp::from_candidates(vec![
+            candidate("1.1.1.1", "cloudflare", 20),
+            candidate("2.2.2.2", "ipw", 10),
+            candidate("::1", "ipw", 10),
+        ]);
+        assert_eq!(
+            ip.ips(),
+            (Some(Ipv4Addr::new(1, 1, 1, 1)), Some(Ipv6Addr::LOCALHOST))
+        );
+        assert_eq!(ip.from_source("ipw").v4(), Some(Ipv4Addr::new(2, 2, 2, 2)));
+        assert_eq!(
+            ip,
+            PublicIp::new(Some(Ipv4Addr::new(1, 1, 1, 1)), Some(Ipv6Addr::LOCALHOST))
+        );
+
+        let json = serde_json::to_value(&ip).unwrap();
+        assert_eq!(json["v4"], "1.1.1.1");
+        assert_eq!(json["candidates"][1]["source"], "ipw");
+        let back: PublicIp = serde_json::from_value(json).unwrap();
+        assert_eq!(back.candidates(), ip.candidates());
+
+        // State files written before candidates existed
+        let old: PublicIp = serde_json::from_str(r#"{"v4":"3.3.3.3","v6":null}"#).unwrap();
+        assert_eq!(old.ips(), (Some(Ipv4Addr::new(3, 3, 3, 3)), None));
+        assert_eq!(old.candidates().len(), 1);
+    }
 
-impl ProviderRecord {
-    pub fn assign_public_ip_if_unassigned(
-        &mut self,
-        v4: Option<Ipv4Addr>,
-        v6: Option<Ipv6Addr>,
-    ) -> Result<()> {
-        match (&self.content, (v4, v6)) {
-            (RecordContent::Unassigned(RecordType::A), (Some(v4), _)) => {
-                self.content = RecordContent::A(v4)
-            }
-            (RecordContent::Unassigned(RecordType::AAAA), (_, Some(v6))) => {
-                self.content = RecordContent::AAAA(v6)
-            }
-            (RecordContent::Unassigned(ty), (_, _)) => {
-                return Err(Error::Provider(format!(
-                    "content is declared as {} but neither v4 nor v6 is provided",
-                    ty.as_str()
-                )));
-            }
-            _ => {
-                return Err(Error::Provider(format!(
-                    "content should be have a type like A or AAAA, but it is not. Maybe a bug?",
-                )));
-            }
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86400));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("999999999999999999d").is_err());
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_duration() {
+        #[derive(Deserialize)]
+        struct Helper {
+            #[serde(deserialize_with = "deserialize_duration")]
+            interval: Duration,
         }
 
-        Ok(())
+        let helper: Helper = serde_yaml::from_str("interval: 30").unwrap();
+        assert_eq!(helper.interval, Duration::from_secs(30));
+        let helper: Helper = serde_yaml::from_str("interval: 2m").unwrap();
+        assert_eq!(helper.interval, Duration::from_secs(120));
+        assert!(serde_yaml::from_str::<Helper>("interval: -1").is_err());
     }
 }
@@ -0,0 +1,348 @@
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::CheckStatus;
+use super::classify;
+use crate::error::Error;
+use crate::error::Result;
+use crate::provider::BackendRecords;
+use crate::provider::SyncOutcome;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::types::PublicIp;
+use crate::wrapper::dns;
+use crate::wrapper::http::Client;
+use crate::wrapper::http::Header;
+use crate::wrapper::http::HeaderKey;
+
+pub const DEFAULT_DOH_RESOLVERS: [&str; 3] = ["cloudflare", "google", "quad9"];
+
+// A DNS-over-HTTPS resolver answering the JSON API of RFC 8484 servers, e.g.
+// `https://cloudflare-dns.com/dns-query?name=...&type=A`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DohResolver {
+    pub name: String,
+    pub url: String,
+}
+
+impl DohResolver {
+    // `cloudflare`, `google`, `quad9` or the https URL of another resolver
+    pub fn parse(value: &str) -> Result<Self> {
+        let url = match value {
+            "cloudflare" => "https://cloudflare-dns.com/dns-query",
+            "google" => "https://dns.google/resolve",
+            "quad9" => "https://dns.quad9.net:5053/dns-query",
+            url if url.starts_with("https://") => url,
+            _ => {
+                return Err(Error::ParseError(format!(
+                    "invalid DoH resolver {}, not a known name or https URL",
+                    value
+                )));
+            }
+        };
+        Ok(Self {
+            name: value.to_string(),
+            url: url.to_string(),
+        })
+    }
+}
+
+// What a resolver answered for a synced record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Propagation {
+    pub resolver: String,
+    pub status: CheckStatus,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub answers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DohAnswer {
+    name: String,
+    r#type: u16,
+    #[serde(rename = "TTL", default)]
+    ttl: u32,
+    data: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u8,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+// Resolves the records a sync created or updated at several DoH resolvers at
+// once, so a provider accepting a change but serving stale data shows up in
+// the sync report
+pub struct DohVerifier {
+    client: Client,
+    resolvers: Vec<DohResolver>,
+    // Time given to the provider to publish the changes before asking
+    delay: Duration,
+}
+
+impl DohVerifier {
+    pub fn new(resolvers: Vec<DohResolver>, delay: Duration, timeout: Duration) -> Result<Self> {
+        Ok(Self::with_client(
+            Client::new_with_timeout(timeout)?,
+            resolvers,
+            delay,
+        ))
+    }
+
+    pub fn with_client(client: Client, resolvers: Vec<DohResolver>, delay: Duration) -> Self {
+        Self {
+            client,
+            resolvers,
+            delay,
+        }
+    }
+
+    async fn query(
+        &self,
+        resolver: &DohResolver,
+        name: &str,
+        r#type: u16,
+    ) -> Result<dns::Response> {
+        let url = format!("{}?name={}&type={}", resolver.url, name, r#type);
+        let headers = vec![Header::new(
+            HeaderKey::Custom("Accept".to_string()),
+            "application/dns-json".to_string(),
+        )];
+        let body = self.client.get(&url, Some(headers)).await?.into_body()?;
+        let resp: DohResponse = serde_json::from_str(&body).map_err(|e| {
+            Error::ParseError(format!("{}: invalid DoH answer: {}", resolver.name, e))
+        })?;
+        if resp.status != 0 && resp.status != 3 {
+            return Err(Error::ParseError(format!(
+                "{} answered rcode {}",
+                resolver.name, resp.status
+            )));
+        }
+        Ok(dns::Response {
+            nxdomain: resp.status == 3,
+            answers: resp
+                .answer
+                .into_iter()
+                .map(|a| dns::Answer {
+                    name: a.name.trim_end_matches('.').to_string(),
+                    r#type: a.r#type,
+                    ttl: a.ttl,
+                    data: a.data,
+                })
+                .collect(),
+        })
+    }
+
+    // Every resolver asked about `record`, concurrently
+    pub async fn check(&self, record: &ProviderRecord) -> Vec<Propagation> {
+        let r#type = match record.content {
+            RecordContent::A(_) => dns::TYPE_A,
+            RecordContent::AAAA(_) => dns::TYPE_AAAA,
+            _ => dns::TYPE_CNAME,
+        };
+        let checks = self.resolvers.iter().map(|resolver| async move {
+            match self.query(resolver, &record.name, r#type).await {
+                Ok(resp) => {
+                    let (answers, _, status) = classify(record, r#type, &resp);
+                    Propagation {
+                        resolver: resolver.name.clone(),
+                        status,
+                        answers,
+                        error: None,
+                    }
+                }
+                Err(e) => Propagation {
+                    resolver: resolver.name.clone(),
+                    status: CheckStatus::Error,
+                    answers: vec![],
+                    error: Some(e.to_string()),
+                },
+            }
+        });
+        join_all(checks).await
+    }
+
+    // Add the propagation of the records `outcome` created or updated to it.
+    // `records` are the ones the provider synced, for their desired content.
+    pub async fn verify(
+        &self,
+        records: &BackendRecords,
+        public_ip: &PublicIp,
+        outcome: &mut SyncOutcome,
+    ) {
+        let changed = outcome.records().any(|r| r.status.is_change());
+        if !changed || self.resolvers.is_empty() {
+            return;
+        }
+        tokio::time::sleep(self.delay).await;
+
+        for zone in outcome.zones.iter_mut() {
+            let Some(zone_records) = records.zones.get(&zone.zone) else {
+                continue;
+            };
+            let desired = zone_records.desired(&zone.zone, public_ip);
+            let checks = zone
+                .records
+                .iter_mut()
+                .filter(|r| r.status.is_change())
+                .map(|outcome| {
+                    let record = desired.iter().find(|d| {
+                        d.name.eq_ignore_ascii_case(&outcome.name)
+                            && d.content.record_type().as_str() == outcome.r#type
+                    });
+                    async move {
+                        if let Some(record) = record {
+                            outcome.propagation = self.check(record).await;
+                        }
+                        outcome
+                    }
+                });
+            for outcome in join_all(checks).await {
+                let stale = outcome
+                    .propagation
+                    .iter()
+                    .filter(|p| p.status != CheckStatus::Ok && p.status != CheckStatus::Proxied)
+                    .map(|p| format!("{} {}", p.resolver, p.status.as_str()))
+                    .collect::<Vec<_>>();
+                if !stale.is_empty() {
+                    log::warn!(
+                        record = outcome.name;
+                        "record {} not propagated yet: {}", outcome.name, stale.join(", ")
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::net::Ipv4Addr;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::provider::RecordOutcome;
+    use crate::provider::RecordStatus;
+    use crate::provider::ZoneRecords;
+    use crate::provider::ZoneSyncOutcome;
+    use crate::record::RecordOp;
+    use crate::record::TTL;
+    use crate::wrapper::http::MemoryTransport;
+    use crate::wrapper::http::Method;
+
+    #[test]
+    fn test_doh_resolver_parse() {
+        let resolver = DohResolver::parse("google").unwrap();
+        assert_eq!(resolver.url, "https://dns.google/resolve");
+        let resolver = DohResolver::parse("https://doh.example.net/dns-query").unwrap();
+        assert_eq!(resolver.name, resolver.url);
+        assert!(DohResolver::parse("8.8.8.8").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_doh_verify() {
+        let transport = Arc::new(MemoryTransport::new());
+        let answer = |ip: &str| {
+            format!(
+                r#"{{"Status": 0, "Answer": [{{"name": "home.example.org.", "type": 1, "TTL": 300, "data": "{}"}}]}}"#,
+                ip
+            )
+        };
+        transport.route(
+            Method::Get,
+            "https://cloudflare-dns.com/dns-query",
+            200,
+            &answer("192.0.2.1"),
+        );
+        transport.route(
+            Method::Get,
+            "https://dns.google/resolve",
+            200,
+            &answer("192.0.2.9"),
+        );
+        transport.route(
+            Method::Get,
+            "https://dns.quad9.net:5053/dns-query",
+            200,
+            r#"{"Status": 3}"#,
+        );
+        let resolvers = DEFAULT_DOH_RESOLVERS
+            .iter()
+            .map(|r| DohResolver::parse(r).unwrap())
+            .collect();
+        let verifier = DohVerifier::with_client(
+            Client::with_transport(transport.clone()),
+            resolvers,
+            Duration::ZERO,
+        );
+
+        let record = ProviderRecord {
+            name: "home".to_string(),
+            content: RecordContent::Unassigned(crate::record::RecordType::A),
+            comment: None,
+            op: RecordOp::Create,
+            ttl: TTL::Auto,
+            params: vec![],
+        };
+        let records = BackendRecords {
+            zones: HashMap::from([(
+                "example.org".to_string(),
+                ZoneRecords {
+                    records: vec![record],
+                },
+            )]),
+        };
+        let outcome = |status| RecordOutcome {
+            zone: "example.org".to_string(),
+            name: "home.example.org".to_string(),
+            r#type: "A".to_string(),
+            status,
+            error: None,
+            propagation: vec![],
+        };
+        let mut sync = SyncOutcome {
+            zones: vec![ZoneSyncOutcome {
+                zone: "example.org".to_string(),
+                records: vec![outcome(RecordStatus::Updated)],
+                ..Default::default()
+            }],
+            errors: vec![],
+            requests: 0,
+        };
+        let public_ip = PublicIp::new(Some(Ipv4Addr::new(192, 0, 2, 1)), None);
+        verifier.verify(&records, &public_ip, &mut sync).await;
+
+        let propagation = &sync.zones[0].records[0].propagation;
+        let statuses = propagation
+            .iter()
+            .map(|p| (p.resolver.as_str(), p.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                ("cloudflare", CheckStatus::Ok),
+                ("google", CheckStatus::Stale),
+                ("quad9", CheckStatus::Missing),
+            ]
+        );
+        assert_eq!(propagation[1].answers, vec!["192.0.2.9"]);
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].url.ends_with("?name=home.example.org&type=1"));
+
+        // Unchanged records are not asked about
+        sync.zones[0].records = vec![outcome(RecordStatus::Unchanged)];
+        verifier.verify(&records, &public_ip, &mut sync).await;
+        assert!(sync.zones[0].records[0].propagation.is_empty());
+        assert_eq!(transport.requests().len(), 3);
+    }
+}
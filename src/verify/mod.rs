@@ -0,0 +1,227 @@
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::wrapper::dns;
+
+mod doh;
+pub use doh::*;
+
+pub const DEFAULT_RESOLVERS: [&str; 2] = ["1.1.1.1", "8.8.8.8"];
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Resolver address, port 53 when none is given
+pub fn parse_resolver(value: &str) -> Result<SocketAddr> {
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    value
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, 53))
+        .map_err(|_| Error::ParseError(format!("invalid resolver address {}", value)))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    // The resolver answers the desired content only
+    Ok,
+    // The resolver answers something else, the old content is still cached or
+    // the record was changed behind our back
+    Stale,
+    // The name does not exist or has no record of the type
+    Missing,
+    // Proxied records resolve to the proxy, the content cannot be compared
+    Proxied,
+    Error,
+}
+
+impl CheckStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Stale => "stale",
+            CheckStatus::Missing => "missing",
+            CheckStatus::Proxied => "proxied",
+            CheckStatus::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordCheck {
+    pub name: String,
+    pub r#type: String,
+    pub resolver: SocketAddr,
+    pub desired: String,
+    pub answers: Vec<String>,
+    // Lowest TTL of the answers, how long a stale answer may still be served
+    pub ttl: Option<u32>,
+    pub status: CheckStatus,
+    pub error: Option<String>,
+}
+
+// Resolve a record with its full name and final content at `resolver`
+pub async fn check_record(
+    resolver: SocketAddr,
+    record: &ProviderRecord,
+    timeout: Duration,
+) -> RecordCheck {
+    let r#type = match record.content {
+        RecordContent::A(_) => dns::TYPE_A,
+        RecordContent::AAAA(_) => dns::TYPE_AAAA,
+        _ => dns::TYPE_CNAME,
+    };
+
+    let mut check = RecordCheck {
+        name: record.name.clone(),
+        r#type: record.content.record_type().as_str().to_string(),
+        resolver,
+        desired: record.content.to_string(),
+        answers: vec![],
+        ttl: None,
+        status: CheckStatus::Error,
+        error: None,
+    };
+    match dns::query(resolver, &record.name, r#type, timeout).await {
+        Ok(resp) => compare(&mut check, record, r#type, &resp),
+        Err(e) => check.error = Some(e.to_string()),
+    }
+    check
+}
+
+fn compare(check: &mut RecordCheck, record: &ProviderRecord, r#type: u16, resp: &dns::Response) {
+    let (answers, ttl, status) = classify(record, r#type, resp);
+    check.answers = answers;
+    check.ttl = ttl;
+    check.status = status;
+}
+
+// Answers of a resolver for `record`, their lowest TTL and how they compare
+// with the desired content
+fn classify(
+    record: &ProviderRecord,
+    r#type: u16,
+    resp: &dns::Response,
+) -> (Vec<String>, Option<u32>, CheckStatus) {
+    let answers = resp
+        .answers
+        .iter()
+        .filter(|a| a.r#type == r#type && a.name.eq_ignore_ascii_case(&record.name))
+        .collect::<Vec<_>>();
+    let ttl = answers.iter().map(|a| a.ttl).min();
+    let answers = answers.iter().map(|a| a.data.clone()).collect::<Vec<_>>();
+
+    let proxied = record
+        .params
+        .iter()
+        .any(|p| p.name == "proxied" && p.value == "true");
+    let desired = record.content.to_string();
+    let desired = desired.trim_end_matches('.');
+
+    let status = if answers.is_empty() {
+        CheckStatus::Missing
+    } else if proxied {
+        CheckStatus::Proxied
+    } else if answers
+        .iter()
+        .all(|a| a.trim_end_matches('.').eq_ignore_ascii_case(desired))
+    {
+        CheckStatus::Ok
+    } else {
+        CheckStatus::Stale
+    };
+    (answers, ttl, status)
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::record::ProviderParam;
+    use crate::record::RecordOp;
+    use crate::record::TTL;
+
+    fn record(params: Vec<ProviderParam>) -> ProviderRecord {
+        ProviderRecord {
+            name: "home.example.org".to_string(),
+            content: RecordContent::A(Ipv4Addr::new(1, 2, 3, 4)),
+            comment: None,
+            op: RecordOp::Create,
+            ttl: TTL::Auto,
+            params,
+        }
+    }
+
+    fn answer(data: &str, ttl: u32) -> dns::Answer {
+        dns::Answer {
+            name: "home.example.org".to_string(),
+            r#type: dns::TYPE_A,
+            ttl,
+            data: data.to_string(),
+        }
+    }
+
+    fn status(record: &ProviderRecord, answers: Vec<dns::Answer>) -> RecordCheck {
+        let mut check = RecordCheck {
+            name: record.name.clone(),
+            r#type: "A".to_string(),
+            resolver: parse_resolver("1.1.1.1").unwrap(),
+            desired: record.content.to_string(),
+            answers: vec![],
+            ttl: None,
+            status: CheckStatus::Error,
+            error: None,
+        };
+        let resp = dns::Response {
+            nxdomain: answers.is_empty(),
+            answers,
+        };
+        compare(&mut check, record, dns::TYPE_A, &resp);
+        check
+    }
+
+    #[test]
+    fn test_compare() {
+        let plain = record(vec![]);
+        assert_eq!(
+            status(&plain, vec![answer("1.2.3.4", 300)]).status,
+            CheckStatus::Ok
+        );
+        assert_eq!(status(&plain, vec![]).status, CheckStatus::Missing);
+
+        let stale = status(&plain, vec![answer("1.2.3.4", 300), answer("5.6.7.8", 120)]);
+        assert_eq!(stale.status, CheckStatus::Stale);
+        assert_eq!(stale.ttl, Some(120));
+        assert_eq!(stale.answers, vec!["1.2.3.4", "5.6.7.8"]);
+
+        let proxied = record(vec![ProviderParam {
+            name: "proxied".to_string(),
+            value: "true".to_string(),
+        }]);
+        assert_eq!(
+            status(&proxied, vec![answer("104.16.0.1", 300)]).status,
+            CheckStatus::Proxied
+        );
+    }
+
+    #[test]
+    fn test_parse_resolver() {
+        assert_eq!(parse_resolver("1.1.1.1").unwrap().to_string(), "1.1.1.1:53");
+        assert_eq!(parse_resolver("9.9.9.9:5353").unwrap().port(), 5353);
+        assert_eq!(
+            parse_resolver("2606:4700:4700::1111").unwrap().to_string(),
+            "[2606:4700:4700::1111]:53"
+        );
+        assert!(parse_resolver("resolver.example").is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use std::time::Duration;
+
+use chrono::DateTime;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+use crate::wrapper::http;
+
+const IMDS_ENDPOINT: &str = "http://169.254.169.254";
+const ECS_ENDPOINT: &str = "http://169.254.170.2";
+
+///////////////////////////////////////////////////////////
+// Credentials
+///////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RemoteCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    token: Option<String>,
+}
+
+impl From<RemoteCredentials> for Credentials {
+    fn from(remote: RemoteCredentials) -> Self {
+        Self {
+            access_key_id: remote.access_key_id,
+            secret_access_key: remote.secret_access_key,
+            session_token: remote.token,
+        }
+    }
+}
+
+impl Credentials {
+    // Look up the ambient credentials the same way the AWS SDKs do, in order:
+    // environment variables, ECS task role and EC2 instance profile.
+    pub async fn ambient() -> Result<Self> {
+        if let Some(creds) = Self::from_env() {
+            return Ok(creds);
+        }
+        if let Some(creds) = Self::from_ecs().await? {
+            return Ok(creds);
+        }
+        Self::from_imds().await
+    }
+
+    fn from_env() -> Option<Self> {
+        let access_key_id = std::env::var("AWS_ACCESS_KEY_ID").ok()?;
+        let secret_access_key = std::env::var("AWS_SECRET_ACCESS_KEY").ok()?;
+        Some(Self {
+            access_key_id,
+            secret_access_key,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    async fn from_ecs() -> Result<Option<Self>> {
+        let url = match (
+            std::env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI"),
+            std::env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI"),
+        ) {
+            (Ok(relative), _) => format!("{}{}", ECS_ENDPOINT, relative),
+            (_, Ok(full)) => full,
+            _ => return Ok(None),
+        };
+
+        let headers = std::env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN")
+            .ok()
+            .map(|token| vec![http::Header::new(http::HeaderKey::Authorization, token)]);
+
+        let cli = http::Client::new_with_timeout(Duration::from_secs(5))?;
+        let body = cli.get(&url, headers).await?.into_body()?;
+        let remote: RemoteCredentials = serde_json::from_str(&body)?;
+        Ok(Some(remote.into()))
+    }
+
+    async fn from_imds() -> Result<Self> {
+        let imds = Imds::connect().await?;
+        let role = imds
+            .get("/latest/meta-data/iam/security-credentials/")
+            .await?;
+        let role = role.lines().next().unwrap_or_default().trim();
+        if role.is_empty() {
+            return Err(Error::Secret(
+                "no aws credentials in environment, ECS or instance profile".to_string(),
+            ));
+        }
+
+        let path = format!("/latest/meta-data/iam/security-credentials/{}", role);
+        let remote: RemoteCredentials = serde_json::from_str(&imds.get(&path).await?)?;
+        Ok(remote.into())
+    }
+}
+
+// Region from AWS_REGION/AWS_DEFAULT_REGION, falling back to the instance metadata
+pub async fn region() -> Result<String> {
+    if let Ok(region) = std::env::var("AWS_REGION").or(std::env::var("AWS_DEFAULT_REGION")) {
+        return Ok(region);
+    }
+
+    let imds = Imds::connect().await?;
+    imds.get("/latest/meta-data/placement/region").await
+}
+
+// EC2 instance metadata service, IMDSv2 session
+struct Imds {
+    cli: http::Client,
+    token: String,
+}
+
+impl Imds {
+    async fn connect() -> Result<Self> {
+        let cli = http::Client::new_with_timeout(Duration::from_secs(2))?;
+        let url = format!("{}/latest/api/token", IMDS_ENDPOINT);
+        let headers = vec![http::Header::new(
+            http::HeaderKey::Custom("X-aws-ec2-metadata-token-ttl-seconds".to_string()),
+            "60".to_string(),
+        )];
+        let token = cli
+            .put(&url, Some(headers), String::new())
+            .await
+            .map_err(|e| Error::Secret(format!("instance metadata unreachable: {}", e)))?
+            .into_body()?;
+        Ok(Self { cli, token })
+    }
+
+    async fn get(&self, path: &str) -> Result<String> {
+        let url = format!("{}{}", IMDS_ENDPOINT, path);
+        let headers = vec![http::Header::new(
+            http::HeaderKey::Custom("X-aws-ec2-metadata-token".to_string()),
+            self.token.clone(),
+        )];
+        self.cli.get(&url, Some(headers)).await?.into_body()
+    }
+}
+
+///////////////////////////////////////////////////////////
+// Signature V4
+///////////////////////////////////////////////////////////
+pub struct SigV4<'a> {
+    pub credentials: &'a Credentials,
+    pub region: &'a str,
+    pub service: &'a str,
+}
+
+impl SigV4<'_> {
+    // Compute the headers to add to a request: x-amz-date, the session token if
+    // any and the authorization. `headers` must contain every other header sent.
+    pub fn sign(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(String, String)],
+        body: &[u8],
+        now: DateTime<Utc>,
+    ) -> Result<Vec<http::Header>> {
+        let url = reqwest::Url::parse(url).map_err(|e| Error::ParseError(e.to_string()))?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let mut canonical_headers: Vec<(String, String)> = headers
+            .iter()
+            .map(|(k, v)| (k.to_ascii_lowercase(), v.trim().to_string()))
+            .collect();
+        canonical_headers.push(("host".to_string(), host));
+        canonical_headers.push(("x-amz-date".to_string(), amz_date.clone()));
+        if let Some(token) = self.credentials.session_token.as_ref() {
+            canonical_headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        canonical_headers.sort();
+
+        let signed_headers = canonical_headers
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let mut query: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(k, v)| (uri_encode(&k, true), uri_encode(&v, true)))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            uri_encode(url.path(), false),
+            canonical_query,
+            canonical_headers
+                .iter()
+                .map(|(k, v)| format!("{}:{}\n", k, v))
+                .collect::<String>(),
+            signed_headers,
+            hex::encode(Sha256::digest(body)),
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = format!("AWS4{}", self.credentials.secret_access_key);
+        let key = hmac_sha256(key.as_bytes(), date.as_bytes());
+        let key = hmac_sha256(&key, self.region.as_bytes());
+        let key = hmac_sha256(&key, self.service.as_bytes());
+        let key = hmac_sha256(&key, b"aws4_request");
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        let mut ret = vec![http::Header::new(
+            http::HeaderKey::Custom("X-Amz-Date".to_string()),
+            amz_date,
+        )];
+        if let Some(token) = self.credentials.session_token.as_ref() {
+            ret.push(http::Header::new(
+                http::HeaderKey::Custom("X-Amz-Security-Token".to_string()),
+                token.clone(),
+            ));
+        }
+        ret.push(http::Header::new(
+            http::HeaderKey::Authorization,
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.credentials.access_key_id, scope, signed_headers, signature
+            ),
+        ));
+        Ok(ret)
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub(crate) fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut ret = String::new();
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                ret.push(b as char)
+            }
+            b'/' if !encode_slash => ret.push('/'),
+            b => ret.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    ret
+}
+
+// Error body of the AWS JSON protocols
+#[derive(Debug, Deserialize)]
+pub struct JsonError {
+    #[serde(rename = "__type", default)]
+    pub r#type: String,
+    #[serde(alias = "Message", default)]
+    pub message: String,
+}
+
+impl JsonError {
+    pub fn from_body(body: &str) -> String {
+        match serde_json::from_str::<JsonError>(body) {
+            Ok(e) => format!("{}: {}", e.r#type, e.message),
+            Err(_) => body.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    // "get-vanilla" case of the AWS signature v4 test suite
+    #[test]
+    fn test_sigv4_get_vanilla() {
+        let credentials = Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let signer = SigV4 {
+            credentials: &credentials,
+            region: "us-east-1",
+            service: "service",
+        };
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = signer
+            .sign("GET", "https://example.amazonaws.com/", &[], b"", now)
+            .unwrap();
+
+        let auth = headers.last().unwrap();
+        assert_eq!(
+            auth.value(),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(uri_encode("/a b/c", false), "/a%20b/c");
+        assert_eq!(uri_encode("/a b/c", true), "%2Fa%20b%2Fc");
+    }
+}
@@ -0,0 +1,241 @@
+use std::io;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use tokio::net::UdpSocket;
+
+use crate::error::{Error, Result};
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_AAAA: u16 = 28;
+
+const CLASS_IN: u16 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Answer {
+    pub name: String,
+    pub r#type: u16,
+    pub ttl: u32,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub nxdomain: bool,
+    pub answers: Vec<Answer>,
+}
+
+// Ask `server` over UDP for the records of `name`, recursion desired. Truncated
+// answers are returned as they are, records synced by this tool are small.
+pub async fn query(
+    server: SocketAddr,
+    name: &str,
+    r#type: u16,
+    timeout: Duration,
+) -> Result<Response> {
+    let bind: SocketAddr = if server.is_ipv4() {
+        "0.0.0.0:0".parse().unwrap()
+    } else {
+        "[::]:0".parse().unwrap()
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(server).await?;
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let id = (nanos ^ std::process::id()) as u16;
+    let packet = encode_query(id, name, r#type)?;
+    socket.send(&packet).await?;
+
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let len = tokio::time::timeout(timeout, socket.recv(&mut buf))
+            .await
+            .map_err(|_| {
+                Error::IoError(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("dns query to {} timed out", server),
+                ))
+            })??;
+        // Replies to an earlier query are ignored
+        if len >= 2 && u16::from_be_bytes([buf[0], buf[1]]) == id {
+            return decode_response(&buf[..len]);
+        }
+    }
+}
+
+fn encode_query(id: u16, name: &str, r#type: u16) -> Result<Vec<u8>> {
+    let mut ret = Vec::with_capacity(32 + name.len());
+    ret.extend_from_slice(&id.to_be_bytes());
+    // Standard query, recursion desired, one question
+    ret.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::ParseError(format!("invalid dns name {}", name)));
+        }
+        ret.push(label.len() as u8);
+        ret.extend_from_slice(label.as_bytes());
+    }
+    ret.push(0);
+    ret.extend_from_slice(&r#type.to_be_bytes());
+    ret.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(ret)
+}
+
+fn decode_response(packet: &[u8]) -> Result<Response> {
+    let err = || Error::ParseError("malformed dns response".to_string());
+    if packet.len() < 12 {
+        return Err(err());
+    }
+
+    let rcode = packet[3] & 0x0f;
+    if rcode != 0 && rcode != RCODE_NXDOMAIN {
+        return Err(Error::IoError(io::Error::other(format!(
+            "dns server answered rcode {}",
+            rcode
+        ))));
+    }
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    let ancount = u16::from_be_bytes([packet[6], packet[7]]);
+
+    let mut pos = 12;
+    for _ in 0..qdcount {
+        let (_, next) = read_name(packet, pos)?;
+        pos = next + 4;
+    }
+
+    let mut answers = vec![];
+    for _ in 0..ancount {
+        let (name, next) = read_name(packet, pos)?;
+        let fixed = packet.get(next..next + 10).ok_or_else(err)?;
+        let r#type = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+        let rdlength = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let rdata_pos = next + 10;
+        let rdata = packet
+            .get(rdata_pos..rdata_pos + rdlength)
+            .ok_or_else(err)?;
+
+        let data = match r#type {
+            TYPE_A if rdlength == 4 => {
+                Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]).to_string()
+            }
+            TYPE_AAAA if rdlength == 16 => {
+                let octets: [u8; 16] = rdata.try_into().map_err(|_| err())?;
+                Ipv6Addr::from(octets).to_string()
+            }
+            TYPE_CNAME => read_name(packet, rdata_pos)?.0,
+            _ => hex::encode(rdata),
+        };
+        answers.push(Answer {
+            name,
+            r#type,
+            ttl,
+            data,
+        });
+        pos = rdata_pos + rdlength;
+    }
+
+    Ok(Response {
+        nxdomain: rcode == RCODE_NXDOMAIN,
+        answers,
+    })
+}
+
+// Read a possibly compressed name, return it with the position right after it
+pub(super) fn read_name(packet: &[u8], mut pos: usize) -> Result<(String, usize)> {
+    let err = || Error::ParseError("malformed dns name".to_string());
+    let mut labels = vec![];
+    let mut end = None;
+
+    // Every jump goes backwards in a valid packet, bounding the jumps stops loops
+    for _ in 0..packet.len() {
+        let len = *packet.get(pos).ok_or_else(err)? as usize;
+        match len {
+            0 => {
+                let name = labels.join(".");
+                return Ok((name, end.unwrap_or(pos + 1)));
+            }
+            len if len & 0xc0 == 0xc0 => {
+                let low = *packet.get(pos + 1).ok_or_else(err)? as usize;
+                end.get_or_insert(pos + 2);
+                pos = ((len & 0x3f) << 8) | low;
+            }
+            len => {
+                let label = packet.get(pos + 1..pos + 1 + len).ok_or_else(err)?;
+                labels.push(String::from_utf8_lossy(label).to_string());
+                pos += 1 + len;
+            }
+        }
+    }
+    Err(err())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_query() {
+        let packet = encode_query(0x1234, "home.example.org.", TYPE_A).unwrap();
+        assert_eq!(&packet[..4], &[0x12, 0x34, 0x01, 0x00]);
+        assert_eq!(&packet[12..17], &[4, b'h', b'o', b'm', b'e']);
+        assert_eq!(&packet[packet.len() - 5..], &[0, 0, 1, 0, 1]);
+        assert!(encode_query(1, "a..org", TYPE_A).is_err());
+    }
+
+    #[test]
+    fn test_decode_response() {
+        let mut packet = encode_query(0x1234, "www.example.org", TYPE_A).unwrap();
+        // Response, recursion available, one question and two answers
+        packet[2] = 0x81;
+        packet[3] = 0x80;
+        packet[7] = 2;
+        // www.example.org CNAME home.example.org, pointing back to the question
+        packet.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0x0e, 0x10, 0, 7]);
+        let home = packet.len();
+        packet.extend_from_slice(&[4, b'h', b'o', b'm', b'e', 0xc0, 16]);
+        // home.example.org A 1.2.3.4
+        packet.extend_from_slice(&[0xc0, home as u8, 0, 1, 0, 1, 0, 0, 1, 0x2c, 0, 4]);
+        packet.extend_from_slice(&[1, 2, 3, 4]);
+
+        let resp = decode_response(&packet).unwrap();
+        assert!(!resp.nxdomain);
+        assert_eq!(resp.answers.len(), 2);
+        assert_eq!(resp.answers[0].name, "www.example.org");
+        assert_eq!(resp.answers[0].r#type, TYPE_CNAME);
+        assert_eq!(resp.answers[0].ttl, 3600);
+        assert_eq!(resp.answers[0].data, "home.example.org");
+        assert_eq!(resp.answers[1].name, "home.example.org");
+        assert_eq!(resp.answers[1].data, "1.2.3.4");
+        assert_eq!(resp.answers[1].ttl, 300);
+    }
+
+    #[test]
+    fn test_decode_nxdomain() {
+        let mut packet = encode_query(1, "gone.example.org", TYPE_AAAA).unwrap();
+        packet[2] = 0x81;
+        packet[3] = 0x83;
+        let resp = decode_response(&packet).unwrap();
+        assert!(resp.nxdomain);
+        assert!(resp.answers.is_empty());
+
+        packet[3] = 0x82;
+        assert!(decode_response(&packet).is_err());
+    }
+
+    #[test]
+    fn test_read_name_loop() {
+        let packet = [0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xc0, 12];
+        assert!(read_name(&packet, 12).is_err());
+    }
+}
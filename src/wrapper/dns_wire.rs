@@ -0,0 +1,1096 @@
+use std::io;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hmac::Hmac;
+use hmac::Mac;
+use sha2::Sha256;
+use sha2::Sha384;
+use sha2::Sha512;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use super::dns::read_name;
+use crate::error::Error;
+use crate::error::Result;
+
+// DNS messages as authoritative servers take them: queries, RFC 2136 dynamic
+// updates and zone transfers over TCP, signed with TSIG (RFC 8945) when a
+// key is given. Names are written uncompressed and read either way.
+
+pub use super::dns::TYPE_A;
+pub use super::dns::TYPE_AAAA;
+pub use super::dns::TYPE_CNAME;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_TSIG: u16 = 250;
+pub const TYPE_AXFR: u16 = 252;
+
+pub const CLASS_IN: u16 = 1;
+// Deletes a whole record set in an update, and the class of TSIG records
+pub const CLASS_ANY: u16 = 255;
+
+pub const OPCODE_QUERY: u8 = 0;
+pub const OPCODE_UPDATE: u8 = 5;
+
+pub const RCODE_NOERROR: u16 = 0;
+pub const RCODE_NXDOMAIN: u16 = 3;
+pub const RCODE_REFUSED: u16 = 5;
+pub const RCODE_NOTAUTH: u16 = 9;
+
+pub const DEFAULT_PORT: u16 = 53;
+// Seconds the clocks of the signer and the verifier may differ by
+const TSIG_FUDGE: u16 = 300;
+// A zone transfer may leave up to 99 messages in a row unsigned
+const TSIG_UNSIGNED_MAX: usize = 99;
+
+// Mnemonic of a response or TSIG error code
+pub fn rcode_name(rcode: u16) -> String {
+    match rcode {
+        0 => "NOERROR",
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        6 => "YXDOMAIN",
+        7 => "YXRRSET",
+        8 => "NXRRSET",
+        9 => "NOTAUTH",
+        10 => "NOTZONE",
+        16 => "BADSIG",
+        17 => "BADKEY",
+        18 => "BADTIME",
+        22 => "BADTRUNC",
+        rcode => return format!("RCODE{}", rcode),
+    }
+    .to_string()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Soa {
+    pub mname: String,
+    pub rname: String,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+// Variants named after the record types, like `RecordContent`
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    // Target of a CNAME or NS record
+    Name(String),
+    Soa(Soa),
+    // Any other type as it is on the wire, empty in the deletes of an update
+    Raw(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Question {
+    pub name: String,
+    pub r#type: u16,
+    pub class: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub r#type: u16,
+    pub class: u16,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+// A DNS message. In an update the questions are the zone, the answers the
+// prerequisites and the authorities the updates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Message {
+    pub id: u16,
+    pub response: bool,
+    pub opcode: u8,
+    pub authoritative: bool,
+    pub truncated: bool,
+    pub recursion_desired: bool,
+    pub rcode: u16,
+    pub questions: Vec<Question>,
+    pub answers: Vec<Record>,
+    pub authorities: Vec<Record>,
+    pub additionals: Vec<Record>,
+}
+
+impl Message {
+    // Query of the `type` records of `name`, without recursion
+    pub fn query(name: &str, r#type: u16) -> Self {
+        Self {
+            id: new_id(),
+            opcode: OPCODE_QUERY,
+            questions: vec![Question {
+                name: name.to_string(),
+                r#type,
+                class: CLASS_IN,
+            }],
+            ..Default::default()
+        }
+    }
+
+    // Update of `zone` without prerequisites nor updates yet
+    pub fn update(zone: &str) -> Self {
+        Self {
+            id: new_id(),
+            opcode: OPCODE_UPDATE,
+            questions: vec![Question {
+                name: zone.to_string(),
+                r#type: TYPE_SOA,
+                class: CLASS_IN,
+            }],
+            ..Default::default()
+        }
+    }
+
+    // Update deleting the record set of `name` and `type`
+    pub fn delete_rrset(&mut self, name: &str, r#type: u16) {
+        self.authorities.push(Record {
+            name: name.to_string(),
+            r#type,
+            class: CLASS_ANY,
+            ttl: 0,
+            data: RData::Raw(vec![]),
+        });
+    }
+
+    // Update adding a record
+    pub fn add_record(&mut self, name: &str, r#type: u16, ttl: u32, data: RData) {
+        self.authorities.push(Record {
+            name: name.to_string(),
+            r#type,
+            class: CLASS_IN,
+            ttl,
+            data,
+        });
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut ret = Vec::with_capacity(512);
+        ret.extend_from_slice(&self.id.to_be_bytes());
+        let flags = ((self.response as u16) << 15)
+            | (((self.opcode & 0x0f) as u16) << 11)
+            | ((self.authoritative as u16) << 10)
+            | ((self.truncated as u16) << 9)
+            | ((self.recursion_desired as u16) << 8)
+            | (self.rcode & 0x0f);
+        ret.extend_from_slice(&flags.to_be_bytes());
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ] {
+            let count = u16::try_from(count)
+                .map_err(|_| Error::ParseError("too many dns records".to_string()))?;
+            ret.extend_from_slice(&count.to_be_bytes());
+        }
+
+        for question in self.questions.iter() {
+            write_name(&mut ret, &question.name)?;
+            ret.extend_from_slice(&question.r#type.to_be_bytes());
+            ret.extend_from_slice(&question.class.to_be_bytes());
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(self.authorities.iter())
+            .chain(self.additionals.iter())
+        {
+            write_record(&mut ret, record)?;
+        }
+        Ok(ret)
+    }
+
+    pub fn decode(packet: &[u8]) -> Result<Self> {
+        Ok(parse(packet)?.0)
+    }
+}
+
+// Message of `packet` with where its TSIG record starts, if it has one
+fn parse(packet: &[u8]) -> Result<(Message, Option<usize>)> {
+    let err = || Error::ParseError("malformed dns message".to_string());
+    let header = packet.get(..12).ok_or_else(err)?;
+    let word = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]);
+    let flags = word(2);
+    let mut ret = Message {
+        id: word(0),
+        response: flags & 0x8000 != 0,
+        opcode: ((flags >> 11) & 0x0f) as u8,
+        authoritative: flags & 0x0400 != 0,
+        truncated: flags & 0x0200 != 0,
+        recursion_desired: flags & 0x0100 != 0,
+        rcode: flags & 0x0f,
+        ..Default::default()
+    };
+
+    let mut pos = 12;
+    for _ in 0..word(4) {
+        let (name, next) = read_name(packet, pos)?;
+        let fixed = packet.get(next..next + 4).ok_or_else(err)?;
+        ret.questions.push(Question {
+            name,
+            r#type: u16::from_be_bytes([fixed[0], fixed[1]]),
+            class: u16::from_be_bytes([fixed[2], fixed[3]]),
+        });
+        pos = next + 4;
+    }
+
+    let mut tsig = None;
+    for (section, count) in [word(6), word(8), word(10)].into_iter().enumerate() {
+        for _ in 0..count {
+            let start = pos;
+            let (record, next) = read_record(packet, pos)?;
+            pos = next;
+            match section {
+                0 => ret.answers.push(record),
+                1 => ret.authorities.push(record),
+                _ => {
+                    if record.r#type == TYPE_TSIG {
+                        tsig = Some(start);
+                    }
+                    ret.additionals.push(record);
+                }
+            }
+        }
+    }
+    // Only the last record may be a TSIG one
+    if tsig.is_some() && ret.additionals.last().map(|r| r.r#type) != Some(TYPE_TSIG) {
+        return Err(Error::ParseError(
+            "dns message has a TSIG record before its end".to_string(),
+        ));
+    }
+    Ok((ret, tsig))
+}
+
+fn new_id() -> u16 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    (nanos ^ std::process::id()) as u16
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) -> Result<()> {
+    let err = || Error::ParseError(format!("invalid dns name {}", name));
+    let name = name.trim_end_matches('.');
+    let start = buf.len();
+    if !name.is_empty() {
+        for label in name.split('.') {
+            if label.is_empty() || label.len() > 63 {
+                return Err(err());
+            }
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+    }
+    buf.push(0);
+    if buf.len() - start > 255 {
+        return Err(err());
+    }
+    Ok(())
+}
+
+fn write_record(buf: &mut Vec<u8>, record: &Record) -> Result<()> {
+    write_name(buf, &record.name)?;
+    buf.extend_from_slice(&record.r#type.to_be_bytes());
+    buf.extend_from_slice(&record.class.to_be_bytes());
+    buf.extend_from_slice(&record.ttl.to_be_bytes());
+
+    let len_pos = buf.len();
+    buf.extend_from_slice(&[0, 0]);
+    match &record.data {
+        RData::A(ip) => buf.extend_from_slice(&ip.octets()),
+        RData::AAAA(ip) => buf.extend_from_slice(&ip.octets()),
+        RData::Name(name) => write_name(buf, name)?,
+        RData::Soa(soa) => {
+            write_name(buf, &soa.mname)?;
+            write_name(buf, &soa.rname)?;
+            for v in [soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum] {
+                buf.extend_from_slice(&v.to_be_bytes());
+            }
+        }
+        RData::Raw(data) => buf.extend_from_slice(data),
+    }
+    let len = u16::try_from(buf.len() - len_pos - 2)
+        .map_err(|_| Error::ParseError(format!("dns record {} is too long", record.name)))?;
+    buf[len_pos..len_pos + 2].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+// Read a record, return it with the position right after it
+fn read_record(packet: &[u8], pos: usize) -> Result<(Record, usize)> {
+    let err = || Error::ParseError("malformed dns record".to_string());
+    let (name, next) = read_name(packet, pos)?;
+    let fixed = packet.get(next..next + 10).ok_or_else(err)?;
+    let r#type = u16::from_be_bytes([fixed[0], fixed[1]]);
+    let class = u16::from_be_bytes([fixed[2], fixed[3]]);
+    let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+    let rdlength = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+    let rdata_pos = next + 10;
+    let rdata = packet
+        .get(rdata_pos..rdata_pos + rdlength)
+        .ok_or_else(err)?;
+
+    let data = match r#type {
+        // Deletes of an update carry no data
+        _ if rdlength == 0 => RData::Raw(vec![]),
+        TYPE_A if rdlength == 4 => RData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+        TYPE_AAAA if rdlength == 16 => {
+            let octets: [u8; 16] = rdata.try_into().map_err(|_| err())?;
+            RData::AAAA(Ipv6Addr::from(octets))
+        }
+        TYPE_CNAME | TYPE_NS => RData::Name(read_name(packet, rdata_pos)?.0),
+        TYPE_SOA => {
+            let (mname, next) = read_name(packet, rdata_pos)?;
+            let (rname, next) = read_name(packet, next)?;
+            let fixed = packet.get(next..next + 20).ok_or_else(err)?;
+            let v = |i: usize| u32::from_be_bytes(fixed[i..i + 4].try_into().unwrap());
+            RData::Soa(Soa {
+                mname,
+                rname,
+                serial: v(0),
+                refresh: v(4),
+                retry: v(8),
+                expire: v(12),
+                minimum: v(16),
+            })
+        }
+        _ => RData::Raw(rdata.to_vec()),
+    };
+    Ok((
+        Record {
+            name,
+            r#type,
+            class,
+            ttl,
+            data,
+        },
+        rdata_pos + rdlength,
+    ))
+}
+
+///////////////////////////////////////////////////////////
+// TSIG
+///////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TsigAlgorithm {
+    HmacSha256,
+    HmacSha384,
+    HmacSha512,
+}
+
+impl TsigAlgorithm {
+    // Names as BIND and Knot configure them, with or without the trailing dot
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim_end_matches('.').to_ascii_lowercase().as_str() {
+            "hmac-sha256" => Some(Self::HmacSha256),
+            "hmac-sha384" => Some(Self::HmacSha384),
+            "hmac-sha512" => Some(Self::HmacSha512),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::HmacSha256 => "hmac-sha256",
+            Self::HmacSha384 => "hmac-sha384",
+            Self::HmacSha512 => "hmac-sha512",
+        }
+    }
+
+    fn mac(&self, secret: &[u8]) -> TsigMac {
+        // HMAC takes keys of any length
+        match self {
+            Self::HmacSha256 => TsigMac::Sha256(Hmac::new_from_slice(secret).unwrap()),
+            Self::HmacSha384 => TsigMac::Sha384(Hmac::new_from_slice(secret).unwrap()),
+            Self::HmacSha512 => TsigMac::Sha512(Hmac::new_from_slice(secret).unwrap()),
+        }
+    }
+}
+
+enum TsigMac {
+    Sha256(Hmac<Sha256>),
+    Sha384(Hmac<Sha384>),
+    Sha512(Hmac<Sha512>),
+}
+
+impl TsigMac {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(m) => m.update(data),
+            Self::Sha384(m) => m.update(data),
+            Self::Sha512(m) => m.update(data),
+        }
+    }
+
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Self::Sha256(m) => m.finalize().into_bytes().to_vec(),
+            Self::Sha384(m) => m.finalize().into_bytes().to_vec(),
+            Self::Sha512(m) => m.finalize().into_bytes().to_vec(),
+        }
+    }
+
+    // Compare in constant time
+    fn verify(self, mac: &[u8]) -> bool {
+        match self {
+            Self::Sha256(m) => m.verify_slice(mac).is_ok(),
+            Self::Sha384(m) => m.verify_slice(mac).is_ok(),
+            Self::Sha512(m) => m.verify_slice(mac).is_ok(),
+        }
+    }
+}
+
+// The data of a TSIG record
+#[derive(Debug, Clone, PartialEq)]
+struct Tsig {
+    algorithm: String,
+    time_signed: u64,
+    fudge: u16,
+    mac: Vec<u8>,
+    original_id: u16,
+    error: u16,
+    other: Vec<u8>,
+}
+
+impl Tsig {
+    fn decode(record: &Record) -> Result<Self> {
+        let err = || Error::ParseError("malformed TSIG record".to_string());
+        let RData::Raw(data) = &record.data else {
+            return Err(err());
+        };
+        let (algorithm, pos) = read_name(data, 0)?;
+        let word = |i: usize| -> Result<u16> {
+            let b = data.get(i..i + 2).ok_or_else(err)?;
+            Ok(u16::from_be_bytes([b[0], b[1]]))
+        };
+        let time = data.get(pos..pos + 6).ok_or_else(err)?;
+        let time_signed = time.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        let fudge = word(pos + 6)?;
+        let mac_len = word(pos + 8)? as usize;
+        let mac = data.get(pos + 10..pos + 10 + mac_len).ok_or_else(err)?;
+        let pos = pos + 10 + mac_len;
+        let other_len = word(pos + 4)? as usize;
+        Ok(Self {
+            algorithm,
+            time_signed,
+            fudge,
+            mac: mac.to_vec(),
+            original_id: word(pos)?,
+            error: word(pos + 2)?,
+            other: data
+                .get(pos + 6..pos + 6 + other_len)
+                .ok_or_else(err)?
+                .to_vec(),
+        })
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut ret = vec![];
+        write_name(&mut ret, &self.algorithm)?;
+        ret.extend_from_slice(&self.time_signed.to_be_bytes()[2..]);
+        ret.extend_from_slice(&self.fudge.to_be_bytes());
+        ret.extend_from_slice(&(self.mac.len() as u16).to_be_bytes());
+        ret.extend_from_slice(&self.mac);
+        ret.extend_from_slice(&self.original_id.to_be_bytes());
+        ret.extend_from_slice(&self.error.to_be_bytes());
+        ret.extend_from_slice(&(self.other.len() as u16).to_be_bytes());
+        ret.extend_from_slice(&self.other);
+        Ok(ret)
+    }
+}
+
+#[derive(Clone)]
+pub struct TsigKey {
+    pub name: String,
+    pub algorithm: TsigAlgorithm,
+    secret: Vec<u8>,
+}
+
+// Never prints the secret
+impl std::fmt::Debug for TsigKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TsigKey")
+            .field("name", &self.name)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TsigKey {
+    // Key of `name` with its secret in base64, as `tsig-keygen` and
+    // `keymgr` print it
+    pub fn new(name: &str, algorithm: TsigAlgorithm, secret: &str) -> Result<Self> {
+        let secret = BASE64.decode(secret.trim()).map_err(|e| {
+            Error::ParseError(format!("invalid secret of TSIG key {}: {}", name, e))
+        })?;
+        Ok(Self {
+            name: name.trim_end_matches('.').to_ascii_lowercase(),
+            algorithm,
+            secret,
+        })
+    }
+
+    // Sign `msg` at `now`, in seconds since the epoch. Returns the packet
+    // with its MAC, which the responses are signed over.
+    pub fn sign(&self, msg: &Message, now: u64) -> Result<(Vec<u8>, Vec<u8>)> {
+        self.sign_over(msg, None, true, now)
+    }
+
+    // Sign a response, as a server does, over the MAC of the request or of
+    // the message before it in a zone transfer, whose messages after the
+    // first cover the timers only
+    #[cfg(test)]
+    pub(crate) fn sign_response(
+        &self,
+        msg: &Message,
+        prior_mac: &[u8],
+        first: bool,
+        now: u64,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        self.sign_over(msg, Some(prior_mac), first, now)
+    }
+
+    fn sign_over(
+        &self,
+        msg: &Message,
+        prior_mac: Option<&[u8]>,
+        first: bool,
+        now: u64,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut packet = msg.encode()?;
+        let mut tsig = Tsig {
+            algorithm: self.algorithm.as_str().to_string(),
+            time_signed: now,
+            fudge: TSIG_FUDGE,
+            mac: vec![],
+            original_id: msg.id,
+            error: 0,
+            other: vec![],
+        };
+        let mut mac = self.algorithm.mac(&self.secret);
+        if let Some(prior_mac) = prior_mac {
+            mac.update(&(prior_mac.len() as u16).to_be_bytes());
+            mac.update(prior_mac);
+        }
+        mac.update(&packet);
+        if first {
+            mac.update(&self.variables(&tsig)?);
+        } else {
+            mac.update(&timers(&tsig));
+        }
+        tsig.mac = mac.finalize();
+
+        write_record(
+            &mut packet,
+            &Record {
+                name: self.name.clone(),
+                r#type: TYPE_TSIG,
+                class: CLASS_ANY,
+                ttl: 0,
+                data: RData::Raw(tsig.encode()?),
+            },
+        )?;
+        let arcount = u16::from_be_bytes([packet[10], packet[11]]) + 1;
+        packet[10..12].copy_from_slice(&arcount.to_be_bytes());
+        Ok((packet, tsig.mac))
+    }
+
+    // What the MAC covers besides the message: the key name, class and TTL
+    // of the TSIG record, then its data but the MAC and original id
+    fn variables(&self, tsig: &Tsig) -> Result<Vec<u8>> {
+        let mut ret = vec![];
+        write_name(&mut ret, &self.name)?;
+        ret.extend_from_slice(&CLASS_ANY.to_be_bytes());
+        ret.extend_from_slice(&0u32.to_be_bytes());
+        write_name(&mut ret, &tsig.algorithm.to_ascii_lowercase())?;
+        ret.extend_from_slice(&timers(tsig));
+        ret.extend_from_slice(&tsig.error.to_be_bytes());
+        ret.extend_from_slice(&(tsig.other.len() as u16).to_be_bytes());
+        ret.extend_from_slice(&tsig.other);
+        Ok(ret)
+    }
+}
+
+fn timers(tsig: &Tsig) -> Vec<u8> {
+    let mut ret = tsig.time_signed.to_be_bytes()[2..].to_vec();
+    ret.extend_from_slice(&tsig.fudge.to_be_bytes());
+    ret
+}
+
+fn tsig_error(reason: String) -> Error {
+    Error::Auth {
+        provider: String::new(),
+        reason,
+    }
+}
+
+// Checks the TSIG of the responses to one signed request: a single one, or
+// every message of a zone transfer, each signed over the MAC before it
+pub struct TsigVerifier<'a> {
+    key: &'a TsigKey,
+    prior_mac: Vec<u8>,
+    // Unsigned messages since the last signed one
+    unsigned: Vec<u8>,
+    unsigned_count: usize,
+    first: bool,
+}
+
+impl<'a> TsigVerifier<'a> {
+    // Verifier of the responses to a request signed with `request_mac`, or
+    // of a request, as a server checks it, without one
+    pub fn new(key: &'a TsigKey, request_mac: Vec<u8>) -> Self {
+        Self {
+            key,
+            prior_mac: request_mac,
+            unsigned: vec![],
+            unsigned_count: 0,
+            first: true,
+        }
+    }
+
+    pub fn verify(&mut self, packet: &[u8], now: u64) -> Result<Message> {
+        let (mut msg, tsig_at) = parse(packet)?;
+        let Some(tsig_at) = tsig_at else {
+            if self.first || self.unsigned_count >= TSIG_UNSIGNED_MAX {
+                return Err(tsig_error(format!(
+                    "response is not signed with TSIG key {}",
+                    self.key.name
+                )));
+            }
+            self.unsigned.extend_from_slice(packet);
+            self.unsigned_count += 1;
+            return Ok(msg);
+        };
+
+        let record = msg.additionals.pop().unwrap();
+        let tsig = Tsig::decode(&record)?;
+        if tsig.error != 0 {
+            return Err(tsig_error(format!(
+                "server rejected TSIG key {}: {}",
+                self.key.name,
+                rcode_name(tsig.error)
+            )));
+        }
+        if !record.name.eq_ignore_ascii_case(&self.key.name)
+            || TsigAlgorithm::parse(&tsig.algorithm) != Some(self.key.algorithm)
+        {
+            return Err(tsig_error(format!(
+                "response is signed with key {} {} instead of {} {}",
+                record.name,
+                tsig.algorithm,
+                self.key.name,
+                self.key.algorithm.as_str()
+            )));
+        }
+
+        // The message as it was before the TSIG record was added
+        let mut unsigned = packet[..tsig_at].to_vec();
+        unsigned[..2].copy_from_slice(&tsig.original_id.to_be_bytes());
+        let arcount = u16::from_be_bytes([unsigned[10], unsigned[11]]) - 1;
+        unsigned[10..12].copy_from_slice(&arcount.to_be_bytes());
+
+        let mut mac = self.key.algorithm.mac(&self.key.secret);
+        if !self.prior_mac.is_empty() {
+            mac.update(&(self.prior_mac.len() as u16).to_be_bytes());
+            mac.update(&self.prior_mac);
+        }
+        mac.update(&self.unsigned);
+        mac.update(&unsigned);
+        if self.first {
+            mac.update(&self.key.variables(&tsig)?);
+        } else {
+            mac.update(&timers(&tsig));
+        }
+        if !mac.verify(&tsig.mac) {
+            return Err(tsig_error(format!(
+                "response signature of TSIG key {} does not match",
+                self.key.name
+            )));
+        }
+        if now.abs_diff(tsig.time_signed) > tsig.fudge as u64 {
+            return Err(tsig_error(format!(
+                "response is signed {} seconds apart from the local clock",
+                now.abs_diff(tsig.time_signed)
+            )));
+        }
+
+        self.prior_mac = tsig.mac;
+        self.unsigned.clear();
+        self.unsigned_count = 0;
+        self.first = false;
+        Ok(msg)
+    }
+
+    // MAC of the last signed message, the one its response is signed over
+    #[cfg(test)]
+    pub(crate) fn mac(&self) -> &[u8] {
+        &self.prior_mac
+    }
+
+    // The last message of a zone transfer has to be signed
+    pub fn finish(&self) -> Result<()> {
+        if self.unsigned_count > 0 {
+            return Err(tsig_error(
+                "zone transfer does not end with a signed message".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+///////////////////////////////////////////////////////////
+// Client
+///////////////////////////////////////////////////////////
+
+// Exchanges messages with an authoritative server over TCP, a connection per
+// exchange, signing them when a key is given
+#[derive(Debug, Clone)]
+pub struct Client {
+    // `host:port`
+    server: String,
+    key: Option<TsigKey>,
+    timeout: Duration,
+}
+
+impl Client {
+    // `server` is a host name or address, with an optional port
+    pub fn new(server: &str, key: Option<TsigKey>, timeout: Duration) -> Self {
+        let server = if server.parse::<SocketAddr>().is_ok() {
+            server.to_string()
+        } else if let Ok(ip) = server.trim_matches(['[', ']']).parse::<IpAddr>() {
+            SocketAddr::new(ip, DEFAULT_PORT).to_string()
+        } else if server.contains(':') {
+            server.to_string()
+        } else {
+            format!("{}:{}", server, DEFAULT_PORT)
+        };
+        Self {
+            server,
+            key,
+            timeout,
+        }
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn key(&self) -> Option<&TsigKey> {
+        self.key.as_ref()
+    }
+
+    // Send `msg` and return the response. Its TSIG is checked, except for
+    // unsigned error responses of servers not knowing the key, and removed.
+    pub async fn send(&self, msg: &Message) -> Result<Message> {
+        let (packet, mac) = self.sign(msg)?;
+        let mut stream = self.connect().await?;
+        self.write(&mut stream, &packet).await?;
+        loop {
+            let packet = self.read(&mut stream).await?;
+            let (resp, tsig) = parse(&packet)?;
+            if resp.id != msg.id {
+                continue;
+            }
+            return match (&self.key, tsig) {
+                (Some(key), Some(_)) => TsigVerifier::new(key, mac).verify(&packet, unix_now()),
+                (Some(key), None) if resp.rcode == RCODE_NOERROR => Err(tsig_error(format!(
+                    "response is not signed with TSIG key {}",
+                    key.name
+                ))),
+                _ => Ok(resp),
+            };
+        }
+    }
+
+    // Every record of `zone` by a zone transfer, the SOA record first. An
+    // error rcode is returned as an error.
+    pub async fn transfer(&self, zone: &str) -> Result<Vec<Record>> {
+        let msg = Message::query(zone, TYPE_AXFR);
+        let (packet, mac) = self.sign(&msg)?;
+        let mut stream = self.connect().await?;
+        self.write(&mut stream, &packet).await?;
+
+        let mut verifier = self.key.as_ref().map(|k| TsigVerifier::new(k, mac));
+        let mut ret: Vec<Record> = vec![];
+        loop {
+            let packet = self.read(&mut stream).await?;
+            let resp = match verifier.as_mut() {
+                Some(verifier) if Message::decode(&packet)?.rcode == RCODE_NOERROR => {
+                    verifier.verify(&packet, unix_now())?
+                }
+                _ => Message::decode(&packet)?,
+            };
+            if resp.rcode != RCODE_NOERROR {
+                return Err(rcode_error(&resp, &format!("transfer of zone {}", zone)));
+            }
+            for record in resp.answers {
+                let soa = record.r#type == TYPE_SOA;
+                ret.push(record);
+                // The SOA record opens and closes the transfer
+                if soa && ret.len() > 1 {
+                    if let Some(verifier) = verifier.as_ref() {
+                        verifier.finish()?;
+                    }
+                    return Ok(ret);
+                }
+            }
+            if ret.first().is_none_or(|r| r.r#type != TYPE_SOA) {
+                return Err(Error::ParseError(format!(
+                    "transfer of zone {} does not start with its SOA record",
+                    zone
+                )));
+            }
+        }
+    }
+
+    fn sign(&self, msg: &Message) -> Result<(Vec<u8>, Vec<u8>)> {
+        match &self.key {
+            Some(key) => key.sign(msg, unix_now()),
+            None => Ok((msg.encode()?, vec![])),
+        }
+    }
+
+    fn timed_out(&self) -> Error {
+        Error::IoError(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("dns server {} timed out", self.server),
+        ))
+    }
+
+    async fn connect(&self) -> Result<TcpStream> {
+        tokio::time::timeout(self.timeout, TcpStream::connect(&self.server))
+            .await
+            .map_err(|_| self.timed_out())?
+            .map_err(|e| {
+                Error::IoError(io::Error::new(e.kind(), format!("{}: {}", self.server, e)))
+            })
+    }
+
+    async fn write(&self, stream: &mut TcpStream, packet: &[u8]) -> Result<()> {
+        tokio::time::timeout(self.timeout, write_message(stream, packet))
+            .await
+            .map_err(|_| self.timed_out())?
+    }
+
+    async fn read(&self, stream: &mut TcpStream) -> Result<Vec<u8>> {
+        tokio::time::timeout(self.timeout, read_message(stream))
+            .await
+            .map_err(|_| self.timed_out())?
+    }
+}
+
+// Read a length prefixed message of a TCP stream
+pub async fn read_message<S: AsyncReadExt + Unpin>(stream: &mut S) -> Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let mut ret = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut ret).await?;
+    Ok(ret)
+}
+
+// Write a message to a TCP stream, prefixed with its length
+pub async fn write_message<S: AsyncWriteExt + Unpin>(stream: &mut S, packet: &[u8]) -> Result<()> {
+    let len = u16::try_from(packet.len())
+        .map_err(|_| Error::ParseError("dns message is too long".to_string()))?;
+    let mut buf = len.to_be_bytes().to_vec();
+    buf.extend_from_slice(packet);
+    stream.write_all(&buf).await?;
+    Ok(())
+}
+
+// Error of a response with an error rcode to `what`
+pub fn rcode_error(resp: &Message, what: &str) -> Error {
+    let reason = format!("{} failed: {}", what, rcode_name(resp.rcode));
+    match resp.rcode {
+        RCODE_NOTAUTH | RCODE_REFUSED => Error::Auth {
+            provider: String::new(),
+            reason,
+        },
+        _ => Error::Provider(reason),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key() -> TsigKey {
+        TsigKey::new(
+            "ddns-key.",
+            TsigAlgorithm::HmacSha256,
+            "c2VjcmV0IG9mIHRoZSB0ZXN0IGtleQ==",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut msg = Message::update("example.org");
+        msg.delete_rrset("home.example.org", TYPE_A);
+        msg.add_record(
+            "home.example.org",
+            TYPE_A,
+            300,
+            RData::A("1.2.3.4".parse().unwrap()),
+        );
+        msg.add_record(
+            "www.example.org",
+            TYPE_CNAME,
+            60,
+            RData::Name("home.example.org".to_string()),
+        );
+        let packet = msg.encode().unwrap();
+        assert_eq!(packet[2], OPCODE_UPDATE << 3);
+        assert_eq!(Message::decode(&packet).unwrap(), msg);
+
+        let mut soa = Message::query("example.org", TYPE_SOA);
+        soa.response = true;
+        soa.authoritative = true;
+        soa.answers.push(Record {
+            name: "example.org".to_string(),
+            r#type: TYPE_SOA,
+            class: CLASS_IN,
+            ttl: 3600,
+            data: RData::Soa(Soa {
+                mname: "ns1.example.org".to_string(),
+                rname: "hostmaster.example.org".to_string(),
+                serial: 2024010101,
+                refresh: 7200,
+                retry: 900,
+                expire: 1209600,
+                minimum: 300,
+            }),
+        });
+        let packet = soa.encode().unwrap();
+        assert_eq!(Message::decode(&packet).unwrap(), soa);
+
+        assert!(Message::query("a..org", TYPE_A).encode().is_err());
+        assert!(Message::decode(&packet[..packet.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn test_tsig_sign() {
+        let key = key();
+        let mut msg = Message::update("example.org");
+        msg.id = 0x1234;
+        let (packet, mac) = key.sign(&msg, 1_700_000_000).unwrap();
+        // HMAC-SHA256 of the message and the TSIG variables, as RFC 8945 lays
+        // them out
+        assert_eq!(
+            hex::encode(&mac),
+            "756b042fc4fe063f1ff64263c0243b0f34b54e8f9b924710194e4eb5e11fb66d"
+        );
+
+        let (decoded, tsig_at) = parse(&packet).unwrap();
+        assert_eq!(tsig_at, Some(msg.encode().unwrap().len()));
+        let record = decoded.additionals.last().unwrap();
+        assert_eq!(record.name, "ddns-key");
+        assert_eq!(record.class, CLASS_ANY);
+        let tsig = Tsig::decode(record).unwrap();
+        assert_eq!(tsig.algorithm, "hmac-sha256");
+        assert_eq!(tsig.time_signed, 1_700_000_000);
+        assert_eq!(tsig.fudge, TSIG_FUDGE);
+        assert_eq!(tsig.original_id, msg.id);
+        assert_eq!(tsig.mac, mac);
+    }
+
+    #[test]
+    fn test_tsig_verify() {
+        let key = key();
+        let now = 1_700_000_000;
+        let (_, request_mac) = key.sign(&Message::update("example.org"), now).unwrap();
+
+        let mut resp = Message::update("example.org");
+        resp.response = true;
+        let (packet, _) = key.sign_response(&resp, &request_mac, true, now).unwrap();
+        let verified = TsigVerifier::new(&key, request_mac.clone())
+            .verify(&packet, now + 10)
+            .unwrap();
+        assert_eq!(verified, resp);
+
+        // Another request, a tampered message or a skewed clock fail
+        assert!(
+            TsigVerifier::new(&key, vec![0; 32])
+                .verify(&packet, now)
+                .is_err()
+        );
+        let mut tampered = packet.clone();
+        tampered[3] ^= 0x01;
+        assert!(
+            TsigVerifier::new(&key, request_mac.clone())
+                .verify(&tampered, now)
+                .is_err()
+        );
+        let err = TsigVerifier::new(&key, request_mac.clone())
+            .verify(&packet, now + 1000)
+            .unwrap_err();
+        assert!(err.is_auth());
+        // Messages of a zone transfer are signed over the one before, the
+        // last one has to be signed
+        let (first, mac) = key.sign_response(&resp, &request_mac, true, now).unwrap();
+        let (second, _) = key.sign_response(&resp, &mac, false, now).unwrap();
+        let mut verifier = TsigVerifier::new(&key, request_mac.clone());
+        verifier.verify(&first, now).unwrap();
+        verifier.verify(&second, now).unwrap();
+        verifier.finish().unwrap();
+        let mut verifier = TsigVerifier::new(&key, request_mac.clone());
+        verifier.verify(&first, now).unwrap();
+        verifier.verify(&resp.encode().unwrap(), now).unwrap();
+        assert!(verifier.finish().is_err());
+
+        // An unsigned response is rejected
+        let err = TsigVerifier::new(&key, request_mac)
+            .verify(&resp.encode().unwrap(), now)
+            .unwrap_err();
+        assert!(err.is_auth());
+    }
+
+    #[test]
+    fn test_client_server() {
+        let cases = [
+            ("192.0.2.1", "192.0.2.1:53"),
+            ("192.0.2.1:5353", "192.0.2.1:5353"),
+            ("2001:db8::1", "[2001:db8::1]:53"),
+            ("[2001:db8::1]:5353", "[2001:db8::1]:5353"),
+            ("ns1.example.org", "ns1.example.org:53"),
+            ("ns1.example.org:5353", "ns1.example.org:5353"),
+        ];
+        for (server, expected) in cases {
+            let cli = Client::new(server, None, Duration::from_secs(1));
+            assert_eq!(cli.server(), expected);
+        }
+        assert_eq!(
+            TsigAlgorithm::parse("HMAC-SHA512."),
+            Some(TsigAlgorithm::HmacSha512)
+        );
+        assert_eq!(TsigAlgorithm::parse("hmac-md5"), None);
+        assert!(TsigKey::new("k", TsigAlgorithm::HmacSha256, "not base64!").is_err());
+    }
+}
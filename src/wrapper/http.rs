@@ -1,6 +1,150 @@
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use async_trait::async_trait;
 
 use crate::error::{Error, Result};
+use crate::types::DnsConfig;
+use crate::types::HttpConfig;
+use crate::wrapper::resolver::Resolver;
+
+static HTTP_CONFIG: Mutex<Option<HttpConfig>> = Mutex::new(None);
+static SHARED_CLIENTS: Mutex<Option<SharedClients>> = Mutex::new(None);
+static SHARED_RESOLVER: Mutex<Option<Resolver>> = Mutex::new(None);
+
+// Longer than the usual check interval, so a daemon finds the connection of
+// the last cycle still open
+const DEFAULT_POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+pub fn set_config(cfg: HttpConfig) -> Result<()> {
+    let mut guard = HTTP_CONFIG
+        .lock()
+        .map_err(|_| Error::HttpError("failed to lock http config".to_string()))?;
+    // Installing the same settings again keeps the open connections
+    if guard.as_ref() == Some(&cfg) && lock_shared_clients()?.is_some() {
+        return Ok(());
+    }
+
+    // Build the clients now so that a bad proxy or CA bundle is reported early
+    let clients = SharedClients::build(&cfg)?;
+    *guard = Some(cfg);
+    *lock_shared_clients()? = Some(clients);
+    Ok(())
+}
+
+// Clients of the process wide settings shared by every request, so
+// connections and TLS sessions are pooled across fetch cycles. Fetchers asking
+// for one address family get the client bound to it.
+#[derive(Clone)]
+struct SharedClients {
+    any: reqwest::Client,
+    v4: reqwest::Client,
+    v6: reqwest::Client,
+}
+
+impl SharedClients {
+    fn build(cfg: &HttpConfig) -> Result<Self> {
+        let v4 = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        let v6 = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+        Ok(Self {
+            any: client_builder_with(cfg)?.build()?,
+            v4: client_builder_with(cfg)?.local_address(v4).build()?,
+            v6: client_builder_with(cfg)?.local_address(v6).build()?,
+        })
+    }
+
+    // The clients of the current settings, built on first use
+    fn get() -> Result<Self> {
+        if let Some(clients) = lock_shared_clients()?.as_ref() {
+            return Ok(clients.clone());
+        }
+        let cfg = HTTP_CONFIG
+            .lock()
+            .map_err(|_| Error::HttpError("failed to lock http config".to_string()))?
+            .clone()
+            .unwrap_or_default();
+        let clients = Self::build(&cfg)?;
+        Ok(lock_shared_clients()?.get_or_insert(clients).clone())
+    }
+}
+
+fn lock_shared_clients() -> Result<std::sync::MutexGuard<'static, Option<SharedClients>>> {
+    SHARED_CLIENTS
+        .lock()
+        .map_err(|_| Error::HttpError("failed to lock http clients".to_string()))
+}
+
+// Every client of the same `dns` settings resolves through one resolver, so
+// they share its cache
+fn shared_resolver(cfg: &DnsConfig) -> Result<Option<Resolver>> {
+    if cfg.is_system() {
+        return Ok(None);
+    }
+    let mut guard = SHARED_RESOLVER
+        .lock()
+        .map_err(|_| Error::HttpError("failed to lock dns resolver".to_string()))?;
+    if let Some(resolver) = guard.as_ref()
+        && resolver.config() == cfg
+    {
+        return Ok(Some(resolver.clone()));
+    }
+    let resolver = Resolver::new(cfg.clone());
+    *guard = Some(resolver.clone());
+    Ok(Some(resolver))
+}
+
+fn client_builder() -> Result<reqwest::ClientBuilder> {
+    let cfg = HTTP_CONFIG
+        .lock()
+        .map_err(|_| Error::HttpError("failed to lock http config".to_string()))?
+        .clone()
+        .unwrap_or_default();
+    client_builder_with(&cfg)
+}
+
+// Connections are kept open and alive between requests, HTTP/2 is negotiated
+// with the servers offering it
+fn client_builder_with(cfg: &HttpConfig) -> Result<reqwest::ClientBuilder> {
+    let keep_alive = cfg.keep_alive.unwrap_or(DEFAULT_KEEP_ALIVE);
+    let mut builder = reqwest::Client::builder()
+        .pool_idle_timeout(cfg.pool_idle_timeout.unwrap_or(DEFAULT_POOL_IDLE_TIMEOUT))
+        .tcp_keepalive(keep_alive)
+        .http2_keep_alive_interval(keep_alive)
+        .http2_keep_alive_while_idle(true)
+        .http2_adaptive_window(true);
+
+    if let Some(timeout) = cfg.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = cfg.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(proxy) = cfg.proxy.as_ref() {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+    if let Some(user_agent) = cfg.user_agent.as_ref() {
+        builder = builder.user_agent(user_agent);
+    }
+    if let Some(max) = cfg.max_redirects {
+        builder = builder.redirect(reqwest::redirect::Policy::limited(max));
+    }
+    if let Some(path) = cfg.ca_bundle.as_ref() {
+        let pem = std::fs::read(path)?;
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(resolver) = shared_resolver(&cfg.dns)? {
+        builder = builder.dns_resolver(Arc::new(resolver));
+    }
+
+    Ok(builder)
+}
 
 #[derive(Debug, Clone)]
 pub enum HeaderKey {
@@ -23,14 +167,30 @@ impl HeaderKey {
 pub struct Response {
     pub status: u16,
     pub body: String,
+    // Retry-After of a 429 or 503 answer, when given in seconds
+    pub retry_after: Option<Duration>,
 }
 
 impl Response {
+    pub(crate) async fn read(response: reqwest::Response) -> Result<Self> {
+        let retry_after = response
+            .headers()
+            .get("retry-after")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        Ok(Self {
+            status: response.status().into(),
+            retry_after,
+            body: response.text().await?,
+        })
+    }
+
     pub fn into_body(self) -> Result<String> {
         if self.status == 200 {
             Ok(self.body)
         } else {
-            Err(Error::HttpError(format!("status: {}", self.status)))
+            Err(Error::from_status(self.status, self.retry_after))
         }
     }
 }
@@ -45,23 +205,242 @@ impl Header {
     pub fn new(key: HeaderKey, value: String) -> Self {
         Self { key, value }
     }
+
+    pub fn name(&self) -> &str {
+        self.key.as_str()
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
 }
 
-pub struct Client {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+        }
+    }
+}
+
+// A request as a transport sends it, default headers of the client included
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<Header>,
+    pub body: Option<String>,
+}
+
+// Sends the requests of a client. Providers talk HTTP through it so they can
+// be tested against canned answers instead of the live API.
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+    async fn send(&self, request: Request) -> Result<Response>;
+}
+
+// Transport of the process wide HTTP settings, see `HttpConfig`
+pub struct ReqwestTransport {
     cli: reqwest::Client,
-    dft_headers: Vec<Header>,
 }
 
-impl Default for Client {
-    fn default() -> Self {
-        Self::new()
+impl ReqwestTransport {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            cli: SharedClients::get()?.any,
+        })
+    }
+
+    pub fn new_with_timeout(timeout: Duration) -> Result<Self> {
+        Ok(Self {
+            cli: client_builder()?.timeout(timeout).build()?,
+        })
+    }
+
+    // Transport also trusting the certificates of `ca_bundle`, for services
+    // of a private CA like the Kubernetes API
+    pub fn new_with_ca(ca_bundle: &Path) -> Result<Self> {
+        let mut builder = client_builder()?;
+        let pem = std::fs::read(ca_bundle)?;
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+        Ok(Self {
+            cli: builder.build()?,
+        })
+    }
+
+    // GET whose body is read line by line as it comes, for watch streams.
+    // No timeout applies to the body.
+    pub async fn get_lines(&self, url: &str, headers: &[Header]) -> Result<(u16, LineStream)> {
+        let mut builder = self
+            .cli
+            .get(url)
+            .timeout(Duration::from_secs(u32::MAX.into()));
+        for header in headers.iter() {
+            builder = builder.header(header.name(), header.value());
+        }
+        let response = send_logged("GET", url, builder).await?;
+        let status = response.status().as_u16();
+        let lines = LineStream {
+            response,
+            pending: vec![],
+        };
+        Ok((status, lines))
     }
 }
 
-impl Client {
+// Body of a streamed answer, see `ReqwestTransport::get_lines`
+pub struct LineStream {
+    response: reqwest::Response,
+    pending: Vec<u8>,
+}
+
+impl LineStream {
+    // Next line without its line break, `None` at the end of the body
+    pub async fn line(&mut self) -> Result<Option<String>> {
+        loop {
+            if let Some(line) = take_line(&mut self.pending) {
+                return Ok(Some(line));
+            }
+            match self.response.chunk().await? {
+                Some(data) => self.pending.extend_from_slice(&data),
+                None => return Ok(take_rest(&mut self.pending)),
+            }
+        }
+    }
+}
+
+// First complete line of `pending`, removed from it
+pub(crate) fn take_line(pending: &mut Vec<u8>) -> Option<String> {
+    let pos = pending.iter().position(|b| *b == b'\n')?;
+    let line = pending.drain(..=pos).collect::<Vec<_>>();
+    Some(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+// What is left of `pending` at the end of a body, as a last line
+pub(crate) fn take_rest(pending: &mut Vec<u8>) -> Option<String> {
+    if pending.is_empty() {
+        return None;
+    }
+    let line = std::mem::take(pending);
+    Some(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+    async fn send(&self, request: Request) -> Result<Response> {
+        let method = match request.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Post => reqwest::Method::POST,
+            Method::Put => reqwest::Method::PUT,
+        };
+        let mut builder = self.cli.request(method, &request.url);
+        for header in request.headers.iter() {
+            builder = builder.header(header.name(), header.value());
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let response = send_logged(request.method.as_str(), &request.url, builder).await?;
+        Response::read(response).await
+    }
+}
+
+// Transport answering canned responses and keeping every request it got, for
+// tests. Clones share the routes and the requests.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTransport {
+    routes: Arc<Mutex<Vec<Route>>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    method: Method,
+    url: String,
+    response: Response,
+}
+
+impl MemoryTransport {
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Answer `method` requests to `url` with `status` and `body`. A url
+    // without a query matches whatever query the request has. The route added
+    // last wins.
+    pub fn route(&self, method: Method, url: &str, status: u16, body: &str) {
+        self.routes.lock().unwrap().push(Route {
+            method,
+            url: url.to_string(),
+            response: Response {
+                status,
+                body: body.to_string(),
+                retry_after: None,
+            },
+        });
+    }
+
+    // Requests sent so far, in order
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl HttpTransport for MemoryTransport {
+    // Requests matching no route are answered 404
+    async fn send(&self, request: Request) -> Result<Response> {
+        let path = request.url.split('?').next().unwrap_or_default();
+        let response = self
+            .routes
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|r| r.method == request.method && (r.url == request.url || r.url == path))
+            .map(|r| r.response.clone())
+            .unwrap_or(Response {
+                status: 404,
+                body: String::new(),
+                retry_after: None,
+            });
+        self.requests.lock().unwrap().push(request);
+        Ok(response)
+    }
+}
+
+pub struct Client {
+    transport: Arc<dyn HttpTransport>,
+    dft_headers: Vec<Header>,
+}
+
+impl Client {
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_transport(Arc::new(ReqwestTransport::new()?)))
+    }
+
+    // Client for endpoints that must answer quickly or not at all, like the
+    // cloud instance metadata services.
+    pub fn new_with_timeout(timeout: Duration) -> Result<Self> {
+        let transport = ReqwestTransport::new_with_timeout(timeout)?;
+        Ok(Self::with_transport(Arc::new(transport)))
+    }
+
+    pub fn with_transport(transport: Arc<dyn HttpTransport>) -> Self {
         Self {
-            cli: reqwest::Client::new(),
+            transport,
             dft_headers: vec![],
         }
     }
@@ -71,14 +450,7 @@ impl Client {
     }
 
     pub async fn get(&self, url: &str, headers: Option<Vec<Header>>) -> Result<Response> {
-        let mut builder = self.cli.get(url);
-        builder = self.add_headers(builder, headers);
-
-        let response = builder.send().await?;
-        Ok(Response {
-            status: response.status().into(),
-            body: response.text().await?,
-        })
+        self.send(Method::Get, url, headers, None).await
     }
 
     pub async fn post(
@@ -87,69 +459,182 @@ impl Client {
         headers: Option<Vec<Header>>,
         body: String,
     ) -> Result<Response> {
-        let mut builder = self.cli.post(url);
-        builder = self.add_headers(builder, headers);
+        self.send(Method::Post, url, headers, Some(body)).await
+    }
 
-        let response = builder.body(body).send().await?;
-        Ok(Response {
-            status: response.status().into(),
-            body: response.text().await?,
-        })
+    pub async fn put(
+        &self,
+        url: &str,
+        headers: Option<Vec<Header>>,
+        body: String,
+    ) -> Result<Response> {
+        self.send(Method::Put, url, headers, Some(body)).await
     }
 
-    fn add_headers(
+    async fn send(
         &self,
-        mut builder: reqwest::RequestBuilder,
+        method: Method,
+        url: &str,
         headers: Option<Vec<Header>>,
-    ) -> reqwest::RequestBuilder {
+        body: Option<String>,
+    ) -> Result<Response> {
         let mut hdrs = self.dft_headers.clone();
         if let Some(headers) = headers {
             hdrs.extend(headers);
         }
 
-        for header in hdrs {
-            builder = builder.header(header.key.as_str(), header.value.as_str());
-        }
-
-        builder
+        let request = Request {
+            method,
+            url: url.to_string(),
+            headers: hdrs,
+            body,
+        };
+        self.transport.send(request).await
     }
 }
 
-#[allow(dead_code)]
-pub async fn get_body(url: &str) -> Result<String> {
-    let response = reqwest::get(url).await?;
-    if response.status().is_success() {
-        Ok(response.text().await?)
-    } else {
-        Err(Error::HttpError(format!(
-            "get returns non-200 status: {}",
-            response.status()
-        )))
+// Query params whose values are never logged
+const SECRET_PARAMS: &[&str] = &[
+    "token",
+    "key",
+    "secret",
+    "password",
+    "passwd",
+    "auth",
+    "signature",
+    "credential",
+];
+
+// Response headers carrying the id the remote end assigned to a request
+const REQUEST_ID_HEADERS: &[&str] = &[
+    "cf-ray",
+    "x-request-id",
+    "x-amzn-requestid",
+    "x-amz-request-id",
+];
+
+// Send a request and log it at debug level with its status, latency and the
+// remote request id, so provider issues can be traced from user reports
+async fn send_logged(
+    method: &str,
+    url: &str,
+    builder: reqwest::RequestBuilder,
+) -> Result<reqwest::Response> {
+    let start = Instant::now();
+    let result = builder.send().await;
+    let duration_ms = start.elapsed().as_millis() as u64;
+    let url = redact_url(url);
+
+    match result.as_ref() {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            let request_id = REQUEST_ID_HEADERS
+                .iter()
+                .find_map(|name| response.headers().get(*name))
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("-");
+            log::debug!(
+                method = method, url = url.as_str(), status = status,
+                duration_ms = duration_ms, request_id = request_id;
+                "{} {} -> {} in {}ms", method, url, status, duration_ms
+            );
+        }
+        Err(e) => log::debug!(
+            method = method, url = url.as_str(), duration_ms = duration_ms;
+            "{} {} failed in {}ms: {}", method, url, duration_ms, e
+        ),
     }
+
+    Ok(result?)
+}
+
+// Replace the values of query params looking like secrets with `***`
+pub fn redact_url(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if is_secret_param(name) => format!("{}=***", name),
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{}", base, query)
+}
+
+fn is_secret_param(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_PARAMS.iter().any(|s| name.contains(s))
 }
 
 pub async fn get_body_v4(url: &str) -> Result<String> {
-    do_get_body(url, Some(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)))).await
+    do_get_body(&SharedClients::get()?.v4, url).await
 }
 
 pub async fn get_body_v6(url: &str) -> Result<String> {
-    do_get_body(url, Some(IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)))).await
+    do_get_body(&SharedClients::get()?.v6, url).await
 }
 
-pub async fn do_get_body(url: &str, addr: Option<std::net::IpAddr>) -> Result<String> {
-    let response = reqwest::Client::builder()
-        .local_address(addr)
-        .build()?
-        .get(url)
-        .send()
-        .await?;
+async fn do_get_body(cli: &reqwest::Client, url: &str) -> Result<String> {
+    let response = send_logged("GET", url, cli.get(url)).await?;
 
     if response.status().is_success() {
         Ok(response.text().await?)
     } else {
-        Err(Error::HttpError(format!(
-            "get returns non-200 status: {}",
-            response.status()
-        )))
+        Err(Error::from_status(response.status().as_u16(), None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_memory_transport() {
+        let transport = MemoryTransport::new();
+        transport.route(Method::Get, "https://api.example.com/zones", 200, "all");
+        transport.route(
+            Method::Get,
+            "https://api.example.com/zones?name=a",
+            200,
+            "a",
+        );
+
+        let mut cli = Client::with_transport(Arc::new(transport.clone()));
+        cli.set_default_headers(vec![Header::new(
+            HeaderKey::Authorization,
+            "Bearer x".to_string(),
+        )]);
+        let resp = cli.get("https://api.example.com/zones?name=a", None).await;
+        assert_eq!(resp.unwrap().body, "a");
+        let resp = cli.get("https://api.example.com/zones?page=2", None).await;
+        assert_eq!(resp.unwrap().body, "all");
+        let resp = cli.post("https://api.example.com/zones", None, "{}".to_string());
+        assert_eq!(resp.await.unwrap().status, 404);
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].headers[0].value(), "Bearer x");
+        assert_eq!(requests[2].method, Method::Post);
+        assert_eq!(requests[2].body.as_deref(), Some("{}"));
+    }
+
+    #[test]
+    fn test_redact_url() {
+        assert_eq!(
+            redact_url("https://api.example.com/v4/zones"),
+            "https://api.example.com/v4/zones"
+        );
+        assert_eq!(
+            redact_url("https://api.example.com/update?hostname=a.example.com&token=abc&apiKey=x"),
+            "https://api.example.com/update?hostname=a.example.com&token=***&apiKey=***"
+        );
+        assert_eq!(
+            redact_url("https://s3.amazonaws.com/b?X-Amz-Signature=f00&page=2"),
+            "https://s3.amazonaws.com/b?X-Amz-Signature=***&page=2"
+        );
     }
 }
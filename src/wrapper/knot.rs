@@ -0,0 +1,74 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+use crate::error::Error;
+use crate::error::Result;
+
+// What knotc prints for a zone the server does not have
+const NO_SUCH_ZONE: &str = "no such zone found";
+
+// The control interface of Knot DNS, reached through knotc, which finds the
+// control socket in knot.conf or takes it with `-s`
+#[derive(Clone)]
+pub struct Knotc {
+    command: Vec<String>,
+}
+
+impl Knotc {
+    // knotc and its arguments, like `knotc -s /run/knot/knot.sock`
+    pub fn new(command: Vec<String>) -> Self {
+        Self { command }
+    }
+
+    // The program run, for errors and logs
+    pub fn describe(&self) -> String {
+        self.command.first().cloned().unwrap_or_default()
+    }
+
+    // Run `command` with `args`, the zone first for the zone commands, and
+    // return what it printed. knotc exits with a failure and prints the error
+    // on stderr when the server refuses the command.
+    pub async fn call(&self, command: &str, args: &[&str], timeout: Duration) -> Result<String> {
+        let Some((program, program_args)) = self.command.split_first() else {
+            return Err(Error::Provider("knot has no control command".to_string()));
+        };
+        let child = Command::new(program)
+            .args(program_args)
+            .arg(command)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Error::Provider(format!("{} failed to start: {}", program, e)))?;
+        let output = tokio::time::timeout(timeout, child.wait_with_output())
+            .await
+            .map_err(|_| {
+                Error::Provider(format!(
+                    "{} {} timed out after {}s",
+                    program,
+                    command,
+                    timeout.as_secs()
+                ))
+            })??;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stderr = stderr.trim();
+            if stderr.contains(NO_SUCH_ZONE)
+                && let Some(zone) = args.first()
+            {
+                return Err(Error::ZoneNotFound {
+                    zone: zone.to_string(),
+                });
+            }
+            return Err(Error::Provider(format!(
+                "{} {} failed: {}",
+                program, command, stderr
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
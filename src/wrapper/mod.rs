@@ -1 +1,10 @@
+pub mod aws;
+pub mod dns;
+pub mod dns_wire;
 pub mod http;
+pub mod knot;
+pub mod mqtt;
+pub mod resolver;
+pub mod unbound;
+#[cfg(unix)]
+pub mod unix;
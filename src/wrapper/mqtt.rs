@@ -0,0 +1,297 @@
+use std::time::Duration;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+use crate::error::Error;
+use crate::error::Result;
+
+// Minimal MQTT 3.1.1 client publishing messages over a connection of its
+// own, for sinks sending a few messages now and then. QoS 0 and 1 only,
+// nothing is subscribed to.
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const KEEP_ALIVE: u16 = 60;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const DISCONNECT: u8 = 0xe0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Broker {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Broker {
+    // `mqtt://host[:port]` or `mqtts://host[:port]`, ports 1883 and 8883 by
+    // default
+    pub fn parse(url: &str, client_id: &str) -> Result<Self> {
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("mqtts://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("mqtt://") {
+            (false, rest)
+        } else {
+            return Err(Error::ParseError(format!(
+                "mqtt: url {} is not mqtt:// or mqtts://",
+                url
+            )));
+        };
+        let rest = rest.trim_end_matches('/');
+        let (host, port) = match rest.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                let port = port
+                    .parse()
+                    .map_err(|_| Error::ParseError(format!("mqtt: invalid port in {}", url)))?;
+                (host, port)
+            }
+            _ => (rest, if tls { 8883 } else { 1883 }),
+        };
+        if host.is_empty() {
+            return Err(Error::ParseError(format!("mqtt: no host in {}", url)));
+        }
+        Ok(Self {
+            host: host.trim_matches(|c| c == '[' || c == ']').to_string(),
+            port,
+            tls,
+            client_id: client_id.to_string(),
+            username: None,
+            password: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub qos: u8,
+    pub retain: bool,
+}
+
+// Connect, publish `messages` in order and disconnect. Messages of QoS 1 are
+// sent once the previous one is acknowledged.
+pub async fn publish(broker: &Broker, messages: &[Message]) -> Result<()> {
+    let published = async {
+        let stream = TcpStream::connect((broker.host.as_str(), broker.port)).await?;
+        if !broker.tls {
+            return session(stream, broker, messages).await;
+        }
+        let connector = tokio_native_tls::native_tls::TlsConnector::new()
+            .map_err(|e| Error::Notify(format!("mqtt: {}", e)))?;
+        let stream = tokio_native_tls::TlsConnector::from(connector)
+            .connect(&broker.host, stream)
+            .await
+            .map_err(|e| Error::Notify(format!("mqtt: {}", e)))?;
+        session(stream, broker, messages).await
+    };
+    tokio::time::timeout(TIMEOUT, published)
+        .await
+        .map_err(|_| Error::Notify(format!("mqtt: {}:{} timed out", broker.host, broker.port)))?
+}
+
+async fn session<S>(mut stream: S, broker: &Broker, messages: &[Message]) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(&connect_packet(broker)).await?;
+    let (header, body) = read_packet(&mut stream).await?;
+    if header != CONNACK || body.len() != 2 {
+        return Err(Error::Notify(
+            "mqtt: no CONNACK from the broker".to_string(),
+        ));
+    }
+    if body[1] != 0 {
+        return Err(Error::Notify(format!(
+            "mqtt: connection refused: {}",
+            connack_reason(body[1])
+        )));
+    }
+
+    for (idx, message) in messages.iter().enumerate() {
+        // Packet ids start at 1
+        let packet_id = idx as u16 + 1;
+        stream
+            .write_all(&publish_packet(message, packet_id))
+            .await?;
+        if message.qos == 0 {
+            continue;
+        }
+        let (header, body) = read_packet(&mut stream).await?;
+        if header & 0xf0 != PUBACK || body != packet_id.to_be_bytes() {
+            return Err(Error::Notify(format!(
+                "mqtt: {} not acknowledged",
+                message.topic
+            )));
+        }
+    }
+
+    stream.write_all(&[DISCONNECT, 0]).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+fn connack_reason(code: u8) -> &'static str {
+    match code {
+        1 => "unacceptable protocol version",
+        2 => "client id rejected",
+        3 => "server unavailable",
+        4 => "bad username or password",
+        5 => "not authorized",
+        _ => "unknown reason",
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &[u8]) {
+    buf.extend((s.len() as u16).to_be_bytes());
+    buf.extend(s);
+}
+
+// Fixed header with the remaining length, then `body`
+fn packet(header: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut ret = vec![header];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        ret.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    ret.extend(body);
+    ret
+}
+
+fn connect_packet(broker: &Broker) -> Vec<u8> {
+    let mut flags = 0x02; // clean session
+    if broker.username.is_some() {
+        flags |= 0x80;
+    }
+    if broker.password.is_some() {
+        flags |= 0x40;
+    }
+    let mut body = vec![];
+    put_str(&mut body, b"MQTT");
+    body.push(4); // protocol level of 3.1.1
+    body.push(flags);
+    body.extend(KEEP_ALIVE.to_be_bytes());
+    put_str(&mut body, broker.client_id.as_bytes());
+    if let Some(username) = broker.username.as_ref() {
+        put_str(&mut body, username.as_bytes());
+    }
+    if let Some(password) = broker.password.as_ref() {
+        put_str(&mut body, password.as_bytes());
+    }
+    packet(CONNECT, body)
+}
+
+fn publish_packet(message: &Message, packet_id: u16) -> Vec<u8> {
+    let qos = message.qos.min(1);
+    let header = PUBLISH | (qos << 1) | message.retain as u8;
+    let mut body = vec![];
+    put_str(&mut body, message.topic.as_bytes());
+    if qos > 0 {
+        body.extend(packet_id.to_be_bytes());
+    }
+    body.extend(&message.payload);
+    packet(header, body)
+}
+
+// Fixed header byte and body of the next packet
+async fn read_packet<S: AsyncRead + Unpin>(stream: &mut S) -> Result<(u8, Vec<u8>)> {
+    let header = stream.read_u8().await?;
+    let mut len = 0usize;
+    for shift in 0..4 {
+        let byte = stream.read_u8().await?;
+        len |= ((byte & 0x7f) as usize) << (7 * shift);
+        if byte & 0x80 == 0 {
+            let mut body = vec![0; len];
+            stream.read_exact(&mut body).await?;
+            return Ok((header, body));
+        }
+    }
+    Err(Error::Notify("mqtt: malformed packet length".to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_mqtt_broker_parse() {
+        let broker = Broker::parse("mqtt://broker.lan", "dns-syncer").unwrap();
+        assert_eq!(
+            (broker.host.as_str(), broker.port, broker.tls),
+            ("broker.lan", 1883, false)
+        );
+        let broker = Broker::parse("mqtts://[::1]:8884/", "dns-syncer").unwrap();
+        assert_eq!(
+            (broker.host.as_str(), broker.port, broker.tls),
+            ("::1", 8884, true)
+        );
+        assert!(Broker::parse("tcp://broker.lan", "dns-syncer").is_err());
+        assert!(Broker::parse("mqtt://broker.lan:x", "dns-syncer").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mqtt_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // A broker acknowledging everything and handing out what it got
+        let broker = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut packets = vec![];
+            loop {
+                let (header, body) = read_packet(&mut stream).await.unwrap();
+                match header & 0xf0 {
+                    CONNECT => stream.write_all(&[CONNACK, 2, 0, 0]).await.unwrap(),
+                    PUBLISH if header & 0x06 != 0 => {
+                        let id = &body[2 + body[1] as usize..][..2];
+                        stream.write_all(&[PUBACK, 2, id[0], id[1]]).await.unwrap();
+                    }
+                    DISCONNECT => break,
+                    _ => {}
+                }
+                packets.push((header, body));
+            }
+            packets
+        });
+
+        let mut broker_cfg = Broker::parse(&format!("mqtt://127.0.0.1:{}", port), "test").unwrap();
+        broker_cfg.username = Some("user".to_string());
+        broker_cfg.password = Some("pass".to_string());
+        let message = Message {
+            topic: "dns-syncer/public_ip".to_string(),
+            payload: b"192.0.2.1".to_vec(),
+            qos: 1,
+            retain: true,
+        };
+        publish(&broker_cfg, &[message]).await.unwrap();
+
+        let packets = broker.await.unwrap();
+        assert_eq!(packets.len(), 2);
+        let (header, body) = &packets[0];
+        assert_eq!(*header, CONNECT);
+        assert_eq!(body[7], 0xc2);
+        assert!(body.ends_with(b"\x00\x04user\x00\x04pass"));
+        let (header, body) = &packets[1];
+        assert_eq!(*header, PUBLISH | 0x02 | 0x01);
+        assert_eq!(&body[..22], b"\x00\x14dns-syncer/public_ip");
+        assert_eq!(&body[22..], b"\x00\x01192.0.2.1");
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::error::{Error, Result};
+use crate::types::DnsConfig;
+use crate::wrapper::dns;
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+struct CacheEntry {
+    addresses: Vec<IpAddr>,
+    expires_at: Instant,
+}
+
+// Resolves the hostnames the HTTP clients connect to by the `dns` settings:
+// fixed addresses first, then the cache, then the configured nameservers or
+// the system resolver. Clones share the cache.
+#[derive(Clone)]
+pub struct Resolver {
+    config: Arc<DnsConfig>,
+    // `resolve` of the config, by normalized hostname
+    overrides: Arc<HashMap<String, Vec<IpAddr>>>,
+    cache: Arc<Mutex<HashMap<String, CacheEntry>>>,
+}
+
+impl Resolver {
+    pub fn new(config: DnsConfig) -> Self {
+        let overrides = config
+            .resolve
+            .iter()
+            .map(|(host, addresses)| (normalize(host), addresses.clone()))
+            .collect();
+        Self {
+            config: Arc::new(config),
+            overrides: Arc::new(overrides),
+            cache: Arc::default(),
+        }
+    }
+
+    pub fn config(&self) -> &DnsConfig {
+        &self.config
+    }
+
+    pub async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let host = normalize(host);
+        if let Some(addresses) = self.overrides.get(&host) {
+            return Ok(addresses.clone());
+        }
+        if let Some(addresses) = self.cached(&host) {
+            return Ok(addresses);
+        }
+
+        let (addresses, ttl) = if self.config.nameservers.is_empty() {
+            let addresses = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .map(|addr| addr.ip())
+                .collect::<Vec<_>>();
+            (addresses, self.config.cache_ttl)
+        } else {
+            let (addresses, ttl) = self.query(&host).await?;
+            let ttl = self.config.cache_ttl.map_or(ttl, |max| ttl.min(max));
+            (addresses, Some(ttl))
+        };
+        if addresses.is_empty() {
+            return Err(Error::HttpError(format!("no address found for {}", host)));
+        }
+
+        if let Some(ttl) = ttl.filter(|ttl| !ttl.is_zero())
+            && let Ok(mut cache) = self.cache.lock()
+        {
+            cache.insert(
+                host,
+                CacheEntry {
+                    addresses: addresses.clone(),
+                    expires_at: Instant::now() + ttl,
+                },
+            );
+        }
+        Ok(addresses)
+    }
+
+    fn cached(&self, host: &str) -> Option<Vec<IpAddr>> {
+        let mut cache = self.cache.lock().ok()?;
+        match cache.get(host) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.addresses.clone()),
+            Some(_) => {
+                cache.remove(host);
+                None
+            }
+            None => None,
+        }
+    }
+
+    // A and AAAA addresses of `host` with the lowest TTL among them, from the
+    // first nameserver answering
+    async fn query(&self, host: &str) -> Result<(Vec<IpAddr>, Duration)> {
+        let mut errors = vec![];
+        for server in self.config.nameservers.iter() {
+            let (v4, v6) = tokio::join!(
+                dns::query(*server, host, dns::TYPE_A, QUERY_TIMEOUT),
+                dns::query(*server, host, dns::TYPE_AAAA, QUERY_TIMEOUT),
+            );
+            let answers = match (v4, v6) {
+                (Err(e), Err(_)) => {
+                    errors.push(e.context(&format!("nameserver {}", server)));
+                    continue;
+                }
+                (v4, v6) => v4
+                    .into_iter()
+                    .chain(v6)
+                    .flat_map(|resp| resp.answers)
+                    .collect::<Vec<_>>(),
+            };
+
+            let mut ttl = u32::MAX;
+            let mut addresses = vec![];
+            for answer in answers {
+                if let Ok(address) = answer.data.parse::<IpAddr>()
+                    && matches!(answer.r#type, dns::TYPE_A | dns::TYPE_AAAA)
+                {
+                    ttl = ttl.min(answer.ttl);
+                    addresses.push(address);
+                }
+            }
+            return Ok((addresses, Duration::from_secs(ttl.into())));
+        }
+        Error::from_errors(errors)?;
+        Err(Error::HttpError(format!(
+            "no nameserver to resolve {}",
+            host
+        )))
+    }
+}
+
+fn normalize(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+impl reqwest::dns::Resolve for Resolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addresses = resolver.lookup(name.as_str()).await?;
+            // Port 0 is replaced by the port of the URL
+            let addrs: reqwest::dns::Addrs = Box::new(
+                addresses
+                    .into_iter()
+                    .map(|ip| SocketAddr::new(ip, 0))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::AtomicU32;
+    use std::sync::atomic::Ordering;
+
+    use tokio::net::UdpSocket;
+
+    use super::*;
+
+    // Nameserver answering every A query with 192.0.2.1, TTL 300, and AAAA
+    // queries with nothing
+    async fn nameserver(queries: Arc<AtomicU32>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                queries.fetch_add(1, Ordering::SeqCst);
+                let mut reply = buf[..len].to_vec();
+                reply[2] = 0x81;
+                reply[3] = 0x80;
+                if reply[len - 3] == dns::TYPE_A as u8 {
+                    reply[7] = 1;
+                    reply.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 1, 0x2c, 0, 4]);
+                    reply.extend_from_slice(&[192, 0, 2, 1]);
+                }
+                let _ = socket.send_to(&reply, peer).await;
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_resolver() {
+        let queries = Arc::new(AtomicU32::new(0));
+        let server = nameserver(queries.clone()).await;
+        let pinned = IpAddr::V4(Ipv4Addr::new(104, 16, 132, 229));
+        let resolver = Resolver::new(DnsConfig {
+            nameservers: vec![server],
+            cache_ttl: Some(Duration::from_secs(60)),
+            resolve: HashMap::from([("API.cloudflare.com.".to_string(), vec![pinned])]),
+        });
+
+        // Fixed addresses are asked nowhere
+        let addresses = resolver.lookup("api.cloudflare.com").await.unwrap();
+        assert_eq!(addresses, vec![pinned]);
+        assert_eq!(queries.load(Ordering::SeqCst), 0);
+
+        let expected = vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))];
+        assert_eq!(resolver.lookup("one.one.one.one").await.unwrap(), expected);
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // The answer is cached, by clones too
+        let clone = resolver.clone();
+        assert_eq!(clone.lookup("ONE.one.one.one").await.unwrap(), expected);
+        assert_eq!(queries.load(Ordering::SeqCst), 2);
+
+        // Without caching every lookup asks again
+        let resolver = Resolver::new(DnsConfig {
+            nameservers: vec![server],
+            cache_ttl: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        resolver.lookup("one.one.one.one").await.unwrap();
+        resolver.lookup("one.one.one.one").await.unwrap();
+        assert_eq!(queries.load(Ordering::SeqCst), 6);
+    }
+}
@@ -0,0 +1,239 @@
+use std::io;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+use crate::error::Error;
+use crate::error::Result;
+
+// Remote control port of Unbound
+pub const DEFAULT_PORT: u16 = 8953;
+// Host name the certificate of unbound-control-setup is issued to
+const SERVER_NAME: &str = "unbound";
+// Ends the lines following a command, like unbound-control sends it
+const END_OF_INPUT: &[u8] = b"\x04\n";
+
+// Certificates of a remote control interface with `control-use-cert: yes`,
+// PEM encoded. The key is PKCS#8 (`BEGIN PRIVATE KEY`).
+#[derive(Clone)]
+pub struct ControlTls {
+    pub server_cert: Vec<u8>,
+    pub control_cert: Vec<u8>,
+    pub control_key: Vec<u8>,
+}
+
+impl ControlTls {
+    // The `server-cert-file`, `control-cert-file` and `control-key-file` of
+    // unbound.conf
+    pub fn from_files(server_cert: &Path, control_cert: &Path, control_key: &Path) -> Result<Self> {
+        let read = |path: &Path| {
+            std::fs::read(path).map_err(|e| {
+                Error::IoError(io::Error::new(
+                    e.kind(),
+                    format!("{}: {}", path.display(), e),
+                ))
+            })
+        };
+        Ok(Self {
+            server_cert: read(server_cert)?,
+            control_cert: read(control_cert)?,
+            control_key: read(control_key)?,
+        })
+    }
+
+    fn connector(&self) -> Result<tokio_native_tls::TlsConnector> {
+        use tokio_native_tls::native_tls;
+
+        let tls_error =
+            |e: native_tls::Error| Error::Provider(format!("unbound control tls: {}", e));
+        let identity = native_tls::Identity::from_pkcs8(&self.control_cert, &self.control_key)
+            .map_err(tls_error)?;
+        let server_cert =
+            native_tls::Certificate::from_pem(&self.server_cert).map_err(tls_error)?;
+        let connector = native_tls::TlsConnector::builder()
+            .identity(identity)
+            .add_root_certificate(server_cert)
+            .disable_built_in_roots(true)
+            .build()
+            .map_err(tls_error)?;
+        Ok(tokio_native_tls::TlsConnector::from(connector))
+    }
+}
+
+// How commands reach the remote control interface of Unbound
+#[derive(Clone)]
+pub enum Control {
+    // unbound-control and its arguments, which finds the server and its
+    // certificates in unbound.conf
+    Program(Vec<String>),
+    // The interface itself: `host:port`, or the path of a unix socket. TLS is
+    // left out for interfaces with `control-use-cert: no`.
+    Socket {
+        server: String,
+        tls: Option<ControlTls>,
+    },
+}
+
+impl Control {
+    // Socket at `server`, a unix socket path or a host name or address with
+    // an optional port (8953 by default)
+    pub fn socket(server: &str, tls: Option<ControlTls>) -> Self {
+        let server = if server.starts_with('/') || server.parse::<SocketAddr>().is_ok() {
+            server.to_string()
+        } else if let Ok(ip) = server.trim_matches(['[', ']']).parse::<IpAddr>() {
+            SocketAddr::new(ip, DEFAULT_PORT).to_string()
+        } else if server.contains(':') {
+            server.to_string()
+        } else {
+            format!("{}:{}", server, DEFAULT_PORT)
+        };
+        Control::Socket { server, tls }
+    }
+
+    // Where commands go, for errors and logs
+    pub fn describe(&self) -> String {
+        match self {
+            Control::Program(command) => command.first().cloned().unwrap_or_default(),
+            Control::Socket { server, .. } => server.clone(),
+        }
+    }
+
+    // Run `command` with `input` as the lines following it, and return what
+    // Unbound answered. Answers with `error` lines fail, Unbound goes on with
+    // the lines following a failing one.
+    pub async fn call(&self, command: &str, input: &[String], timeout: Duration) -> Result<String> {
+        let answer = tokio::time::timeout(timeout, self.exchange(command, input))
+            .await
+            .map_err(|_| {
+                Error::IoError(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("unbound control {} timed out", self.describe()),
+                ))
+            })??;
+        let errors = answer
+            .lines()
+            .filter(|l| l.starts_with("error"))
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(Error::Provider(format!(
+                "unbound {} failed: {}",
+                command,
+                errors.join(", ")
+            )));
+        }
+        Ok(answer)
+    }
+
+    async fn exchange(&self, command: &str, input: &[String]) -> Result<String> {
+        let (server, tls) = match self {
+            Control::Program(program) => return Self::run(program, command, input).await,
+            Control::Socket { server, tls } => (server, tls),
+        };
+        let connect_error =
+            |e: io::Error| Error::IoError(io::Error::new(e.kind(), format!("{}: {}", server, e)));
+        #[cfg(unix)]
+        if server.starts_with('/') {
+            let stream = tokio::net::UnixStream::connect(server)
+                .await
+                .map_err(connect_error)?;
+            return session(stream, command, input).await;
+        }
+        let stream = TcpStream::connect(server).await.map_err(connect_error)?;
+        match tls {
+            Some(tls) => {
+                let stream = tls
+                    .connector()?
+                    .connect(SERVER_NAME, stream)
+                    .await
+                    .map_err(|e| Error::Provider(format!("unbound control tls: {}", e)))?;
+                session(stream, command, input).await
+            }
+            None => session(stream, command, input).await,
+        }
+    }
+
+    // unbound-control reads the lines following the command on stdin, and
+    // exits with a failure when the answer is an error
+    async fn run(program: &[String], command: &str, input: &[String]) -> Result<String> {
+        let Some((program, args)) = program.split_first() else {
+            return Err(Error::Provider(
+                "unbound control has no command".to_string(),
+            ));
+        };
+        let mut child = Command::new(program)
+            .args(args)
+            .args(command.split_whitespace())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Error::Provider(format!("{} failed to start: {}", program, e)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            for line in input.iter() {
+                stdin.write_all(format!("{}\n", line).as_bytes()).await?;
+            }
+        }
+        let output = child.wait_with_output().await?;
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        if !output.status.success() && !stdout.lines().any(|l| l.starts_with("error")) {
+            return Err(Error::Provider(format!(
+                "{} {} failed with {}: {}",
+                program,
+                command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(stdout)
+    }
+}
+
+// One command of the protocol: `UBCT1 <command>`, the lines following it,
+// and the answer up to the end of the connection
+async fn session<S>(mut stream: S, command: &str, input: &[String]) -> Result<String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut request = format!("UBCT1 {}\n", command).into_bytes();
+    if !input.is_empty() {
+        for line in input.iter() {
+            request.extend_from_slice(line.as_bytes());
+            request.push(b'\n');
+        }
+        request.extend_from_slice(END_OF_INPUT);
+    }
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+    let mut answer = vec![];
+    stream.read_to_end(&mut answer).await?;
+    Ok(String::from_utf8_lossy(&answer).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_control_socket() {
+        let server = |s: &str| match Control::socket(s, None) {
+            Control::Socket { server, .. } => server,
+            Control::Program(_) => unreachable!(),
+        };
+        assert_eq!(server("127.0.0.1"), "127.0.0.1:8953");
+        assert_eq!(server("127.0.0.1:953"), "127.0.0.1:953");
+        assert_eq!(server("::1"), "[::1]:8953");
+        assert_eq!(server("unbound.lan"), "unbound.lan:8953");
+        assert_eq!(server("[::1]:953"), "[::1]:953");
+        assert_eq!(server("/run/unbound.ctl"), "/run/unbound.ctl");
+    }
+}
@@ -0,0 +1,189 @@
+use std::path::Path;
+
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::UnixStream;
+
+use crate::error::{Error, Result};
+use crate::wrapper::http;
+
+// Minimal HTTP/1.1 over a Unix socket, for local daemons like Docker which
+// only listen there. GET requests only, the body is read chunked, by length
+// or up to the end of the connection.
+
+enum Framing {
+    Chunked,
+    Length(usize),
+    Close,
+}
+
+// Answer whose body is read piece by piece, for streams like Docker events
+pub struct Body {
+    reader: BufReader<UnixStream>,
+    framing: Framing,
+    done: bool,
+    // Bytes read past the last complete line
+    pending: Vec<u8>,
+}
+
+pub async fn get_stream(socket: &Path, path: &str) -> Result<(u16, Body)> {
+    let mut stream = UnixStream::connect(socket)
+        .await
+        .map_err(|e| Error::HttpError(format!("{}: {}", socket.display(), e)))?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .ok_or_else(|| Error::HttpError(format!("bad status line: {}", line.trim_end())))?;
+
+    let mut framing = Framing::Close;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(Error::HttpError("connection closed in headers".to_string()));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((key, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if key.eq_ignore_ascii_case("transfer-encoding") && value.eq_ignore_ascii_case("chunked") {
+            framing = Framing::Chunked;
+        } else if key.eq_ignore_ascii_case("content-length")
+            && let Ok(len) = value.parse()
+        {
+            framing = Framing::Length(len);
+        }
+    }
+
+    let body = Body {
+        reader,
+        framing,
+        done: false,
+        pending: vec![],
+    };
+    Ok((status, body))
+}
+
+// Status and full body of a GET request
+pub async fn get(socket: &Path, path: &str) -> Result<(u16, String)> {
+    let (status, mut body) = get_stream(socket, path).await?;
+    let mut data = vec![];
+    while let Some(chunk) = body.chunk().await? {
+        data.extend(chunk);
+    }
+    let body = String::from_utf8(data).map_err(|e| Error::HttpError(e.to_string()))?;
+    Ok((status, body))
+}
+
+impl Body {
+    // Next piece of the body, `None` at its end
+    pub async fn chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.done {
+            return Ok(None);
+        }
+        let data = match self.framing {
+            Framing::Chunked => {
+                let mut line = String::new();
+                if self.reader.read_line(&mut line).await? == 0 {
+                    return Err(Error::HttpError("connection closed in body".to_string()));
+                }
+                let size = line.trim_end().split(';').next().unwrap_or_default();
+                let size = usize::from_str_radix(size, 16)
+                    .map_err(|_| Error::HttpError(format!("bad chunk size: {}", size)))?;
+                let mut data = vec![0; size];
+                self.reader.read_exact(&mut data).await?;
+                // CRLF ending the chunk, or the (empty) trailer of the last one
+                line.clear();
+                self.reader.read_line(&mut line).await?;
+                if size == 0 {
+                    self.done = true;
+                    return Ok(None);
+                }
+                data
+            }
+            Framing::Length(len) => {
+                let mut data = vec![0; len];
+                self.reader.read_exact(&mut data).await?;
+                self.done = true;
+                data
+            }
+            Framing::Close => {
+                let mut data = vec![];
+                self.reader.read_to_end(&mut data).await?;
+                self.done = true;
+                data
+            }
+        };
+        Ok(Some(data))
+    }
+
+    // Next line of the body without its line break, `None` at its end
+    pub async fn line(&mut self) -> Result<Option<String>> {
+        loop {
+            if let Some(line) = http::take_line(&mut self.pending) {
+                return Ok(Some(line));
+            }
+            match self.chunk().await? {
+                Some(data) => self.pending.extend(data),
+                None => return Ok(http::take_rest(&mut self.pending)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::net::UnixListener;
+
+    use super::*;
+
+    // Serves `answer` to every connection on a socket of a temp directory
+    fn server(name: &str, answer: &'static str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("dns-syncer-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = vec![0; 1024];
+                let _ = stream.read(&mut buf).await;
+                let _ = stream.write_all(answer.as_bytes()).await;
+            }
+        });
+        path
+    }
+
+    #[tokio::test]
+    async fn test_unix_get() {
+        let path = server("length", "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n[]");
+        assert_eq!(
+            get(&path, "/containers/json").await.unwrap(),
+            (200, "[]".to_string())
+        );
+
+        let path = server(
+            "chunked",
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             6\r\n{\"a\":1\r\n6\r\n}\n{\"b\"\r\n3\r\n:2}\r\n0\r\n\r\n",
+        );
+        let (status, mut body) = get_stream(&path, "/events").await.unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body.line().await.unwrap().as_deref(), Some("{\"a\":1}"));
+        assert_eq!(body.line().await.unwrap().as_deref(), Some("{\"b\":2}"));
+        assert_eq!(body.line().await.unwrap(), None);
+    }
+}
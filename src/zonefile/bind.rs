@@ -0,0 +1,318 @@
+use crate::error::Error;
+use crate::error::Result;
+
+use super::ZoneEntry;
+
+const CLASSES: [&str; 4] = ["IN", "CH", "HS", "CS"];
+
+// Parse a RFC 1035 master file. `$ORIGIN` and `$TTL` directives, parentheses
+// spanning several lines, `@` and blank owners are understood. Relative names,
+// CNAME targets included, are completed with the origin when one is known,
+// otherwise they are kept relative.
+pub fn parse_bind(content: &str, origin: Option<&str>) -> Result<Vec<ZoneEntry>> {
+    let mut origin = origin.map(|o| o.trim_end_matches('.').to_string());
+    let mut default_ttl: Option<u32> = None;
+    let mut last_owner: Option<String> = None;
+    let mut entries = vec![];
+
+    for (lineno, line) in logical_lines(content) {
+        let starts_blank = line.starts_with([' ', '\t']);
+        let (tokens, comment) = tokenize(&line);
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let err = |msg: &str| Error::ParseError(format!("zone file line {}: {}", lineno, msg));
+
+        match tokens[0].as_str() {
+            "$ORIGIN" => {
+                let value = tokens.get(1).ok_or(err("$ORIGIN without value"))?;
+                origin = Some(value.trim_end_matches('.').to_string());
+                continue;
+            }
+            "$TTL" => {
+                let value = tokens.get(1).ok_or(err("$TTL without value"))?;
+                default_ttl = Some(parse_ttl(value).ok_or(err("invalid $TTL"))?);
+                continue;
+            }
+            directive if directive.starts_with('$') => {
+                return Err(err(&format!("unsupported directive {}", directive)));
+            }
+            _ => {}
+        }
+
+        let mut rest = tokens.as_slice();
+        let owner = if starts_blank {
+            last_owner.clone().ok_or(err("record without owner"))?
+        } else {
+            let owner =
+                absolute_name(&rest[0], origin.as_deref()).ok_or(err("@ used without origin"))?;
+            rest = &rest[1..];
+            owner
+        };
+        last_owner = Some(owner.clone());
+
+        // TTL and class may come in either order and are both optional
+        let mut ttl = None;
+        for _ in 0..2 {
+            match rest.first() {
+                Some(tok) if CLASSES.contains(&tok.to_ascii_uppercase().as_str()) => {
+                    rest = &rest[1..]
+                }
+                Some(tok) if ttl.is_none() && parse_ttl(tok).is_some() => {
+                    ttl = parse_ttl(tok);
+                    rest = &rest[1..];
+                }
+                _ => break,
+            }
+        }
+
+        let (ty, data) = rest.split_first().ok_or(err("missing record type"))?;
+        if data.is_empty() {
+            return Err(err("missing record data"));
+        }
+        let ty = ty.to_ascii_uppercase();
+        let mut data = data.join(" ");
+        if ty == "CNAME" {
+            data = absolute_name(&data, origin.as_deref()).ok_or(err("@ used without origin"))?;
+        }
+
+        entries.push(ZoneEntry {
+            name: owner,
+            ttl: ttl.or(default_ttl),
+            r#type: ty,
+            data,
+            comment,
+        });
+    }
+
+    Ok(entries)
+}
+
+// Write entries as a RFC 1035 master file that `parse_bind` reads back. Names are
+// written fully qualified, CNAME targets too, so no `$ORIGIN` is needed.
+pub fn write_bind(entries: &[ZoneEntry]) -> String {
+    let mut ret = String::new();
+    for entry in entries {
+        let ttl = entry.ttl.map(|t| t.to_string()).unwrap_or_default();
+        let data = match entry.r#type.as_str() {
+            "CNAME" if !entry.data.ends_with('.') => format!("{}.", entry.data),
+            _ => entry.data.clone(),
+        };
+        let mut line = format!("{}.\t{}\tIN\t{}\t{}", entry.name, ttl, entry.r#type, data);
+        if let Some(comment) = entry.comment.as_deref() {
+            line.push_str(&format!(" ; {}", comment.replace(['\n', '\r'], " ")));
+        }
+        ret.push_str(&line);
+        ret.push('\n');
+    }
+    ret
+}
+
+fn absolute_name(name: &str, origin: Option<&str>) -> Option<String> {
+    match (name, origin) {
+        ("@", Some(origin)) => Some(origin.to_string()),
+        ("@", None) => None,
+        (name, _) if name.ends_with('.') => Some(name.trim_end_matches('.').to_string()),
+        (name, Some(origin)) => Some(format!("{}.{}", name, origin)),
+        (name, None) => Some(name.to_string()),
+    }
+}
+
+// TTLs are either plain seconds or BIND style units like 1h30m
+fn parse_ttl(value: &str) -> Option<u32> {
+    if let Ok(secs) = value.parse::<u32>() {
+        return Some(secs);
+    }
+
+    let mut total: u32 = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 604800,
+            _ => return None,
+        };
+        total = total.checked_add(number.parse::<u32>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+
+    if number.is_empty() { Some(total) } else { None }
+}
+
+// Join lines enclosed in parentheses into one logical line, keeping the line
+// number of the first physical line for error messages.
+fn logical_lines(content: &str) -> Vec<(usize, String)> {
+    let mut ret = vec![];
+    let mut pending: Option<(usize, String)> = None;
+    let mut depth = 0i32;
+
+    for (idx, line) in content.lines().enumerate() {
+        let code = strip_comment(line);
+        depth += code.matches('(').count() as i32 - code.matches(')').count() as i32;
+
+        match pending.as_mut() {
+            Some((_, acc)) => {
+                acc.push(' ');
+                acc.push_str(&line.replace(['(', ')'], " "));
+            }
+            None => pending = Some((idx + 1, line.replace(['(', ')'], " "))),
+        }
+
+        if depth <= 0 {
+            depth = 0;
+            ret.extend(pending.take());
+        }
+    }
+    ret.extend(pending);
+    ret
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quote = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => in_quote = !in_quote,
+            ';' if !in_quote => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn tokenize(line: &str) -> (Vec<String>, Option<String>) {
+    let code = strip_comment(line);
+    let comment = line[code.len()..]
+        .trim_start_matches(';')
+        .trim()
+        .to_string();
+
+    let mut tokens = vec![];
+    let mut cur = String::new();
+    let mut in_quote = false;
+    for c in code.chars() {
+        match c {
+            '"' => {
+                cur.push(c);
+                in_quote = !in_quote;
+            }
+            c if c.is_whitespace() && !in_quote => {
+                if !cur.is_empty() {
+                    tokens.push(std::mem::take(&mut cur));
+                }
+            }
+            c => cur.push(c),
+        }
+    }
+    if !cur.is_empty() {
+        tokens.push(cur);
+    }
+
+    let comment = if comment.is_empty() {
+        None
+    } else {
+        Some(comment)
+    };
+    (tokens, comment)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_bind() {
+        let content = r#"
+$ORIGIN example.org.
+$TTL 1h
+@       IN  SOA ns1.example.org. admin.example.org. (
+            2024010101 ; serial
+            3600 900 604800 300 )
+        IN  NS  ns1.example.org.
+home    300 IN  A   1.2.3.4 ; home router
+        IN  AAAA 2001:db8::1
+www         IN  CNAME home
+ext.other.org. A 5.6.7.8
+txt     TXT "v=spf1 ; -all"
+"#;
+        let entries = parse_bind(content, None).unwrap();
+        assert_eq!(entries.len(), 7);
+        assert_eq!(entries[0].r#type, "SOA");
+        assert_eq!(entries[0].name, "example.org");
+        assert_eq!(entries[1].name, "example.org");
+        assert_eq!(entries[1].ttl, Some(3600));
+
+        assert_eq!(entries[2].name, "home.example.org");
+        assert_eq!(entries[2].ttl, Some(300));
+        assert_eq!(entries[2].data, "1.2.3.4");
+        assert_eq!(entries[2].comment.as_deref(), Some("home router"));
+        assert_eq!(entries[3].name, "home.example.org");
+        assert_eq!(entries[3].r#type, "AAAA");
+
+        assert_eq!(entries[4].data, "home.example.org");
+        assert_eq!(entries[5].name, "ext.other.org");
+        assert_eq!(entries[6].data, "\"v=spf1 ; -all\"");
+    }
+
+    #[test]
+    fn test_write_bind() {
+        let entries = vec![
+            ZoneEntry {
+                name: "home.example.org".to_string(),
+                ttl: Some(300),
+                r#type: "A".to_string(),
+                data: "1.2.3.4".to_string(),
+                comment: Some("home router".to_string()),
+            },
+            ZoneEntry {
+                name: "www.example.org".to_string(),
+                ttl: None,
+                r#type: "CNAME".to_string(),
+                data: "home.example.org".to_string(),
+                comment: None,
+            },
+        ];
+        let content = write_bind(&entries);
+        assert!(content.contains("www.example.org.\t\tIN\tCNAME\thome.example.org."));
+
+        let parsed = parse_bind(&content, None).unwrap();
+        assert_eq!(parsed, entries);
+    }
+
+    #[test]
+    fn test_parse_bind_without_origin() {
+        let entries = parse_bind("home A 1.2.3.4\n", None).unwrap();
+        assert_eq!(entries[0].name, "home");
+        assert!(parse_bind("@ A 1.2.3.4\n", None).is_err());
+
+        let entries = parse_bind("@ A 1.2.3.4\n", Some("example.org")).unwrap();
+        assert_eq!(entries[0].name, "example.org");
+
+        // CNAME targets are completed like names
+        let entries = parse_bind("www CNAME home\n", None).unwrap();
+        assert_eq!(entries[0].data, "home");
+        assert!(parse_bind("www CNAME @\n", None).is_err());
+        let entries = parse_bind(
+            "www CNAME @\nftp CNAME nas.example.net.\n",
+            Some("example.org"),
+        )
+        .unwrap();
+        assert_eq!(entries[0].data, "example.org");
+        assert_eq!(entries[1].data, "nas.example.net");
+    }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("300"), Some(300));
+        assert_eq!(parse_ttl("1h30m"), Some(5400));
+        assert_eq!(parse_ttl("1d"), Some(86400));
+        assert_eq!(parse_ttl("IN"), None);
+    }
+}
@@ -0,0 +1,146 @@
+use crate::error::Error;
+use crate::error::Result;
+
+use super::ZoneEntry;
+
+// Parse records from a CSV file. The first line is a header naming the columns,
+// `name`, `type` and `content` are mandatory, `ttl` and `comment` are optional.
+// Empty lines and lines starting with '#' are ignored.
+pub fn parse_csv(content: &str) -> Result<Vec<ZoneEntry>> {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+
+    let header = match lines.next() {
+        Some((_, header)) => split_line(header)
+            .into_iter()
+            .map(|h| h.to_ascii_lowercase())
+            .collect::<Vec<_>>(),
+        None => return Ok(vec![]),
+    };
+
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let missing = |name: &str| Error::ParseError(format!("csv header has no {} column", name));
+    let name_col = column("name").ok_or(missing("name"))?;
+    let type_col = column("type").ok_or(missing("type"))?;
+    let content_col = column("content").ok_or(missing("content"))?;
+    let ttl_col = column("ttl");
+    let comment_col = column("comment");
+
+    let mut entries = vec![];
+    for (idx, line) in lines {
+        let fields = split_line(line);
+        let field = |col: usize| fields.get(col).map(|f| f.trim()).unwrap_or_default();
+        let optional = |col: Option<usize>| col.map(field).filter(|f| !f.is_empty());
+
+        let ttl = match optional(ttl_col) {
+            Some(ttl) => Some(ttl.parse::<u32>().map_err(|e| {
+                Error::ParseError(format!("csv line {}: invalid ttl: {}", idx + 1, e))
+            })?),
+            None => None,
+        };
+
+        entries.push(ZoneEntry {
+            name: field(name_col).trim_end_matches('.').to_string(),
+            ttl,
+            r#type: field(type_col).to_ascii_uppercase(),
+            data: field(content_col).to_string(),
+            comment: optional(comment_col).map(String::from),
+        });
+    }
+
+    Ok(entries)
+}
+
+// Write entries with the header `parse_csv` expects
+pub fn write_csv(entries: &[ZoneEntry]) -> String {
+    let mut ret = String::from("name,type,content,ttl,comment\n");
+    for entry in entries {
+        let fields = [
+            entry.name.clone(),
+            entry.r#type.clone(),
+            entry.data.clone(),
+            entry.ttl.map(|t| t.to_string()).unwrap_or_default(),
+            entry.comment.clone().unwrap_or_default(),
+        ];
+        let line = fields.iter().map(|f| quote(f)).collect::<Vec<_>>();
+        ret.push_str(&line.join(","));
+        ret.push('\n');
+    }
+    ret
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn split_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut cur = String::new();
+    let mut in_quote = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quote && chars.peek() == Some(&'"') => {
+                cur.push('"');
+                chars.next();
+            }
+            '"' => in_quote = !in_quote,
+            ',' if !in_quote => fields.push(std::mem::take(&mut cur)),
+            c => cur.push(c),
+        }
+    }
+    fields.push(cur);
+    fields
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let content = r#"
+# exported records
+name,type,content,ttl,comment
+home.example.org,A,1.2.3.4,300,"home, sweet ""home"""
+www,cname,home.example.org.,,
+"#;
+        let entries = parse_csv(content).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "home.example.org");
+        assert_eq!(entries[0].ttl, Some(300));
+        assert_eq!(entries[0].comment.as_deref(), Some("home, sweet \"home\""));
+        assert_eq!(entries[1].r#type, "CNAME");
+        assert_eq!(entries[1].ttl, None);
+        assert_eq!(entries[1].comment, None);
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let entries = vec![ZoneEntry {
+            name: "home.example.org".to_string(),
+            ttl: Some(300),
+            r#type: "A".to_string(),
+            data: "1.2.3.4".to_string(),
+            comment: Some("home, sweet \"home\"".to_string()),
+        }];
+        let content = write_csv(&entries);
+        assert_eq!(
+            content,
+            "name,type,content,ttl,comment\nhome.example.org,A,1.2.3.4,300,\"home, sweet \"\"home\"\"\"\n"
+        );
+        assert_eq!(parse_csv(&content).unwrap(), entries);
+    }
+
+    #[test]
+    fn test_parse_csv_missing_column() {
+        assert!(parse_csv("name,type\nhome,A\n").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+mod bind;
+pub use bind::*;
+
+mod csv;
+pub use csv::*;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::record::ProviderRecord;
+use crate::record::RecordContent;
+use crate::record::RecordType;
+
+// A single resource record read from a zone file or a CSV file. The record data
+// is kept verbatim so that callers can decide what to do with types we cannot sync,
+// only CNAME targets of zone files are completed with the origin.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoneEntry {
+    pub name: String,
+    pub ttl: Option<u32>,
+    pub r#type: String,
+    pub data: String,
+    pub comment: Option<String>,
+}
+
+impl ZoneEntry {
+    // `None` for records whose content is not known, they cannot be written out
+    pub fn from_record(record: &ProviderRecord) -> Option<Self> {
+        let data = match &record.content {
+            RecordContent::A(_) | RecordContent::AAAA(_) | RecordContent::CNAME(_) => {
+                record.content.to_string()
+            }
+            RecordContent::Unassigned(_) | RecordContent::Unknown => return None,
+        };
+        Some(Self {
+            name: record.name.clone(),
+            ttl: record.ttl.seconds(),
+            r#type: record.content.record_type().as_str().to_string(),
+            data,
+            comment: record.comment.clone(),
+        })
+    }
+
+    pub fn content(&self) -> Result<RecordContent> {
+        if RecordType::parse(&self.r#type).is_none() {
+            return Err(Error::ParseError(format!(
+                "unsupported record type {} for {}",
+                self.r#type, self.name
+            )));
+        }
+        RecordContent::parse(&self.r#type, Some(&self.data))
+    }
+}